@@ -0,0 +1,85 @@
+//! Benchmarks of the code run for every email or every page view.
+//!
+//! `cargo bench --features bench-helpers`
+use chrono::{Duration, Utc};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use secrecy::Secret;
+use std::collections::HashMap;
+use zero2prod::configuration::{ThemeSettings, TokenSigningSettings};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::{EmailClient, MessageStream};
+use zero2prod::image_proxy::rewrite_image_sources;
+use zero2prod::theme::{Page, Theme};
+use zero2prod::token_signer::TokenSigner;
+
+/// A newsletter issue of a realistic size, with a few external images.
+fn issue_html() -> String {
+    let paragraph = "<p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+                     eiusmod tempor incididunt ut labore et dolore magna aliqua.</p>\n";
+    let image = "<img src=\"https://example.com/images/figure.png?width=600&amp;v=2\">\n";
+    (0..40)
+        .map(|i| if i % 8 == 0 { image } else { paragraph })
+        .collect()
+}
+
+fn email_payload(c: &mut Criterion) {
+    let sender = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+    let recipient = SubscriberEmail::parse("ursula@example.com".into()).unwrap();
+    let client = EmailClient::new(
+        "http://localhost".into(),
+        sender,
+        Secret::new("public".into()),
+        Secret::new("private".into()),
+        std::time::Duration::from_secs(1),
+    )
+    .unwrap();
+    let html = issue_html();
+    c.bench_function("serialise a send request", |b| {
+        b.iter(|| {
+            client.request_body(
+                black_box(&recipient),
+                "Issue #42",
+                black_box(&html),
+                black_box(&html),
+                MessageStream::Broadcast,
+            )
+        })
+    });
+}
+
+fn templates(c: &mut Criterion) {
+    let theme = Theme::new(&ThemeSettings {
+        accent_color: "#1f6feb".into(),
+        logo_url: None,
+        custom_css: String::new(),
+        template_directory: None,
+    })
+    .unwrap();
+    let html = issue_html();
+    c.bench_function("render an archive page", |b| {
+        b.iter(|| theme.render(Page::new("archive_issue", "Issue #42", black_box(&html))))
+    });
+    let secret = Secret::new("a-secret".into());
+    c.bench_function("rewrite image sources", |b| {
+        b.iter(|| rewrite_image_sources(black_box(&html), &secret))
+    });
+}
+
+fn tokens(c: &mut Criterion) {
+    let signer = TokenSigner::new(&TokenSigningSettings {
+        current_key: "k1".into(),
+        keys: HashMap::from([("k1".into(), Secret::new("k".repeat(32)))]),
+    })
+    .unwrap();
+    let expires_at = Utc::now() + Duration::days(30);
+    c.bench_function("sign a token", |b| {
+        b.iter(|| signer.sign("unsubscribe", black_box("subscriber-42"), expires_at))
+    });
+    let token = signer.sign("unsubscribe", "subscriber-42", expires_at);
+    c.bench_function("verify a token", |b| {
+        b.iter(|| signer.verify("unsubscribe", black_box(&token)))
+    });
+}
+
+criterion_group!(benches, email_payload, templates, tokens);
+criterion_main!(benches);
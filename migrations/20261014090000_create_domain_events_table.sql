@@ -0,0 +1,7 @@
+CREATE TABLE domain_events
+(
+    id          BIGSERIAL   PRIMARY KEY,
+    event_type  TEXT        NOT NULL,
+    payload     JSONB       NOT NULL,
+    occurred_at timestamptz NOT NULL
+);
@@ -0,0 +1,12 @@
+CREATE TABLE events_outbox
+(
+    id           BIGSERIAL   PRIMARY KEY,
+    ordering_key TEXT        NOT NULL,
+    event_type   TEXT        NOT NULL,
+    payload      JSONB       NOT NULL,
+    created_at   timestamptz NOT NULL,
+    published_at timestamptz NULL,
+    attempts     INT         NOT NULL DEFAULT 0,
+    last_error   TEXT        NULL
+);
+CREATE INDEX events_outbox_unpublished ON events_outbox (id) WHERE published_at IS NULL;
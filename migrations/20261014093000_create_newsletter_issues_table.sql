@@ -0,0 +1,12 @@
+CREATE TABLE newsletter_issues
+(
+    newsletter_issue_id uuid        NOT NULL,
+    title               TEXT        NOT NULL,
+    text_content        TEXT        NOT NULL,
+    html_content        TEXT        NOT NULL,
+    published_at        timestamptz NOT NULL,
+    status              TEXT        NOT NULL,
+    sent_count          INT         NOT NULL DEFAULT 0,
+    failed_count        INT         NOT NULL DEFAULT 0,
+    PRIMARY KEY (newsletter_issue_id)
+);
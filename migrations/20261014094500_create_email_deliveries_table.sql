@@ -0,0 +1,10 @@
+CREATE TABLE email_deliveries
+(
+    id                  BIGSERIAL   PRIMARY KEY,
+    newsletter_issue_id uuid        NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email    TEXT        NOT NULL,
+    attempted_at        timestamptz NOT NULL,
+    succeeded           BOOLEAN     NOT NULL
+);
+CREATE INDEX email_deliveries_attempted_at ON email_deliveries (attempted_at);
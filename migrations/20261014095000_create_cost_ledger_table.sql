@@ -0,0 +1,8 @@
+-- Estimated sending costs, aggregated per month and provider
+CREATE TABLE cost_ledger(
+    month DATE NOT NULL,
+    provider TEXT NOT NULL,
+    emails_sent BIGINT NOT NULL DEFAULT 0,
+    estimated_cost DOUBLE PRECISION NOT NULL DEFAULT 0,
+    PRIMARY KEY (month, provider)
+);
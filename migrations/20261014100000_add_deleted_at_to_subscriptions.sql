@@ -0,0 +1,2 @@
+-- Soft-deleted subscribers are purged after the retention period
+ALTER TABLE subscriptions ADD COLUMN deleted_at timestamptz NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions ADD COLUMN paid BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE subscriptions ADD COLUMN stripe_customer_id TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN paid_only BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+CREATE TABLE magic_links(
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    purpose TEXT NOT NULL,
+    subject_id uuid NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL
+);
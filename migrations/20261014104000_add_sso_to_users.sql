@@ -0,0 +1,4 @@
+-- Users provisioned through single sign-on have no password.
+ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+ALTER TABLE users ADD COLUMN oidc_subject TEXT NULL UNIQUE;
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';
@@ -0,0 +1,2 @@
+-- Deprovisioned admins are deactivated rather than deleted.
+ALTER TABLE users ADD COLUMN active BOOLEAN NOT NULL DEFAULT true;
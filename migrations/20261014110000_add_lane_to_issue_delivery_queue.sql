@@ -0,0 +1,5 @@
+-- Tasks in the priority lane are dequeued ahead of the bulk lane.
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN lane TEXT NOT NULL DEFAULT 'bulk'
+        CHECK (lane IN ('priority', 'bulk'));
+CREATE INDEX issue_delivery_queue_lane_idx ON issue_delivery_queue (lane);
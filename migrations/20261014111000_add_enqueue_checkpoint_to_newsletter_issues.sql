@@ -0,0 +1,7 @@
+-- Delivery tasks are enqueued in batches, in subscriber email order:
+-- `enqueue_cursor` is the last email enqueued so far, so that an interrupted
+-- enqueue can resume where it stopped.
+ALTER TABLE newsletter_issues
+    ADD COLUMN delivery_lane TEXT NOT NULL DEFAULT 'bulk',
+    ADD COLUMN enqueue_cursor TEXT NULL,
+    ADD COLUMN enqueue_completed BOOLEAN NOT NULL DEFAULT true;
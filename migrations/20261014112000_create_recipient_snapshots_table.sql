@@ -0,0 +1,19 @@
+-- The recipients of an issue, frozen when it is published: subscribers who
+-- confirm while it is being sent wait for the next one.
+CREATE TABLE recipient_snapshots
+(
+    snapshot_id         uuid        PRIMARY KEY,
+    newsletter_issue_id uuid        NOT NULL UNIQUE
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    taken_at            timestamptz NOT NULL,
+    recipient_count     INTEGER     NOT NULL
+);
+CREATE TABLE recipient_snapshot_members
+(
+    snapshot_id      uuid NOT NULL
+        REFERENCES recipient_snapshots (snapshot_id) ON DELETE CASCADE,
+    subscriber_email TEXT NOT NULL,
+    PRIMARY KEY (snapshot_id, subscriber_email)
+);
+ALTER TABLE email_deliveries
+    ADD COLUMN snapshot_id uuid NULL REFERENCES recipient_snapshots (snapshot_id);
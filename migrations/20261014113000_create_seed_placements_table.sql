@@ -0,0 +1,12 @@
+-- Where the seed copy of an issue landed in each seed inbox.
+CREATE TABLE seed_placements
+(
+    newsletter_issue_id uuid        NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    seed_email          TEXT        NOT NULL,
+    sent                BOOLEAN     NOT NULL,
+    placement           TEXT        NULL CHECK (placement IN ('inbox', 'spam', 'missing')),
+    reported_by         TEXT        NULL CHECK (reported_by IN ('manual', 'webhook')),
+    reported_at         timestamptz NULL,
+    PRIMARY KEY (newsletter_issue_id, seed_email)
+);
@@ -0,0 +1,5 @@
+-- When the email provider throttles the delivery of an issue, its lane is
+-- paused until `throttled_until`.
+ALTER TABLE newsletter_issues
+    ADD COLUMN throttled_until timestamptz NULL,
+    ADD COLUMN provider_throttles INT NOT NULL DEFAULT 0;
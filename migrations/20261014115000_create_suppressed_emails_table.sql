@@ -0,0 +1,8 @@
+-- Addresses we must not send to anymore, e.g. after a hard bounce or a spam
+-- complaint.
+CREATE TABLE suppressed_emails
+(
+    email         TEXT        NOT NULL PRIMARY KEY,
+    reason        TEXT        NOT NULL,
+    suppressed_at timestamptz NOT NULL
+);
@@ -0,0 +1,12 @@
+-- The sessions admins are logged in with, so that they can be listed and
+-- revoked.
+CREATE TABLE admin_sessions
+(
+    session_id   uuid        NOT NULL PRIMARY KEY,
+    user_id      uuid        NOT NULL REFERENCES users (user_id),
+    user_agent   TEXT,
+    client_ip    TEXT,
+    created_at   timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL
+);
+CREATE INDEX admin_sessions_user_id_idx ON admin_sessions (user_id);
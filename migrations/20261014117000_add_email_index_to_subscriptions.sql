@@ -0,0 +1,3 @@
+-- The blind index of the subscriber email, to look it up once it is sealed.
+ALTER TABLE subscriptions ADD COLUMN email_index TEXT;
+CREATE UNIQUE INDEX subscriptions_email_index_key ON subscriptions (email_index);
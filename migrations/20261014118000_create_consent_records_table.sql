@@ -0,0 +1,12 @@
+-- Proof of opt-in: what each subscriber agreed to, when, and from where.
+CREATE TABLE consent_records (
+    id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    recorded_at timestamptz NOT NULL,
+    client_ip TEXT,
+    user_agent TEXT,
+    form_source TEXT,
+    consent_text_version TEXT NOT NULL
+);
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id);
@@ -0,0 +1,12 @@
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NULL;
+
+-- Translations of an issue, sent to the subscribers with a matching locale.
+CREATE TABLE newsletter_issue_variants (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, locale)
+);
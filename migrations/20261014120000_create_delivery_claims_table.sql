@@ -0,0 +1,11 @@
+-- At most one send of an issue to a subscriber. A delivery is claimed, and
+-- the claim committed, before the email goes to the provider: a task
+-- dequeued again after a worker crash or a requeue finds it taken.
+CREATE TABLE delivery_claims (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    claimed_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
+CREATE INDEX delivery_claims_subscriber_id_idx ON delivery_claims (subscriber_id);
@@ -0,0 +1,10 @@
+-- The issues subscribers asked us to send them again, from the delivery
+-- status page. Counted to rate limit the requests.
+CREATE TABLE issue_resends (
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    requested_at timestamptz NOT NULL
+);
+CREATE INDEX issue_resends_subscriber_id_idx ON issue_resends (subscriber_id, requested_at);
@@ -0,0 +1,22 @@
+-- Reusable content blocks, e.g. a sponsor block or a signature. Saving a
+-- snippet adds a version: the latest one is inserted into new issues.
+CREATE TABLE snippet_versions (
+    name TEXT NOT NULL,
+    version INT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    created_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    PRIMARY KEY (name, version)
+);
+
+-- The snippet versions an issue was published with. Their content is in
+-- the issue itself, so later versions leave it as it was sent.
+CREATE TABLE newsletter_issue_snippets (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    version INT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, name),
+    FOREIGN KEY (name, version) REFERENCES snippet_versions (name, version)
+);
@@ -0,0 +1,20 @@
+-- The sponsor of an issue, and where their tracked link leads.
+CREATE TABLE sponsor_slots (
+    slot_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL UNIQUE
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    sponsor TEXT NOT NULL,
+    target_url TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX sponsor_slots_sponsor_idx ON sponsor_slots (sponsor);
+
+-- Impressions (the slot's pixel was loaded, i.e. the email was opened)
+-- and clicks on the slot's link.
+CREATE TABLE sponsor_slot_events (
+    id BIGSERIAL PRIMARY KEY,
+    slot_id uuid NOT NULL REFERENCES sponsor_slots (slot_id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('impression', 'click')),
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX sponsor_slot_events_slot_id_idx ON sponsor_slot_events (slot_id, occurred_at);
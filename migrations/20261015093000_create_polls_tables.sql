@@ -0,0 +1,18 @@
+-- One-click polls: each option of an issue's poll is a link, and following
+-- it counts the vote of the subscriber it was sent to.
+CREATE TABLE polls (
+    poll_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL UNIQUE
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    options TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL
+);
+-- A subscriber's first vote is the one that counts.
+CREATE TABLE poll_votes (
+    poll_id uuid NOT NULL REFERENCES polls (poll_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    option_index SMALLINT NOT NULL,
+    voted_at timestamptz NOT NULL,
+    PRIMARY KEY (poll_id, subscriber_id)
+);
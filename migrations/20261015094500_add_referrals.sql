@@ -0,0 +1,14 @@
+-- Every subscriber gets a code for their share link the first time an issue
+-- shows it to them. Subscribers who signed up through a share link remember
+-- who referred them.
+ALTER TABLE subscriptions
+    ADD COLUMN referral_code TEXT NULL UNIQUE,
+    ADD COLUMN referred_by uuid NULL REFERENCES subscriptions (id) ON DELETE SET NULL;
+CREATE INDEX subscriptions_referred_by_idx ON subscriptions (referred_by);
+-- The milestones each subscriber reached, so that every reward is sent once.
+CREATE TABLE referral_rewards (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    milestone INTEGER NOT NULL,
+    reached_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, milestone)
+);
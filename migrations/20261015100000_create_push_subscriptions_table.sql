@@ -0,0 +1,12 @@
+-- The Web Push subscriptions the subscribers registered from their browsers,
+-- with the keys their notifications are encrypted for.
+CREATE TABLE push_subscriptions (
+    endpoint TEXT PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX push_subscriptions_subscriber_id_idx ON push_subscriptions (subscriber_id);
+-- Whether an issue is also sent as a push notification.
+ALTER TABLE newsletter_issues ADD COLUMN push BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,31 @@
+-- The phone number a subscriber can be texted at, in E.164 form, with the
+-- country it belongs to. Only a verified number is texted, and only once
+-- the subscriber opted in to texts.
+ALTER TABLE subscriptions
+    ADD COLUMN phone_number TEXT NULL,
+    ADD COLUMN phone_country TEXT NULL,
+    ADD COLUMN phone_verified_at timestamptz NULL,
+    ADD COLUMN sms_opted_in_at timestamptz NULL;
+-- Short announcements sent by text message.
+CREATE TABLE sms_blasts (
+    blast_id uuid PRIMARY KEY,
+    message TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    created_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL
+);
+-- The texts of a blast waiting to be sent, emptied country by country at
+-- the pace of its rate limit.
+CREATE TABLE sms_delivery_queue (
+    blast_id uuid NOT NULL REFERENCES sms_blasts (blast_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    phone_country TEXT NOT NULL,
+    PRIMARY KEY (blast_id, subscriber_id)
+);
+CREATE TABLE sms_deliveries (
+    blast_id uuid NOT NULL REFERENCES sms_blasts (blast_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    attempted_at timestamptz NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    provider_message_id TEXT NULL,
+    PRIMARY KEY (blast_id, subscriber_id)
+);
@@ -0,0 +1,11 @@
+-- A phone number waiting for its subscriber to type the code texted to it,
+-- one per subscriber. The number is stored sealed, like the verified one.
+CREATE TABLE phone_verifications (
+    subscriber_id uuid PRIMARY KEY REFERENCES subscriptions (id) ON DELETE CASCADE,
+    phone_number TEXT NOT NULL,
+    phone_country TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    sent_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,14 @@
+-- The issues to mirror to the Telegram channel, kept once posted. An issue
+-- published with the mirror turned off gets no row.
+CREATE TABLE telegram_posts (
+    newsletter_issue_id uuid PRIMARY KEY
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    posted_at timestamptz NULL,
+    message_id BIGINT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    next_attempt_at timestamptz NOT NULL
+);
+CREATE INDEX telegram_posts_pending_idx ON telegram_posts (next_attempt_at)
+    WHERE posted_at IS NULL;
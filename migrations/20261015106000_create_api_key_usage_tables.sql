@@ -0,0 +1,14 @@
+-- The requests made with each API key, counted against the quotas of its
+-- tier. Only the current minute is kept; the days are kept for the usage
+-- report.
+CREATE TABLE api_key_minute_usage (
+    key_id TEXT PRIMARY KEY,
+    minute timestamptz NOT NULL,
+    requests INT NOT NULL
+);
+CREATE TABLE api_key_daily_usage (
+    key_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    requests INT NOT NULL,
+    PRIMARY KEY (key_id, day)
+);
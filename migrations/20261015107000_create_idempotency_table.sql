@@ -0,0 +1,14 @@
+-- The responses to the requests sent with an `Idempotency-Key`, replayed
+-- when the same request is retried. `caller` is the API key id, or empty
+-- outside `/api`.
+CREATE TABLE idempotency (
+    caller TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    route TEXT NOT NULL,
+    request_hash BYTEA NOT NULL,
+    created_at timestamptz NOT NULL,
+    response_status SMALLINT NOT NULL,
+    response_headers JSONB NOT NULL,
+    response_body BYTEA NOT NULL,
+    PRIMARY KEY (caller, idempotency_key, route)
+);
@@ -0,0 +1,32 @@
+-- Admin operations too long for a single transaction. The plan is written
+-- before the first step runs; each step commits with its checkpoint, so
+-- that an interrupted operation resumes, or rolls back, where it stopped.
+CREATE TABLE admin_operations (
+    id uuid PRIMARY KEY,
+    kind TEXT NOT NULL,
+    plan JSONB NOT NULL,
+    status TEXT NOT NULL CHECK (
+        status IN (
+            'pending', 'running', 'failed', 'completed',
+            'rolling_back', 'rollback_failed', 'rolled_back'
+        )
+    ),
+    -- The steps done: the next one to run, or the last one to undo.
+    checkpoint INT NOT NULL DEFAULT 0,
+    total_steps INT NOT NULL,
+    last_error TEXT NULL,
+    requested_by uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+
+CREATE INDEX admin_operations_status_idx ON admin_operations (status, created_at);
+
+-- Whether each step changed anything, e.g. a subscriber already deleted
+-- before the operation is not restored by its rollback.
+CREATE TABLE admin_operation_steps (
+    operation_id uuid NOT NULL REFERENCES admin_operations (id) ON DELETE CASCADE,
+    step INT NOT NULL,
+    applied BOOLEAN NOT NULL,
+    PRIMARY KEY (operation_id, step)
+);
@@ -0,0 +1,45 @@
+-- The content of an issue as written, before its snippets were expanded
+-- and its sponsor slot inserted: what a clone starts from. NULL for the
+-- issues published before.
+ALTER TABLE newsletter_issues
+    ADD COLUMN source_text_content TEXT NULL,
+    ADD COLUMN source_html_content TEXT NULL;
+
+ALTER TABLE sponsor_slots ADD COLUMN message TEXT NOT NULL DEFAULT '';
+
+-- Issues waiting to be published, e.g. clones of a published one.
+CREATE TABLE newsletter_drafts (
+    draft_id uuid PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    paid_only BOOLEAN NOT NULL,
+    priority BOOLEAN NOT NULL,
+    push BOOLEAN NOT NULL,
+    sponsor TEXT NOT NULL,
+    sponsor_url TEXT NOT NULL,
+    sponsor_message TEXT NOT NULL,
+    poll_question TEXT NOT NULL,
+    -- One per line, as in the form.
+    poll_options TEXT NOT NULL,
+    cloned_from uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+    created_by uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL
+);
+
+-- The structure of an issue, to start new drafts from. Sponsors and polls
+-- are particular to an issue and are left out.
+CREATE TABLE issue_templates (
+    template_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    paid_only BOOLEAN NOT NULL,
+    priority BOOLEAN NOT NULL,
+    push BOOLEAN NOT NULL,
+    created_from uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,24 @@
+-- The feeds of the configuration, as they are checked on their schedule.
+CREATE TABLE rss_feeds (
+    name TEXT PRIMARY KEY,
+    last_checked_at timestamptz NULL,
+    last_error TEXT NULL
+);
+
+-- The entries already put in an issue or a draft, never to be sent twice.
+CREATE TABLE rss_feed_entries (
+    feed TEXT NOT NULL REFERENCES rss_feeds (name) ON DELETE CASCADE,
+    -- The guid of the entry, or its link.
+    entry_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    link TEXT NOT NULL,
+    seen_at timestamptz NOT NULL,
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE SET NULL,
+    -- Drafts are deleted once published.
+    draft_id uuid NULL,
+    PRIMARY KEY (feed, entry_id)
+);
+
+-- The drafts made from a feed have no author.
+ALTER TABLE newsletter_drafts ALTER COLUMN created_by DROP NOT NULL;
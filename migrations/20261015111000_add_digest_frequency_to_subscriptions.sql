@@ -0,0 +1,18 @@
+-- Subscribers on a digest get the issues compiled weekly or monthly,
+-- instead of each one as it is published.
+ALTER TABLE subscriptions
+    ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediate'
+        CHECK (digest_frequency IN ('immediate', 'weekly', 'monthly')),
+    -- The issues published since are in the next digest.
+    ADD COLUMN last_digest_at timestamptz NULL;
+
+CREATE INDEX subscriptions_digest_idx ON subscriptions (last_digest_at)
+    WHERE digest_frequency <> 'immediate';
+
+CREATE TABLE digest_deliveries (
+    digest_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    sent_at timestamptz NOT NULL,
+    newsletter_issue_ids uuid[] NOT NULL,
+    succeeded BOOLEAN NOT NULL
+);
@@ -0,0 +1,17 @@
+-- The audit analytics are read from hourly rollups instead of the raw
+-- requests: the changes each admin made, and the failed password logins.
+CREATE TABLE admin_activity_rollups (
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    hour SMALLINT NOT NULL CHECK (hour BETWEEN 0 AND 23),
+    actions INT NOT NULL,
+    PRIMARY KEY (user_id, day, hour)
+);
+-- Logins for usernames that do not exist are counted under ''.
+CREATE TABLE failed_login_rollups (
+    day DATE NOT NULL,
+    hour SMALLINT NOT NULL CHECK (hour BETWEEN 0 AND 23),
+    username TEXT NOT NULL,
+    failures INT NOT NULL,
+    PRIMARY KEY (day, hour, username)
+);
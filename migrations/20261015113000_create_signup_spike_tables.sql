@@ -0,0 +1,19 @@
+-- The signups per hour of each form source, '' for none, as the baseline
+-- of the spike detector.
+CREATE TABLE signup_hourly_counts (
+    hour timestamptz NOT NULL,
+    source TEXT NOT NULL,
+    signups INT NOT NULL,
+    PRIMARY KEY (hour, source)
+);
+-- The spikes detected, by hour and source, '*' for all sources together.
+-- Subscribing is strict until the latest `strict_until`.
+CREATE TABLE signup_spikes (
+    hour timestamptz NOT NULL,
+    source TEXT NOT NULL,
+    signups INT NOT NULL,
+    baseline DOUBLE PRECISION NOT NULL,
+    detected_at timestamptz NOT NULL,
+    strict_until timestamptz NOT NULL,
+    PRIMARY KEY (hour, source)
+);
@@ -0,0 +1,6 @@
+-- Signups flagged as likely bots wait in quarantine, without a
+-- confirmation email, until an admin approves or rejects them.
+ALTER TABLE subscriptions ADD COLUMN quarantine_reason TEXT;
+ALTER TABLE subscriptions ADD COLUMN quarantined_at timestamptz;
+CREATE INDEX subscriptions_quarantined_at_idx ON subscriptions (quarantined_at)
+    WHERE status = 'quarantined';
@@ -0,0 +1,17 @@
+-- The visits the operator's website reports for the readers who followed a
+-- link of an issue, along with the UTM parameters they landed with.
+CREATE TABLE landing_visits (
+    visit_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('visit', 'conversion')),
+    utm_source TEXT,
+    utm_medium TEXT,
+    utm_campaign TEXT,
+    utm_content TEXT,
+    utm_term TEXT,
+    page TEXT,
+    visited_at timestamptz NOT NULL
+);
+CREATE INDEX landing_visits_issue_idx ON landing_visits (newsletter_issue_id);
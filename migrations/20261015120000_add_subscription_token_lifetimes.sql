@@ -0,0 +1,2 @@
+ALTER TABLE subscription_tokens ADD COLUMN issued_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE subscriptions ADD COLUMN tokens_reset_at timestamptz NULL;
@@ -0,0 +1,7 @@
+-- A request is claimed before its handler runs: until its response is saved,
+-- the row has no response and the retries sent with the same key are
+-- answered with a conflict.
+ALTER TABLE idempotency
+    ALTER COLUMN response_status DROP NOT NULL,
+    ALTER COLUMN response_headers DROP NOT NULL,
+    ALTER COLUMN response_body DROP NOT NULL;
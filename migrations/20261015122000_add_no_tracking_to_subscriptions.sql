@@ -0,0 +1,3 @@
+-- Subscribers who opted out of tracking get the links to the operator's
+-- website without a click token, and their visits are not recorded.
+ALTER TABLE subscriptions ADD COLUMN no_tracking BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,337 @@
+//! Analytics over what the admins do, to help owners spot a compromised
+//! account: the changes each admin made per day and per hour, the hours
+//! that are unusual for them, and the failed password logins.
+//!
+//! Every change made under `/admin` and every failed login is counted in an
+//! hourly rollup as it happens; the analytics only ever read the rollups.
+use crate::authentication::UserId;
+use crate::database::ObserveQuery;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage};
+use actix_web_lab::middleware::Next;
+use chrono::{DateTime, Duration, NaiveDate, Timelike, Utc};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// An admin must have been active on as many other days before an hour
+/// of theirs can be unusual.
+const MIN_BASELINE_DAYS: usize = 5;
+
+#[tracing::instrument(skip(pool))]
+pub async fn record_admin_action(
+    pool: &PgPool,
+    user_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_activity_rollups (user_id, day, hour, actions)
+        VALUES ($1, $2, $3, 1)
+        ON CONFLICT (user_id, day, hour) DO UPDATE
+        SET actions = admin_activity_rollups.actions + 1
+        "#,
+        user_id,
+        now.date_naive(),
+        now.hour() as i16
+    )
+    .execute(pool)
+    .observe("record_admin_action")
+    .await?;
+    Ok(())
+}
+
+/// Usernames that do not belong to anyone are counted together, so that
+/// guessing them cannot grow the rollup without bounds.
+#[tracing::instrument(skip(pool))]
+pub async fn record_failed_login(
+    pool: &PgPool,
+    username: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_login_rollups (day, hour, username, failures)
+        VALUES (
+            $1, $2, COALESCE((SELECT username FROM users WHERE username = $3), ''), 1
+        )
+        ON CONFLICT (day, hour, username) DO UPDATE
+        SET failures = failed_login_rollups.failures + 1
+        "#,
+        now.date_naive(),
+        now.hour() as i16,
+        username
+    )
+    .execute(pool)
+    .observe("record_failed_login")
+    .await?;
+    Ok(())
+}
+
+/// Count every change the logged-in admin makes, which is every request
+/// but the ones that only read. Goes inside `reject_anonymous_users`.
+pub async fn record_admin_activity(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let reads = [Method::GET, Method::HEAD, Method::OPTIONS];
+    if !reads.contains(req.method()) {
+        let Some(user_id) = req.extensions().get::<UserId>().copied() else {
+            return Err(e500("The admin is not authenticated"));
+        };
+        let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+            return Err(e500("The connection pool is not registered"));
+        };
+        record_admin_action(&pool, *user_id, Utc::now())
+            .await
+            .map_err(e500)?;
+    }
+    next.call(req).await
+}
+
+#[derive(Clone, Debug)]
+pub struct HourlyActions {
+    pub user_id: Uuid,
+    pub username: String,
+    pub day: NaiveDate,
+    pub hour: i16,
+    pub actions: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct DailyActions {
+    pub day: NaiveDate,
+    pub actions: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct AdminActivity {
+    pub user_id: Uuid,
+    pub username: String,
+    /// Oldest first. Days without actions are left out.
+    pub daily: Vec<DailyActions>,
+    /// The actions per hour of the day, UTC, over the whole period.
+    pub heatmap: [i64; 24],
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct UnusualHour {
+    pub user_id: Uuid,
+    pub username: String,
+    pub day: NaiveDate,
+    pub hour: i16,
+    pub actions: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct DailyFailures {
+    pub day: NaiveDate,
+    pub failures: i64,
+    /// For usernames that do not belong to anyone.
+    pub unknown_usernames: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct TargetedUsername {
+    pub username: String,
+    pub failures: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct AuditAnalytics {
+    pub days: i64,
+    pub admins: Vec<AdminActivity>,
+    pub unusual_hours: Vec<UnusualHour>,
+    pub failed_logins: Vec<DailyFailures>,
+    /// The existing usernames with failed logins, most failures first.
+    pub targeted_usernames: Vec<TargetedUsername>,
+}
+
+/// Flag the hours an admin was active at although they never were, give
+/// or take an hour, on any other day of the period. Admins without enough
+/// days of activity to tell are left alone.
+pub fn flag_unusual_hours(rollups: &[HourlyActions]) -> Vec<UnusualHour> {
+    let mut by_admin: BTreeMap<Uuid, Vec<&HourlyActions>> = BTreeMap::new();
+    for rollup in rollups {
+        by_admin.entry(rollup.user_id).or_default().push(rollup);
+    }
+    let mut flagged = Vec::new();
+    for rollups in by_admin.values() {
+        for rollup in rollups {
+            let other_days: Vec<_> = rollups.iter().filter(|r| r.day != rollup.day).collect();
+            let baseline_days: BTreeSet<_> = other_days.iter().map(|r| r.day).collect();
+            if baseline_days.len() < MIN_BASELINE_DAYS {
+                continue;
+            }
+            let usual = other_days.iter().any(|r| {
+                let distance = (r.hour - rollup.hour).rem_euclid(24);
+                distance <= 1 || distance == 23
+            });
+            if !usual {
+                flagged.push(UnusualHour {
+                    user_id: rollup.user_id,
+                    username: rollup.username.clone(),
+                    day: rollup.day,
+                    hour: rollup.hour,
+                    actions: rollup.actions,
+                });
+            }
+        }
+    }
+    flagged.sort_by_key(|u| std::cmp::Reverse((u.day, u.hour)));
+    flagged
+}
+
+fn summarize_admins(rollups: &[HourlyActions]) -> Vec<AdminActivity> {
+    let mut admins: Vec<AdminActivity> = Vec::new();
+    for rollup in rollups {
+        let admin = match admins.iter_mut().find(|a| a.user_id == rollup.user_id) {
+            Some(admin) => admin,
+            None => {
+                admins.push(AdminActivity {
+                    user_id: rollup.user_id,
+                    username: rollup.username.clone(),
+                    daily: Vec::new(),
+                    heatmap: [0; 24],
+                });
+                admins.last_mut().unwrap()
+            }
+        };
+        match admin.daily.last_mut() {
+            Some(daily) if daily.day == rollup.day => daily.actions += rollup.actions as i64,
+            _ => admin.daily.push(DailyActions {
+                day: rollup.day,
+                actions: rollup.actions as i64,
+            }),
+        }
+        admin.heatmap[rollup.hour as usize] += rollup.actions as i64;
+    }
+    admins
+}
+
+/// The analytics of the last `days` days, today included.
+#[tracing::instrument(skip(pool))]
+pub async fn get_audit_analytics(
+    pool: &PgPool,
+    days: i64,
+    now: DateTime<Utc>,
+) -> Result<AuditAnalytics, sqlx::Error> {
+    let since = now.date_naive() - Duration::days(days);
+    let rollups = sqlx::query_as!(
+        HourlyActions,
+        r#"
+        SELECT r.user_id, u.username, r.day, r.hour, r.actions
+        FROM admin_activity_rollups r
+        JOIN users u USING (user_id)
+        WHERE r.day > $1
+        ORDER BY u.username, r.day, r.hour
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .observe("get_admin_activity_rollups")
+    .await?;
+    let failed_logins = sqlx::query_as!(
+        DailyFailures,
+        r#"
+        SELECT
+            day,
+            SUM(failures) AS "failures!",
+            COALESCE(SUM(failures) FILTER (WHERE username = ''), 0) AS "unknown_usernames!"
+        FROM failed_login_rollups
+        WHERE day > $1
+        GROUP BY day
+        ORDER BY day
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .observe("get_failed_login_trend")
+    .await?;
+    let targeted_usernames = sqlx::query_as!(
+        TargetedUsername,
+        r#"
+        SELECT username, SUM(failures) AS "failures!"
+        FROM failed_login_rollups
+        WHERE day > $1 AND username <> ''
+        GROUP BY username
+        ORDER BY 2 DESC, username
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .observe("get_targeted_usernames")
+    .await?;
+    Ok(AuditAnalytics {
+        days,
+        admins: summarize_admins(&rollups),
+        unusual_hours: flag_unusual_hours(&rollups),
+        failed_logins,
+        targeted_usernames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flag_unusual_hours, HourlyActions};
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    fn rollup(user_id: Uuid, day: u32, hour: i16) -> HourlyActions {
+        HourlyActions {
+            user_id,
+            username: "ursula".into(),
+            day: NaiveDate::from_ymd_opt(2026, 10, day).unwrap(),
+            hour,
+            actions: 3,
+        }
+    }
+
+    /// Active around 9 to 10 on the first days of October.
+    fn office_hours(user_id: Uuid) -> Vec<HourlyActions> {
+        (1..=6)
+            .flat_map(|day| [rollup(user_id, day, 9), rollup(user_id, day, 10)])
+            .collect()
+    }
+
+    #[test]
+    fn the_usual_hours_of_an_admin_are_not_flagged() {
+        let user_id = Uuid::new_v4();
+        let mut rollups = office_hours(user_id);
+        rollups.push(rollup(user_id, 7, 11));
+
+        assert!(flag_unusual_hours(&rollups).is_empty());
+    }
+
+    #[test]
+    fn activity_far_from_the_usual_hours_is_flagged() {
+        let user_id = Uuid::new_v4();
+        let mut rollups = office_hours(user_id);
+        rollups.push(rollup(user_id, 7, 3));
+        // Someone else's night shift says nothing about this admin.
+        let night_owl = Uuid::new_v4();
+        rollups.extend((1..=6).map(|day| rollup(night_owl, day, 3)));
+
+        let flagged = flag_unusual_hours(&rollups);
+
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].user_id, user_id);
+        assert_eq!(flagged[0].hour, 3);
+        assert_eq!(
+            flagged[0].day,
+            NaiveDate::from_ymd_opt(2026, 10, 7).unwrap()
+        );
+    }
+
+    #[test]
+    fn hours_are_not_flagged_without_enough_history() {
+        let user_id = Uuid::new_v4();
+        let mut rollups: Vec<_> = (1..=3).map(|day| rollup(user_id, day, 9)).collect();
+        rollups.push(rollup(user_id, 4, 3));
+
+        assert!(flag_unusual_hours(&rollups).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// How many events a slow WebSocket client can fall behind before it starts
+/// missing some.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A real-time event that is pushed to the admins connected to the
+/// notifications channel.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminEvent {
+    /// The channel is no place for addresses: they are sealed at rest and
+    /// scrubbed from the logs.
+    NewSubscriber {
+        subscriber_id: Uuid,
+    },
+    BounceSpike {
+        issue_id: Uuid,
+        bounce_rate: f64,
+    },
+    JobFailure {
+        job: String,
+        error: String,
+    },
+    WebhookDeliveryFailure {
+        url: String,
+        error: String,
+    },
+    SloBurn {
+        route: String,
+        burn_rate: f64,
+    },
+    SignupSpike {
+        source: String,
+        signups: i64,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AdminEventKind {
+    NewSubscriber,
+    BounceSpike,
+    JobFailure,
+    WebhookDeliveryFailure,
+    SloBurn,
+    SignupSpike,
+}
+
+impl AdminEvent {
+    pub fn kind(&self) -> AdminEventKind {
+        match self {
+            AdminEvent::NewSubscriber { .. } => AdminEventKind::NewSubscriber,
+            AdminEvent::BounceSpike { .. } => AdminEventKind::BounceSpike,
+            AdminEvent::JobFailure { .. } => AdminEventKind::JobFailure,
+            AdminEvent::WebhookDeliveryFailure { .. } => AdminEventKind::WebhookDeliveryFailure,
+            AdminEvent::SloBurn { .. } => AdminEventKind::SloBurn,
+            AdminEvent::SignupSpike { .. } => AdminEventKind::SignupSpike,
+        }
+    }
+}
+
+impl AdminEventKind {
+    pub const ALL: [AdminEventKind; 6] = [
+        AdminEventKind::NewSubscriber,
+        AdminEventKind::BounceSpike,
+        AdminEventKind::JobFailure,
+        AdminEventKind::WebhookDeliveryFailure,
+        AdminEventKind::SloBurn,
+        AdminEventKind::SignupSpike,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminEventKind::NewSubscriber => "new_subscriber",
+            AdminEventKind::BounceSpike => "bounce_spike",
+            AdminEventKind::JobFailure => "job_failure",
+            AdminEventKind::WebhookDeliveryFailure => "webhook_delivery_failure",
+            AdminEventKind::SloBurn => "slo_burn",
+            AdminEventKind::SignupSpike => "signup_spike",
+        }
+    }
+}
+
+impl TryFrom<&str> for AdminEventKind {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        AdminEventKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a supported admin event type.", s))
+    }
+}
+
+/// The set of event types a client is interested in.
+#[derive(Clone, Debug)]
+pub struct AdminEventFilter(HashSet<AdminEventKind>);
+
+impl AdminEventFilter {
+    /// Parse a comma-separated list of event types.
+    /// An empty list subscribes to every event type.
+    pub fn parse(s: &str) -> Result<AdminEventFilter, String> {
+        let kinds = s
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(AdminEventKind::try_from)
+            .collect::<Result<HashSet<_>, _>>()?;
+        if kinds.is_empty() {
+            Ok(Self::all())
+        } else {
+            Ok(Self(kinds))
+        }
+    }
+
+    pub fn all() -> AdminEventFilter {
+        Self(AdminEventKind::ALL.into_iter().collect())
+    }
+
+    pub fn matches(&self, event: &AdminEvent) -> bool {
+        self.0.contains(&event.kind())
+    }
+}
+
+/// Fan-out of admin events to every connected notification channel.
+#[derive(Clone)]
+pub struct AdminEventBroadcaster(broadcast::Sender<AdminEvent>);
+
+impl AdminEventBroadcaster {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self(sender)
+    }
+
+    pub fn publish(&self, event: AdminEvent) {
+        // Sending fails only when nobody is listening, which is fine.
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AdminEvent> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for AdminEventBroadcaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AdminEvent, AdminEventBroadcaster, AdminEventFilter};
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    fn new_subscriber() -> AdminEvent {
+        AdminEvent::NewSubscriber {
+            subscriber_id: Uuid::new_v4(),
+        }
+    }
+
+    fn job_failure() -> AdminEvent {
+        AdminEvent::JobFailure {
+            job: "newsletter_delivery".into(),
+            error: "boom".into(),
+        }
+    }
+
+    #[test]
+    fn an_empty_filter_matches_every_event() {
+        let filter = AdminEventFilter::parse("").unwrap();
+        assert!(filter.matches(&new_subscriber()));
+        assert!(filter.matches(&job_failure()));
+    }
+
+    #[test]
+    fn a_filter_only_matches_the_listed_event_types() {
+        let filter = AdminEventFilter::parse("new_subscriber, bounce_spike").unwrap();
+        assert!(filter.matches(&new_subscriber()));
+        assert!(!filter.matches(&job_failure()));
+    }
+
+    #[test]
+    fn unknown_event_types_are_rejected() {
+        assert_err!(AdminEventFilter::parse("new_subscriber,unknown"));
+    }
+
+    #[tokio::test]
+    async fn published_events_reach_every_subscriber() {
+        let broadcaster = AdminEventBroadcaster::new();
+        let mut first = broadcaster.subscribe();
+        let mut second = broadcaster.subscribe();
+
+        broadcaster.publish(job_failure());
+
+        assert_ok!(first.recv().await);
+        assert_ok!(second.recv().await);
+    }
+}
@@ -0,0 +1,178 @@
+//! The sessions admins are logged in with.
+//!
+//! The session state lives in Redis. Each admin session also gets a row
+//! here, keyed on an id stored in the session, so that an admin can see
+//! where they are logged in and end the sessions they don't recognise: a
+//! session whose row is gone is logged out on its next request.
+use crate::database::ObserveQuery;
+use crate::request_tracing::ClientIp;
+use crate::session_state::TypedSession;
+use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+/// Where a request comes from, as recorded with admin sessions and consent.
+pub struct Device {
+    pub user_agent: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+impl FromRequest for Device {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.chars().take(256).collect());
+        let client_ip = ClientIp::from_request(req, payload)
+            .into_inner()
+            .ok()
+            .map(|ip| ip.to_string());
+        ready(Ok(Self {
+            user_agent,
+            client_ip,
+        }))
+    }
+}
+
+/// Log `user_id` into `session`, ending their least recently used sessions
+/// beyond `max_sessions`.
+#[tracing::instrument(name = "Start an admin session", skip(pool, session, device))]
+pub async fn log_in(
+    pool: &PgPool,
+    session: &TypedSession,
+    device: &Device,
+    user_id: Uuid,
+    max_sessions: i64,
+) -> Result<(), anyhow::Error> {
+    // A new session key on every login defeats session fixation.
+    session.renew();
+    let session_id = Uuid::new_v4();
+    let now = Utc::now();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_sessions (session_id, user_id, user_agent, client_ip, created_at, last_seen_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        "#,
+        session_id,
+        user_id,
+        device.user_agent,
+        device.client_ip,
+        now
+    )
+    .execute(&mut *transaction)
+    .observe("insert_admin_session")
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM admin_sessions
+        WHERE session_id IN (
+            SELECT session_id FROM admin_sessions
+            WHERE user_id = $1
+            ORDER BY last_seen_at DESC, created_at DESC
+            OFFSET $2
+        )
+        "#,
+        user_id,
+        max_sessions.max(1)
+    )
+    .execute(&mut *transaction)
+    .observe("evict_admin_sessions")
+    .await?;
+    transaction.commit().await?;
+    session.insert_user_id(user_id)?;
+    session.insert_admin_session_id(session_id)?;
+    Ok(())
+}
+
+/// Record that the session is in use. Returns `false` when it has been
+/// revoked.
+pub async fn touch_session(
+    pool: &PgPool,
+    session_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE admin_sessions SET last_seen_at = $3
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id,
+        Utc::now()
+    )
+    .execute(pool)
+    .observe("touch_admin_session")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub struct AdminSession {
+    pub session_id: Uuid,
+    pub user_agent: Option<String>,
+    pub client_ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// The sessions of `user_id`, the most recently used first.
+pub async fn list_sessions(pool: &PgPool, user_id: Uuid) -> Result<Vec<AdminSession>, sqlx::Error> {
+    sqlx::query_as!(
+        AdminSession,
+        r#"
+        SELECT session_id, user_agent, client_ip, created_at, last_seen_at
+        FROM admin_sessions
+        WHERE user_id = $1
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .observe("list_admin_sessions")
+    .await
+}
+
+/// End one of the sessions of `user_id`. Returns `false` when they have no
+/// such session.
+#[tracing::instrument(name = "Revoke an admin session", skip(pool))]
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM admin_sessions WHERE session_id = $1 AND user_id = $2",
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .observe("revoke_admin_session")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// End every session of `user_id` but `current`, returning how many.
+#[tracing::instrument(name = "Revoke the other admin sessions", skip(pool))]
+pub async fn revoke_other_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM admin_sessions WHERE user_id = $1 AND session_id <> $2",
+        user_id,
+        current
+    )
+    .execute(pool)
+    .observe("revoke_other_admin_sessions")
+    .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,325 @@
+//! The keys of the callers of `/api`, and the quotas of their tier.
+//!
+//! Every request made with a key counts against the requests per minute
+//! and per day of its tier, rejected ones included. The counts live in the
+//! database, so that they hold across instances.
+use crate::configuration::{ApiSettings, ApiTierSettings};
+use crate::database::ObserveQuery;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{web, HttpMessage, HttpResponse};
+use actix_web_lab::middleware::Next;
+use chrono::{DateTime, Duration, DurationRound, NaiveDate, Utc};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/// The days of the usage report.
+const USAGE_REPORT_DAYS: i64 = 30;
+
+#[derive(Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub tier: String,
+    pub limits: ApiTierSettings,
+    digest: Vec<u8>,
+}
+
+pub struct ApiKeys(Vec<ApiKey>);
+
+impl ApiKeys {
+    pub fn new(settings: &ApiSettings) -> Result<Self, String> {
+        let mut ids = HashSet::new();
+        let mut keys = Vec::new();
+        for key in &settings.keys {
+            if !ids.insert(key.id.as_str()) {
+                return Err(format!("the key id {} is used twice", key.id));
+            }
+            let Some(limits) = settings.tiers.get(&key.tier) else {
+                return Err(format!(
+                    "the key {} has an unknown tier, {}",
+                    key.id, key.tier
+                ));
+            };
+            keys.push(ApiKey {
+                id: key.id.clone(),
+                tier: key.tier.clone(),
+                limits: *limits,
+                digest: Sha256::digest(key.key.expose_secret()).to_vec(),
+            });
+        }
+        Ok(Self(keys))
+    }
+
+    /// The key a caller presented, if it is one of ours. Comparing digests
+    /// keeps the comparison time independent of the key.
+    pub fn authenticate(&self, key: &str) -> Option<&ApiKey> {
+        let digest = Sha256::digest(key);
+        self.0
+            .iter()
+            .find(|valid| digest.as_slice() == valid.digest)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ApiKey> {
+        self.0.iter().find(|key| key.id == id)
+    }
+}
+
+/// Where a key stands against the quota closest to running out.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+    pub exceeded: bool,
+}
+
+impl RateLimitStatus {
+    /// `requests_this_minute` and `requests_today` count the current
+    /// request.
+    pub fn new(
+        limits: &ApiTierSettings,
+        requests_this_minute: u32,
+        requests_today: u32,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let minute = Self::window(
+            limits.requests_per_minute,
+            requests_this_minute,
+            now.duration_trunc(Duration::minutes(1)).unwrap() + Duration::minutes(1),
+        );
+        let day = Self::window(
+            limits.requests_per_day,
+            requests_today,
+            now.duration_trunc(Duration::days(1)).unwrap() + Duration::days(1),
+        );
+        // An exhausted day outlasts an exhausted minute.
+        if day.exceeded || (!minute.exceeded && day.remaining < minute.remaining) {
+            day
+        } else {
+            minute
+        }
+    }
+
+    fn window(limit: u32, requests: u32, reset: DateTime<Utc>) -> Self {
+        Self {
+            limit,
+            remaining: limit.saturating_sub(requests),
+            reset,
+            exceeded: requests > limit,
+        }
+    }
+
+    /// `X-RateLimit-Reset` is the end of the window, in seconds since the
+    /// epoch.
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        for (name, value) in [
+            ("x-ratelimit-limit", self.limit.to_string()),
+            ("x-ratelimit-remaining", self.remaining.to_string()),
+            ("x-ratelimit-reset", self.reset.timestamp().to_string()),
+        ] {
+            headers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_str(&value).unwrap(),
+            );
+        }
+    }
+}
+
+/// Count a request made with `key_id`. Returns the requests made this
+/// minute and today, this one included.
+#[tracing::instrument(skip(pool))]
+pub async fn record_api_request(
+    pool: &PgPool,
+    key_id: &str,
+    now: DateTime<Utc>,
+) -> Result<(u32, u32), sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        WITH minute AS (
+            INSERT INTO api_key_minute_usage (key_id, minute, requests)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (key_id) DO UPDATE SET
+                requests = CASE
+                    WHEN api_key_minute_usage.minute = EXCLUDED.minute
+                    THEN api_key_minute_usage.requests + 1
+                    ELSE 1
+                END,
+                minute = EXCLUDED.minute
+            RETURNING requests
+        ), day AS (
+            INSERT INTO api_key_daily_usage (key_id, day, requests)
+            VALUES ($1, $3, 1)
+            ON CONFLICT (key_id, day) DO UPDATE
+            SET requests = api_key_daily_usage.requests + 1
+            RETURNING requests
+        )
+        SELECT minute.requests AS "this_minute!", day.requests AS "today!"
+        FROM minute, day
+        "#,
+        key_id,
+        now.duration_trunc(Duration::minutes(1)).unwrap(),
+        now.date_naive()
+    )
+    .fetch_one(pool)
+    .observe_one("record_api_request")
+    .await?;
+    Ok((r.this_minute as u32, r.today as u32))
+}
+
+/// Enforce the quotas of the key `reject_invalid_api_key` let through, and
+/// tell the caller where they stand with the `X-RateLimit-*` headers.
+pub async fn limit_api_key_rate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(key) = req.extensions().get::<ApiKey>().cloned() else {
+        return Err(e500("The API key is not authenticated"));
+    };
+    let Some(pool) = req.app_data::<web::Data<PgPool>>().cloned() else {
+        return Err(e500("The connection pool is not registered"));
+    };
+    let now = Utc::now();
+    let (this_minute, today) = record_api_request(&pool, &key.id, now)
+        .await
+        .map_err(e500)?;
+    let status = RateLimitStatus::new(&key.limits, this_minute, today, now);
+    if status.exceeded {
+        let retry_after = (status.reset - now).num_seconds().max(1);
+        let mut response = HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .finish();
+        status.insert_headers(response.headers_mut());
+        let e = anyhow::anyhow!("The API key {} is over its quota", key.id);
+        return Err(InternalError::from_response(e, response).into());
+    }
+    let mut response = next.call(req).await?;
+    status.insert_headers(response.headers_mut());
+    Ok(response)
+}
+
+#[derive(serde::Serialize)]
+pub struct DailyUsage {
+    pub day: NaiveDate,
+    pub requests: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiKeyUsage {
+    pub key_id: String,
+    pub tier: String,
+    pub requests_per_minute: u32,
+    pub requests_per_day: u32,
+    pub requests_this_minute: i32,
+    pub requests_today: i32,
+    /// Over the last 30 days, most recent first. Days without requests
+    /// are left out.
+    pub daily: Vec<DailyUsage>,
+}
+
+#[tracing::instrument(skip(pool, key), fields(key_id = %key.id))]
+pub async fn get_api_key_usage(
+    pool: &PgPool,
+    key: &ApiKey,
+    now: DateTime<Utc>,
+) -> Result<ApiKeyUsage, sqlx::Error> {
+    let today = now.date_naive();
+    let daily = sqlx::query_as!(
+        DailyUsage,
+        r#"
+        SELECT day, requests FROM api_key_daily_usage
+        WHERE key_id = $1 AND day > $2
+        ORDER BY day DESC
+        "#,
+        key.id,
+        today - Duration::days(USAGE_REPORT_DAYS)
+    )
+    .fetch_all(pool)
+    .observe("get_api_key_daily_usage")
+    .await?;
+    let this_minute = sqlx::query!(
+        "SELECT requests FROM api_key_minute_usage WHERE key_id = $1 AND minute = $2",
+        key.id,
+        now.duration_trunc(Duration::minutes(1)).unwrap()
+    )
+    .fetch_optional(pool)
+    .observe("get_api_key_minute_usage")
+    .await?;
+    Ok(ApiKeyUsage {
+        key_id: key.id.clone(),
+        tier: key.tier.clone(),
+        requests_per_minute: key.limits.requests_per_minute,
+        requests_per_day: key.limits.requests_per_day,
+        requests_this_minute: this_minute.map_or(0, |r| r.requests),
+        requests_today: daily
+            .iter()
+            .find(|d| d.day == today)
+            .map_or(0, |d| d.requests),
+        daily,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKeys, RateLimitStatus};
+    use crate::configuration::{ApiKeySettings, ApiSettings, ApiTierSettings};
+    use chrono::{TimeZone, Utc};
+    use secrecy::Secret;
+
+    const LIMITS: ApiTierSettings = ApiTierSettings {
+        requests_per_minute: 10,
+        requests_per_day: 100,
+    };
+
+    #[test]
+    fn the_window_closest_to_running_out_is_reported() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 15, 12, 30, 20).unwrap();
+
+        let status = RateLimitStatus::new(&LIMITS, 3, 50, now);
+        assert_eq!(status.remaining, 7);
+        assert_eq!(
+            status.reset,
+            Utc.with_ymd_and_hms(2026, 10, 15, 12, 31, 0).unwrap()
+        );
+        assert!(!status.exceeded);
+
+        let status = RateLimitStatus::new(&LIMITS, 3, 95, now);
+        assert_eq!((status.limit, status.remaining), (100, 5));
+        assert_eq!(
+            status.reset,
+            Utc.with_ymd_and_hms(2026, 10, 16, 0, 0, 0).unwrap()
+        );
+
+        let status = RateLimitStatus::new(&LIMITS, 11, 95, now);
+        assert_eq!((status.limit, status.remaining), (10, 0));
+        assert!(status.exceeded);
+
+        let status = RateLimitStatus::new(&LIMITS, 11, 101, now);
+        assert_eq!(status.limit, 100);
+        assert!(status.exceeded);
+    }
+
+    #[test]
+    fn keys_must_have_a_known_tier_and_a_unique_id() {
+        let key = |id: &str, tier: &str| ApiKeySettings {
+            id: id.into(),
+            key: Secret::new(format!("{}-key", id)),
+            tier: tier.into(),
+        };
+        let settings = |keys| ApiSettings {
+            keys,
+            tiers: [("free".to_string(), LIMITS)].into(),
+        };
+
+        let keys = ApiKeys::new(&settings(vec![key("a", "free"), key("b", "free")])).unwrap();
+        assert_eq!(keys.authenticate("b-key").unwrap().id, "b");
+        assert!(keys.authenticate("c-key").is_none());
+
+        assert!(ApiKeys::new(&settings(vec![key("a", "gold")])).is_err());
+        assert!(ApiKeys::new(&settings(vec![key("a", "free"), key("a", "free")])).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Double-submit cookie protection against cross-site request forgery.
+//!
+//! Every admin response makes sure the browser holds a random token in a
+//! cookie, and every form rendered under `/admin` carries the same token in
+//! a hidden field. A state-changing request only goes through when it
+//! submits the token of its cookie, as that field or in the `X-CSRF-Token`
+//! header: another site can make the browser send the cookie, but it cannot
+//! read it to fill in the field.
+use crate::authentication::tokens_match;
+use crate::utils::e500;
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{web, FromRequest, HttpMessage};
+use actix_web_lab::middleware::Next;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_FIELD: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const TOKEN_LENGTH: usize = 32;
+
+pub async fn protect_against_csrf(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // The token ends up in the rendered HTML: anything but a token we could
+    // have issued is replaced.
+    let cookie_token = req
+        .cookie(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .filter(|token| is_well_formed(token));
+    if !req.method().is_safe() {
+        let submitted = submitted_token(&mut req).await?;
+        let valid = matches!(
+            (&cookie_token, &submitted),
+            (Some(expected), Some(submitted)) if tokens_match(submitted, expected)
+        );
+        if !valid {
+            return Err(actix_web::error::ErrorForbidden(
+                "Missing or mismatched CSRF token",
+            ));
+        }
+    }
+    let (token, is_new) = match cookie_token {
+        Some(token) => (token, false),
+        None => (generate_csrf_token(), true),
+    };
+    let mut response = add_token_to_html(next.call(req).await?, &token).await?;
+    if is_new {
+        let cookie = Cookie::build(CSRF_COOKIE, token)
+            .path("/admin")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Strict)
+            .finish();
+        response.response_mut().add_cookie(&cookie).map_err(e500)?;
+    }
+    Ok(response)
+}
+
+fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(TOKEN_LENGTH)
+        .collect()
+}
+
+fn is_well_formed(token: &str) -> bool {
+    token.len() == TOKEN_LENGTH && token.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// The token sent in the header or, failing that, in the form body. The body
+/// is put back for the handler to read.
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(header) = req.headers().get(CSRF_HEADER) {
+        return Ok(header.to_str().ok().map(str::to_owned));
+    }
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(None);
+    }
+    let body = {
+        let (http_request, payload) = req.parts_mut();
+        web::Bytes::from_request(http_request, payload).await?
+    };
+    let token = form_field(&body, CSRF_FIELD);
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(Payload::from(payload));
+    Ok(token)
+}
+
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    std::str::from_utf8(body)
+        .ok()?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| {
+            urlencoding::decode(&value.replace('+', " "))
+                .ok()
+                .map(|value| value.into_owned())
+        })
+}
+
+async fn add_token_to_html<B: MessageBody + 'static>(
+    response: ServiceResponse<B>,
+    token: &str,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.starts_with("text/html"));
+    if !is_html {
+        return Ok(response.map_into_boxed_body());
+    }
+    let (request, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = to_bytes(body)
+        .await
+        .map_err(|e| e500(e.into().to_string()))?;
+    let html = add_token_to_forms(&String::from_utf8_lossy(&body), token);
+    let response = response.set_body(html).map_into_boxed_body();
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// Add the token as a hidden field to every POST form of `html`.
+fn add_token_to_forms(html: &str, token: &str) -> String {
+    let field = format!(r#"<input hidden type="text" name="{CSRF_FIELD}" value="{token}">"#);
+    // Lower-casing ASCII keeps the byte offsets of `html`.
+    let lowercase = html.to_ascii_lowercase();
+    let mut rendered = String::with_capacity(html.len());
+    let mut copied = 0;
+    let mut searched = 0;
+    while let Some(start) = lowercase[searched..].find("<form").map(|i| searched + i) {
+        let Some(end) = lowercase[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let tag = &lowercase[start..end];
+        let is_post_form = tag[5..].starts_with(|c: char| c.is_ascii_whitespace())
+            && ["method=\"post\"", "method='post'", "method=post"]
+                .iter()
+                .any(|method| tag.contains(method));
+        if is_post_form {
+            rendered.push_str(&html[copied..end]);
+            rendered.push_str(&field);
+            copied = end;
+        }
+        searched = end;
+    }
+    rendered.push_str(&html[copied..]);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_token_to_forms, form_field, is_well_formed};
+
+    #[test]
+    fn only_post_forms_get_the_token() {
+        let html =
+            r#"<FORM action="/a" method="POST"><input></FORM><form action="/b"></form><formula>"#;
+        assert_eq!(
+            add_token_to_forms(html, "t"),
+            r#"<FORM action="/a" method="POST"><input hidden type="text" name="csrf_token" value="t"><input></FORM><form action="/b"></form><formula>"#
+        );
+    }
+
+    #[test]
+    fn the_token_is_read_from_a_form_body() {
+        assert_eq!(
+            form_field(b"title=a+b&csrf_token=abc%31", "csrf_token").as_deref(),
+            Some("abc1")
+        );
+        assert_eq!(form_field(b"title=a", "csrf_token"), None);
+    }
+
+    #[test]
+    fn only_tokens_we_could_have_issued_are_accepted_from_the_cookie() {
+        assert!(is_well_formed("aB3dE5gH7jK9mN1pQ3sT5vW7yZ9bC1dE"));
+        assert!(!is_well_formed(r#""><script>alert(1)</script>aaaaaaa"#));
+        assert!(!is_well_formed("short"));
+    }
+}
@@ -1,10 +1,18 @@
+use crate::admin_sessions::touch_session;
+use crate::api_keys::ApiKeys;
+use crate::authentication::{bearer_token, tokens_match};
+use crate::configuration::ScimSettings;
+use crate::database::{retry_read, ObserveQuery};
+use crate::request_tracing::{AdminAllowlist, ClientIp};
 use crate::session_state::TypedSession;
 use crate::utils::{e500, see_other};
 use actix_web::body::MessageBody;
-use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
 use actix_web_lab::middleware::Next;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -34,11 +42,27 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = session.get_user_id().map_err(e500)?;
+    let session_id = session.get_admin_session_id().map_err(e500)?;
+    let active = match (user_id, session_id, req.app_data::<web::Data<PgPool>>()) {
+        (Some(user_id), Some(session_id), Some(pool)) => is_logged_in(pool, user_id, session_id)
+            .await
+            .map_err(e500)?,
+        _ => false,
+    };
+    match user_id {
+        Some(user_id) if active => {
             req.extensions_mut().insert(UserId(user_id));
             next.call(req).await
         }
+        Some(_) => {
+            // The account was deactivated, or the session revoked, after
+            // logging in.
+            session.log_out();
+            let response = see_other("/login");
+            let e = anyhow::anyhow!("The user or their session is no longer active");
+            Err(InternalError::from_response(e, response).into())
+        }
         None => {
             let response = see_other("/login");
             let e = anyhow::anyhow!("The user has not logged in");
@@ -46,3 +70,81 @@ pub async fn reject_anonymous_users(
         }
     }
 }
+
+/// Hide `/admin` from the clients outside of the allowlist, when there is
+/// one: they get the same 404 as for a route that does not exist.
+pub async fn reject_disallowed_admin_clients(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(allowlist) = req.app_data::<web::Data<AdminAllowlist>>() {
+        let client_ip = ClientIp::from_request(req.request(), &mut Payload::None)
+            .into_inner()
+            .ok();
+        if !client_ip.is_some_and(|ip| allowlist.allows(ip.0)) {
+            let e = anyhow::anyhow!("The client is not on the admin allowlist");
+            return Err(InternalError::from_response(e, HttpResponse::NotFound().finish()).into());
+        }
+    }
+    next.call(req).await
+}
+
+/// Only let the identity provider through to the SCIM endpoints.
+pub async fn reject_invalid_scim_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(scim) = req.app_data::<web::Data<ScimSettings>>() else {
+        return Err(actix_web::error::ErrorNotFound(
+            "SCIM provisioning is not enabled",
+        ));
+    };
+    let valid = bearer_token(req.headers())
+        .is_some_and(|token| tokens_match(token, scim.bearer_token.expose_secret()));
+    if !valid {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Invalid SCIM bearer token",
+        ));
+    }
+    next.call(req).await
+}
+
+/// Only let callers holding one of the configured keys through to `/api`.
+/// The key they presented goes into the request extensions, for
+/// `limit_api_key_rate`.
+pub async fn reject_invalid_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(api_keys) = req.app_data::<web::Data<ApiKeys>>() else {
+        return Err(actix_web::error::ErrorNotFound("The API is not enabled"));
+    };
+    let key = bearer_token(req.headers())
+        .and_then(|key| api_keys.authenticate(key))
+        .cloned();
+    let Some(key) = key else {
+        return Err(actix_web::error::ErrorUnauthorized("Invalid API key"));
+    };
+    req.extensions_mut().insert(key);
+    next.call(req).await
+}
+
+/// Whether `user_id` is still active and their session `session_id` was not
+/// revoked. Seeing the session marks it as in use.
+pub async fn is_logged_in(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    Ok(is_active(pool, user_id).await? && touch_session(pool, session_id, user_id).await?)
+}
+
+async fn is_active(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = retry_read(|| {
+        sqlx::query!(r#"SELECT active FROM users WHERE user_id = $1"#, user_id)
+            .fetch_optional(pool)
+            .observe("is_user_active")
+    })
+    .await?;
+    Ok(row.is_some_and(|row| row.active))
+}
@@ -1,5 +1,15 @@
+mod csrf;
 mod middleware;
+pub use csrf::{protect_against_csrf, CSRF_COOKIE, CSRF_FIELD, CSRF_HEADER};
 mod password;
+mod roles;
+mod tokens;
+pub use middleware::is_logged_in;
 pub use middleware::reject_anonymous_users;
+pub use middleware::reject_disallowed_admin_clients;
+pub use middleware::reject_invalid_api_key;
+pub use middleware::reject_invalid_scim_token;
 pub use middleware::UserId;
 pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use roles::{is_owner, OWNER_ROLE};
+pub use tokens::{bearer_token, tokens_match};
@@ -1,3 +1,4 @@
+use crate::database::{retry_read, ObserveQuery};
 use crate::telemetry::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
@@ -23,18 +24,22 @@ async fn get_stored_credentials(
     username: &str,
     pool: &PgPool,
 ) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
-    let row = sqlx::query!(
-        r#"
-        SELECT user_id, password_hash
-        FROM users
-        WHERE username = $1
-        "#,
-        username,
-    )
-    .fetch_optional(pool)
+    let row = retry_read(|| {
+        sqlx::query!(
+            r#"
+            SELECT user_id, password_hash
+            FROM users
+            WHERE username = $1 AND active
+            "#,
+            username,
+        )
+        .fetch_optional(pool)
+        .observe("get_stored_credentials")
+    })
     .await
     .context("Failed to performed a query to retrieve stored credentials.")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    // Users provisioned through single sign-on can't log in with a password.
+    .and_then(|row| Some((row.user_id, Secret::new(row.password_hash?))));
     Ok(row)
 }
 
@@ -108,6 +113,7 @@ pub async fn change_password(
         user_id
     )
     .execute(pool)
+    .observe("change_password")
     .await
     .context("Failed to change user's password in the database.")?;
     Ok(())
@@ -0,0 +1,18 @@
+//! The roles of the admins, set by hand or mapped from the groups of the
+//! single sign-on provider. Every role can use the admin panel: only the
+//! actions showing what a subscriber sees are reserved to owners.
+use crate::database::{retry_read, ObserveQuery};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const OWNER_ROLE: &str = "owner";
+
+pub async fn is_owner(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let row = retry_read(|| {
+        sqlx::query!(r#"SELECT role FROM users WHERE user_id = $1"#, user_id)
+            .fetch_optional(pool)
+            .observe("get_user_role")
+    })
+    .await?;
+    Ok(row.is_some_and(|row| row.role == OWNER_ROLE))
+}
@@ -0,0 +1,39 @@
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use sha2::{Digest, Sha256};
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+}
+
+/// Whether a presented token is the expected one. Comparing digests keeps
+/// the comparison time independent of the tokens.
+pub fn tokens_match(presented: &str, expected: &str) -> bool {
+    Sha256::digest(presented) == Sha256::digest(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bearer_token, tokens_match};
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    #[test]
+    fn only_bearer_credentials_are_read() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Basic dXNlcg=="));
+        assert_eq!(bearer_token(&headers), None);
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        assert_eq!(bearer_token(&headers), Some("secret"));
+    }
+
+    #[test]
+    fn tokens_match_only_themselves() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("", "secret"));
+    }
+}
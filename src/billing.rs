@@ -0,0 +1,271 @@
+//! Paid subscriptions through Stripe Checkout.
+use crate::configuration::BillingSettings;
+use crate::database::ObserveQuery;
+use crate::startup::StartupError;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub struct StripeClient {
+    http_client: Client,
+    base_url: String,
+    secret_key: Secret<String>,
+    webhook_secret: Secret<String>,
+    price_id: String,
+    success_url: String,
+    cancel_url: String,
+    signature_tolerance: Duration,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub url: String,
+}
+
+impl StripeClient {
+    pub fn new(settings: BillingSettings) -> Result<Self, StartupError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .map_err(|e| StartupError::HttpClient("Stripe client", e))?;
+        Ok(Self {
+            http_client,
+            base_url: settings.api_base_url,
+            secret_key: settings.secret_key,
+            webhook_secret: settings.webhook_secret,
+            price_id: settings.price_id,
+            success_url: settings.success_url,
+            cancel_url: settings.cancel_url,
+            signature_tolerance: Duration::from_secs(settings.signature_tolerance_seconds),
+        })
+    }
+
+    /// Start a Checkout session for the paid tier. The subscriber id travels
+    /// as the client reference, so that the webhook knows whom to upgrade.
+    #[tracing::instrument(name = "Create a Stripe checkout session", skip(self, email))]
+    pub async fn create_checkout_session(
+        &self,
+        subscriber_id: Uuid,
+        email: &str,
+    ) -> Result<CheckoutSession, reqwest::Error> {
+        let subscriber_id = subscriber_id.to_string();
+        self.http_client
+            .post(format!("{}/v1/checkout/sessions", self.base_url))
+            .bearer_auth(self.secret_key.expose_secret())
+            .form(&[
+                ("mode", "subscription"),
+                ("line_items[0][price]", self.price_id.as_str()),
+                ("line_items[0][quantity]", "1"),
+                ("customer_email", email),
+                ("client_reference_id", subscriber_id.as_str()),
+                ("success_url", self.success_url.as_str()),
+                ("cancel_url", self.cancel_url.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /// Check the `Stripe-Signature` header of a webhook delivery.
+    pub fn verify_signature(
+        &self,
+        payload: &[u8],
+        header: &str,
+        now: i64,
+    ) -> Result<(), SignatureError> {
+        verify_signature(
+            payload,
+            header,
+            &self.webhook_secret,
+            self.signature_tolerance,
+            now,
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("The signature header is malformed")]
+    Malformed,
+    #[error("The signature is too old")]
+    Expired,
+    #[error("No signature matches the payload")]
+    Mismatch,
+}
+
+/// Stripe signs `<timestamp>.<payload>` with HMAC-SHA256 and sends
+/// `t=<timestamp>,v1=<hex signature>` - with one `v1` entry per active secret.
+pub fn verify_signature(
+    payload: &[u8],
+    header: &str,
+    secret: &Secret<String>,
+    tolerance: Duration,
+    now: i64,
+) -> Result<(), SignatureError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for (key, value) in header.split(',').filter_map(|pair| pair.split_once('=')) {
+        match key.trim() {
+            "t" => timestamp = value.parse::<i64>().ok(),
+            "v1" => signatures.push(hex::decode(value).map_err(|_| SignatureError::Malformed)?),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or(SignatureError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(SignatureError::Malformed);
+    }
+    if now.abs_diff(timestamp) > tolerance.as_secs() {
+        return Err(SignatureError::Expired);
+    }
+    let matches = signatures.iter().any(|signature| {
+        signer(secret, timestamp, payload)
+            .verify_slice(signature)
+            .is_ok()
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+fn signer(secret: &Secret<String>, timestamp: i64, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+/// Compute the `Stripe-Signature` header for `payload`.
+pub fn sign(payload: &[u8], secret: &Secret<String>, timestamp: i64) -> String {
+    let signature = signer(secret, timestamp, payload).finalize().into_bytes();
+    format!("t={},v1={}", timestamp, hex::encode(signature))
+}
+
+/// The subset of the Stripe events we act upon.
+#[derive(serde::Deserialize)]
+pub struct WebhookEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub data: WebhookEventData,
+}
+
+#[derive(serde::Deserialize)]
+pub struct WebhookEventData {
+    pub object: serde_json::Value,
+}
+
+#[tracing::instrument(name = "Grant the paid tier to a subscriber", skip(pool))]
+pub async fn grant_paid(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    customer_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET paid = true, stripe_customer_id = $2
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        customer_id
+    )
+    .execute(pool)
+    .observe("grant_paid")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Update the paid tier of a Stripe customer", skip(pool))]
+pub async fn set_paid_for_customer(
+    pool: &PgPool,
+    customer_id: &str,
+    paid: bool,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET paid = $2
+        WHERE stripe_customer_id = $1
+        "#,
+        customer_id,
+        paid
+    )
+    .execute(pool)
+    .observe("set_paid_for_customer")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify_signature, SignatureError};
+    use claims::{assert_err_eq, assert_ok};
+    use secrecy::Secret;
+    use std::time::Duration;
+
+    const TOLERANCE: Duration = Duration::from_secs(300);
+
+    fn secret() -> Secret<String> {
+        Secret::new("whsec_test".into())
+    }
+
+    #[test]
+    fn a_fresh_signature_is_accepted() {
+        let header = sign(b"{}", &secret(), 1_000);
+        assert_ok!(verify_signature(
+            b"{}",
+            &header,
+            &secret(),
+            TOLERANCE,
+            1_100
+        ));
+    }
+
+    #[test]
+    fn any_of_several_signatures_can_match() {
+        let header = sign(b"{}", &secret(), 1_000).replace("v1=", "v1=00,v1=");
+        assert_ok!(verify_signature(
+            b"{}",
+            &header,
+            &secret(),
+            TOLERANCE,
+            1_000
+        ));
+    }
+
+    #[test]
+    fn a_tampered_payload_is_rejected() {
+        let header = sign(b"{}", &secret(), 1_000);
+        assert_err_eq!(
+            verify_signature(b"{ }", &header, &secret(), TOLERANCE, 1_000),
+            SignatureError::Mismatch
+        );
+    }
+
+    #[test]
+    fn an_old_signature_is_rejected() {
+        let header = sign(b"{}", &secret(), 1_000);
+        assert_err_eq!(
+            verify_signature(b"{}", &header, &secret(), TOLERANCE, 2_000),
+            SignatureError::Expired
+        );
+    }
+
+    #[test]
+    fn a_header_without_signatures_is_malformed() {
+        assert_err_eq!(
+            verify_signature(b"{}", "t=1000", &secret(), TOLERANCE, 1_000),
+            SignatureError::Malformed
+        );
+    }
+}
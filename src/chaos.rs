@@ -0,0 +1,106 @@
+//! Faults injected on purpose, to watch the retries, the provider failover
+//! and the circuit breakers at work before production does.
+//!
+//! Only built with the `chaos` feature. Nothing is injected until the faults
+//! are set through `/admin/chaos`, which is only mounted in the local
+//! environment.
+use crate::configuration::Environment;
+use rand::Rng;
+use std::sync::RwLock;
+use std::time::Duration;
+
+static FAULTS: RwLock<Faults> = RwLock::new(Faults {
+    email_failure_rate: 0.0,
+    db_latency_milliseconds: 0,
+    webhook_drop_rate: 0.0,
+});
+
+/// The faults left out of a request are cleared.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    /// The share of the requests to the email providers that fail, as if the
+    /// provider was down.
+    pub email_failure_rate: f64,
+    /// Added to every database query.
+    pub db_latency_milliseconds: u64,
+    /// The share of the admin notifications lost on their way to the webhook.
+    pub webhook_drop_rate: f64,
+}
+
+impl Faults {
+    fn validate(&self) -> Result<(), String> {
+        for (name, rate) in [
+            ("email_failure_rate", self.email_failure_rate),
+            ("webhook_drop_rate", self.webhook_drop_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("{} must be between 0 and 1.", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Faults can only be injected in the local environment.
+pub fn allowed() -> bool {
+    matches!(Environment::current(), Ok(Environment::Local))
+}
+
+pub fn current() -> Faults {
+    FAULTS.read().unwrap().clone()
+}
+
+pub fn set(faults: Faults) -> Result<(), String> {
+    faults.validate()?;
+    *FAULTS.write().unwrap() = faults;
+    Ok(())
+}
+
+pub fn fail_email() -> bool {
+    happens(FAULTS.read().unwrap().email_failure_rate)
+}
+
+pub fn drop_webhook() -> bool {
+    happens(FAULTS.read().unwrap().webhook_drop_rate)
+}
+
+pub async fn delay_query() {
+    let latency = FAULTS.read().unwrap().db_latency_milliseconds;
+    if latency > 0 {
+        tokio::time::sleep(Duration::from_millis(latency)).await;
+    }
+}
+
+fn happens(rate: f64) -> bool {
+    rate > 0.0 && rand::thread_rng().gen_bool(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{happens, Faults};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn rates_must_be_between_0_and_1() {
+        for rate in [-0.1, 1.5, f64::NAN] {
+            let faults = Faults {
+                email_failure_rate: rate,
+                ..Faults::default()
+            };
+            assert_err!(faults.validate());
+        }
+        assert_ok!(Faults {
+            email_failure_rate: 1.0,
+            webhook_drop_rate: 0.5,
+            db_latency_milliseconds: 100,
+        }
+        .validate());
+    }
+
+    #[test]
+    fn faults_happen_at_their_rate() {
+        assert!(!happens(0.0));
+        assert!(happens(1.0));
+    }
+}
@@ -1,35 +1,178 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{DomainValidationError, SubscriberEmail};
+use crate::email_client::{EmailClient, MessageStreams};
+use crate::i18n::Language;
+use crate::startup::StartupError;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use sqlx::ConnectOptions;
+use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 
-#[derive(serde::Deserialize, Clone)]
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_failover: Option<EmailFailoverSettings>,
+    #[schemars(with = "String")]
     pub redis_uri: Secret<String>,
+    pub events: EventsSettings,
+    pub notifications: Option<NotificationSettings>,
+    pub delivery_alerts: Option<DeliveryAlertSettings>,
+    pub delivery_lanes: DeliveryLaneSettings,
+    pub delivery_enqueue: DeliveryEnqueueSettings,
+    pub domain_throttling: DomainThrottlingSettings,
+    pub warm_up: Option<WarmUpSettings>,
+    pub deliverability: DeliverabilitySettings,
+    pub send_quota: Option<SendQuotaSettings>,
+    pub subscriber_retention: SubscriberRetentionSettings,
+    #[serde(default)]
+    pub data_retention: DataRetentionSettings,
+    pub billing: Option<BillingSettings>,
+    pub magic_links: MagicLinkSettings,
+    pub subscription_tokens: SubscriptionTokenSettings,
+    pub login: LoginSettings,
+    pub oidc: Option<OidcSettings>,
+    pub scim: Option<ScimSettings>,
+    pub link_checker: LinkCheckerSettings,
+    pub spam_check: Option<SpamCheckSettings>,
+    pub image_proxy: ImageProxySettings,
+    /// Log filter directives, e.g. `info` or `info,sqlx=warn`. `RUST_LOG`
+    /// takes precedence when set.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    #[serde(default)]
+    pub log_scrubbing: LogScrubbingSettings,
+    #[serde(default)]
+    pub network: NetworkSettings,
+    #[serde(default)]
+    pub admin: AdminSettings,
+    pub slo: SloSettings,
+    pub load_shedding: LoadSheddingSettings,
+    pub seed_list: Option<SeedListSettings>,
+    pub delivery_events: Option<DeliveryEventsSettings>,
+    pub api: Option<ApiSettings>,
+    pub idempotency: IdempotencySettings,
+    pub email_verification: EmailVerificationSettings,
+    #[serde(default)]
+    pub subscriber_redirects: SubscriberRedirectSettings,
+    /// Public signup pages served under `/l/{slug}`.
+    #[serde(default)]
+    pub signup_pages: Vec<SignupPageSettings>,
+    pub http_cache: HttpCacheSettings,
+    pub theme: ThemeSettings,
+    pub pii_encryption: Option<PiiEncryptionSettings>,
+    pub consent: ConsentSettings,
+    pub resends: ResendSettings,
+    #[serde(default)]
+    pub i18n: I18nSettings,
+    #[serde(default)]
+    pub referrals: ReferralSettings,
+    pub web_push: Option<WebPushSettings>,
+    pub sms: Option<SmsSettings>,
+    pub telegram: Option<TelegramSettings>,
+    pub rss: Option<RssSettings>,
+    pub signup_protection: Option<SignupProtectionSettings>,
+    pub landing_analytics: Option<LandingAnalyticsSettings>,
+    pub token_signing: TokenSigningSettings,
+    pub runtime: RuntimeSettings,
 }
 
-#[derive(serde::Deserialize, Clone)]
+fn default_log_level() -> String {
+    "info".into()
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
+    #[serde(default)]
+    pub listener: ListenerSettings,
     pub base_url: String,
+    #[schemars(with = "String")]
     pub hmac_secret: Secret<String>,
 }
 
-#[derive(serde::Deserialize, Clone)]
+/// How the async runtime and the HTTP server use the machine. Unset values
+/// keep the defaults of Tokio and actix-web.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct RuntimeSettings {
+    /// Threads of the Tokio scheduler, one per CPU core by default.
+    pub worker_threads: Option<usize>,
+    /// The cap on the threads of Tokio's blocking pool, 512 by default.
+    pub max_blocking_threads: Option<usize>,
+    /// Actix-web workers, each with its own copy of the app, one per
+    /// physical core by default.
+    pub http_workers: Option<usize>,
+    /// How often the scheduler stats are exported to `/metrics`. They are
+    /// only collected by builds with `RUSTFLAGS="--cfg tokio_unstable"`.
+    pub metrics_interval_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct NetworkSettings {
+    /// The reverse proxies and load balancers in front of us, as addresses
+    /// or CIDR ranges. Only they are trusted with `X-Forwarded-For`,
+    /// `X-Request-Id` and `traceparent`.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct AdminSettings {
+    /// When set, `/admin` answers 404 to every client outside these
+    /// addresses or CIDR ranges. The client is the one behind our trusted
+    /// proxies.
+    pub allowed_cidrs: Option<Vec<String>>,
+}
+
+/// Replace personal data in the logs with pseudonyms.
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct LogScrubbingSettings {
+    pub enabled: bool,
+    /// Fields whose whole value is scrubbed, e.g. `subscriber_name`.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Regular expressions scrubbed from every field and message, on top of
+    /// email addresses.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// How the server gets its listening socket. `host` and `port` only apply
+/// to `tcp`, the default.
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ListenerSettings {
+    #[default]
+    Tcp,
+    /// Bind a Unix domain socket, e.g. for a reverse proxy on the same host.
+    Unix { path: std::path::PathBuf },
+    /// Inherit the socket from systemd socket activation (`LISTEN_FDS`).
+    Systemd,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct DatabaseSettings {
     pub username: String,
+    #[schemars(with = "String")]
     pub password: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// Statements slower than this are logged as warnings.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub slow_query_threshold_milliseconds: u64,
+    /// How many times to try connecting on startup before giving up.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_attempts: u32,
+    /// The delay before the first retry, doubled after every attempt.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_retry_interval_milliseconds: u64,
 }
 
 impl DatabaseSettings {
@@ -48,48 +191,834 @@ impl DatabaseSettings {
     }
 
     pub fn with_db(&self) -> PgConnectOptions {
-        self.without_db().database(&self.database_name)
+        self.without_db()
+            .database(&self.database_name)
+            .log_slow_statements(
+                log::LevelFilter::Warn,
+                std::time::Duration::from_millis(self.slow_query_threshold_milliseconds),
+            )
     }
 }
 
-#[derive(serde::Deserialize, Clone)]
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
 pub struct EmailClientSettings {
     pub base_url: String,
     pub sender_email: String,
+    #[schemars(with = "String")]
     pub api_public_key: Secret<String>,
+    #[schemars(with = "String")]
     pub api_private_key: Secret<String>,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub timeout_milliseconds: u64,
+    #[serde(default)]
+    pub message_streams: MessageStreams,
+    /// The estimated price of a single email, in the billing currency.
+    #[serde(default)]
+    pub cost_per_email: f64,
 }
 
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn client(self) -> Result<EmailClient, StartupError> {
+        let sender_email = self
+            .sender()
+            .map_err(|e| StartupError::InvalidConfiguration(format!("sender_email: {}", e)))?;
+        let timeout = self.timeout();
+        Ok(EmailClient::new(
+            self.base_url,
+            sender_email,
+            self.api_public_key,
+            self.api_private_key,
+            timeout,
+        )?
+        .with_message_streams(self.message_streams)
+        .with_cost_per_email(self.cost_per_email))
+    }
+
+    pub fn sender(&self) -> Result<SubscriberEmail, DomainValidationError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
+    /// The domain we send emails from.
+    pub fn sender_domain(&self) -> Option<&str> {
+        self.sender_email.rsplit_once('@').map(|(_, domain)| domain)
+    }
+
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 }
 
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct EmailFailoverSettings {
+    pub secondary: EmailClientSettings,
+    /// Consecutive primary failures before sends go to the secondary provider.
+    pub failure_threshold: u32,
+    /// How long to wait before probing the primary provider again.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub probe_interval_seconds: u64,
+}
+
+impl Settings {
+    /// The email client for the primary provider, with failover to the
+    /// secondary one when configured.
+    pub fn build_email_client(&self) -> Result<EmailClient, StartupError> {
+        let client = self.email_client.clone().client()?;
+        Ok(match &self.email_failover {
+            Some(failover) => client.with_failover(
+                failover.secondary.clone().client()?,
+                failover.failure_threshold,
+                std::time::Duration::from_secs(failover.probe_interval_seconds),
+            ),
+            None => client,
+        })
+    }
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct EventsSettings {
+    /// Also append every published event to the `domain_events` table.
+    pub durable: bool,
+    /// Relay events to an external broker through the `events_outbox` table.
+    pub export: Option<EventExportSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct EventExportSettings {
+    pub broker: BrokerSettings,
+    /// Events are published on `<subject_prefix>.<event_type>`.
+    pub subject_prefix: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BrokerSettings {
+    Nats { url: String },
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct NotificationSettings {
+    pub provider: NotificationProvider,
+    #[schemars(with = "String")]
+    pub webhook_url: Secret<String>,
+    /// The event types to forward, e.g. `issue_sent`, `delivery_failed` or
+    /// `job_failed`. Startup fails on names that are not event types.
+    pub events: Vec<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_messages_per_minute: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationProvider {
+    Slack,
+    Discord,
+}
+
+/// Pause an in-flight newsletter issue when too many deliveries fail, to
+/// protect the sender reputation.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DeliveryAlertSettings {
+    /// E.g. `0.05` pauses the issue once more than 5% of the attempts failed.
+    pub failure_rate_threshold: f64,
+    /// The failure rate is not evaluated until this many deliveries were attempted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_attempts: i32,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DeliveryLaneSettings {
+    /// How many priority deliveries in a row before a bulk one, while both
+    /// lanes have work pending.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_consecutive_priority_tasks: u32,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DeliveryEnqueueSettings {
+    /// Delivery tasks are enqueued this many subscribers per transaction.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: i64,
+}
+
+/// Caps on the deliveries to each recipient domain, on top of the overall
+/// pacing.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DomainThrottlingSettings {
+    /// The caps of every domain without an entry in `domains`.
+    pub default: DomainLimits,
+    /// Keyed by lower-case domain, e.g. `gmail.com`.
+    #[serde(default)]
+    pub domains: HashMap<String, DomainLimits>,
+}
+
+impl DomainThrottlingSettings {
+    pub fn limits(&self, domain: &str) -> &DomainLimits {
+        self.domains.get(domain).unwrap_or(&self.default)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DomainLimits {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_minute: u32,
+    /// How many sends to the domain may be in flight at once.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrency: u32,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct WarmUpSettings {
+    pub start_date: chrono::NaiveDate,
+    /// The maximum number of newsletter emails per day, one entry per week.
+    pub daily_limits: Vec<i64>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DeliverabilitySettings {
+    /// The selector the email provider signs our emails with.
+    pub dkim_selector: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub dns_timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SendQuotaSettings {
+    /// The maximum number of newsletter emails per calendar month.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub monthly_limit: i64,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SubscriberRetentionSettings {
+    /// How long deleted subscribers can be restored before they are purged.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_days: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub purge_interval_seconds: u64,
+}
+
+/// How long each class of data is kept, in days, enforced along with the
+/// purge of deleted subscribers. Data without a window is kept forever.
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct DataRetentionSettings {
+    /// The domain event store, our audit log.
+    pub audit_log_days: Option<u32>,
+    /// The IP address and user agent of consent records are erased past
+    /// this; the rest of the record is kept as proof of opt-in.
+    pub consent_ip_days: Option<u32>,
+    /// The log of delivery attempts. The send quota counts the deliveries of
+    /// the current month, so it must be at least 31 days.
+    pub delivery_log_days: Option<u32>,
+    /// The visits to the website reported for the readers of our issues.
+    pub landing_visits_days: Option<u32>,
+}
+
+/// Paid subscriptions through Stripe Checkout.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct BillingSettings {
+    pub api_base_url: String,
+    #[schemars(with = "String")]
+    pub secret_key: Secret<String>,
+    /// The signing secret of the `/webhooks/stripe` endpoint.
+    #[schemars(with = "String")]
+    pub webhook_secret: Secret<String>,
+    /// The Stripe price of the paid tier.
+    pub price_id: String,
+    pub success_url: String,
+    pub cancel_url: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// Webhook deliveries signed longer ago than this are rejected.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub signature_tolerance_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct MagicLinkSettings {
+    /// How long an emailed login link stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+}
+
+impl MagicLinkSettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SubscriptionTokenSettings {
+    /// How long the links we email to a subscriber keep working.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_days: i64,
+}
+
+impl SubscriptionTokenSettings {
+    pub fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::days(self.ttl_days)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct LoginSettings {
+    pub methods: LoginMethods,
+    /// Login link requests are silently dropped once this many links issued
+    /// to the same admin have not expired yet, to avoid flooding their inbox.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_active_magic_links: i64,
+    /// Logging in once more ends the least recently used sessions of the
+    /// admin beyond this many.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_sessions_per_user: i64,
+}
+
+/// How admins can log in.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMethods {
+    Password,
+    MagicLink,
+    PasswordAndMagicLink,
+}
+
+impl LoginMethods {
+    pub fn password(&self) -> bool {
+        matches!(self, Self::Password | Self::PasswordAndMagicLink)
+    }
+
+    pub fn magic_link(&self) -> bool {
+        matches!(self, Self::MagicLink | Self::PasswordAndMagicLink)
+    }
+}
+
+/// Single sign-on for the admin panel through an OpenID Connect provider.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct OidcSettings {
+    /// E.g. `https://accounts.google.com`, used for discovery.
+    pub issuer_url: String,
+    pub client_id: String,
+    #[schemars(with = "String")]
+    pub client_secret: Secret<String>,
+    /// The ID token claim listing the groups of the user.
+    pub groups_claim: String,
+    /// The role of users who belong to none of the mapped groups. Without
+    /// one, those users are turned away: every role opens the admin panel.
+    #[serde(default)]
+    pub default_role: Option<String>,
+    /// Checked in order: the first group the user belongs to sets their role.
+    #[serde(default)]
+    pub group_roles: Vec<GroupRole>,
+    /// When set, only users with a verified email address in one of these
+    /// domains may log in.
+    #[serde(default)]
+    pub allowed_email_domains: Vec<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct GroupRole {
+    pub group: String,
+    pub role: String,
+}
+
+/// Inboxes that receive every issue ahead of the subscribers, to check
+/// where it lands.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SeedListSettings {
+    pub inboxes: Vec<String>,
+    /// Placement monitoring services report through
+    /// `/webhooks/seed-placements` with `Authorization: Bearer <token>`.
+    #[schemars(with = "String")]
+    pub webhook_token: Secret<String>,
+}
+
+/// Bounces and spam complaints reported by the email provider.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct DeliveryEventsSettings {
+    /// Selects how the reports are parsed.
+    pub provider: DeliveryEventProvider,
+    /// The provider reports through `/webhooks/delivery-events` with HTTP
+    /// Basic authentication, this token being the password.
+    #[schemars(with = "String")]
+    pub webhook_token: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryEventProvider {
+    Postmark,
+    Sendgrid,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SignupPageSettings {
+    /// Lower-case letters, digits and dashes.
+    pub slug: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// Overrides the theme's logo.
+    pub logo_url: Option<String>,
+    /// Overrides the theme's accent color.
+    pub accent_color: Option<String>,
+}
+
+/// The look of the public pages.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ThemeSettings {
+    /// A `#rrggbb` color for the headings and the buttons.
+    pub accent_color: String,
+    pub logo_url: Option<String>,
+    /// Appended to the stylesheet of every public page, once sanitized.
+    #[serde(default)]
+    pub custom_css: String,
+    /// Holds templates replacing the built-in layout: `layout.html` for every
+    /// page, or e.g. `signup.html` for a single one.
+    pub template_directory: Option<String>,
+}
+
+/// How long browsers and CDNs may reuse the public pages before
+/// revalidating them.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct HttpCacheSettings {
+    pub signup_page_max_age_seconds: u32,
+}
+
+/// Where subscribers land after confirming, instead of our built-in page.
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct SubscriberRedirectSettings {
+    pub post_confirm_url: Option<String>,
+    /// The redirect URL must point to one of these domains, or below.
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ConsentSettings {
+    /// The version of the consent wording shown next to the subscribe
+    /// forms, kept with every consent record. Bump it whenever the wording
+    /// changes.
+    pub text_version: String,
+}
+
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct I18nSettings {
+    /// The language of the public pages for visitors whose
+    /// `Accept-Language` names none of the supported ones.
+    #[serde(default)]
+    pub default_language: Language,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ResendSettings {
+    /// How many times a day a subscriber can have the latest issue sent
+    /// again from the delivery status page.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_day: i64,
+}
+
+/// The rewards of the subscribers who bring in new ones.
+#[derive(serde::Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct ReferralSettings {
+    #[serde(default)]
+    pub milestones: Vec<ReferralMilestone>,
+}
+
+/// The email a subscriber gets once `referrals` of the subscribers they
+/// referred confirmed.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ReferralMilestone {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub referrals: i64,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// Issues sent as Web Push notifications too, to the browsers the
+/// subscribers registered.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct WebPushSettings {
+    /// The VAPID key pair, as the uncompressed P-256 public key and the
+    /// private scalar, both base64url-encoded.
+    pub vapid_public_key: String,
+    #[schemars(with = "String")]
+    pub vapid_private_key: Secret<String>,
+    /// How push services can reach us, e.g. `mailto:admin@example.com`.
+    pub subject: String,
+    /// How long push services keep a notification for an offline browser.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// The hosts, subdomains included, that subscriptions may point at.
+    /// Defaults to the push services of the major browsers.
+    #[serde(default = "default_push_services")]
+    pub push_services: Vec<String>,
+    /// Also accept plain http endpoints on private addresses. For tests
+    /// only: subscribers choose the endpoints we send requests to.
+    #[serde(default)]
+    pub allow_private_hosts: bool,
+}
+
+fn default_push_services() -> Vec<String> {
+    [
+        "fcm.googleapis.com",
+        "updates.push.services.mozilla.com",
+        "web.push.apple.com",
+        "notify.windows.com",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Short announcements by text message, to the subscribers who verified
+/// their phone number and opted in.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SmsSettings {
+    pub provider: SmsProviderSettings,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// The wording subscribers opt in to, shown next to the opt-in button.
+    pub consent_text: String,
+    /// Recorded with each opt-in: bump it whenever `consent_text` changes.
+    pub consent_text_version: String,
+    pub rate_limits: SmsRateLimitSettings,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SmsProviderSettings {
+    /// Twilio, or any provider with a Twilio-compatible messages API.
+    Twilio {
+        base_url: String,
+        account_sid: String,
+        #[schemars(with = "String")]
+        auth_token: Secret<String>,
+        /// The number, or messaging service id, the texts are sent from.
+        from: String,
+    },
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SmsRateLimitSettings {
+    /// The texts per minute to every country without an entry in
+    /// `countries`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub default_per_minute: u32,
+    /// Keyed by ISO 3166-1 alpha-2 country code, e.g. `US`.
+    #[serde(default)]
+    pub countries: HashMap<String, u32>,
+}
+
+impl SmsRateLimitSettings {
+    pub fn per_minute(&self, country: &str) -> u32 {
+        self.countries
+            .get(country)
+            .copied()
+            .unwrap_or(self.default_per_minute)
+    }
+}
+
+/// Each published issue mirrored to a Telegram channel, as a summary and
+/// a link to the archive.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct TelegramSettings {
+    /// `https://api.telegram.org`, or a local Bot API server.
+    pub base_url: String,
+    #[schemars(with = "String")]
+    pub bot_token: Secret<String>,
+    /// The channel, as `@username` or as its numeric id. The bot must be
+    /// one of its admins.
+    pub chat_id: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// Past this many failed attempts, an issue is no longer posted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_attempts: i32,
+}
+
+/// Issues made of the new entries of RSS or Atom feeds, checked on a
+/// schedule.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct RssSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    pub feeds: Vec<RssFeedSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct RssFeedSettings {
+    /// The entries already sent are remembered by feed name: renaming a
+    /// feed sends its entries again.
+    pub name: String,
+    pub url: String,
+    /// How often the feed is checked for new entries.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_minutes: u32,
+    /// The issue template the entries are rendered into, in place of
+    /// `{{rss}}` or at the end. Without one, the issue is titled after the
+    /// feed.
+    pub template: Option<String>,
+    /// Publish the issue straight away, rather than leaving a draft to
+    /// approve.
+    #[serde(default)]
+    pub auto_send: bool,
+    /// The most entries in an issue. The older new entries are skipped.
+    #[serde(default = "default_rss_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_rss_max_entries() -> usize {
+    20
+}
+
+/// Watch the signups per hour of every form source for the spikes of a bot
+/// attack, and make subscribing stricter while one lasts.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SignupProtectionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub check_interval_seconds: u64,
+    /// The hours before the current one that make up the baseline.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub baseline_hours: u32,
+    /// An hour is a spike with this many times its average signups...
+    pub spike_factor: f64,
+    /// ...and at least this many, so that quiet sources are not flagged
+    /// for a handful.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_signups: u32,
+    /// How long subscribing stays strict after the last spike.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub strict_mode_minutes: u32,
+    /// The signups allowed from one IP address per hour, in strict mode.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub strict_signups_per_ip_per_hour: u32,
+    /// Signups still in quarantine after this many days are rejected.
+    #[serde(default = "default_quarantine_days")]
+    pub quarantine_days: u32,
+    /// Without a captcha, the signups made in strict mode are quarantined.
+    pub captcha: Option<CaptchaSettings>,
+}
+
+fn default_quarantine_days() -> u32 {
+    7
+}
+
+/// A captcha provider with a `siteverify` endpoint, such as hCaptcha,
+/// Turnstile or reCAPTCHA. Forms send the token of their widget as
+/// `captcha_response`.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct CaptchaSettings {
+    pub verify_url: String,
+    #[schemars(with = "String")]
+    pub secret_key: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+/// Credit the visits to the operator's website to the issues that sent the
+/// readers there.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct LandingAnalyticsSettings {
+    /// The origins of the website, such as `https://example.com`. The links
+    /// of the issues to them are tagged with UTM parameters and a click
+    /// token, and only pages served from them may report visits.
+    pub allowed_origins: Vec<String>,
+    /// The `utm_source` of the tagged links.
+    #[serde(default = "default_utm_source")]
+    pub utm_source: String,
+}
+
+fn default_utm_source() -> String {
+    "newsletter".into()
+}
+
+/// The keys of the signed tokens in the links we email.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct TokenSigningSettings {
+    /// The id of the key new tokens are signed with.
+    pub current_key: String,
+    /// HMAC-SHA256 keys by id, at least 32 bytes each. A previous key must
+    /// stay until the tokens it signed have expired.
+    #[schemars(with = "HashMap<String, String>")]
+    pub keys: HashMap<String, Secret<String>>,
+}
+
+/// Encrypt the email and name of subscribers at rest.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct PiiEncryptionSettings {
+    /// The id of the key new values are sealed with.
+    pub active_key: String,
+    /// AES-256 keys by id, as base64. A retired key must stay until
+    /// `pii rotate-keys` has resealed the values it sealed.
+    #[schemars(with = "HashMap<String, String>")]
+    pub keys: HashMap<String, Secret<String>>,
+    /// The HMAC key of the email lookup index, as base64 of 32 bytes. It is
+    /// not rotated: the index would have to be rebuilt.
+    #[schemars(with = "String")]
+    pub index_key: Secret<String>,
+}
+
+/// The replay of the POST and PUT requests sent with an `Idempotency-Key`.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct IdempotencySettings {
+    /// How long a response is replayed to the retries of its request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub retention_hours: u32,
+}
+
+/// The endpoints under `/api`, for our other services.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ApiSettings {
+    /// Callers authenticate with `Authorization: Bearer <key>`. Several keys
+    /// can be valid at once, to rotate them.
+    pub keys: Vec<ApiKeySettings>,
+    /// The quotas of the keys, keyed by tier name.
+    pub tiers: HashMap<String, ApiTierSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ApiKeySettings {
+    /// Names the key in its usage report, e.g. `signup-form`.
+    pub id: String,
+    #[schemars(with = "String")]
+    pub key: Secret<String>,
+    /// One of the `tiers`.
+    pub tier: String,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, schemars::JsonSchema)]
+pub struct ApiTierSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub requests_per_minute: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub requests_per_day: u32,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct EmailVerificationSettings {
+    /// Domains handing out throwaway addresses, e.g. `mailinator.com`.
+    #[serde(default)]
+    pub disposable_domains: Vec<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub dns_timeout_milliseconds: u64,
+}
+
+/// Admin provisioning from an identity provider through `/scim/v2`.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ScimSettings {
+    /// The identity provider authenticates with `Authorization: Bearer <token>`.
+    #[schemars(with = "String")]
+    pub bearer_token: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct LinkCheckerSettings {
+    /// Per request: a link that doesn't answer in time is reported as broken.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrency: usize,
+}
+
+/// Fetching and caching the external images of archived issues.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct ImageProxySettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// Larger images are not proxied.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_image_bytes: usize,
+    /// The in-memory cache drops the oldest images past this size.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_cache_bytes: usize,
+    /// Also fetch from loopback and private addresses. For tests only: it
+    /// lets whoever writes an issue probe our internal network.
+    #[serde(default)]
+    pub allow_private_hosts: bool,
+}
+
+/// Latency objectives per route, checked in-process against a rolling
+/// window of requests.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SloSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub evaluation_interval_seconds: u64,
+    /// Alert when the error budget is spent this many times faster than it
+    /// lasts, e.g. `14.4` for 2% of a 30-day budget in an hour.
+    pub burn_rate_threshold: f64,
+    /// Objectives with fewer requests in their window are not evaluated.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_requests: u64,
+    #[serde(default)]
+    pub objectives: Vec<SloObjectiveSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SloObjectiveSettings {
+    /// The route pattern, as registered: `/archive/{issue_id}`.
+    pub route: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub latency_milliseconds: u64,
+    /// The share of requests to answer within the latency, e.g. `0.99`.
+    pub target: f64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+}
+
+/// When to answer low-priority routes with a 503 straight away.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct LoadSheddingSettings {
+    /// Across all workers, the request being shed included.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_in_flight_requests: usize,
+    /// How late a worker's event loop may run behind schedule.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_event_loop_lag_milliseconds: u64,
+    /// Path prefixes that are shed first, e.g. `/archive`.
+    #[serde(default)]
+    pub low_priority_routes: Vec<String>,
+}
+
+/// A SpamAssassin daemon (spamd) to score issues before they are sent.
+#[derive(serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct SpamCheckSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
-    let base_path = std::env::current_dir().expect("Failed to determine the current directory");
+    let base_path = std::env::current_dir().map_err(|e| {
+        config::ConfigError::Message(format!("Failed to determine the current directory: {}", e))
+    })?;
     let configuration_directory = base_path.join("configuration");
 
-    // Detect the running environment.
-    // Default to `local` if unspecified.
-    let environment: Environment = std::env::var("APP_ENVIRONMENT")
-        .unwrap_or_else(|_| "local".into())
-        .try_into()
-        .expect("Failed to parse APP_ENVIRONMENT.");
+    let environment = Environment::current().map_err(config::ConfigError::Message)?;
     let environment_filename = format!("{}.yaml", environment.as_str());
+    load_configuration(
+        &configuration_directory.join("base.yaml"),
+        &configuration_directory.join(environment_filename),
+    )
+}
+
+fn load_configuration(
+    base_file: &std::path::Path,
+    environment_file: &std::path::Path,
+) -> Result<Settings, config::ConfigError> {
     let settings = config::Config::builder()
-        .add_source(config::File::from(
-            configuration_directory.join("base.yaml"),
-        ))
-        .add_source(config::File::from(
-            configuration_directory.join(environment_filename),
-        ))
+        .add_source(config::File::from(base_file))
+        .add_source(config::File::from(environment_file))
         // Add in settings from environment variables (with a prefix of APP and '__' as separator)
         // E.g. `APP_APPLICATION__PORT=5001 would set `Settings.application.port`
         .add_source(
@@ -102,6 +1031,19 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     settings.try_deserialize::<Settings>()
 }
 
+/// The JSON Schema of the configuration, derived from the types above.
+pub fn configuration_schema() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(Settings)).expect("The schema is valid JSON")
+}
+
+/// Check that `path` is a valid configuration once layered on top of
+/// `base.yaml` and the `APP_` environment variables, as it would be at runtime.
+pub fn validate_configuration_file(path: &std::path::Path) -> Result<(), config::ConfigError> {
+    let base_path = std::env::current_dir().expect("Failed to determine the current directory");
+    load_configuration(&base_path.join("configuration").join("base.yaml"), path)?;
+    Ok(())
+}
+
 /// The possible runtime environment for our application.
 pub enum Environment {
     Local,
@@ -109,6 +1051,14 @@ pub enum Environment {
 }
 
 impl Environment {
+    /// Detect the running environment from `APP_ENVIRONMENT`.
+    /// Default to `local` if unspecified.
+    pub fn current() -> Result<Self, String> {
+        std::env::var("APP_ENVIRONMENT")
+            .unwrap_or_else(|_| "local".into())
+            .try_into()
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Environment::Local => "local",
@@ -131,3 +1081,35 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{configuration_schema, validate_configuration_file};
+    use claims::{assert_err, assert_ok};
+    use std::path::Path;
+
+    #[test]
+    fn the_schema_describes_every_section() {
+        let schema = configuration_schema();
+        let properties = schema["properties"].as_object().unwrap();
+        assert!(properties.contains_key("database"));
+        assert!(properties.contains_key("email_client"));
+        assert_eq!(schema["definitions"]["Secret"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn the_local_configuration_is_valid() {
+        assert_ok!(validate_configuration_file(Path::new(
+            "configuration/local.yaml"
+        )));
+    }
+
+    #[test]
+    fn a_malformed_configuration_file_is_rejected() {
+        let path = std::env::temp_dir().join(format!("{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "application:\n  port: not-a-number\n").unwrap();
+        let outcome = validate_configuration_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,133 @@
+//! Proof of opt-in: what a subscriber agreed to, when, and from where.
+//!
+//! A record is kept when somebody subscribes and another when they confirm,
+//! as the lawful basis for emailing them.
+use crate::admin_sessions::Device;
+use crate::configuration::ConsentSettings;
+use crate::database::ObserveQuery;
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+/// Form sources longer than this are cut.
+pub const MAX_FORM_SOURCE_LENGTH: usize = 100;
+
+#[derive(Copy, Clone, Debug)]
+pub enum ConsentAction {
+    Subscribed,
+    Confirmed,
+    SmsOptedIn,
+    SmsOptedOut,
+}
+
+impl ConsentAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConsentAction::Subscribed => "subscribed",
+            ConsentAction::Confirmed => "confirmed",
+            ConsentAction::SmsOptedIn => "sms_opted_in",
+            ConsentAction::SmsOptedOut => "sms_opted_out",
+        }
+    }
+}
+
+/// Where a consent given with the request comes from, and the wording it
+/// was given to.
+pub struct ConsentContext {
+    pub device: Device,
+    pub text_version: String,
+}
+
+impl FromRequest for ConsentContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let Some(settings) = req.app_data::<web::Data<ConsentSettings>>() else {
+            return ready(Err(actix_web::error::ErrorInternalServerError(
+                "The consent settings are not registered",
+            )));
+        };
+        let text_version = settings.text_version.clone();
+        ready(
+            Device::from_request(req, payload)
+                .into_inner()
+                .map(|device| Self {
+                    device,
+                    text_version,
+                }),
+        )
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConsentRecord {
+    pub action: String,
+    pub recorded_at: DateTime<Utc>,
+    pub client_ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Which form the subscriber used, e.g. `l/earthsea` for a hosted
+    /// signup page.
+    pub form_source: Option<String>,
+    /// The version of the consent wording shown next to the form.
+    pub consent_text_version: String,
+}
+
+#[tracing::instrument(name = "Record a consent", skip(executor, context))]
+pub async fn record_consent<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+    action: ConsentAction,
+    context: &ConsentContext,
+    form_source: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let form_source: Option<String> =
+        form_source.map(|source| source.chars().take(MAX_FORM_SOURCE_LENGTH).collect());
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            id, subscriber_id, action, recorded_at, client_ip, user_agent, form_source,
+            consent_text_version
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        action.as_str(),
+        Utc::now(),
+        context.device.client_ip,
+        context.device.user_agent,
+        form_source,
+        context.text_version
+    )
+    .execute(executor)
+    .observe("insert_consent_record")
+    .await?;
+    Ok(())
+}
+
+/// The consent records of a subscriber, oldest first.
+pub async fn get_consent_records(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT action, recorded_at, client_ip, user_agent, form_source, consent_text_version
+        FROM consent_records
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .observe("get_consent_records")
+    .await
+}
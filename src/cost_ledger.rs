@@ -0,0 +1,58 @@
+use crate::database::{retry_read, ObserveQuery};
+use crate::email_client::SentEmail;
+use chrono::{Datelike, NaiveDate, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+
+/// Add an email to the running total of the current month.
+#[tracing::instrument(name = "Record the cost of a sent email", skip(executor))]
+pub async fn record_send<'a, E>(executor: E, sent: &SentEmail) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO cost_ledger (month, provider, emails_sent, estimated_cost)
+        VALUES ($1, $2, 1, $3)
+        ON CONFLICT (month, provider) DO UPDATE
+        SET
+            emails_sent = cost_ledger.emails_sent + 1,
+            estimated_cost = cost_ledger.estimated_cost + EXCLUDED.estimated_cost
+        "#,
+        current_month(),
+        sent.provider,
+        sent.estimated_cost
+    )
+    .execute(executor)
+    .observe("record_send")
+    .await?;
+    Ok(())
+}
+
+pub struct MonthlySpend {
+    pub provider: String,
+    pub emails_sent: i64,
+    pub estimated_cost: f64,
+}
+
+#[tracing::instrument(name = "Get this month's sending costs", skip(pool))]
+pub async fn current_month_spend(pool: &PgPool) -> Result<Vec<MonthlySpend>, sqlx::Error> {
+    retry_read(|| {
+        sqlx::query_as!(
+            MonthlySpend,
+            r#"
+            SELECT provider, emails_sent, estimated_cost
+            FROM cost_ledger
+            WHERE month = $1
+            ORDER BY provider
+            "#,
+            current_month()
+        )
+        .fetch_all(pool)
+        .observe("current_month_spend")
+    })
+    .await
+}
+
+fn current_month() -> NaiveDate {
+    Utc::now().date_naive().with_day(1).unwrap()
+}
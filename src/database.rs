@@ -0,0 +1,155 @@
+use crate::metrics::DB_QUERY_DURATION;
+use sqlx::postgres::PgQueryResult;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+const READ_ATTEMPTS: u32 = 3;
+const READ_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Retry an idempotent read a few times, with exponential backoff, when the
+/// failure looks transient, e.g. while Postgres is restarting.
+pub async fn retry_read<T, F, Fut>(mut query: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut backoff = READ_BACKOFF;
+    for attempt in 1..READ_ATTEMPTS {
+        match query().await {
+            Err(e) if is_transient(&e) => {
+                tracing::warn!(
+                    error.message = %e,
+                    attempt,
+                    "A database read failed, retrying in {:?}",
+                    backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            outcome => return outcome,
+        }
+    }
+    query().await
+}
+
+/// Errors that are likely to go away once the connection is re-established.
+pub fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::Tls(_) => true,
+        // Class 08 is "connection exception", 57P0x are server shutdowns.
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P0")),
+        _ => false,
+    }
+}
+
+/// How many rows a query returned or touched.
+pub trait QueryRows {
+    fn rows(&self) -> u64;
+}
+
+impl QueryRows for PgQueryResult {
+    fn rows(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+impl<T> QueryRows for Vec<T> {
+    fn rows(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T> QueryRows for Option<T> {
+    fn rows(&self) -> u64 {
+        self.is_some().into()
+    }
+}
+
+/// Run every query inside a span named after it and record its latency in
+/// the `db_query_duration_seconds` histogram.
+pub trait ObserveQuery<T>: Future<Output = Result<T, sqlx::Error>> + Sized {
+    fn observe(self, name: &'static str) -> impl Future<Output = Result<T, sqlx::Error>>
+    where
+        T: QueryRows,
+    {
+        observe(name, self, |r: &T| r.rows())
+    }
+
+    /// For `fetch_one`, which either returns a single row or fails.
+    fn observe_one(self, name: &'static str) -> impl Future<Output = Result<T, sqlx::Error>> {
+        observe(name, self, |_| 1)
+    }
+}
+
+impl<T, F> ObserveQuery<T> for F where F: Future<Output = Result<T, sqlx::Error>> {}
+
+async fn observe<T, F>(
+    name: &'static str,
+    query: F,
+    rows: impl FnOnce(&T) -> u64,
+) -> Result<T, sqlx::Error>
+where
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    let span = tracing::info_span!(
+        "Database query",
+        db.query = name,
+        db.rows = tracing::field::Empty,
+        db.duration_ms = tracing::field::Empty,
+    );
+    let start = Instant::now();
+    #[cfg(feature = "chaos")]
+    crate::chaos::delay_query().await;
+    let outcome = query.instrument(span.clone()).await;
+    let elapsed = start.elapsed();
+    DB_QUERY_DURATION
+        .with_label_values(&[name])
+        .observe(elapsed.as_secs_f64());
+    span.record("db.duration_ms", elapsed.as_millis() as u64);
+    if let Ok(result) = &outcome {
+        span.record("db.rows", rows(result));
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry_read;
+    use claims::{assert_err, assert_ok_eq};
+    use std::cell::Cell;
+
+    #[tokio::test]
+    async fn transient_failures_are_retried() {
+        let calls = Cell::new(0);
+        let outcome = retry_read(|| {
+            calls.set(calls.get() + 1);
+            let attempt = calls.get();
+            async move {
+                if attempt < 3 {
+                    Err(sqlx::Error::PoolTimedOut)
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_ok_eq!(outcome, 3);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_retried() {
+        let calls = Cell::new(0);
+        let outcome: Result<(), _> = retry_read(|| {
+            calls.set(calls.get() + 1);
+            async { Err(sqlx::Error::RowNotFound) }
+        })
+        .await;
+
+        assert_err!(outcome);
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -0,0 +1,351 @@
+use crate::configuration::DeliverabilitySettings;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The evaluation of a single DNS record.
+#[derive(Debug, serde::Serialize)]
+pub struct RecordCheck {
+    pub record: &'static str,
+    pub name: String,
+    pub status: CheckStatus,
+    pub value: Option<String>,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DnsReport {
+    pub domain: String,
+    /// The worst status across all checks.
+    pub status: CheckStatus,
+    pub checks: Vec<RecordCheck>,
+}
+
+/// The outcome of a TXT lookup.
+#[derive(Debug)]
+pub enum TxtRecords {
+    Found(Vec<String>),
+    NotFound,
+    LookupFailed(String),
+}
+
+/// A resolver using the system configuration, giving up on a lookup after
+/// `timeout`.
+pub fn system_resolver(timeout: Duration) -> TokioAsyncResolver {
+    let (config, mut opts) = hickory_resolver::system_conf::read_system_conf()
+        .unwrap_or_else(|_| (ResolverConfig::default(), ResolverOpts::default()));
+    opts.timeout = timeout;
+    opts.attempts = 1;
+    TokioAsyncResolver::tokio(config, opts)
+}
+
+/// Checks that the sending domain is set up to authenticate our emails.
+#[derive(Clone)]
+pub struct DnsChecker {
+    resolver: TokioAsyncResolver,
+    domain: String,
+    dkim_selector: String,
+    timeout: Duration,
+}
+
+impl DnsChecker {
+    pub fn new(domain: String, settings: &DeliverabilitySettings) -> Self {
+        let timeout = Duration::from_millis(settings.dns_timeout_milliseconds);
+        Self {
+            resolver: system_resolver(timeout),
+            domain,
+            dkim_selector: settings.dkim_selector.clone(),
+            timeout,
+        }
+    }
+
+    #[tracing::instrument(name = "Check sending domain DNS records", skip(self), fields(domain = %self.domain))]
+    pub async fn check(&self) -> DnsReport {
+        let dkim_name = format!("{}._domainkey.{}", self.dkim_selector, self.domain);
+        let dmarc_name = format!("_dmarc.{}", self.domain);
+        let (spf, dkim, dmarc) = tokio::join!(
+            self.txt_records(&self.domain),
+            self.txt_records(&dkim_name),
+            self.txt_records(&dmarc_name),
+        );
+        let checks = vec![
+            evaluate_spf(&self.domain, spf),
+            evaluate_dkim(&dkim_name, dkim),
+            evaluate_dmarc(&dmarc_name, dmarc),
+        ];
+        DnsReport {
+            domain: self.domain.clone(),
+            status: checks
+                .iter()
+                .map(|c| c.status)
+                .max()
+                .unwrap_or(CheckStatus::Pass),
+            checks,
+        }
+    }
+
+    async fn txt_records(&self, name: &str) -> TxtRecords {
+        match tokio::time::timeout(self.timeout, self.resolver.txt_lookup(name)).await {
+            Ok(Ok(lookup)) => TxtRecords::Found(lookup.iter().map(|txt| txt.to_string()).collect()),
+            Ok(Err(e)) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => TxtRecords::NotFound,
+                _ => TxtRecords::LookupFailed(e.to_string()),
+            },
+            Err(_) => TxtRecords::LookupFailed("The lookup timed out".into()),
+        }
+    }
+}
+
+/// Keep only the records that belong to the given mechanism (e.g. `v=spf1`).
+fn versioned(records: TxtRecords, version: &str) -> Result<Vec<String>, String> {
+    match records {
+        TxtRecords::Found(records) => Ok(records
+            .into_iter()
+            .filter(|r| {
+                r.trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with(&version.to_ascii_lowercase())
+            })
+            .collect()),
+        TxtRecords::NotFound => Ok(vec![]),
+        TxtRecords::LookupFailed(e) => Err(e),
+    }
+}
+
+fn check(
+    record: &'static str,
+    name: &str,
+    status: CheckStatus,
+    value: Option<String>,
+    hint: Option<String>,
+) -> RecordCheck {
+    RecordCheck {
+        record,
+        name: name.into(),
+        status,
+        value,
+        hint,
+    }
+}
+
+fn lookup_failed(record: &'static str, name: &str, error: String) -> RecordCheck {
+    check(
+        record,
+        name,
+        CheckStatus::Warn,
+        None,
+        Some(format!(
+            "The {name} TXT record could not be resolved ({error}). Try again later."
+        )),
+    )
+}
+
+pub fn evaluate_spf(domain: &str, records: TxtRecords) -> RecordCheck {
+    let records = match versioned(records, "v=spf1") {
+        Ok(records) => records,
+        Err(e) => return lookup_failed("spf", domain, e),
+    };
+    let record = match records.as_slice() {
+        [] => {
+            return check(
+                "spf",
+                domain,
+                CheckStatus::Fail,
+                None,
+                Some(format!(
+                    "Publish a TXT record on {domain} starting with `v=spf1` \
+                     that authorizes your email provider, e.g. `v=spf1 include:<provider> ~all`."
+                )),
+            )
+        }
+        [record] => record.clone(),
+        _ => {
+            return check(
+                "spf",
+                domain,
+                CheckStatus::Fail,
+                Some(records.join(" | ")),
+                Some("Only one SPF record is allowed: merge them into a single record.".into()),
+            )
+        }
+    };
+    let all = record
+        .split_whitespace()
+        .find(|term| term.trim_start_matches(['+', '-', '~', '?']) == "all");
+    let (status, hint) = match all {
+        Some("-all") | Some("~all") => (CheckStatus::Pass, None),
+        Some("all") | Some("+all") => (
+            CheckStatus::Fail,
+            Some("`+all` lets anybody send on your behalf: use `~all` or `-all`.".into()),
+        ),
+        _ => (
+            CheckStatus::Warn,
+            Some("End the SPF record with `~all` or `-all` to reject unauthorized senders.".into()),
+        ),
+    };
+    check("spf", domain, status, Some(record), hint)
+}
+
+pub fn evaluate_dkim(name: &str, records: TxtRecords) -> RecordCheck {
+    let records = match records {
+        TxtRecords::Found(records) => records,
+        TxtRecords::NotFound => vec![],
+        TxtRecords::LookupFailed(e) => return lookup_failed("dkim", name, e),
+    };
+    let Some(record) = records.into_iter().find(|r| tag_value(r, "p").is_some()) else {
+        return check(
+            "dkim",
+            name,
+            CheckStatus::Fail,
+            None,
+            Some(format!(
+                "Publish the DKIM public key provided by your email provider as a TXT record on {name}."
+            )),
+        );
+    };
+    let (status, hint) = match tag_value(&record, "p") {
+        Some("") => (
+            CheckStatus::Fail,
+            Some("The DKIM key has been revoked: publish the current public key.".into()),
+        ),
+        _ => (CheckStatus::Pass, None),
+    };
+    check("dkim", name, status, Some(record), hint)
+}
+
+pub fn evaluate_dmarc(name: &str, records: TxtRecords) -> RecordCheck {
+    let records = match versioned(records, "v=DMARC1") {
+        Ok(records) => records,
+        Err(e) => return lookup_failed("dmarc", name, e),
+    };
+    let record = match records.as_slice() {
+        [] => {
+            return check(
+                "dmarc",
+                name,
+                CheckStatus::Fail,
+                None,
+                Some(format!(
+                    "Publish a TXT record on {name} such as `v=DMARC1; p=quarantine; rua=mailto:<address>`."
+                )),
+            )
+        }
+        [record] => record.clone(),
+        _ => {
+            return check(
+                "dmarc",
+                name,
+                CheckStatus::Fail,
+                Some(records.join(" | ")),
+                Some("Only one DMARC record is allowed: remove the extra ones.".into()),
+            )
+        }
+    };
+    let (status, hint) = match tag_value(&record, "p")
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("quarantine") | Some("reject") => (CheckStatus::Pass, None),
+        Some("none") => (
+            CheckStatus::Warn,
+            Some("`p=none` only monitors: move to `p=quarantine` once reports look clean.".into()),
+        ),
+        _ => (
+            CheckStatus::Fail,
+            Some("The DMARC record must set a policy with `p=none|quarantine|reject`.".into()),
+        ),
+    };
+    check("dmarc", name, status, Some(record), hint)
+}
+
+/// The value of a `tag=value` pair in a `;`-separated record.
+fn tag_value<'a>(record: &'a str, tag: &str) -> Option<&'a str> {
+    record.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key.trim().eq_ignore_ascii_case(tag)).then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate_dkim, evaluate_dmarc, evaluate_spf, CheckStatus, TxtRecords};
+
+    fn found(records: &[&str]) -> TxtRecords {
+        TxtRecords::Found(records.iter().map(|r| r.to_string()).collect())
+    }
+
+    #[test]
+    fn a_strict_spf_record_passes() {
+        let check = evaluate_spf(
+            "domain.com",
+            found(&[
+                "google-site-verification=abc",
+                "v=spf1 include:spf.mailjet.com -all",
+            ]),
+        );
+        assert_eq!(check.status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn a_permissive_spf_record_fails() {
+        let check = evaluate_spf("domain.com", found(&["v=spf1 +all"]));
+        assert_eq!(check.status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn an_spf_record_without_all_is_a_warning() {
+        let check = evaluate_spf("domain.com", found(&["v=spf1 include:spf.mailjet.com"]));
+        assert_eq!(check.status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn multiple_spf_records_fail() {
+        let check = evaluate_spf("domain.com", found(&["v=spf1 -all", "v=spf1 ~all"]));
+        assert_eq!(check.status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn a_missing_record_fails_with_a_hint() {
+        let check = evaluate_dmarc("_dmarc.domain.com", TxtRecords::NotFound);
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.hint.is_some());
+    }
+
+    #[test]
+    fn a_failed_lookup_is_a_warning() {
+        let check = evaluate_spf("domain.com", TxtRecords::LookupFailed("timeout".into()));
+        assert_eq!(check.status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn a_revoked_dkim_key_fails() {
+        let check = evaluate_dkim("s1._domainkey.domain.com", found(&["v=DKIM1; k=rsa; p="]));
+        assert_eq!(check.status, CheckStatus::Fail);
+    }
+
+    #[test]
+    fn a_published_dkim_key_passes() {
+        let check = evaluate_dkim(
+            "s1._domainkey.domain.com",
+            found(&["v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQC"]),
+        );
+        assert_eq!(check.status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn a_monitoring_only_dmarc_policy_is_a_warning() {
+        let check = evaluate_dmarc("_dmarc.domain.com", found(&["v=DMARC1; p=none"]));
+        assert_eq!(check.status, CheckStatus::Warn);
+        let check = evaluate_dmarc("_dmarc.domain.com", found(&["v=DMARC1; p=reject"]));
+        assert_eq!(check.status, CheckStatus::Pass);
+    }
+}
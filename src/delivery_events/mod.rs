@@ -0,0 +1,102 @@
+//! Bounces and spam complaints reported by the email provider.
+//!
+//! Each provider reports them in its own shape: a parser per provider turns
+//! a webhook payload into `DeliveryEvent`s, and the addresses that cannot
+//! be mailed anymore are suppressed.
+mod postmark;
+mod sendgrid;
+
+use crate::configuration::DeliveryEventProvider;
+use crate::database::ObserveQuery;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+pub use postmark::PostmarkParser;
+pub use sendgrid::SendgridParser;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryEventKind {
+    Delivered,
+    /// A temporary failure: the provider keeps retrying.
+    SoftBounce,
+    HardBounce,
+    SpamComplaint,
+}
+
+impl DeliveryEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryEventKind::Delivered => "delivered",
+            DeliveryEventKind::SoftBounce => "soft_bounce",
+            DeliveryEventKind::HardBounce => "hard_bounce",
+            DeliveryEventKind::SpamComplaint => "spam_complaint",
+        }
+    }
+
+    /// Whether the address must not be mailed anymore.
+    pub fn suppresses(&self) -> bool {
+        matches!(
+            self,
+            DeliveryEventKind::HardBounce | DeliveryEventKind::SpamComplaint
+        )
+    }
+}
+
+/// What happened to an email after the provider accepted it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeliveryEvent {
+    pub email: String,
+    pub kind: DeliveryEventKind,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid delivery event payload: {0}")]
+pub struct ParseError(String);
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+pub trait DeliveryEventParser: Send + Sync {
+    /// The events of a webhook payload. The events we have no use for, e.g.
+    /// opens, are left out.
+    fn parse(&self, body: &[u8]) -> Result<Vec<DeliveryEvent>, ParseError>;
+}
+
+impl DeliveryEventProvider {
+    pub fn parser(&self) -> &'static dyn DeliveryEventParser {
+        match self {
+            DeliveryEventProvider::Postmark => &PostmarkParser,
+            DeliveryEventProvider::Sendgrid => &SendgridParser,
+        }
+    }
+}
+
+/// Suppress the address of `event` if it cannot be mailed anymore. Returns
+/// whether it was newly suppressed.
+#[tracing::instrument(name = "Record a delivery event", skip(pool, event), fields(kind = event.kind.as_str()))]
+pub async fn record_delivery_event(
+    pool: &PgPool,
+    event: &DeliveryEvent,
+) -> Result<bool, sqlx::Error> {
+    if !event.kind.suppresses() {
+        return Ok(false);
+    }
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, suppressed_at)
+        VALUES (lower($1), $2, $3)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        event.email,
+        event.kind.as_str(),
+        event.occurred_at
+    )
+    .execute(pool)
+    .observe("suppress_email")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
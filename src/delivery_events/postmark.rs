@@ -0,0 +1,119 @@
+use super::{DeliveryEvent, DeliveryEventKind, DeliveryEventParser, ParseError};
+use chrono::{DateTime, Utc};
+
+/// Postmark posts one event per request, told apart by `RecordType`.
+///
+/// See <https://postmarkapp.com/developer/webhooks/webhooks-overview>.
+pub struct PostmarkParser;
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "RecordType")]
+enum Payload {
+    Delivery {
+        #[serde(rename = "Recipient")]
+        recipient: String,
+        #[serde(rename = "DeliveredAt")]
+        delivered_at: DateTime<Utc>,
+    },
+    Bounce {
+        #[serde(rename = "Email")]
+        email: String,
+        /// Whether Postmark deactivated the address, which it does after
+        /// the permanent bounces only.
+        #[serde(rename = "Inactive")]
+        inactive: bool,
+        #[serde(rename = "BouncedAt")]
+        bounced_at: DateTime<Utc>,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+        #[serde(rename = "BouncedAt")]
+        bounced_at: DateTime<Utc>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl DeliveryEventParser for PostmarkParser {
+    fn parse(&self, body: &[u8]) -> Result<Vec<DeliveryEvent>, ParseError> {
+        let event = match serde_json::from_slice(body)? {
+            Payload::Delivery {
+                recipient,
+                delivered_at,
+            } => DeliveryEvent {
+                email: recipient,
+                kind: DeliveryEventKind::Delivered,
+                occurred_at: delivered_at,
+            },
+            Payload::Bounce {
+                email,
+                inactive,
+                bounced_at,
+            } => DeliveryEvent {
+                email,
+                kind: if inactive {
+                    DeliveryEventKind::HardBounce
+                } else {
+                    DeliveryEventKind::SoftBounce
+                },
+                occurred_at: bounced_at,
+            },
+            Payload::SpamComplaint { email, bounced_at } => DeliveryEvent {
+                email,
+                kind: DeliveryEventKind::SpamComplaint,
+                occurred_at: bounced_at,
+            },
+            Payload::Other => return Ok(vec![]),
+        };
+        Ok(vec![event])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkParser;
+    use crate::delivery_events::{DeliveryEventKind, DeliveryEventParser};
+
+    fn parse(fixture: &str) -> Vec<(String, DeliveryEventKind)> {
+        PostmarkParser
+            .parse(fixture.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.email, event.kind))
+            .collect()
+    }
+
+    #[test]
+    fn bounces_are_hard_once_the_address_is_deactivated() {
+        assert_eq!(
+            parse(include_str!("fixtures/postmark_hard_bounce.json")),
+            vec![("john@example.com".into(), DeliveryEventKind::HardBounce)]
+        );
+        assert_eq!(
+            parse(include_str!("fixtures/postmark_soft_bounce.json")),
+            vec![("john@example.com".into(), DeliveryEventKind::SoftBounce)]
+        );
+    }
+
+    #[test]
+    fn spam_complaints_and_deliveries_are_parsed() {
+        assert_eq!(
+            parse(include_str!("fixtures/postmark_spam_complaint.json")),
+            vec![("john@example.com".into(), DeliveryEventKind::SpamComplaint)]
+        );
+        assert_eq!(
+            parse(include_str!("fixtures/postmark_delivery.json")),
+            vec![("john@example.com".into(), DeliveryEventKind::Delivered)]
+        );
+    }
+
+    #[test]
+    fn other_record_types_are_ignored() {
+        let open = r#"{"RecordType":"Open","Recipient":"john@example.com","FirstOpen":true}"#;
+        assert_eq!(parse(open), vec![]);
+        assert!(PostmarkParser
+            .parse(b"{\"RecordType\":\"Bounce\"}")
+            .is_err());
+    }
+}
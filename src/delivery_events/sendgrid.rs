@@ -0,0 +1,82 @@
+use super::{DeliveryEvent, DeliveryEventKind, DeliveryEventParser, ParseError};
+use chrono::{DateTime, Utc};
+
+/// SendGrid posts batches of events, told apart by `event`.
+///
+/// See <https://docs.sendgrid.com/for-developers/tracking-events/event>.
+pub struct SendgridParser;
+
+#[derive(serde::Deserialize)]
+struct Event {
+    email: String,
+    /// A Unix timestamp, in seconds.
+    timestamp: i64,
+    event: String,
+    /// For a `bounce`, `blocked` when the receiving server turned the email
+    /// down for now rather than for good.
+    #[serde(rename = "type")]
+    bounce_type: Option<String>,
+}
+
+impl DeliveryEventParser for SendgridParser {
+    fn parse(&self, body: &[u8]) -> Result<Vec<DeliveryEvent>, ParseError> {
+        let events: Vec<Event> = serde_json::from_slice(body)?;
+        events
+            .into_iter()
+            .filter_map(|event| {
+                let kind = match event.event.as_str() {
+                    "delivered" => DeliveryEventKind::Delivered,
+                    "deferred" => DeliveryEventKind::SoftBounce,
+                    "bounce" if event.bounce_type.as_deref() == Some("blocked") => {
+                        DeliveryEventKind::SoftBounce
+                    }
+                    "bounce" => DeliveryEventKind::HardBounce,
+                    "spamreport" => DeliveryEventKind::SpamComplaint,
+                    _ => return None,
+                };
+                Some(
+                    DateTime::<Utc>::from_timestamp(event.timestamp, 0)
+                        .ok_or_else(|| ParseError(format!("invalid timestamp {}", event.timestamp)))
+                        .map(|occurred_at| DeliveryEvent {
+                            email: event.email,
+                            kind,
+                            occurred_at,
+                        }),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendgridParser;
+    use crate::delivery_events::{DeliveryEventKind, DeliveryEventParser};
+
+    #[test]
+    fn a_batch_keeps_the_events_we_act_on() {
+        let events = SendgridParser
+            .parse(include_str!("fixtures/sendgrid_events.json").as_bytes())
+            .unwrap();
+        let events: Vec<_> = events
+            .iter()
+            .map(|event| (event.email.as_str(), event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("delivered@example.com", DeliveryEventKind::Delivered),
+                ("deferred@example.com", DeliveryEventKind::SoftBounce),
+                ("bounce@example.com", DeliveryEventKind::HardBounce),
+                ("blocked@example.com", DeliveryEventKind::SoftBounce),
+                ("spamreport@example.com", DeliveryEventKind::SpamComplaint),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_single_event_is_not_a_batch() {
+        let event = r#"{"email":"bounce@example.com","timestamp":1513299569,"event":"bounce"}"#;
+        assert!(SendgridParser.parse(event.as_bytes()).is_err());
+    }
+}
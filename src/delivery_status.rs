@@ -0,0 +1,237 @@
+//! What happened to the recent issues, from the point of view of one
+//! subscriber, for the page answering "I didn't get it".
+use crate::database::ObserveQuery;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How far the status page goes back.
+pub const RECENT_ISSUES: i64 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// The email provider accepted the email.
+    Sent,
+    /// Waiting in the delivery queue.
+    Queued,
+    /// The email provider refused it.
+    Failed,
+    /// Not attempted: the address was suppressed.
+    Suppressed,
+    /// Not addressed to the subscriber, e.g. published before they
+    /// subscribed or reserved to paid subscribers.
+    NotSent,
+}
+
+impl DeliveryStatus {
+    fn new(delivered: Option<bool>, queued: bool, suppressed: bool) -> Self {
+        match (delivered, queued) {
+            (Some(true), _) => Self::Sent,
+            (_, true) => Self::Queued,
+            (Some(false), false) => Self::Failed,
+            (None, false) if suppressed => Self::Suppressed,
+            (None, false) => Self::NotSent,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Sent => "Sent",
+            Self::Queued => "On its way",
+            Self::Failed => "Could not be sent",
+            Self::Suppressed => "Not sent: your address is blocked",
+            Self::NotSent => "Not sent to you",
+        }
+    }
+}
+
+pub struct IssueDelivery {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub status: DeliveryStatus,
+}
+
+/// Why issues and digests are not sent to an address anymore, as reported
+/// by the email provider.
+pub struct Suppression {
+    pub reason: String,
+    pub suppressed_at: DateTime<Utc>,
+}
+
+impl Suppression {
+    pub fn describe(&self) -> &str {
+        match self.reason.as_str() {
+            "hard_bounce" => "your mail server refused one of our emails",
+            "spam_complaint" => "one of our emails was reported as spam",
+            reason => reason,
+        }
+    }
+}
+
+/// The suppression of `email`, in clear, if any.
+#[tracing::instrument(skip_all)]
+pub async fn get_suppression(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"SELECT reason, suppressed_at FROM suppressed_emails WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(pool)
+    .observe("get_email_suppression")
+    .await
+}
+
+/// The delivery of the `RECENT_ISSUES` last issues to `subscriber_id`.
+#[tracing::instrument(skip(pool))]
+pub async fn get_recent_deliveries(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    suppressed: bool,
+) -> Result<Vec<IssueDelivery>, sqlx::Error> {
+    // The queue and the delivery log hold the email as stored.
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            (
+                SELECT bool_or(d.succeeded)
+                FROM email_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                    AND d.subscriber_email = s.email
+            ) AS delivered,
+            EXISTS (
+                SELECT 1
+                FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+                    AND q.subscriber_email = s.email
+            ) AS "queued!"
+        FROM newsletter_issues i
+        CROSS JOIN subscriptions s
+        WHERE s.id = $1
+        ORDER BY i.published_at DESC
+        LIMIT $2
+        "#,
+        subscriber_id,
+        RECENT_ISSUES
+    )
+    .fetch_all(pool)
+    .observe("get_recent_deliveries")
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| IssueDelivery {
+            issue_id: r.newsletter_issue_id,
+            title: r.title,
+            published_at: r.published_at,
+            status: DeliveryStatus::new(r.delivered, r.queued, suppressed),
+        })
+        .collect())
+}
+
+pub struct LatestIssue {
+    pub issue_id: Uuid,
+    /// The email of the subscriber as stored, to pick the translation of
+    /// the issue.
+    pub subscriber_email: String,
+}
+
+/// The latest issue fully delivered that `subscriber_id` can read.
+#[tracing::instrument(skip(pool))]
+pub async fn get_latest_issue(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<LatestIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        LatestIssue,
+        r#"
+        SELECT i.newsletter_issue_id AS issue_id, s.email AS subscriber_email
+        FROM newsletter_issues i
+        CROSS JOIN subscriptions s
+        WHERE s.id = $1 AND i.status = 'completed' AND (NOT i.paid_only OR s.paid)
+        ORDER BY i.published_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .observe("get_latest_issue_for_resend")
+    .await
+}
+
+/// How many re-sends `subscriber_id` asked for in the last day.
+#[tracing::instrument(skip(pool))]
+pub async fn count_resends_today(pool: &PgPool, subscriber_id: Uuid) -> Result<i64, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM issue_resends
+        WHERE subscriber_id = $1 AND requested_at > $2
+        "#,
+        subscriber_id,
+        Utc::now() - Duration::days(1)
+    )
+    .fetch_one(pool)
+    .observe_one("count_issue_resends")
+    .await?;
+    Ok(r.count)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn record_resend(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_resends (subscriber_id, newsletter_issue_id, requested_at)
+        VALUES ($1, $2, $3)
+        "#,
+        subscriber_id,
+        issue_id,
+        Utc::now()
+    )
+    .execute(pool)
+    .observe("insert_issue_resend")
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryStatus;
+
+    #[test]
+    fn a_successful_attempt_wins_over_the_rest() {
+        assert_eq!(
+            DeliveryStatus::new(Some(true), false, true),
+            DeliveryStatus::Sent
+        );
+        assert_eq!(
+            DeliveryStatus::new(Some(false), true, false),
+            DeliveryStatus::Queued
+        );
+        assert_eq!(
+            DeliveryStatus::new(Some(false), false, true),
+            DeliveryStatus::Failed
+        );
+    }
+
+    #[test]
+    fn issues_never_attempted_are_explained_by_the_suppression() {
+        assert_eq!(
+            DeliveryStatus::new(None, false, true),
+            DeliveryStatus::Suppressed
+        );
+        assert_eq!(
+            DeliveryStatus::new(None, false, false),
+            DeliveryStatus::NotSent
+        );
+    }
+}
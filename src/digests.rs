@@ -0,0 +1,464 @@
+//! Digests: the issues of a week or a month compiled into a single email,
+//! for the subscribers who prefer it to getting each issue as published.
+//!
+//! Digest subscribers are left out of the recipients of each issue. Once
+//! their period has passed since their last digest, the digest worker sends
+//! them the issues published since, moving `last_digest_at` forward in the
+//! same transaction.
+use crate::configuration::Settings;
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::delivery_status::get_suppression;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, MessageStream, SendEmailError};
+use crate::events::EventBus;
+use crate::pii::PiiCipher;
+use crate::polls::{self, PollLinks};
+use crate::referrals;
+use crate::startup::get_connection_pool;
+use crate::token_signer::TokenSigner;
+use crate::web_version::WebVersion;
+use htmlescape::encode_minimal;
+use sqlx::{Executor, PgPool, Postgres};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DigestFrequency {
+    /// Each issue as it is published.
+    Immediate,
+    Weekly,
+    Monthly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [
+        DigestFrequency::Immediate,
+        DigestFrequency::Weekly,
+        DigestFrequency::Monthly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Weekly => "weekly",
+            DigestFrequency::Monthly => "monthly",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| format!("{} is not a digest frequency.", s))
+    }
+
+    /// As offered on the preferences page.
+    pub fn description(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "Each issue as soon as it is published",
+            DigestFrequency::Weekly => "A weekly digest",
+            DigestFrequency::Monthly => "A monthly digest",
+        }
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_digest_frequency(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<DigestFrequency, anyhow::Error> {
+    let frequency = sqlx::query!(
+        "SELECT digest_frequency FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .observe_one("get_digest_frequency")
+    .await?
+    .digest_frequency;
+    DigestFrequency::parse(&frequency).map_err(anyhow::Error::msg)
+}
+
+/// Moving to a digest starts its period now: the earlier issues were
+/// delivered already.
+#[tracing::instrument(skip(executor))]
+pub async fn set_digest_frequency<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+    frequency: DigestFrequency,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            digest_frequency = $2,
+            last_digest_at = CASE
+                WHEN $2 = 'immediate' THEN NULL
+                WHEN digest_frequency = 'immediate' THEN now()
+                ELSE last_digest_at
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        frequency.as_str()
+    )
+    .execute(executor)
+    .observe("set_digest_frequency")
+    .await?;
+    Ok(())
+}
+
+/// An issue of a digest, as sent to its subscriber.
+pub struct DigestIssue {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+    pub web_version_link: String,
+}
+
+/// The subject, HTML and text of the digest of `issues`.
+pub fn compile_digest(
+    frequency: DigestFrequency,
+    issues: &[DigestIssue],
+) -> (String, String, String) {
+    let period = match frequency {
+        DigestFrequency::Monthly => "monthly",
+        _ => "weekly",
+    };
+    let subject = match issues.len() {
+        1 => format!("Your {} digest: {}", period, issues[0].title),
+        n => format!("Your {} digest: {} issues", period, n),
+    };
+    let mut html_content = format!("<h1>Your {} digest</h1>\n", period);
+    let mut text_content = String::new();
+    for issue in issues {
+        html_content.push_str(&format!(
+            "<h2><a href=\"{}\">{}</a></h2>\n{}\n<hr>\n",
+            encode_minimal(&issue.web_version_link),
+            encode_minimal(&issue.title),
+            body_of(&issue.html_content)
+        ));
+        text_content.push_str(&format!(
+            "{}\nView in browser: {}\n\n{}\n\n---\n\n",
+            issue.title,
+            issue.web_version_link,
+            issue.text_content.trim()
+        ));
+    }
+    (subject, html_content, text_content.trim_end().to_owned())
+}
+
+/// The content of the `<body>` of full documents, all of it otherwise.
+fn body_of(html: &str) -> &str {
+    // Lowercasing ASCII keeps the byte offsets.
+    let lowercase = html.to_ascii_lowercase();
+    let Some(start) = lowercase
+        .find("<body")
+        .and_then(|tag| lowercase[tag..].find('>').map(|end| tag + end + 1))
+    else {
+        return html;
+    };
+    let end = lowercase[start..]
+        .find("</body>")
+        .map_or(html.len(), |end| start + end);
+    &html[start..end]
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DigestOutcome {
+    Sent,
+    /// Nothing was published during the period.
+    Empty,
+    /// The digest is skipped, e.g. rejected by the provider.
+    Failed,
+    /// The provider asked us to wait: the digest is sent again later.
+    Throttled(Duration),
+    NoneDue,
+}
+
+/// Send the next digest due, if any.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_send_next_digest(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    pii: &PiiCipher,
+    web_version: &WebVersion,
+    poll_links: &PollLinks,
+) -> Result<DigestOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, email, paid, digest_frequency, last_digest_at AS "last_digest_at!"
+        FROM subscriptions
+        WHERE status = 'confirmed'
+            AND deleted_at IS NULL
+            AND digest_frequency <> 'immediate'
+            AND NOT EXISTS (
+                SELECT 1 FROM suppressed_emails x WHERE lower(x.email) = lower(subscriptions.email)
+            )
+            AND last_digest_at <= now() - CASE digest_frequency
+                WHEN 'weekly' THEN interval '7 days'
+                ELSE interval '1 month'
+            END
+        ORDER BY last_digest_at
+        FOR UPDATE SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("dequeue_digest")
+    .await?;
+    let Some(subscriber) = subscriber else {
+        return Ok(DigestOutcome::NoneDue);
+    };
+    let frequency =
+        DigestFrequency::parse(&subscriber.digest_frequency).map_err(anyhow::Error::msg)?;
+    let issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, html_content, text_content, paid_only
+        FROM newsletter_issues
+        WHERE published_at > $1 AND (NOT paid_only OR $2)
+        ORDER BY published_at
+        "#,
+        subscriber.last_digest_at,
+        subscriber.paid
+    )
+    .fetch_all(&mut *transaction)
+    .observe("get_digest_issues")
+    .await?;
+    let issue_ids: Vec<_> = issues.iter().map(|i| i.newsletter_issue_id).collect();
+    let outcome = if issues.is_empty() {
+        DigestOutcome::Empty
+    } else {
+        let mut digest_issues = Vec::with_capacity(issues.len());
+        for issue in issues {
+            let (html_content, text_content) = polls::render_for_delivery(
+                pool,
+                poll_links,
+                issue.newsletter_issue_id,
+                &subscriber.email,
+                &issue.html_content,
+                &issue.text_content,
+            )
+            .await?;
+            let (html_content, text_content) = referrals::render_for_delivery(
+                pool,
+                web_version.base_url(),
+                &subscriber.email,
+                &html_content,
+                &text_content,
+            )
+            .await?;
+            digest_issues.push(DigestIssue {
+                title: issue.title,
+                html_content,
+                text_content,
+                web_version_link: web_version.link(issue.newsletter_issue_id, issue.paid_only),
+            });
+        }
+        let (subject, html_content, text_content) = compile_digest(frequency, &digest_issues);
+        let recipient = pii
+            .open_email(&subscriber.email)
+            .and_then(|email| SubscriberEmail::parse(email).map_err(anyhow::Error::from));
+        let sent = match recipient {
+            Ok(recipient) if get_suppression(pool, recipient.as_ref()).await?.is_some() => {
+                tracing::info!("Skipping the digest of a suppressed address");
+                return skip_digest(transaction, subscriber.id, &issue_ids).await;
+            }
+            Ok(recipient) => {
+                email_client
+                    .send_email(
+                        &recipient,
+                        &subject,
+                        &html_content,
+                        &text_content,
+                        MessageStream::Broadcast,
+                    )
+                    .await
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Skipping the digest of a subscriber. Their stored contact details are invalid",
+                );
+                return skip_digest(transaction, subscriber.id, &issue_ids).await;
+            }
+        };
+        match sent {
+            Ok(sent) => {
+                record_send(&mut *transaction, &sent).await?;
+                DigestOutcome::Sent
+            }
+            Err(SendEmailError::Throttled { retry_after }) => {
+                transaction.rollback().await?;
+                return Ok(DigestOutcome::Throttled(retry_after));
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a digest. Skipping.",
+                );
+                return skip_digest(transaction, subscriber.id, &issue_ids).await;
+            }
+        }
+    };
+    if outcome == DigestOutcome::Sent {
+        record_digest(&mut *transaction, subscriber.id, &issue_ids, true).await?;
+    }
+    advance_digest(&mut *transaction, subscriber.id).await?;
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+/// The issues of a failed digest are not sent again.
+async fn skip_digest(
+    mut transaction: sqlx::Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    issue_ids: &[Uuid],
+) -> Result<DigestOutcome, anyhow::Error> {
+    record_digest(&mut *transaction, subscriber_id, issue_ids, false).await?;
+    advance_digest(&mut *transaction, subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(DigestOutcome::Failed)
+}
+
+async fn record_digest<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+    issue_ids: &[Uuid],
+    succeeded: bool,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO digest_deliveries (digest_id, subscriber_id, sent_at, newsletter_issue_ids, succeeded)
+        VALUES ($1, $2, now(), $3, $4)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        issue_ids,
+        succeeded
+    )
+    .execute(executor)
+    .observe("record_digest_delivery")
+    .await?;
+    Ok(())
+}
+
+async fn advance_digest<'a, E>(executor: E, subscriber_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE subscriptions SET last_digest_at = now() WHERE id = $1",
+        subscriber_id
+    )
+    .execute(executor)
+    .observe("advance_digest")
+    .await?;
+    Ok(())
+}
+
+async fn digest_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    pii: PiiCipher,
+    web_version: WebVersion,
+    poll_links: PollLinks,
+    event_bus: EventBus,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_next_digest(&pool, &email_client, &pii, &web_version, &poll_links).await {
+            Ok(DigestOutcome::NoneDue) => tokio::time::sleep(Duration::from_secs(60)).await,
+            Ok(DigestOutcome::Throttled(retry_after)) => tokio::time::sleep(retry_after).await,
+            Err(e) => {
+                event_bus.publish_job_failure("digests", &e).await;
+                tokio::time::sleep(Duration::from_secs(1)).await
+            }
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Send the digests as they fall due until the process is stopped.
+pub async fn run_digests_until_stopped(
+    configuration: Settings,
+    event_bus: EventBus,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    let email_client = configuration.build_email_client()?;
+    let pii = PiiCipher::new(configuration.pii_encryption.as_ref()).map_err(anyhow::Error::msg)?;
+    let signer = TokenSigner::new(&configuration.token_signing).map_err(anyhow::Error::msg)?;
+    let signer = Arc::new(signer);
+    let web_version = WebVersion::new(configuration.application.base_url.clone(), signer.clone());
+    let poll_links = PollLinks::new(configuration.application.base_url, signer);
+    digest_loop(
+        connection_pool,
+        email_client,
+        pii,
+        web_version,
+        poll_links,
+        event_bus,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{body_of, compile_digest, DigestFrequency, DigestIssue};
+
+    #[test]
+    fn frequencies_round_trip() {
+        for frequency in DigestFrequency::ALL {
+            assert_eq!(DigestFrequency::parse(frequency.as_str()), Ok(frequency));
+        }
+        assert!(DigestFrequency::parse("daily").is_err());
+    }
+
+    #[test]
+    fn full_documents_are_reduced_to_their_body() {
+        assert_eq!(
+            body_of("<html><head><title>T</title></head><BODY class=\"x\"><p>Hi</p></BODY></html>"),
+            "<p>Hi</p>"
+        );
+        assert_eq!(body_of("<p>Hi</p>"), "<p>Hi</p>");
+    }
+
+    #[test]
+    fn digests_list_each_issue_with_its_link() {
+        let issues = [
+            DigestIssue {
+                title: "Issue <1>".into(),
+                html_content: "<p>One</p>".into(),
+                text_content: "One\n".into(),
+                web_version_link: "https://example.com/archive/1".into(),
+            },
+            DigestIssue {
+                title: "Issue 2".into(),
+                html_content: "<p>Two</p>".into(),
+                text_content: "Two".into(),
+                web_version_link: "https://example.com/archive/2".into(),
+            },
+        ];
+        let (subject, html, text) = compile_digest(DigestFrequency::Monthly, &issues);
+        assert_eq!(subject, "Your monthly digest: 2 issues");
+        assert!(html.contains(
+            "<h2><a href=\"https://example.com/archive/1\">Issue &lt;1&gt;</a></h2>\n<p>One</p>"
+        ));
+        assert_eq!(
+            text,
+            "Issue <1>\nView in browser: https://example.com/archive/1\n\nOne\n\n---\n\n\
+             Issue 2\nView in browser: https://example.com/archive/2\n\nTwo\n\n---"
+        );
+        let (subject, _, _) = compile_digest(DigestFrequency::Weekly, &issues[1..]);
+        assert_eq!(subject, "Your weekly digest: Issue 2");
+    }
+}
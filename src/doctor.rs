@@ -0,0 +1,191 @@
+//! `zero2prod doctor`: check that the environment the application is about
+//! to run in is healthy.
+use crate::configuration::Settings;
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{Connection, PgConnection};
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(5);
+
+pub struct CheckReport {
+    pub name: &'static str,
+    /// A short description of what was found.
+    pub outcome: Result<String, String>,
+}
+
+/// Run every check and print a pass/fail line for each of them.
+/// Returns whether they all passed.
+pub async fn run_doctor(configuration: Settings) -> bool {
+    let reports = run_checks(&configuration).await;
+    for report in &reports {
+        match &report.outcome {
+            Ok(details) => println!("[PASS] {}: {}", report.name, details),
+            Err(details) => println!("[FAIL] {}: {}", report.name, details),
+        }
+    }
+    reports.iter().all(|report| report.outcome.is_ok())
+}
+
+pub async fn run_checks(configuration: &Settings) -> Vec<CheckReport> {
+    vec![
+        check("Database connectivity", check_database(configuration)).await,
+        check("Database migrations", check_migrations(configuration)).await,
+        check("Redis", check_redis(configuration)).await,
+        check("Email provider credentials", check_email(configuration)).await,
+        check("Base URL DNS", check_base_url(configuration)).await,
+        check("Clock skew", check_clock_skew(configuration)).await,
+    ]
+}
+
+async fn check(
+    name: &'static str,
+    check: impl Future<Output = Result<String, String>>,
+) -> CheckReport {
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {:?}", CHECK_TIMEOUT)));
+    CheckReport { name, outcome }
+}
+
+async fn connect(configuration: &Settings) -> Result<PgConnection, String> {
+    PgConnection::connect_with(&configuration.database.with_db())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn check_database(configuration: &Settings) -> Result<String, String> {
+    connect(configuration).await?;
+    Ok(format!(
+        "connected to {}:{}/{}",
+        configuration.database.host,
+        configuration.database.port,
+        configuration.database.database_name
+    ))
+}
+
+async fn check_migrations(configuration: &Settings) -> Result<String, String> {
+    let mut connection = connect(configuration).await?;
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+            .fetch_all(&mut connection)
+            .await
+            .map_err(|e| format!("no migrations have been applied ({})", e))?;
+    let expected: Vec<i64> = sqlx::migrate!("./migrations")
+        .iter()
+        .map(|migration| migration.version)
+        .collect();
+    let pending = pending_migrations(&expected, &applied);
+    if pending.is_empty() {
+        Ok(format!("{} migrations applied", applied.len()))
+    } else {
+        Err(format!("pending migrations: {:?}", pending))
+    }
+}
+
+fn pending_migrations(expected: &[i64], applied: &[i64]) -> Vec<i64> {
+    expected
+        .iter()
+        .filter(|version| !applied.contains(version))
+        .copied()
+        .collect()
+}
+
+async fn check_redis(configuration: &Settings) -> Result<String, String> {
+    let url = reqwest::Url::parse(configuration.redis_uri.expose_secret())
+        .map_err(|e| format!("invalid redis_uri: {}", e))?;
+    let host = url.host_str().ok_or("redis_uri has no host")?;
+    let port = url.port().unwrap_or(6379);
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(b"*1\r\n$4\r\nPING\r\n")
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut response = [0; 64];
+    let read = stream
+        .read(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    let response = String::from_utf8_lossy(&response[..read]);
+    if response.starts_with("+PONG") {
+        Ok(format!("{}:{} answered PING", host, port))
+    } else {
+        Err(format!("unexpected reply to PING: {}", response.trim()))
+    }
+}
+
+async fn check_email(configuration: &Settings) -> Result<String, String> {
+    configuration
+        .build_email_client()
+        .map_err(|e| e.to_string())?
+        .check_credentials()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok("the provider accepted a sandboxed request".into())
+}
+
+async fn check_base_url(configuration: &Settings) -> Result<String, String> {
+    let base_url = &configuration.application.base_url;
+    let url = reqwest::Url::parse(base_url)
+        .or_else(|_| reqwest::Url::parse(&format!("http://{}", base_url)))
+        .map_err(|e| format!("invalid base_url: {}", e))?;
+    let host = url.host_str().ok_or("base_url has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("{} does not resolve: {}", host, e))?
+        .collect();
+    Ok(format!("{} resolves to {:?}", host, addresses))
+}
+
+async fn check_clock_skew(configuration: &Settings) -> Result<String, String> {
+    let mut connection = connect(configuration).await?;
+    let database_now: DateTime<Utc> = sqlx::query_scalar("SELECT now()")
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|e| e.to_string())?;
+    evaluate_clock_skew(Utc::now(), database_now)
+}
+
+fn evaluate_clock_skew(local: DateTime<Utc>, reference: DateTime<Utc>) -> Result<String, String> {
+    let skew = (local - reference).abs().to_std().unwrap_or_default();
+    if skew <= MAX_CLOCK_SKEW {
+        Ok(format!("{:?} away from the database clock", skew))
+    } else {
+        Err(format!(
+            "{:?} away from the database clock, more than {:?}",
+            skew, MAX_CLOCK_SKEW
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate_clock_skew, pending_migrations};
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn migrations_missing_from_the_database_are_pending() {
+        assert_eq!(pending_migrations(&[1, 2, 3], &[1, 3]), vec![2]);
+        assert!(pending_migrations(&[1, 2], &[1, 2]).is_empty());
+    }
+
+    #[test]
+    fn a_small_clock_skew_passes() {
+        let now = Utc::now();
+        assert_ok!(evaluate_clock_skew(now, now - Duration::seconds(2)));
+    }
+
+    #[test]
+    fn a_large_clock_skew_fails() {
+        let now = Utc::now();
+        assert_err!(evaluate_clock_skew(now, now + Duration::minutes(1)));
+    }
+}
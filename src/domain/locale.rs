@@ -0,0 +1,64 @@
+use crate::domain::DomainValidationError;
+
+/// A language tag such as `en` or `pt-br`, lower-cased.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale(String);
+
+impl Locale {
+    /// Accepts a primary language of 2 or 3 letters followed by subtags of
+    /// up to 8 letters or digits, separated by `-` or `_`.
+    pub fn parse(s: String) -> Result<Locale, DomainValidationError> {
+        let normalized = s.trim().to_lowercase().replace('_', "-");
+        let mut subtags = normalized.split('-');
+        let language_is_valid = subtags.next().is_some_and(|language| {
+            (2..=3).contains(&language.len()) && language.bytes().all(|b| b.is_ascii_lowercase())
+        });
+        let subtags_are_valid = subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        });
+        if language_is_valid && subtags_are_valid && normalized.len() <= 35 {
+            Ok(Self(normalized))
+        } else {
+            Err(DomainValidationError::InvalidLocale)
+        }
+    }
+}
+
+impl AsRef<str> for Locale {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Locale;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn locales_are_normalised() {
+        assert_ok_eq!(
+            Locale::parse(" pt_BR ".into()).map(|l| l.as_ref().to_owned()),
+            "pt-br".to_owned()
+        );
+        assert_ok_eq!(
+            Locale::parse("zh-Hant-TW".into()).map(|l| l.as_ref().to_owned()),
+            "zh-hant-tw".to_owned()
+        );
+    }
+
+    #[test]
+    fn malformed_locales_are_rejected() {
+        for locale in [
+            "",
+            "e",
+            "english",
+            "en-",
+            "en--us",
+            "en-toolongsubtag",
+            "e1",
+        ] {
+            assert_err!(Locale::parse(locale.into()));
+        }
+    }
+}
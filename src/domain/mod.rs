@@ -1,7 +1,13 @@
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_phone;
+mod validation_error;
 
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_phone::SubscriberPhone;
+pub use validation_error::{DomainValidationError, ValidationRule};
@@ -1,3 +1,4 @@
+use crate::domain::Locale;
 use crate::domain::SubscriberEmail;
 use crate::domain::SubscriberName;
 
@@ -5,4 +6,6 @@ pub struct NewSubscriber {
     // We are not using `String` anymore!
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    /// Picks the translation of the issues sent to the subscriber.
+    pub locale: Option<Locale>,
 }
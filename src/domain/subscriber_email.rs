@@ -1,14 +1,15 @@
+use crate::domain::DomainValidationError;
 use validator::validate_email;
 
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
+    pub fn parse(s: String) -> Result<SubscriberEmail, DomainValidationError> {
         if validate_email(&s) {
             Ok(Self(s))
         } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+            Err(DomainValidationError::InvalidEmail)
         }
     }
 }
@@ -1,13 +1,17 @@
+use crate::domain::DomainValidationError;
+use crate::pii;
 use unicode_segmentation::UnicodeSegmentation;
 
+const MAX_LENGTH: usize = 256;
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
     /// Returns an instance of `SubscriberName` if the input satisfies all
-    /// our validation constraints on subscriber names.
-    /// It panics otherwise.
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
+    /// our validation constraints on subscriber names, or the first
+    /// constraint it breaks.
+    pub fn parse(s: String) -> Result<SubscriberName, DomainValidationError> {
         // `.trim()` returns a view over the input `s` without trailing
         // whitespace-like characters.
         // `.is_empty` checks if the view contains any character.
@@ -20,15 +24,24 @@ impl SubscriberName {
         // `graphemes` returns an iterator over the graphemes in the input `s`.
         // `true` specifies that we want to use the extended grapheme definition set,
         // the recommended one.
-        let is_too_long = s.graphemes(true).count() > 256;
+        let is_too_long = s.graphemes(true).count() > MAX_LENGTH;
 
         // Iterate over all characters in the input `s` to check if any of them matches
         // one of the characters in the forbidden array.
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
         let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
+        // Stored names starting like sealed ones would be taken for them.
+        let has_reserved_prefix = s.starts_with(pii::PREFIX);
+
+        if is_empty_or_whitespace {
+            Err(DomainValidationError::EmptyName)
+        } else if is_too_long {
+            Err(DomainValidationError::NameTooLong { max: MAX_LENGTH })
+        } else if contains_forbidden_characters {
+            Err(DomainValidationError::NameForbiddenCharacters)
+        } else if has_reserved_prefix {
+            Err(DomainValidationError::NameReservedPrefix)
         } else {
             Ok(Self(s))
         }
@@ -43,8 +56,8 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use crate::domain::{DomainValidationError, SubscriberName};
+    use claims::{assert_err, assert_err_eq, assert_ok};
 
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
@@ -55,7 +68,10 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(
+            SubscriberName::parse(name),
+            DomainValidationError::NameTooLong { max: 256 }
+        );
     }
 
     #[test]
@@ -74,10 +90,21 @@ mod tests {
     fn names_containing_an_invalid_character_are_rejected() {
         for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
             let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+            assert_err_eq!(
+                SubscriberName::parse(name),
+                DomainValidationError::NameForbiddenCharacters
+            );
         }
     }
 
+    #[test]
+    fn names_that_read_as_sealed_are_rejected() {
+        assert_err_eq!(
+            SubscriberName::parse("pii:x:y".into()),
+            DomainValidationError::NameReservedPrefix
+        );
+    }
+
     #[test]
     fn a_valid_name_is_parsed_successfully() {
         let name = "Ursula Le Guin".to_string();
@@ -0,0 +1,274 @@
+use crate::domain::DomainValidationError;
+
+/// The longest number E.164 allows, country calling code included.
+const MAX_DIGITS: usize = 15;
+/// The shortest national numbers, e.g. in Niue, have 4 digits.
+const MIN_NATIONAL_DIGITS: usize = 4;
+
+/// Country calling codes, with the country of their numbers as an ISO
+/// 3166-1 alpha-2 code. The codes shared by several countries map to the
+/// main one, except for Canada within the North American plan.
+const CALLING_CODES: &[(&str, &str)] = &[
+    ("1", "US"),
+    ("7", "RU"),
+    ("20", "EG"),
+    ("27", "ZA"),
+    ("30", "GR"),
+    ("31", "NL"),
+    ("32", "BE"),
+    ("33", "FR"),
+    ("34", "ES"),
+    ("36", "HU"),
+    ("39", "IT"),
+    ("40", "RO"),
+    ("41", "CH"),
+    ("43", "AT"),
+    ("44", "GB"),
+    ("45", "DK"),
+    ("46", "SE"),
+    ("47", "NO"),
+    ("48", "PL"),
+    ("49", "DE"),
+    ("51", "PE"),
+    ("52", "MX"),
+    ("53", "CU"),
+    ("54", "AR"),
+    ("55", "BR"),
+    ("56", "CL"),
+    ("57", "CO"),
+    ("58", "VE"),
+    ("60", "MY"),
+    ("61", "AU"),
+    ("62", "ID"),
+    ("63", "PH"),
+    ("64", "NZ"),
+    ("65", "SG"),
+    ("66", "TH"),
+    ("81", "JP"),
+    ("82", "KR"),
+    ("84", "VN"),
+    ("86", "CN"),
+    ("90", "TR"),
+    ("91", "IN"),
+    ("92", "PK"),
+    ("93", "AF"),
+    ("94", "LK"),
+    ("95", "MM"),
+    ("98", "IR"),
+    ("211", "SS"),
+    ("212", "MA"),
+    ("213", "DZ"),
+    ("216", "TN"),
+    ("218", "LY"),
+    ("220", "GM"),
+    ("221", "SN"),
+    ("233", "GH"),
+    ("234", "NG"),
+    ("251", "ET"),
+    ("254", "KE"),
+    ("255", "TZ"),
+    ("256", "UG"),
+    ("260", "ZM"),
+    ("263", "ZW"),
+    ("351", "PT"),
+    ("352", "LU"),
+    ("353", "IE"),
+    ("354", "IS"),
+    ("355", "AL"),
+    ("356", "MT"),
+    ("357", "CY"),
+    ("358", "FI"),
+    ("359", "BG"),
+    ("370", "LT"),
+    ("371", "LV"),
+    ("372", "EE"),
+    ("373", "MD"),
+    ("374", "AM"),
+    ("375", "BY"),
+    ("376", "AD"),
+    ("377", "MC"),
+    ("378", "SM"),
+    ("380", "UA"),
+    ("381", "RS"),
+    ("382", "ME"),
+    ("385", "HR"),
+    ("386", "SI"),
+    ("387", "BA"),
+    ("389", "MK"),
+    ("420", "CZ"),
+    ("421", "SK"),
+    ("423", "LI"),
+    ("852", "HK"),
+    ("853", "MO"),
+    ("855", "KH"),
+    ("856", "LA"),
+    ("880", "BD"),
+    ("886", "TW"),
+    ("960", "MV"),
+    ("961", "LB"),
+    ("962", "JO"),
+    ("963", "SY"),
+    ("964", "IQ"),
+    ("965", "KW"),
+    ("966", "SA"),
+    ("967", "YE"),
+    ("968", "OM"),
+    ("970", "PS"),
+    ("971", "AE"),
+    ("972", "IL"),
+    ("973", "BH"),
+    ("974", "QA"),
+    ("975", "BT"),
+    ("976", "MN"),
+    ("977", "NP"),
+    ("992", "TJ"),
+    ("993", "TM"),
+    ("994", "AZ"),
+    ("995", "GE"),
+    ("996", "KG"),
+    ("998", "UZ"),
+];
+
+/// The Canadian area codes of the North American numbering plan.
+const CANADIAN_AREA_CODES: &[&str] = &[
+    "204", "226", "236", "249", "250", "263", "289", "306", "343", "354", "365", "367", "368",
+    "382", "403", "416", "418", "428", "431", "437", "438", "450", "468", "474", "506", "514",
+    "519", "548", "579", "581", "584", "587", "604", "613", "639", "647", "672", "683", "705",
+    "709", "742", "753", "778", "780", "782", "807", "819", "825", "867", "873", "879", "902",
+    "905",
+];
+
+/// A phone number in E.164 form, e.g. `+14155550100`, with the country it
+/// belongs to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriberPhone {
+    number: String,
+    country: &'static str,
+}
+
+impl SubscriberPhone {
+    /// Accepts international numbers, starting with `+` or `00`, written
+    /// with spaces, dashes, dots or parentheses between the digits.
+    pub fn parse(s: String) -> Result<SubscriberPhone, DomainValidationError> {
+        let compact: String = s
+            .trim()
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+            .collect();
+        let digits = compact
+            .strip_prefix('+')
+            .or_else(|| compact.strip_prefix("00"))
+            .ok_or(DomainValidationError::InvalidPhoneNumber)?;
+        if digits.len() > MAX_DIGITS
+            || digits.starts_with('0')
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(DomainValidationError::InvalidPhoneNumber);
+        }
+        let (calling_code, country) = CALLING_CODES
+            .iter()
+            .find(|(calling_code, _)| digits.starts_with(calling_code))
+            .ok_or(DomainValidationError::InvalidPhoneNumber)?;
+        let national = &digits[calling_code.len()..];
+        let country = if *calling_code == "1" {
+            // North American numbers are a 3-digit area code followed by
+            // a 7-digit number.
+            if national.len() != 10 {
+                return Err(DomainValidationError::InvalidPhoneNumber);
+            }
+            if CANADIAN_AREA_CODES.contains(&&national[..3]) {
+                "CA"
+            } else {
+                country
+            }
+        } else {
+            if national.len() < MIN_NATIONAL_DIGITS {
+                return Err(DomainValidationError::InvalidPhoneNumber);
+            }
+            country
+        };
+        Ok(Self {
+            number: format!("+{}", digits),
+            country,
+        })
+    }
+
+    /// The ISO 3166-1 alpha-2 code of the country of the number, e.g. `US`.
+    pub fn country(&self) -> &'static str {
+        self.country
+    }
+}
+
+impl AsRef<str> for SubscriberPhone {
+    fn as_ref(&self) -> &str {
+        &self.number
+    }
+}
+
+impl std::fmt::Display for SubscriberPhone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.number.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubscriberPhone, CALLING_CODES};
+    use claims::assert_err;
+
+    fn parse(s: &str) -> (String, &'static str) {
+        let phone = SubscriberPhone::parse(s.into()).unwrap();
+        (phone.to_string(), phone.country())
+    }
+
+    #[test]
+    fn numbers_are_normalised_to_e164() {
+        assert_eq!(parse("+1 (415) 555-0100"), ("+14155550100".into(), "US"));
+        assert_eq!(parse("0044 20 7946 0958"), ("+442079460958".into(), "GB"));
+        assert_eq!(parse(" +49.30.123456 "), ("+4930123456".into(), "DE"));
+    }
+
+    #[test]
+    fn the_country_is_detected_from_the_calling_code() {
+        assert_eq!(parse("+351 912 345 678").1, "PT");
+        assert_eq!(parse("+33 6 12 34 56 78").1, "FR");
+        assert_eq!(parse("+971 50 123 4567").1, "AE");
+    }
+
+    #[test]
+    fn canadian_numbers_are_told_apart_from_american_ones() {
+        assert_eq!(parse("+1 416 555 0100").1, "CA");
+        assert_eq!(parse("+1 212 555 0100").1, "US");
+    }
+
+    #[test]
+    fn malformed_numbers_are_rejected() {
+        for number in [
+            "",
+            "4155550100",
+            "+",
+            "+1 415 555 010",
+            "+44 20 7946 0958 1234 5",
+            "+0 123 456",
+            "+44 20 79a6 0958",
+            "+999 123 456",
+            "+49 12",
+        ] {
+            assert_err!(SubscriberPhone::parse(number.into()), "{:?}", number);
+        }
+    }
+
+    #[test]
+    fn calling_codes_are_prefix_free() {
+        for (code, _) in CALLING_CODES {
+            for (other, _) in CALLING_CODES {
+                assert!(
+                    code == other || !other.starts_with(code),
+                    "{} {}",
+                    code,
+                    other
+                );
+            }
+        }
+    }
+}
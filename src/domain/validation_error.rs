@@ -0,0 +1,88 @@
+/// The rule a rejected value broke.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationRule {
+    Required,
+    TooLong,
+    ForbiddenCharacters,
+    InvalidFormat,
+}
+
+/// A value rejected by one of the domain parsers.
+///
+/// The message never quotes the value: it can be shown to the user and
+/// logged as is.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum DomainValidationError {
+    #[error("The email address is not valid.")]
+    InvalidEmail,
+    #[error("The name is required.")]
+    EmptyName,
+    #[error("The name must be at most {max} characters long.")]
+    NameTooLong { max: usize },
+    #[error("The name cannot contain any of / ( ) \" < > \\ {{ }}.")]
+    NameForbiddenCharacters,
+    #[error("The name cannot start with `pii:`.")]
+    NameReservedPrefix,
+    #[error("The locale is not a valid language tag, e.g. `pt-br`.")]
+    InvalidLocale,
+    #[error("The phone number is not a valid international number, e.g. `+14155550100`.")]
+    InvalidPhoneNumber,
+}
+
+impl DomainValidationError {
+    /// The field of the form or the payload the value came from.
+    pub fn field(&self) -> &'static str {
+        match self {
+            DomainValidationError::InvalidEmail => "email",
+            DomainValidationError::EmptyName
+            | DomainValidationError::NameTooLong { .. }
+            | DomainValidationError::NameForbiddenCharacters
+            | DomainValidationError::NameReservedPrefix => "name",
+            DomainValidationError::InvalidLocale => "locale",
+            DomainValidationError::InvalidPhoneNumber => "phone_number",
+        }
+    }
+
+    pub fn rule(&self) -> ValidationRule {
+        match self {
+            DomainValidationError::EmptyName => ValidationRule::Required,
+            DomainValidationError::NameTooLong { .. } => ValidationRule::TooLong,
+            DomainValidationError::NameForbiddenCharacters => ValidationRule::ForbiddenCharacters,
+            DomainValidationError::InvalidEmail
+            | DomainValidationError::NameReservedPrefix
+            | DomainValidationError::InvalidLocale
+            | DomainValidationError::InvalidPhoneNumber => ValidationRule::InvalidFormat,
+        }
+    }
+}
+
+/// Serialised as `{"field": ..., "rule": ..., "message": ...}`, the body of
+/// the validation errors of the API.
+impl serde::Serialize for DomainValidationError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("DomainValidationError", 3)?;
+        s.serialize_field("field", self.field())?;
+        s.serialize_field("rule", &self.rule())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainValidationError;
+
+    #[test]
+    fn errors_serialise_with_their_field_and_rule() {
+        assert_eq!(
+            serde_json::to_value(DomainValidationError::NameTooLong { max: 256 }).unwrap(),
+            serde_json::json!({
+                "field": "name",
+                "rule": "too_long",
+                "message": "The name must be at most 256 characters long.",
+            })
+        );
+    }
+}
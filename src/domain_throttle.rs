@@ -0,0 +1,176 @@
+//! Pace the deliveries to each recipient domain on its own.
+//!
+//! Big mailbox providers throttle bursts from senders they do not know yet:
+//! each domain gets its own cap on sends per minute and on sends in flight,
+//! so that a broadcast to thousands of Gmail users does not hold up, or get
+//! throttled along with, the few deliveries to everybody else.
+use crate::configuration::{DomainLimits, DomainThrottlingSettings};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The lower-cased domain of `email`.
+pub fn recipient_domain(email: &str) -> String {
+    email
+        .rsplit_once('@')
+        .map_or(email, |(_, domain)| domain)
+        .to_lowercase()
+}
+
+#[derive(Default)]
+struct DomainUsage {
+    sent_at: VecDeque<Instant>,
+    in_flight: u32,
+}
+
+impl DomainUsage {
+    fn prune(&mut self, now: Instant) {
+        while let Some(oldest) = self.sent_at.front() {
+            if now.duration_since(*oldest) >= RATE_WINDOW {
+                self.sent_at.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn is_saturated(&self, limits: &DomainLimits) -> bool {
+        self.sent_at.len() >= limits.max_per_minute as usize
+            || self.in_flight >= limits.max_concurrency
+    }
+
+    fn is_idle(&self) -> bool {
+        self.sent_at.is_empty() && self.in_flight == 0
+    }
+}
+
+/// The sends to each domain over the last minute and in flight. The limits
+/// are passed on every call, so that they follow the hot-reloaded settings.
+#[derive(Clone, Default)]
+pub struct DomainThrottle {
+    usage: Arc<Mutex<HashMap<String, DomainUsage>>>,
+}
+
+impl DomainThrottle {
+    /// The domains that cannot take another send right now.
+    pub fn saturated_domains(
+        &self,
+        settings: &DomainThrottlingSettings,
+        now: Instant,
+    ) -> Vec<String> {
+        let mut usage = self.usage.lock().unwrap();
+        usage.values_mut().for_each(|u| u.prune(now));
+        usage.retain(|_, u| !u.is_idle());
+        usage
+            .iter()
+            .filter(|(domain, u)| u.is_saturated(settings.limits(domain)))
+            .map(|(domain, _)| domain.clone())
+            .collect()
+    }
+
+    /// Reserve a send to `domain`, or return `None` when it is at its caps.
+    pub fn try_acquire(
+        &self,
+        settings: &DomainThrottlingSettings,
+        domain: &str,
+        now: Instant,
+    ) -> Option<DomainPermit> {
+        let mut usage = self.usage.lock().unwrap();
+        let entry = usage.entry(domain.to_owned()).or_default();
+        entry.prune(now);
+        if entry.is_saturated(settings.limits(domain)) {
+            return None;
+        }
+        entry.sent_at.push_back(now);
+        entry.in_flight += 1;
+        Some(DomainPermit {
+            usage: self.usage.clone(),
+            domain: domain.to_owned(),
+        })
+    }
+}
+
+/// A send in flight to a domain, released when dropped.
+pub struct DomainPermit {
+    usage: Arc<Mutex<HashMap<String, DomainUsage>>>,
+    domain: String,
+}
+
+impl Drop for DomainPermit {
+    fn drop(&mut self) {
+        if let Some(usage) = self.usage.lock().unwrap().get_mut(&self.domain) {
+            usage.in_flight = usage.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{recipient_domain, DomainThrottle};
+    use crate::configuration::{DomainLimits, DomainThrottlingSettings};
+    use std::time::{Duration, Instant};
+
+    fn settings() -> DomainThrottlingSettings {
+        DomainThrottlingSettings {
+            default: DomainLimits {
+                max_per_minute: 100,
+                max_concurrency: 1,
+            },
+            domains: [(
+                "gmail.com".to_string(),
+                DomainLimits {
+                    max_per_minute: 2,
+                    max_concurrency: 2,
+                },
+            )]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn the_domain_is_taken_from_the_last_at_sign_and_lower_cased() {
+        assert_eq!(recipient_domain("Ursula@GMail.com"), "gmail.com");
+        assert_eq!(recipient_domain("\"a@b\"@outlook.com"), "outlook.com");
+    }
+
+    #[test]
+    fn a_domain_is_throttled_once_it_used_its_rate_for_the_minute() {
+        let throttle = DomainThrottle::default();
+        let settings = settings();
+        let now = Instant::now();
+        drop(throttle.try_acquire(&settings, "gmail.com", now).unwrap());
+        drop(throttle.try_acquire(&settings, "gmail.com", now).unwrap());
+        assert!(throttle.try_acquire(&settings, "gmail.com", now).is_none());
+        assert_eq!(
+            throttle.saturated_domains(&settings, now),
+            vec!["gmail.com"]
+        );
+        // Other domains are paced on their own.
+        assert!(throttle
+            .try_acquire(&settings, "outlook.com", now)
+            .is_some());
+
+        let later = now + Duration::from_secs(60);
+        assert!(throttle.saturated_domains(&settings, later).is_empty());
+        assert!(throttle
+            .try_acquire(&settings, "gmail.com", later)
+            .is_some());
+    }
+
+    #[test]
+    fn a_domain_is_throttled_while_its_concurrency_is_used_up() {
+        let throttle = DomainThrottle::default();
+        let settings = settings();
+        let now = Instant::now();
+        let permit = throttle.try_acquire(&settings, "outlook.com", now).unwrap();
+        assert!(throttle
+            .try_acquire(&settings, "outlook.com", now)
+            .is_none());
+        drop(permit);
+        assert!(throttle
+            .try_acquire(&settings, "outlook.com", now)
+            .is_some());
+    }
+}
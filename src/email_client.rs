@@ -1,12 +1,90 @@
+use crate::circuit_breaker::CircuitBreaker;
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::metrics::{
+    EMAIL_PROVIDER_REQUEST_DURATION, EMAIL_PROVIDER_RESPONSES, EMAIL_REQUEST_SIZE, EMAIL_SENDS,
+    EMAIL_SEND_RETRIES,
+};
+use crate::startup::StartupError;
+use reqwest::header::{CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, Secret};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+
 pub struct EmailClient {
+    primary: EmailProvider,
+    failover: Option<Failover>,
+}
+
+/// A secondary provider that takes over while the primary is unhealthy.
+struct Failover {
+    secondary: EmailProvider,
+    breaker: CircuitBreaker,
+}
+
+struct EmailProvider {
+    name: &'static str,
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     api_public_key: Secret<String>,
     api_private_key: Secret<String>,
+    message_streams: MessageStreams,
+    cost_per_email: f64,
+}
+
+/// An email accepted by a provider.
+#[derive(Debug)]
+pub struct SentEmail {
+    pub provider: &'static str,
+    pub estimated_cost: f64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    /// The provider rate-limited us and said when to come back.
+    #[error("The email provider asked us to retry after {retry_after:?}")]
+    Throttled { retry_after: Duration },
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[cfg(feature = "chaos")]
+    #[error("The request to the email provider failed on purpose")]
+    Injected,
+}
+
+/// Providers keep transactional and bulk traffic apart to protect the
+/// deliverability of the former.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageStream {
+    /// Emails triggered by an action of the recipient, e.g. a confirmation.
+    Transactional,
+    /// Newsletter issues.
+    Broadcast,
+}
+
+/// The identifiers a provider uses for each message stream.
+#[derive(Clone, Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct MessageStreams {
+    pub transactional: String,
+    pub broadcast: String,
+}
+
+impl MessageStreams {
+    fn name(&self, stream: MessageStream) -> &str {
+        match stream {
+            MessageStream::Transactional => &self.transactional,
+            MessageStream::Broadcast => &self.broadcast,
+        }
+    }
+}
+
+impl Default for MessageStreams {
+    fn default() -> Self {
+        Self {
+            transactional: "transactional".into(),
+            broadcast: "broadcast".into(),
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -17,6 +95,7 @@ struct Message {
     subject: String,
     text_part: String,
     html_part: String,
+    message_stream: String,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -34,6 +113,13 @@ struct Recipient {
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct Messages {
     messages: Vec<Message>,
+    /// The provider validates the request without delivering anything.
+    #[serde(
+        rename = "SandboxMode",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    sandbox_mode: bool,
 }
 
 impl EmailClient {
@@ -43,27 +129,243 @@ impl EmailClient {
         api_public_key: Secret<String>,
         api_private_key: Secret<String>,
         timeout: std::time::Duration,
+    ) -> Result<Self, StartupError> {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| StartupError::HttpClient("email client", e))?;
+        Ok(Self {
+            primary: EmailProvider {
+                name: "primary",
+                http_client,
+                base_url,
+                sender,
+                api_public_key,
+                api_private_key,
+                message_streams: MessageStreams::default(),
+                cost_per_email: 0.0,
+            },
+            failover: None,
+        })
+    }
+
+    /// Use the provider's own identifiers for the message streams.
+    pub fn with_message_streams(mut self, message_streams: MessageStreams) -> Self {
+        self.primary.message_streams = message_streams;
+        self
+    }
+
+    /// What the provider charges per email, for cost tracking.
+    pub fn with_cost_per_email(mut self, cost_per_email: f64) -> Self {
+        self.primary.cost_per_email = cost_per_email;
+        self
+    }
+
+    /// Fail over to `secondary` once the primary provider fails
+    /// `failure_threshold` times in a row. The primary is probed again after
+    /// `probe_interval`.
+    pub fn with_failover(
+        mut self,
+        secondary: EmailClient,
+        failure_threshold: u32,
+        probe_interval: Duration,
     ) -> Self {
-        let http_client = Client::builder().timeout(timeout).build().unwrap();
-        Self {
-            http_client,
-            base_url,
-            sender,
-            api_public_key,
-            api_private_key,
-        }
+        let mut secondary = secondary.primary;
+        secondary.name = "secondary";
+        self.failover = Some(Failover {
+            secondary,
+            breaker: CircuitBreaker::new(failure_threshold, probe_interval),
+        });
+        self
     }
 
+    /// The span carries the provider that got the last request and what it
+    /// answered.
+    #[tracing::instrument(
+        name = "Send an email",
+        skip_all,
+        fields(
+            email.provider = Empty,
+            email.retries = 0,
+            email.status_code = Empty,
+            email.duration_ms = Empty,
+            email.request_bytes = Empty,
+        )
+    )]
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/email", self.base_url);
+        stream: MessageStream,
+    ) -> Result<SentEmail, SendEmailError> {
+        let Some(failover) = &self.failover else {
+            return self
+                .primary
+                .send_email(recipient, subject, html_content, text_content, stream)
+                .await;
+        };
+        if failover.breaker.allow_request() {
+            match self
+                .primary
+                .send_email(recipient, subject, html_content, text_content, stream)
+                .await
+            {
+                Ok(sent) => {
+                    failover.breaker.record_success();
+                    return Ok(sent);
+                }
+                Err(e) if !is_provider_failure(&e) => return Err(e),
+                Err(e) if !was_not_sent(&e) => {
+                    failover.breaker.record_failure();
+                    return Err(e);
+                }
+                Err(e) => {
+                    failover.breaker.record_failure();
+                    EMAIL_SEND_RETRIES
+                        .with_label_values(&[self.primary.name])
+                        .inc();
+                    tracing::Span::current().record("email.retries", 1);
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        "The primary email provider failed, falling back to the secondary one"
+                    );
+                }
+            }
+        }
+        failover
+            .secondary
+            .send_email(recipient, subject, html_content, text_content, stream)
+            .await
+    }
+
+    /// The body of a send request to the primary provider, as it goes on the
+    /// wire.
+    #[cfg(feature = "bench-helpers")]
+    pub fn request_body(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+    ) -> Vec<u8> {
+        let request_body = Messages {
+            messages: vec![self.primary.message(
+                recipient,
+                subject,
+                html_content,
+                text_content,
+                stream,
+            )],
+            sandbox_mode: false,
+        };
+        serde_json::to_vec(&request_body).expect("Serialising a message does not fail")
+    }
+
+    /// Ask every configured provider to validate our credentials, in sandbox
+    /// mode so that nothing is delivered.
+    pub async fn check_credentials(&self) -> Result<(), SendEmailError> {
+        self.primary.check_credentials().await?;
+        if let Some(failover) = &self.failover {
+            failover.secondary.check_credentials().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Server errors, timeouts and connection issues mean the provider is
+/// unhealthy. Client errors are our fault and would fail elsewhere too, and
+/// a throttled provider is healthy but wants us to slow down.
+fn is_provider_failure(e: &SendEmailError) -> bool {
+    match e {
+        SendEmailError::Throttled { .. } => false,
+        SendEmailError::Request(e) => e.status().is_none_or(|status| status.is_server_error()),
+        #[cfg(feature = "chaos")]
+        SendEmailError::Injected => true,
+    }
+}
+
+/// Whether the primary provider certainly did not send the email, hence the
+/// secondary one can without sending it twice. A request that timed out may
+/// still have gone through: it is left to the caller to retry.
+fn was_not_sent(e: &SendEmailError) -> bool {
+    match e {
+        SendEmailError::Throttled { .. } => true,
+        SendEmailError::Request(e) => {
+            e.is_connect() || e.status().is_some_and(|status| status.is_server_error())
+        }
+        #[cfg(feature = "chaos")]
+        SendEmailError::Injected => true,
+    }
+}
+
+/// `Retry-After` holds either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl EmailProvider {
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+    ) -> Result<SentEmail, SendEmailError> {
+        let request_body = Messages {
+            messages: vec![self.message(recipient, subject, html_content, text_content, stream)],
+            sandbox_mode: false,
+        };
+        let outcome = self.post(&request_body).await;
+        let label = match &outcome {
+            Ok(()) => "ok",
+            Err(SendEmailError::Throttled { .. }) => "throttled",
+            Err(SendEmailError::Request(_)) => "error",
+            #[cfg(feature = "chaos")]
+            Err(SendEmailError::Injected) => "error",
+        };
+        EMAIL_SENDS.with_label_values(&[self.name, label]).inc();
+        outcome?;
+        Ok(SentEmail {
+            provider: self.name,
+            estimated_cost: self.cost_per_email,
+        })
+    }
 
-        let message = Message {
+    async fn check_credentials(&self) -> Result<(), SendEmailError> {
+        let request_body = Messages {
+            messages: vec![self.message(
+                &self.sender,
+                "Credentials check",
+                "<p>Credentials check</p>",
+                "Credentials check",
+                MessageStream::Transactional,
+            )],
+            sandbox_mode: true,
+        };
+        self.post(&request_body).await
+    }
+
+    fn message(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        stream: MessageStream,
+    ) -> Message {
+        Message {
             from: Sender {
                 email: self.sender.as_ref().to_owned(),
                 name: "sender".to_string(),
@@ -75,22 +377,60 @@ impl EmailClient {
             subject: subject.to_owned(),
             text_part: text_content.to_owned(),
             html_part: html_content.to_owned(),
-        };
-
-        let request_body = Messages {
-            messages: vec![message],
-        };
+            message_stream: self.message_streams.name(stream).to_owned(),
+        }
+    }
 
-        self.http_client
+    async fn post(&self, request_body: &Messages) -> Result<(), SendEmailError> {
+        #[cfg(feature = "chaos")]
+        if crate::chaos::fail_email() {
+            return Err(SendEmailError::Injected);
+        }
+        let span = tracing::Span::current();
+        span.record("email.provider", self.name);
+        let url = format!("{}/email", self.base_url);
+        let body = serde_json::to_vec(request_body).expect("Serialising a message does not fail");
+        EMAIL_REQUEST_SIZE
+            .with_label_values(&[self.name])
+            .observe(body.len() as f64);
+        span.record("email.request_bytes", body.len());
+        let start = Instant::now();
+        let response = self
+            .http_client
             .post(url.as_str())
             .basic_auth(
                 self.api_public_key.expose_secret(),
                 Some(self.api_private_key.expose_secret()),
             )
-            .json(&request_body)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await;
+        let elapsed = start.elapsed();
+        EMAIL_PROVIDER_REQUEST_DURATION
+            .with_label_values(&[self.name])
+            .observe(elapsed.as_secs_f64());
+        span.record("email.duration_ms", elapsed.as_millis() as u64);
+        let status = response.as_ref().map_or_else(
+            |_| "none".to_owned(),
+            |response| response.status().as_u16().to_string(),
+        );
+        EMAIL_PROVIDER_RESPONSES
+            .with_label_values(&[self.name, &status])
+            .inc();
+        let response = response?;
+        span.record("email.status_code", response.status().as_u16());
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            if let Some(retry_after) = retry_after {
+                return Err(SendEmailError::Throttled { retry_after });
+            }
+        }
+        response.error_for_status()?;
         Ok(())
     }
 }
@@ -98,14 +438,18 @@ impl EmailClient {
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{
+        parse_retry_after, EmailClient, MessageStream, MessageStreams, SendEmailError,
+    };
+    use crate::metrics::{EMAIL_PROVIDER_RESPONSES, EMAIL_REQUEST_SIZE, EMAIL_SEND_RETRIES};
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::{ExposeSecret, Secret};
     use serde_json::Value;
-    use wiremock::matchers::{any, basic_auth, header, method, path};
+    use std::time::Duration;
+    use wiremock::matchers::{any, basic_auth, body_partial_json, header, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     #[tokio::test]
@@ -130,10 +474,70 @@ mod tests {
 
         // Act
         let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
             .await;
     }
 
+    #[tokio::test]
+    async fn send_email_tags_the_message_with_the_provider_stream() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let (email_client, _, _) = create_test_email_client(&mock_server);
+        let email_client = email_client.with_message_streams(MessageStreams {
+            transactional: "outbound".into(),
+            broadcast: "newsletters".into(),
+        });
+
+        Mock::given(body_partial_json(serde_json::json!({
+            "messages": [{ "MessageStream": "outbound" }]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Transactional,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn check_credentials_does_not_deliver_anything() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let (email_client, _, _) = create_test_email_client(&mock_server);
+
+        Mock::given(body_partial_json(
+            serde_json::json!({ "SandboxMode": true }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Act
+        let outcome = email_client.check_credentials().await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         // Arrange
@@ -148,7 +552,13 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
             .await;
 
         // Assert
@@ -169,7 +579,13 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
             .await;
 
         // Assert
@@ -192,13 +608,326 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
             .await;
 
         // Assert
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_fails_over_to_the_secondary_provider() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let secondary_server = MockServer::start().await;
+        let email_client = create_test_email_client_with_failover(
+            &primary_server,
+            &secondary_server,
+            Duration::from_secs(60),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&secondary_server)
+            .await;
+
+        // Act
+        let first = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+        let second = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(first);
+        assert_ok!(second);
+    }
+
+    #[tokio::test]
+    async fn timeouts_do_not_trigger_a_failover() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let secondary_server = MockServer::start().await;
+        let email_client = create_test_email_client_with_failover(
+            &primary_server,
+            &secondary_server,
+            Duration::from_secs(60),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&secondary_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn provider_responses_and_retries_are_measured() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let secondary_server = MockServer::start().await;
+        let email_client = create_test_email_client_with_failover(
+            &primary_server,
+            &secondary_server,
+            Duration::from_secs(60),
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&secondary_server)
+            .await;
+        // Other tests send concurrently: the metrics only ever grow.
+        let failures = EMAIL_PROVIDER_RESPONSES.with_label_values(&["primary", "503"]);
+        let retries = EMAIL_SEND_RETRIES.with_label_values(&["primary"]);
+        let sizes = EMAIL_REQUEST_SIZE.with_label_values(&["secondary"]);
+        let (failures_before, retries_before, sizes_before) =
+            (failures.get(), retries.get(), sizes.get_sample_count());
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        assert!(failures.get() > failures_before);
+        assert!(retries.get() > retries_before);
+        assert!(sizes.get_sample_count() > sizes_before);
+    }
+
+    #[tokio::test]
+    async fn client_errors_do_not_trigger_a_failover() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let secondary_server = MockServer::start().await;
+        let email_client = create_test_email_client_with_failover(
+            &primary_server,
+            &secondary_server,
+            Duration::from_secs(60),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&secondary_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_429_with_retry_after_is_reported_as_throttling() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let (email_client, _, _) = create_test_email_client(&mock_server);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::Throttled { retry_after }) if retry_after == Duration::from_secs(120)
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_429_without_retry_after_is_a_plain_error() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let (email_client, _, _) = create_test_email_client(&mock_server);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(outcome, Err(SendEmailError::Request(_))));
+    }
+
+    #[test]
+    fn retry_after_can_be_an_http_date() {
+        let in_a_minute = (chrono::Utc::now() + chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let retry_after = parse_retry_after(&in_a_minute).unwrap();
+        assert!(retry_after > Duration::from_secs(50) && retry_after <= Duration::from_secs(60));
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_back_once_a_probe_succeeds() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let secondary_server = MockServer::start().await;
+        let email_client = create_test_email_client_with_failover(
+            &primary_server,
+            &secondary_server,
+            Duration::ZERO,
+        );
+
+        let outage = Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount_as_scoped(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&secondary_server)
+            .await;
+        let _ = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+        drop(outage);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&primary_server)
+            .await;
+
+        // Act
+        let probe = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+        let next = email_client
+            .send_email(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                MessageStream::Broadcast,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(probe);
+        assert_ok!(next);
+    }
+
+    fn create_test_email_client_with_failover(
+        primary_server: &MockServer,
+        secondary_server: &MockServer,
+        probe_interval: Duration,
+    ) -> EmailClient {
+        let (primary, _, _) = create_test_email_client(primary_server);
+        let (secondary, _, _) = create_test_email_client(secondary_server);
+        primary.with_failover(secondary, 1, probe_interval)
+    }
+
     fn create_test_email_client(
         mock_server: &MockServer,
     ) -> (EmailClient, Secret<String>, Secret<String>) {
@@ -211,7 +940,8 @@ mod tests {
             api_public_key_fake.clone(),
             api_private_key_fake.clone(),
             std::time::Duration::from_millis(200),
-        );
+        )
+        .unwrap();
         (email_client, api_public_key_fake, api_private_key_fake)
     }
 
@@ -232,11 +962,11 @@ mod tests {
     impl wiremock::Match for SendEmailBodyMatcher {
         fn matches(&self, request: &Request) -> bool {
             let result: Result<Value, _> = serde_json::from_slice(&request.body);
-            return if let Ok(body) = result {
+            if let Ok(body) = result {
                 self.validate_body(&body)
             } else {
                 false
-            };
+            }
         }
     }
 
@@ -256,33 +986,34 @@ mod tests {
                     && message.get("Subject").is_some()
                     && message.get("HtmlPart").is_some()
                     && message.get("TextPart").is_some()
+                    && message.get("MessageStream").is_some()
             } else {
                 false
             }
         }
 
         fn validate_from(&self, message: &Value) -> bool {
-            return if let Some(from) = message.get("From") {
+            if let Some(from) = message.get("From") {
                 from.get("email").is_some() && from.get("name").is_some()
             } else {
                 false
-            };
+            }
         }
 
         fn validate_to(&self, message: &Value) -> bool {
-            return if let Some(to) = message.get("To") {
+            if let Some(to) = message.get("To") {
                 to.is_array() && validate_single_to(to.get(0))
             } else {
                 false
-            };
+            }
         }
     }
 
     fn validate_single_to(to: Option<&Value>) -> bool {
-        return if let Some(to) = to {
+        if let Some(to) = to {
             to.get("email").is_some() && to.get("name").is_some()
         } else {
             false
-        };
+        }
     }
 }
@@ -0,0 +1,192 @@
+//! Tell whether an address is worth subscribing, before anybody sends it a
+//! confirmation email.
+use crate::configuration::EmailVerificationSettings;
+use crate::database::ObserveQuery;
+use crate::deliverability::system_resolver;
+use crate::domain::SubscriberEmail;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// From the best to the worst.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Valid,
+    /// A check could not be carried out, e.g. a DNS lookup timed out.
+    Unknown,
+    Invalid,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    InvalidSyntax,
+    DisposableDomain,
+    Suppressed,
+    /// The domain has neither MX nor address records, or a null MX.
+    NoMailServer,
+    DnsLookupFailed,
+}
+
+impl Reason {
+    fn verdict(&self) -> Verdict {
+        match self {
+            Reason::DnsLookupFailed => Verdict::Unknown,
+            _ => Verdict::Invalid,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Verification {
+    pub email: String,
+    pub verdict: Verdict,
+    pub reasons: Vec<Reason>,
+}
+
+impl Verification {
+    fn new(email: String, reasons: Vec<Reason>) -> Self {
+        let verdict = reasons
+            .iter()
+            .map(Reason::verdict)
+            .max()
+            .unwrap_or(Verdict::Valid);
+        Self {
+            email,
+            verdict,
+            reasons,
+        }
+    }
+}
+
+pub struct EmailVerifier {
+    resolver: TokioAsyncResolver,
+    disposable_domains: Vec<String>,
+    timeout: Duration,
+}
+
+impl EmailVerifier {
+    pub fn new(settings: &EmailVerificationSettings) -> Self {
+        let timeout = Duration::from_millis(settings.dns_timeout_milliseconds);
+        Self {
+            resolver: system_resolver(timeout),
+            disposable_domains: settings
+                .disposable_domains
+                .iter()
+                .map(|domain| domain.to_lowercase())
+                .collect(),
+            timeout,
+        }
+    }
+
+    /// Run the checks from the cheapest to the most expensive: the DNS is only
+    /// asked about addresses that passed every other check.
+    #[tracing::instrument(name = "Verify an email address", skip(self, pool))]
+    pub async fn verify(&self, pool: &PgPool, email: String) -> Result<Verification, sqlx::Error> {
+        let Ok(email) = SubscriberEmail::parse(email.clone()) else {
+            return Ok(Verification::new(email, vec![Reason::InvalidSyntax]));
+        };
+        let email = email.as_ref().to_owned();
+        let domain = email
+            .rsplit_once('@')
+            .map_or("", |(_, domain)| domain)
+            .to_lowercase();
+        let mut reasons = Vec::new();
+        if self.is_disposable(&domain) {
+            reasons.push(Reason::DisposableDomain);
+        }
+        if is_suppressed(pool, &email).await? {
+            reasons.push(Reason::Suppressed);
+        }
+        if reasons.is_empty() {
+            reasons.extend(self.check_mail_server(&domain).await);
+        }
+        Ok(Verification::new(email, reasons))
+    }
+
+    /// Subdomains of a disposable domain are disposable too.
+    fn is_disposable(&self, domain: &str) -> bool {
+        self.disposable_domains.iter().any(|disposable| {
+            domain == disposable
+                || domain
+                    .strip_suffix(disposable.as_str())
+                    .is_some_and(|rest| rest.ends_with('.'))
+        })
+    }
+
+    /// Mail goes to the MX hosts of the domain or, without any, to the domain
+    /// itself.
+    async fn check_mail_server(&self, domain: &str) -> Option<Reason> {
+        match tokio::time::timeout(self.timeout, self.resolver.mx_lookup(domain)).await {
+            Ok(Ok(lookup)) => {
+                let exchanges: Vec<String> =
+                    lookup.iter().map(|mx| mx.exchange().to_utf8()).collect();
+                is_null_mx(&exchanges).then_some(Reason::NoMailServer)
+            }
+            Ok(Err(e)) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                match tokio::time::timeout(self.timeout, self.resolver.lookup_ip(domain)).await {
+                    Ok(Ok(_)) => None,
+                    Ok(Err(e)) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                        Some(Reason::NoMailServer)
+                    }
+                    _ => Some(Reason::DnsLookupFailed),
+                }
+            }
+            _ => Some(Reason::DnsLookupFailed),
+        }
+    }
+}
+
+/// A domain that accepts no mail publishes a single MX record pointing at
+/// `.` (RFC 7505).
+fn is_null_mx(exchanges: &[String]) -> bool {
+    matches!(exchanges, [exchange] if exchange == "." || exchange.is_empty())
+}
+
+async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT email FROM suppressed_emails WHERE lower(email) = lower($1)"#,
+        email
+    )
+    .fetch_optional(pool)
+    .observe("is_email_suppressed")
+    .await?;
+    Ok(row.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_null_mx, EmailVerifier, Reason, Verdict, Verification};
+    use crate::configuration::EmailVerificationSettings;
+
+    #[tokio::test]
+    async fn subdomains_of_disposable_domains_are_disposable() {
+        let verifier = EmailVerifier::new(&EmailVerificationSettings {
+            disposable_domains: vec!["Mailinator.com".into()],
+            dns_timeout_milliseconds: 100,
+        });
+        assert!(verifier.is_disposable("mailinator.com"));
+        assert!(verifier.is_disposable("eu.mailinator.com"));
+        assert!(!verifier.is_disposable("notmailinator.com"));
+    }
+
+    #[test]
+    fn a_single_dot_exchange_is_a_null_mx() {
+        assert!(is_null_mx(&[".".into()]));
+        assert!(!is_null_mx(&["mx.example.com.".into()]));
+        assert!(!is_null_mx(&[".".into(), "mx.example.com.".into()]));
+    }
+
+    #[test]
+    fn a_failed_check_outweighs_one_that_could_not_run() {
+        let verification = Verification::new(
+            "a@example.com".into(),
+            vec![Reason::DnsLookupFailed, Reason::Suppressed],
+        );
+        assert_eq!(verification.verdict, Verdict::Invalid);
+        let verification = Verification::new("a@example.com".into(), vec![]);
+        assert_eq!(verification.verdict, Verdict::Valid);
+    }
+}
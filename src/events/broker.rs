@@ -0,0 +1,322 @@
+use crate::configuration::BrokerSettings;
+use anyhow::Context;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+/// An external message broker that outbox events can be relayed to.
+pub enum Broker {
+    Nats(NatsBroker),
+}
+
+impl Broker {
+    pub fn from_settings(settings: &BrokerSettings) -> Self {
+        match settings {
+            BrokerSettings::Nats { url } => Broker::Nats(NatsBroker::new(url)),
+        }
+    }
+
+    pub async fn connect(&self) -> Result<BrokerConnection, anyhow::Error> {
+        match self {
+            Broker::Nats(broker) => Ok(BrokerConnection::Nats(broker.connect().await?)),
+        }
+    }
+}
+
+pub enum BrokerConnection {
+    Nats(NatsConnection),
+}
+
+impl BrokerConnection {
+    /// Publish a message and wait for the broker to acknowledge it.
+    ///
+    /// `message_id` is forwarded to the broker so that redeliveries of the
+    /// same outbox row can be de-duplicated downstream.
+    pub async fn publish(
+        &mut self,
+        subject: &str,
+        message_id: &str,
+        ordering_key: &str,
+        payload: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            BrokerConnection::Nats(connection) => {
+                connection
+                    .publish(subject, message_id, ordering_key, payload)
+                    .await
+            }
+        }
+    }
+}
+
+/// A minimal client for the NATS text protocol, publishing to JetStream.
+///
+/// Plain NATS publishes are fire-and-forget: they are dropped when nobody is
+/// subscribed. Publishing with a reply subject lets the JetStream stream that
+/// stores the subject acknowledge each message once it has been persisted.
+pub struct NatsBroker {
+    address: String,
+}
+
+/// How long to wait for JetStream to acknowledge a message.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl NatsBroker {
+    pub fn new(url: &str) -> Self {
+        let address = url.strip_prefix("nats://").unwrap_or(url).to_owned();
+        Self { address }
+    }
+
+    async fn connect(&self) -> Result<NatsConnection, anyhow::Error> {
+        let stream = TcpStream::connect(&self.address)
+            .await
+            .with_context(|| format!("Failed to connect to NATS at {}", self.address))?;
+        let mut connection = NatsConnection {
+            stream: BufReader::new(stream),
+            inbox: format!("_INBOX.{}", Uuid::new_v4().simple()),
+            poisoned: false,
+        };
+        let info = connection.read_line().await?;
+        if !info.starts_with("INFO") {
+            anyhow::bail!("Unexpected NATS greeting: {}", info);
+        }
+        connection
+            .stream
+            .write_all(
+                b"CONNECT {\"verbose\":false,\"pedantic\":false,\"headers\":true,\"no_responders\":true}\r\n",
+            )
+            .await?;
+        let subscribe = format!("SUB {}.* 1\r\n", connection.inbox);
+        connection.stream.write_all(subscribe.as_bytes()).await?;
+        connection.flush().await?;
+        Ok(connection)
+    }
+}
+
+pub struct NatsConnection {
+    stream: BufReader<TcpStream>,
+    /// The subject prefix JetStream acknowledgements are sent to.
+    inbox: String,
+    /// Set when we stopped reading halfway through a reply: the stream can no
+    /// longer be parsed and the connection has to be re-established.
+    poisoned: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct PubAck {
+    error: Option<PubAckError>,
+}
+
+#[derive(serde::Deserialize)]
+struct PubAckError {
+    description: String,
+}
+
+impl NatsConnection {
+    async fn publish(
+        &mut self,
+        subject: &str,
+        message_id: &str,
+        ordering_key: &str,
+        payload: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        if self.poisoned {
+            anyhow::bail!("The NATS connection must be re-established.");
+        }
+        let reply_to = format!("{}.{}", self.inbox, message_id);
+        let headers = format!(
+            "NATS/1.0\r\nNats-Msg-Id: {}\r\nOrdering-Key: {}\r\n\r\n",
+            message_id, ordering_key
+        );
+        let command = format!(
+            "HPUB {} {} {} {}\r\n",
+            subject,
+            reply_to,
+            headers.len(),
+            headers.len() + payload.len()
+        );
+        self.stream.write_all(command.as_bytes()).await?;
+        self.stream.write_all(headers.as_bytes()).await?;
+        self.stream.write_all(payload).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+        match tokio::time::timeout(ACK_TIMEOUT, self.wait_for_ack(&reply_to)).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                self.poisoned = true;
+                anyhow::bail!("JetStream did not acknowledge the message in time.")
+            }
+        }
+    }
+
+    /// Read messages until the acknowledgement sent to `reply_to` arrives.
+    /// Late acknowledgements of earlier messages are skipped.
+    async fn wait_for_ack(&mut self, reply_to: &str) -> Result<(), anyhow::Error> {
+        loop {
+            let line = self.read_line().await?;
+            let parts: Vec<_> = line.split_whitespace().collect();
+            match parts.first().copied() {
+                Some("PING") => self.stream.write_all(b"PONG\r\n").await?,
+                Some("-ERR") => anyhow::bail!("NATS rejected the message: {}", line),
+                // MSG <subject> <sid> [reply-to] <#bytes>
+                Some("MSG") => {
+                    let length = message_length(&parts)?;
+                    let body = self.read_payload(length).await?;
+                    if parts[1] == reply_to {
+                        return check_ack(&body);
+                    }
+                }
+                // HMSG <subject> <sid> [reply-to] <#header bytes> <#total bytes>
+                Some("HMSG") => {
+                    let length = message_length(&parts)?;
+                    let header_length: usize = parts[parts.len() - 2].parse()?;
+                    let body = self.read_payload(length).await?;
+                    if parts[1] == reply_to {
+                        // Only status messages carry headers on the inbox, e.g.
+                        // `NATS/1.0 503` when no stream stores the subject.
+                        let headers = String::from_utf8_lossy(&body[..header_length.min(length)]);
+                        let status = headers.lines().next().unwrap_or_default();
+                        if status.trim() != "NATS/1.0" {
+                            anyhow::bail!("JetStream did not store the message: {}", status);
+                        }
+                        return check_ack(&body[header_length.min(length)..]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn read_payload(&mut self, length: usize) -> Result<Vec<u8>, anyhow::Error> {
+        let mut payload = vec![0; length + 2];
+        self.poisoned = true;
+        self.stream.read_exact(&mut payload).await?;
+        self.poisoned = false;
+        payload.truncate(length);
+        Ok(payload)
+    }
+
+    /// Round-trip a PING so that we know the server has processed everything
+    /// we sent before it.
+    async fn flush(&mut self) -> Result<(), anyhow::Error> {
+        self.stream.write_all(b"PING\r\n").await?;
+        loop {
+            let line = self.read_line().await?;
+            if line == "PONG" {
+                return Ok(());
+            } else if line.starts_with("-ERR") {
+                anyhow::bail!("NATS rejected the message: {}", line);
+            } else if line == "PING" {
+                self.stream.write_all(b"PONG\r\n").await?;
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, anyhow::Error> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("The NATS server closed the connection.");
+        }
+        Ok(line.trim_end().to_owned())
+    }
+}
+
+fn message_length(parts: &[&str]) -> Result<usize, anyhow::Error> {
+    parts
+        .last()
+        .context("Malformed NATS message")?
+        .parse()
+        .context("Malformed NATS message")
+}
+
+fn check_ack(body: &[u8]) -> Result<(), anyhow::Error> {
+    let ack: PubAck =
+        serde_json::from_slice(body).context("JetStream sent an unreadable acknowledgement")?;
+    match ack.error {
+        Some(error) => anyhow::bail!("JetStream rejected the message: {}", error.description),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NatsBroker;
+    use claims::{assert_err, assert_ok};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Accept a single client, answer every PING with `pong`, every publish
+    /// with the JetStream acknowledgement `ack` and hand back the published
+    /// payloads once the client disconnects.
+    async fn fake_nats_server(
+        pong: &'static str,
+        ack: &'static str,
+    ) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = BufReader::new(socket);
+            socket.write_all(b"INFO {}\r\n").await.unwrap();
+            let mut payloads = Vec::new();
+            loop {
+                let mut line = String::new();
+                if socket.read_line(&mut line).await.unwrap() == 0 {
+                    return payloads;
+                }
+                let parts: Vec<_> = line.split_whitespace().collect();
+                match parts.first().copied() {
+                    Some("PING") => socket.write_all(pong.as_bytes()).await.unwrap(),
+                    Some("HPUB") => {
+                        let header_len: usize = parts[3].parse().unwrap();
+                        let total_len: usize = parts[4].parse().unwrap();
+                        let mut message = vec![0; total_len + 2];
+                        socket.read_exact(&mut message).await.unwrap();
+                        payloads.push(
+                            String::from_utf8(message[header_len..total_len].to_vec()).unwrap(),
+                        );
+                        let reply = format!("MSG {} 1 {}\r\n{}\r\n", parts[2], ack.len(), ack);
+                        socket.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        });
+        (address, handle)
+    }
+
+    #[tokio::test]
+    async fn acknowledged_messages_reach_the_server() {
+        let (address, server) =
+            fake_nats_server("PONG\r\n", r#"{"stream":"EVENTS","seq":1}"#).await;
+        let broker = NatsBroker::new(&format!("nats://{}", address));
+
+        let mut connection = broker.connect().await.unwrap();
+        assert_ok!(connection.publish("events.test", "1", "key", b"{}").await);
+        drop(connection);
+
+        assert_eq!(server.await.unwrap(), vec!["{}".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn messages_jetstream_did_not_store_are_reported() {
+        let (address, _server) = fake_nats_server(
+            "PONG\r\n",
+            r#"{"error":{"code":503,"description":"storage failure"}}"#,
+        )
+        .await;
+        let broker = NatsBroker::new(&address);
+
+        let mut connection = broker.connect().await.unwrap();
+        assert_err!(connection.publish("events.test", "1", "key", b"{}").await);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_reported() {
+        let (address, _server) = fake_nats_server("-ERR 'Permissions Violation'\r\n", "").await;
+        let broker = NatsBroker::new(&address);
+
+        assert!(broker.connect().await.is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::database::ObserveQuery;
+use crate::events::{store_outbox_event, DomainEvent};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::future::Future;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// How many events a slow subscriber can fall behind before it starts
+/// missing some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// In-process publish/subscribe hub for [`DomainEvent`]s.
+///
+/// The durable variant also appends every event to the `domain_events` table
+/// before fanning it out, so that events survive a restart and can be
+/// replayed by consumers that were not running at the time.
+/// When event export is enabled, events are additionally written to the
+/// `events_outbox` table to be relayed to an external broker.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+    store: Option<PgPool>,
+    outbox: Option<PgPool>,
+}
+
+impl EventBus {
+    pub fn in_memory() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            sender,
+            store: None,
+            outbox: None,
+        }
+    }
+
+    pub fn durable(pool: PgPool) -> Self {
+        Self {
+            store: Some(pool),
+            ..Self::in_memory()
+        }
+    }
+
+    pub fn with_outbox(self, pool: PgPool) -> Self {
+        Self {
+            outbox: Some(pool),
+            ..self
+        }
+    }
+
+    /// For the events that do not follow from a change in the database: the
+    /// others are recorded with [`EventBus::record`].
+    #[tracing::instrument(name = "Publish a domain event", skip(self, event), fields(event_type = event.event_type()))]
+    pub async fn publish(&self, event: DomainEvent) -> Result<(), anyhow::Error> {
+        if let Some(pool) = &self.store {
+            store_event(pool, &event)
+                .await
+                .context("Failed to persist a domain event.")?;
+        }
+        if let Some(pool) = &self.outbox {
+            store_outbox_event(pool, &event)
+                .await
+                .context("Failed to store a domain event in the outbox.")?;
+        }
+        self.fan_out(event);
+        Ok(())
+    }
+
+    /// Write `event` to the event store and to the outbox as part of
+    /// `transaction`, so that it is kept if and only if the change that
+    /// caused it is committed. Hand it to [`EventBus::fan_out`] once it is.
+    pub async fn record(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        event: &DomainEvent,
+    ) -> Result<(), anyhow::Error> {
+        if self.store.is_some() {
+            store_event(&mut **transaction, event)
+                .await
+                .context("Failed to persist a domain event.")?;
+        }
+        if self.outbox.is_some() {
+            store_outbox_event(&mut **transaction, event)
+                .await
+                .context("Failed to store a domain event in the outbox.")?;
+        }
+        Ok(())
+    }
+
+    /// Send a committed event to the subscribers in this process.
+    pub fn fan_out(&self, event: DomainEvent) {
+        // Sending fails only when nobody is listening, which is fine.
+        let _ = self.sender.send(event);
+    }
+
+    /// Let the admins know that a run of the background job `job` failed.
+    /// Failing to is only logged: the job goes on retrying regardless.
+    pub async fn publish_job_failure(&self, job: &str, error: &anyhow::Error) {
+        let event = DomainEvent::JobFailed {
+            job: job.to_owned(),
+            error: error.to_string(),
+        };
+        if let Err(e) = self.publish(event).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                job,
+                "Failed to publish a job failure"
+            );
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Run `handler` on a background task for every event published from now on.
+    pub fn spawn_subscriber<F, Fut>(&self, name: &'static str, handler: F) -> JoinHandle<()>
+    where
+        F: Fn(DomainEvent) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let mut receiver = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => handler(event).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            subscriber = name,
+                            skipped,
+                            "Event subscriber is lagging behind"
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+#[tracing::instrument(name = "Store a domain event", skip(executor, event))]
+async fn store_event<'a, E>(executor: E, event: &DomainEvent) -> Result<(), anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let payload = serde_json::to_value(event)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO domain_events (event_type, payload, occurred_at)
+        VALUES ($1, $2, $3)
+        "#,
+        event.event_type(),
+        payload,
+        Utc::now()
+    )
+    .execute(executor)
+    .observe("store_event")
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{DomainEvent, EventBus};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn event() -> DomainEvent {
+        DomainEvent::SubscriberConfirmed {
+            subscriber_id: Uuid::new_v4(),
+        }
+    }
+
+    #[tokio::test]
+    async fn every_subscriber_receives_published_events() {
+        let bus = EventBus::in_memory();
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+        let event = event();
+
+        bus.publish(event.clone()).await.unwrap();
+
+        assert_eq!(first.recv().await.unwrap(), event);
+        assert_eq!(second.recv().await.unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn publishing_without_subscribers_succeeds() {
+        let bus = EventBus::in_memory();
+        claims::assert_ok!(bus.publish(event()).await);
+    }
+
+    #[tokio::test]
+    async fn spawned_subscribers_handle_events() {
+        let bus = EventBus::in_memory();
+        let handled = Arc::new(AtomicUsize::new(0));
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::channel(1);
+        {
+            let handled = handled.clone();
+            bus.spawn_subscriber("test", move |_| {
+                let handled = handled.clone();
+                let done_tx = done_tx.clone();
+                async move {
+                    handled.fetch_add(1, Ordering::SeqCst);
+                    let _ = done_tx.send(()).await;
+                }
+            });
+        }
+
+        bus.publish(event()).await.unwrap();
+        done_rx.recv().await.unwrap();
+
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,160 @@
+use uuid::Uuid;
+
+/// Something noteworthy that happened in the application.
+///
+/// Events are published on the [`EventBus`](super::EventBus) by the handlers
+/// and workers that cause them, and every interested component subscribes
+/// to the bus instead of being called directly.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    SubscriberConfirmed {
+        subscriber_id: Uuid,
+    },
+    IssueSent {
+        issue_id: Uuid,
+        title: String,
+        recipients: usize,
+    },
+    /// Events leave the database: they name subscribers by id, never by
+    /// address. `None` for an address no subscriber is stored with anymore.
+    DeliveryFailed {
+        subscriber_id: Option<Uuid>,
+        error: String,
+    },
+    FailureRateExceeded {
+        issue_id: Uuid,
+        failure_rate: f64,
+    },
+    SloBudgetBurning {
+        route: String,
+        burn_rate: f64,
+    },
+    /// An owner looked at the newsletter through the eyes of a subscriber.
+    SubscriberViewImpersonated {
+        user_id: Uuid,
+        subscriber_id: Uuid,
+    },
+    /// As many of the subscribers `subscriber_id` referred as one of the
+    /// reward milestones confirmed.
+    ReferralMilestoneReached {
+        subscriber_id: Uuid,
+        referrals: i64,
+    },
+    /// `source` had many more signups this hour than usual, `*` standing
+    /// for all sources together.
+    SignupSpikeDetected {
+        source: String,
+        signups: i64,
+        baseline: f64,
+    },
+    /// A run of the background job `job` failed. It is retried.
+    JobFailed {
+        job: String,
+        error: String,
+    },
+}
+
+impl DomainEvent {
+    /// Every value [`DomainEvent::event_type`] returns.
+    pub const EVENT_TYPES: [&'static str; 9] = [
+        "subscriber_confirmed",
+        "issue_sent",
+        "delivery_failed",
+        "failure_rate_exceeded",
+        "slo_budget_burning",
+        "subscriber_view_impersonated",
+        "referral_milestone_reached",
+        "signup_spike_detected",
+        "job_failed",
+    ];
+
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DomainEvent::SubscriberConfirmed { .. } => "subscriber_confirmed",
+            DomainEvent::IssueSent { .. } => "issue_sent",
+            DomainEvent::DeliveryFailed { .. } => "delivery_failed",
+            DomainEvent::FailureRateExceeded { .. } => "failure_rate_exceeded",
+            DomainEvent::SloBudgetBurning { .. } => "slo_budget_burning",
+            DomainEvent::SubscriberViewImpersonated { .. } => "subscriber_view_impersonated",
+            DomainEvent::ReferralMilestoneReached { .. } => "referral_milestone_reached",
+            DomainEvent::SignupSpikeDetected { .. } => "signup_spike_detected",
+            DomainEvent::JobFailed { .. } => "job_failed",
+        }
+    }
+
+    /// Events sharing an ordering key are relayed to brokers in the order
+    /// they were published.
+    pub fn ordering_key(&self) -> String {
+        match self {
+            DomainEvent::SubscriberConfirmed { subscriber_id } => subscriber_id.to_string(),
+            DomainEvent::IssueSent { issue_id, .. } => issue_id.to_string(),
+            DomainEvent::DeliveryFailed { subscriber_id, .. } => subscriber_id
+                .map(|subscriber_id| subscriber_id.to_string())
+                .unwrap_or_default(),
+            DomainEvent::FailureRateExceeded { issue_id, .. } => issue_id.to_string(),
+            DomainEvent::SloBudgetBurning { route, .. } => route.clone(),
+            DomainEvent::SignupSpikeDetected { source, .. } => source.clone(),
+            DomainEvent::JobFailed { job, .. } => job.clone(),
+            DomainEvent::SubscriberViewImpersonated { subscriber_id, .. }
+            | DomainEvent::ReferralMilestoneReached { subscriber_id, .. } => {
+                subscriber_id.to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainEvent;
+    use uuid::Uuid;
+
+    #[test]
+    fn the_serialized_type_tag_matches_the_event_type() {
+        let events = [
+            DomainEvent::SubscriberConfirmed {
+                subscriber_id: Uuid::new_v4(),
+            },
+            DomainEvent::IssueSent {
+                issue_id: Uuid::new_v4(),
+                title: "Issue #1".into(),
+                recipients: 3,
+            },
+            DomainEvent::DeliveryFailed {
+                subscriber_id: Some(Uuid::new_v4()),
+                error: "timeout".into(),
+            },
+            DomainEvent::FailureRateExceeded {
+                issue_id: Uuid::new_v4(),
+                failure_rate: 0.1,
+            },
+            DomainEvent::SloBudgetBurning {
+                route: "/subscriptions".into(),
+                burn_rate: 14.4,
+            },
+            DomainEvent::SubscriberViewImpersonated {
+                user_id: Uuid::new_v4(),
+                subscriber_id: Uuid::new_v4(),
+            },
+            DomainEvent::ReferralMilestoneReached {
+                subscriber_id: Uuid::new_v4(),
+                referrals: 3,
+            },
+            DomainEvent::SignupSpikeDetected {
+                source: "l/earthsea".into(),
+                signups: 120,
+                baseline: 2.5,
+            },
+            DomainEvent::JobFailed {
+                job: "issue_delivery".into(),
+                error: "connection refused".into(),
+            },
+        ];
+        assert_eq!(events.len(), DomainEvent::EVENT_TYPES.len());
+        for event in events {
+            let payload = serde_json::to_value(&event).unwrap();
+            assert_eq!(payload["type"], event.event_type());
+            assert!(DomainEvent::EVENT_TYPES.contains(&event.event_type()));
+        }
+    }
+}
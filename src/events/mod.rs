@@ -0,0 +1,9 @@
+mod broker;
+mod bus;
+mod domain_event;
+mod outbox;
+
+pub use broker::{Broker, BrokerConnection};
+pub use bus::EventBus;
+pub use domain_event::DomainEvent;
+pub use outbox::{run_relay_until_stopped, store_outbox_event, try_relay_batch, RelayOutcome};
@@ -0,0 +1,182 @@
+use crate::configuration::{EventExportSettings, Settings};
+use crate::database::ObserveQuery;
+use crate::events::broker::{Broker, BrokerConnection};
+use crate::events::DomainEvent;
+use crate::startup::get_connection_pool;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Append an event to the `events_outbox` table, ready to be relayed to the
+/// configured broker.
+#[tracing::instrument(name = "Store an event in the outbox", skip(executor, event))]
+pub async fn store_outbox_event<'a, E>(
+    executor: E,
+    event: &DomainEvent,
+) -> Result<(), anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let payload = serde_json::to_value(event)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO events_outbox (ordering_key, event_type, payload, created_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        event.ordering_key(),
+        event.event_type(),
+        payload,
+        Utc::now()
+    )
+    .execute(executor)
+    .observe("store_outbox_event")
+    .await?;
+    Ok(())
+}
+
+pub enum RelayOutcome {
+    Relayed(usize),
+    EmptyOutbox,
+    /// Another relay holds the outbox.
+    Busy,
+}
+
+/// Relay the oldest batch of unpublished outbox events to the broker.
+///
+/// A row is only marked as published once the broker has acknowledged it,
+/// hence delivery is at-least-once. Rows are relayed in insertion order and,
+/// as soon as one fails, every later row sharing its ordering key is held back
+/// until the next attempt, so that consumers never observe them out of order.
+///
+/// Only one relay works through the outbox at a time: two relays splitting a
+/// batch between them could publish events sharing an ordering key out of
+/// order.
+#[tracing::instrument(skip_all)]
+pub async fn try_relay_batch(
+    pool: &PgPool,
+    connection: &mut BrokerConnection,
+    settings: &EventExportSettings,
+) -> Result<RelayOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let locked = sqlx::query!(
+        r#"SELECT pg_try_advisory_xact_lock(hashtextextended('events_outbox', 0)) AS "locked!""#
+    )
+    .fetch_one(&mut *transaction)
+    .observe_one("lock_outbox")
+    .await?
+    .locked;
+    if !locked {
+        return Ok(RelayOutcome::Busy);
+    }
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, ordering_key, event_type, payload
+        FROM events_outbox
+        WHERE published_at IS NULL
+        ORDER BY id
+        LIMIT $1
+        "#,
+        settings.batch_size
+    )
+    .fetch_all(&mut *transaction)
+    .observe("fetch_outbox_batch")
+    .await?;
+    if rows.is_empty() {
+        return Ok(RelayOutcome::EmptyOutbox);
+    }
+
+    let mut blocked_keys = HashSet::new();
+    let mut relayed = 0;
+    for row in rows {
+        if blocked_keys.contains(&row.ordering_key) {
+            continue;
+        }
+        let subject = format!("{}.{}", settings.subject_prefix, row.event_type);
+        let payload = serde_json::to_vec(&row.payload)?;
+        let outcome = connection
+            .publish(&subject, &row.id.to_string(), &row.ordering_key, &payload)
+            .await;
+        match outcome {
+            Ok(()) => {
+                sqlx::query!(
+                    r#"UPDATE events_outbox SET published_at = now() WHERE id = $1"#,
+                    row.id
+                )
+                .execute(&mut *transaction)
+                .observe("mark_outbox_event_published")
+                .await?;
+                relayed += 1;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    outbox_id = row.id,
+                    "Failed to relay an outbox event"
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE events_outbox
+                    SET attempts = attempts + 1, last_error = $2
+                    WHERE id = $1
+                    "#,
+                    row.id,
+                    e.to_string()
+                )
+                .execute(&mut *transaction)
+                .observe("record_outbox_failure")
+                .await?;
+                blocked_keys.insert(row.ordering_key);
+            }
+        }
+    }
+    transaction.commit().await?;
+    Ok(RelayOutcome::Relayed(relayed))
+}
+
+async fn relay_loop(pool: PgPool, settings: EventExportSettings) -> Result<(), anyhow::Error> {
+    let broker = Broker::from_settings(&settings.broker);
+    let poll_interval = Duration::from_millis(settings.poll_interval_milliseconds);
+    let mut connection = None;
+    loop {
+        if connection.is_none() {
+            match broker.connect().await {
+                Ok(c) => connection = Some(c),
+                Err(e) => {
+                    tracing::error!(error.cause_chain = ?e, "Failed to connect to the broker");
+                    tokio::time::sleep(poll_interval).await;
+                    continue;
+                }
+            }
+        }
+        let c = connection.as_mut().unwrap();
+        match try_relay_batch(&pool, c, &settings).await {
+            Ok(RelayOutcome::EmptyOutbox | RelayOutcome::Busy) => {
+                tokio::time::sleep(poll_interval).await
+            }
+            Ok(RelayOutcome::Relayed(0)) => {
+                // Nothing went through: start from a fresh connection.
+                connection = None;
+                tokio::time::sleep(poll_interval).await;
+            }
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to relay outbox events");
+                connection = None;
+                tokio::time::sleep(poll_interval).await;
+            }
+            Ok(RelayOutcome::Relayed(_)) => {}
+        }
+    }
+}
+
+/// Relay outbox events to the broker until the process is stopped.
+///
+/// Never resolves if event export is not configured.
+pub async fn run_relay_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let Some(settings) = configuration.events.export else {
+        return std::future::pending().await;
+    };
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    relay_loop(connection_pool, settings).await
+}
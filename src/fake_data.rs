@@ -0,0 +1,283 @@
+//! Fake subscribers and issues, for performance work and for developing the
+//! dashboards against a dataset of a known size:
+//! `zero2prod seed --subscribers 100000 --issues 20`.
+//!
+//! The rows go straight into the configured database, sealed like real ones
+//! when PII encryption is enabled.
+use crate::database::ObserveQuery;
+use crate::issue_delivery_worker::IssueStatus;
+use crate::pii::PiiCipher;
+use chrono::{DateTime, Duration, Utc};
+use fake::faker::internet::en::{FreeEmailProvider, Username};
+use fake::faker::lorem::en::{Paragraphs, Sentence};
+use fake::faker::name::en::Name;
+use fake::Fake;
+use rand::Rng;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Rows per `INSERT`.
+const BATCH_SIZE: usize = 1000;
+const LOCALES: [&str; 4] = ["en", "en-gb", "pt-br", "de"];
+
+/// How many rows `zero2prod seed` generates.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SeedOptions {
+    pub subscribers: usize,
+    pub issues: usize,
+}
+
+impl SeedOptions {
+    /// Parses `--subscribers <n>` and `--issues <n>`, both optional.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut options = Self {
+            subscribers: 100_000,
+            issues: 20,
+        };
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let target = match *flag {
+                "--subscribers" => &mut options.subscribers,
+                "--issues" => &mut options.issues,
+                _ => return Err(format!("unknown option {}", flag)),
+            };
+            *target = args
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| format!("{} expects a number", flag))?;
+        }
+        Ok(options)
+    }
+}
+
+struct FakeSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed: bool,
+    locale: Option<&'static str>,
+    paid: bool,
+}
+
+impl FakeSubscriber {
+    /// `n` keeps the emails of a run unique.
+    fn generate(n: usize, now: DateTime<Utc>) -> Self {
+        let mut rng = rand::thread_rng();
+        let username: String = Username().fake();
+        let provider: String = FreeEmailProvider().fake();
+        Self {
+            id: Uuid::new_v4(),
+            email: format!("{}.{}@{}", username.to_lowercase(), n, provider),
+            name: Name().fake(),
+            subscribed_at: now - Duration::minutes(rng.gen_range(0..365 * 24 * 60)),
+            confirmed: rng.gen_bool(0.9),
+            locale: rng
+                .gen_bool(0.3)
+                .then(|| LOCALES[rng.gen_range(0..LOCALES.len())]),
+            paid: rng.gen_bool(0.05),
+        }
+    }
+}
+
+struct FakeIssue {
+    id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    paid_only: bool,
+}
+
+impl FakeIssue {
+    /// Issue `n` of `count`, published weekly until `now`.
+    fn generate(n: usize, count: usize, now: DateTime<Utc>) -> Self {
+        let paragraphs: Vec<String> = Paragraphs(3..8).fake();
+        let title: String = Sentence(3..8).fake();
+        Self {
+            id: Uuid::new_v4(),
+            title: title.trim_end_matches('.').to_owned(),
+            text_content: paragraphs.join("\n\n"),
+            html_content: paragraphs
+                .iter()
+                .map(|paragraph| format!("<p>{}</p>", paragraph))
+                .collect(),
+            published_at: now - Duration::weeks((count - n) as i64),
+            paid_only: rand::thread_rng().gen_bool(0.2),
+        }
+    }
+}
+
+/// Returns how many subscribers and issues were inserted.
+#[tracing::instrument(skip(pool, pii))]
+pub async fn seed(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    options: &SeedOptions,
+) -> Result<(u64, u64), anyhow::Error> {
+    let now = Utc::now();
+    let mut subscribers = 0;
+    let mut confirmed = 0;
+    for start in (0..options.subscribers).step_by(BATCH_SIZE) {
+        let end = options.subscribers.min(start + BATCH_SIZE);
+        let batch: Vec<_> = (start..end)
+            .map(|n| FakeSubscriber::generate(n, now))
+            .collect();
+        confirmed += batch.iter().filter(|s| s.confirmed).count() as i32;
+        subscribers += insert_subscribers(pool, pii, &batch).await?;
+    }
+    let mut issues = 0;
+    for start in (0..options.issues).step_by(BATCH_SIZE) {
+        let end = options.issues.min(start + BATCH_SIZE);
+        let batch: Vec<_> = (start..end)
+            .map(|n| FakeIssue::generate(n, options.issues, now))
+            .collect();
+        issues += insert_issues(pool, &batch, confirmed).await?;
+    }
+    Ok((subscribers, issues))
+}
+
+async fn insert_subscribers(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    batch: &[FakeSubscriber],
+) -> Result<u64, sqlx::Error> {
+    let ids: Vec<_> = batch.iter().map(|s| s.id).collect();
+    let emails: Vec<_> = batch.iter().map(|s| pii.seal_email(&s.email)).collect();
+    let names: Vec<_> = batch.iter().map(|s| pii.seal(&s.name)).collect();
+    let subscribed_at: Vec<_> = batch.iter().map(|s| s.subscribed_at).collect();
+    let statuses: Vec<_> = batch
+        .iter()
+        .map(|s| {
+            if s.confirmed {
+                "confirmed".to_owned()
+            } else {
+                "pending_confirmation".to_owned()
+            }
+        })
+        .collect();
+    let email_indexes: Vec<_> = batch.iter().map(|s| pii.email_index(&s.email)).collect();
+    let locales: Vec<_> = batch.iter().map(|s| s.locale.map(String::from)).collect();
+    let paid: Vec<_> = batch.iter().map(|s| s.paid).collect();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, email_index, locale, paid)
+        SELECT * FROM UNNEST(
+            $1::uuid[], $2::text[], $3::text[], $4::timestamptz[],
+            $5::text[], $6::text[], $7::text[], $8::bool[]
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        &ids,
+        &emails,
+        &names,
+        &subscribed_at,
+        &statuses,
+        &email_indexes as &[Option<String>],
+        &locales as &[Option<String>],
+        &paid
+    )
+    .execute(pool)
+    .observe("seed_subscribers")
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// The issues are seeded as delivered to `recipients` subscribers.
+async fn insert_issues(
+    pool: &PgPool,
+    batch: &[FakeIssue],
+    recipients: i32,
+) -> Result<u64, sqlx::Error> {
+    let ids: Vec<_> = batch.iter().map(|i| i.id).collect();
+    let titles: Vec<_> = batch.iter().map(|i| i.title.clone()).collect();
+    let text_contents: Vec<_> = batch.iter().map(|i| i.text_content.clone()).collect();
+    let html_contents: Vec<_> = batch.iter().map(|i| i.html_content.clone()).collect();
+    let published_at: Vec<_> = batch.iter().map(|i| i.published_at).collect();
+    let paid_only: Vec<_> = batch.iter().map(|i| i.paid_only).collect();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            paid_only,
+            status,
+            sent_count
+        )
+        SELECT *, $7, $8 FROM UNNEST(
+            $1::uuid[], $2::text[], $3::text[], $4::text[], $5::timestamptz[], $6::bool[]
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        &ids,
+        &titles,
+        &text_contents,
+        &html_contents,
+        &published_at,
+        &paid_only,
+        IssueStatus::Completed.as_str(),
+        recipients
+    )
+    .execute(pool)
+    .observe("seed_issues")
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FakeIssue, FakeSubscriber, SeedOptions};
+    use chrono::Utc;
+    use std::collections::HashSet;
+
+    #[test]
+    fn options_default_to_a_large_dataset() {
+        assert_eq!(
+            SeedOptions::parse(&[]),
+            Ok(SeedOptions {
+                subscribers: 100_000,
+                issues: 20
+            })
+        );
+        assert_eq!(
+            SeedOptions::parse(&["--issues", "3", "--subscribers", "50"]),
+            Ok(SeedOptions {
+                subscribers: 50,
+                issues: 3
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        for args in [
+            &["--subscribers"][..],
+            &["--subscribers", "many"],
+            &["--lists", "3"],
+        ] {
+            assert!(SeedOptions::parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn fake_subscribers_have_unique_emails() {
+        let now = Utc::now();
+        let emails: HashSet<_> = (0..1000)
+            .map(|n| FakeSubscriber::generate(n, now).email)
+            .collect();
+        assert_eq!(emails.len(), 1000);
+    }
+
+    #[test]
+    fn fake_issues_are_published_in_order_until_now() {
+        let now = Utc::now();
+        let issues: Vec<_> = (0..5).map(|n| FakeIssue::generate(n, 5, now)).collect();
+        assert!(issues
+            .windows(2)
+            .all(|w| w[0].published_at < w[1].published_at));
+        assert!(issues.iter().all(|issue| issue.published_at < now));
+    }
+}
@@ -0,0 +1,107 @@
+//! Caching headers for the public pages a traffic spike would hit, e.g. a
+//! signup page linked from a popular blog post: browsers and CDNs reuse
+//! them, and revalidate them with a conditional request once they expire.
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, EntityTag, Header, IfNoneMatch, ETAG,
+};
+use actix_web::{HttpRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+
+/// A strong validator of `body`: it changes whenever the body does.
+fn entity_tag(body: &str) -> EntityTag {
+    EntityTag::new_strong(hex::encode(&Sha256::digest(body)[..16]))
+}
+
+/// `body` with an `ETag` and `Cache-Control: public, max-age`, or a bodiless
+/// `304 Not Modified` when `request` holds a copy that is still current.
+pub fn cached_response(
+    request: &HttpRequest,
+    content_type: ContentType,
+    body: String,
+    max_age_seconds: u32,
+) -> HttpResponse {
+    let etag = entity_tag(&body);
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(max_age_seconds),
+    ]);
+    // `If-None-Match` uses the weak comparison.
+    let not_modified = match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(cache_control)
+        .insert_header((ETAG, etag.to_string()));
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(content_type).body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cached_response;
+    use actix_web::http::header::{ContentType, CACHE_CONTROL, ETAG, IF_NONE_MATCH};
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    fn etag(response: &actix_web::HttpResponse) -> String {
+        response
+            .headers()
+            .get(ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    fn respond(if_none_match: Option<&str>, body: &str) -> actix_web::HttpResponse {
+        let mut request = TestRequest::default();
+        if let Some(if_none_match) = if_none_match {
+            request = request.insert_header((IF_NONE_MATCH, if_none_match));
+        }
+        cached_response(
+            &request.to_http_request(),
+            ContentType::html(),
+            body.into(),
+            300,
+        )
+    }
+
+    #[test]
+    fn responses_can_be_cached_and_carry_a_validator() {
+        let response = respond(None, "<p>Hello</p>");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=300"
+        );
+        assert!(response.headers().contains_key(ETAG));
+    }
+
+    #[test]
+    fn a_current_copy_is_not_sent_again() {
+        let current = etag(&respond(None, "<p>Hello</p>"));
+        for if_none_match in [current.clone(), format!("W/{}", current), "*".into()] {
+            let response = respond(Some(&if_none_match), "<p>Hello</p>");
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(etag(&response), current);
+        }
+    }
+
+    #[test]
+    fn a_stale_copy_is_replaced() {
+        let stale = etag(&respond(None, "<p>Hello</p>"));
+        let response = respond(Some(&stale), "<p>Goodbye</p>");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(etag(&response), stale);
+    }
+}
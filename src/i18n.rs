@@ -0,0 +1,213 @@
+//! The public pages and the validation errors in the visitor's language,
+//! negotiated from their `Accept-Language` header.
+use crate::configuration::I18nSettings;
+use crate::domain::DomainValidationError;
+use actix_web::dev::Payload;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{web, FromRequest, HttpRequest};
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+
+/// The languages the messages below are translated to.
+#[derive(serde::Deserialize, Copy, Clone, Debug, Default, PartialEq, Eq, schemars::JsonSchema)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "pt")]
+    Portuguese,
+    #[serde(rename = "de")]
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::English, Language::Portuguese, Language::German];
+
+    /// The ISO 639-1 code, used as the `lang` of the pages.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Portuguese => "pt",
+            Language::German => "de",
+        }
+    }
+
+    /// Regional variants share a language: `pt-BR` is Portuguese.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim();
+        Self::ALL
+            .into_iter()
+            .find(|language| language.as_str().eq_ignore_ascii_case(primary))
+    }
+
+    /// The supported language the visitor prefers, by quality and then by
+    /// order, `default` when none of theirs is supported.
+    pub fn negotiate(accept_language: Option<&str>, default: Language) -> Language {
+        let mut best: Option<(f32, Language)> = None;
+        for range in accept_language.unwrap_or_default().split(',') {
+            let mut parts = range.split(';');
+            let Some(language) = parts.next().and_then(Self::from_tag) else {
+                continue;
+            };
+            let quality = parts
+                .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+                best = Some((quality, language));
+            }
+        }
+        best.map_or(default, |(_, language)| language)
+    }
+}
+
+/// The language of the visitor, for the handlers rendering public pages.
+pub struct PreferredLanguage(pub Language);
+
+impl FromRequest for PreferredLanguage {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let default = req
+            .app_data::<web::Data<I18nSettings>>()
+            .map(|settings| settings.default_language)
+            .unwrap_or_default();
+        let accept_language = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok());
+        ready(Ok(Self(Language::negotiate(accept_language, default))))
+    }
+}
+
+/// The strings of the public pages. `{email}` is replaced by the caller.
+#[derive(Copy, Clone, Debug)]
+pub enum Message {
+    ConfirmTitle,
+    ConfirmPrompt,
+    ConfirmButton,
+    ConfirmedTitle,
+    ConfirmedHeading,
+    ConfirmedBody,
+}
+
+impl Message {
+    pub fn translate(&self, language: Language) -> &'static str {
+        use Language::*;
+        match (self, language) {
+            (Message::ConfirmTitle, English) => "Confirm your subscription",
+            (Message::ConfirmTitle, Portuguese) => "Confirme a sua inscrição",
+            (Message::ConfirmTitle, German) => "Bestätigen Sie Ihr Abonnement",
+            (Message::ConfirmPrompt, English) => {
+                "One last step: confirm that you want to receive our newsletter at {email}."
+            }
+            (Message::ConfirmPrompt, Portuguese) => {
+                "Falta só um passo: confirme que quer receber a nossa newsletter em {email}."
+            }
+            (Message::ConfirmPrompt, German) => {
+                "Nur noch ein Schritt: Bestätigen Sie, dass Sie unseren Newsletter an {email} erhalten möchten."
+            }
+            (Message::ConfirmButton, English) => "Confirm my subscription",
+            (Message::ConfirmButton, Portuguese) => "Confirmar a minha inscrição",
+            (Message::ConfirmButton, German) => "Abonnement bestätigen",
+            (Message::ConfirmedTitle, English) => "Subscription confirmed",
+            (Message::ConfirmedTitle, Portuguese) => "Inscrição confirmada",
+            (Message::ConfirmedTitle, German) => "Abonnement bestätigt",
+            (Message::ConfirmedHeading, English) => "Thank you!",
+            (Message::ConfirmedHeading, Portuguese) => "Obrigado!",
+            (Message::ConfirmedHeading, German) => "Vielen Dank!",
+            (Message::ConfirmedBody, English) => "Your subscription is confirmed.",
+            (Message::ConfirmedBody, Portuguese) => "A sua inscrição está confirmada.",
+            (Message::ConfirmedBody, German) => "Ihr Abonnement ist bestätigt.",
+        }
+    }
+}
+
+/// The message of a validation error, in English as the error displays it.
+pub fn validation_message(error: &DomainValidationError, language: Language) -> String {
+    use DomainValidationError::*;
+    use Language::*;
+    match (error, language) {
+        (_, English) => error.to_string(),
+        (InvalidEmail, Portuguese) => "O endereço de email não é válido.".into(),
+        (InvalidEmail, German) => "Die E-Mail-Adresse ist ungültig.".into(),
+        (EmptyName, Portuguese) => "O nome é obrigatório.".into(),
+        (EmptyName, German) => "Der Name ist erforderlich.".into(),
+        (NameTooLong { max }, Portuguese) => {
+            format!("O nome deve ter no máximo {} caracteres.", max)
+        }
+        (NameTooLong { max }, German) => {
+            format!("Der Name darf höchstens {} Zeichen lang sein.", max)
+        }
+        (NameForbiddenCharacters, Portuguese) => {
+            "O nome não pode conter nenhum de / ( ) \" < > \\ { }.".into()
+        }
+        (NameForbiddenCharacters, German) => {
+            "Der Name darf keines von / ( ) \" < > \\ { } enthalten.".into()
+        }
+        (NameReservedPrefix, Portuguese) => "O nome não pode começar por `pii:`.".into(),
+        (NameReservedPrefix, German) => "Der Name darf nicht mit `pii:` beginnen.".into(),
+        (InvalidLocale, Portuguese) => {
+            "A língua não é uma etiqueta de idioma válida, por exemplo `pt-br`.".into()
+        }
+        (InvalidLocale, German) => {
+            "Die Sprache ist kein gültiges Sprach-Tag, z. B. `pt-br`.".into()
+        }
+        (InvalidPhoneNumber, Portuguese) => {
+            "O número de telefone não é um número internacional válido, por exemplo `+14155550100`."
+                .into()
+        }
+        (InvalidPhoneNumber, German) => {
+            "Die Telefonnummer ist keine gültige internationale Nummer, z. B. `+14155550100`."
+                .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validation_message, Language};
+    use crate::domain::DomainValidationError;
+
+    #[test]
+    fn the_preferred_supported_language_wins() {
+        let negotiate = |header| Language::negotiate(header, Language::English);
+        assert_eq!(
+            negotiate(Some("pt-BR,pt;q=0.9,en;q=0.8")),
+            Language::Portuguese
+        );
+        assert_eq!(
+            negotiate(Some("fr-FR, de;q=0.7, en;q=0.5")),
+            Language::German
+        );
+        assert_eq!(negotiate(Some("en;q=0.5, de")), Language::German);
+        assert_eq!(negotiate(Some("de;q=0, pt;q=0.1")), Language::Portuguese);
+        assert_eq!(negotiate(Some("*")), Language::English);
+    }
+
+    #[test]
+    fn the_default_language_is_used_without_a_supported_one() {
+        assert_eq!(
+            Language::negotiate(Some("fr, it;q=0.5"), Language::German),
+            Language::German
+        );
+        assert_eq!(
+            Language::negotiate(None, Language::Portuguese),
+            Language::Portuguese
+        );
+    }
+
+    #[test]
+    fn validation_messages_are_translated() {
+        let error = DomainValidationError::NameTooLong { max: 256 };
+        assert_eq!(
+            validation_message(&error, Language::English),
+            error.to_string()
+        );
+        assert_eq!(
+            validation_message(&error, Language::German),
+            "Der Name darf höchstens 256 Zeichen lang sein."
+        );
+    }
+}
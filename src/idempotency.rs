@@ -0,0 +1,311 @@
+//! Safe retries for the JSON API. A POST or PUT sent with an
+//! `Idempotency-Key` header is processed once: its response is saved and
+//! replayed to the retries sent with the same key, to the same route, within
+//! the retention window.
+//!
+//! A retry with another body means the key was reused, and is rejected.
+//! The key is claimed before the handler runs, and the response saved once
+//! it is done: no transaction is held in between, and the retries arriving
+//! meanwhile get a 409 to try again later. Server errors are not saved, so
+//! that the request can be retried for real. A claim left without response,
+//! e.g. by a crash, is taken over after `ABANDONED_AFTER_MINUTES`.
+use crate::api_keys::ApiKey;
+use crate::configuration::IdempotencySettings;
+use crate::database::ObserveQuery;
+use crate::utils::e500;
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use actix_web_lab::middleware::Next;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Set on the replayed responses.
+pub const REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+/// Far longer than any request takes.
+const ABANDONED_AFTER_MINUTES: i64 = 5;
+
+/// The key is any printable ASCII, up to 255 characters.
+fn parse_key(header: &[u8]) -> Option<&str> {
+    let key = std::str::from_utf8(header).ok()?;
+    let valid =
+        !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.bytes().all(|b| b.is_ascii_graphic());
+    valid.then_some(key)
+}
+
+/// Whose key it is: the id of the API key of the caller, or nobody outside
+/// `/api`. Two callers picking the same key do not see each other's
+/// responses.
+struct Scope {
+    caller: String,
+    key: String,
+    route: String,
+}
+
+pub async fn replay_idempotent_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if !matches!(*req.method(), Method::POST | Method::PUT) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let Some(header) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let Some(key) = parse_key(header.as_bytes()).map(str::to_owned) else {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The Idempotency-Key must be 1 to 255 printable ASCII characters",
+        ));
+    };
+    let (Some(pool), Some(settings)) = (
+        req.app_data::<web::Data<PgPool>>().cloned(),
+        req.app_data::<web::Data<IdempotencySettings>>().cloned(),
+    ) else {
+        return Err(e500("The idempotency settings are not registered"));
+    };
+    let scope = Scope {
+        caller: req
+            .extensions()
+            .get::<ApiKey>()
+            .map(|api_key| api_key.id.clone())
+            .unwrap_or_default(),
+        key,
+        route: format!("{} {}", req.method(), req.path()),
+    };
+    let request_hash = Sha256::digest(read_body(&mut req).await?).to_vec();
+
+    let since = Utc::now() - Duration::hours(settings.retention_hours.into());
+    if !claim(&pool, &scope, &request_hash, since)
+        .await
+        .map_err(e500)?
+    {
+        let saved = get_saved_response(&pool, &scope).await.map_err(e500)?;
+        return match saved {
+            Some(saved) if saved.request_hash != request_hash => {
+                Err(actix_web::error::ErrorUnprocessableEntity(
+                    "The Idempotency-Key was already used for another request",
+                ))
+            }
+            Some(saved) => match saved.into_response()? {
+                Some(response) => Ok(req.into_response(response)),
+                None => Err(in_progress()),
+            },
+            // Released by a server error since the claim failed.
+            None => Err(in_progress()),
+        };
+    }
+
+    let response = match next.call(req).await {
+        Ok(response) if !response.status().is_server_error() => response,
+        outcome => {
+            release(&pool, &scope).await.map_err(e500)?;
+            return Ok(outcome?.map_into_boxed_body());
+        }
+    };
+    let (request, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(&pool, &scope).await.map_err(e500)?;
+            return Err(e500(e.into().to_string()));
+        }
+    };
+    save_response(&pool, &scope, response.status(), response.headers(), &body)
+        .await
+        .map_err(e500)?;
+    let response = response.set_body(body).map_into_boxed_body();
+    Ok(ServiceResponse::new(request, response))
+}
+
+fn in_progress() -> actix_web::Error {
+    actix_web::error::ErrorConflict(
+        "A request with this Idempotency-Key is being processed: retry it later",
+    )
+}
+
+/// The body of the request, put back for the handler to read.
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let body = {
+        let (http_request, payload) = req.parts_mut();
+        web::Bytes::from_request(http_request, payload).await?
+    };
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    req.set_payload(Payload::from(payload));
+    Ok(body)
+}
+
+/// Record that the request is being processed, unless the key is already
+/// used: false then. The row saved before `since`, or claimed without
+/// response for longer than `ABANDONED_AFTER_MINUTES`, is replaced.
+async fn claim(
+    pool: &PgPool,
+    scope: &Scope,
+    request_hash: &[u8],
+    since: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (caller, idempotency_key, route, request_hash, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (caller, idempotency_key, route) DO UPDATE SET
+            request_hash = EXCLUDED.request_hash,
+            created_at = EXCLUDED.created_at,
+            response_status = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at <= $5
+            OR (idempotency.response_status IS NULL AND idempotency.created_at <= $6)
+        "#,
+        scope.caller,
+        scope.key,
+        scope.route,
+        request_hash,
+        since,
+        Utc::now() - Duration::minutes(ABANDONED_AFTER_MINUTES)
+    )
+    .execute(pool)
+    .observe("claim_idempotency_key")
+    .await?
+    .rows_affected();
+    Ok(claimed == 1)
+}
+
+/// Give the key up, so that the request can be retried.
+async fn release(pool: &PgPool, scope: &Scope) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE caller = $1 AND idempotency_key = $2 AND route = $3
+            AND response_status IS NULL
+        "#,
+        scope.caller,
+        scope.key,
+        scope.route
+    )
+    .execute(pool)
+    .observe("release_idempotency_key")
+    .await?;
+    Ok(())
+}
+
+/// The response is missing while the request is being processed.
+struct SavedResponse {
+    request_hash: Vec<u8>,
+    response_status: Option<i16>,
+    response_headers: Option<serde_json::Value>,
+    response_body: Option<Vec<u8>>,
+}
+
+impl SavedResponse {
+    fn into_response(self) -> Result<Option<HttpResponse>, actix_web::Error> {
+        let (Some(status), Some(headers), Some(body)) = (
+            self.response_status,
+            self.response_headers,
+            self.response_body,
+        ) else {
+            return Ok(None);
+        };
+        let status = u16::try_from(status)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .ok_or_else(|| e500("A saved response has an invalid status"))?;
+        let headers: Vec<(String, String)> = serde_json::from_value(headers).map_err(e500)?;
+        let mut response = HttpResponse::build(status);
+        for header in headers {
+            response.append_header(header);
+        }
+        Ok(Some(
+            response.insert_header((REPLAYED_HEADER, "true")).body(body),
+        ))
+    }
+}
+
+async fn get_saved_response(
+    pool: &PgPool,
+    scope: &Scope,
+) -> Result<Option<SavedResponse>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedResponse,
+        r#"
+        SELECT request_hash, response_status, response_headers, response_body
+        FROM idempotency
+        WHERE caller = $1 AND idempotency_key = $2 AND route = $3
+        "#,
+        scope.caller,
+        scope.key,
+        scope.route
+    )
+    .fetch_optional(pool)
+    .observe("get_saved_response")
+    .await
+}
+
+/// Headers whose value is not UTF-8 are left out.
+async fn save_response(
+    pool: &PgPool,
+    scope: &Scope,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), sqlx::Error> {
+    let headers: Vec<(&str, &str)> = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect();
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET response_status = $4, response_headers = $5, response_body = $6
+        WHERE caller = $1 AND idempotency_key = $2 AND route = $3
+        "#,
+        scope.caller,
+        scope.key,
+        scope.route,
+        status.as_u16() as i16,
+        serde_json::json!(headers),
+        body
+    )
+    .execute(pool)
+    .observe("save_response")
+    .await?;
+    Ok(())
+}
+
+/// Delete the responses saved before `before`, which are no longer replayed.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_expired_responses(
+    pool: &PgPool,
+    before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query!("DELETE FROM idempotency WHERE created_at <= $1", before)
+        .execute(pool)
+        .observe("delete_expired_idempotency_responses")
+        .await?
+        .rows_affected();
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_key;
+
+    #[test]
+    fn keys_are_short_printable_ascii() {
+        assert_eq!(
+            parse_key(b"4f8b6b8e-9c5e-4d3b-9d53-1c8a5c6f2e1a"),
+            Some("4f8b6b8e-9c5e-4d3b-9d53-1c8a5c6f2e1a")
+        );
+        assert_eq!(parse_key(b""), None);
+        assert_eq!(parse_key(b"two words"), None);
+        assert_eq!(parse_key("é".as_bytes()), None);
+        assert_eq!(parse_key(&[b'k'; 256]), None);
+    }
+}
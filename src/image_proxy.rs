@@ -0,0 +1,312 @@
+//! Serve the external images of archived issues from our own origin: readers'
+//! IPs don't leak to third parties, and cached images survive their host.
+use crate::configuration::ImageProxySettings;
+use crate::links;
+use crate::outbound::{is_private_ip_literal, PublicResolver};
+use crate::startup::StartupError;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxiedImage {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImageProxyError {
+    #[error("The image could not be fetched")]
+    Upstream(#[from] reqwest::Error),
+    #[error("The upstream server returned {0}")]
+    UpstreamStatus(reqwest::StatusCode),
+    #[error("Unsupported content type: {0}")]
+    NotAnImage(String),
+    #[error("The image is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("{0} is not a public address")]
+    PrivateHost(String),
+}
+
+fn signer(secret: &Secret<String>, url: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(b"image-proxy:");
+    mac.update(url.as_bytes());
+    mac
+}
+
+/// Only URLs we signed can be proxied: we are not an open proxy.
+pub fn sign_image_url(secret: &Secret<String>, url: &str) -> String {
+    hex::encode(signer(secret, url).finalize().into_bytes())
+}
+
+pub fn verify_image_url(secret: &Secret<String>, url: &str, signature: &str) -> bool {
+    hex::decode(signature)
+        .is_ok_and(|signature| signer(secret, url).verify_slice(&signature).is_ok())
+}
+
+/// Point the external `src` attributes of `html` at the image proxy.
+pub fn rewrite_image_sources(html: &str, secret: &Secret<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(index) = rest.find("src=") {
+        let (before, after) = rest.split_at(index + "src=".len());
+        rewritten.push_str(before);
+        rest = after;
+        let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            continue;
+        };
+        let raw = &rest[1..=end];
+        let url = htmlescape::decode_html(raw).unwrap_or_else(|_| raw.to_owned());
+        if url.starts_with("http://") || url.starts_with("https://") {
+            let proxied = links::proxied_image(secret, &url).to_string();
+            rewritten.push(quote);
+            rewritten.push_str(&htmlescape::encode_minimal(&proxied));
+            rewritten.push(quote);
+        } else {
+            rewritten.push_str(&rest[..=end + 1]);
+        }
+        rest = &rest[end + 2..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// Images by URL, evicting the oldest entries once `max_bytes` is exceeded.
+struct ImageCache {
+    images: HashMap<String, ProxiedImage>,
+    order: VecDeque<String>,
+    size: usize,
+    max_bytes: usize,
+}
+
+impl ImageCache {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            images: HashMap::new(),
+            order: VecDeque::new(),
+            size: 0,
+            max_bytes,
+        }
+    }
+
+    fn get(&self, url: &str) -> Option<ProxiedImage> {
+        self.images.get(url).cloned()
+    }
+
+    fn insert(&mut self, url: String, image: ProxiedImage) {
+        if image.bytes.len() > self.max_bytes || self.images.contains_key(&url) {
+            return;
+        }
+        while self.size + image.bytes.len() > self.max_bytes {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.images.remove(&oldest) {
+                self.size -= evicted.bytes.len();
+            }
+        }
+        self.size += image.bytes.len();
+        self.order.push_back(url.clone());
+        self.images.insert(url, image);
+    }
+}
+
+pub struct ImageProxy {
+    http_client: Client,
+    allow_private_hosts: bool,
+    max_image_bytes: usize,
+    cache: Mutex<ImageCache>,
+}
+
+impl ImageProxy {
+    pub fn new(settings: &ImageProxySettings) -> Result<Self, StartupError> {
+        // A redirect could lead anywhere: the upstream server gets one try.
+        let mut builder = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .redirect(reqwest::redirect::Policy::none());
+        if !settings.allow_private_hosts {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let http_client = builder
+            .build()
+            .map_err(|e| StartupError::HttpClient("image proxy", e))?;
+        Ok(Self {
+            http_client,
+            allow_private_hosts: settings.allow_private_hosts,
+            max_image_bytes: settings.max_image_bytes,
+            cache: Mutex::new(ImageCache::new(settings.max_cache_bytes)),
+        })
+    }
+
+    #[tracing::instrument(name = "Proxy an image", skip(self))]
+    pub async fn get(&self, url: &str) -> Result<ProxiedImage, ImageProxyError> {
+        if let Some(image) = self.cache.lock().unwrap().get(url) {
+            return Ok(image);
+        }
+        let image = self.fetch(url).await?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(url.to_owned(), image.clone());
+        Ok(image)
+    }
+
+    async fn fetch(&self, url: &str) -> Result<ProxiedImage, ImageProxyError> {
+        let request = self.http_client.get(url).build()?;
+        if !self.allow_private_hosts && is_private_ip_literal(request.url()) {
+            return Err(ImageProxyError::PrivateHost(
+                request.url().host_str().unwrap_or_default().to_owned(),
+            ));
+        }
+        let mut response = self.http_client.execute(request).await?;
+        if !response.status().is_success() {
+            return Err(ImageProxyError::UpstreamStatus(response.status()));
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        if !is_raster_image(&content_type) {
+            return Err(ImageProxyError::NotAnImage(content_type));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_image_bytes as u64)
+        {
+            return Err(ImageProxyError::TooLarge(self.max_image_bytes));
+        }
+        // The declared length can't be trusted: stop reading past the limit.
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > self.max_image_bytes {
+                return Err(ImageProxyError::TooLarge(self.max_image_bytes));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(ProxiedImage {
+            content_type,
+            bytes,
+        })
+    }
+}
+
+/// SVG is an image type too, but it can carry scripts: we don't serve it.
+fn is_raster_image(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        mime.as_str(),
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/avif"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_raster_image, rewrite_image_sources, sign_image_url, verify_image_url, ImageCache,
+        ImageProxy, ImageProxyError, ProxiedImage,
+    };
+    use crate::configuration::ImageProxySettings;
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("secret".into())
+    }
+
+    fn image(size: usize) -> ProxiedImage {
+        ProxiedImage {
+            content_type: "image/png".into(),
+            bytes: vec![0; size],
+        }
+    }
+
+    #[test]
+    fn only_signed_urls_are_accepted() {
+        let url = "https://example.com/logo.png";
+        let signature = sign_image_url(&secret(), url);
+        assert!(verify_image_url(&secret(), url, &signature));
+        assert!(!verify_image_url(
+            &secret(),
+            "https://example.com/other.png",
+            &signature
+        ));
+        assert!(!verify_image_url(&secret(), url, "not-hex"));
+    }
+
+    #[test]
+    fn external_image_sources_are_rewritten() {
+        let html = r#"<img src="https://example.com/a.png?x=1&amp;y=2"> <img src='/local.png'>"#;
+        let rewritten = rewrite_image_sources(html, &secret());
+        let expected_url = "https://example.com/a.png?x=1&y=2";
+        assert_eq!(
+            rewritten,
+            format!(
+                r#"<img src="/archive/images?url={}&amp;signature={}"> <img src='/local.png'>"#,
+                urlencoding::encode(expected_url),
+                sign_image_url(&secret(), expected_url)
+            )
+        );
+    }
+
+    #[test]
+    fn the_cache_evicts_the_oldest_images_to_stay_within_its_limit() {
+        let mut cache = ImageCache::new(10);
+        cache.insert("a".into(), image(4));
+        cache.insert("b".into(), image(4));
+        cache.insert("c".into(), image(4));
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+        assert!(cache.get("c").is_some());
+        cache.insert("huge".into(), image(11));
+        assert!(cache.get("huge").is_none());
+    }
+
+    #[test]
+    fn svg_is_not_served() {
+        assert!(is_raster_image("image/png"));
+        assert!(is_raster_image("image/JPEG; charset=binary"));
+        assert!(!is_raster_image("image/svg+xml"));
+        assert!(!is_raster_image("text/html"));
+    }
+
+    #[tokio::test]
+    async fn internal_hosts_are_not_fetched() {
+        let proxy = ImageProxy::new(&ImageProxySettings {
+            timeout_milliseconds: 1000,
+            max_image_bytes: 1024,
+            max_cache_bytes: 1024,
+            allow_private_hosts: false,
+        })
+        .unwrap();
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/logo.png",
+            "http://localhost/logo.png",
+        ] {
+            let result = proxy.get(url).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(ImageProxyError::PrivateHost(_) | ImageProxyError::Upstream(_))
+                ),
+                "{}",
+                url
+            );
+        }
+    }
+}
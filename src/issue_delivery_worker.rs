@@ -0,0 +1,818 @@
+use crate::configuration::{
+    DeliveryAlertSettings, DeliveryLaneSettings, DomainThrottlingSettings, Settings,
+};
+use crate::cost_ledger::record_send;
+use crate::database::{retry_read, ObserveQuery};
+use crate::delivery_status::get_suppression;
+use crate::domain::SubscriberEmail;
+use crate::domain_throttle::{recipient_domain, DomainThrottle};
+use crate::email_client::{EmailClient, MessageStream, SendEmailError};
+use crate::events::{DomainEvent, EventBus};
+use crate::issue_enqueue::resume_interrupted_enqueues;
+use crate::landing_analytics::{self, LandingLinks};
+use crate::metrics::DELIVERY_LANE_PAUSES;
+use crate::pii::PiiCipher;
+use crate::polls::{render_for_delivery, PollLinks};
+use crate::referrals;
+use crate::reload::ReloadableSettings;
+use crate::startup::get_connection_pool;
+use crate::token_signer::TokenSigner;
+use crate::warm_up::WarmUpSchedule;
+use crate::web_push::{notify_issue, WebPush};
+use crate::web_version::WebVersion;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+    DailyQuotaReached,
+    /// Every pending task goes to a domain at its caps or sits in a paused
+    /// lane.
+    Throttled,
+}
+
+/// The rules the worker enforces while draining the queue.
+#[derive(Clone)]
+pub struct DeliveryPolicy {
+    pub alerts: Option<DeliveryAlertSettings>,
+    pub warm_up: Option<WarmUpSchedule>,
+    pub lanes: DeliveryLaneSettings,
+    pub enqueue_batch_size: i64,
+    pub domain_throttling: DomainThrottlingSettings,
+}
+
+impl DeliveryPolicy {
+    pub fn from_settings(configuration: &Settings) -> Self {
+        Self {
+            alerts: configuration.delivery_alerts.clone(),
+            warm_up: configuration.warm_up.clone().map(WarmUpSchedule::new),
+            lanes: configuration.delivery_lanes.clone(),
+            enqueue_batch_size: configuration.delivery_enqueue.batch_size,
+            domain_throttling: configuration.domain_throttling.clone(),
+        }
+    }
+}
+
+/// The delivery queue is split in two lanes: urgent issues go through the
+/// priority lane and never wait behind a large broadcast in the bulk lane.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DeliveryLane {
+    Priority,
+    Bulk,
+}
+
+impl DeliveryLane {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryLane::Priority => "priority",
+            DeliveryLane::Bulk => "bulk",
+        }
+    }
+
+    fn parse(s: &str) -> Self {
+        if s == "priority" {
+            DeliveryLane::Priority
+        } else {
+            DeliveryLane::Bulk
+        }
+    }
+}
+
+/// Picks the lane to drain next: the priority lane first, except that the
+/// bulk lane gets a turn after every `max_consecutive_priority_tasks`
+/// priority deliveries, so it cannot starve. A lane the email provider
+/// throttled is left alone until the provider's `Retry-After` elapses.
+#[derive(Default)]
+pub struct LaneScheduler {
+    consecutive_priority_tasks: u32,
+    paused_until: HashMap<DeliveryLane, Instant>,
+}
+
+impl LaneScheduler {
+    fn preferred(&self, lanes: &DeliveryLaneSettings) -> DeliveryLane {
+        if self.consecutive_priority_tasks >= lanes.max_consecutive_priority_tasks {
+            DeliveryLane::Bulk
+        } else {
+            DeliveryLane::Priority
+        }
+    }
+
+    fn served(&mut self, lane: DeliveryLane) {
+        match lane {
+            DeliveryLane::Priority => self.consecutive_priority_tasks += 1,
+            DeliveryLane::Bulk => self.consecutive_priority_tasks = 0,
+        }
+    }
+
+    fn pause(&mut self, lane: DeliveryLane, until: Instant) {
+        let paused_until = self.paused_until.entry(lane).or_insert(until);
+        *paused_until = (*paused_until).max(until);
+    }
+
+    fn paused_lanes(&mut self, now: Instant) -> Vec<String> {
+        self.paused_until.retain(|_, until| *until > now);
+        self.paused_until
+            .keys()
+            .map(|lane| lane.as_str().to_owned())
+            .collect()
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty
+    ),
+    err
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    pii: &PiiCipher,
+    web_version: &WebVersion,
+    poll_links: &PollLinks,
+    landing_links: Option<&LandingLinks>,
+    web_push: Option<&WebPush>,
+    event_bus: &EventBus,
+    policy: &DeliveryPolicy,
+    lanes: &mut LaneScheduler,
+    domains: &DomainThrottle,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    if let Some(warm_up) = &policy.warm_up {
+        if warm_up.remaining_quota(pool).await? == Some(0) {
+            return Ok(ExecutionOutcome::DailyQuotaReached);
+        }
+    }
+    let now = Instant::now();
+    let saturated = domains.saturated_domains(&policy.domain_throttling, now);
+    let paused = lanes.paused_lanes(now);
+    let task = dequeue_task(pool, lanes.preferred(&policy.lanes), &paused, &saturated).await?;
+    if task.is_none() {
+        return Ok(if saturated.is_empty() && paused.is_empty() {
+            ExecutionOutcome::EmptyQueue
+        } else {
+            ExecutionOutcome::Throttled
+        });
+    }
+    let (mut transaction, issue_id, email, lane) = task.unwrap();
+    // Another worker sharing the throttle may have used up the domain since.
+    let Some(_permit) = domains.try_acquire(
+        &policy.domain_throttling,
+        &recipient_domain(&email),
+        Instant::now(),
+    ) else {
+        return Ok(ExecutionOutcome::Throttled);
+    };
+    lanes.served(lane);
+    Span::current()
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email));
+    let mut events = Vec::new();
+    // The queue holds the email as stored: its domain is in clear, so that
+    // the throttling above does not need it opened.
+    let recipient = pii
+        .open_email(&email)
+        .and_then(|email| SubscriberEmail::parse(email).map_err(anyhow::Error::from));
+    // `None` when an earlier run already claimed the delivery, or the address
+    // bounced or complained since the issue was enqueued.
+    let delivered = match recipient {
+        Ok(recipient) if get_suppression(pool, recipient.as_ref()).await?.is_some() => {
+            tracing::info!("Skipping a suppressed address");
+            None
+        }
+        Ok(recipient) => {
+            let issue = get_issue(pool, issue_id, &email).await?;
+            // Rendered ahead of the claim: a failure past it would leave the
+            // delivery claimed, and never sent. Tagged first, before the
+            // links to us are added.
+            let (html_content, text_content) = match landing_links {
+                Some(landing_links) => {
+                    landing_analytics::render_for_delivery(
+                        pool,
+                        landing_links,
+                        issue_id,
+                        &email,
+                        &issue.html_content,
+                        &issue.text_content,
+                    )
+                    .await?
+                }
+                None => (issue.html_content.clone(), issue.text_content.clone()),
+            };
+            let (html_content, text_content) = render_for_delivery(
+                pool,
+                poll_links,
+                issue_id,
+                &email,
+                &html_content,
+                &text_content,
+            )
+            .await?;
+            let (html_content, text_content) = referrals::render_for_delivery(
+                pool,
+                web_version.base_url(),
+                &email,
+                &html_content,
+                &text_content,
+            )
+            .await?;
+            let (html_content, text_content) =
+                web_version.inject(issue_id, issue.paid_only, &html_content, &text_content);
+            if !claim_delivery(pool, issue_id, &email).await? {
+                tracing::warn!(
+                    "Skipping a delivery claimed by an earlier attempt, which was interrupted \
+                     and may or may not have reached the provider"
+                );
+                None
+            } else {
+                match email_client
+                    .send_email(
+                        &recipient,
+                        &issue.title,
+                        &html_content,
+                        &text_content,
+                        MessageStream::Broadcast,
+                    )
+                    .await
+                {
+                    Ok(sent) => {
+                        record_send(&mut *transaction, &sent).await?;
+                        if let Some(web_push) = web_push.filter(|_| issue.push) {
+                            let url = web_version.link(issue_id, issue.paid_only);
+                            if let Err(e) =
+                                notify_issue(pool, web_push, &email, &issue.title, &url).await
+                            {
+                                tracing::warn!(
+                                    error.cause_chain = ?e,
+                                    error.message = %e,
+                                    "Failed to send the push notifications of a delivery"
+                                );
+                            }
+                        }
+                        Some(true)
+                    }
+                    Err(SendEmailError::Throttled { retry_after }) => {
+                        // Retrying now would only be throttled again: the
+                        // task goes back to the queue and its lane waits.
+                        tracing::warn!(
+                            lane = lane.as_str(),
+                            retry_after_seconds = retry_after.as_secs(),
+                            "The email provider throttled us, pausing the delivery lane"
+                        );
+                        release_claim(pool, issue_id, &email).await?;
+                        transaction.rollback().await?;
+                        lanes.pause(lane, Instant::now() + retry_after);
+                        DELIVERY_LANE_PAUSES
+                            .with_label_values(&[lane.as_str()])
+                            .inc();
+                        record_throttle(pool, issue_id, retry_after).await?;
+                        return Ok(ExecutionOutcome::Throttled);
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "Failed to deliver issue to a confirmed subscriber. \
+                             Skipping.",
+                        );
+                        events.push(DomainEvent::DeliveryFailed {
+                            subscriber_id: get_subscriber_id(pool, &email).await?,
+                            error: e.to_string(),
+                        });
+                        Some(false)
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                 Their stored contact details are invalid",
+            );
+            Some(false)
+        }
+    };
+    let stats = match delivered {
+        Some(delivered) => record_attempt(&mut transaction, issue_id, &email, delivered).await?,
+        None => get_delivery_stats(&mut transaction, issue_id).await?,
+    };
+    delete_task(&mut transaction, issue_id, &email).await?;
+
+    if let Some(alerts) = &policy.alerts {
+        if let Some(failure_rate) = stats.exceeded_failure_rate(alerts) {
+            tracing::warn!(
+                failure_rate,
+                "Pausing the delivery of a newsletter issue: the failure rate is too high"
+            );
+            set_issue_status(&mut *transaction, issue_id, IssueStatus::Paused).await?;
+            events.push(DomainEvent::FailureRateExceeded {
+                issue_id,
+                failure_rate,
+            });
+        }
+    }
+    if remaining_tasks(&mut transaction, issue_id).await? == 0 {
+        set_issue_status(&mut *transaction, issue_id, IssueStatus::Completed).await?;
+        events.push(DomainEvent::IssueSent {
+            issue_id,
+            title: stats.title,
+            recipients: stats.sent_count as usize,
+        });
+    }
+    for event in &events {
+        event_bus.record(&mut transaction, event).await?;
+    }
+    transaction.commit().await?;
+
+    for event in events {
+        event_bus.fan_out(event);
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// The lifecycle of a newsletter issue as seen by the delivery worker.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IssueStatus {
+    InProgress,
+    Paused,
+    Completed,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::InProgress => "in_progress",
+            IssueStatus::Paused => "paused",
+            IssueStatus::Completed => "completed",
+        }
+    }
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+/// Dequeue from the `preferred` lane, or from the other one when it is empty,
+/// leaving alone the `paused` lanes and the tasks to the `saturated` domains.
+#[tracing::instrument(skip_all, fields(preferred_lane = preferred.as_str()))]
+async fn dequeue_task(
+    pool: &PgPool,
+    preferred: DeliveryLane,
+    paused: &[String],
+    saturated: &[String],
+) -> Result<Option<(PgTransaction, Uuid, String, DeliveryLane)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.lane
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.status = 'in_progress' AND i.enqueue_completed
+            AND q.lane <> ALL($2)
+            AND lower(split_part(q.subscriber_email, '@', -1)) <> ALL($3)
+        ORDER BY q.lane = $1 DESC
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+        preferred.as_str(),
+        paused,
+        saturated
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("dequeue_task")
+    .await?;
+    if let Some(r) = r {
+        Ok(Some((
+            transaction,
+            r.newsletter_issue_id,
+            r.subscriber_email,
+            DeliveryLane::parse(&r.lane),
+        )))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Show on the issue that the provider is throttling its delivery.
+async fn record_throttle(
+    pool: &PgPool,
+    issue_id: Uuid,
+    retry_after: Duration,
+) -> Result<(), anyhow::Error> {
+    let throttled_until = Utc::now() + chrono::Duration::from_std(retry_after)?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET throttled_until = $2, provider_throttles = provider_throttles + 1
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        throttled_until
+    )
+    .execute(pool)
+    .observe("record_provider_throttle")
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        issue_id,
+        email
+    );
+    transaction.execute(query).observe("delete_task").await?;
+    Ok(())
+}
+
+/// Claim the delivery of `issue_id` to the subscriber stored as `email`,
+/// returning `false` if it was claimed already. The claim is committed on
+/// its own, ahead of the send, so that it outlives a crash mid-send.
+/// Addresses no subscriber is stored with anymore are not guarded.
+async fn claim_delivery(pool: &PgPool, issue_id: Uuid, email: &str) -> Result<bool, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        WITH subscriber AS (
+            SELECT id FROM subscriptions WHERE email = $2 LIMIT 1
+        ), claimed AS (
+            INSERT INTO delivery_claims (newsletter_issue_id, subscriber_id, claimed_at)
+            SELECT $1, id, now() FROM subscriber
+            ON CONFLICT DO NOTHING
+            RETURNING subscriber_id
+        )
+        SELECT
+            EXISTS (SELECT 1 FROM subscriber) AS "known!",
+            EXISTS (SELECT 1 FROM claimed) AS "claimed!"
+        "#,
+        issue_id,
+        email
+    )
+    .fetch_one(pool)
+    .observe_one("claim_delivery")
+    .await?;
+    Ok(r.claimed || !r.known)
+}
+
+async fn get_subscriber_id(pool: &PgPool, email: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    let r = sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_optional(pool)
+        .observe("get_delivery_subscriber_id")
+        .await?;
+    Ok(r.map(|r| r.id))
+}
+
+/// Give a claim back, for a task that goes back to the queue unsent.
+async fn release_claim(pool: &PgPool, issue_id: Uuid, email: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM delivery_claims
+        WHERE newsletter_issue_id = $1
+            AND subscriber_id IN (SELECT id FROM subscriptions WHERE email = $2)
+        "#,
+        issue_id,
+        email
+    )
+    .execute(pool)
+    .observe("release_delivery_claim")
+    .await?;
+    Ok(())
+}
+
+pub struct NewsletterIssue {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub paid_only: bool,
+    /// Also sent as a push notification.
+    pub push: bool,
+}
+
+/// The issue in the locale of the subscriber stored as `email`. Without a
+/// variant in their locale (`pt-br`), one in its language (`pt`) is picked,
+/// then the issue itself.
+#[tracing::instrument(skip_all)]
+pub async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = retry_read(|| {
+        sqlx::query_as!(
+            NewsletterIssue,
+            r#"
+            SELECT
+                COALESCE(v.title, i.title) AS "title!",
+                COALESCE(v.text_content, i.text_content) AS "text_content!",
+                COALESCE(v.html_content, i.html_content) AS "html_content!",
+                i.paid_only,
+                i.push
+            FROM newsletter_issues i
+            LEFT JOIN LATERAL (
+                SELECT v.title, v.text_content, v.html_content
+                FROM newsletter_issue_variants v
+                JOIN subscriptions s ON s.email = $2
+                WHERE v.newsletter_issue_id = i.newsletter_issue_id
+                    AND (s.locale = v.locale OR s.locale LIKE v.locale || '-%')
+                ORDER BY length(v.locale) DESC
+                LIMIT 1
+            ) v ON true
+            WHERE i.newsletter_issue_id = $1
+            "#,
+            issue_id,
+            email
+        )
+        .fetch_one(pool)
+        .observe_one("get_issue")
+    })
+    .await?;
+    Ok(issue)
+}
+
+struct DeliveryStats {
+    title: String,
+    sent_count: i32,
+    failed_count: i32,
+}
+
+impl DeliveryStats {
+    /// Returns the failure rate if it is above the configured threshold and
+    /// enough attempts have been made for it to be meaningful.
+    fn exceeded_failure_rate(&self, alerts: &DeliveryAlertSettings) -> Option<f64> {
+        let attempts = self.sent_count + self.failed_count;
+        if attempts < alerts.min_attempts {
+            return None;
+        }
+        let failure_rate = f64::from(self.failed_count) / f64::from(attempts);
+        (failure_rate > alerts.failure_rate_threshold).then_some(failure_rate)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_attempt(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    delivered: bool,
+) -> Result<DeliveryStats, anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_deliveries (
+            newsletter_issue_id, subscriber_email, attempted_at, succeeded, snapshot_id
+        )
+        VALUES (
+            $1, $2, $3, $4,
+            (SELECT snapshot_id FROM recipient_snapshots WHERE newsletter_issue_id = $1)
+        )
+        "#,
+        issue_id,
+        email,
+        Utc::now(),
+        delivered
+    );
+    transaction
+        .execute(query)
+        .observe("insert_email_delivery")
+        .await?;
+    let (sent, failed) = if delivered { (1, 0) } else { (0, 1) };
+    let stats = sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        UPDATE newsletter_issues
+        SET
+            sent_count = sent_count + $2,
+            failed_count = failed_count + $3
+        WHERE newsletter_issue_id = $1
+        RETURNING title, sent_count, failed_count
+        "#,
+        issue_id,
+        sent,
+        failed
+    )
+    .fetch_one(&mut **transaction)
+    .observe_one("update_delivery_counters")
+    .await?;
+    Ok(stats)
+}
+
+async fn get_delivery_stats(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+) -> Result<DeliveryStats, anyhow::Error> {
+    let stats = sqlx::query_as!(
+        DeliveryStats,
+        r#"
+        SELECT title, sent_count, failed_count
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut **transaction)
+    .observe_one("get_delivery_counters")
+    .await?;
+    Ok(stats)
+}
+
+pub async fn set_issue_status<'a, E>(
+    executor: E,
+    issue_id: Uuid,
+    status: IssueStatus,
+) -> Result<(), anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET status = $2 WHERE newsletter_issue_id = $1"#,
+        issue_id,
+        status.as_str()
+    )
+    .execute(executor)
+    .observe("set_issue_status")
+    .await?;
+    Ok(())
+}
+
+async fn remaining_tasks(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+) -> Result<i64, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(&mut **transaction)
+    .observe_one("remaining_tasks")
+    .await?;
+    Ok(r.count)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    pii: PiiCipher,
+    web_version: WebVersion,
+    poll_links: PollLinks,
+    landing_links: Option<LandingLinks>,
+    web_push: Option<WebPush>,
+    event_bus: EventBus,
+    settings: ReloadableSettings,
+) -> Result<(), anyhow::Error> {
+    let mut lanes = LaneScheduler::default();
+    let domains = DomainThrottle::default();
+    loop {
+        let policy = &settings.load().delivery;
+        match try_execute_task(
+            &pool,
+            &email_client,
+            &pii,
+            &web_version,
+            &poll_links,
+            landing_links.as_ref(),
+            web_push.as_ref(),
+            &event_bus,
+            policy,
+            &mut lanes,
+            &domains,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                match resume_interrupted_enqueues(&pool, policy.enqueue_batch_size).await {
+                    Ok(0) => tokio::time::sleep(Duration::from_secs(10)).await,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "Failed to resume an interrupted enqueue"
+                        );
+                        tokio::time::sleep(Duration::from_secs(10)).await;
+                    }
+                }
+            }
+            Ok(ExecutionOutcome::Throttled) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::DailyQuotaReached) => {
+                // The remaining deliveries spill over to the next day.
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            Err(e) => {
+                event_bus.publish_job_failure("issue_delivery", &e).await;
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    event_bus: EventBus,
+    settings: ReloadableSettings,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    let email_client = configuration.build_email_client()?;
+    let pii = PiiCipher::new(configuration.pii_encryption.as_ref()).map_err(anyhow::Error::msg)?;
+    let signer = TokenSigner::new(&configuration.token_signing).map_err(anyhow::Error::msg)?;
+    let signer = Arc::new(signer);
+    let web_version = WebVersion::new(configuration.application.base_url.clone(), signer.clone());
+    let landing_links = configuration
+        .landing_analytics
+        .as_ref()
+        .map(|settings| LandingLinks::new(settings, signer.clone()))
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+    let poll_links = PollLinks::new(configuration.application.base_url, signer);
+    let web_push = configuration.web_push.map(WebPush::new).transpose()?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        pii,
+        web_version,
+        poll_links,
+        landing_links,
+        web_push,
+        event_bus,
+        settings,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryLane, LaneScheduler};
+    use crate::configuration::DeliveryLaneSettings;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn the_bulk_lane_gets_a_turn_after_a_burst_of_priority_tasks() {
+        let lanes = DeliveryLaneSettings {
+            max_consecutive_priority_tasks: 2,
+        };
+        let mut scheduler = LaneScheduler::default();
+        let mut preferred = Vec::new();
+        for _ in 0..6 {
+            let lane = scheduler.preferred(&lanes);
+            preferred.push(lane);
+            scheduler.served(lane);
+        }
+        use DeliveryLane::*;
+        assert_eq!(
+            preferred,
+            vec![Priority, Priority, Bulk, Priority, Priority, Bulk]
+        );
+    }
+
+    #[test]
+    fn the_priority_lane_is_preferred_again_once_bulk_was_served() {
+        let lanes = DeliveryLaneSettings {
+            max_consecutive_priority_tasks: 1,
+        };
+        let mut scheduler = LaneScheduler::default();
+        scheduler.served(DeliveryLane::Priority);
+        assert_eq!(scheduler.preferred(&lanes), DeliveryLane::Bulk);
+        // The bulk lane served a task, possibly because the priority one was empty.
+        scheduler.served(DeliveryLane::Bulk);
+        assert_eq!(scheduler.preferred(&lanes), DeliveryLane::Priority);
+    }
+
+    #[test]
+    fn a_paused_lane_stays_paused_until_the_latest_retry_after() {
+        let mut scheduler = LaneScheduler::default();
+        let now = Instant::now();
+        scheduler.pause(DeliveryLane::Bulk, now + Duration::from_secs(60));
+        scheduler.pause(DeliveryLane::Bulk, now + Duration::from_secs(30));
+        assert_eq!(
+            scheduler.paused_lanes(now + Duration::from_secs(45)),
+            vec!["bulk"]
+        );
+        assert!(scheduler
+            .paused_lanes(now + Duration::from_secs(60))
+            .is_empty());
+    }
+}
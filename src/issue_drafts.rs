@@ -0,0 +1,338 @@
+//! Issues started from another one: drafts cloned from a published issue,
+//! and templates saved from one to start new drafts from.
+//!
+//! Both keep the content as it was written, with its `{{snippet:…}}`,
+//! `{{sponsor}}` and `{{poll}}` tags, so that the snippets are expanded
+//! again with their latest version and the sponsor slot gets its own
+//! tracking links.
+use crate::database::ObserveQuery;
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+const MAX_TEMPLATE_NAME_LENGTH: usize = 64;
+
+/// The fields of the publish form.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IssueDraft {
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub paid_only: bool,
+    pub priority: bool,
+    pub push: bool,
+    pub sponsor: String,
+    pub sponsor_url: String,
+    pub sponsor_message: String,
+    pub poll_question: String,
+    /// One per line.
+    pub poll_options: String,
+}
+
+/// The issue as the form it was published with.
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_as_draft(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueDraft>, sqlx::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            COALESCE(i.source_text_content, i.text_content) AS "text_content!",
+            COALESCE(i.source_html_content, i.html_content) AS "html_content!",
+            i.paid_only,
+            i.delivery_lane = 'priority' AS "priority!",
+            i.push,
+            s.sponsor AS "sponsor?",
+            s.target_url AS "sponsor_url?",
+            s.message AS "sponsor_message?",
+            p.question AS "poll_question?",
+            p.options AS "poll_options?"
+        FROM newsletter_issues i
+        LEFT JOIN sponsor_slots s USING (newsletter_issue_id)
+        LEFT JOIN polls p USING (newsletter_issue_id)
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .observe("get_issue_as_draft")
+    .await?;
+    Ok(issue.map(|issue| IssueDraft {
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        paid_only: issue.paid_only,
+        priority: issue.priority,
+        push: issue.push,
+        sponsor: issue.sponsor.unwrap_or_default(),
+        sponsor_url: issue.sponsor_url.unwrap_or_default(),
+        sponsor_message: issue.sponsor_message.unwrap_or_default(),
+        poll_question: issue.poll_question.unwrap_or_default(),
+        poll_options: issue.poll_options.unwrap_or_default().join("\n"),
+    }))
+}
+
+/// Clone `issue_id` into a new draft. Returns its id, `None` if there is no
+/// such issue.
+#[tracing::instrument(skip(pool))]
+pub async fn clone_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    created_by: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let Some(draft) = get_issue_as_draft(pool, issue_id).await? else {
+        return Ok(None);
+    };
+    let draft_id = insert_draft(pool, &draft, Some(issue_id), Some(created_by)).await?;
+    Ok(Some(draft_id))
+}
+
+/// Store a new draft, returning its id. `created_by` is `None` for the
+/// drafts of the RSS feeds.
+#[tracing::instrument(skip(executor, draft))]
+pub async fn insert_draft<'a, E>(
+    executor: E,
+    draft: &IssueDraft,
+    cloned_from: Option<Uuid>,
+    created_by: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id, title, text_content, html_content, paid_only, priority, push,
+            sponsor, sponsor_url, sponsor_message, poll_question, poll_options,
+            cloned_from, created_by, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, now())
+        "#,
+        draft_id,
+        draft.title,
+        draft.text_content,
+        draft.html_content,
+        draft.paid_only,
+        draft.priority,
+        draft.push,
+        draft.sponsor,
+        draft.sponsor_url,
+        draft.sponsor_message,
+        draft.poll_question,
+        draft.poll_options,
+        cloned_from,
+        created_by
+    )
+    .execute(executor)
+    .observe("insert_newsletter_draft")
+    .await?;
+    Ok(draft_id)
+}
+
+pub struct DraftSummary {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// The drafts waiting to be published, most recent first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        DraftSummary,
+        "SELECT draft_id, title, created_at FROM newsletter_drafts ORDER BY created_at DESC"
+    )
+    .fetch_all(pool)
+    .observe("list_newsletter_drafts")
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<IssueDraft>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueDraft,
+        r#"
+        SELECT
+            title, text_content, html_content, paid_only, priority, push,
+            sponsor, sponsor_url, sponsor_message, poll_question, poll_options
+        FROM newsletter_drafts
+        WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .observe("get_newsletter_draft")
+    .await
+}
+
+/// A draft is gone once published.
+#[tracing::instrument(skip(executor))]
+pub async fn delete_draft<'a, E>(executor: E, draft_id: Uuid) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .execute(executor)
+    .observe("delete_newsletter_draft")
+    .await?;
+    Ok(())
+}
+
+/// Template names are shown in the publish form.
+pub fn parse_template_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_TEMPLATE_NAME_LENGTH {
+        return Err(format!(
+            "The template name must be between 1 and {} characters long.",
+            MAX_TEMPLATE_NAME_LENGTH
+        ));
+    }
+    Ok(name)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveTemplateOutcome {
+    Saved(Uuid),
+    IssueNotFound,
+    NameTaken,
+}
+
+/// Save the structure of `issue_id` as a template named `name`.
+#[tracing::instrument(skip(pool))]
+pub async fn save_issue_as_template(
+    pool: &PgPool,
+    issue_id: Uuid,
+    name: &str,
+) -> Result<SaveTemplateOutcome, sqlx::Error> {
+    let Some(draft) = get_issue_as_draft(pool, issue_id).await? else {
+        return Ok(SaveTemplateOutcome::IssueNotFound);
+    };
+    let template_id = Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO issue_templates (
+            template_id, name, title, text_content, html_content, paid_only, priority, push,
+            created_from, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        template_id,
+        name,
+        draft.title,
+        draft.text_content,
+        draft.html_content,
+        draft.paid_only,
+        draft.priority,
+        draft.push,
+        issue_id
+    )
+    .execute(pool)
+    .observe("insert_issue_template")
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(SaveTemplateOutcome::NameTaken);
+    }
+    Ok(SaveTemplateOutcome::Saved(template_id))
+}
+
+pub struct TemplateSummary {
+    pub template_id: Uuid,
+    pub name: String,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn list_templates(pool: &PgPool) -> Result<Vec<TemplateSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        TemplateSummary,
+        "SELECT template_id, name FROM issue_templates ORDER BY name"
+    )
+    .fetch_all(pool)
+    .observe("list_issue_templates")
+    .await
+}
+
+/// A new draft from `template_id`, without sponsor nor poll.
+#[tracing::instrument(skip(pool))]
+pub async fn get_template(
+    pool: &PgPool,
+    template_id: Uuid,
+) -> Result<Option<IssueDraft>, sqlx::Error> {
+    let template = sqlx::query_as!(
+        TemplateFields,
+        r#"
+        SELECT title, text_content, html_content, paid_only, priority, push
+        FROM issue_templates
+        WHERE template_id = $1
+        "#,
+        template_id
+    )
+    .fetch_optional(pool)
+    .observe("get_issue_template")
+    .await?;
+    Ok(template.map(IssueDraft::from))
+}
+
+/// As [`get_template`], for the templates named in the configuration.
+#[tracing::instrument(skip(executor))]
+pub async fn get_template_by_name<'a, E>(
+    executor: E,
+    name: &str,
+) -> Result<Option<IssueDraft>, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let template = sqlx::query_as!(
+        TemplateFields,
+        r#"
+        SELECT title, text_content, html_content, paid_only, priority, push
+        FROM issue_templates
+        WHERE name = $1
+        "#,
+        name
+    )
+    .fetch_optional(executor)
+    .observe("get_issue_template_by_name")
+    .await?;
+    Ok(template.map(IssueDraft::from))
+}
+
+struct TemplateFields {
+    title: String,
+    text_content: String,
+    html_content: String,
+    paid_only: bool,
+    priority: bool,
+    push: bool,
+}
+
+impl From<TemplateFields> for IssueDraft {
+    fn from(template: TemplateFields) -> Self {
+        Self {
+            title: template.title,
+            text_content: template.text_content,
+            html_content: template.html_content,
+            paid_only: template.paid_only,
+            priority: template.priority,
+            push: template.push,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_template_name;
+
+    #[test]
+    fn template_names_are_trimmed_and_bounded() {
+        assert_eq!(parse_template_name("  Weekly digest "), Ok("Weekly digest"));
+        assert!(parse_template_name("   ").is_err());
+        assert!(parse_template_name(&"a".repeat(65)).is_err());
+    }
+}
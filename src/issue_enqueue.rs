@@ -0,0 +1,193 @@
+//! Fill the delivery queue of a newsletter issue in batches.
+//!
+//! The recipients are frozen in a snapshot when the issue is published.
+//! The queue is then filled from the snapshot one batch per transaction,
+//! keyed on the subscriber email, moving the checkpoint stored on the
+//! issue: a large send never holds one huge transaction, and an enqueue
+//! interrupted by a restart resumes from its checkpoint instead of starting
+//! over. The worker leaves an issue alone until its queue is complete.
+use crate::database::ObserveQuery;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct RecipientSnapshot {
+    pub snapshot_id: Uuid,
+    pub recipient_count: u64,
+}
+
+/// Record who `issue_id` goes to: the subscribers confirmed right now,
+/// restricted to the paid tier for `paid_only` issues. The subscribers on a
+/// digest get it with their next one instead.
+#[tracing::instrument(name = "Take a recipient snapshot", skip(transaction))]
+pub async fn take_recipient_snapshot(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    paid_only: bool,
+) -> Result<RecipientSnapshot, sqlx::Error> {
+    let snapshot_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO recipient_snapshots (snapshot_id, newsletter_issue_id, taken_at, recipient_count)
+        VALUES ($1, $2, $3, 0)
+        "#,
+        snapshot_id,
+        issue_id,
+        Utc::now()
+    )
+    .execute(&mut **transaction)
+    .observe("insert_recipient_snapshot")
+    .await?;
+    let members = sqlx::query!(
+        r#"
+        INSERT INTO recipient_snapshot_members (snapshot_id, subscriber_email)
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+            AND deleted_at IS NULL
+            AND (paid OR NOT $2)
+            AND digest_frequency = 'immediate'
+            AND NOT EXISTS (
+                SELECT 1 FROM suppressed_emails x WHERE lower(x.email) = lower(subscriptions.email)
+            )
+        "#,
+        snapshot_id,
+        paid_only
+    )
+    .execute(&mut **transaction)
+    .observe("insert_recipient_snapshot_members")
+    .await?;
+    let recipient_count = members.rows_affected();
+    let query = sqlx::query!(
+        "UPDATE recipient_snapshots SET recipient_count = $2 WHERE snapshot_id = $1",
+        snapshot_id,
+        recipient_count as i32
+    );
+    transaction
+        .execute(query)
+        .observe("update_recipient_snapshot_count")
+        .await?;
+    Ok(RecipientSnapshot {
+        snapshot_id,
+        recipient_count,
+    })
+}
+
+/// Enqueue the deliveries of `issue_id` from its checkpoint onwards,
+/// returning how many tasks were added.
+#[tracing::instrument(name = "Enqueue delivery tasks", skip(pool))]
+pub async fn enqueue_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let mut enqueued = 0;
+    while let Some(batch) = enqueue_batch(pool, issue_id, batch_size.max(1)).await? {
+        enqueued += batch;
+    }
+    Ok(enqueued)
+}
+
+/// Enqueue one batch, or return `None` once the queue of the issue is
+/// complete.
+async fn enqueue_batch(
+    pool: &PgPool,
+    issue_id: Uuid,
+    batch_size: i64,
+) -> Result<Option<u64>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // The row lock serialises concurrent enqueuers of the same issue: the
+    // next one picks up from the checkpoint this one leaves behind.
+    let Some(issue) = sqlx::query!(
+        r#"
+        SELECT enqueue_cursor, delivery_lane
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND NOT enqueue_completed
+        FOR UPDATE
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("lock_issue_for_enqueue")
+    .await?
+    else {
+        return Ok(None);
+    };
+    // Members who have deleted their subscription since the snapshot are
+    // skipped.
+    let batch = sqlx::query!(
+        r#"
+        WITH batch AS (
+            SELECT m.subscriber_email AS email
+            FROM recipient_snapshots s
+            JOIN recipient_snapshot_members m USING (snapshot_id)
+            WHERE s.newsletter_issue_id = $1
+                AND ($2::TEXT IS NULL OR m.subscriber_email > $2)
+            ORDER BY m.subscriber_email
+            LIMIT $3
+        ), inserted AS (
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, lane)
+            SELECT $1, email, $4
+            FROM batch
+            WHERE EXISTS (
+                SELECT 1 FROM subscriptions
+                WHERE subscriptions.email = batch.email AND deleted_at IS NULL
+            )
+            ON CONFLICT DO NOTHING
+        )
+        SELECT MAX(email) AS last_email, COUNT(*) AS "count!" FROM batch
+        "#,
+        issue_id,
+        issue.enqueue_cursor,
+        batch_size,
+        issue.delivery_lane
+    )
+    .fetch_one(&mut *transaction)
+    .observe_one("enqueue_delivery_batch")
+    .await?;
+    let completed = batch.count < batch_size;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET enqueue_cursor = COALESCE($2, enqueue_cursor), enqueue_completed = $3
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        batch.last_email,
+        completed
+    )
+    .execute(&mut *transaction)
+    .observe("update_enqueue_checkpoint")
+    .await?;
+    transaction.commit().await?;
+    Ok(Some(batch.count as u64))
+}
+
+/// Finish the enqueues that were interrupted, e.g. by a restart while
+/// publishing. Resuming an issue that is still being enqueued is harmless:
+/// both enqueuers take turns on its row lock.
+#[tracing::instrument(name = "Resume interrupted enqueues", skip(pool))]
+pub async fn resume_interrupted_enqueues(
+    pool: &PgPool,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let mut enqueued = 0;
+    loop {
+        let r = sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE NOT enqueue_completed
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(pool)
+        .observe("find_interrupted_enqueue")
+        .await?;
+        let Some(r) = r else {
+            return Ok(enqueued);
+        };
+        enqueued += enqueue_issue(pool, r.newsletter_issue_id, batch_size).await?;
+    }
+}
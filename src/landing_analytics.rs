@@ -0,0 +1,517 @@
+//! What readers do on the operator's website after following a link of an
+//! issue.
+//!
+//! The links of the issues we send to the website carry UTM parameters and
+//! a click token signed for the recipient. The website hands them back to
+//! `/api/analytics/visit` when the reader lands, and again when they
+//! convert, so that the report of the issue counts its downstream visits
+//! and conversions. Subscribers who opted out of tracking get the UTM
+//! parameters only, and their visits are not recorded.
+use crate::configuration::LandingAnalyticsSettings;
+use crate::database::ObserveQuery;
+use crate::token_signer::TokenSigner;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN, VARY};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use sqlx::{Executor, PgPool, Postgres};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const CLICK_PURPOSE: &str = "landing_click";
+/// How long the links of an issue credit the visits they bring.
+const CLICK_TTL_DAYS: i64 = 90;
+/// The query parameter of the click token.
+pub const CLICK_PARAMETER: &str = "z2p_click";
+/// Longer values reported by the website are cut.
+const MAX_FIELD_LENGTH: usize = 500;
+
+/// The tagging of the links to the website in the issues we send, and the
+/// check of the click tokens coming back.
+#[derive(Clone)]
+pub struct LandingLinks {
+    origins: Vec<String>,
+    utm_source: String,
+    signer: Arc<TokenSigner>,
+}
+
+impl LandingLinks {
+    pub fn new(
+        settings: &LandingAnalyticsSettings,
+        signer: Arc<TokenSigner>,
+    ) -> Result<Self, String> {
+        let origins = settings
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                normalize_origin(origin).ok_or_else(|| format!("{} is not a valid origin", origin))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            origins,
+            utm_source: settings.utm_source.clone(),
+            signer,
+        })
+    }
+
+    /// Whether pages served from `origin` may report visits.
+    pub fn allows(&self, origin: &str) -> bool {
+        normalize_origin(origin).is_some_and(|origin| self.origins.contains(&origin))
+    }
+
+    /// The click token of `subscriber_id` for the links of `issue_id`.
+    pub fn click_token(&self, issue_id: Uuid, subscriber_id: Uuid) -> String {
+        self.signer.sign(
+            CLICK_PURPOSE,
+            &format!("{}.{}", issue_id, subscriber_id),
+            Utc::now() + Duration::days(CLICK_TTL_DAYS),
+        )
+    }
+
+    /// The issue and the subscriber `click_token` was signed for.
+    pub fn clicker(&self, click_token: &str) -> Option<(Uuid, Uuid)> {
+        let payload = self.signer.verify(CLICK_PURPOSE, click_token).ok()?;
+        let (issue_id, subscriber_id) = payload.split_once('.')?;
+        Some((issue_id.parse().ok()?, subscriber_id.parse().ok()?))
+    }
+
+    /// `url` with the UTM parameters of `issue_id` and `click_token`, if it
+    /// points to the website. The UTM parameters the link already has are
+    /// kept.
+    fn tag(&self, url: &str, issue_id: Uuid, click_token: Option<&str>) -> Option<String> {
+        let mut url = Url::parse(url).ok()?;
+        if !self.origins.contains(&url.origin().ascii_serialization()) {
+            return None;
+        }
+        let present: Vec<String> = url.query_pairs().map(|(key, _)| key.into_owned()).collect();
+        let campaign = issue_id.to_string();
+        let mut query = url.query_pairs_mut();
+        for (key, value) in [
+            ("utm_source", self.utm_source.as_str()),
+            ("utm_medium", "email"),
+            ("utm_campaign", campaign.as_str()),
+        ] {
+            if !present.iter().any(|p| p == key) {
+                query.append_pair(key, value);
+            }
+        }
+        if let Some(click_token) = click_token {
+            query.append_pair(CLICK_PARAMETER, click_token);
+        }
+        drop(query);
+        Some(url.into())
+    }
+
+    /// The HTML and text content of `issue_id` as sent to `subscriber_id`,
+    /// with the links to the website tagged: without click token when there
+    /// is no subscriber to credit.
+    pub fn render(
+        &self,
+        issue_id: Uuid,
+        subscriber_id: Option<Uuid>,
+        html_content: &str,
+        text_content: &str,
+    ) -> (String, String) {
+        let click_token =
+            subscriber_id.map(|subscriber_id| self.click_token(issue_id, subscriber_id));
+        let tag = |url: &str| self.tag(url, issue_id, click_token.as_deref());
+        (
+            rewrite_hrefs(html_content, tag),
+            rewrite_text_urls(text_content, tag),
+        )
+    }
+}
+
+/// `scheme://host[:port]`, as browsers send it in the `Origin` header.
+fn normalize_origin(origin: &str) -> Option<String> {
+    let origin = Url::parse(origin).ok()?.origin();
+    origin.is_tuple().then(|| origin.ascii_serialization())
+}
+
+/// `html` with the `href` attributes `tag` has a replacement for replaced.
+fn rewrite_hrefs(html: &str, tag: impl Fn(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(index) = rest.find("href=") {
+        let (before, after) = rest.split_at(index + "href=".len());
+        rewritten.push_str(before);
+        rest = after;
+        let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let Some(end) = rest[1..].find(quote) else {
+            continue;
+        };
+        let raw = &rest[1..=end];
+        let url = htmlescape::decode_html(raw).unwrap_or_else(|_| raw.to_owned());
+        match tag(&url) {
+            Some(tagged) => {
+                rewritten.push(quote);
+                rewritten.push_str(&htmlescape::encode_minimal(&tagged));
+                rewritten.push(quote);
+            }
+            None => rewritten.push_str(&rest[..=end + 1]),
+        }
+        rest = &rest[end + 2..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// `text` with the bare URLs `tag` has a replacement for replaced. A URL
+/// runs until the next whitespace, without its trailing punctuation.
+fn rewrite_text_urls(text: &str, tag: impl Fn(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find("http") {
+        let (before, after) = rest.split_at(index);
+        rewritten.push_str(before);
+        let end = after
+            .find(|c: char| c.is_whitespace() || c == '<' || c == '>' || c == '"')
+            .unwrap_or(after.len());
+        let url = after[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+        match tag(url) {
+            Some(tagged) => rewritten.push_str(&tagged),
+            None => rewritten.push_str(url),
+        }
+        rest = &after[url.len()..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// The content of `issue_id` as sent to the subscriber stored as `email`,
+/// with the links to the website tagged for them. Addresses no subscriber
+/// is stored with anymore get the content as is.
+#[tracing::instrument(skip_all)]
+pub async fn render_for_delivery(
+    pool: &PgPool,
+    links: &LandingLinks,
+    issue_id: Uuid,
+    email: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(String, String), sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT id, no_tracking FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .observe("get_landing_subscriber")
+    .await?;
+    Ok(match subscriber {
+        Some(subscriber) => {
+            let clicker = (!subscriber.no_tracking).then_some(subscriber.id);
+            links.render(issue_id, clicker, html_content, text_content)
+        }
+        None => (html_content.to_owned(), text_content.to_owned()),
+    })
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_no_tracking(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query!(
+        "SELECT no_tracking FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .observe_one("get_no_tracking")
+    .await?;
+    Ok(r.no_tracking)
+}
+
+/// Opting out also forgets the visits recorded so far.
+#[tracing::instrument(skip(executor))]
+pub async fn set_no_tracking<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+    no_tracking: bool,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        WITH forgotten AS (
+            DELETE FROM landing_visits WHERE subscriber_id = $1 AND $2
+        )
+        UPDATE subscriptions SET no_tracking = $2 WHERE id = $1
+        "#,
+        subscriber_id,
+        no_tracking
+    )
+    .execute(executor)
+    .observe("set_no_tracking")
+    .await?;
+    Ok(())
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VisitKind {
+    #[default]
+    Visit,
+    Conversion,
+}
+
+impl VisitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VisitKind::Visit => "visit",
+            VisitKind::Conversion => "conversion",
+        }
+    }
+}
+
+/// A visit as reported by the website.
+#[derive(Debug, Default)]
+pub struct LandingVisit {
+    pub kind: VisitKind,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_content: Option<String>,
+    pub utm_term: Option<String>,
+    /// The path of the page visited.
+    pub page: Option<String>,
+}
+
+fn truncate(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(|value| value.chars().take(MAX_FIELD_LENGTH).collect())
+}
+
+/// Credit `visit` to `issue_id`. Returns whether it was recorded: the
+/// subscriber or the issue may have been deleted since the click, or the
+/// subscriber opted out of tracking.
+#[tracing::instrument(skip(pool, visit), fields(kind = visit.kind.as_str()))]
+pub async fn record_visit(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    visit: &LandingVisit,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO landing_visits (
+            visit_id, newsletter_issue_id, subscriber_id, kind, utm_source, utm_medium,
+            utm_campaign, utm_content, utm_term, page, visited_at
+        )
+        SELECT $1, i.newsletter_issue_id, s.id, $4, $5, $6, $7, $8, $9, $10, $11
+        FROM newsletter_issues i, subscriptions s
+        WHERE i.newsletter_issue_id = $2 AND s.id = $3 AND NOT s.no_tracking
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        subscriber_id,
+        visit.kind.as_str(),
+        truncate(&visit.utm_source),
+        truncate(&visit.utm_medium),
+        truncate(&visit.utm_campaign),
+        truncate(&visit.utm_content),
+        truncate(&visit.utm_term),
+        truncate(&visit.page),
+        now
+    )
+    .execute(pool)
+    .observe("record_landing_visit")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CampaignVisits {
+    pub utm_campaign: Option<String>,
+    pub utm_content: Option<String>,
+    pub visits: i64,
+    pub conversions: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LandingReport {
+    pub visits: i64,
+    /// The subscribers who visited at least once.
+    pub visitors: i64,
+    pub conversions: i64,
+    /// The subscribers who converted at least once.
+    pub converted_visitors: i64,
+    /// Most visits first.
+    pub campaigns: Vec<CampaignVisits>,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_landing_report(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<LandingReport, sqlx::Error> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE kind = 'visit') AS "visits!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'visit') AS "visitors!",
+            COUNT(*) FILTER (WHERE kind = 'conversion') AS "conversions!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'conversion')
+                AS "converted_visitors!"
+        FROM landing_visits
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .observe_one("get_landing_totals")
+    .await?;
+    let campaigns = sqlx::query_as!(
+        CampaignVisits,
+        r#"
+        SELECT
+            utm_campaign,
+            utm_content,
+            COUNT(*) FILTER (WHERE kind = 'visit') AS "visits!",
+            COUNT(*) FILTER (WHERE kind = 'conversion') AS "conversions!"
+        FROM landing_visits
+        WHERE newsletter_issue_id = $1
+        GROUP BY utm_campaign, utm_content
+        ORDER BY 3 DESC, 4 DESC, utm_campaign, utm_content
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .observe("get_landing_campaigns")
+    .await?;
+    Ok(LandingReport {
+        visits: totals.visits,
+        visitors: totals.visitors,
+        conversions: totals.conversions,
+        converted_visitors: totals.converted_visitors,
+        campaigns,
+    })
+}
+
+/// Only let pages served from the website through to `/api/analytics`, and
+/// let their scripts read the responses. Callers without an `Origin`, such
+/// as the website's backend, are let through: the click token is what
+/// proves a visit.
+pub async fn allow_landing_origins(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(links) = req.app_data::<web::Data<LandingLinks>>() else {
+        return Err(actix_web::error::ErrorNotFound(
+            "Landing analytics are not enabled",
+        ));
+    };
+    let origin = req.headers().get(ORIGIN).cloned();
+    if let Some(origin) = &origin {
+        if !origin.to_str().is_ok_and(|origin| links.allows(origin)) {
+            return Err(actix_web::error::ErrorForbidden(
+                "The origin is not allowed",
+            ));
+        }
+    }
+    let mut response = next.call(req).await?;
+    if let Some(origin) = origin {
+        let headers = response.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(VARY, HeaderValue::from_static("Origin"));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::TokenSigningSettings;
+    use secrecy::Secret;
+
+    fn links() -> LandingLinks {
+        let signer = TokenSigner::new(&TokenSigningSettings {
+            current_key: "k1".into(),
+            keys: [("k1".to_string(), Secret::new("k".repeat(32)))].into(),
+        })
+        .unwrap();
+        LandingLinks::new(
+            &LandingAnalyticsSettings {
+                allowed_origins: vec!["https://example.com/".into()],
+                utm_source: "newsletter".into(),
+            },
+            Arc::new(signer),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn origins_are_compared_as_browsers_send_them() {
+        let links = links();
+        assert!(links.allows("https://example.com"));
+        assert!(links.allows("https://example.com:443"));
+        assert!(!links.allows("http://example.com"));
+        assert!(!links.allows("https://example.com.evil.com"));
+        assert!(!links.allows("null"));
+    }
+
+    #[test]
+    fn only_the_links_to_the_website_are_tagged() {
+        let links = links();
+        let (issue_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let html = r#"<a href="https://example.com/pricing">Pricing</a> <a href="https://elsewhere.com/">Elsewhere</a>"#;
+        let text = "Pricing: https://example.com/pricing. Elsewhere: https://elsewhere.com/";
+
+        let (html, text) = links.render(issue_id, Some(subscriber_id), html, text);
+
+        let prefix = format!(
+            "https://example.com/pricing?utm_source=newsletter&amp;utm_medium=email&amp;utm_campaign={}&amp;z2p_click=",
+            issue_id
+        );
+        assert!(html.contains(&format!(r#"<a href="{}"#, prefix)));
+        assert!(html.contains(r#"<a href="https://elsewhere.com/">"#));
+        let tagged = text
+            .split_whitespace()
+            .find(|word| word.starts_with("https://example.com"))
+            .unwrap()
+            .trim_end_matches('.');
+        assert!(text.contains("Elsewhere: https://elsewhere.com/"));
+        let url = Url::parse(tagged).unwrap();
+        let token = url
+            .query_pairs()
+            .find(|(key, _)| key == CLICK_PARAMETER)
+            .unwrap()
+            .1;
+        assert_eq!(links.clicker(&token), Some((issue_id, subscriber_id)));
+    }
+
+    #[test]
+    fn the_utm_parameters_of_a_link_are_kept() {
+        let links = links();
+        let tagged = links
+            .tag(
+                "https://example.com/?utm_campaign=spring&ref=1",
+                Uuid::nil(),
+                Some("token"),
+            )
+            .unwrap();
+        assert_eq!(
+            tagged,
+            "https://example.com/?utm_campaign=spring&ref=1&utm_source=newsletter&utm_medium=email&z2p_click=token"
+        );
+    }
+
+    #[test]
+    fn links_carry_no_click_token_without_a_subscriber() {
+        let links = links();
+        let (html, text) = links.render(
+            Uuid::nil(),
+            None,
+            r#"<a href="https://example.com/">Home</a>"#,
+            "Home: https://example.com/",
+        );
+        assert!(html.contains("utm_source=newsletter"));
+        assert!(!html.contains(CLICK_PARAMETER));
+        assert!(text.contains("utm_source=newsletter"));
+        assert!(!text.contains(CLICK_PARAMETER));
+    }
+}
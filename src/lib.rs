@@ -1,9 +1,74 @@
+pub mod admin_audit;
+pub mod admin_events;
+pub mod admin_sessions;
+pub mod api_keys;
 pub mod authentication;
+pub mod billing;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod circuit_breaker;
 pub mod configuration;
+pub mod consent;
+pub mod cost_ledger;
+pub mod database;
+pub mod deliverability;
+pub mod delivery_events;
+pub mod delivery_status;
+pub mod digests;
+pub mod doctor;
 pub mod domain;
+pub mod domain_throttle;
 pub mod email_client;
+pub mod email_verification;
+pub mod events;
+pub mod fake_data;
+pub mod http_cache;
+pub mod i18n;
+pub mod idempotency;
+pub mod image_proxy;
+pub mod issue_delivery_worker;
+pub mod issue_drafts;
+pub mod issue_enqueue;
+pub mod landing_analytics;
+pub mod link_checker;
+pub mod links;
+pub mod listener;
+pub mod load_shedding;
+pub mod log_scrubbing;
+pub mod magic_link;
+pub mod metrics;
+pub mod notifier;
+pub mod oidc;
+pub mod operations;
+pub mod outbound;
+pub mod pii;
+pub mod polls;
+pub mod quarantine;
+pub mod queues;
+pub mod referrals;
+pub mod reload;
+pub mod request_tracing;
+pub mod retention;
 pub mod routes;
+pub mod rss;
+pub mod runtime;
+pub mod seed_list;
+pub mod send_quota;
 pub mod session_state;
+pub mod signup_protection;
+pub mod slo;
+pub mod sms;
+pub mod snippets;
+pub mod spam_check;
+pub mod sponsorship;
 pub mod startup;
+pub mod subscribers;
+pub mod telegram;
 pub mod telemetry;
+pub mod theme;
+pub mod token_signer;
 pub mod utils;
+pub mod verified_subscriber;
+pub mod warm_up;
+pub mod web_push;
+pub mod web_version;
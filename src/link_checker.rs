@@ -0,0 +1,161 @@
+use crate::configuration::LinkCheckerSettings;
+use crate::startup::StartupError;
+use reqwest::{Client, Method, StatusCode};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+#[derive(Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LinkStatus {
+    Ok,
+    Redirected {
+        code: u16,
+        location: Option<String>,
+    },
+    Broken {
+        code: Option<u16>,
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LinkReport {
+    pub url: String,
+    #[serde(flatten)]
+    pub status: LinkStatus,
+}
+
+/// Every distinct `http(s)` URL referenced by an `href` or `src` attribute.
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links = BTreeSet::new();
+    for attribute in ["href=", "src="] {
+        for (index, _) in html.match_indices(attribute) {
+            let rest = &html[index + attribute.len()..];
+            let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                continue;
+            };
+            let Some(end) = rest[1..].find(quote) else {
+                continue;
+            };
+            let url = htmlescape::decode_html(&rest[1..=end])
+                .unwrap_or_else(|_| rest[1..=end].to_owned());
+            if url.starts_with("http://") || url.starts_with("https://") {
+                links.insert(url);
+            }
+        }
+    }
+    links.into_iter().collect()
+}
+
+#[derive(Clone)]
+pub struct LinkChecker {
+    http_client: Client,
+    max_concurrency: usize,
+}
+
+impl LinkChecker {
+    pub fn new(settings: &LinkCheckerSettings) -> Result<Self, StartupError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            // Redirects are reported rather than followed.
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| StartupError::HttpClient("link checker", e))?;
+        Ok(Self {
+            http_client,
+            max_concurrency: settings.max_concurrency,
+        })
+    }
+
+    /// Check `urls` concurrently, returning the reports in the same order.
+    #[tracing::instrument(name = "Check links", skip_all, fields(links = urls.len()))]
+    pub async fn check(&self, urls: Vec<String>) -> Vec<LinkReport> {
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for (index, url) in urls.into_iter().enumerate() {
+            let checker = self.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                let status = checker.check_link(&url).await;
+                (index, LinkReport { url, status })
+            });
+        }
+        let mut reports = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            reports.push(result.expect("A link check panicked"));
+        }
+        reports.sort_by_key(|(index, _)| *index);
+        reports.into_iter().map(|(_, report)| report).collect()
+    }
+
+    /// Try a cheap HEAD first: some servers reject or mishandle it, so any
+    /// failure is retried with a GET before calling the link broken.
+    async fn check_link(&self, url: &str) -> LinkStatus {
+        match self.request(Method::HEAD, url).await {
+            status @ (LinkStatus::Ok | LinkStatus::Redirected { .. }) => status,
+            LinkStatus::Broken { .. } => self.request(Method::GET, url).await,
+        }
+    }
+
+    async fn request(&self, method: Method, url: &str) -> LinkStatus {
+        match self.http_client.request(method, url).send().await {
+            Ok(response) => classify(
+                response.status(),
+                response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|l| l.to_str().ok()),
+            ),
+            Err(e) => LinkStatus::Broken {
+                code: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+fn classify(status: StatusCode, location: Option<&str>) -> LinkStatus {
+    if status.is_success() {
+        LinkStatus::Ok
+    } else if status.is_redirection() {
+        LinkStatus::Redirected {
+            code: status.as_u16(),
+            location: location.map(str::to_owned),
+        }
+    } else {
+        LinkStatus::Broken {
+            code: Some(status.as_u16()),
+            error: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_links;
+
+    #[test]
+    fn links_are_extracted_from_href_and_src_attributes() {
+        let html = r#"<a href="https://example.com/a?x=1&amp;y=2">a</a>
+            <img src='https://example.com/logo.png'>
+            <a href="https://example.com/a?x=1&amp;y=2">again</a>"#;
+        assert_eq!(
+            extract_links(html),
+            vec![
+                "https://example.com/a?x=1&y=2".to_string(),
+                "https://example.com/logo.png".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_and_non_http_links_are_ignored() {
+        let html =
+            r#"<a href="/archive">a</a> <a href="mailto:me@example.com">b</a> <a href=bare>c</a>"#;
+        assert!(extract_links(html).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+//! The URLs we hand out: in emails, to identity providers, in API payloads.
+//!
+//! Building them here, rather than with `format!` at each call site, keeps
+//! the joining with the base URL, the percent-encoding of the query and the
+//! signing of the links that need it in one place.
+use crate::image_proxy::sign_image_url;
+use secrecy::Secret;
+use std::fmt;
+use uuid::Uuid;
+
+/// A path on the application and its query string.
+///
+/// `Display` renders it relative to the root: use [`Link::absolute`] when it
+/// leaves the browser, e.g. in an email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    path: String,
+    query: Vec<(&'static str, String)>,
+}
+
+impl Link {
+    fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            query: Vec::new(),
+        }
+    }
+
+    fn query(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.query.push((key, value.to_string()));
+        self
+    }
+
+    /// The link prefixed with `base_url`, with or without a trailing `/`.
+    pub fn absolute(&self, base_url: &str) -> String {
+        format!("{}{}", base_url.trim_end_matches('/'), self)
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)?;
+        for (i, (key, value)) in self.query.iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            write!(f, "{}{}={}", separator, key, urlencoding::encode(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a new subscriber confirms their subscription.
+pub fn subscription_confirmation(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/confirm").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber redeems a magic link to read `issue_id` in the archive.
+pub fn archive_login(token: &str, issue_id: Uuid) -> Link {
+    Link::new("/archive/login/confirm")
+        .query("token", token)
+        .query("issue_id", issue_id)
+}
+
+/// Where a subscriber sees what happened to the recent issues.
+pub fn delivery_status(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/deliveries").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber asks for the latest issue again.
+pub fn resend_latest_issue(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/deliveries/resend").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber chooses between each issue and a digest.
+pub fn subscriber_preferences(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/preferences").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber registers a browser for push notifications.
+pub fn push_subscriptions(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/push").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber opts in to announcements by text message, or out.
+pub fn sms_preferences(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/sms").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber gives the phone number to text them at.
+pub fn sms_phone(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/sms/phone").query("subscription_token", subscription_token)
+}
+
+/// Where a subscriber types back the code texted to their number.
+pub fn sms_phone_verification(subscription_token: &str) -> Link {
+    Link::new("/subscriptions/sms/phone/verify").query("subscription_token", subscription_token)
+}
+
+/// The share link of a subscriber, to a signup form crediting them.
+pub fn referral(referral_code: &str) -> Link {
+    Link::new(format!("/r/{}", referral_code))
+}
+
+/// Where an admin redeems a magic link to log in.
+pub fn admin_login(token: &str) -> Link {
+    Link::new("/login/magic-link/confirm").query("token", token)
+}
+
+/// The archive page of `issue_id`. A `preview_token` opens it to whoever
+/// holds the link, even when it is reserved to paid subscribers.
+pub fn issue_web_version(issue_id: Uuid, preview_token: Option<&str>) -> Link {
+    let link = Link::new(format!("/archive/{}", issue_id));
+    match preview_token {
+        Some(token) => link.query("preview", token),
+        None => link,
+    }
+}
+
+/// The tracked link of a sponsor slot, redirecting to the sponsor.
+pub fn sponsor_click(slot_id: Uuid) -> Link {
+    Link::new(format!("/sponsors/{}/click", slot_id))
+}
+
+/// The pixel counting the impressions of a sponsor slot.
+pub fn sponsor_impression(slot_id: Uuid) -> Link {
+    Link::new(format!("/sponsors/{}/impression.gif", slot_id))
+}
+
+/// The link voting for `option` of a poll, for the subscriber `delivery`
+/// was signed for.
+pub fn poll_vote(poll_id: Uuid, option: usize, delivery: &str) -> Link {
+    Link::new(format!("/p/{}/{}", poll_id, option)).query("d", delivery)
+}
+
+/// The live results of a poll.
+pub fn poll_results(poll_id: Uuid) -> Link {
+    Link::new(format!("/p/{}", poll_id))
+}
+
+/// Where the OpenID Connect provider sends the admin back after logging in.
+pub fn oidc_callback() -> Link {
+    Link::new("/login/oidc/callback")
+}
+
+/// The external image at `url`, served through the image proxy. The link is
+/// signed with `secret`: the proxy refuses the URLs we did not sign.
+pub fn proxied_image(secret: &Secret<String>, url: &str) -> Link {
+    Link::new("/archive/images")
+        .query("url", url)
+        .query("signature", sign_image_url(secret, url))
+}
+
+/// The SCIM resource of the admin `user_id`.
+pub fn scim_user(user_id: Uuid) -> Link {
+    Link::new(format!("/scim/v2/Users/{}", user_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_proxy::verify_image_url;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-secret".into())
+    }
+
+    #[test]
+    fn links_are_joined_to_the_base_url_with_a_single_slash() {
+        let link = admin_login("abc");
+        for base_url in ["http://localhost:8000", "http://localhost:8000/"] {
+            assert_eq!(
+                link.absolute(base_url),
+                "http://localhost:8000/login/magic-link/confirm?token=abc"
+            );
+        }
+    }
+
+    #[test]
+    fn links_without_a_query_have_no_question_mark() {
+        assert_eq!(
+            oidc_callback().absolute("https://example.com"),
+            "https://example.com/login/oidc/callback"
+        );
+    }
+
+    #[test]
+    fn query_values_are_percent_encoded() {
+        assert_eq!(
+            subscription_confirmation("a b&c=d/é").to_string(),
+            "/subscriptions/confirm?subscription_token=a%20b%26c%3Dd%2F%C3%A9"
+        );
+    }
+
+    #[test]
+    fn the_archive_login_link_carries_the_token_and_the_issue() {
+        let issue_id = Uuid::nil();
+        assert_eq!(
+            archive_login("abc", issue_id).absolute("https://example.com"),
+            format!(
+                "https://example.com/archive/login/confirm?token=abc&issue_id={}",
+                issue_id
+            )
+        );
+    }
+
+    #[test]
+    fn the_web_version_carries_the_preview_token_if_any() {
+        let issue_id = Uuid::nil();
+        assert_eq!(
+            issue_web_version(issue_id, None).to_string(),
+            format!("/archive/{}", issue_id)
+        );
+        assert_eq!(
+            issue_web_version(issue_id, Some("k1.1.e30.sig")).to_string(),
+            format!("/archive/{}?preview=k1.1.e30.sig", issue_id)
+        );
+    }
+
+    #[test]
+    fn proxied_image_links_carry_a_valid_signature() {
+        let url = "https://example.com/a.png?x=1&y=2";
+        let link = proxied_image(&secret(), url);
+        assert_eq!(
+            link.to_string(),
+            format!(
+                "/archive/images?url={}&signature={}",
+                urlencoding::encode(url),
+                sign_image_url(&secret(), url)
+            )
+        );
+        let (_, signature) = link.query[1].clone();
+        assert!(verify_image_url(&secret(), url, &signature));
+        assert!(!verify_image_url(
+            &Secret::new("another-secret".into()),
+            url,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn the_scim_location_is_the_user_resource() {
+        let user_id = Uuid::nil();
+        assert_eq!(
+            scim_user(user_id).absolute("https://example.com/"),
+            format!("https://example.com/scim/v2/Users/{}", user_id)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Where the HTTP server accepts connections: a TCP port, a Unix socket, or
+//! a socket inherited from systemd socket activation.
+use crate::configuration::{ApplicationSettings, ListenerSettings};
+use crate::startup::StartupError;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+/// The first file descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn bind(settings: &ApplicationSettings) -> Result<Self, StartupError> {
+        match &settings.listener {
+            ListenerSettings::Tcp => {
+                let address = format!("{}:{}", settings.host, settings.port);
+                TcpListener::bind(&address)
+                    .map(Self::Tcp)
+                    .map_err(|source| StartupError::Listen { address, source })
+            }
+            ListenerSettings::Unix { path } => bind_unix(path),
+            ListenerSettings::Systemd => {
+                let fd = systemd_listen_fd(
+                    std::env::var("LISTEN_PID").ok().as_deref(),
+                    std::env::var("LISTEN_FDS").ok().as_deref(),
+                    std::process::id(),
+                )
+                .map_err(StartupError::SocketActivation)?;
+                // SAFETY: systemd hands us ownership of this descriptor, and
+                // nothing else in the process claims it.
+                Ok(Self::from_inherited_fd(unsafe {
+                    TcpListener::from_raw_fd(fd)
+                }))
+            }
+        }
+    }
+
+    /// systemd passes TCP and Unix sockets alike: only TCP sockets have an
+    /// IP address.
+    fn from_inherited_fd(listener: TcpListener) -> Self {
+        if listener.local_addr().is_ok() {
+            Self::Tcp(listener)
+        } else {
+            // SAFETY: ownership moves from one listener to the other.
+            Self::Unix(unsafe { UnixListener::from_raw_fd(listener.into_raw_fd()) })
+        }
+    }
+
+    /// The TCP port we are listening on, if any.
+    pub fn port(&self) -> Option<u16> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().ok().map(|address| address.port()),
+            Self::Unix(_) => None,
+        }
+    }
+
+    /// For logs and error messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => listener
+                .local_addr()
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+            Self::Unix(listener) => listener
+                .local_addr()
+                .ok()
+                .and_then(|address| address.as_pathname().map(|p| p.display().to_string()))
+                .unwrap_or_else(|| "an unnamed Unix socket".into()),
+        }
+    }
+}
+
+fn bind_unix(path: &Path) -> Result<Listener, StartupError> {
+    let listen_error = |source| StartupError::Listen {
+        address: path.display().to_string(),
+        source,
+    };
+    // A socket left behind by a previous run would make the bind fail.
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path).map_err(listen_error)?;
+    }
+    UnixListener::bind(path)
+        .map(Listener::Unix)
+        .map_err(listen_error)
+}
+
+/// The descriptor to listen on, following `sd_listen_fds(3)`.
+fn systemd_listen_fd(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Result<RawFd, String> {
+    let listen_fds = listen_fds.ok_or("LISTEN_FDS is not set")?;
+    if listen_pid.and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        return Err("LISTEN_PID does not match our process id".into());
+    }
+    match listen_fds.parse::<u32>() {
+        Ok(1) => Ok(SD_LISTEN_FDS_START),
+        _ => Err(format!(
+            "expected exactly one socket, got LISTEN_FDS={}",
+            listen_fds
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{systemd_listen_fd, Listener};
+    use claims::{assert_err, assert_ok_eq};
+    use std::net::TcpListener;
+
+    #[test]
+    fn the_socket_passed_by_systemd_is_used() {
+        assert_ok_eq!(systemd_listen_fd(Some("42"), Some("1"), 42), 3);
+    }
+
+    #[test]
+    fn sockets_meant_for_another_process_are_ignored() {
+        assert_err!(systemd_listen_fd(Some("41"), Some("1"), 42));
+        assert_err!(systemd_listen_fd(None, Some("1"), 42));
+        assert_err!(systemd_listen_fd(Some("42"), None, 42));
+    }
+
+    #[test]
+    fn exactly_one_socket_is_expected() {
+        assert_err!(systemd_listen_fd(Some("42"), Some("2"), 42));
+        assert_err!(systemd_listen_fd(Some("42"), Some("0"), 42));
+    }
+
+    #[test]
+    fn inherited_tcp_and_unix_sockets_are_told_apart() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(matches!(Listener::from_inherited_fd(tcp), Listener::Tcp(_)));
+
+        let path = std::env::temp_dir().join(format!("zero2prod-{}.sock", uuid::Uuid::new_v4()));
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let unix = TcpListener::from(std::os::fd::OwnedFd::from(unix));
+        assert!(matches!(
+            Listener::from_inherited_fd(unix),
+            Listener::Unix(_)
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+//! Turn away low-priority traffic quickly when we are overloaded, so that
+//! subscribing and confirming keep working.
+use crate::configuration::LoadSheddingSettings;
+use crate::metrics::{HTTP_REQUESTS_IN_FLIGHT, SHED_REQUESTS};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::{web, HttpResponse};
+use actix_web_lab::middleware::Next;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often each worker measures how late its event loop runs.
+const LAG_PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    /// Actix runs one event loop per worker thread: each worker probes its
+    /// own, and sheds according to its own lag.
+    static EVENT_LOOP_LAG: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Overload {
+    InFlight,
+    EventLoopLag,
+}
+
+impl Overload {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Overload::InFlight => "in_flight",
+            Overload::EventLoopLag => "event_loop_lag",
+        }
+    }
+}
+
+pub struct LoadShedder {
+    in_flight: AtomicUsize,
+    max_in_flight: usize,
+    max_event_loop_lag: Duration,
+    low_priority_routes: Vec<String>,
+}
+
+impl LoadShedder {
+    pub fn new(settings: &LoadSheddingSettings) -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: settings.max_in_flight_requests,
+            max_event_loop_lag: Duration::from_millis(settings.max_event_loop_lag_milliseconds),
+            low_priority_routes: settings.low_priority_routes.clone(),
+        }
+    }
+
+    /// `path` is low priority when it is one of the configured routes or
+    /// lives below one of them.
+    pub fn is_low_priority(&self, path: &str) -> bool {
+        self.low_priority_routes.iter().any(|route| {
+            let prefix = route.trim_end_matches('/');
+            path == prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    /// Whether a request arriving while `in_flight` requests (itself
+    /// included) are being served, on an event loop running `lag` late, finds
+    /// us overloaded.
+    pub fn overload(&self, in_flight: usize, lag: Duration) -> Option<Overload> {
+        if in_flight > self.max_in_flight {
+            Some(Overload::InFlight)
+        } else if lag > self.max_event_loop_lag {
+            Some(Overload::EventLoopLag)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decrements the in-flight count however the request ends.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn start(counter: &'a AtomicUsize) -> (Self, usize) {
+        HTTP_REQUESTS_IN_FLIGHT.inc();
+        (Self(counter), counter.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        HTTP_REQUESTS_IN_FLIGHT.dec();
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The lag of the current worker's event loop, starting its probe on the
+/// first call.
+fn event_loop_lag() -> Duration {
+    EVENT_LOOP_LAG.with(|lag| match lag.get() {
+        Some(lag) => lag,
+        None => {
+            lag.set(Some(Duration::ZERO));
+            actix_web::rt::spawn(probe_event_loop_lag());
+            Duration::ZERO
+        }
+    })
+}
+
+async fn probe_event_loop_lag() {
+    loop {
+        let started = Instant::now();
+        actix_web::rt::time::sleep(LAG_PROBE_INTERVAL).await;
+        let lag = started.elapsed().saturating_sub(LAG_PROBE_INTERVAL);
+        EVENT_LOOP_LAG.with(|cell| cell.set(Some(lag)));
+    }
+}
+
+pub async fn shed_load(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(shedder) = req.app_data::<web::Data<LoadShedder>>().cloned() else {
+        return next.call(req).await;
+    };
+    let (_in_flight, count) = InFlight::start(&shedder.in_flight);
+    if shedder.is_low_priority(req.path()) {
+        if let Some(overload) = shedder.overload(count, event_loop_lag()) {
+            SHED_REQUESTS.with_label_values(&[overload.as_str()]).inc();
+            let response = HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", "1"))
+                .finish();
+            let e = anyhow::anyhow!("Shedding a low-priority request: {}", overload.as_str());
+            return Err(InternalError::from_response(e, response).into());
+        }
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoadShedder, Overload};
+    use crate::configuration::LoadSheddingSettings;
+    use std::time::Duration;
+
+    fn shedder() -> LoadShedder {
+        LoadShedder::new(&LoadSheddingSettings {
+            max_in_flight_requests: 10,
+            max_event_loop_lag_milliseconds: 200,
+            low_priority_routes: vec!["/archive".into(), "/widget/".into()],
+        })
+    }
+
+    #[test]
+    fn low_priority_routes_match_whole_path_segments() {
+        let shedder = shedder();
+        assert!(shedder.is_low_priority("/archive"));
+        assert!(shedder.is_low_priority("/archive/images"));
+        assert!(shedder.is_low_priority("/widget/embed.js"));
+        assert!(!shedder.is_low_priority("/archived"));
+        assert!(!shedder.is_low_priority("/subscriptions"));
+    }
+
+    #[test]
+    fn either_threshold_means_overload() {
+        let shedder = shedder();
+        assert_eq!(shedder.overload(10, Duration::from_millis(200)), None);
+        assert_eq!(
+            shedder.overload(11, Duration::ZERO),
+            Some(Overload::InFlight)
+        );
+        assert_eq!(
+            shedder.overload(1, Duration::from_millis(201)),
+            Some(Overload::EventLoopLag)
+        );
+    }
+}
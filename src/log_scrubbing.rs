@@ -0,0 +1,241 @@
+//! Keep personal data out of the logs.
+//!
+//! Every log line goes through a `LogScrubber` on its way to the sink:
+//! email addresses, the configured patterns and the whole value of the
+//! configured fields are replaced with a keyed hash. A value always gets the
+//! same pseudonym, so the lines about one subscriber can still be followed
+//! without carrying their address.
+use crate::configuration::LogScrubbingSettings;
+use hmac::{Hmac, Mac};
+use regex::{Captures, Regex};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::io::Write;
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(\.[A-Za-z0-9-]+)+";
+
+/// The fields the Bunyan formatter adds to every line, never scrubbed as a
+/// whole.
+const BUNYAN_FIELDS: [&str; 10] = [
+    "v", "name", "msg", "level", "hostname", "pid", "time", "target", "line", "file",
+];
+
+struct Scrubbing {
+    fields: Vec<String>,
+    patterns: Vec<Regex>,
+    key: Secret<String>,
+}
+
+/// Scrubs log lines, or lets them through when scrubbing is disabled.
+#[derive(Clone, Default)]
+pub struct LogScrubber(Option<Arc<Scrubbing>>);
+
+impl LogScrubber {
+    /// The pseudonyms are keyed with `key`, so that they cannot be matched
+    /// against the hashes of guessed addresses.
+    pub fn new(settings: &LogScrubbingSettings, key: &Secret<String>) -> Result<Self, String> {
+        if !settings.enabled {
+            return Ok(Self(None));
+        }
+        let patterns = std::iter::once(EMAIL_PATTERN)
+            .chain(settings.patterns.iter().map(String::as_str))
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|e| format!("invalid pattern {:?}: {}", pattern, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self(Some(Arc::new(Scrubbing {
+            fields: settings.fields.clone(),
+            patterns,
+            key: key.clone(),
+        }))))
+    }
+
+    /// Scrub the lines written to the sinks of `make_writer`.
+    pub fn wrap<M>(&self, make_writer: M) -> ScrubbingMakeWriter<M> {
+        ScrubbingMakeWriter {
+            inner: make_writer,
+            scrubber: self.clone(),
+        }
+    }
+
+    pub fn scrub(&self, line: &str) -> String {
+        let Some(scrubbing) = &self.0 else {
+            return line.to_owned();
+        };
+        let mut line = line.to_owned();
+        if !scrubbing.fields.is_empty() {
+            // The line is edited in place rather than serialised again, to
+            // keep the order of its fields.
+            if let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(&line) {
+                for (key, value) in fields {
+                    if value.is_null()
+                        || BUNYAN_FIELDS.contains(&key.as_str())
+                        || !scrubbing.fields.contains(&key)
+                    {
+                        continue;
+                    }
+                    let key = serde_json::Value::String(key);
+                    let pseudonym = match &value {
+                        serde_json::Value::String(value) => self.pseudonym(value),
+                        value => self.pseudonym(&value.to_string()),
+                    };
+                    line = line.replace(
+                        &format!("{}:{}", key, value),
+                        &format!("{}:\"{}\"", key, pseudonym),
+                    );
+                }
+            }
+        }
+        for pattern in &scrubbing.patterns {
+            line = pattern
+                .replace_all(&line, |captures: &Captures| self.pseudonym(&captures[0]))
+                .into_owned();
+        }
+        line
+    }
+
+    fn pseudonym(&self, value: &str) -> String {
+        let Some(scrubbing) = &self.0 else {
+            return value.to_owned();
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(scrubbing.key.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.trim().to_lowercase().as_bytes());
+        let hash = hex::encode(mac.finalize().into_bytes());
+        format!("redacted:{}", &hash[..12])
+    }
+}
+
+pub struct ScrubbingMakeWriter<M> {
+    inner: M,
+    scrubber: LogScrubber,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for ScrubbingMakeWriter<M> {
+    type Writer = ScrubbingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        ScrubbingWriter {
+            inner: self.inner.make_writer(),
+            scrubber: self.scrubber.clone(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// Holds what is written back until a line is complete, and scrubs it.
+pub struct ScrubbingWriter<W: Write> {
+    inner: W,
+    scrubber: LogScrubber,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> ScrubbingWriter<W> {
+    fn write_scrubbed(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let line = self.scrubber.scrub(&String::from_utf8_lossy(line));
+        self.inner.write_all(line.as_bytes())
+    }
+}
+
+impl<W: Write> Write for ScrubbingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.scrubber.0.is_none() {
+            return self.inner.write(buf);
+        }
+        self.buffer.extend_from_slice(buf);
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.write_scrubbed(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ScrubbingWriter<W> {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let rest = std::mem::take(&mut self.buffer);
+            let _ = self.write_scrubbed(&rest);
+            let _ = self.inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogScrubber;
+    use crate::configuration::LogScrubbingSettings;
+    use secrecy::Secret;
+    use std::io::Write;
+
+    fn scrubber(fields: &[&str], patterns: &[&str]) -> LogScrubber {
+        LogScrubber::new(
+            &LogScrubbingSettings {
+                enabled: true,
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+                patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            },
+            &Secret::new("secret".into()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn emails_get_the_same_pseudonym_wherever_they_appear() {
+        let scrubber = scrubber(&[], &[]);
+        let line = scrubber
+            .scrub(r#"{"msg":"Sent to Ursula@gmail.com","subscriber_email":"ursula@gmail.com"}"#);
+        assert!(!line.contains("gmail.com"));
+        let pseudonym = scrubber.pseudonym("ursula@gmail.com");
+        assert_eq!(
+            line,
+            format!(
+                r#"{{"msg":"Sent to {0}","subscriber_email":"{0}"}}"#,
+                pseudonym
+            )
+        );
+        assert_ne!(pseudonym, scrubber.pseudonym("octavia@gmail.com"));
+    }
+
+    #[test]
+    fn configured_fields_and_patterns_are_scrubbed() {
+        let scrubber = scrubber(&["subscriber_name", "name"], &[r"\+44\d{10}"]);
+        let line = scrubber.scrub(
+            r#"{"name":"zero2prod","subscriber_name":"Ursula Le Guin","msg":"Call +441234567890"}"#,
+        );
+        assert!(line.starts_with(r#"{"name":"zero2prod","subscriber_name":"redacted:"#));
+        assert!(!line.contains("Le Guin"));
+        assert!(!line.contains("+44"));
+    }
+
+    #[test]
+    fn lines_are_scrubbed_once_complete() {
+        let scrubber = scrubber(&[], &[]);
+        let mut sink = Vec::new();
+        {
+            let mut writer = super::ScrubbingWriter {
+                inner: &mut sink,
+                scrubber: scrubber.clone(),
+                buffer: Vec::new(),
+            };
+            writer.write_all(b"{\"msg\":\"ursula@").unwrap();
+            writer
+                .write_all(b"gmail.com\"}\n{\"msg\":\"done\"}")
+                .unwrap();
+        }
+        let written = String::from_utf8(sink).unwrap();
+        assert_eq!(
+            written,
+            format!(
+                "{{\"msg\":\"{}\"}}\n{{\"msg\":\"done\"}}",
+                scrubber.pseudonym("ursula@gmail.com")
+            )
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Single-use, short-lived login links sent by email.
+use crate::database::ObserveQuery;
+use crate::routes::generate_subscription_token;
+use chrono::{Duration, Utc};
+use sha3::{Digest, Sha3_256};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+/// What a magic link logs into. A link issued for one purpose can't be
+/// redeemed for another.
+#[derive(Copy, Clone, Debug)]
+pub enum MagicLinkPurpose {
+    /// A subscriber session to read paid-only issues in the archive.
+    Archive,
+    /// An admin session, as an alternative to the password.
+    AdminLogin,
+}
+
+impl MagicLinkPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MagicLinkPurpose::Archive => "archive",
+            MagicLinkPurpose::AdminLogin => "admin_login",
+        }
+    }
+}
+
+/// Only the hash is stored: a database leak doesn't hand out live links.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha3_256::digest(token.as_bytes()))
+}
+
+/// Store a new link for `subject_id` and return its token.
+#[tracing::instrument(name = "Issue a magic link", skip(executor))]
+pub async fn issue_magic_link<'a, E>(
+    executor: E,
+    purpose: MagicLinkPurpose,
+    subject_id: Uuid,
+    ttl: std::time::Duration,
+) -> Result<String, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let token = generate_subscription_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO magic_links (token_hash, purpose, subject_id, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_token(&token),
+        purpose.as_str(),
+        subject_id,
+        Utc::now() + Duration::from_std(ttl)?
+    )
+    .execute(executor)
+    .observe("issue_magic_link")
+    .await?;
+    Ok(token)
+}
+
+/// Consume a link, returning whom it was issued for. Expired, already used
+/// and unknown tokens all yield `None`.
+#[tracing::instrument(name = "Redeem a magic link", skip(pool, token))]
+pub async fn redeem_magic_link(
+    pool: &PgPool,
+    purpose: MagicLinkPurpose,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        UPDATE magic_links
+        SET used_at = now()
+        WHERE
+            token_hash = $1 AND
+            purpose = $2 AND
+            used_at IS NULL AND
+            expires_at > now()
+        RETURNING subject_id
+        "#,
+        hash_token(token),
+        purpose.as_str()
+    )
+    .fetch_optional(pool)
+    .observe("redeem_magic_link")
+    .await?;
+    Ok(r.map(|r| r.subject_id))
+}
+
+/// How many links issued for `subject_id` have not expired yet, used or not.
+#[tracing::instrument(name = "Count unexpired magic links", skip(pool))]
+pub async fn count_unexpired_magic_links(
+    pool: &PgPool,
+    purpose: MagicLinkPurpose,
+    subject_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM magic_links
+        WHERE purpose = $1 AND subject_id = $2 AND expires_at > now()
+        "#,
+        purpose.as_str(),
+        subject_id
+    )
+    .fetch_one(pool)
+    .observe_one("count_unexpired_magic_links")
+    .await?;
+    Ok(r.count)
+}
@@ -1,14 +1,174 @@
-use zero2prod::configuration::get_configuration;
-use zero2prod::startup::Application;
+use std::fmt::{Debug, Display};
+use std::path::Path;
+use std::time::Duration;
+use tokio::task::JoinError;
+use zero2prod::configuration::{
+    configuration_schema, get_configuration, validate_configuration_file, Settings,
+};
+use zero2prod::digests::run_digests_until_stopped;
+use zero2prod::doctor::run_doctor;
+use zero2prod::events::run_relay_until_stopped;
+use zero2prod::fake_data::{seed, SeedOptions};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::log_scrubbing::LogScrubber;
+use zero2prod::operations::run_operations_until_stopped;
+use zero2prod::pii::{rotate_keys, PiiCipher};
+use zero2prod::reload::run_reload_on_sighup;
+use zero2prod::rss::run_rss_until_stopped;
+use zero2prod::runtime::{build_runtime, run_runtime_metrics};
+use zero2prod::sms::run_sms_worker_until_stopped;
+use zero2prod::startup::{get_connection_pool, Application, StartupError};
+use zero2prod::subscribers::run_purge_until_stopped;
+use zero2prod::telegram::run_telegram_mirror_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["config", "schema"] => {
+            println!("{:#}", configuration_schema());
+            return Ok(());
+        }
+        ["config", "validate", path] => match validate_configuration_file(Path::new(path)) {
+            Ok(()) => {
+                println!("{} is a valid configuration", path);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{} is not a valid configuration: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        _ => {}
+    }
+
+    // The runtime is sized from the configuration, so it is loaded first.
+    let configuration = get_configuration().unwrap_or_else(|e| exit_on_startup_error(e.into()));
+    let runtime =
+        build_runtime(&configuration.runtime).unwrap_or_else(|e| exit_on_startup_error(e));
+    runtime.block_on(run(args, configuration))
+}
+
+async fn run(args: Vec<String>, configuration: Settings) -> anyhow::Result<()> {
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["doctor"] => {
+            let healthy = run_doctor(configuration).await;
+            std::process::exit(if healthy { 0 } else { 1 });
+        }
+        ["pii", "rotate-keys"] => {
+            let cipher = PiiCipher::new(configuration.pii_encryption.as_ref())
+                .map_err(anyhow::Error::msg)?;
+            let pool = get_connection_pool(&configuration.database).await?;
+            let resealed = rotate_keys(&pool, &cipher, 500).await?;
+            println!("Resealed {} subscribers", resealed);
+            return Ok(());
+        }
+        ["seed", options @ ..] => {
+            let options = SeedOptions::parse(options).map_err(anyhow::Error::msg)?;
+            let cipher = PiiCipher::new(configuration.pii_encryption.as_ref())
+                .map_err(anyhow::Error::msg)?;
+            let pool = get_connection_pool(&configuration.database).await?;
+            let (subscribers, issues) = seed(&pool, &cipher, &options).await?;
+            println!("Inserted {} subscribers and {} issues", subscribers, issues);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let scrubber = LogScrubber::new(
+        &configuration.log_scrubbing,
+        &configuration.application.hmac_secret,
+    )
+    .unwrap_or_else(|e| {
+        exit_on_startup_error(StartupError::InvalidConfiguration(format!(
+            "log_scrubbing: {}",
+            e
+        )))
+    });
+    let subscriber = get_subscriber(
+        "zero2prod".into(),
+        configuration.log_level.clone(),
+        scrubber.wrap(std::io::stdout),
+    );
     init_subscriber(subscriber);
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    let application = Application::build(configuration.clone())
+        .await
+        .unwrap_or_else(|e| exit_on_startup_error(e));
+    let event_bus = application.event_bus();
+    let settings = application.reloadable_settings();
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        event_bus.clone(),
+        settings.clone(),
+    ));
+    let reload_task = tokio::spawn(run_reload_on_sighup(settings));
+    let outbox_relay_task = tokio::spawn(run_relay_until_stopped(configuration.clone()));
+    tokio::spawn(run_runtime_metrics(Duration::from_secs(
+        configuration.runtime.metrics_interval_seconds,
+    )));
+    let sms_task = tokio::spawn(run_sms_worker_until_stopped(configuration.clone()));
+    let telegram_task = tokio::spawn(run_telegram_mirror_until_stopped(configuration.clone()));
+    let operations_task = tokio::spawn(run_operations_until_stopped(
+        configuration.clone(),
+        event_bus.clone(),
+    ));
+    let digest_task = tokio::spawn(run_digests_until_stopped(configuration.clone(), event_bus));
+    let rss_task = tokio::spawn(run_rss_until_stopped(configuration.clone()));
+    let purge_task = tokio::spawn(run_purge_until_stopped(configuration));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = outbox_relay_task => report_exit("Outbox relay", o),
+        o = sms_task => report_exit("SMS worker", o),
+        o = telegram_task => report_exit("Telegram mirror", o),
+        o = operations_task => report_exit("Operations worker", o),
+        o = digest_task => report_exit("Digest worker", o),
+        o = rss_task => report_exit("RSS feeds", o),
+        o = purge_task => report_exit("Subscriber purge", o),
+        o = reload_task => report_exit("Configuration reload", o),
+    };
     Ok(())
 }
+
+/// Explain why we could not start and what to check, rather than panicking.
+fn exit_on_startup_error(e: StartupError) -> ! {
+    eprintln!("zero2prod failed to start: {:?}\nHint: {}", e, e.hint());
+    std::process::exit(1)
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "'{}' task failed to complete",
+                task_name
+            )
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, GaugeVec, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+};
+
+/// Newsletter and transactional emails, by the provider that handled the
+/// request and whether it was accepted.
+pub static EMAIL_SENDS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "email_sends_total",
+        "Emails handed over to an email provider.",
+        &["provider", "outcome"]
+    )
+    .unwrap()
+});
+
+/// Requests to the email providers, from sending the body to the response
+/// headers.
+pub static EMAIL_PROVIDER_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "email_provider_request_duration_seconds",
+        "Latency of the requests to the email providers.",
+        &["provider"]
+    )
+    .unwrap()
+});
+
+/// `status` is `none` when no response came back, e.g. on a timeout.
+pub static EMAIL_PROVIDER_RESPONSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "email_provider_responses_total",
+        "Responses of the email providers, by status code.",
+        &["provider", "status"]
+    )
+    .unwrap()
+});
+
+/// Sends retried on the secondary provider, by the provider that failed.
+pub static EMAIL_SEND_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "email_send_retries_total",
+        "Sends retried on another email provider.",
+        &["provider"]
+    )
+    .unwrap()
+});
+
+pub static EMAIL_REQUEST_SIZE: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "email_request_size_bytes",
+        "Size of the bodies sent to the email providers.",
+        &["provider"],
+        prometheus::exponential_buckets(1024.0, 4.0, 8).unwrap()
+    )
+    .unwrap()
+});
+
+pub static DB_QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "db_query_duration_seconds",
+        "Latency of database queries.",
+        &["query"]
+    )
+    .unwrap()
+});
+
+/// How fast the error budget of each latency objective is being spent:
+/// 1 means it lasts exactly the objective's window.
+pub static SLO_BURN_RATE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "slo_burn_rate",
+        "Error budget burn rate of the latency objectives.",
+        &["route"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUESTS_IN_FLIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "http_requests_in_flight",
+        "Requests being served right now."
+    )
+    .unwrap()
+});
+
+/// Low-priority requests turned away with a 503, by the threshold they hit.
+pub static SHED_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "http_requests_shed_total",
+        "Requests rejected by load shedding.",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+pub static DELIVERY_LANE_PAUSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "delivery_lane_pauses_total",
+        "Delivery lanes paused because the email provider throttled us.",
+        &["lane"]
+    )
+    .unwrap()
+});
+
+/// Scheduler-wide stats of the Tokio runtime, e.g. `active_tasks` or
+/// `injection_queue_depth`. See `runtime::run_runtime_metrics`.
+pub static TOKIO_RUNTIME: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!("tokio_runtime", "Stats of the Tokio scheduler.", &["stat"]).unwrap()
+});
+
+/// Stats of each Tokio worker thread. `busy_seconds`, `polls`, `steals`
+/// and `parks` only grow, since the runtime started.
+pub static TOKIO_WORKER: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "tokio_worker",
+        "Stats of the Tokio worker threads.",
+        &["worker", "stat"]
+    )
+    .unwrap()
+});
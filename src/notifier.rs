@@ -0,0 +1,414 @@
+use crate::admin_events::{AdminEvent, AdminEventBroadcaster};
+use crate::configuration::{NotificationProvider, NotificationSettings};
+use crate::events::{DomainEvent, EventBus};
+use crate::startup::StartupError;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Forwards selected domain events to a Slack or Discord incoming webhook.
+pub struct Notifier {
+    http_client: Client,
+    provider: NotificationProvider,
+    webhook_url: Secret<String>,
+    events: HashSet<String>,
+    rate_limiter: Mutex<RateLimiter>,
+    admin_events: Option<AdminEventBroadcaster>,
+}
+
+impl Notifier {
+    pub fn new(settings: NotificationSettings) -> Result<Self, StartupError> {
+        if let Some(unknown) = settings
+            .events
+            .iter()
+            .find(|e| !DomainEvent::EVENT_TYPES.contains(&e.as_str()))
+        {
+            return Err(StartupError::InvalidConfiguration(format!(
+                "notifications: {} is not an event type. Pick from {}.",
+                unknown,
+                DomainEvent::EVENT_TYPES.join(", ")
+            )));
+        }
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .map_err(|e| StartupError::HttpClient("admin notifier", e))?;
+        Ok(Self {
+            http_client,
+            provider: settings.provider,
+            webhook_url: settings.webhook_url,
+            events: settings.events.into_iter().collect(),
+            rate_limiter: Mutex::new(RateLimiter::new(
+                settings.max_messages_per_minute,
+                Duration::from_secs(60),
+            )),
+            admin_events: None,
+        })
+    }
+
+    /// Report the notifications the webhook did not take on the admin
+    /// notifications channel.
+    pub fn with_admin_events(self, admin_events: AdminEventBroadcaster) -> Self {
+        Self {
+            admin_events: Some(admin_events),
+            ..self
+        }
+    }
+
+    /// Subscribe to `event_bus` and forward matching events in the background.
+    pub fn spawn(self, event_bus: &EventBus) {
+        let notifier = std::sync::Arc::new(self);
+        event_bus.spawn_subscriber("notifier", move |event| {
+            let notifier = notifier.clone();
+            async move { notifier.handle(&event).await }
+        });
+    }
+
+    #[tracing::instrument(name = "Notify admins", skip(self, event), fields(event_type = event.event_type()))]
+    pub async fn handle(&self, event: &DomainEvent) {
+        if !self.events.contains(event.event_type()) {
+            return;
+        }
+        if !self
+            .rate_limiter
+            .lock()
+            .unwrap()
+            .try_acquire(Instant::now())
+        {
+            tracing::warn!("Dropping an admin notification: rate limit exceeded");
+            return;
+        }
+        #[cfg(feature = "chaos")]
+        if crate::chaos::drop_webhook() {
+            tracing::warn!("Dropping an admin notification: injected fault");
+            return;
+        }
+        if let Err(e) = self.post(&format_message(event)).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to deliver an admin notification"
+            );
+            if let Some(admin_events) = &self.admin_events {
+                admin_events.publish(AdminEvent::WebhookDeliveryFailure {
+                    url: self.webhook_origin(),
+                    error: e.without_url().to_string(),
+                });
+            }
+        }
+    }
+
+    /// The webhook URL carries its secret in the path: only its origin is
+    /// shown to the admins.
+    fn webhook_origin(&self) -> String {
+        reqwest::Url::parse(self.webhook_url.expose_secret())
+            .map(|url| url.origin().ascii_serialization())
+            .unwrap_or_default()
+    }
+
+    async fn post(&self, text: &str) -> Result<(), reqwest::Error> {
+        let body = match self.provider {
+            NotificationProvider::Slack => serde_json::json!({ "text": text }),
+            NotificationProvider::Discord => serde_json::json!({ "content": text }),
+        };
+        self.http_client
+            .post(self.webhook_url.expose_secret())
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn format_message(event: &DomainEvent) -> String {
+    match event {
+        DomainEvent::SubscriberConfirmed { subscriber_id } => {
+            format!(
+                ":tada: Subscriber {} confirmed their subscription.",
+                subscriber_id
+            )
+        }
+        DomainEvent::IssueSent {
+            title, recipients, ..
+        } => {
+            format!(
+                ":email: Newsletter issue \"{}\" was sent to {} subscribers.",
+                title, recipients
+            )
+        }
+        DomainEvent::DeliveryFailed {
+            subscriber_id: Some(subscriber_id),
+            error,
+        } => {
+            format!(
+                ":warning: Delivery to subscriber {} failed: {}",
+                subscriber_id, error
+            )
+        }
+        DomainEvent::DeliveryFailed {
+            subscriber_id: None,
+            error,
+        } => {
+            format!(
+                ":warning: Delivery to a deleted subscriber failed: {}",
+                error
+            )
+        }
+        DomainEvent::FailureRateExceeded {
+            issue_id,
+            failure_rate,
+        } => {
+            format!(
+                ":rotating_light: Delivery of newsletter issue {} was paused: {:.1}% of the attempts failed.",
+                issue_id,
+                failure_rate * 100.0
+            )
+        }
+        DomainEvent::SloBudgetBurning { route, burn_rate } => {
+            format!(
+                ":hourglass: The latency error budget of {} is burning {:.1}x too fast.",
+                route, burn_rate
+            )
+        }
+        DomainEvent::SubscriberViewImpersonated {
+            user_id,
+            subscriber_id,
+        } => {
+            format!(
+                ":eyes: Admin {} viewed the newsletter as subscriber {}.",
+                user_id, subscriber_id
+            )
+        }
+        DomainEvent::ReferralMilestoneReached {
+            subscriber_id,
+            referrals,
+        } => {
+            format!(
+                ":trophy: Subscriber {} brought in {} confirmed subscribers.",
+                subscriber_id, referrals
+            )
+        }
+        DomainEvent::SignupSpikeDetected {
+            source,
+            signups,
+            baseline,
+        } => {
+            format!(
+                ":robot_face: {} signups this hour from {}, against {:.1} on average: subscribing is in strict mode.",
+                signups,
+                if source == "*" { "all sources" } else { source },
+                baseline
+            )
+        }
+        DomainEvent::JobFailed { job, error } => {
+            format!(":x: The {} job failed: {}", job, error)
+        }
+    }
+}
+
+/// Allow at most `capacity` acquisitions in any `window`.
+struct RateLimiter {
+    capacity: usize,
+    window: Duration,
+    sent_at: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    fn new(capacity: usize, window: Duration) -> Self {
+        Self {
+            capacity,
+            window,
+            sent_at: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        while let Some(oldest) = self.sent_at.front() {
+            if now.duration_since(*oldest) >= self.window {
+                self.sent_at.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.sent_at.len() < self.capacity {
+            self.sent_at.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notifier, RateLimiter};
+    use crate::admin_events::{AdminEvent, AdminEventBroadcaster};
+    use crate::configuration::{NotificationProvider, NotificationSettings};
+    use crate::events::DomainEvent;
+    use secrecy::Secret;
+    use std::time::{Duration, Instant};
+    use wiremock::matchers::{any, body_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn notifier(
+        mock_server: &MockServer,
+        provider: NotificationProvider,
+        max_messages_per_minute: usize,
+    ) -> Notifier {
+        Notifier::new(NotificationSettings {
+            provider,
+            webhook_url: Secret::new(mock_server.uri()),
+            events: vec!["issue_sent".into()],
+            max_messages_per_minute,
+            timeout_milliseconds: 200,
+        })
+        .unwrap()
+    }
+
+    fn issue_sent() -> DomainEvent {
+        DomainEvent::IssueSent {
+            issue_id: uuid::Uuid::new_v4(),
+            title: "Issue #1".into(),
+            recipients: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn slack_notifications_are_posted_as_text() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(serde_json::json!({
+                "text": ":email: Newsletter issue \"Issue #1\" was sent to 2 subscribers."
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        notifier(&mock_server, NotificationProvider::Slack, 10)
+            .handle(&issue_sent())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn discord_notifications_are_posted_as_content() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(serde_json::json!({
+                "content": ":email: Newsletter issue \"Issue #1\" was sent to 2 subscribers."
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        notifier(&mock_server, NotificationProvider::Discord, 10)
+            .handle(&issue_sent())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn events_that_were_not_selected_are_not_forwarded() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        notifier(&mock_server, NotificationProvider::Slack, 10)
+            .handle(&DomainEvent::DeliveryFailed {
+                subscriber_id: Some(uuid::Uuid::new_v4()),
+                error: "timeout".into(),
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn job_failures_are_forwarded_when_selected() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(serde_json::json!({
+                "text": ":x: The digests job failed: connection refused"
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let notifier = Notifier::new(NotificationSettings {
+            provider: NotificationProvider::Slack,
+            webhook_url: Secret::new(mock_server.uri()),
+            events: vec!["job_failed".into()],
+            max_messages_per_minute: 10,
+            timeout_milliseconds: 200,
+        })
+        .unwrap();
+
+        notifier
+            .handle(&DomainEvent::JobFailed {
+                job: "digests".into(),
+                error: "connection refused".into(),
+            })
+            .await;
+    }
+
+    #[test]
+    fn unknown_event_types_are_rejected_at_startup() {
+        let result = Notifier::new(NotificationSettings {
+            provider: NotificationProvider::Slack,
+            webhook_url: Secret::new("https://hooks.slack.com/services/x".into()),
+            events: vec!["issue_sent".into(), "isue_sent".into()],
+            max_messages_per_minute: 10,
+            timeout_milliseconds: 200,
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn notifications_over_the_rate_limit_are_dropped() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let notifier = notifier(&mock_server, NotificationProvider::Slack, 1);
+
+        notifier.handle(&issue_sent()).await;
+        notifier.handle(&issue_sent()).await;
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_reported_on_the_admin_channel() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+        let admin_events = AdminEventBroadcaster::new();
+        let mut received = admin_events.subscribe();
+
+        notifier(&mock_server, NotificationProvider::Slack, 10)
+            .with_admin_events(admin_events)
+            .handle(&issue_sent())
+            .await;
+
+        match received.try_recv().unwrap() {
+            AdminEvent::WebhookDeliveryFailure { url, .. } => assert_eq!(url, mock_server.uri()),
+            event => panic!("Unexpected admin event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn the_rate_limiter_frees_up_capacity_after_the_window() {
+        let mut limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start + Duration::from_secs(30)));
+        assert!(limiter.try_acquire(start + Duration::from_secs(60)));
+    }
+}
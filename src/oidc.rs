@@ -0,0 +1,519 @@
+//! Admin single sign-on: the OpenID Connect authorization code flow with PKCE.
+use crate::configuration::OidcSettings;
+use crate::database::ObserveQuery;
+use crate::links;
+use crate::startup::StartupError;
+use anyhow::Context;
+use base64::Engine;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use reqwest::{Client, Url};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub struct OidcClient {
+    http_client: Client,
+    settings: OidcSettings,
+    redirect_uri: String,
+}
+
+/// What has to survive the round trip to the identity provider, kept in the
+/// session of the user logging in.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct OidcFlow {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The ID token claims we rely on.
+#[derive(serde::Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: Audience,
+    pub exp: i64,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl IdTokenClaims {
+    /// The email address of the user, if the identity provider checked it.
+    pub fn verified_email(&self) -> Option<&str> {
+        match self.email_verified {
+            Some(true) => self.email.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::Single(aud) => aud == client_id,
+            Audience::Multiple(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+fn random_string(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+/// The S256 PKCE challenge for `code_verifier`.
+pub fn code_challenge(code_verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier))
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings, base_url: &str) -> Result<Self, StartupError> {
+        if settings.default_role.is_none() && settings.group_roles.is_empty() {
+            return Err(StartupError::InvalidConfiguration(
+                "oidc: without `group_roles` nor a `default_role`, nobody can log in".into(),
+            ));
+        }
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .map_err(|e| StartupError::HttpClient("OpenID Connect client", e))?;
+        Ok(Self {
+            http_client,
+            settings,
+            redirect_uri: links::oidc_callback().absolute(base_url),
+        })
+    }
+
+    async fn discover(&self) -> Result<ProviderMetadata, anyhow::Error> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.settings.issuer_url.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self
+            .http_client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid provider metadata")?;
+        anyhow::ensure!(
+            metadata.issuer.trim_end_matches('/') == self.settings.issuer_url.trim_end_matches('/'),
+            "The provider metadata belongs to another issuer"
+        );
+        Ok(metadata)
+    }
+
+    /// Where to send the user to log in, along with the flow to keep around
+    /// until they come back.
+    #[tracing::instrument(name = "Start an OIDC login", skip(self))]
+    pub async fn authorization_url(&self) -> Result<(Url, OidcFlow), anyhow::Error> {
+        let metadata = self.discover().await?;
+        let flow = OidcFlow {
+            state: random_string(32),
+            nonce: random_string(32),
+            code_verifier: random_string(64),
+        };
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.settings.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", "openid email profile"),
+                ("state", flow.state.as_str()),
+                ("nonce", flow.nonce.as_str()),
+                (
+                    "code_challenge",
+                    code_challenge(&flow.code_verifier).as_str(),
+                ),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("Invalid authorization endpoint")?;
+        Ok((url, flow))
+    }
+
+    /// Redeem the authorization code and validate the ID token it yields.
+    #[tracing::instrument(name = "Complete an OIDC login", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        flow: &OidcFlow,
+    ) -> Result<IdTokenClaims, anyhow::Error> {
+        let metadata = self.discover().await?;
+        let response: TokenResponse = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("client_id", self.settings.client_id.as_str()),
+                ("client_secret", self.settings.client_secret.expose_secret()),
+                ("code_verifier", flow.code_verifier.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid token response")?;
+        let claims = decode_id_token(&response.id_token)?;
+        validate_claims(
+            &claims,
+            &metadata.issuer,
+            &self.settings.client_id,
+            &flow.nonce,
+            chrono::Utc::now().timestamp(),
+        )?;
+        Ok(claims)
+    }
+
+    /// The role granted by the first mapped group the user belongs to.
+    /// Users outside of the allowed domains, or of every mapped group when
+    /// there is no default role, are turned away.
+    pub fn role_for(&self, claims: &IdTokenClaims) -> Result<String, anyhow::Error> {
+        let allowed_domains = &self.settings.allowed_email_domains;
+        if !allowed_domains.is_empty() {
+            let domain = claims
+                .verified_email()
+                .and_then(|email| email.rsplit_once('@'))
+                .map(|(_, domain)| domain)
+                .context("The user has no verified email address")?;
+            anyhow::ensure!(
+                allowed_domains
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(domain)),
+                "The email domain of the user is not allowed"
+            );
+        }
+        let groups: Vec<&str> = claims
+            .other
+            .get(&self.settings.groups_claim)
+            .and_then(|groups| groups.as_array())
+            .map(|groups| groups.iter().filter_map(|g| g.as_str()).collect())
+            .unwrap_or_default();
+        self.settings
+            .group_roles
+            .iter()
+            .find(|mapping| groups.contains(&mapping.group.as_str()))
+            .map(|mapping| mapping.role.clone())
+            .or_else(|| self.settings.default_role.clone())
+            .context("The user belongs to none of the mapped groups")
+    }
+}
+
+/// Read the claims of an ID token.
+///
+/// The signature is not checked: the token comes straight from the token
+/// endpoint over TLS, which OpenID Connect Core (3.1.3.7) accepts in place of
+/// signature validation for confidential clients.
+fn decode_id_token(id_token: &str) -> Result<IdTokenClaims, anyhow::Error> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .context("The ID token is not a JWT")?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .context("The ID token payload is not base64url-encoded")?;
+    serde_json::from_slice(&payload).context("Invalid ID token claims")
+}
+
+fn validate_claims(
+    claims: &IdTokenClaims,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<(), anyhow::Error> {
+    anyhow::ensure!(claims.iss == issuer, "Unexpected ID token issuer");
+    anyhow::ensure!(
+        claims.aud.contains(client_id),
+        "The ID token was not issued for us"
+    );
+    anyhow::ensure!(claims.exp > now, "The ID token has expired");
+    anyhow::ensure!(
+        claims.nonce.as_deref() == Some(nonce),
+        "The ID token nonce does not match"
+    );
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("A password account named {0} already exists")]
+pub struct UsernameTaken(pub String);
+
+/// Find the admin behind `claims`, creating them on their first login, and
+/// bring their role in line with the identity provider.
+#[tracing::instrument(name = "Provision an SSO user", skip(pool, claims), fields(sub = %claims.sub))]
+pub async fn provision_user(
+    pool: &PgPool,
+    claims: &IdTokenClaims,
+    role: &str,
+) -> Result<Uuid, anyhow::Error> {
+    let existing = sqlx::query!(
+        r#"
+        UPDATE users SET role = $2
+        WHERE oidc_subject = $1
+        RETURNING user_id, active
+        "#,
+        claims.sub,
+        role
+    )
+    .fetch_optional(pool)
+    .observe("update_sso_user")
+    .await?;
+    if let Some(existing) = existing {
+        anyhow::ensure!(existing.active, "The user has been deactivated");
+        return Ok(existing.user_id);
+    }
+    // The address is stored with the account and used to email the admin.
+    let email = claims
+        .verified_email()
+        .context("New SSO users need a verified email address")?;
+    let username = claims
+        .preferred_username
+        .clone()
+        .unwrap_or_else(|| email.to_owned());
+    // Admins provisioned over SCIM have neither a password nor a subject
+    // until their first SSO login, which claims the account if it comes with
+    // the address the identity provider registered for it.
+    let provisioned = sqlx::query!(
+        r#"
+        UPDATE users SET oidc_subject = $1, role = $4
+        WHERE username = $2
+            AND oidc_subject IS NULL
+            AND password_hash IS NULL
+            AND lower(email) = lower($3)
+        RETURNING user_id, active
+        "#,
+        claims.sub,
+        username,
+        email,
+        role
+    )
+    .fetch_optional(pool)
+    .observe("link_provisioned_sso_user")
+    .await?;
+    if let Some(provisioned) = provisioned {
+        anyhow::ensure!(provisioned.active, "The user has been deactivated");
+        return Ok(provisioned.user_id);
+    }
+    let user_id = Uuid::new_v4();
+    // Never attach the identity to an existing password account: whoever
+    // controls the IdP could otherwise take it over by picking its username.
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, oidc_subject, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        email,
+        claims.sub,
+        role
+    )
+    .execute(pool)
+    .observe("insert_sso_user")
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(UsernameTaken(username).into());
+    }
+    Ok(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_challenge, decode_id_token, validate_claims, IdTokenClaims, OidcClient};
+    use crate::configuration::{GroupRole, OidcSettings};
+    use base64::Engine;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn client(
+        default_role: Option<&str>,
+        allowed_email_domains: &[&str],
+    ) -> Result<OidcClient, crate::startup::StartupError> {
+        let settings = OidcSettings {
+            issuer_url: "https://idp.example.com".into(),
+            client_id: "zero2prod".into(),
+            client_secret: Secret::new("secret".into()),
+            groups_claim: "groups".into(),
+            default_role: default_role.map(Into::into),
+            group_roles: vec![GroupRole {
+                group: "newsletter-admins".into(),
+                role: "admin".into(),
+            }],
+            allowed_email_domains: allowed_email_domains
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            timeout_milliseconds: 1000,
+        };
+        OidcClient::new(settings, "https://zero2prod.example.com")
+    }
+
+    fn claims(claims: serde_json::Value) -> IdTokenClaims {
+        serde_json::from_value(claims).unwrap()
+    }
+
+    fn id_token(claims: serde_json::Value) -> String {
+        let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        format!(
+            "{}.{}.signature",
+            engine.encode(r#"{"alg":"RS256"}"#),
+            engine.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn the_code_challenge_matches_the_rfc_example() {
+        // RFC 7636, appendix B.
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn valid_claims_are_accepted() {
+        let claims = decode_id_token(&id_token(serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "123",
+            "aud": ["other", "zero2prod"],
+            "exp": 2_000,
+            "nonce": "n-0S6_WzA2Mj",
+        })))
+        .unwrap();
+        assert_ok!(validate_claims(
+            &claims,
+            "https://idp.example.com",
+            "zero2prod",
+            "n-0S6_WzA2Mj",
+            1_000
+        ));
+    }
+
+    #[test]
+    fn tokens_for_another_client_or_flow_are_rejected() {
+        let claims = decode_id_token(&id_token(serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "123",
+            "aud": "zero2prod",
+            "exp": 2_000,
+            "nonce": "n-0S6_WzA2Mj",
+        })))
+        .unwrap();
+        let issuer = "https://idp.example.com";
+        assert_err!(validate_claims(
+            &claims,
+            issuer,
+            "other",
+            "n-0S6_WzA2Mj",
+            1_000
+        ));
+        assert_err!(validate_claims(
+            &claims,
+            issuer,
+            "zero2prod",
+            "replayed",
+            1_000
+        ));
+        assert_err!(validate_claims(
+            &claims,
+            issuer,
+            "zero2prod",
+            "n-0S6_WzA2Mj",
+            3_000
+        ));
+        assert_err!(validate_claims(
+            &claims,
+            "https://evil.com",
+            "zero2prod",
+            "n-0S6_WzA2Mj",
+            1_000
+        ));
+    }
+
+    #[test]
+    fn users_outside_of_the_mapped_groups_are_turned_away_without_a_default_role() {
+        let admin = claims(serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "1",
+            "aud": "zero2prod",
+            "exp": 2_000,
+            "groups": ["newsletter-admins"],
+        }));
+        let stranger = claims(serde_json::json!({
+            "iss": "https://idp.example.com",
+            "sub": "2",
+            "aud": "zero2prod",
+            "exp": 2_000,
+        }));
+
+        let without_default = client(None, &[]).unwrap();
+        assert_eq!(without_default.role_for(&admin).unwrap(), "admin");
+        assert_err!(without_default.role_for(&stranger));
+
+        let with_default = client(Some("editor"), &[]).unwrap();
+        assert_eq!(with_default.role_for(&stranger).unwrap(), "editor");
+    }
+
+    #[test]
+    fn only_verified_addresses_in_the_allowed_domains_get_in() {
+        let client = client(Some("editor"), &["example.com"]).unwrap();
+        let with_email = |email: &str, verified: bool| {
+            claims(serde_json::json!({
+                "iss": "https://idp.example.com",
+                "sub": "1",
+                "aud": "zero2prod",
+                "exp": 2_000,
+                "email": email,
+                "email_verified": verified,
+            }))
+        };
+
+        assert_ok!(client.role_for(&with_email("grace@example.com", true)));
+        assert_err!(client.role_for(&with_email("grace@example.com", false)));
+        assert_err!(client.role_for(&with_email("grace@gmail.com", true)));
+    }
+
+    #[test]
+    fn a_configuration_letting_nobody_in_is_rejected() {
+        let mut settings = client(None, &[]).unwrap().settings;
+        settings.group_roles.clear();
+        assert!(OidcClient::new(settings, "https://zero2prod.example.com").is_err());
+    }
+}
@@ -0,0 +1,489 @@
+//! Write-ahead intents for the admin operations too long for a single
+//! transaction, such as deleting or merging many subscribers at once.
+//!
+//! The plan of an operation is recorded before anything runs. The operations
+//! worker then runs it one step per transaction, committing each step with
+//! the checkpoint: an operation interrupted, e.g. by a deploy, resumes at its
+//! next step, and rolling it back undoes the steps applied, last first. A
+//! failed step stops the operation until an admin resumes or rolls it back.
+use crate::configuration::Settings;
+use crate::database::ObserveQuery;
+use crate::events::EventBus;
+use crate::pii::PiiCipher;
+use crate::startup::get_connection_pool;
+use crate::subscribers::{
+    merge_subscribers_in, restore_subscriber, soft_delete_subscriber, MergeOutcome,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Larger operations are split by the admin.
+pub const MAX_STEPS: usize = 10_000;
+
+/// What an operation does, one step per item.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OperationPlan {
+    DeleteSubscribers {
+        subscriber_ids: Vec<Uuid>,
+    },
+    /// Survivor and duplicate pairs. Merges cannot be rolled back: the
+    /// duplicates lose their tokens and pending deliveries.
+    MergeSubscribers {
+        merges: Vec<(Uuid, Uuid)>,
+    },
+}
+
+impl OperationPlan {
+    /// `items` holds a subscriber id per line to delete, or a survivor id
+    /// and a duplicate id per line to merge.
+    pub fn parse(kind: &str, items: &str) -> Result<Self, String> {
+        let lines: Vec<(usize, &str)> = items
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            return Err("The operation has nothing to do.".into());
+        }
+        if lines.len() > MAX_STEPS {
+            return Err(format!(
+                "An operation is limited to {} subscribers.",
+                MAX_STEPS
+            ));
+        }
+        let parse_id = |number: usize, id: &str| {
+            Uuid::parse_str(id)
+                .map_err(|_| format!("Line {}: {} is not a subscriber id.", number + 1, id))
+        };
+        match kind {
+            "delete_subscribers" => {
+                let subscriber_ids = lines
+                    .into_iter()
+                    .map(|(number, line)| parse_id(number, line))
+                    .collect::<Result<_, _>>()?;
+                Ok(Self::DeleteSubscribers { subscriber_ids })
+            }
+            "merge_subscribers" => {
+                let merges = lines
+                    .into_iter()
+                    .map(|(number, line)| {
+                        let ids: Vec<&str> = line.split_whitespace().collect();
+                        let [survivor, duplicate] = ids[..] else {
+                            return Err(format!(
+                                "Line {}: expected a survivor id and a duplicate id.",
+                                number + 1
+                            ));
+                        };
+                        Ok((parse_id(number, survivor)?, parse_id(number, duplicate)?))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Self::MergeSubscribers { merges })
+            }
+            _ => Err(format!("{} is not an operation.", kind)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::DeleteSubscribers { .. } => "delete_subscribers",
+            Self::MergeSubscribers { .. } => "merge_subscribers",
+        }
+    }
+
+    pub fn total_steps(&self) -> usize {
+        match self {
+            Self::DeleteSubscribers { subscriber_ids } => subscriber_ids.len(),
+            Self::MergeSubscribers { merges } => merges.len(),
+        }
+    }
+
+    pub fn is_reversible(&self) -> bool {
+        matches!(self, Self::DeleteSubscribers { .. })
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Operation {
+    pub id: Uuid,
+    pub kind: String,
+    pub status: String,
+    pub checkpoint: i32,
+    pub total_steps: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Record the intent of `plan`, for the operations worker to carry out.
+#[tracing::instrument(skip(pool, plan), fields(kind = plan.kind()))]
+pub async fn create_operation(
+    pool: &PgPool,
+    plan: &OperationPlan,
+    requested_by: Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_operations (
+            id, kind, plan, status, total_steps, requested_by, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, 'pending', $4, $5, now(), now())
+        "#,
+        id,
+        plan.kind(),
+        serde_json::to_value(plan)?,
+        plan.total_steps() as i32,
+        requested_by
+    )
+    .execute(pool)
+    .observe("create_admin_operation")
+    .await?;
+    Ok(id)
+}
+
+/// The most recent operations first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_operations(pool: &PgPool, limit: i64) -> Result<Vec<Operation>, sqlx::Error> {
+    sqlx::query_as!(
+        Operation,
+        r#"
+        SELECT id, kind, status, checkpoint, total_steps, last_error, created_at, updated_at
+        FROM admin_operations
+        ORDER BY created_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .observe("list_admin_operations")
+    .await
+}
+
+/// Pick a failed operation up where it stopped, in the direction it was
+/// going. Returns whether there was such an operation.
+#[tracing::instrument(skip(pool))]
+pub async fn resume_operation(pool: &PgPool, operation_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE admin_operations
+        SET
+            status = CASE status WHEN 'failed' THEN 'running' ELSE 'rolling_back' END,
+            last_error = NULL,
+            updated_at = now()
+        WHERE id = $1 AND status IN ('failed', 'rollback_failed')
+        "#,
+        operation_id
+    )
+    .execute(pool)
+    .observe("resume_admin_operation")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RollBackOutcome {
+    RollingBack,
+    Irreversible,
+    /// Either the operation doesn't exist, or it is already rolled back or
+    /// rolling back.
+    NotFound,
+}
+
+/// Undo the steps applied so far, stopping the operation if it is running.
+#[tracing::instrument(skip(pool))]
+pub async fn roll_back_operation(
+    pool: &PgPool,
+    operation_id: Uuid,
+) -> Result<RollBackOutcome, anyhow::Error> {
+    let Some(operation) = sqlx::query!(
+        "SELECT plan FROM admin_operations WHERE id = $1",
+        operation_id
+    )
+    .fetch_optional(pool)
+    .observe("get_admin_operation_plan")
+    .await?
+    else {
+        return Ok(RollBackOutcome::NotFound);
+    };
+    let plan: OperationPlan = serde_json::from_value(operation.plan)?;
+    if !plan.is_reversible() {
+        return Ok(RollBackOutcome::Irreversible);
+    }
+    let result = sqlx::query!(
+        r#"
+        UPDATE admin_operations
+        SET status = 'rolling_back', last_error = NULL, updated_at = now()
+        WHERE id = $1 AND status IN ('pending', 'running', 'failed', 'completed')
+        "#,
+        operation_id
+    )
+    .execute(pool)
+    .observe("roll_back_admin_operation")
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(RollBackOutcome::NotFound);
+    }
+    Ok(RollBackOutcome::RollingBack)
+}
+
+/// Carry out the step of `plan` at `step`. Returns whether it changed
+/// anything: deleting a subscriber already deleted does not.
+async fn run_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    pii: &PiiCipher,
+    plan: &OperationPlan,
+    step: usize,
+) -> Result<bool, anyhow::Error> {
+    match plan {
+        OperationPlan::DeleteSubscribers { subscriber_ids } => {
+            Ok(soft_delete_subscriber(&mut **transaction, subscriber_ids[step]).await?)
+        }
+        OperationPlan::MergeSubscribers { merges } => {
+            let (survivor_id, duplicate_id) = merges[step];
+            let outcome = merge_subscribers_in(transaction, pii, survivor_id, duplicate_id).await?;
+            Ok(outcome == MergeOutcome::Merged)
+        }
+    }
+}
+
+async fn undo_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    plan: &OperationPlan,
+    step: usize,
+) -> Result<(), anyhow::Error> {
+    match plan {
+        OperationPlan::DeleteSubscribers { subscriber_ids } => {
+            restore_subscriber(&mut **transaction, subscriber_ids[step]).await?;
+            Ok(())
+        }
+        OperationPlan::MergeSubscribers { .. } => Err(anyhow!("Merges cannot be rolled back")),
+    }
+}
+
+pub enum StepOutcome {
+    Progressed,
+    Finished,
+    Failed,
+    EmptyQueue,
+}
+
+/// Run, or undo, the next step of the oldest operation under way, if any.
+///
+/// The operation stays locked for the whole step: concurrent workers move
+/// on to other operations.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_execute_next_step(
+    pool: &PgPool,
+    pii: &PiiCipher,
+) -> Result<StepOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let operation = sqlx::query!(
+        r#"
+        SELECT id, plan, status, checkpoint, total_steps
+        FROM admin_operations
+        WHERE status IN ('pending', 'running', 'rolling_back')
+        ORDER BY created_at
+        FOR UPDATE SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("dequeue_admin_operation")
+    .await?;
+    let Some(operation) = operation else {
+        return Ok(StepOutcome::EmptyQueue);
+    };
+    let plan: OperationPlan = serde_json::from_value(operation.plan)?;
+    let rolling_back = operation.status == "rolling_back";
+    let finished = if rolling_back {
+        operation.checkpoint <= 0
+    } else {
+        operation.checkpoint >= operation.total_steps
+    };
+    if finished {
+        sqlx::query!(
+            "UPDATE admin_operations SET status = $2, updated_at = now() WHERE id = $1",
+            operation.id,
+            if rolling_back {
+                "rolled_back"
+            } else {
+                "completed"
+            }
+        )
+        .execute(&mut *transaction)
+        .observe("finish_admin_operation")
+        .await?;
+        transaction.commit().await?;
+        return Ok(StepOutcome::Finished);
+    }
+
+    let (step, checkpoint) = if rolling_back {
+        (operation.checkpoint - 1, operation.checkpoint - 1)
+    } else {
+        (operation.checkpoint, operation.checkpoint + 1)
+    };
+    let result = if rolling_back {
+        undo_applied_step(&mut transaction, &plan, operation.id, step).await
+    } else {
+        run_and_record_step(&mut transaction, pii, &plan, operation.id, step).await
+    };
+    if let Err(e) = result {
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            operation_id = %operation.id,
+            step,
+            "An admin operation failed"
+        );
+        transaction.rollback().await?;
+        sqlx::query!(
+            r#"
+            UPDATE admin_operations SET status = $2, last_error = $3, updated_at = now()
+            WHERE id = $1
+            "#,
+            operation.id,
+            if rolling_back {
+                "rollback_failed"
+            } else {
+                "failed"
+            },
+            e.to_string()
+        )
+        .execute(pool)
+        .observe("record_admin_operation_failure")
+        .await?;
+        return Ok(StepOutcome::Failed);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE admin_operations
+        SET
+            checkpoint = $2,
+            status = CASE status WHEN 'pending' THEN 'running' ELSE status END,
+            updated_at = now()
+        WHERE id = $1
+        "#,
+        operation.id,
+        checkpoint
+    )
+    .execute(&mut *transaction)
+    .observe("checkpoint_admin_operation")
+    .await?;
+    transaction.commit().await?;
+    Ok(StepOutcome::Progressed)
+}
+
+async fn run_and_record_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    pii: &PiiCipher,
+    plan: &OperationPlan,
+    operation_id: Uuid,
+    step: i32,
+) -> Result<(), anyhow::Error> {
+    let applied = run_step(transaction, pii, plan, step as usize).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_operation_steps (operation_id, step, applied)
+        VALUES ($1, $2, $3)
+        "#,
+        operation_id,
+        step,
+        applied
+    )
+    .execute(&mut **transaction)
+    .observe("record_admin_operation_step")
+    .await?;
+    Ok(())
+}
+
+async fn undo_applied_step(
+    transaction: &mut Transaction<'_, Postgres>,
+    plan: &OperationPlan,
+    operation_id: Uuid,
+    step: i32,
+) -> Result<(), anyhow::Error> {
+    let applied = sqlx::query!(
+        r#"
+        DELETE FROM admin_operation_steps
+        WHERE operation_id = $1 AND step = $2
+        RETURNING applied
+        "#,
+        operation_id,
+        step
+    )
+    .fetch_optional(&mut **transaction)
+    .observe("delete_admin_operation_step")
+    .await?
+    .is_some_and(|r| r.applied);
+    if applied {
+        undo_step(transaction, plan, step as usize).await?;
+    }
+    Ok(())
+}
+
+async fn operations_loop(
+    pool: PgPool,
+    pii: PiiCipher,
+    event_bus: EventBus,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_next_step(&pool, &pii).await {
+            Ok(StepOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(5)).await,
+            Err(e) => {
+                event_bus.publish_job_failure("admin_operations", &e).await;
+                tokio::time::sleep(Duration::from_secs(1)).await
+            }
+            Ok(StepOutcome::Progressed | StepOutcome::Finished | StepOutcome::Failed) => {}
+        }
+    }
+}
+
+/// Carry out the admin operations until the process is stopped.
+pub async fn run_operations_until_stopped(
+    configuration: Settings,
+    event_bus: EventBus,
+) -> Result<(), anyhow::Error> {
+    let pii = PiiCipher::new(configuration.pii_encryption.as_ref()).map_err(anyhow::Error::msg)?;
+    let pool = get_connection_pool(&configuration.database).await?;
+    operations_loop(pool, pii, event_bus).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OperationPlan;
+    use uuid::Uuid;
+
+    const A: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    const B: &str = "a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8";
+
+    #[test]
+    fn plans_are_parsed_one_item_per_line() {
+        let plan =
+            OperationPlan::parse("delete_subscribers", &format!("{A}\n\n  {B}  \n")).unwrap();
+        assert_eq!(
+            plan,
+            OperationPlan::DeleteSubscribers {
+                subscriber_ids: vec![Uuid::parse_str(A).unwrap(), Uuid::parse_str(B).unwrap()]
+            }
+        );
+        assert_eq!(plan.total_steps(), 2);
+        assert!(plan.is_reversible());
+
+        let plan = OperationPlan::parse("merge_subscribers", &format!("{A} {B}")).unwrap();
+        assert_eq!(plan.total_steps(), 1);
+        assert!(!plan.is_reversible());
+    }
+
+    #[test]
+    fn malformed_plans_are_rejected() {
+        assert!(OperationPlan::parse("delete_subscribers", " \n").is_err());
+        assert!(OperationPlan::parse("delete_subscribers", "not-an-id").is_err());
+        assert!(OperationPlan::parse("merge_subscribers", A).is_err());
+        assert!(OperationPlan::parse("drop_everything", A).is_err());
+    }
+}
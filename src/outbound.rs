@@ -0,0 +1,116 @@
+//! Guards for the requests we send to URLs that issue authors or subscribers
+//! chose: they must not reach into our own network.
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Whether `ip` is routable on the internet: loopback, private, link-local
+/// and the other special-purpose ranges are not.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 100.64.0.0/10, shared by carriers' NATs.
+        || (a == 100 && (64..128).contains(&b))
+        // 0.0.0.0/8 and 240.0.0.0/4.
+        || a == 0
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7, unique local addresses.
+        || (first & 0xfe00) == 0xfc00
+        // fe80::/10, link-local addresses.
+        || (first & 0xffc0) == 0xfe80
+        // 2001:db8::/32, documentation.
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+/// Resolves host names to their public addresses only, failing when there
+/// are none. Checking the addresses we connect to, rather than the names in
+/// URLs, also holds against DNS records that change between two lookups.
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public_address(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `url` names an IP address that is not public. The resolver does
+/// not see those.
+pub fn is_private_ip_literal(url: &reqwest::Url) -> bool {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .and_then(|host| host.parse::<IpAddr>().ok())
+        .is_some_and(|ip| !is_public_address(ip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_private_ip_literal, is_public_address};
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for ip in ["93.184.216.34", "8.8.8.8", "2606:4700::1111"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn private_ip_literals_are_spotted_in_urls() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_private_ip_literal(&url("http://169.254.169.254/latest")));
+        assert!(is_private_ip_literal(&url("http://[::1]:8080/")));
+        assert!(!is_private_ip_literal(&url("https://8.8.8.8/")));
+        assert!(!is_private_ip_literal(&url("https://example.com/")));
+    }
+}
@@ -0,0 +1,317 @@
+//! Application-level encryption of the subscribers' personal data.
+//!
+//! With `pii_encryption` configured, the email and name of a subscriber are
+//! stored sealed with AES-256-GCM under the active key of the keyring, as
+//! `pii:<key id>:<base64 nonce and ciphertext>`. Sealing is randomised, so
+//! emails are looked up through a blind index instead: an HMAC of the
+//! lower-cased address.
+//!
+//! The domain of a sealed email stays in clear, after an `@`: per-domain
+//! throttling works on it, and a sealed address that slips through without
+//! being opened is not one we could send to.
+//!
+//! Values stored before encryption was enabled are read as they are.
+//! `pii rotate-keys` seals them, and reseals those sealed with a retired key.
+use crate::configuration::PiiEncryptionSettings;
+use crate::database::ObserveQuery;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Marks sealed values. `SubscriberName::parse` keeps names from starting
+/// with it, so that no plaintext reads as sealed.
+pub(crate) const PREFIX: &str = "pii:";
+const NONCE_LENGTH: usize = 12;
+
+struct Keyring {
+    active_key: String,
+    keys: HashMap<String, Aes256Gcm>,
+    index_key: Secret<Vec<u8>>,
+}
+
+/// Seals and opens personal data. It lets values through unchanged when
+/// encryption is not configured.
+#[derive(Clone, Default)]
+pub struct PiiCipher(Option<Arc<Keyring>>);
+
+impl PiiCipher {
+    pub fn new(settings: Option<&PiiEncryptionSettings>) -> Result<Self, String> {
+        let Some(settings) = settings else {
+            return Ok(Self(None));
+        };
+        let decode_key = |name: &str, key: &Secret<String>| {
+            STANDARD
+                .decode(key.expose_secret())
+                .ok()
+                .filter(|key| key.len() == 32)
+                .ok_or_else(|| format!("{} must be 32 bytes encoded in base64", name))
+        };
+        let mut keys = HashMap::new();
+        for (id, key) in &settings.keys {
+            // The id is part of the stored values.
+            if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+                return Err(format!("the key id {:?} is not alphanumeric", id));
+            }
+            let key = decode_key(&format!("the key {}", id), key)?;
+            keys.insert(id.clone(), Aes256Gcm::new_from_slice(&key).unwrap());
+        }
+        if !keys.contains_key(&settings.active_key) {
+            return Err(format!(
+                "the active key {} is not in the keyring",
+                settings.active_key
+            ));
+        }
+        Ok(Self(Some(Arc::new(Keyring {
+            active_key: settings.active_key.clone(),
+            keys,
+            index_key: Secret::new(decode_key("the index key", &settings.index_key)?),
+        }))))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn seal(&self, value: &str) -> String {
+        let Some(keyring) = &self.0 else {
+            return value.to_owned();
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = keyring.keys[&keyring.active_key]
+            .encrypt(&nonce, value.as_bytes())
+            .expect("Encrypting in memory does not fail");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        format!(
+            "{}{}:{}",
+            PREFIX,
+            keyring.active_key,
+            STANDARD.encode(sealed)
+        )
+    }
+
+    pub fn open(&self, stored: &str) -> Result<String, anyhow::Error> {
+        let Some(sealed) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_owned());
+        };
+        let keyring = self
+            .0
+            .as_ref()
+            .context("A value is sealed but PII encryption is not configured")?;
+        let (key_id, sealed) = sealed.split_once(':').context("Malformed sealed value")?;
+        let key = keyring
+            .keys
+            .get(key_id)
+            .with_context(|| format!("The key {} is not in the keyring", key_id))?;
+        let sealed = STANDARD.decode(sealed).context("Malformed sealed value")?;
+        if sealed.len() < NONCE_LENGTH {
+            return Err(anyhow!("Malformed sealed value"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let plaintext = key
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to open a sealed value"))?;
+        String::from_utf8(plaintext).context("A sealed value is not UTF-8")
+    }
+
+    pub fn seal_email(&self, email: &str) -> String {
+        match (&self.0, email.rsplit_once('@')) {
+            (Some(_), Some((_, domain))) => format!("{}@{}", self.seal(email), domain),
+            _ => self.seal(email),
+        }
+    }
+
+    pub fn open_email(&self, stored: &str) -> Result<String, anyhow::Error> {
+        if !stored.starts_with(PREFIX) {
+            return Ok(stored.to_owned());
+        }
+        let sealed = stored.rsplit_once('@').map_or(stored, |(sealed, _)| sealed);
+        self.open(sealed)
+    }
+
+    /// The blind index of `email`, when encryption is configured.
+    pub fn email_index(&self, email: &str) -> Option<String> {
+        let keyring = self.0.as_ref()?;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(keyring.index_key.expose_secret())
+            .expect("HMAC accepts keys of any length");
+        mac.update(email.trim().to_lowercase().as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+/// Seal, with the active key, the subscribers stored in clear or under a
+/// retired key, `batch_size` at a time. Returns how many were resealed.
+///
+/// The numbers waiting for their verification code are left as they are:
+/// the codes expire within minutes.
+#[tracing::instrument(name = "Rotate the PII encryption keys", skip(pool, cipher))]
+pub async fn rotate_keys(
+    pool: &PgPool,
+    cipher: &PiiCipher,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let Some(keyring) = &cipher.0 else {
+        return Err(anyhow!("PII encryption is not configured"));
+    };
+    let active = format!("{}{}:%", PREFIX, keyring.active_key);
+    let mut resealed = 0;
+    loop {
+        let mut transaction = pool.begin().await?;
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, email, name, phone_number FROM subscriptions
+            WHERE email NOT LIKE $1 OR name NOT LIKE $1 OR phone_number NOT LIKE $1
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+            "#,
+            active,
+            batch_size.max(1)
+        )
+        .fetch_all(&mut *transaction)
+        .observe("select_subscribers_to_reseal")
+        .await?;
+        if rows.is_empty() {
+            return Ok(resealed);
+        }
+        for row in &rows {
+            reseal_subscriber(
+                &mut transaction,
+                cipher,
+                row.id,
+                &row.email,
+                &row.name,
+                row.phone_number.as_deref(),
+            )
+            .await?;
+        }
+        transaction.commit().await?;
+        resealed += rows.len() as u64;
+    }
+}
+
+/// The stored email is copied into the snapshots, the queue and the
+/// delivery log: the copies follow it, or they would no longer match.
+async fn reseal_subscriber(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    cipher: &PiiCipher,
+    subscriber_id: Uuid,
+    stored_email: &str,
+    stored_name: &str,
+    stored_phone_number: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let email = cipher.open_email(stored_email)?;
+    let sealed_email = cipher.seal_email(&email);
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET email = $2, name = $3, email_index = $4, phone_number = $5
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        sealed_email,
+        cipher.seal(&cipher.open(stored_name)?),
+        cipher.email_index(&email),
+        stored_phone_number
+            .map(|phone_number| cipher.open(phone_number))
+            .transpose()?
+            .map(|phone_number| cipher.seal(&phone_number))
+    )
+    .execute(&mut **transaction)
+    .observe("reseal_subscriber")
+    .await?;
+    sqlx::query!(
+        "UPDATE recipient_snapshot_members SET subscriber_email = $2 WHERE subscriber_email = $1",
+        stored_email,
+        sealed_email
+    )
+    .execute(&mut **transaction)
+    .observe("reseal_snapshot_members")
+    .await?;
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+        stored_email,
+        sealed_email
+    )
+    .execute(&mut **transaction)
+    .observe("reseal_delivery_tasks")
+    .await?;
+    sqlx::query!(
+        "UPDATE email_deliveries SET subscriber_email = $2 WHERE subscriber_email = $1",
+        stored_email,
+        sealed_email
+    )
+    .execute(&mut **transaction)
+    .observe("reseal_email_deliveries")
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PiiCipher;
+    use crate::configuration::PiiEncryptionSettings;
+    use secrecy::Secret;
+
+    const KEY_1: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+    const KEY_2: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+    fn cipher(active_key: &str, keys: &[(&str, &str)]) -> PiiCipher {
+        PiiCipher::new(Some(&PiiEncryptionSettings {
+            active_key: active_key.into(),
+            keys: keys
+                .iter()
+                .map(|(id, key)| (id.to_string(), Secret::new(key.to_string())))
+                .collect(),
+            index_key: Secret::new(KEY_1.into()),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn a_sealed_email_keeps_its_domain_and_opens_back() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+        let sealed = cipher.seal_email("ursula@example.com");
+        assert!(sealed.starts_with("pii:k1:"));
+        assert!(sealed.ends_with("@example.com"));
+        assert!(!sealed.contains("ursula"));
+        assert_ne!(sealed, cipher.seal_email("ursula@example.com"));
+        assert_eq!(cipher.open_email(&sealed).unwrap(), "ursula@example.com");
+    }
+
+    #[test]
+    fn values_sealed_with_a_retired_key_still_open() {
+        let sealed = cipher("k1", &[("k1", KEY_1)]).seal("Ursula");
+        let rotated = cipher("k2", &[("k1", KEY_1), ("k2", KEY_2)]);
+        assert_eq!(rotated.open(&sealed).unwrap(), "Ursula");
+        assert!(rotated.seal("Ursula").starts_with("pii:k2:"));
+        assert!(cipher("k2", &[("k2", KEY_2)]).open(&sealed).is_err());
+    }
+
+    #[test]
+    fn the_email_index_ignores_case() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+        assert_eq!(
+            cipher.email_index("Ursula@Example.com"),
+            cipher.email_index("ursula@example.com")
+        );
+        assert_eq!(PiiCipher::default().email_index("ursula@example.com"), None);
+    }
+
+    #[test]
+    fn without_a_keyring_values_are_stored_in_clear() {
+        let cipher = PiiCipher::default();
+        assert_eq!(
+            cipher.seal_email("ursula@example.com"),
+            "ursula@example.com"
+        );
+        assert_eq!(cipher.open("Ursula").unwrap(), "Ursula");
+    }
+}
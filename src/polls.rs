@@ -0,0 +1,371 @@
+//! One-click polls: each option of an issue's poll is a link, and following
+//! it counts the vote of the subscriber the issue was sent to.
+//!
+//! The poll goes in place of a `{{poll}}` tag, or at the end of the issue.
+//! The vote links are rendered for each delivery: they carry a token signed
+//! for the recipient, so that every subscriber votes once.
+use crate::database::ObserveQuery;
+use crate::links;
+use crate::token_signer::TokenSigner;
+use chrono::{Duration, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const TAG: &str = "{{poll}}";
+const VOTE_PURPOSE: &str = "poll_vote";
+/// How long the links of an issue count votes.
+const VOTE_TTL_DAYS: i64 = 90;
+const MAX_QUESTION_LENGTH: usize = 500;
+const MAX_OPTION_LENGTH: usize = 200;
+const MAX_OPTIONS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct Poll {
+    pub poll_id: Uuid,
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+impl Poll {
+    /// `None` without a question: the issue has no poll. The options are
+    /// one per line.
+    pub fn parse(question: &str, options: &str) -> Result<Option<Self>, String> {
+        let question = question.trim();
+        if question.is_empty() {
+            return Ok(None);
+        }
+        if question.chars().count() > MAX_QUESTION_LENGTH {
+            return Err(format!(
+                "The poll question must be at most {} characters long.",
+                MAX_QUESTION_LENGTH
+            ));
+        }
+        let options: Vec<String> = options
+            .lines()
+            .map(str::trim)
+            .filter(|option| !option.is_empty())
+            .map(String::from)
+            .collect();
+        if !(2..=MAX_OPTIONS).contains(&options.len()) {
+            return Err(format!(
+                "A poll needs between 2 and {} options, one per line.",
+                MAX_OPTIONS
+            ));
+        }
+        if options
+            .iter()
+            .any(|option| option.chars().count() > MAX_OPTION_LENGTH)
+        {
+            return Err(format!(
+                "The poll options must be at most {} characters long.",
+                MAX_OPTION_LENGTH
+            ));
+        }
+        Ok(Some(Self {
+            poll_id: Uuid::new_v4(),
+            question: question.to_owned(),
+            options,
+        }))
+    }
+
+    /// The HTML and text content of an issue with the poll in place of the
+    /// `{{poll}}` tag, or at the end without one. `link` is where each
+    /// option leads, by index.
+    pub fn render(
+        &self,
+        html_content: &str,
+        text_content: &str,
+        link: impl Fn(usize) -> String,
+    ) -> (String, String) {
+        let mut html_block = format!("<p>{}</p><ul>", htmlescape::encode_minimal(&self.question));
+        let mut text_block = self.question.clone();
+        for (index, option) in self.options.iter().enumerate() {
+            let link = link(index);
+            html_block.push_str(&format!(
+                r#"<li><a href="{}">{}</a></li>"#,
+                htmlescape::encode_minimal(&link),
+                htmlescape::encode_minimal(option)
+            ));
+            text_block.push_str(&format!("\n- {}: {}", option, link));
+        }
+        html_block.push_str("</ul>");
+        (
+            place(html_content, &html_block),
+            place(text_content, &text_block),
+        )
+    }
+}
+
+fn place(content: &str, block: &str) -> String {
+    if content.contains(TAG) {
+        content.replace(TAG, block)
+    } else {
+        format!("{}\n{}", content, block)
+    }
+}
+
+/// The vote links of the issues we send, and the check of the votes
+/// coming back.
+#[derive(Clone)]
+pub struct PollLinks {
+    base_url: String,
+    signer: Arc<TokenSigner>,
+}
+
+impl PollLinks {
+    pub fn new(base_url: String, signer: Arc<TokenSigner>) -> Self {
+        Self { base_url, signer }
+    }
+
+    /// The link voting for `option` as `subscriber_id`.
+    pub fn vote(&self, poll_id: Uuid, option: usize, subscriber_id: Uuid) -> String {
+        let delivery = self.signer.sign(
+            VOTE_PURPOSE,
+            &format!("{}.{}", poll_id, subscriber_id),
+            Utc::now() + Duration::days(VOTE_TTL_DAYS),
+        );
+        links::poll_vote(poll_id, option, &delivery).absolute(&self.base_url)
+    }
+
+    pub fn results(&self, poll_id: Uuid) -> String {
+        links::poll_results(poll_id).absolute(&self.base_url)
+    }
+
+    /// The subscriber `delivery` was signed for, if it was for `poll_id`.
+    pub fn voter(&self, poll_id: Uuid, delivery: &str) -> Option<Uuid> {
+        let payload = self.signer.verify(VOTE_PURPOSE, delivery).ok()?;
+        let (poll, subscriber_id) = payload.split_once('.')?;
+        if poll != poll_id.to_string() {
+            return None;
+        }
+        subscriber_id.parse().ok()
+    }
+}
+
+#[tracing::instrument(skip(transaction, poll), fields(poll_id = %poll.poll_id))]
+pub async fn insert_poll(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    poll: &Poll,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO polls (poll_id, newsletter_issue_id, question, options, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        poll.poll_id,
+        issue_id,
+        poll.question,
+        &poll.options
+    );
+    transaction.execute(query).observe("insert_poll").await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_poll(pool: &PgPool, issue_id: Uuid) -> Result<Option<Poll>, sqlx::Error> {
+    sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT poll_id, question, options
+        FROM polls
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .observe("get_issue_poll")
+    .await
+}
+
+/// The content of `issue_id` as sent to the subscriber stored as `email`,
+/// with their vote links. Addresses no subscriber is stored with anymore
+/// get links to the results.
+#[tracing::instrument(skip_all)]
+pub async fn render_for_delivery(
+    pool: &PgPool,
+    links: &PollLinks,
+    issue_id: Uuid,
+    email: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(String, String), sqlx::Error> {
+    let Some(poll) = get_issue_poll(pool, issue_id).await? else {
+        return Ok((html_content.to_owned(), text_content.to_owned()));
+    };
+    let voter = sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_optional(pool)
+        .observe("get_poll_voter")
+        .await?
+        .map(|r| r.id);
+    Ok(
+        poll.render(html_content, text_content, |option| match voter {
+            Some(subscriber_id) => links.vote(poll.poll_id, option, subscriber_id),
+            None => links.results(poll.poll_id),
+        }),
+    )
+}
+
+/// Count the vote of `subscriber_id`, unless they voted already. Returns
+/// whether it was counted.
+#[tracing::instrument(skip(pool))]
+pub async fn record_vote(
+    pool: &PgPool,
+    poll_id: Uuid,
+    option: usize,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO poll_votes (poll_id, subscriber_id, option_index, voted_at)
+        SELECT $1, id, $3, now() FROM subscriptions WHERE id = $2
+        ON CONFLICT DO NOTHING
+        "#,
+        poll_id,
+        subscriber_id,
+        option as i16
+    )
+    .execute(pool)
+    .observe("record_poll_vote")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OptionResult {
+    pub option: String,
+    pub votes: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PollResults {
+    pub poll_id: Uuid,
+    pub question: String,
+    pub options: Vec<OptionResult>,
+    pub votes: i64,
+}
+
+impl PollResults {
+    fn new(poll: Poll, counts: &[(i16, i64)]) -> Self {
+        let options: Vec<OptionResult> = poll
+            .options
+            .into_iter()
+            .enumerate()
+            .map(|(index, option)| OptionResult {
+                option,
+                votes: counts
+                    .iter()
+                    .find(|(i, _)| *i as usize == index)
+                    .map_or(0, |(_, votes)| *votes),
+            })
+            .collect();
+        Self {
+            poll_id: poll.poll_id,
+            question: poll.question,
+            votes: options.iter().map(|option| option.votes).sum(),
+            options,
+        }
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_poll(pool: &PgPool, poll_id: Uuid) -> Result<Option<Poll>, sqlx::Error> {
+    sqlx::query_as!(
+        Poll,
+        "SELECT poll_id, question, options FROM polls WHERE poll_id = $1",
+        poll_id
+    )
+    .fetch_optional(pool)
+    .observe("get_poll")
+    .await
+}
+
+/// The votes of each option of `poll` so far.
+#[tracing::instrument(skip(pool, poll), fields(poll_id = %poll.poll_id))]
+pub async fn poll_results(pool: &PgPool, poll: Poll) -> Result<PollResults, sqlx::Error> {
+    let counts = sqlx::query!(
+        r#"
+        SELECT option_index, COUNT(*) AS "votes!"
+        FROM poll_votes
+        WHERE poll_id = $1
+        GROUP BY option_index
+        "#,
+        poll.poll_id
+    )
+    .fetch_all(pool)
+    .observe("get_poll_results")
+    .await?;
+    let counts: Vec<(i16, i64)> = counts
+        .into_iter()
+        .map(|r| (r.option_index, r.votes))
+        .collect();
+    Ok(PollResults::new(poll, &counts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Poll, PollLinks, PollResults};
+    use crate::configuration::TokenSigningSettings;
+    use crate::token_signer::TokenSigner;
+    use claims::{assert_err, assert_none};
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn poll() -> Poll {
+        Poll::parse("Tea or coffee?", "Tea\n\n  Coffee & cake \n")
+            .unwrap()
+            .unwrap()
+    }
+
+    fn poll_links() -> PollLinks {
+        let signer = TokenSigner::new(&TokenSigningSettings {
+            current_key: "k1".into(),
+            keys: [("k1".to_string(), Secret::new("k".repeat(32)))].into(),
+        })
+        .unwrap();
+        PollLinks::new("https://news.example".into(), Arc::new(signer))
+    }
+
+    #[test]
+    fn a_poll_needs_a_question_and_options() {
+        assert_none!(Poll::parse(" ", "Tea\nCoffee").unwrap());
+        assert_err!(Poll::parse("Tea or coffee?", "Tea"));
+        assert_err!(Poll::parse("Tea or coffee?", &"Tea\n".repeat(11)));
+        assert_eq!(poll().options, vec!["Tea", "Coffee & cake"]);
+    }
+
+    #[test]
+    fn every_option_is_a_link() {
+        let (html, text) = poll().render("<p>Hi</p>{{poll}}", "Hi", |option| {
+            format!("https://news.example/{}", option)
+        });
+        assert_eq!(
+            html,
+            r#"<p>Hi</p><p>Tea or coffee?</p><ul><li><a href="https://news.example/0">Tea</a></li><li><a href="https://news.example/1">Coffee &amp; cake</a></li></ul>"#
+        );
+        assert_eq!(
+            text,
+            "Hi\nTea or coffee?\n- Tea: https://news.example/0\n- Coffee & cake: https://news.example/1"
+        );
+    }
+
+    #[test]
+    fn a_vote_link_only_counts_for_its_poll() {
+        let links = poll_links();
+        let (poll_id, subscriber_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let link = links.vote(poll_id, 1, subscriber_id);
+        let delivery = link.split_once("?d=").unwrap().1;
+        assert_eq!(links.voter(poll_id, delivery), Some(subscriber_id));
+        assert_eq!(links.voter(Uuid::new_v4(), delivery), None);
+    }
+
+    #[test]
+    fn options_without_votes_count_zero() {
+        let results = PollResults::new(poll(), &[(1, 3)]);
+        assert_eq!(results.votes, 3);
+        assert_eq!(results.options[0].votes, 0);
+        assert_eq!(results.options[1].votes, 3);
+    }
+}
@@ -0,0 +1,193 @@
+//! Signups that look like bots, held back for an admin to review.
+//!
+//! A quarantined subscriber is stored like any other, token included, but
+//! is not sent a confirmation email until approved. Rejected signups, and
+//! the ones left in quarantine for too long, are deleted, and purged with
+//! the other deleted subscribers.
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::pii::PiiCipher;
+use crate::routes::send_confirmation_email;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Quarantine a signup", skip(executor))]
+pub async fn quarantine<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+    reason: &str,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'quarantined', quarantine_reason = $2, quarantined_at = $3
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        reason,
+        now
+    )
+    .execute(executor)
+    .observe("quarantine_subscriber")
+    .await?;
+    Ok(())
+}
+
+pub struct QuarantinedSignup {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub reason: String,
+    pub form_source: Option<String>,
+    pub client_ip: Option<String>,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// The signups in quarantine, oldest first.
+#[tracing::instrument(skip(pool, pii))]
+pub async fn list_quarantined(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    limit: i64,
+) -> Result<Vec<QuarantinedSignup>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            s.quarantine_reason AS "reason!",
+            s.quarantined_at AS "quarantined_at!",
+            c.form_source AS "form_source?",
+            c.client_ip AS "client_ip?"
+        FROM subscriptions s
+        LEFT JOIN LATERAL (
+            SELECT form_source, client_ip FROM consent_records
+            WHERE subscriber_id = s.id AND action = 'subscribed'
+            ORDER BY recorded_at DESC
+            LIMIT 1
+        ) c ON true
+        WHERE s.status = 'quarantined' AND s.deleted_at IS NULL
+        ORDER BY s.quarantined_at
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .observe("list_quarantined_signups")
+    .await?;
+    let mut signups = Vec::with_capacity(rows.len());
+    for row in rows {
+        signups.push(QuarantinedSignup {
+            subscriber_id: row.id,
+            email: pii.open_email(&row.email)?,
+            name: pii.open(&row.name)?,
+            reason: row.reason,
+            form_source: row.form_source,
+            client_ip: row.client_ip,
+            quarantined_at: row.quarantined_at,
+        });
+    }
+    Ok(signups)
+}
+
+/// Let the signups out of quarantine and send them their confirmation
+/// email. Returns how many were approved; ids that are not in quarantine
+/// are skipped.
+#[tracing::instrument(skip(pool, email_client, pii, base_url))]
+pub async fn approve_signups(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    pii: &PiiCipher,
+    base_url: &str,
+    subscriber_ids: &[Uuid],
+) -> Result<usize, anyhow::Error> {
+    let mut approved = 0;
+    for subscriber_id in subscriber_ids {
+        // Left in quarantine if the email cannot be sent.
+        let mut transaction = pool.begin().await?;
+        let Some(subscriber) = sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET status = 'pending_confirmation', quarantine_reason = NULL, quarantined_at = NULL
+            WHERE id = $1 AND status = 'quarantined' AND deleted_at IS NULL
+            RETURNING email, name
+            "#,
+            subscriber_id
+        )
+        .fetch_optional(&mut *transaction)
+        .observe("approve_quarantined_signup")
+        .await?
+        else {
+            continue;
+        };
+        let token = sqlx::query!(
+            "SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        )
+        .fetch_one(&mut *transaction)
+        .observe_one("get_quarantined_subscription_token")
+        .await?
+        .subscription_token;
+        let new_subscriber = NewSubscriber {
+            email: SubscriberEmail::parse(pii.open_email(&subscriber.email)?)?,
+            name: SubscriberName::parse(pii.open(&subscriber.name)?)?,
+            locale: None,
+        };
+        let sent = send_confirmation_email(email_client, new_subscriber, base_url, &token)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to send a confirmation email to approved subscriber {}",
+                    subscriber_id
+                )
+            })?;
+        record_send(&mut *transaction, &sent).await?;
+        transaction.commit().await?;
+        approved += 1;
+    }
+    Ok(approved)
+}
+
+/// Delete the signups in quarantine. Returns how many were rejected.
+#[tracing::instrument(skip(pool))]
+pub async fn reject_signups(pool: &PgPool, subscriber_ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET deleted_at = now()
+        WHERE id = ANY($1) AND status = 'quarantined' AND deleted_at IS NULL
+        "#,
+        subscriber_ids
+    )
+    .execute(pool)
+    .observe("reject_quarantined_signups")
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Reject the signups quarantined before `quarantined_before`.
+#[tracing::instrument(skip(pool))]
+pub async fn expire_quarantine(
+    pool: &PgPool,
+    quarantined_before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET deleted_at = now()
+        WHERE status = 'quarantined' AND deleted_at IS NULL AND quarantined_at < $1
+        "#,
+        quarantined_before
+    )
+    .execute(pool)
+    .observe("expire_quarantined_signups")
+    .await?;
+    Ok(result.rows_affected())
+}
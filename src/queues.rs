@@ -0,0 +1,206 @@
+//! The work waiting in the database, as seen by the operators: how much of
+//! it, how old, and how much of it fails.
+//!
+//! Only the delivery queue and the events outbox are queues: sends and
+//! broker relays are the only work we defer.
+use crate::database::ObserveQuery;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+/// The failure rates are computed over this window.
+const FAILURE_RATE_WINDOW_MINUTES: i64 = 60;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Queue {
+    /// `issue_delivery_queue`, emptied by the delivery workers.
+    Delivery,
+    /// `events_outbox`, emptied by the broker relay.
+    Outbox,
+}
+
+impl Queue {
+    pub const ALL: [Queue; 2] = [Queue::Delivery, Queue::Outbox];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Queue::Delivery => "delivery",
+            Queue::Outbox => "outbox",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|queue| queue.as_str() == name)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct QueueStats {
+    pub name: &'static str,
+    /// The items waiting.
+    pub depth: i64,
+    pub oldest_item_age_seconds: Option<i64>,
+    /// The items being worked on, when the queue keeps track of them.
+    pub in_flight: Option<i64>,
+    /// The items that failed and are not retried unless asked to.
+    pub failed: i64,
+    /// Over the last hour, `None` without any attempt.
+    pub failure_rate: Option<f64>,
+}
+
+pub async fn get_queue_stats(pool: &PgPool, queue: Queue) -> Result<QueueStats, sqlx::Error> {
+    let since = Utc::now() - Duration::minutes(FAILURE_RATE_WINDOW_MINUTES);
+    match queue {
+        Queue::Delivery => delivery_stats(pool, since).await,
+        Queue::Outbox => outbox_stats(pool, since).await,
+    }
+}
+
+/// The tasks carry no timestamp: a task is as old as its issue. A task in
+/// flight is one whose delivery was claimed, to be sent or being sent.
+#[tracing::instrument(skip(pool))]
+async fn delivery_stats(
+    pool: &PgPool,
+    since: chrono::DateTime<Utc>,
+) -> Result<QueueStats, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "depth!",
+            (
+                SELECT EXTRACT(EPOCH FROM now() - MIN(i.published_at))::BIGINT
+                FROM issue_delivery_queue q
+                JOIN newsletter_issues i USING (newsletter_issue_id)
+            ) AS oldest_item_age_seconds,
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue q
+                JOIN subscriptions s ON s.email = q.subscriber_email
+                JOIN delivery_claims c
+                    ON c.newsletter_issue_id = q.newsletter_issue_id AND c.subscriber_id = s.id
+            ) AS "in_flight!",
+            (
+                SELECT COUNT(DISTINCT (d.newsletter_issue_id, d.subscriber_email))
+                FROM email_deliveries d
+                WHERE NOT d.succeeded AND NOT EXISTS (
+                    SELECT 1 FROM email_deliveries o
+                    WHERE o.newsletter_issue_id = d.newsletter_issue_id
+                        AND o.subscriber_email = d.subscriber_email
+                        AND o.succeeded
+                )
+            ) AS "failed!",
+            (
+                SELECT AVG(CASE WHEN succeeded THEN 0.0 ELSE 1.0 END)::FLOAT8
+                FROM email_deliveries
+                WHERE attempted_at >= $1
+            ) AS failure_rate
+        "#,
+        since
+    )
+    .fetch_one(pool)
+    .observe_one("delivery_queue_stats")
+    .await?;
+    Ok(QueueStats {
+        name: Queue::Delivery.as_str(),
+        depth: r.depth,
+        oldest_item_age_seconds: r.oldest_item_age_seconds,
+        in_flight: Some(r.in_flight),
+        failed: r.failed,
+        failure_rate: r.failure_rate,
+    })
+}
+
+/// The relay retries failing events on its own, the failure rate is the
+/// share of the recent events that failed at least once.
+#[tracing::instrument(skip(pool))]
+async fn outbox_stats(
+    pool: &PgPool,
+    since: chrono::DateTime<Utc>,
+) -> Result<QueueStats, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM events_outbox WHERE published_at IS NULL) AS "depth!",
+            (
+                SELECT EXTRACT(EPOCH FROM now() - MIN(created_at))::BIGINT
+                FROM events_outbox
+                WHERE published_at IS NULL
+            ) AS oldest_item_age_seconds,
+            (
+                SELECT COUNT(*)
+                FROM events_outbox
+                WHERE published_at IS NULL AND attempts > 0
+            ) AS "failed!",
+            (
+                SELECT AVG(CASE WHEN attempts > 0 THEN 1.0 ELSE 0.0 END)::FLOAT8
+                FROM events_outbox
+                WHERE created_at >= $1
+            ) AS failure_rate
+        "#,
+        since
+    )
+    .fetch_one(pool)
+    .observe_one("outbox_stats")
+    .await?;
+    Ok(QueueStats {
+        name: Queue::Outbox.as_str(),
+        depth: r.depth,
+        oldest_item_age_seconds: r.oldest_item_age_seconds,
+        in_flight: None,
+        failed: r.failed,
+        failure_rate: r.failure_rate,
+    })
+}
+
+/// Put back in the queue the deliveries that failed and never succeeded
+/// since, to subscribers still around. Their issues go back in progress.
+/// Returns how many deliveries were requeued.
+#[tracing::instrument(skip(pool))]
+pub async fn retry_failed_deliveries(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        WITH failed AS (
+            SELECT DISTINCT d.newsletter_issue_id, d.subscriber_email, s.id AS subscriber_id
+            FROM email_deliveries d
+            JOIN subscriptions s ON s.email = d.subscriber_email AND s.deleted_at IS NULL
+            WHERE NOT d.succeeded AND NOT EXISTS (
+                SELECT 1 FROM email_deliveries o
+                WHERE o.newsletter_issue_id = d.newsletter_issue_id
+                    AND o.subscriber_email = d.subscriber_email
+                    AND o.succeeded
+            )
+        ), released AS (
+            DELETE FROM delivery_claims c
+            USING failed f
+            WHERE c.newsletter_issue_id = f.newsletter_issue_id
+                AND c.subscriber_id = f.subscriber_id
+        ), requeued AS (
+            INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            SELECT newsletter_issue_id, subscriber_email FROM failed
+            ON CONFLICT DO NOTHING
+            RETURNING newsletter_issue_id
+        ), resumed AS (
+            UPDATE newsletter_issues
+            SET status = 'in_progress'
+            WHERE newsletter_issue_id IN (SELECT newsletter_issue_id FROM requeued)
+        )
+        SELECT COUNT(*) AS "requeued!" FROM requeued
+        "#
+    )
+    .fetch_one(pool)
+    .observe_one("retry_failed_deliveries")
+    .await?;
+    Ok(r.requeued as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Queue;
+
+    #[test]
+    fn queues_are_found_by_name() {
+        for queue in Queue::ALL {
+            assert_eq!(Queue::parse(queue.as_str()), Some(queue));
+        }
+        assert_eq!(Queue::parse("jobs"), None);
+    }
+}
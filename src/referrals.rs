@@ -0,0 +1,394 @@
+//! Referrals: every subscriber has a share link, and the subscribers who
+//! sign up through it and confirm count towards the rewards of the one who
+//! shared it.
+//!
+//! Rewards are automated on the event bus: a confirmation that brings a
+//! referrer to a milestone publishes a
+//! [`DomainEvent::ReferralMilestoneReached`], which sends them the email of
+//! the milestone.
+//!
+//! Issues show a subscriber their count and share link in place of a
+//! `{{referrals}}` tag.
+use crate::configuration::{ReferralMilestone, ReferralSettings};
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, MessageStream};
+use crate::events::{DomainEvent, EventBus};
+use crate::links;
+use crate::pii::PiiCipher;
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const TAG: &str = "{{referrals}}";
+const CODE_LENGTH: usize = 12;
+
+/// Referral codes are ours: anything else is no code of a subscriber.
+pub fn is_valid_code(code: &str) -> bool {
+    code.len() == CODE_LENGTH && code.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+fn generate_code() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(CODE_LENGTH)
+        .collect()
+}
+
+/// The referral code of `subscriber_id`, given one on first use.
+#[tracing::instrument(skip(pool))]
+pub async fn referral_code(pool: &PgPool, subscriber_id: Uuid) -> Result<String, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET referral_code = COALESCE(referral_code, $2)
+        WHERE id = $1
+        RETURNING referral_code AS "referral_code!"
+        "#,
+        subscriber_id,
+        generate_code()
+    )
+    .fetch_one(pool)
+    .observe_one("get_referral_code")
+    .await?;
+    Ok(r.referral_code)
+}
+
+/// Whether `code` is the referral code of a subscriber.
+#[tracing::instrument(skip(pool))]
+pub async fn is_known_code(pool: &PgPool, code: &str) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM subscriptions WHERE referral_code = $1 AND deleted_at IS NULL
+        ) AS "known!"
+        "#,
+        code
+    )
+    .fetch_one(pool)
+    .observe_one("find_referral_code")
+    .await?;
+    Ok(r.known)
+}
+
+/// Credit the owner of `code` with the signup of `subscriber_id`. Unknown
+/// codes, and one's own, credit nobody.
+#[tracing::instrument(skip(transaction))]
+pub async fn record_referrer(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    code: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s SET referred_by = r.id
+        FROM subscriptions r
+        WHERE s.id = $1 AND r.referral_code = $2 AND r.id <> $1
+        "#,
+        subscriber_id,
+        code
+    )
+    .execute(&mut **transaction)
+    .observe("record_referrer")
+    .await?;
+    Ok(())
+}
+
+/// The milestones the referrer of `subscriber_id` reached with their
+/// confirmation. Each is reached once, even when confirmations race past it.
+#[tracing::instrument(skip(pool, milestones))]
+async fn credit_referrer(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    milestones: &[ReferralMilestone],
+) -> Result<Vec<DomainEvent>, sqlx::Error> {
+    let milestones: Vec<i32> = milestones
+        .iter()
+        .map(|milestone| milestone.referrals as i32)
+        .collect();
+    let reached = sqlx::query!(
+        r#"
+        WITH referrer AS (
+            SELECT
+                s.referred_by AS id,
+                (
+                    SELECT COUNT(*) FROM subscriptions r
+                    WHERE r.referred_by = s.referred_by
+                        AND r.status = 'confirmed'
+                        AND r.deleted_at IS NULL
+                ) AS referrals
+            FROM subscriptions s
+            WHERE s.id = $1 AND s.referred_by IS NOT NULL
+        )
+        INSERT INTO referral_rewards (subscriber_id, milestone, reached_at)
+        SELECT referrer.id, milestone, now()
+        FROM referrer, UNNEST($2::INT[]) AS milestone
+        WHERE milestone <= referrer.referrals
+        ON CONFLICT DO NOTHING
+        RETURNING subscriber_id, milestone
+        "#,
+        subscriber_id,
+        &milestones
+    )
+    .fetch_all(pool)
+    .observe("credit_referrer")
+    .await?;
+    Ok(reached
+        .into_iter()
+        .map(|r| DomainEvent::ReferralMilestoneReached {
+            subscriber_id: r.subscriber_id,
+            referrals: r.milestone.into(),
+        })
+        .collect())
+}
+
+/// Credits referrers as their referrals confirm, and sends the reward of
+/// each milestone they reach.
+pub struct ReferralRewards {
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    pii: PiiCipher,
+    milestones: Vec<ReferralMilestone>,
+    event_bus: EventBus,
+}
+
+impl ReferralRewards {
+    pub fn new(
+        pool: PgPool,
+        email_client: Arc<EmailClient>,
+        pii: PiiCipher,
+        settings: ReferralSettings,
+        event_bus: EventBus,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            pii,
+            milestones: settings.milestones,
+            event_bus,
+        }
+    }
+
+    /// Subscribe to the event bus, unless there is no reward to send.
+    pub fn spawn(self) {
+        if self.milestones.is_empty() {
+            return;
+        }
+        let event_bus = self.event_bus.clone();
+        let rewards = Arc::new(self);
+        event_bus.spawn_subscriber("referral_rewards", move |event| {
+            let rewards = rewards.clone();
+            async move {
+                if let Err(e) = rewards.handle(event).await {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to reward a referrer"
+                    );
+                }
+            }
+        });
+    }
+
+    async fn handle(&self, event: DomainEvent) -> Result<(), anyhow::Error> {
+        match event {
+            DomainEvent::SubscriberConfirmed { subscriber_id } => {
+                for event in credit_referrer(&self.pool, subscriber_id, &self.milestones).await? {
+                    self.event_bus.publish(event).await?;
+                }
+            }
+            DomainEvent::ReferralMilestoneReached {
+                subscriber_id,
+                referrals,
+            } => {
+                if let Some(milestone) = self
+                    .milestones
+                    .iter()
+                    .find(|milestone| milestone.referrals == referrals)
+                {
+                    send_reward(
+                        &self.pool,
+                        &self.email_client,
+                        &self.pii,
+                        subscriber_id,
+                        milestone,
+                    )
+                    .await?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[tracing::instrument(skip(pool, email_client, pii, milestone), fields(referrals = milestone.referrals))]
+async fn send_reward(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    pii: &PiiCipher,
+    subscriber_id: Uuid,
+    milestone: &ReferralMilestone,
+) -> Result<(), anyhow::Error> {
+    let Some(subscriber) = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 AND deleted_at IS NULL",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .observe("get_referrer")
+    .await?
+    else {
+        return Ok(());
+    };
+    let email =
+        SubscriberEmail::parse(pii.open_email(&subscriber.email)?).map_err(anyhow::Error::from)?;
+    let sent = email_client
+        .send_email(
+            &email,
+            &milestone.subject,
+            &milestone.html_content,
+            &milestone.text_content,
+            MessageStream::Transactional,
+        )
+        .await
+        .context("Failed to send a referral reward")?;
+    record_send(pool, &sent).await?;
+    Ok(())
+}
+
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub subscriber_id: Uuid,
+    /// As stored: sealed with PII encryption on.
+    pub name: String,
+    pub referrals: i64,
+}
+
+/// The subscribers with the most confirmed referrals, ties sharing a rank.
+#[tracing::instrument(skip(pool))]
+pub async fn leaderboard(pool: &PgPool, size: i64) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        LeaderboardEntry,
+        r#"
+        WITH counts AS (
+            SELECT referred_by AS subscriber_id, COUNT(*) AS referrals
+            FROM subscriptions
+            WHERE referred_by IS NOT NULL
+                AND status = 'confirmed'
+                AND deleted_at IS NULL
+            GROUP BY referred_by
+        )
+        SELECT
+            RANK() OVER (ORDER BY c.referrals DESC) AS "rank!",
+            s.id AS subscriber_id,
+            s.name,
+            c.referrals AS "referrals!"
+        FROM counts c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        WHERE s.deleted_at IS NULL
+        ORDER BY c.referrals DESC, s.subscribed_at
+        LIMIT $1
+        "#,
+        size
+    )
+    .fetch_all(pool)
+    .observe("get_referral_leaderboard")
+    .await
+}
+
+/// The content of an issue as sent to the subscriber stored as `email`,
+/// with their count and share link in place of the `{{referrals}}` tag.
+#[tracing::instrument(skip_all)]
+pub async fn render_for_delivery(
+    pool: &PgPool,
+    base_url: &str,
+    email: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(String, String), sqlx::Error> {
+    if !html_content.contains(TAG) && !text_content.contains(TAG) {
+        return Ok((html_content.to_owned(), text_content.to_owned()));
+    }
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            (
+                SELECT COUNT(*) FROM subscriptions r
+                WHERE r.referred_by = s.id
+                    AND r.status = 'confirmed'
+                    AND r.deleted_at IS NULL
+            ) AS "referrals!"
+        FROM subscriptions s
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .observe("get_subscriber_referrals")
+    .await?
+    else {
+        return Ok((html_content.replace(TAG, ""), text_content.replace(TAG, "")));
+    };
+    let code = referral_code(pool, subscriber.id).await?;
+    let link = links::referral(&code).absolute(base_url);
+    Ok(render(
+        html_content,
+        text_content,
+        subscriber.referrals,
+        &link,
+    ))
+}
+
+fn render(html_content: &str, text_content: &str, referrals: i64, link: &str) -> (String, String) {
+    let count = match referrals {
+        0 => "Nobody joined through your link yet.".to_owned(),
+        1 => "1 reader joined through your link.".to_owned(),
+        n => format!("{} readers joined through your link.", n),
+    };
+    let html_block = format!(
+        r#"<p>{} Share it: <a href="{}">{}</a></p>"#,
+        count,
+        htmlescape::encode_minimal(link),
+        htmlescape::encode_minimal(link)
+    );
+    let text_block = format!("{} Share it: {}", count, link);
+    (
+        html_content.replace(TAG, &html_block),
+        text_content.replace(TAG, &text_block),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_code, is_valid_code, render};
+
+    #[test]
+    fn generated_codes_are_valid() {
+        assert!(is_valid_code(&generate_code()));
+        assert!(!is_valid_code("short"));
+        assert!(!is_valid_code("abc/def-ghij"));
+    }
+
+    #[test]
+    fn the_block_shows_the_count_and_the_link() {
+        let (html, text) = render(
+            "<p>Hi</p>{{referrals}}",
+            "Hi\n{{referrals}}",
+            2,
+            "https://news.example/r/abc",
+        );
+        assert_eq!(
+            html,
+            r#"<p>Hi</p><p>2 readers joined through your link. Share it: <a href="https://news.example/r/abc">https://news.example/r/abc</a></p>"#
+        );
+        assert_eq!(
+            text,
+            "Hi\n2 readers joined through your link. Share it: https://news.example/r/abc"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Settings that can change without restarting the server.
+use crate::configuration::{get_configuration, SendQuotaSettings, Settings};
+use crate::issue_delivery_worker::DeliveryPolicy;
+use crate::telemetry::reload_log_filter;
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// The part of the configuration applied on reload. Everything else is only
+/// read at startup.
+pub struct HotSettings {
+    /// The warm-up schedule and the failure-rate alerts.
+    pub delivery: DeliveryPolicy,
+    pub send_quota: Option<SendQuotaSettings>,
+    pub log_level: String,
+}
+
+impl HotSettings {
+    pub fn from_settings(configuration: &Settings) -> Self {
+        Self {
+            delivery: DeliveryPolicy::from_settings(configuration),
+            send_quota: configuration.send_quota.clone(),
+            log_level: configuration.log_level.clone(),
+        }
+    }
+}
+
+/// Shared by the API and the background worker: a reload is visible to both.
+#[derive(Clone)]
+pub struct ReloadableSettings(Arc<ArcSwap<HotSettings>>);
+
+impl ReloadableSettings {
+    pub fn new(configuration: &Settings) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(HotSettings::from_settings(
+            configuration,
+        ))))
+    }
+
+    pub fn load(&self) -> Arc<HotSettings> {
+        self.0.load_full()
+    }
+
+    /// Swap in the hot settings of `configuration`. Nothing changes if any of
+    /// them is invalid.
+    pub fn apply(&self, configuration: &Settings) -> Result<(), anyhow::Error> {
+        let settings = HotSettings::from_settings(configuration);
+        reload_log_filter(&settings.log_level)?;
+        self.0.store(Arc::new(settings));
+        Ok(())
+    }
+
+    /// Re-read the configuration files and environment.
+    #[tracing::instrument(name = "Reload the configuration", skip(self))]
+    pub fn reload(&self) -> Result<(), anyhow::Error> {
+        let configuration = get_configuration().context("Failed to read the configuration")?;
+        self.apply(&configuration)
+    }
+}
+
+/// Reload the configuration whenever the process receives SIGHUP.
+pub async fn run_reload_on_sighup(settings: ReloadableSettings) -> Result<(), anyhow::Error> {
+    let mut hangups = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+    while hangups.recv().await.is_some() {
+        match settings.reload() {
+            Ok(()) => tracing::info!("Reloaded the configuration"),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to reload the configuration"
+            ),
+        }
+    }
+    Ok(())
+}
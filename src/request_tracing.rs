@@ -0,0 +1,333 @@
+//! Correlate our logs with the load balancer's: reuse the request id and
+//! trace context it forwards, when it is one of our trusted proxies.
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest};
+use actix_web_lab::middleware::Next;
+use ipnet::IpNet;
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RequestId, RootSpanBuilder};
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The proxies allowed to vouch for the client IP, request id and trace
+/// context of the requests they forward.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        parse_ip_ranges(entries).map(Self)
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|range| range.contains(&ip))
+    }
+
+    /// Walk `X-Forwarded-For` from the right - the hop closest to us - and
+    /// stop at the first address we don't trust: anything further left could
+    /// have been made up by the client.
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        if !self.contains(peer) {
+            return peer;
+        }
+        let mut client = peer;
+        for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+            let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = hop;
+            if !self.contains(hop) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The clients allowed to reach `/admin`.
+#[derive(Clone, Debug)]
+pub struct AdminAllowlist(Vec<IpNet>);
+
+impl AdminAllowlist {
+    pub fn parse(entries: &[String]) -> Result<Self, String> {
+        parse_ip_ranges(entries).map(Self)
+    }
+
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|range| range.contains(&ip))
+    }
+}
+
+/// Each entry is either a CIDR range (`10.0.0.0/8`) or a single address.
+fn parse_ip_ranges(entries: &[String]) -> Result<Vec<IpNet>, String> {
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("{} is neither an IP address nor a CIDR range", entry))
+        })
+        .collect()
+}
+
+/// How the current request is identified in the logs.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub trace_id: Option<String>,
+    pub client_ip: Option<IpAddr>,
+}
+
+/// The address of the client behind our trusted proxies, for the handlers
+/// that throttle, screen or audit by IP.
+///
+/// Falls back to the peer address outside of `propagate_request_context`;
+/// extraction fails when neither is known, e.g. over a Unix socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl std::fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromRequest for ClientIp {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let client_ip = req
+            .extensions()
+            .get::<RequestContext>()
+            .and_then(|context| context.client_ip)
+            .or_else(|| req.peer_addr().map(|address| address.ip()));
+        ready(
+            client_ip
+                .map(ClientIp)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("The client address is unknown")),
+        )
+    }
+}
+
+/// An upstream request id is reused if it is short, printable ASCII.
+fn parse_request_id(value: &str) -> Option<String> {
+    let valid =
+        !value.is_empty() && value.len() <= 128 && value.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| value.to_owned())
+}
+
+/// The trace id of a W3C `traceparent` header, e.g.
+/// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+fn parse_traceparent(value: &str) -> Option<String> {
+    let mut parts = value.trim().split('-');
+    let (version, trace_id, parent_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let valid = is_hex(version, 2)
+        && version != "ff"
+        && is_hex(trace_id, 32)
+        && trace_id.bytes().any(|b| b != b'0')
+        && is_hex(parent_id, 16)
+        && is_hex(flags, 2);
+    valid.then(|| trace_id.to_owned())
+}
+
+/// Work out the `RequestContext` for `TracingLogger`, then echo the request
+/// id back in the response headers.
+pub async fn propagate_request_context(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let trusted_proxies = req
+        .app_data::<web::Data<TrustedProxies>>()
+        .map(|proxies| proxies.get_ref().clone())
+        .unwrap_or_default();
+    let peer = req.peer_addr().map(|address| address.ip());
+    let from_trusted_proxy = peer.is_some_and(|peer| trusted_proxies.contains(peer));
+    let header = |name: &str| {
+        from_trusted_proxy
+            .then(|| req.headers().get(name)?.to_str().ok())
+            .flatten()
+    };
+    let context = RequestContext {
+        request_id: header("X-Request-Id")
+            .and_then(parse_request_id)
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        trace_id: header("traceparent").and_then(parse_traceparent),
+        client_ip: peer.map(|peer| {
+            trusted_proxies.client_ip(
+                peer,
+                req.headers()
+                    .get("X-Forwarded-For")
+                    .and_then(|value| value.to_str().ok()),
+            )
+        }),
+    };
+    let request_id = HeaderValue::from_str(&context.request_id).ok();
+    req.extensions_mut().insert(context);
+
+    let echo_request_id = |headers: &mut HeaderMap| {
+        if let Some(request_id) = request_id {
+            headers.insert(REQUEST_ID_HEADER, request_id);
+        }
+    };
+    match next.call(req).await {
+        Ok(mut response) => {
+            echo_request_id(response.headers_mut());
+            Ok(response.map_into_boxed_body())
+        }
+        // Errors from inner middlewares only become responses further up:
+        // render it now to attach the header.
+        Err(e) => {
+            let mut response = e.error_response();
+            echo_request_id(response.headers_mut());
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// The root span of `TracingLogger`, with the request id, trace id and
+/// client IP of the `RequestContext`.
+pub struct PropagatedRootSpanBuilder;
+
+impl RootSpanBuilder for PropagatedRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let context = request.extensions().get::<RequestContext>().cloned();
+        let request_id = match &context {
+            Some(context) => context.request_id.clone(),
+            None => request
+                .extensions()
+                .get::<RequestId>()
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+        };
+        let trace_id = context.as_ref().and_then(|c| c.trace_id.clone());
+        let client_ip = context
+            .as_ref()
+            .and_then(|c| c.client_ip)
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+        let http_route = request.match_pattern().unwrap_or_else(|| "default".into());
+        let connection_info = request.connection_info();
+        tracing::info_span!(
+            "HTTP request",
+            http.method = %request.method(),
+            http.route = %http_route,
+            http.flavor = ?request.version(),
+            http.scheme = %connection_info.scheme(),
+            http.host = %connection_info.host(),
+            http.client_ip = %client_ip,
+            http.user_agent = %request
+                .headers()
+                .get("User-Agent")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default(),
+            http.target = %request.uri().path_and_query().map(|p| p.as_str()).unwrap_or_default(),
+            http.status_code = tracing::field::Empty,
+            otel.name = %format!("HTTP {} {}", request.method(), http_route),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            trace_id = trace_id,
+            request_id = %request_id,
+            exception.message = tracing::field::Empty,
+            exception.details = tracing::field::Empty,
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_request_id, parse_traceparent, ClientIp, RequestContext, TrustedProxies};
+    use actix_web::test::TestRequest;
+    use actix_web::{FromRequest, HttpMessage};
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn the_trace_id_is_read_from_a_valid_traceparent() {
+        assert_eq!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+        );
+        assert_eq!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
+        );
+        assert_eq!(parse_traceparent("00-4BF9-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent("garbage"), None);
+    }
+
+    #[test]
+    fn request_ids_must_be_short_and_printable() {
+        assert!(parse_request_id("lb-1234").is_some());
+        assert!(parse_request_id("").is_none());
+        assert!(parse_request_id("with space").is_none());
+        assert!(parse_request_id(&"a".repeat(129)).is_none());
+    }
+
+    #[test]
+    fn forwarded_for_is_only_walked_through_trusted_hops() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8".into(), "192.168.1.1".into()]).unwrap();
+        let xff = Some("6.6.6.6, 1.2.3.4, 10.0.0.2");
+        // An untrusted peer can't vouch for anybody.
+        assert_eq!(proxies.client_ip(ip("8.8.8.8"), xff), ip("8.8.8.8"));
+        // The first untrusted hop from the right is the client.
+        assert_eq!(proxies.client_ip(ip("192.168.1.1"), xff), ip("1.2.3.4"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), None), ip("10.0.0.1"));
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), Some("not-an-ip, 10.0.0.3")),
+            ip("10.0.0.3")
+        );
+    }
+
+    #[actix_web::test]
+    async fn the_client_ip_comes_from_the_request_context() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .to_http_request();
+        req.extensions_mut().insert(RequestContext {
+            request_id: "lb-1234".into(),
+            trace_id: None,
+            client_ip: Some(ip("1.2.3.4")),
+        });
+        let client_ip = ClientIp::extract(&req).await.unwrap();
+        assert_eq!(client_ip, ClientIp(ip("1.2.3.4")));
+    }
+
+    #[actix_web::test]
+    async fn the_client_ip_falls_back_to_the_peer_address() {
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .to_http_request();
+        assert_eq!(
+            ClientIp::extract(&req).await.unwrap(),
+            ClientIp(ip("10.0.0.1"))
+        );
+        assert!(ClientIp::extract(&TestRequest::default().to_http_request())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_trusted_proxies_are_rejected() {
+        assert!(TrustedProxies::parse(&["10.0.0.0/33".into()]).is_err());
+        assert!(TrustedProxies::parse(&["proxy.internal".into()]).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! How long each class of data is kept.
+//!
+//! The windows are enforced by the purge job, along with the purge of
+//! deleted subscribers, and listed at `/admin/retention` for compliance
+//! reviews.
+use crate::configuration::{DataRetentionSettings, SubscriberRetentionSettings};
+use crate::database::ObserveQuery;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+/// The send quota counts the deliveries of the current month.
+const MIN_DELIVERY_LOG_DAYS: u32 = 31;
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct RetentionRule {
+    pub data_class: &'static str,
+    pub description: &'static str,
+    /// `None` when the data is kept forever.
+    pub retention_days: Option<u32>,
+}
+
+/// The effective retention policy.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+}
+
+impl RetentionPolicy {
+    pub fn new(
+        subscribers: &SubscriberRetentionSettings,
+        data: &DataRetentionSettings,
+    ) -> Result<Self, String> {
+        let windows = [
+            ("audit_log_days", data.audit_log_days),
+            ("consent_ip_days", data.consent_ip_days),
+            ("delivery_log_days", data.delivery_log_days),
+            ("landing_visits_days", data.landing_visits_days),
+        ];
+        if let Some((name, _)) = windows.iter().find(|(_, days)| *days == Some(0)) {
+            return Err(format!("{} must be at least 1", name));
+        }
+        if data
+            .delivery_log_days
+            .is_some_and(|days| days < MIN_DELIVERY_LOG_DAYS)
+        {
+            return Err(format!(
+                "delivery_log_days must be at least {}: the send quota counts the deliveries of \
+                 the current month",
+                MIN_DELIVERY_LOG_DAYS
+            ));
+        }
+        Ok(Self {
+            rules: vec![
+                RetentionRule {
+                    data_class: "deleted_subscribers",
+                    description: "Deleted subscribers, purged once they can no longer be restored",
+                    retention_days: Some(subscribers.retention_days),
+                },
+                RetentionRule {
+                    data_class: "audit_log",
+                    description: "The domain events",
+                    retention_days: data.audit_log_days,
+                },
+                RetentionRule {
+                    data_class: "consent_ip_addresses",
+                    description: "The IP address and user agent of consent records",
+                    retention_days: data.consent_ip_days,
+                },
+                RetentionRule {
+                    data_class: "delivery_log",
+                    description: "The attempts to deliver newsletter issues",
+                    retention_days: data.delivery_log_days,
+                },
+                RetentionRule {
+                    data_class: "landing_visits",
+                    description:
+                        "The website visits of the readers who followed a link of an issue",
+                    retention_days: data.landing_visits_days,
+                },
+            ],
+        })
+    }
+}
+
+/// What an enforcement of the retention windows removed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RetentionOutcome {
+    pub audit_events_deleted: u64,
+    pub consent_records_anonymised: u64,
+    pub delivery_attempts_deleted: u64,
+    pub landing_visits_deleted: u64,
+}
+
+/// Remove the data older than its retention window, as of `now`. Deleted
+/// subscribers are purged on their own, by `purge_deleted_subscribers`.
+#[tracing::instrument(name = "Enforce the data retention windows", skip(pool, settings))]
+pub async fn enforce_retention(
+    pool: &PgPool,
+    settings: &DataRetentionSettings,
+    now: DateTime<Utc>,
+) -> Result<RetentionOutcome, sqlx::Error> {
+    let cutoff = |days: u32| now - Duration::days(days.into());
+    let mut outcome = RetentionOutcome::default();
+    if let Some(days) = settings.audit_log_days {
+        outcome.audit_events_deleted = sqlx::query!(
+            "DELETE FROM domain_events WHERE occurred_at < $1",
+            cutoff(days)
+        )
+        .execute(pool)
+        .observe("delete_expired_domain_events")
+        .await?
+        .rows_affected();
+    }
+    if let Some(days) = settings.consent_ip_days {
+        outcome.consent_records_anonymised = sqlx::query!(
+            r#"
+            UPDATE consent_records SET client_ip = NULL, user_agent = NULL
+            WHERE recorded_at < $1 AND (client_ip IS NOT NULL OR user_agent IS NOT NULL)
+            "#,
+            cutoff(days)
+        )
+        .execute(pool)
+        .observe("anonymise_expired_consent_records")
+        .await?
+        .rows_affected();
+    }
+    if let Some(days) = settings.delivery_log_days {
+        outcome.delivery_attempts_deleted = sqlx::query!(
+            "DELETE FROM email_deliveries WHERE attempted_at < $1",
+            cutoff(days)
+        )
+        .execute(pool)
+        .observe("delete_expired_email_deliveries")
+        .await?
+        .rows_affected();
+    }
+    if let Some(days) = settings.landing_visits_days {
+        outcome.landing_visits_deleted = sqlx::query!(
+            "DELETE FROM landing_visits WHERE visited_at < $1",
+            cutoff(days)
+        )
+        .execute(pool)
+        .observe("delete_expired_landing_visits")
+        .await?
+        .rows_affected();
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetentionPolicy;
+    use crate::configuration::{DataRetentionSettings, SubscriberRetentionSettings};
+
+    fn subscribers() -> SubscriberRetentionSettings {
+        SubscriberRetentionSettings {
+            retention_days: 30,
+            purge_interval_seconds: 3600,
+        }
+    }
+
+    #[test]
+    fn the_delivery_log_must_cover_a_month_of_quota() {
+        let data = DataRetentionSettings {
+            delivery_log_days: Some(7),
+            ..Default::default()
+        };
+        assert!(RetentionPolicy::new(&subscribers(), &data).is_err());
+        let data = DataRetentionSettings {
+            delivery_log_days: Some(31),
+            ..Default::default()
+        };
+        assert!(RetentionPolicy::new(&subscribers(), &data).is_ok());
+    }
+
+    #[test]
+    fn empty_windows_are_rejected() {
+        let data = DataRetentionSettings {
+            consent_ip_days: Some(0),
+            ..Default::default()
+        };
+        assert!(RetentionPolicy::new(&subscribers(), &data).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+use crate::api_keys::{get_api_key_usage, ApiKeys};
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+
+pub async fn api_key_usage(
+    key_id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    api_keys: Option<web::Data<ApiKeys>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(key) = api_keys.as_ref().and_then(|keys| keys.get(&key_id)) else {
+        return Ok(HttpResponse::NotFound().body("No such API key."));
+    };
+    let usage = get_api_key_usage(&pool, key, Utc::now())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(usage))
+}
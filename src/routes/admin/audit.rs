@@ -0,0 +1,41 @@
+use crate::admin_audit::get_audit_analytics;
+use crate::authentication::{is_owner, UserId};
+use crate::utils::e500;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct AnalyticsParameters {
+    #[serde(default = "default_days")]
+    days: i64,
+}
+
+fn default_days() -> i64 {
+    30
+}
+
+/// Who did how much and when, and the failed logins, to spot a compromised
+/// account. Owners only, since it covers every admin.
+#[tracing::instrument(
+    name = "Show the audit analytics",
+    skip(pool, parameters, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn audit_analytics(
+    parameters: web::Query<AnalyticsParameters>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_owner(&pool, **user_id).await.map_err(e500)? {
+        return Ok(HttpResponse::Forbidden().body("Only owners can see the audit analytics."));
+    }
+    let days = parameters.days.clamp(1, 90);
+    let analytics = get_audit_analytics(&pool, days, Utc::now())
+        .await
+        .context("Failed to compute the audit analytics")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(analytics))
+}
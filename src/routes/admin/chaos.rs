@@ -0,0 +1,24 @@
+use crate::authentication::UserId;
+use crate::chaos::{self, Faults};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+
+pub async fn chaos_faults() -> HttpResponse {
+    HttpResponse::Ok().json(chaos::current())
+}
+
+#[tracing::instrument(
+    name = "Set the injected faults",
+    skip(faults, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn set_chaos_faults(faults: web::Json<Faults>, user_id: ReqData<UserId>) -> HttpResponse {
+    let faults = faults.into_inner();
+    match chaos::set(faults.clone()) {
+        Ok(()) => {
+            tracing::warn!(?faults, "Injecting faults");
+            HttpResponse::Ok().json(faults)
+        }
+        Err(e) => HttpResponse::BadRequest().body(e),
+    }
+}
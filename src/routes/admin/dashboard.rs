@@ -1,14 +1,21 @@
+use crate::cost_ledger::current_month_spend;
+use crate::database::{retry_read, ObserveQuery};
+use crate::reload::ReloadableSettings;
 use crate::session_state::TypedSession;
 use crate::utils::e500;
 use actix_web::http::header::LOCATION;
 use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: web::Data<PgPool>,
+    settings: web::Data<ReloadableSettings>,
+    flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
         get_username(user_id, &pool).await.map_err(e500)?
@@ -17,6 +24,30 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
+    let warm_up_html = match &settings.load().delivery.warm_up {
+        Some(warm_up) => match warm_up.remaining_quota(&pool).await.map_err(e500)? {
+            Some(remaining) => {
+                format!("<p>Sender warm-up: {remaining} more emails can be sent today.</p>")
+            }
+            None => String::new(),
+        },
+        None => String::new(),
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut spend_html = String::new();
+    for spend in current_month_spend(&pool).await.map_err(e500)? {
+        writeln!(
+            spend_html,
+            "<li>{}: {} emails, {:.2} estimated</li>",
+            htmlescape::encode_minimal(&spend.provider),
+            spend.emails_sent,
+            spend.estimated_cost
+        )
+        .unwrap();
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -27,10 +58,24 @@ pub async fn admin_dashboard(
     <title>Admin dashboard</title>
 </head>
 <body>
+    {msg_html}
     <p>Welcome {username}!</p>
+    {warm_up_html}
+    <p>Sending costs this month:</p>
+    <ul>{spend_html}</ul>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/operations">Bulk operations</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/quarantine">Quarantined signups</a></li>
+        <li><a href="/admin/sessions">Active sessions</a></li>
+        <li><a href="/admin/snippets">Snippets</a></li>
+        <li><a href="/admin/sms">SMS announcements</a></li>
+        <li>
+          <form name="reloadForm" action="/admin/settings/reload" method="post">
+            <input type="submit" value="Reload configuration">
+          </form>
+        </li>
         <li>
           <form name="logoutForm" action="/admin/logout" method="post">
             <input type="submit" value="Logout">
@@ -44,15 +89,18 @@ pub async fn admin_dashboard(
 
 #[tracing::instrument(name = "Get username", skip(pool))]
 pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
-    let row = sqlx::query!(
-        r#"
-        SELECT username
-        FROM users
-        WHERE user_id = $1
-        "#,
-        user_id,
-    )
-    .fetch_one(pool)
+    let row = retry_read(|| {
+        sqlx::query!(
+            r#"
+            SELECT username
+            FROM users
+            WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .fetch_one(pool)
+        .observe_one("get_username")
+    })
     .await
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
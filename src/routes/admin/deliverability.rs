@@ -0,0 +1,6 @@
+use crate::deliverability::DnsChecker;
+use actix_web::{web, HttpResponse};
+
+pub async fn check_dns_records(checker: web::Data<DnsChecker>) -> HttpResponse {
+    HttpResponse::Ok().json(checker.check().await)
+}
@@ -1,9 +1,52 @@
+mod api_keys;
+mod audit;
+#[cfg(feature = "chaos")]
+mod chaos;
 mod dashboard;
+mod deliverability;
 mod logout;
 mod newsletter;
+mod notifications;
+mod operations;
 mod password;
+mod quarantine;
+mod queues;
+mod quota;
+mod referrals;
+mod retention;
+mod sessions;
+mod settings;
+mod sms;
+mod snippets;
+mod sponsors;
+mod subscribers;
+mod view_as;
 
+pub use api_keys::api_key_usage;
+pub use audit::audit_analytics;
+#[cfg(feature = "chaos")]
+pub use chaos::{chaos_faults, set_chaos_faults};
 pub use dashboard::admin_dashboard;
+pub use deliverability::check_dns_records;
 pub use logout::log_out;
 pub use newsletter::*;
+pub use notifications::admin_notifications;
+pub use operations::{
+    admin_operations, resume_admin_operation, roll_back_admin_operation, start_admin_operation,
+};
 pub use password::*;
+pub use quarantine::{quarantine_queue, review_quarantine};
+pub use queues::{queue_stats, retry_failed};
+pub use quota::send_quota_usage;
+pub use referrals::referral_leaderboard;
+pub use retention::retention_policy;
+pub use sessions::{admin_sessions, revoke_admin_session, revoke_other_admin_sessions};
+pub use settings::reload_settings;
+pub use sms::{send_sms_blast, sms_blast_form};
+pub use snippets::{save_snippet_version, snippet_library};
+pub use sponsors::sponsor_report;
+pub use subscribers::{
+    delete_subscriber, merge_subscriber, reset_subscriber_links, restore_subscriber,
+    subscriber_consent,
+};
+pub use view_as::view_as_subscriber;
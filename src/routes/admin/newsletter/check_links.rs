@@ -0,0 +1,23 @@
+use crate::link_checker::{extract_links, LinkChecker, LinkStatus};
+use actix_web::{web, HttpResponse};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    html_content: String,
+}
+
+/// Check the links of an issue before publishing it.
+pub async fn check_newsletter_links(
+    form: web::Form<FormData>,
+    checker: web::Data<LinkChecker>,
+) -> HttpResponse {
+    let links = checker.check(extract_links(&form.html_content)).await;
+    let count = |predicate: fn(&LinkStatus) -> bool| {
+        links.iter().filter(|link| predicate(&link.status)).count()
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "broken": count(|s| matches!(s, LinkStatus::Broken { .. })),
+        "redirected": count(|s| matches!(s, LinkStatus::Redirected { .. })),
+        "links": links,
+    }))
+}
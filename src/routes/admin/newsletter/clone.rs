@@ -0,0 +1,76 @@
+use crate::authentication::UserId;
+use crate::issue_drafts::{
+    clone_issue, parse_template_name, save_issue_as_template, SaveTemplateOutcome,
+};
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Open a new draft with the content and settings of the issue.
+#[tracing::instrument(
+    name = "Clone a newsletter issue",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn clone_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = clone_issue(&pool, issue_id.into_inner(), **user_id)
+        .await
+        .context("Failed to clone a newsletter issue.")
+        .map_err(e500)?;
+    match draft_id {
+        Some(draft_id) => Ok(see_other(&format!("/admin/newsletters?draft={}", draft_id))),
+        None => {
+            FlashMessage::error("There is no issue with the provided id.").send();
+            Ok(see_other("/admin/newsletters"))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct TemplateFormData {
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Save a newsletter issue as a template",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn save_issue_template(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<TemplateFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match parse_template_name(&form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let outcome = save_issue_as_template(&pool, issue_id.into_inner(), name)
+        .await
+        .context("Failed to save a newsletter issue as a template.")
+        .map_err(e500)?;
+    match outcome {
+        SaveTemplateOutcome::Saved(_) => {
+            FlashMessage::info(format!("The template {} has been saved.", name)).send()
+        }
+        SaveTemplateOutcome::IssueNotFound => {
+            FlashMessage::error("There is no issue with the provided id.").send()
+        }
+        SaveTemplateOutcome::NameTaken => {
+            FlashMessage::error(format!("There is already a template named {}.", name)).send()
+        }
+    }
+    Ok(see_other("/admin/newsletters"))
+}
@@ -1,15 +1,106 @@
+use crate::issue_drafts::{get_draft, get_template, list_drafts, list_templates, IssueDraft};
+use crate::utils::{e500, see_other};
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
-use actix_web_flash_messages::IncomingFlashMessages;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormQuery {
+    /// Fill the form in with a draft, published along with the form.
+    draft: Option<Uuid>,
+    /// Fill the form in with a template.
+    template: Option<Uuid>,
+}
+
+fn checked(value: bool) -> &'static str {
+    if value {
+        " checked"
+    } else {
+        ""
+    }
+}
 
 pub async fn publish_newsletter_form(
+    query: web::Query<FormQuery>,
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let draft = match (query.draft, query.template) {
+        (Some(draft_id), _) => get_draft(&pool, draft_id).await.map_err(e500)?,
+        (None, Some(template_id)) => get_template(&pool, template_id).await.map_err(e500)?,
+        (None, None) => Some(IssueDraft::default()),
+    };
+    let Some(draft) = draft else {
+        FlashMessage::error("There is no such draft or template.").send();
+        return Ok(see_other("/admin/newsletters"));
+    };
+    let draft_field = match query.draft {
+        Some(draft_id) => format!(r#"<input type="hidden" name="draft_id" value="{draft_id}">"#),
+        None => String::new(),
+    };
+    let mut drafts = String::new();
+    for draft in list_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts,
+            r#"<li><a href="/admin/newsletters?draft={}">{}</a>, {}</li>"#,
+            draft.draft_id,
+            encode_minimal(&draft.title),
+            draft.created_at.format("%Y-%m-%d %H:%M")
+        )
+        .unwrap();
+    }
+    let drafts_html = if drafts.is_empty() {
+        String::new()
+    } else {
+        format!("<p>Drafts waiting to be published:</p>\n    <ul>\n{drafts}    </ul>")
+    };
+    let mut template_options = String::new();
+    for template in list_templates(&pool).await.map_err(e500)? {
+        let selected = if query.template == Some(template.template_id) {
+            " selected"
+        } else {
+            ""
+        };
+        writeln!(
+            template_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            template.template_id,
+            selected,
+            encode_minimal(&template.name)
+        )
+        .unwrap();
+    }
+    let templates_html = if template_options.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#"<form action="/admin/newsletters" method="get">
+        <label>Start from a template:
+            <select name="template">{template_options}</select>
+        </label>
+        <button type="submit">Use template</button>
+    </form>"#
+        )
+    };
+    let title = encode_attribute(&draft.title);
+    let text_content = encode_minimal(&draft.text_content);
+    let html_content = encode_minimal(&draft.html_content);
+    let paid_only = checked(draft.paid_only);
+    let priority = checked(draft.priority);
+    let push = checked(draft.push);
+    let sponsor = encode_attribute(&draft.sponsor);
+    let sponsor_url = encode_attribute(&draft.sponsor_url);
+    let sponsor_message = encode_attribute(&draft.sponsor_message);
+    let poll_question = encode_attribute(&draft.poll_question);
+    let poll_options = encode_minimal(&draft.poll_options);
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -22,12 +113,16 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
     {msg_html}
+    {drafts_html}
+    {templates_html}
     <form action="/admin/newsletters" method="post">
+        {draft_field}
         <label>Title:<br>
             <input
                 type="text"
                 placeholder="Enter the issue title"
                 name="title"
+                value="{title}"
             >
         </label>
         <br>
@@ -37,7 +132,7 @@ pub async fn publish_newsletter_form(
                 name="text_content"
                 rows="20"
                 cols="50"
-            ></textarea>
+            >{text_content}</textarea>
         </label>
         <br>
         <label>HTML content:<br>
@@ -46,9 +141,57 @@ pub async fn publish_newsletter_form(
                 name="html_content"
                 rows="20"
                 cols="50"
-            ></textarea>
+            >{html_content}</textarea>
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="paid_only" value="true"{paid_only}>
+            Paid subscribers only
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="priority" value="true"{priority}>
+            Priority delivery, ahead of bulk sends
+        </label>
+        <br>
+        <label>
+            <input type="checkbox" name="push" value="true"{push}>
+            Also send as a push notification
         </label>
         <br>
+        <label>
+            <input type="checkbox" name="skip_telegram" value="true">
+            Don't post to the Telegram channel
+        </label>
+        <br>
+        <fieldset>
+            <legend>Sponsor slot, in place of <code>{{{{sponsor}}}}</code> or at the end</legend>
+            <label>Sponsor:<br>
+                <input type="text" name="sponsor" value="{sponsor}">
+            </label>
+            <br>
+            <label>Link:<br>
+                <input type="url" placeholder="https://" name="sponsor_url" value="{sponsor_url}">
+            </label>
+            <br>
+            <label>Message:<br>
+                <input type="text" name="sponsor_message" value="{sponsor_message}">
+            </label>
+        </fieldset>
+        <br>
+        <fieldset>
+            <legend>Poll, in place of <code>{{{{poll}}}}</code> or at the end</legend>
+            <label>Question:<br>
+                <input type="text" name="poll_question" value="{poll_question}">
+            </label>
+            <br>
+            <label>Options, one per line:<br>
+                <textarea name="poll_options" rows="5" cols="50">{poll_options}</textarea>
+            </label>
+        </fieldset>
+        <br>
+        <button type="submit" formaction="/admin/newsletters/check-links">Check links</button>
+        <button type="submit" formaction="/admin/newsletters/spam-check">Check for spam</button>
         <button type="submit">Publish</button>
     </form>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
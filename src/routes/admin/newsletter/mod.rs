@@ -1,5 +1,19 @@
+mod check_links;
+mod clone;
 mod get;
 mod post;
+mod report;
+mod resume;
+mod seeds;
+mod spam_check;
+mod variants;
 
+pub use check_links::check_newsletter_links;
+pub use clone::{clone_newsletter_issue, save_issue_template};
 pub use get::publish_newsletter_form;
 pub use post::publish_newsletter;
+pub use report::newsletter_issue_report;
+pub use resume::resume_newsletter_delivery;
+pub use seeds::report_seed_placement;
+pub use spam_check::check_newsletter_spam;
+pub use variants::attach_issue_variant;
@@ -1,83 +1,294 @@
 use crate::authentication::UserId;
-use crate::domain::SubscriberEmail;
+use crate::configuration::{SeedListSettings, TelegramSettings};
+use crate::database::ObserveQuery;
 use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{set_issue_status, DeliveryLane, IssueStatus};
+use crate::issue_drafts::delete_draft;
+use crate::issue_enqueue::{enqueue_issue, take_recipient_snapshot};
+use crate::polls::{insert_poll, Poll, PollLinks};
+use crate::reload::ReloadableSettings;
+use crate::seed_list::send_seed_copies;
+use crate::send_quota::monthly_usage;
+use crate::snippets::{expand_issue, record_issue_snippets, SnippetError};
+use crate::sponsorship::{insert_slot, SponsorSlot};
+use crate::startup::ApplicationBaseUrl;
+use crate::telegram::enqueue_telegram_post;
 use crate::utils::{e500, see_other};
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::PgPool;
+use chrono::Utc;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
+    /// Publish even if the issue would exceed the monthly send quota.
+    #[serde(default)]
+    override_quota: bool,
+    /// Only deliver the issue to subscribers on the paid tier.
+    #[serde(default)]
+    paid_only: bool,
+    /// Deliver ahead of the issues in the bulk lane.
+    #[serde(default)]
+    priority: bool,
+    /// Also send the issue as a push notification, to the subscribers'
+    /// registered browsers.
+    #[serde(default)]
+    push: bool,
+    /// Keep the issue off the Telegram channel the issues are mirrored to.
+    #[serde(default)]
+    skip_telegram: bool,
+    /// The sponsor of the issue's slot, none when empty.
+    #[serde(default)]
+    sponsor: String,
+    #[serde(default)]
+    sponsor_url: String,
+    #[serde(default)]
+    sponsor_message: String,
+    /// The question of the issue's poll, none when empty.
+    #[serde(default)]
+    poll_question: String,
+    /// One option per line.
+    #[serde(default)]
+    poll_options: String,
+    /// The draft the issue was written from, deleted once published.
+    draft_id: Option<Uuid>,
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool, email_client, user_id),
+    skip(
+        form,
+        pool,
+        settings,
+        user_id,
+        email_client,
+        seed_list,
+        base_url,
+        poll_links,
+        telegram
+    ),
     fields(user_id=%*user_id)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
     user_id: ReqData<UserId>,
     pool: web::Data<PgPool>,
+    settings: web::Data<ReloadableSettings>,
     email_client: web::Data<EmailClient>,
+    seed_list: Option<web::Data<SeedListSettings>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    poll_links: web::Data<PollLinks>,
+    telegram: Option<web::Data<TelegramSettings>>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let subscribers = get_confirmed_subscribers(&pool).await.map_err(e500)?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &form.title,
-                        &form.html_content,
-                        &form.text_content,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })
-                    .map_err(e500)?;
+    let slot = match SponsorSlot::parse(&form.sponsor, &form.sponsor_url, &form.sponsor_message) {
+        Ok(slot) => slot,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let poll = match Poll::parse(&form.poll_question, &form.poll_options) {
+        Ok(poll) => poll,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let usage = match &settings.load().send_quota {
+        Some(quota) => Some(
+            monthly_usage(&mut *transaction, quota)
+                .await
+                .context("Failed to compute the send quota usage")
+                .map_err(e500)?,
+        ),
+        None => None,
+    };
+    let lane = if form.priority {
+        DeliveryLane::Priority
+    } else {
+        DeliveryLane::Bulk
+    };
+    // The issue keeps the snippet content it was published with.
+    let mut expanded =
+        match expand_issue(&mut transaction, &form.html_content, &form.text_content).await {
+            Ok(expanded) => expanded,
+            Err(SnippetError::Unknown(name)) => {
+                FlashMessage::error(format!("There is no snippet named {}.", name)).send();
+                return Ok(see_other("/admin/newsletters"));
             }
-            Err(error) => {
-                tracing::warn!(
-                    error.cause_chain = ?error,
-                    error.message = %error,
-                    "Skipping a confirmed subscriber. Their stored contact details are invalid",
-                );
+            Err(e) => {
+                return Err(e500(
+                    anyhow::Error::from(e).context("Failed to expand the snippets of the issue"),
+                ))
             }
+        };
+    if let Some(slot) = &slot {
+        (expanded.html_content, expanded.text_content) =
+            slot.inject(&base_url.0, &expanded.html_content, &expanded.text_content);
+    }
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &form.title,
+        &expanded.text_content,
+        &expanded.html_content,
+        &form.text_content,
+        &form.html_content,
+        form.paid_only,
+        form.push,
+        lane,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    record_issue_snippets(&mut transaction, issue_id, &expanded.snippets)
+        .await
+        .context("Failed to record the snippets of the issue")
+        .map_err(e500)?;
+    if let Some(poll) = &poll {
+        insert_poll(&mut transaction, issue_id, poll)
+            .await
+            .context("Failed to store the poll of the issue")
+            .map_err(e500)?;
+    }
+    if let Some(draft_id) = form.draft_id {
+        delete_draft(&mut *transaction, draft_id)
+            .await
+            .context("Failed to delete the draft of the issue")
+            .map_err(e500)?;
+    }
+    if telegram.is_some() && !form.skip_telegram {
+        enqueue_telegram_post(&mut transaction, issue_id)
+            .await
+            .context("Failed to queue the Telegram post of the issue")
+            .map_err(e500)?;
+    }
+    if let Some(slot) = &slot {
+        insert_slot(&mut transaction, issue_id, slot)
+            .await
+            .context("Failed to store the sponsor slot of the issue")
+            .map_err(e500)?;
+    }
+    let recipients = take_recipient_snapshot(&mut transaction, issue_id, form.paid_only)
+        .await
+        .context("Failed to take a snapshot of the recipients")
+        .map_err(e500)?
+        .recipient_count;
+    if let Some(usage) = usage {
+        if recipients as i64 > usage.remaining && !form.override_quota {
+            // Dropping the transaction discards the issue.
+            return Ok(HttpResponse::PaymentRequired().json(serde_json::json!({
+                "error": "monthly_quota_exceeded",
+                "message": "Publishing this issue would exceed the monthly send quota.",
+                "limit": usage.limit,
+                "used": usage.used,
+                "requested": recipients,
+            })));
         }
     }
+    if recipients == 0 {
+        // There is nobody to deliver to: the worker would never pick this issue up.
+        set_issue_status(&mut *transaction, issue_id, IssueStatus::Completed)
+            .await
+            .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter issue.")
+        .map_err(e500)?;
+    // The seed inboxes get their copy ahead of every subscriber.
+    if let Some(seed_list) = seed_list {
+        // The seeds are no subscribers: their poll links lead to the results.
+        let (html_content, text_content) = match &poll {
+            Some(poll) => poll.render(&expanded.html_content, &expanded.text_content, |_| {
+                poll_links.results(poll.poll_id)
+            }),
+            None => (expanded.html_content, expanded.text_content),
+        };
+        send_seed_copies(
+            &pool,
+            &email_client,
+            &seed_list.inboxes,
+            issue_id,
+            &form.title,
+            &html_content,
+            &text_content,
+        )
+        .await
+        .context("Failed to send the seed copies")
+        .map_err(e500)?;
+    }
+    // Past this point the issue is published: should enqueueing fail
+    // half-way, the worker resumes it from its checkpoint.
+    enqueue_issue(&pool, issue_id, settings.load().delivery.enqueue_batch_size)
+        .await
+        .context("Failed to enqueue delivery tasks")
+        .map_err(e500)?;
     FlashMessage::info("The newsletter issue has been published!").send();
     Ok(see_other("/admin/newsletters"))
 }
 
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
-}
-
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
+/// `source_text_content` and `source_html_content` are the content as
+/// written, before its expansion.
+#[tracing::instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    source_text_content: &str,
+    source_html_content: &str,
+    paid_only: bool,
+    push: bool,
+    lane: DeliveryLane,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let query = sqlx::query!(
         r#"
-        SELECT email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            status,
+            paid_only,
+            push,
+            delivery_lane,
+            enqueue_completed,
+            source_text_content,
+            source_html_content
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, false, $10, $11)
         "#,
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
-    })
-    .collect();
-    Ok(confirmed_subscribers)
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content,
+        Utc::now(),
+        IssueStatus::InProgress.as_str(),
+        paid_only,
+        push,
+        lane.as_str(),
+        source_text_content,
+        source_html_content
+    );
+    transaction
+        .execute(query)
+        .observe("insert_newsletter_issue")
+        .await?;
+    Ok(newsletter_issue_id)
 }
@@ -0,0 +1,77 @@
+use crate::database::ObserveQuery;
+use crate::landing_analytics::get_landing_report;
+use crate::polls::{get_issue_poll, poll_results};
+use crate::seed_list::get_seed_placements;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How the delivery of an issue is going, along with where its seed copies
+/// landed, the votes of its poll and the visits it brought to the website.
+#[tracing::instrument(name = "Report on a newsletter issue", skip(pool))]
+pub async fn newsletter_issue_report(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let Some(issue) = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            status,
+            sent_count,
+            failed_count,
+            provider_throttles,
+            CASE WHEN throttled_until > now() THEN throttled_until END AS throttled_until,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+             WHERE q.newsletter_issue_id = i.newsletter_issue_id) AS "pending!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .observe("get_issue_report")
+    .await
+    .context("Failed to fetch the newsletter issue")
+    .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let seed_placements = get_seed_placements(&pool, issue_id)
+        .await
+        .context("Failed to fetch the seed placements")
+        .map_err(e500)?;
+    let poll = match get_issue_poll(&pool, issue_id)
+        .await
+        .context("Failed to fetch the poll")
+        .map_err(e500)?
+    {
+        Some(poll) => Some(
+            poll_results(&pool, poll)
+                .await
+                .context("Failed to count the poll votes")
+                .map_err(e500)?,
+        ),
+        None => None,
+    };
+    let landing = get_landing_report(&pool, issue_id)
+        .await
+        .context("Failed to count the landing visits")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "issue_id": issue_id,
+        "title": issue.title,
+        "status": issue.status,
+        "sent": issue.sent_count,
+        "failed": issue.failed_count,
+        "pending": issue.pending,
+        "provider_throttles": issue.provider_throttles,
+        "throttled_until": issue.throttled_until,
+        "seed_placements": seed_placements,
+        "poll": poll,
+        "landing": landing,
+    })))
+}
@@ -0,0 +1,44 @@
+use crate::authentication::UserId;
+use crate::database::ObserveQuery;
+use crate::issue_delivery_worker::IssueStatus;
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Resume the delivery of an issue that was paused by the failure-rate alert.
+#[tracing::instrument(
+    name = "Resume the delivery of a newsletter issue",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn resume_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2
+        WHERE newsletter_issue_id = $1 AND status = $3
+        "#,
+        issue_id.into_inner(),
+        IssueStatus::InProgress.as_str(),
+        IssueStatus::Paused.as_str()
+    )
+    .execute(pool.get_ref())
+    .observe("resume_newsletter_delivery")
+    .await
+    .context("Failed to resume the delivery of a newsletter issue.")
+    .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("There is no paused newsletter issue with the provided id.").send();
+    } else {
+        FlashMessage::info("The delivery of the newsletter issue has been resumed.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
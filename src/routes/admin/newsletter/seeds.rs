@@ -0,0 +1,46 @@
+use crate::authentication::UserId;
+use crate::seed_list::{record_placement, Placement, PlacementSource};
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct SeedPlacementFormData {
+    seed_email: String,
+    placement: Placement,
+}
+
+/// Record a placement checked by hand in a seed inbox.
+#[tracing::instrument(
+    name = "Report a seed placement",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, seed_email = %form.seed_email)
+)]
+pub async fn report_seed_placement(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<SeedPlacementFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let recorded = record_placement(
+        &pool,
+        issue_id,
+        &form.seed_email,
+        form.placement,
+        PlacementSource::Manual,
+    )
+    .await
+    .context("Failed to record a seed placement")
+    .map_err(e500)?;
+    if recorded {
+        FlashMessage::info("The seed placement has been recorded.").send();
+    } else {
+        FlashMessage::error("No seed copy of this issue was sent to that address.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
@@ -0,0 +1,25 @@
+use crate::spam_check::SpamAssassinClient;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Score an issue with SpamAssassin before publishing it.
+pub async fn check_newsletter_spam(
+    form: web::Form<FormData>,
+    spam_check: Option<web::Data<SpamAssassinClient>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(spam_check) = spam_check else {
+        return Ok(HttpResponse::NotFound().body("No spam check is configured."));
+    };
+    let report = spam_check
+        .check(&form.title, &form.text_content, &form.html_content)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,77 @@
+use crate::authentication::UserId;
+use crate::database::ObserveQuery;
+use crate::domain::Locale;
+use crate::utils::{e500, see_other};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct VariantFormData {
+    locale: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Attach a translation of an issue, or replace the one in the same locale.
+///
+/// The deliveries still queued pick it up: to have it sent to every
+/// subscriber in its locale, attach it while the issue is paused.
+#[tracing::instrument(
+    name = "Attach a translation to a newsletter issue",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, locale=%form.locale)
+)]
+pub async fn attach_issue_variant(
+    issue_id: web::Path<Uuid>,
+    form: web::Form<VariantFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let VariantFormData {
+        locale,
+        title,
+        text_content,
+        html_content,
+    } = form.into_inner();
+    let locale = match Locale::parse(locale) {
+        Ok(locale) => locale,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_variants
+            (newsletter_issue_id, locale, title, text_content, html_content)
+        SELECT newsletter_issue_id, $2, $3, $4, $5
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        ON CONFLICT (newsletter_issue_id, locale) DO UPDATE
+        SET title = EXCLUDED.title,
+            text_content = EXCLUDED.text_content,
+            html_content = EXCLUDED.html_content
+        "#,
+        issue_id.into_inner(),
+        locale.as_ref(),
+        title,
+        text_content,
+        html_content
+    )
+    .execute(pool.get_ref())
+    .observe("upsert_newsletter_issue_variant")
+    .await
+    .context("Failed to attach a translation to a newsletter issue.")
+    .map_err(e500)?;
+    if result.rows_affected() == 0 {
+        FlashMessage::error("There is no newsletter issue with the provided id.").send();
+    } else {
+        FlashMessage::info("The translation has been attached to the newsletter issue.").send();
+    }
+    Ok(see_other("/admin/newsletters"))
+}
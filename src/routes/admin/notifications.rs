@@ -0,0 +1,125 @@
+use crate::admin_events::{AdminEvent, AdminEventBroadcaster, AdminEventFilter};
+use crate::authentication::{is_logged_in, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use uuid::Uuid;
+
+/// How long a deactivated admin, or a revoked session, keeps the channel.
+const LOGIN_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    #[serde(default)]
+    events: String,
+}
+
+#[tracing::instrument(
+    name = "Open admin notifications channel",
+    skip(request, body, query, broadcaster, user_id, login, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn admin_notifications(
+    request: HttpRequest,
+    body: web::Payload,
+    query: web::Query<QueryParams>,
+    broadcaster: web::Data<AdminEventBroadcaster>,
+    user_id: web::ReqData<UserId>,
+    login: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter =
+        AdminEventFilter::parse(&query.events).map_err(actix_web::error::ErrorBadRequest)?;
+    let Some(session_id) = login.get_admin_session_id().map_err(e500)? else {
+        return Err(actix_web::error::ErrorUnauthorized("No admin session"));
+    };
+    let (response, session, msg_stream) = actix_ws::handle(&request, body)?;
+    actix_web::rt::spawn(forward_events(
+        session,
+        msg_stream,
+        broadcaster.subscribe(),
+        filter,
+        pool.into_inner(),
+        *user_id.into_inner(),
+        session_id,
+    ));
+    Ok(response)
+}
+
+/// Push matching events to the client until either side goes away, or the
+/// admin is no longer logged in.
+async fn forward_events(
+    mut session: Session,
+    mut msg_stream: MessageStream,
+    mut events: Receiver<AdminEvent>,
+    filter: AdminEventFilter,
+    pool: std::sync::Arc<PgPool>,
+    user_id: Uuid,
+    session_id: Uuid,
+) {
+    let mut login_checks = tokio::time::interval(LOGIN_CHECK_INTERVAL);
+    // The middleware just checked: the first tick is not needed.
+    login_checks.tick().await;
+    loop {
+        tokio::select! {
+            _ = login_checks.tick() => match is_logged_in(&pool, user_id, session_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let reason = CloseReason {
+                        code: CloseCode::Policy,
+                        description: Some("The session has ended".into()),
+                    };
+                    let _ = session.close(Some(reason)).await;
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to check the login of an admin notifications client"
+                    );
+                }
+            },
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if !filter.matches(&event) {
+                        continue;
+                    }
+                    let payload = match serde_json::to_string(&event) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            tracing::error!(error.message = %e, "Failed to serialize an admin event");
+                            continue;
+                        }
+                    };
+                    if session.text(payload).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "Admin notifications client is lagging behind");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            msg = msg_stream.recv() => match msg {
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
@@ -0,0 +1,188 @@
+use crate::authentication::UserId;
+use crate::operations::{
+    create_operation, list_operations, resume_operation, roll_back_operation, OperationPlan,
+    RollBackOutcome, MAX_STEPS,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The operations shown, most recent first.
+const LISTED_OPERATIONS: i64 = 50;
+
+/// The progress of the recent operations, and the form to start a new one.
+pub async fn admin_operations(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let operations = list_operations(&pool, LISTED_OPERATIONS)
+        .await
+        .map_err(e500)?;
+    let mut rows = String::new();
+    for operation in &operations {
+        let mut actions = String::new();
+        if matches!(operation.status.as_str(), "failed" | "rollback_failed") {
+            write!(
+                actions,
+                r#"<form action="/admin/operations/{}/resume" method="post"><button type="submit">Resume</button></form>"#,
+                operation.id
+            )
+            .unwrap();
+        }
+        if !matches!(operation.status.as_str(), "rolling_back" | "rolled_back")
+            && operation.kind == "delete_subscribers"
+        {
+            write!(
+                actions,
+                r#"<form action="/admin/operations/{}/roll-back" method="post"><button type="submit">Roll back</button></form>"#,
+                operation.id
+            )
+            .unwrap();
+        }
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}/{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            operation.created_at.format("%Y-%m-%d %H:%M"),
+            operation.kind,
+            operation.status,
+            operation.checkpoint,
+            operation.total_steps,
+            encode_minimal(operation.last_error.as_deref().unwrap_or("")),
+            operation.updated_at.format("%Y-%m-%d %H:%M:%S"),
+            actions
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Operations</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr><th>Started</th><th>Operation</th><th>Status</th><th>Steps done</th><th>Error</th><th>Updated</th><th></th></tr>
+        {rows}
+    </table>
+    <form action="/admin/operations" method="post">
+        <label>Operation:
+            <select name="kind">
+                <option value="delete_subscribers">Delete subscribers</option>
+                <option value="merge_subscribers">Merge subscribers</option>
+            </select>
+        </label>
+        <br>
+        <label>Up to {MAX_STEPS} lines: a subscriber id to delete, or a survivor id and a
+        duplicate id to merge:<br>
+            <textarea name="items" rows="10" cols="80"></textarea>
+        </label>
+        <br>
+        <button type="submit">Start</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct OperationFormData {
+    kind: String,
+    items: String,
+}
+
+/// The operation is carried out by the operations worker.
+#[tracing::instrument(
+    name = "Start an admin operation",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, kind=%form.kind)
+)]
+pub async fn start_admin_operation(
+    form: web::Form<OperationFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let plan = match OperationPlan::parse(&form.kind, &form.items) {
+        Ok(plan) => plan,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/operations"));
+        }
+    };
+    create_operation(&pool, &plan, **user_id)
+        .await
+        .context("Failed to record an admin operation.")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The operation has started: {} steps to go.",
+        plan.total_steps()
+    ))
+    .send();
+    Ok(see_other("/admin/operations"))
+}
+
+#[tracing::instrument(
+    name = "Resume an admin operation",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn resume_admin_operation(
+    operation_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let resumed = resume_operation(&pool, operation_id.into_inner())
+        .await
+        .context("Failed to resume an admin operation.")
+        .map_err(e500)?;
+    if resumed {
+        FlashMessage::info("The operation has been resumed.").send();
+    } else {
+        FlashMessage::error("There is no failed operation with the provided id.").send();
+    }
+    Ok(see_other("/admin/operations"))
+}
+
+#[tracing::instrument(
+    name = "Roll back an admin operation",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn roll_back_admin_operation(
+    operation_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = roll_back_operation(&pool, operation_id.into_inner())
+        .await
+        .context("Failed to roll back an admin operation.")
+        .map_err(e500)?;
+    match outcome {
+        RollBackOutcome::RollingBack => {
+            FlashMessage::info("The operation is being rolled back.").send()
+        }
+        RollBackOutcome::Irreversible => {
+            FlashMessage::error("Merges cannot be rolled back.").send()
+        }
+        RollBackOutcome::NotFound => {
+            FlashMessage::error("There is no operation to roll back with the provided id.").send()
+        }
+    }
+    Ok(see_other("/admin/operations"))
+}
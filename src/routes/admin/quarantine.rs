@@ -0,0 +1,130 @@
+use crate::authentication::UserId;
+use crate::email_client::EmailClient;
+use crate::pii::PiiCipher;
+use crate::quarantine::{approve_signups, list_quarantined, reject_signups};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The signups shown at once, oldest first.
+const LISTED_SIGNUPS: i64 = 200;
+
+/// The signups held back as likely bots, to approve or reject in bulk.
+pub async fn quarantine_queue(
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let signups = list_quarantined(&pool, &pii, LISTED_SIGNUPS)
+        .await
+        .context("Failed to list the quarantined signups")
+        .map_err(e500)?;
+    let mut rows = String::new();
+    for signup in &signups {
+        writeln!(
+            rows,
+            r#"<tr><td><input type="checkbox" name="subscriber_id" value="{}"></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            signup.subscriber_id,
+            encode_minimal(&signup.email),
+            encode_minimal(&signup.name),
+            signup.reason,
+            encode_minimal(signup.form_source.as_deref().unwrap_or("")),
+            encode_minimal(signup.client_ip.as_deref().unwrap_or("")),
+            signup.quarantined_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Quarantine</title>
+</head>
+<body>
+    {msg_html}
+    <p>{} signups are waiting for a review. Approved signups are sent their
+    confirmation email; the others are deleted.</p>
+    <form action="/admin/quarantine" method="post">
+        <table>
+            <tr><th></th><th>Email</th><th>Name</th><th>Reason</th><th>Form</th><th>IP</th><th>Quarantined</th></tr>
+            {rows}
+        </table>
+        <button type="submit" name="action" value="approve">Approve</button>
+        <button type="submit" name="action" value="reject">Reject</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            signups.len()
+        )))
+}
+
+/// The form repeats `subscriber_id` for every checked signup, hence pairs.
+#[tracing::instrument(
+    name = "Review quarantined signups",
+    skip(form, pool, pii, email_client, base_url, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn review_quarantine(
+    form: web::Form<Vec<(String, String)>>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut action = None;
+    let mut subscriber_ids = Vec::new();
+    for (key, value) in form.into_inner() {
+        match key.as_str() {
+            "action" => action = Some(value),
+            "subscriber_id" => match Uuid::parse_str(&value) {
+                Ok(id) => subscriber_ids.push(id),
+                Err(_) => {
+                    FlashMessage::error("One of the selected signups is not valid.").send();
+                    return Ok(see_other("/admin/quarantine"));
+                }
+            },
+            _ => {}
+        }
+    }
+    match action.as_deref() {
+        Some("approve") => {
+            let approved =
+                approve_signups(&pool, &email_client, &pii, &base_url.0, &subscriber_ids)
+                    .await
+                    .context("Failed to approve quarantined signups")
+                    .map_err(e500)?;
+            FlashMessage::info(format!(
+                "{} signups were approved and sent their confirmation email.",
+                approved
+            ))
+            .send();
+        }
+        Some("reject") => {
+            let rejected = reject_signups(&pool, &subscriber_ids)
+                .await
+                .context("Failed to reject quarantined signups")
+                .map_err(e500)?;
+            FlashMessage::info(format!("{} signups were rejected.", rejected)).send();
+        }
+        _ => FlashMessage::error("Choose to approve or to reject the signups.").send(),
+    }
+    Ok(see_other("/admin/quarantine"))
+}
@@ -0,0 +1,51 @@
+use crate::authentication::UserId;
+use crate::queues::{get_queue_stats, retry_failed_deliveries, Queue};
+use crate::utils::e500;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+/// The depth, age and failures of every queue, to diagnose stuck sends
+/// without SQL.
+pub async fn queue_stats(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut queues = Vec::new();
+    for queue in Queue::ALL {
+        queues.push(
+            get_queue_stats(&pool, queue)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to compute the stats of the {} queue",
+                        queue.as_str()
+                    )
+                })
+                .map_err(e500)?,
+        );
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "queues": queues })))
+}
+
+#[tracing::instrument(
+    name = "Retry the failed items of a queue",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn retry_failed(
+    name: web::Path<String>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match Queue::parse(&name) {
+        Some(Queue::Delivery) => {
+            let retried = retry_failed_deliveries(&pool)
+                .await
+                .context("Failed to requeue the failed deliveries.")
+                .map_err(e500)?;
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "retried": retried })))
+        }
+        Some(Queue::Outbox) => Ok(HttpResponse::Conflict()
+            .body("The relay retries the failed outbox events until they are published.")),
+        None => Ok(HttpResponse::NotFound().body(format!("There is no {} queue.", name))),
+    }
+}
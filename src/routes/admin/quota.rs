@@ -0,0 +1,17 @@
+use crate::reload::ReloadableSettings;
+use crate::send_quota::monthly_usage;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+pub async fn send_quota_usage(
+    pool: web::Data<PgPool>,
+    settings: web::Data<ReloadableSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let settings = settings.load();
+    let Some(quota) = &settings.send_quota else {
+        return Ok(HttpResponse::NotFound().body("No send quota is configured."));
+    };
+    let usage = monthly_usage(pool.get_ref(), quota).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(usage))
+}
@@ -0,0 +1,44 @@
+use crate::pii::PiiCipher;
+use crate::referrals::leaderboard;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct LeaderboardParameters {
+    #[serde(default = "default_size")]
+    size: i64,
+}
+
+fn default_size() -> i64 {
+    20
+}
+
+/// The subscribers who brought in the most confirmed subscribers.
+#[tracing::instrument(name = "Show the referral leaderboard", skip(pool, pii, parameters))]
+pub async fn referral_leaderboard(
+    parameters: web::Query<LeaderboardParameters>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let size = parameters.size.clamp(1, 100);
+    let entries = leaderboard(&pool, size)
+        .await
+        .context("Failed to fetch the referral leaderboard")
+        .map_err(e500)?;
+    let mut leaderboard = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let name = pii
+            .open(&entry.name)
+            .context("Failed to open the name of a referrer")
+            .map_err(e500)?;
+        leaderboard.push(serde_json::json!({
+            "rank": entry.rank,
+            "subscriber_id": entry.subscriber_id,
+            "name": name,
+            "referrals": entry.referrals,
+        }));
+    }
+    Ok(HttpResponse::Ok().json(leaderboard))
+}
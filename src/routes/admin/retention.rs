@@ -0,0 +1,7 @@
+use crate::retention::RetentionPolicy;
+use actix_web::{web, HttpResponse};
+
+/// The effective retention policy, for compliance reviews.
+pub async fn retention_policy(policy: web::Data<RetentionPolicy>) -> HttpResponse {
+    HttpResponse::Ok().json(policy.get_ref())
+}
@@ -0,0 +1,120 @@
+use crate::admin_sessions::{list_sessions, revoke_other_sessions, revoke_session};
+use crate::authentication::UserId;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[tracing::instrument(
+    name = "List admin sessions",
+    skip(pool, session, flash_messages, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn admin_sessions(
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current = session.get_admin_session_id().map_err(e500)?;
+    let sessions = list_sessions(&pool, **user_id).await.map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    for s in &sessions {
+        let action = if Some(s.session_id) == current {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post"><input type="submit" value="Revoke"></form>"#,
+                s.session_id
+            )
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(s.user_agent.as_deref().unwrap_or("Unknown device")),
+            encode_minimal(s.client_ip.as_deref().unwrap_or("-")),
+            s.last_seen_at.format("%Y-%m-%d %H:%M UTC"),
+            action
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Active sessions</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr><th>Device</th><th>IP address</th><th>Last seen</th><th></th></tr>
+        {rows_html}
+    </table>
+    <form action="/admin/sessions/revoke-others" method="post">
+        <input type="submit" value="Log out all other sessions">
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(
+    name = "Revoke an admin session",
+    skip(pool, session, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_admin_session(
+    session_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = session_id.into_inner();
+    if session.get_admin_session_id().map_err(e500)? == Some(session_id) {
+        FlashMessage::error("Log out to end the current session.").send();
+    } else if revoke_session(&pool, **user_id, session_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("There is no session with the provided id.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(
+    name = "Revoke the other admin sessions",
+    skip(pool, session, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn revoke_other_admin_sessions(
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The session has been checked by `reject_anonymous_users`.
+    let current = session
+        .get_admin_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session has no id"))?;
+    let revoked = revoke_other_sessions(&pool, **user_id, current)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("{} other session(s) have been revoked.", revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
@@ -0,0 +1,20 @@
+use crate::reload::ReloadableSettings;
+use crate::utils::see_other;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+/// Apply the hot-swappable settings of the configuration files, as SIGHUP does.
+pub async fn reload_settings(settings: web::Data<ReloadableSettings>) -> HttpResponse {
+    match settings.reload() {
+        Ok(()) => FlashMessage::info("The configuration has been reloaded.").send(),
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to reload the configuration"
+            );
+            FlashMessage::error(format!("Failed to reload the configuration: {}", e)).send()
+        }
+    }
+    see_other("/admin/dashboard")
+}
@@ -0,0 +1,91 @@
+use crate::authentication::UserId;
+use crate::configuration::SmsSettings;
+use crate::sms::{count_sms_recipients, create_blast, parse_message, MAX_MESSAGE_LENGTH};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn sms_blast_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    settings: Option<web::Data<SmsSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if settings.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let recipients = count_sms_recipients(&pool).await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>SMS announcements</title>
+</head>
+<body>
+    {msg_html}
+    <p>{recipients} subscribers verified their phone number and opted in to
+    texts.</p>
+    <form action="/admin/sms" method="post">
+        <label>Announcement, up to {MAX_MESSAGE_LENGTH} characters:<br>
+            <textarea name="message" rows="4" cols="50" maxlength="{MAX_MESSAGE_LENGTH}"></textarea>
+        </label>
+        <br>
+        <button type="submit">Text it</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SmsBlastFormData {
+    message: String,
+}
+
+/// The texts are queued here, and sent by the SMS worker at the pace of
+/// the rate limits.
+#[tracing::instrument(
+    name = "Send an SMS announcement",
+    skip(form, pool, user_id, settings),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_sms_blast(
+    form: web::Form<SmsBlastFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    settings: Option<web::Data<SmsSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if settings.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let message = match parse_message(&form.message) {
+        Ok(message) => message,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/sms"));
+        }
+    };
+    let queued = create_blast(&pool, &message, **user_id)
+        .await
+        .context("Failed to queue an SMS announcement")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The announcement is being texted to {} subscribers.",
+        queued
+    ))
+    .send();
+    Ok(see_other("/admin/sms"))
+}
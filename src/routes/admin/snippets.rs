@@ -0,0 +1,111 @@
+use crate::authentication::UserId;
+use crate::snippets::{is_valid_name, list_snippets, save_snippet};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn snippet_library(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rows_html = String::new();
+    for snippet in list_snippets(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td><code>{{{{snippet:{}}}}}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            snippet.name,
+            snippet.latest_version,
+            snippet.updated_at.format("%Y-%m-%d %H:%M"),
+            snippet.issues
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Snippets</title>
+</head>
+<body>
+    {msg_html}
+    <p>Insert a snippet in an issue with its tag. Issues are sent with the
+    version of the snippet they were published with.</p>
+    <table>
+        <tr><th>Tag</th><th>Version</th><th>Updated</th><th>Issues</th></tr>
+        {rows_html}
+    </table>
+    <h2>Add or update a snippet</h2>
+    <form action="/admin/snippets" method="post">
+        <label>Name:<br>
+            <input type="text" placeholder="e.g. sponsor" name="name">
+        </label>
+        <br>
+        <label>Plain text content:<br>
+            <textarea name="text_content" rows="10" cols="50"></textarea>
+        </label>
+        <br>
+        <label>HTML content:<br>
+            <textarea name="html_content" rows="10" cols="50"></textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SnippetFormData {
+    name: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// Saving a snippet adds a version, used by the issues published from now
+/// on.
+#[tracing::instrument(
+    name = "Save a snippet",
+    skip(form, pool, user_id),
+    fields(user_id=%*user_id, name=%form.name)
+)]
+pub async fn save_snippet_version(
+    form: web::Form<SnippetFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if !is_valid_name(name) {
+        FlashMessage::error(
+            "Snippet names are made of lower-case letters, digits, - and _, up to 64 characters.",
+        )
+        .send();
+        return Ok(see_other("/admin/snippets"));
+    }
+    let version = save_snippet(
+        &pool,
+        name,
+        &form.html_content,
+        &form.text_content,
+        **user_id,
+    )
+    .await
+    .context("Failed to save a snippet")
+    .map_err(e500)?;
+    FlashMessage::info(format!("Snippet {} saved as version {}.", name, version)).send();
+    Ok(see_other("/admin/snippets"))
+}
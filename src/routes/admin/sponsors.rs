@@ -0,0 +1,47 @@
+use crate::sponsorship::sponsor_report as get_sponsor_report;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{Days, NaiveDate};
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct ReportParameters {
+    /// The first day of the report, included.
+    from: NaiveDate,
+    /// The last day of the report, included.
+    to: NaiveDate,
+}
+
+/// The impressions and clicks of a sponsor's slots over a range of days,
+/// in UTC.
+#[tracing::instrument(name = "Report on a sponsor", skip(pool, parameters))]
+pub async fn sponsor_report(
+    sponsor: web::Path<String>,
+    parameters: web::Query<ReportParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if parameters.to < parameters.from {
+        return Ok(HttpResponse::BadRequest().body("`to` cannot be before `from`."));
+    }
+    let from = parameters.from.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let to = (parameters.to + Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    let slots = get_sponsor_report(&pool, &sponsor, from, to)
+        .await
+        .context("Failed to report on a sponsor")
+        .map_err(e500)?;
+    let impressions: i64 = slots.iter().map(|slot| slot.impressions).sum();
+    let clicks: i64 = slots.iter().map(|slot| slot.clicks).sum();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "sponsor": sponsor.into_inner(),
+        "from": parameters.from,
+        "to": parameters.to,
+        "impressions": impressions,
+        "clicks": clicks,
+        "click_through_rate": (impressions > 0).then(|| clicks as f64 / impressions as f64),
+        "slots": slots,
+    })))
+}
@@ -0,0 +1,137 @@
+use crate::authentication::UserId;
+use crate::consent::get_consent_records;
+use crate::pii::PiiCipher;
+use crate::subscribers::{
+    merge_subscribers, restore_subscriber as restore, soft_delete_subscriber, MergeOutcome,
+};
+use crate::utils::{e500, see_other};
+use crate::verified_subscriber::reset_subscription_tokens;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The consent records of a subscriber, as proof of their opt-in.
+#[tracing::instrument(
+    name = "Get the consent records of a subscriber",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn subscriber_consent(
+    subscriber_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let records = get_consent_records(&pool, subscriber_id.into_inner())
+        .await
+        .context("Failed to retrieve the consent records of a subscriber.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(records))
+}
+
+#[tracing::instrument(
+    name = "Delete a subscriber",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = soft_delete_subscriber(pool.get_ref(), subscriber_id.into_inner())
+        .await
+        .context("Failed to delete a subscriber.")
+        .map_err(e500)?;
+    if deleted {
+        FlashMessage::info("The subscriber has been deleted.").send();
+    } else {
+        FlashMessage::error("There is no subscriber with the provided id.").send();
+    }
+    Ok(see_other("/admin/dashboard"))
+}
+
+#[tracing::instrument(
+    name = "Restore a subscriber",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn restore_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let restored = restore(pool.get_ref(), subscriber_id.into_inner())
+        .await
+        .context("Failed to restore a subscriber.")
+        .map_err(e500)?;
+    if restored {
+        FlashMessage::info("The subscriber has been restored.").send();
+    } else {
+        FlashMessage::error("There is no deleted subscriber with the provided id.").send();
+    }
+    Ok(see_other("/admin/dashboard"))
+}
+
+/// Revoke the links emailed to a subscriber so far, e.g. after one leaked.
+#[tracing::instrument(
+    name = "Reset the links of a subscriber",
+    skip(pool, user_id),
+    fields(user_id=%*user_id)
+)]
+pub async fn reset_subscriber_links(
+    subscriber_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let reset = reset_subscription_tokens(pool.get_ref(), subscriber_id.into_inner())
+        .await
+        .context("Failed to reset the links of a subscriber.")
+        .map_err(e500)?;
+    if reset {
+        FlashMessage::info("The links emailed to the subscriber no longer work.").send();
+    } else {
+        FlashMessage::error("There is no subscriber with the provided id.").send();
+    }
+    Ok(see_other("/admin/dashboard"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MergeFormData {
+    duplicate_id: Uuid,
+}
+
+/// Fold the duplicate subscriber from the form into the one in the path.
+#[tracing::instrument(
+    name = "Merge a duplicate subscriber",
+    skip(form, pool, pii, user_id),
+    fields(user_id=%*user_id, duplicate_id=%form.duplicate_id)
+)]
+pub async fn merge_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<MergeFormData>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = merge_subscribers(&pool, &pii, subscriber_id.into_inner(), form.duplicate_id)
+        .await
+        .context("Failed to merge subscribers.")
+        .map_err(e500)?;
+    match outcome {
+        MergeOutcome::Merged => FlashMessage::info("The subscribers have been merged.").send(),
+        MergeOutcome::NotFound => {
+            FlashMessage::error("There is no active subscriber with the provided id.").send()
+        }
+        MergeOutcome::SameSubscriber => {
+            FlashMessage::error("A subscriber cannot be merged into itself.").send()
+        }
+        MergeOutcome::BothPaying => FlashMessage::error(
+            "Both subscribers pay: cancel one of the subscriptions before merging them.",
+        )
+        .send(),
+    }
+    Ok(see_other("/admin/dashboard"))
+}
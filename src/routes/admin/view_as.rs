@@ -0,0 +1,162 @@
+use crate::authentication::{is_owner, UserId};
+use crate::database::ObserveQuery;
+use crate::delivery_status::{get_latest_issue, get_recent_deliveries, get_suppression};
+use crate::events::{DomainEvent, EventBus};
+use crate::issue_delivery_worker::get_issue;
+use crate::pii::PiiCipher;
+use crate::polls::{get_issue_poll, PollLinks};
+use crate::routes::delivery_status_content;
+use crate::theme::{Page, Theme};
+use crate::utils::e500;
+use crate::web_version::WebVersion;
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// What the subscriber sees of the newsletter: their delivery status page,
+/// which issues of the archive they can read, and the latest issue as it was
+/// emailed to them, translation included. Owners only, and every view is
+/// published as an event for the audit log.
+#[tracing::instrument(
+    name = "View the newsletter as a subscriber",
+    skip(pool, pii, theme, web_version, poll_links, event_bus, user_id),
+    fields(user_id=%*user_id)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn view_as_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    theme: web::Data<Theme>,
+    web_version: web::Data<WebVersion>,
+    poll_links: web::Data<PollLinks>,
+    event_bus: web::Data<EventBus>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let user_id = **user_id;
+    if !is_owner(&pool, user_id).await.map_err(e500)? {
+        return Ok(HttpResponse::Forbidden().body("Only owners can view as a subscriber."));
+    }
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT email, status, paid, locale
+        FROM subscriptions
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .observe("get_impersonated_subscriber")
+    .await
+    .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // Logged before anything about the subscriber is shown.
+    event_bus
+        .publish(DomainEvent::SubscriberViewImpersonated {
+            user_id,
+            subscriber_id,
+        })
+        .await
+        .context("Failed to record a view as a subscriber")
+        .map_err(e500)?;
+    let email = pii.open_email(&subscriber.email).map_err(e500)?;
+
+    let suppression = get_suppression(&pool, &email).await.map_err(e500)?;
+    let deliveries = get_recent_deliveries(&pool, subscriber_id, suppression.is_some())
+        .await
+        .map_err(e500)?;
+    let delivery_status_html =
+        delivery_status_content(&email, &deliveries, suppression.as_ref(), None);
+
+    // The archive opens paid-only issues to confirmed paid subscribers.
+    let paid = subscriber.paid && subscriber.status == "confirmed";
+    let issues = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, title, published_at, paid_only
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .observe("list_archived_issues")
+    .await
+    .map_err(e500)?;
+    let mut archive_html = String::new();
+    for issue in issues {
+        writeln!(
+            archive_html,
+            r#"<li><a href="/archive/{}">{}</a> ({}): {}</li>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+            if !issue.paid_only || paid {
+                "readable"
+            } else {
+                "locked, paid subscribers only"
+            }
+        )
+        .unwrap();
+    }
+
+    let latest_issue_html = match get_latest_issue(&pool, subscriber_id).await.map_err(e500)? {
+        Some(latest) => {
+            let issue = get_issue(&pool, latest.issue_id, &latest.subscriber_email)
+                .await
+                .map_err(e500)?;
+            // The poll links lead to the results: a click must not vote
+            // on behalf of the subscriber.
+            let html_content = match get_issue_poll(&pool, latest.issue_id).await.map_err(e500)? {
+                Some(poll) => {
+                    poll.render(&issue.html_content, "", |_| {
+                        poll_links.results(poll.poll_id)
+                    })
+                    .0
+                }
+                None => issue.html_content,
+            };
+            let (html_content, _) = web_version.inject(
+                latest.issue_id,
+                issue.paid_only,
+                &html_content,
+                &issue.text_content,
+            );
+            format!(
+                r#"<p>Subject: {}</p>
+    <iframe sandbox title="The latest issue" srcdoc="{}"></iframe>"#,
+                htmlescape::encode_minimal(&issue.title),
+                htmlescape::encode_attribute(&html_content)
+            )
+        }
+        None => "<p>No issue they can read was sent yet.</p>".to_owned(),
+    };
+
+    let content = format!(
+        r#"<p><strong>You are viewing the newsletter as {email} ({status}, {plan}, locale {locale}).
+    Nothing here can be changed, and this view was recorded in the audit log.</strong></p>
+    <section>{delivery_status_html}</section>
+    <section>
+    <h1>Archive</h1>
+    <ul>
+        {archive_html}
+    </ul>
+    </section>
+    <section>
+    <h1>Latest issue</h1>
+    {latest_issue_html}
+    </section>"#,
+        email = htmlescape::encode_minimal(&email),
+        status = subscriber.status,
+        plan = if subscriber.paid { "paid" } else { "free" },
+        locale = htmlescape::encode_minimal(subscriber.locale.as_deref().unwrap_or("default")),
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("view_as", "Viewing as a subscriber", &content))))
+}
@@ -0,0 +1,341 @@
+use crate::configuration::MagicLinkSettings;
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::{EmailClient, MessageStream};
+use crate::image_proxy::{rewrite_image_sources, verify_image_url, ImageProxy};
+use crate::links;
+use crate::magic_link::{issue_magic_link, redeem_magic_link, MagicLinkPurpose};
+use crate::pii::PiiCipher;
+use crate::polls::get_issue_poll;
+use crate::session_state::TypedSession;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscribers::{get_confirmed_subscriber_id, is_paid_subscriber};
+use crate::theme::{Page, Theme};
+use crate::utils::{e500, see_other};
+use crate::web_version::WebVersion;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ArchivedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    paid_only: bool,
+}
+
+#[tracing::instrument(name = "List archived issues", skip(pool, theme))]
+pub async fn archive_index(
+    pool: web::Data<PgPool>,
+    theme: web::Data<Theme>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT newsletter_issue_id, title, published_at, paid_only
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .observe("list_archived_issues")
+    .await
+    .map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/archive/{}">{}</a> ({}){}</li>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d"),
+            if issue.paid_only { " - paid" } else { "" }
+        )
+        .unwrap();
+    }
+    let content = format!(
+        r#"<ul>
+        {issues_html}
+    </ul>"#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("archive_index", "Archive", &content))))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveIssueParameters {
+    /// The locale of the translation to show, e.g. `pt-br`.
+    lang: Option<String>,
+    /// The signed token of the "View in browser" link of a paid-only issue.
+    preview: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Read an archived issue",
+    skip(
+        parameters,
+        pool,
+        session,
+        flash_messages,
+        hmac_secret,
+        theme,
+        web_version
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn archive_issue(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<ArchiveIssueParameters>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    hmac_secret: web::Data<HmacSecret>,
+    theme: web::Data<Theme>,
+    web_version: web::Data<WebVersion>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let ArchiveIssueParameters { lang, preview } = parameters.into_inner();
+    let Some(issue) = sqlx::query!(
+        r#"
+        SELECT title, html_content, paid_only
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .observe("get_archived_issue")
+    .await
+    .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let variants = sqlx::query!(
+        r#"
+        SELECT locale, title, html_content
+        FROM newsletter_issue_variants
+        WHERE newsletter_issue_id = $1
+        ORDER BY locale
+        "#,
+        issue_id
+    )
+    .fetch_all(pool.get_ref())
+    .observe("get_archived_issue_variants")
+    .await
+    .map_err(e500)?;
+    let lang = lang.and_then(|lang| Locale::parse(lang).ok());
+    let variant = lang.and_then(|lang| variants.iter().find(|v| v.locale == lang.as_ref()));
+    let (title, html_content) = match variant {
+        Some(variant) => (variant.title.as_str(), variant.html_content.as_str()),
+        None => (issue.title.as_str(), issue.html_content.as_str()),
+    };
+    let title = htmlescape::encode_minimal(title);
+    // The locales were validated when the variants were attached.
+    let mut language_switcher = String::new();
+    if !variants.is_empty() {
+        language_switcher = format!(r#"<nav><a href="/archive/{issue_id}">Original</a>"#);
+        for v in &variants {
+            write!(
+                language_switcher,
+                r#" | <a href="/archive/{issue_id}?lang={0}">{0}</a>"#,
+                v.locale
+            )
+            .unwrap();
+        }
+        language_switcher.push_str("</nav>");
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+
+    let previewed = preview.is_some_and(|token| web_version.verify_preview(issue_id, &token));
+    let allowed = if issue.paid_only && !previewed {
+        match session.get_subscriber_id().map_err(e500)? {
+            Some(subscriber_id) => is_paid_subscriber(&pool, subscriber_id)
+                .await
+                .map_err(e500)?,
+            None => false,
+        }
+    } else {
+        true
+    };
+    if !allowed {
+        let content = format!(
+            r#"{msg_html}
+    <p>This issue is reserved to paid subscribers. Enter your email to receive a login link.</p>
+    <form action="/archive/login" method="post">
+        <input type="email" placeholder="Enter your email" name="email">
+        <input hidden type="text" name="issue_id" value="{issue_id}">
+        <button type="submit">Send me a link</button>
+    </form>"#
+        );
+        return Ok(HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(theme.render(Page::new("archive_locked", &title, &content))));
+    }
+    let html_content = match get_issue_poll(&pool, issue_id).await.map_err(e500)? {
+        Some(poll) => {
+            poll.render(html_content, "", |_| {
+                links::poll_results(poll.poll_id).to_string()
+            })
+            .0
+        }
+        None => html_content.to_owned(),
+    };
+    let content = format!(
+        r#"{msg_html}
+    {language_switcher}
+    <h1>{title}</h1>
+    {}
+    <p><a href="/archive">&lt;- Archive</a></p>"#,
+        rewrite_image_sources(&html_content, &hmac_secret.0)
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("archive_issue", &title, &content))))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImageParameters {
+    url: String,
+    signature: String,
+}
+
+#[tracing::instrument(name = "Serve an archived image", skip_all, fields(url = %parameters.url))]
+pub async fn archive_image(
+    parameters: web::Query<ImageParameters>,
+    proxy: web::Data<ImageProxy>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> HttpResponse {
+    if !verify_image_url(&hmac_secret.0, &parameters.url, &parameters.signature) {
+        return HttpResponse::Forbidden().finish();
+    }
+    match proxy.get(&parameters.url).await {
+        Ok(image) => HttpResponse::Ok()
+            .content_type(image.content_type)
+            .insert_header(("Cache-Control", "public, max-age=86400"))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .body(image.bytes),
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Failed to proxy an image");
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveLoginFormData {
+    email: String,
+    issue_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Send an archive login link",
+    skip(form, pool, pii, email_client, base_url, magic_links),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_archive_link(
+    form: web::Form<ArchiveLoginFormData>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    magic_links: web::Data<MagicLinkSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ArchiveLoginFormData { email, issue_id } = form.0;
+    let issue_path = format!("/archive/{}", issue_id);
+    // Same answer whether or not the address belongs to a subscriber, so the
+    // form can't be used to find out who is subscribed.
+    FlashMessage::info("If you are a subscriber, a login link is on its way to your inbox.").send();
+    let Ok(email) = SubscriberEmail::parse(email) else {
+        return Ok(see_other(&issue_path));
+    };
+    let Some(subscriber_id) = get_confirmed_subscriber_id(&pool, &pii, email.as_ref())
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(see_other(&issue_path));
+    };
+    let token = issue_magic_link(
+        pool.get_ref(),
+        MagicLinkPurpose::Archive,
+        subscriber_id,
+        magic_links.ttl(),
+    )
+    .await
+    .map_err(e500)?;
+    let link = links::archive_login(&token, issue_id).absolute(&base_url.0);
+    let sent = email_client
+        .send_email(
+            &email,
+            "Your login link",
+            &format!("Click <a href=\"{}\">here</a> to read the issue.", link),
+            &format!("Visit {} to read the issue.", link),
+            MessageStream::Transactional,
+        )
+        .await
+        .context("Failed to send an archive login link")
+        .map_err(e500)?;
+    record_send(pool.get_ref(), &sent)
+        .await
+        .context("Failed to record the cost of an archive login link")
+        .map_err(e500)?;
+    Ok(see_other(&issue_path))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveLoginParameters {
+    token: String,
+    issue_id: Uuid,
+}
+
+/// Mail scanners follow the links in the emails they inspect: the link only
+/// shows a button, and the token is redeemed by the form it submits.
+pub async fn archive_link_form(
+    parameters: web::Query<ArchiveLoginParameters>,
+    theme: web::Data<Theme>,
+) -> HttpResponse {
+    let content = format!(
+        r#"<h1>Read the issue</h1>
+    <form action="{}" method="post">
+        <button type="submit">Log in and read the issue</button>
+    </form>"#,
+        htmlescape::encode_minimal(
+            &links::archive_login(&parameters.token, parameters.issue_id).to_string()
+        )
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("archive_login", "Log in", &content)))
+}
+
+#[tracing::instrument(name = "Log into the archive", skip(parameters, pool, session))]
+pub async fn confirm_archive_link(
+    parameters: web::Query<ArchiveLoginParameters>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) =
+        redeem_magic_link(&pool, MagicLinkPurpose::Archive, &parameters.token)
+            .await
+            .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    session.renew();
+    session.insert_subscriber_id(subscriber_id).map_err(e500)?;
+    Ok(see_other(&format!("/archive/{}", parameters.issue_id)))
+}
@@ -0,0 +1,125 @@
+use crate::billing::{grant_paid, set_paid_for_customer, StripeClient, WebhookEvent};
+use crate::pii::PiiCipher;
+use crate::routes::error_chain_fmt;
+use crate::subscribers::get_confirmed_subscriber_id;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub enum BillingError {
+    #[error("Billing is not enabled")]
+    NotEnabled,
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for BillingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for BillingError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BillingError::NotEnabled => StatusCode::NOT_FOUND,
+            BillingError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            BillingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct CheckoutFormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Start a paid subscription checkout",
+    skip(form, pool, pii, stripe),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn start_checkout(
+    form: web::Form<CheckoutFormData>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    stripe: Option<web::Data<StripeClient>>,
+) -> Result<HttpResponse, BillingError> {
+    let stripe = stripe.ok_or(BillingError::NotEnabled)?;
+    let subscriber_id = get_confirmed_subscriber_id(&pool, &pii, &form.email)
+        .await
+        .context("Failed to look up the subscriber")?
+        .ok_or_else(|| {
+            BillingError::ValidationError(
+                "Only confirmed subscribers can upgrade to the paid tier.".into(),
+            )
+        })?;
+    let session = stripe
+        .create_checkout_session(subscriber_id, &form.email)
+        .await
+        .context("Failed to create a Stripe checkout session")?;
+    Ok(HttpResponse::SeeOther()
+        .insert_header((actix_web::http::header::LOCATION, session.url))
+        .finish())
+}
+
+#[tracing::instrument(
+    name = "Handle a Stripe webhook",
+    skip_all,
+    fields(event_type = tracing::field::Empty)
+)]
+pub async fn stripe_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    stripe: Option<web::Data<StripeClient>>,
+) -> Result<HttpResponse, BillingError> {
+    let stripe = stripe.ok_or(BillingError::NotEnabled)?;
+    let signature = request
+        .headers()
+        .get("Stripe-Signature")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| BillingError::ValidationError("Missing Stripe-Signature header".into()))?;
+    stripe
+        .verify_signature(&body, signature, chrono::Utc::now().timestamp())
+        .map_err(|e| BillingError::ValidationError(e.to_string()))?;
+    let event: WebhookEvent = serde_json::from_slice(&body)
+        .map_err(|e| BillingError::ValidationError(format!("Invalid event: {}", e)))?;
+    tracing::Span::current().record("event_type", event.event_type.as_str());
+
+    let object = &event.data.object;
+    let customer_id = object["customer"].as_str();
+    match (event.event_type.as_str(), customer_id) {
+        ("checkout.session.completed", Some(customer_id)) => {
+            let subscriber_id = object["client_reference_id"]
+                .as_str()
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .ok_or_else(|| {
+                    BillingError::ValidationError("Missing client_reference_id".into())
+                })?;
+            grant_paid(&pool, subscriber_id, customer_id)
+                .await
+                .context("Failed to grant the paid tier")?;
+        }
+        ("customer.subscription.updated", Some(customer_id)) => {
+            let active = matches!(object["status"].as_str(), Some("active" | "trialing"));
+            set_paid_for_customer(&pool, customer_id, active)
+                .await
+                .context("Failed to update the paid tier")?;
+        }
+        ("customer.subscription.deleted", Some(customer_id)) => {
+            set_paid_for_customer(&pool, customer_id, false)
+                .await
+                .context("Failed to revoke the paid tier")?;
+        }
+        (event_type, _) => {
+            tracing::debug!(event_type, "Ignoring a Stripe event");
+        }
+    }
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,68 @@
+use crate::authentication::tokens_match;
+use crate::configuration::DeliveryEventsSettings;
+use crate::delivery_events::record_delivery_event;
+use crate::utils::e500;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use base64::Engine;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+/// Bounces and spam complaints reported by the email provider, which
+/// authenticates with HTTP Basic credentials whose password is the
+/// `webhook_token`.
+#[tracing::instrument(
+    name = "Receive delivery events",
+    skip(request, body, pool, settings),
+    fields(events = tracing::field::Empty, suppressed = tracing::field::Empty)
+)]
+pub async fn delivery_event_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: Option<web::Data<DeliveryEventsSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(settings) = settings else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let password = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|credentials| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credentials)
+                .ok()
+        })
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(_, password)| password.to_owned())
+        });
+    let valid = password
+        .is_some_and(|password| tokens_match(&password, settings.webhook_token.expose_secret()));
+    if !valid {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let events = match settings.provider.parser().parse(&body) {
+        Ok(events) => events,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e.to_string())),
+    };
+    let mut suppressed = 0;
+    for event in &events {
+        if record_delivery_event(&pool, event)
+            .await
+            .context("Failed to record a delivery event")
+            .map_err(e500)?
+        {
+            suppressed += 1;
+        }
+    }
+    tracing::Span::current()
+        .record("events", events.len())
+        .record("suppressed", suppressed);
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,231 @@
+use crate::configuration::ResendSettings;
+use crate::cost_ledger::record_send;
+use crate::delivery_status::{
+    count_resends_today, get_latest_issue, get_recent_deliveries, get_suppression, record_resend,
+    IssueDelivery, Suppression,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, MessageStream};
+use crate::issue_delivery_worker::get_issue;
+use crate::links;
+use crate::theme::{Page, Theme};
+use crate::utils::{e500, see_other};
+use crate::verified_subscriber::VerifiedSubscriber;
+use crate::web_push::WebPush;
+use crate::web_version::WebVersion;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryStatusParameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Show the delivery status of a subscriber",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn delivery_status(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<DeliveryStatusParameters>,
+    pool: web::Data<PgPool>,
+    theme: web::Data<Theme>,
+    flash_messages: IncomingFlashMessages,
+    web_push: Option<web::Data<WebPush>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let suppression = get_suppression(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?;
+    let deliveries = get_recent_deliveries(&pool, subscriber.id, suppression.is_some())
+        .await
+        .map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let resend_action = links::resend_latest_issue(&parameters.subscription_token).to_string();
+    let mut content = format!(
+        "{}\n    {}",
+        msg_html,
+        delivery_status_content(
+            &subscriber.email,
+            &deliveries,
+            suppression.as_ref(),
+            Some(&resend_action)
+        )
+    );
+    if let Some(web_push) = &web_push {
+        let register_action = links::push_subscriptions(&parameters.subscription_token).to_string();
+        content.push_str(&push_notifications_content(
+            web_push.public_key(),
+            &register_action,
+        ));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("delivery_status", "Your recent issues", &content))))
+}
+
+/// The body of the delivery status page. Without a `resend_action`, the
+/// page is shown to someone else than the subscriber, who cannot resend.
+pub(crate) fn delivery_status_content(
+    email: &str,
+    deliveries: &[IssueDelivery],
+    suppression: Option<&Suppression>,
+    resend_action: Option<&str>,
+) -> String {
+    let mut rows_html = String::new();
+    for delivery in deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/archive/{}">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+            delivery.issue_id,
+            htmlescape::encode_minimal(&delivery.title),
+            delivery.published_at.format("%Y-%m-%d"),
+            delivery.status.describe()
+        )
+        .unwrap();
+    }
+    let email = htmlescape::encode_minimal(email);
+    let help = match (suppression, resend_action) {
+        (Some(suppression), _) => format!(
+            "<p>We stopped sending our issues to {} on {}: {}. Reply to any of our \
+             emails to have it unblocked.</p>",
+            email,
+            suppression.suppressed_at.format("%Y-%m-%d"),
+            suppression.describe()
+        ),
+        (None, Some(resend_action)) => format!(
+            r#"<p>Can't find an issue marked as sent? Look in your spam folder, or have the
+    latest one sent to {} again.</p>
+    <form action="{}" method="post">
+        <button type="submit">Send me the latest issue</button>
+    </form>"#,
+            email,
+            htmlescape::encode_minimal(resend_action)
+        ),
+        (None, None) => String::new(),
+    };
+    format!(
+        r#"<h1>Your recent issues</h1>
+    <table>
+        <tr><th>Issue</th><th>Published</th><th>Status</th></tr>
+        {rows_html}
+    </table>
+    {help}"#
+    )
+}
+
+/// The button registering the subscriber's browser for push notifications.
+fn push_notifications_content(public_key: &str, register_action: &str) -> String {
+    format!(
+        r#"
+    <h2>Notifications</h2>
+    <p>Also get each issue as a notification in this browser.</p>
+    <button id="push-subscribe" data-key="{}" data-action="{}">Notify me</button>
+    <script>
+    document.getElementById("push-subscribe").addEventListener("click", async (event) => {{
+        const button = event.target;
+        const registration = await navigator.serviceWorker.register("/push-worker.js");
+        const subscription = await registration.pushManager.subscribe({{
+            userVisibleOnly: true,
+            applicationServerKey: button.dataset.key,
+        }});
+        const response = await fetch(button.dataset.action, {{
+            method: "POST",
+            headers: {{ "Content-Type": "application/json" }},
+            body: JSON.stringify(subscription),
+        }});
+        button.replaceWith(response.ok
+            ? "Notifications are on in this browser."
+            : "Failed to turn notifications on, please try again later.");
+    }});
+    </script>"#,
+        htmlescape::encode_attribute(public_key),
+        htmlescape::encode_attribute(register_action)
+    )
+}
+
+#[tracing::instrument(
+    name = "Send the latest issue again",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn resend_latest_issue(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<DeliveryStatusParameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    web_version: web::Data<WebVersion>,
+    settings: web::Data<ResendSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status_page = links::delivery_status(&parameters.subscription_token).to_string();
+    // The page does not offer it, but the form may have been loaded before
+    // the address was suppressed.
+    if get_suppression(&pool, &subscriber.email)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        FlashMessage::error("We can't send emails to your address while it is blocked.").send();
+        return Ok(see_other(&status_page));
+    }
+    if count_resends_today(&pool, subscriber.id)
+        .await
+        .map_err(e500)?
+        >= settings.max_per_day
+    {
+        FlashMessage::error(format!(
+            "You can have the latest issue sent again {} times a day. Please try again tomorrow.",
+            settings.max_per_day
+        ))
+        .send();
+        return Ok(see_other(&status_page));
+    }
+    let Some(latest) = get_latest_issue(&pool, subscriber.id).await.map_err(e500)? else {
+        FlashMessage::info("There is no issue to send yet.").send();
+        return Ok(see_other(&status_page));
+    };
+    // Recorded before sending: a failing send still counts, so that
+    // retrying cannot get around the limit.
+    record_resend(&pool, subscriber.id, latest.issue_id)
+        .await
+        .map_err(e500)?;
+    let issue = get_issue(&pool, latest.issue_id, &latest.subscriber_email)
+        .await
+        .map_err(e500)?;
+    let recipient = SubscriberEmail::parse(subscriber.email).map_err(e500)?;
+    let (html_content, text_content) = web_version.inject(
+        latest.issue_id,
+        issue.paid_only,
+        &issue.html_content,
+        &issue.text_content,
+    );
+    let sent = email_client
+        .send_email(
+            &recipient,
+            &issue.title,
+            &html_content,
+            &text_content,
+            MessageStream::Broadcast,
+        )
+        .await
+        .context("Failed to send an issue again")
+        .map_err(e500)?;
+    record_send(pool.get_ref(), &sent)
+        .await
+        .context("Failed to record the cost of an issue sent again")
+        .map_err(e500)?;
+    FlashMessage::info("The latest issue is on its way to your inbox.").send();
+    Ok(see_other(&status_page))
+}
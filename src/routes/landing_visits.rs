@@ -0,0 +1,65 @@
+use crate::landing_analytics::{record_visit, LandingLinks, LandingVisit, VisitKind};
+use crate::utils::e500;
+use actix_web::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_MAX_AGE,
+};
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+
+/// What the website read off the query string of the page a reader landed
+/// on.
+#[derive(serde::Deserialize)]
+pub struct VisitRequest {
+    /// The `z2p_click` parameter.
+    click_token: String,
+    #[serde(default)]
+    kind: VisitKind,
+    utm_source: Option<String>,
+    utm_medium: Option<String>,
+    utm_campaign: Option<String>,
+    utm_content: Option<String>,
+    utm_term: Option<String>,
+    page: Option<String>,
+}
+
+/// Credit a visit, or a conversion, to the issue whose link the reader
+/// followed.
+#[tracing::instrument(
+    name = "Record a landing visit",
+    skip(body, pool, links),
+    fields(kind = body.kind.as_str())
+)]
+pub async fn record_landing_visit(
+    body: web::Json<VisitRequest>,
+    pool: web::Data<PgPool>,
+    links: web::Data<LandingLinks>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = body.into_inner();
+    let Some((issue_id, subscriber_id)) = links.clicker(&body.click_token) else {
+        return Ok(HttpResponse::BadRequest().body("The click token is invalid or expired."));
+    };
+    let visit = LandingVisit {
+        kind: body.kind,
+        utm_source: body.utm_source,
+        utm_medium: body.utm_medium,
+        utm_campaign: body.utm_campaign,
+        utm_content: body.utm_content,
+        utm_term: body.utm_term,
+        page: body.page,
+    };
+    record_visit(&pool, issue_id, subscriber_id, &visit, Utc::now())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The CORS preflight of the JSON requests of the website's scripts.
+/// `allow_landing_origins` answers for the origin.
+pub async fn landing_visit_preflight() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((ACCESS_CONTROL_ALLOW_METHODS, "POST"))
+        .insert_header((ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type"))
+        .insert_header((ACCESS_CONTROL_MAX_AGE, "86400"))
+        .finish()
+}
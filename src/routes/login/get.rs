@@ -1,12 +1,28 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use crate::configuration::LoginSettings;
+use crate::oidc::OidcClient;
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    login_settings: web::Data<LoginSettings>,
+    oidc: Option<web::Data<OidcClient>>,
+) -> HttpResponse {
     let mut error_html = String::new();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut forms_html = String::new();
+    if login_settings.methods.password() {
+        forms_html.push_str(PASSWORD_FORM);
+    }
+    if login_settings.methods.magic_link() {
+        forms_html.push_str(MAGIC_LINK_FORM);
+    }
+    if oidc.is_some() {
+        forms_html.push_str(r#"<p><a href="/login/oidc">Log in with single sign-on</a></p>"#);
+    }
     HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -18,6 +34,13 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
 </head>
 <body>
     {error_html}
+    {forms_html}
+</body>
+</html>"#,
+        ))
+}
+
+const PASSWORD_FORM: &str = r#"
     <form action="/login" method="post">
         <label>Username
             <input
@@ -34,8 +57,16 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
             >
         </label>
         <button type="submit">Login</button>
-    </form>
-</body>
-</html>"#,
-        ))
-}
+    </form>"#;
+
+const MAGIC_LINK_FORM: &str = r#"
+    <form action="/login/magic-link" method="post">
+        <label>Username
+            <input
+                type="text"
+                placeholder="Enter Username"
+                name="username"
+            >
+        </label>
+        <button type="submit">Email me a login link</button>
+    </form>"#;
@@ -0,0 +1,165 @@
+use crate::admin_sessions::{log_in, Device};
+use crate::configuration::{LoginSettings, MagicLinkSettings};
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, MessageStream};
+use crate::links;
+use crate::magic_link::{
+    count_unexpired_magic_links, issue_magic_link, redeem_magic_link, MagicLinkPurpose,
+};
+use crate::session_state::TypedSession;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    skip(form, pool, email_client, base_url, login_settings, magic_links),
+    fields(username = %form.username)
+)]
+pub async fn request_login_link(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    login_settings: web::Data<LoginSettings>,
+    magic_links: web::Data<MagicLinkSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !login_settings.methods.magic_link() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    // The answer is the same for unknown usernames, like a failed password login.
+    FlashMessage::info("If the account exists, a login link is on its way to its inbox.").send();
+    let Some((user_id, email)) = get_user_email(&pool, &form.username).await.map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    let active = count_unexpired_magic_links(&pool, MagicLinkPurpose::AdminLogin, user_id)
+        .await
+        .map_err(e500)?;
+    if active >= login_settings.max_active_magic_links {
+        tracing::warn!("Too many login links requested: dropping the request");
+        return Ok(see_other("/login"));
+    }
+    let token = issue_magic_link(
+        pool.get_ref(),
+        MagicLinkPurpose::AdminLogin,
+        user_id,
+        magic_links.ttl(),
+    )
+    .await
+    .map_err(e500)?;
+    let link = links::admin_login(&token).absolute(&base_url.0);
+    let sent = email_client
+        .send_email(
+            &email,
+            "Your login link",
+            &format!(
+                "Click <a href=\"{}\">here</a> to log into the admin panel.",
+                link
+            ),
+            &format!("Visit {} to log into the admin panel.", link),
+            MessageStream::Transactional,
+        )
+        .await
+        .context("Failed to send a login link")
+        .map_err(e500)?;
+    record_send(pool.get_ref(), &sent)
+        .await
+        .context("Failed to record the cost of a login link")
+        .map_err(e500)?;
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(skip(username, pool))]
+async fn get_user_email(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, SubscriberEmail)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id, email FROM users WHERE username = $1 AND active"#,
+        username
+    )
+    .fetch_optional(pool)
+    .observe("get_user_email")
+    .await?;
+    Ok(row.and_then(|row| {
+        let email = SubscriberEmail::parse(row.email?).ok()?;
+        Some((row.user_id, email))
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+/// Mail scanners follow the links in the emails they inspect: the link only
+/// shows a button, and the token is redeemed by the form it submits.
+pub async fn login_link_form(
+    parameters: web::Query<Parameters>,
+    login_settings: web::Data<LoginSettings>,
+) -> HttpResponse {
+    if !login_settings.methods.magic_link() {
+        return HttpResponse::NotFound().finish();
+    }
+    let action = htmlescape::encode_minimal(&links::admin_login(&parameters.token).to_string());
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    <form action="{action}" method="post">
+        <button type="submit">Log into the admin panel</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(skip(parameters, pool, session, login_settings, device))]
+pub async fn confirm_login_link(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    login_settings: web::Data<LoginSettings>,
+    device: Device,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !login_settings.methods.magic_link() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    match redeem_magic_link(&pool, MagicLinkPurpose::AdminLogin, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(user_id) => {
+            log_in(
+                &pool,
+                &session,
+                &device,
+                user_id,
+                login_settings.max_sessions_per_user,
+            )
+            .await
+            .map_err(e500)?;
+            Ok(see_other("/admin/dashboard"))
+        }
+        None => {
+            FlashMessage::error("The login link is invalid or has expired.").send();
+            Ok(see_other("/login"))
+        }
+    }
+}
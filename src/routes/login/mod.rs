@@ -1,5 +1,9 @@
 mod get;
+mod magic_link;
+mod oidc;
 mod post;
 
 pub use get::login_form;
+pub use magic_link::{confirm_login_link, login_link_form, request_login_link};
+pub use oidc::{oidc_callback, oidc_login};
 pub use post::login;
@@ -0,0 +1,91 @@
+use crate::admin_sessions::{log_in, Device};
+use crate::configuration::LoginSettings;
+use crate::oidc::{provision_user, OidcClient};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+
+#[tracing::instrument(skip(oidc, session))]
+pub async fn oidc_login(
+    oidc: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let (url, flow) = oidc.authorization_url().await.map_err(e500)?;
+    session.insert_oidc_flow(&flow).map_err(e500)?;
+    Ok(see_other(url.as_str()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct CallbackParameters {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[tracing::instrument(
+    skip(parameters, oidc, pool, session, login_settings, device),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn oidc_callback(
+    parameters: web::Query<CallbackParameters>,
+    oidc: Option<web::Data<OidcClient>>,
+    pool: web::Data<sqlx::PgPool>,
+    session: TypedSession,
+    login_settings: web::Data<LoginSettings>,
+    device: Device,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let flow = session.take_oidc_flow().map_err(e500)?;
+    let (code, flow) = match (&parameters.code, flow) {
+        (Some(code), Some(flow)) if parameters.state.as_ref() == Some(&flow.state) => (code, flow),
+        _ => {
+            if let Some(error) = &parameters.error {
+                tracing::warn!(error, "The identity provider rejected the login");
+            }
+            return Ok(sso_failed());
+        }
+    };
+    let claims = match oidc.exchange_code(code, &flow).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "Failed to complete an OIDC login");
+            return Ok(sso_failed());
+        }
+    };
+    let role = match oidc.role_for(&claims) {
+        Ok(role) => role,
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "The SSO user is not allowed in");
+            return Ok(sso_failed());
+        }
+    };
+    let user_id = match provision_user(&pool, &claims, &role).await {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "Failed to provision an SSO user");
+            return Ok(sso_failed());
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    log_in(
+        &pool,
+        &session,
+        &device,
+        user_id,
+        login_settings.max_sessions_per_user,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
+
+fn sso_failed() -> HttpResponse {
+    FlashMessage::error("Single sign-on failed").send();
+    see_other("/login")
+}
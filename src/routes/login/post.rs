@@ -1,5 +1,9 @@
+use crate::admin_audit::record_failed_login;
+use crate::admin_sessions::{log_in, Device};
 use crate::authentication::AuthError;
 use crate::authentication::{validate_credentials, Credentials};
+use crate::configuration::LoginSettings;
+use crate::request_tracing::ClientIp;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
 use actix_web::error::InternalError;
@@ -7,6 +11,7 @@ use actix_web::http::header::LOCATION;
 use actix_web::web;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use sqlx::PgPool;
 
@@ -17,34 +22,57 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
-    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+    skip(form, pool, session, login_settings, client_ip, device),
+    fields(
+        username=tracing::field::Empty,
+        user_id=tracing::field::Empty,
+        client_ip=client_ip.map(tracing::field::display)
+    )
 )]
 // We are now injecting `PgPool` to retrieve stored credentials from the database
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    login_settings: web::Data<LoginSettings>,
+    client_ip: Option<ClientIp>,
+    device: Device,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    if !login_settings.methods.password() {
+        return Err(login_redirect(LoginError::AuthError(anyhow::anyhow!(
+            "Password login is disabled"
+        ))));
+    }
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            log_in(
+                &pool,
+                &session,
+                &device,
+                user_id,
+                login_settings.max_sessions_per_user,
+            )
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    record_failed_login(&pool, &username, Utc::now())
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
@@ -0,0 +1,14 @@
+use crate::utils::e500;
+use actix_web::HttpResponse;
+use prometheus::{Encoder, TextEncoder};
+
+pub async fn metrics() -> Result<HttpResponse, actix_web::Error> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&prometheus::gather(), &mut buffer)
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer))
+}
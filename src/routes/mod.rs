@@ -1,13 +1,45 @@
 mod admin;
+mod archive;
+mod billing;
+mod delivery_events;
+mod delivery_status;
 mod health_check;
 mod home;
+mod landing_visits;
 mod login;
+mod metrics;
+mod polls;
+mod preferences;
+mod push;
+mod referrals;
+mod scim;
+mod seed_placements;
+mod signup_page;
+mod sms;
+mod sponsorship;
 mod subscriptions;
 mod subscriptions_confirm;
+mod verify_email;
 
 pub use admin::*;
+pub use archive::*;
+pub use billing::*;
+pub use delivery_events::*;
+pub use delivery_status::*;
 pub use health_check::*;
 pub use home::*;
+pub use landing_visits::*;
 pub use login::*;
+pub use metrics::*;
+pub use polls::*;
+pub use preferences::*;
+pub use push::*;
+pub use referrals::*;
+pub use scim::*;
+pub use seed_placements::*;
+pub use signup_page::*;
+pub use sms::*;
+pub use sponsorship::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use verify_email::*;
@@ -1,6 +1,7 @@
 use crate::authentication::{validate_credentials, AuthError, Credentials};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailClient, MessageStream};
+use crate::pii::PiiCipher;
 use crate::routes::error_chain_fmt;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
@@ -36,11 +37,12 @@ pub enum PublishError {
 
 #[tracing::instrument(
 name = "Publish a newsletter issue",
-skip(body, pool, email_client, request), fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+skip(body, pool, pii, email_client, request), fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
     email_client: web::Data<EmailClient>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
@@ -56,7 +58,7 @@ pub async fn publish_newsletter(
 
     tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
 
-    let subscribers = get_confirmed_subscribers(&pool).await?;
+    let subscribers = get_confirmed_subscribers(&pool, &pii).await?;
     for subscriber in subscribers {
         match subscriber {
             Ok(subscriber) => {
@@ -66,6 +68,7 @@ pub async fn publish_newsletter(
                         &body.title,
                         &body.content.html,
                         &body.content.text,
+                        MessageStream::Broadcast,
                     )
                     .await
                     .with_context(|| {
@@ -115,9 +118,10 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Erro
     })
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool, pii))]
 async fn get_confirmed_subscribers(
     pool: &PgPool,
+    pii: &PiiCipher,
 ) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
     let confirmed_subscribers = sqlx::query!(
         r#"
@@ -129,9 +133,11 @@ async fn get_confirmed_subscribers(
     .fetch_all(pool)
     .await?
     .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(error) => Err(anyhow::anyhow!(error)),
+    .map(|r| {
+        let email = pii.open_email(&r.email)?;
+        SubscriberEmail::parse(email)
+            .map(|email| ConfirmedSubscriber { email })
+            .map_err(|error| anyhow::anyhow!(error))
     })
     .collect();
     Ok(confirmed_subscribers)
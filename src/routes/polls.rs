@@ -0,0 +1,95 @@
+use crate::polls::{
+    get_poll, poll_results as get_poll_results, record_vote, PollLinks, PollResults,
+};
+use crate::theme::{Page, Theme};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct VoteParameters {
+    /// The delivery the link was sent with, signed for its recipient.
+    d: String,
+}
+
+/// Count the vote of the recipient of the link, then thank them with the
+/// results so far. Following a link again, for the same option or another,
+/// leaves their first vote as it was.
+#[tracing::instrument(name = "Vote in a poll", skip(parameters, pool, poll_links, theme))]
+pub async fn poll_vote(
+    path: web::Path<(Uuid, usize)>,
+    parameters: web::Query<VoteParameters>,
+    pool: web::Data<PgPool>,
+    poll_links: web::Data<PollLinks>,
+    theme: web::Data<Theme>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (poll_id, option) = path.into_inner();
+    let Some(subscriber_id) = poll_links.voter(poll_id, &parameters.d) else {
+        return Ok(HttpResponse::BadRequest().body("This voting link is invalid or expired."));
+    };
+    let Some(poll) = get_poll(&pool, poll_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if option >= poll.options.len() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_vote(&pool, poll_id, option, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let results = get_poll_results(&pool, poll).await.map_err(e500)?;
+    Ok(render(&theme, &results, true))
+}
+
+/// The live results of a poll.
+#[tracing::instrument(name = "Show the results of a poll", skip(pool, theme))]
+pub async fn poll_results(
+    poll_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    theme: web::Data<Theme>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(poll) = get_poll(&pool, poll_id.into_inner()).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let results = get_poll_results(&pool, poll).await.map_err(e500)?;
+    Ok(render(&theme, &results, false))
+}
+
+fn render(theme: &Theme, results: &PollResults, voted: bool) -> HttpResponse {
+    let mut options_html = String::new();
+    for option in &results.options {
+        let share = if results.votes > 0 {
+            option.votes * 100 / results.votes
+        } else {
+            0
+        };
+        writeln!(
+            options_html,
+            "<li>{}: {} ({}%)</li>",
+            htmlescape::encode_minimal(&option.option),
+            option.votes,
+            share
+        )
+        .unwrap();
+    }
+    let content = format!(
+        r#"{}
+    <h1>{}</h1>
+    <ul>
+    {}</ul>
+    <p>{} votes so far.</p>"#,
+        if voted {
+            "<p>Thank you for voting!</p>"
+        } else {
+            ""
+        },
+        htmlescape::encode_minimal(&results.question),
+        options_html,
+        results.votes
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("poll", "Poll results", &content)))
+}
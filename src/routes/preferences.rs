@@ -0,0 +1,119 @@
+use crate::digests::{get_digest_frequency, set_digest_frequency, DigestFrequency};
+use crate::landing_analytics::{get_no_tracking, set_no_tracking};
+use crate::links;
+use crate::theme::{Page, Theme};
+use crate::utils::{e500, see_other};
+use crate::verified_subscriber::VerifiedSubscriber;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Show the preferences of a subscriber",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn subscriber_preferences(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    theme: web::Data<Theme>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let frequency = get_digest_frequency(&pool, subscriber.id)
+        .await
+        .map_err(e500)?;
+    let no_tracking = get_no_tracking(&pool, subscriber.id).await.map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let mut options = String::new();
+    for option in DigestFrequency::ALL {
+        let checked = if option == frequency { " checked" } else { "" };
+        writeln!(
+            options,
+            r#"        <label><input type="radio" name="frequency" value="{}"{}> {}</label><br>"#,
+            option.as_str(),
+            checked,
+            option.description()
+        )
+        .unwrap();
+    }
+    let content = format!(
+        r#"{msg_html}
+    <h1>Your preferences</h1>
+    <form action="{}" method="post">
+        <p>How would you like to get our issues?</p>
+{options}        <p><label><input type="checkbox" name="no_tracking"{}> Do not track the visits I make to our website from the links of our issues</label></p>
+        <button type="submit">Save</button>
+    </form>"#,
+        htmlescape::encode_attribute(
+            &links::subscriber_preferences(&parameters.subscription_token).to_string()
+        ),
+        if no_tracking { " checked" } else { "" }
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("preferences", "Your preferences", &content))))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    frequency: String,
+    /// Only sent when the box is ticked.
+    no_tracking: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Update the preferences of a subscriber",
+    skip_all,
+    fields(subscriber_id = %subscriber.id, frequency = %form.frequency)
+)]
+pub async fn update_subscriber_preferences(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<PreferencesParameters>,
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences_page =
+        links::subscriber_preferences(&parameters.subscription_token).to_string();
+    let frequency = match DigestFrequency::parse(&form.frequency) {
+        Ok(frequency) => frequency,
+        Err(_) => {
+            // Not the error: it quotes the submitted value.
+            FlashMessage::error("Please pick one of the offered frequencies.").send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    set_digest_frequency(&mut *transaction, subscriber.id, frequency)
+        .await
+        .context("Failed to update the digest frequency of a subscriber")
+        .map_err(e500)?;
+    set_no_tracking(&mut *transaction, subscriber.id, form.no_tracking.is_some())
+        .await
+        .context("Failed to update the tracking preference of a subscriber")
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(match frequency {
+        DigestFrequency::Immediate => "We will send you each issue as soon as it is published.",
+        DigestFrequency::Weekly => "We will send you a digest of our issues every week.",
+        DigestFrequency::Monthly => "We will send you a digest of our issues every month.",
+    })
+    .send();
+    Ok(see_other(&preferences_page))
+}
@@ -0,0 +1,51 @@
+use crate::utils::e500;
+use crate::verified_subscriber::VerifiedSubscriber;
+use crate::web_push::{save_push_subscription, PushSubscription, WebPush};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Shows the notifications of the issues, and opens the issue clicked on.
+const SERVICE_WORKER: &str = r#"self.addEventListener("push", (event) => {
+  const issue = event.data.json();
+  event.waitUntil(self.registration.showNotification(issue.title, { data: issue.url }));
+});
+self.addEventListener("notificationclick", (event) => {
+  event.notification.close();
+  event.waitUntil(clients.openWindow(event.notification.data));
+});
+"#;
+
+/// Register a browser of the subscriber for the push notifications of the
+/// issues, with the subscription its push manager handed out.
+#[tracing::instrument(
+    name = "Register a push subscription",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn register_push_subscription(
+    subscriber: VerifiedSubscriber,
+    subscription: web::Json<PushSubscription>,
+    pool: web::Data<PgPool>,
+    web_push: Option<web::Data<WebPush>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(web_push) = web_push else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if let Err(e) = subscription.validate(web_push.push_services()) {
+        return Ok(HttpResponse::BadRequest().body(e));
+    }
+    save_push_subscription(&pool, subscriber.id, &subscription)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The service worker behind the notifications.
+pub async fn push_service_worker(web_push: Option<web::Data<WebPush>>) -> HttpResponse {
+    if web_push.is_none() {
+        return HttpResponse::NotFound().finish();
+    }
+    HttpResponse::Ok()
+        .content_type("text/javascript")
+        .body(SERVICE_WORKER)
+}
@@ -0,0 +1,38 @@
+use crate::referrals::{is_known_code, is_valid_code};
+use crate::theme::{Page, Theme};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+/// Where a share link leads: a subscribe form crediting the subscriber who
+/// shared it.
+#[tracing::instrument(name = "Show a referral signup page", skip(pool, theme))]
+pub async fn referral_signup_page(
+    code: web::Path<String>,
+    pool: web::Data<PgPool>,
+    theme: web::Data<Theme>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let code = code.into_inner();
+    if !is_valid_code(&code) || !is_known_code(&pool, &code).await.map_err(e500)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    // The code is alphanumeric: it needs no escaping.
+    let content = format!(
+        r#"<h1>A friend thinks you will like our newsletter</h1>
+    <form action="/subscriptions" method="post">
+        <input type="hidden" name="source" value="referral">
+        <input type="hidden" name="referral_code" value="{code}">
+        <label>Name
+            <input type="text" name="name" required>
+        </label>
+        <label>Email
+            <input type="email" name="email" required>
+        </label>
+        <button type="submit">Subscribe</button>
+    </form>"#
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new("signup", "Subscribe", &content))))
+}
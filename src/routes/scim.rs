@@ -0,0 +1,392 @@
+//! A minimal SCIM v2 Users endpoint (RFC 7644): enough for an identity
+//! provider to create, list and deactivate admins.
+use crate::database::ObserveQuery;
+use crate::links;
+use crate::routes::error_chain_fmt;
+use crate::startup::ApplicationBaseUrl;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(thiserror::Error)]
+pub enum ScimError {
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("{0}")]
+    InvalidFilter(String),
+    #[error("A user named {0} already exists")]
+    Uniqueness(String),
+    #[error("User not found")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ScimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ScimError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ScimError::InvalidValue(_) | ScimError::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+            ScimError::Uniqueness(_) => StatusCode::CONFLICT,
+            ScimError::NotFound => StatusCode::NOT_FOUND,
+            ScimError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let scim_type = match self {
+            ScimError::InvalidValue(_) => Some("invalidValue"),
+            ScimError::InvalidFilter(_) => Some("invalidFilter"),
+            ScimError::Uniqueness(_) => Some("uniqueness"),
+            ScimError::NotFound | ScimError::UnexpectedError(_) => None,
+        };
+        let detail = match self {
+            ScimError::UnexpectedError(_) => "Something went wrong".to_string(),
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code())
+            .content_type(SCIM_CONTENT_TYPE)
+            .json(serde_json::json!({
+                "schemas": [ERROR_SCHEMA],
+                "status": self.status_code().as_u16().to_string(),
+                "scimType": scim_type,
+                "detail": detail,
+            }))
+    }
+}
+
+struct ScimUser {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    active: bool,
+}
+
+impl ScimUser {
+    fn to_resource(&self, base_url: &str) -> serde_json::Value {
+        let emails: Vec<_> = self
+            .email
+            .iter()
+            .map(|email| serde_json::json!({ "value": email, "primary": true }))
+            .collect();
+        serde_json::json!({
+            "schemas": [USER_SCHEMA],
+            "id": self.user_id,
+            "userName": self.username,
+            "active": self.active,
+            "emails": emails,
+            "meta": {
+                "resourceType": "User",
+                "location": links::scim_user(self.user_id).absolute(base_url),
+            },
+        })
+    }
+}
+
+fn scim_response(status: StatusCode, body: serde_json::Value) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(SCIM_CONTENT_TYPE)
+        .json(body)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListParameters {
+    filter: Option<String>,
+    start_index: Option<i64>,
+    count: Option<i64>,
+}
+
+/// Only `userName eq "<value>"` is supported, which is what identity
+/// providers use to look up an account before creating it.
+fn parse_filter(filter: &str) -> Result<String, ScimError> {
+    let mut parts = filter.splitn(3, ' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(attribute), Some(operator), Some(value))
+            if attribute.eq_ignore_ascii_case("userName")
+                && operator.eq_ignore_ascii_case("eq") =>
+        {
+            value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map(str::to_owned)
+                .ok_or_else(|| ScimError::InvalidFilter("The value must be quoted".into()))
+        }
+        _ => Err(ScimError::InvalidFilter(format!(
+            "Unsupported filter: {}",
+            filter
+        ))),
+    }
+}
+
+#[tracing::instrument(name = "List SCIM users", skip(parameters, pool, base_url))]
+pub async fn scim_list_users(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ScimError> {
+    let username = parameters.filter.as_deref().map(parse_filter).transpose()?;
+    let start_index = parameters.start_index.unwrap_or(1).max(1);
+    let count = parameters
+        .count
+        .unwrap_or(MAX_PAGE_SIZE)
+        .clamp(0, MAX_PAGE_SIZE);
+    let total = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM users
+        WHERE $1::text IS NULL OR username = $1
+        "#,
+        username
+    )
+    .fetch_one(pool.get_ref())
+    .observe_one("count_scim_users")
+    .await
+    .context("Failed to count users")?
+    .count;
+    let users = sqlx::query_as!(
+        ScimUser,
+        r#"
+        SELECT user_id, username, email, active
+        FROM users
+        WHERE $1::text IS NULL OR username = $1
+        ORDER BY username
+        OFFSET $2
+        LIMIT $3
+        "#,
+        username,
+        start_index - 1,
+        count
+    )
+    .fetch_all(pool.get_ref())
+    .observe("list_scim_users")
+    .await
+    .context("Failed to list users")?;
+    let resources: Vec<_> = users.iter().map(|u| u.to_resource(&base_url.0)).collect();
+    Ok(scim_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "schemas": [LIST_RESPONSE_SCHEMA],
+            "totalResults": total,
+            "startIndex": start_index,
+            "itemsPerPage": resources.len(),
+            "Resources": resources,
+        }),
+    ))
+}
+
+#[tracing::instrument(name = "Get a SCIM user", skip(pool, base_url))]
+pub async fn scim_get_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ScimError> {
+    let user = get_user(&pool, user_id.into_inner())
+        .await?
+        .ok_or(ScimError::NotFound)?;
+    Ok(scim_response(StatusCode::OK, user.to_resource(&base_url.0)))
+}
+
+async fn get_user(pool: &PgPool, user_id: Uuid) -> Result<Option<ScimUser>, anyhow::Error> {
+    let user = sqlx::query_as!(
+        ScimUser,
+        r#"SELECT user_id, username, email, active FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .observe("get_scim_user")
+    .await
+    .context("Failed to fetch the user")?;
+    Ok(user)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUserBody {
+    user_name: String,
+    #[serde(default)]
+    emails: Vec<Email>,
+    active: Option<bool>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Email {
+    value: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+/// Provisioned admins have no password: they log in through single sign-on,
+/// whose first login claims the account, or a magic link.
+#[tracing::instrument(
+    name = "Create a SCIM user",
+    skip(body, pool, base_url),
+    fields(username = %body.user_name)
+)]
+pub async fn scim_create_user(
+    body: web::Json<CreateUserBody>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ScimError> {
+    if body.user_name.trim().is_empty() {
+        return Err(ScimError::InvalidValue("userName is required".into()));
+    }
+    let email = body
+        .emails
+        .iter()
+        .find(|e| e.primary)
+        .or(body.emails.first())
+        .map(|e| e.value.clone());
+    let user = ScimUser {
+        user_id: Uuid::new_v4(),
+        username: body.user_name.clone(),
+        email,
+        active: body.active.unwrap_or(true),
+    };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, active)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user.user_id,
+        user.username,
+        user.email,
+        user.active
+    )
+    .execute(pool.get_ref())
+    .observe("insert_scim_user")
+    .await
+    .context("Failed to insert the user")?;
+    if inserted.rows_affected() == 0 {
+        return Err(ScimError::Uniqueness(user.username));
+    }
+    Ok(scim_response(
+        StatusCode::CREATED,
+        user.to_resource(&base_url.0),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PatchBody {
+    operations: Vec<PatchOperation>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    value: serde_json::Value,
+}
+
+/// Some identity providers send booleans as strings.
+fn as_bool(value: &serde_json::Value) -> Option<bool> {
+    match value {
+        serde_json::Value::Bool(b) => Some(*b),
+        serde_json::Value::String(s) => s.to_ascii_lowercase().parse().ok(),
+        _ => None,
+    }
+}
+
+/// The `active` value set by a PATCH request: only replacing `active` is
+/// supported, either through its path or as part of a value object.
+fn requested_active(body: &PatchBody) -> Result<Option<bool>, ScimError> {
+    let mut active = None;
+    for operation in &body.operations {
+        if !operation.op.eq_ignore_ascii_case("replace") {
+            return Err(ScimError::InvalidValue(format!(
+                "Unsupported operation: {}",
+                operation.op
+            )));
+        }
+        let value = match operation.path.as_deref() {
+            Some(path) if path.eq_ignore_ascii_case("active") => &operation.value,
+            Some(path) => {
+                return Err(ScimError::InvalidValue(format!(
+                    "Unsupported path: {}",
+                    path
+                )))
+            }
+            None => &operation.value["active"],
+        };
+        active = Some(
+            as_bool(value)
+                .ok_or_else(|| ScimError::InvalidValue("active must be a boolean".into()))?,
+        );
+    }
+    Ok(active)
+}
+
+#[tracing::instrument(name = "Patch a SCIM user", skip(body, pool, base_url))]
+pub async fn scim_patch_user(
+    user_id: web::Path<Uuid>,
+    body: web::Json<PatchBody>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, ScimError> {
+    let user_id = user_id.into_inner();
+    if let Some(active) = requested_active(&body)? {
+        sqlx::query!(
+            r#"UPDATE users SET active = $2 WHERE user_id = $1"#,
+            user_id,
+            active
+        )
+        .execute(pool.get_ref())
+        .observe("set_user_active")
+        .await
+        .context("Failed to update the user")?;
+    }
+    let user = get_user(&pool, user_id).await?.ok_or(ScimError::NotFound)?;
+    Ok(scim_response(StatusCode::OK, user.to_resource(&base_url.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_filter, requested_active, PatchBody};
+    use claims::{assert_err, assert_ok_eq};
+
+    fn patch(body: serde_json::Value) -> PatchBody {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn username_filters_are_parsed() {
+        assert_ok_eq!(parse_filter(r#"userName eq "grace""#), "grace".to_string());
+        assert_err!(parse_filter(r#"emails co "grace""#));
+        assert_err!(parse_filter("userName eq grace"));
+    }
+
+    #[test]
+    fn deactivation_is_understood_in_both_patch_styles() {
+        let by_path = patch(serde_json::json!({
+            "Operations": [{ "op": "replace", "path": "active", "value": false }]
+        }));
+        assert_ok_eq!(requested_active(&by_path), Some(false));
+        let by_value = patch(serde_json::json!({
+            "Operations": [{ "op": "Replace", "value": { "active": "False" } }]
+        }));
+        assert_ok_eq!(requested_active(&by_value), Some(false));
+    }
+
+    #[test]
+    fn other_patches_are_rejected() {
+        let body = patch(serde_json::json!({
+            "Operations": [{ "op": "replace", "path": "userName", "value": "ada" }]
+        }));
+        assert_err!(requested_active(&body));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::authentication::{bearer_token, tokens_match};
+use crate::configuration::SeedListSettings;
+use crate::seed_list::{record_placement, Placement, PlacementSource};
+use crate::utils::e500;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct SeedPlacementReport {
+    issue_id: Uuid,
+    seed_email: String,
+    placement: Placement,
+}
+
+/// Placements reported by an inbox-placement monitoring service, which
+/// authenticates with `Authorization: Bearer <webhook_token>`.
+#[tracing::instrument(
+    name = "Receive a seed placement report",
+    skip(request, report, pool, seed_list),
+    fields(newsletter_issue_id = %report.issue_id, seed_email = %report.seed_email)
+)]
+pub async fn seed_placement_webhook(
+    request: HttpRequest,
+    report: web::Json<SeedPlacementReport>,
+    pool: web::Data<PgPool>,
+    seed_list: Option<web::Data<SeedListSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(seed_list) = seed_list else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let valid = bearer_token(request.headers())
+        .is_some_and(|token| tokens_match(token, seed_list.webhook_token.expose_secret()));
+    if !valid {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let recorded = record_placement(
+        &pool,
+        report.issue_id,
+        &report.seed_email,
+        report.placement,
+        PlacementSource::Webhook,
+    )
+    .await
+    .context("Failed to record a seed placement")
+    .map_err(e500)?;
+    if recorded {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::configuration::{HttpCacheSettings, SignupPageSettings};
+use crate::http_cache::cached_response;
+use crate::i18n::Language;
+use crate::theme::{is_hex_color, Page, Theme};
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
+use htmlescape::encode_minimal;
+use std::collections::HashMap;
+
+/// The hosted signup pages, by slug.
+#[derive(Clone, Default)]
+pub struct SignupPages(HashMap<String, SignupPageSettings>);
+
+impl SignupPages {
+    /// The slug ends up in URLs and the accent color in CSS: both are checked
+    /// strictly rather than escaped.
+    pub fn parse(pages: &[SignupPageSettings]) -> Result<Self, String> {
+        let mut by_slug = HashMap::new();
+        for page in pages {
+            let valid_slug = !page.slug.is_empty()
+                && page
+                    .slug
+                    .bytes()
+                    .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'-'));
+            if !valid_slug {
+                return Err(format!("{} is not a valid slug", page.slug));
+            }
+            if let Some(accent_color) = &page.accent_color {
+                if !is_hex_color(accent_color) {
+                    return Err(format!(
+                        "{}: {} is not a #rrggbb color",
+                        page.slug, accent_color
+                    ));
+                }
+            }
+            if let Some(logo_url) = &page.logo_url {
+                if !logo_url.starts_with("https://") {
+                    return Err(format!("{}: the logo must be an https URL", page.slug));
+                }
+            }
+            if by_slug.insert(page.slug.clone(), page.clone()).is_some() {
+                return Err(format!("{} is used by more than one page", page.slug));
+            }
+        }
+        Ok(Self(by_slug))
+    }
+}
+
+/// A subscribe form for people without a website of their own to embed it in.
+#[tracing::instrument(
+    name = "Show a hosted signup page",
+    skip(request, pages, theme, http_cache)
+)]
+pub async fn hosted_signup_page(
+    request: HttpRequest,
+    slug: web::Path<String>,
+    pages: web::Data<SignupPages>,
+    theme: web::Data<Theme>,
+    http_cache: web::Data<HttpCacheSettings>,
+) -> HttpResponse {
+    let Some(page) = pages.0.get(slug.as_str()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let title = encode_minimal(&page.title);
+    let content = format!(
+        r#"<h1>{title}</h1>
+    <p>{}</p>
+    <form action="/subscriptions" method="post">
+        <input type="hidden" name="source" value="l/{}">
+        <label>Name
+            <input type="text" name="name" required>
+        </label>
+        <label>Email
+            <input type="email" name="email" required>
+        </label>
+        <label aria-hidden="true" style="position: absolute; left: -10000px;">Website
+            <input type="text" name="website" tabindex="-1" autocomplete="off">
+        </label>
+        <button type="submit">Subscribe</button>
+    </form>"#,
+        encode_minimal(&page.description),
+        page.slug
+    );
+    let body = theme.render(Page {
+        name: "signup",
+        title: &title,
+        content: &content,
+        accent_color: page.accent_color.as_deref(),
+        logo_url: page.logo_url.as_deref(),
+        language: Language::English,
+    });
+    cached_response(
+        &request,
+        ContentType::html(),
+        body,
+        http_cache.signup_page_max_age_seconds,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignupPages;
+    use crate::configuration::SignupPageSettings;
+
+    fn page(slug: &str, accent_color: &str) -> SignupPageSettings {
+        SignupPageSettings {
+            slug: slug.into(),
+            title: "Earthsea".into(),
+            description: String::new(),
+            logo_url: None,
+            accent_color: Some(accent_color.into()),
+        }
+    }
+
+    #[test]
+    fn slugs_and_colors_are_validated() {
+        assert!(SignupPages::parse(&[page("earthsea-news", "#1a2B3c")]).is_ok());
+        assert!(SignupPages::parse(&[page("Earthsea", "#1a2b3c")]).is_err());
+        assert!(SignupPages::parse(&[page("earthsea", "red;}body{")]).is_err());
+        assert!(SignupPages::parse(&[page("earthsea", "#fff")]).is_err());
+    }
+
+    #[test]
+    fn slugs_are_unique() {
+        let pages = [page("earthsea", "#000000"), page("earthsea", "#ffffff")];
+        assert!(SignupPages::parse(&pages).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use crate::admin_sessions::Device;
+use crate::configuration::SmsSettings;
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::domain::SubscriberPhone;
+use crate::links;
+use crate::pii::PiiCipher;
+use crate::sms::{
+    confirm_phone_verification, get_sms_status, set_sms_opt_in, start_phone_verification,
+    PhoneVerificationError, PhoneVerificationOutcome, SmsProvider,
+};
+use crate::theme::{Page, Theme};
+use crate::utils::{e500, see_other};
+use crate::verified_subscriber::VerifiedSubscriber;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct SmsPreferencesParameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(
+    name = "Show the SMS preferences of a subscriber",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn sms_preferences(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<SmsPreferencesParameters>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    theme: web::Data<Theme>,
+    flash_messages: IncomingFlashMessages,
+    settings: Option<web::Data<SmsSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(settings) = settings else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let status = get_sms_status(&pool, subscriber.id).await.map_err(e500)?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(m.content())
+        )
+        .unwrap();
+    }
+    let action = links::sms_preferences(&parameters.subscription_token).to_string();
+    let preferences = match (
+        &status.phone_number,
+        status.phone_verified,
+        status.opted_in_at,
+    ) {
+        (Some(phone_number), true, None) => format!(
+            r#"<p>Get our short announcements at {} too.</p>
+    <p>{}</p>
+    <form action="{}" method="post">
+        <input type="hidden" name="opt_in" value="true">
+        <button type="submit">Text me</button>
+    </form>"#,
+            htmlescape::encode_minimal(&pii.open(phone_number).map_err(e500)?),
+            htmlescape::encode_minimal(&settings.consent_text),
+            htmlescape::encode_attribute(&action)
+        ),
+        (Some(phone_number), true, Some(opted_in_at)) => format!(
+            r#"<p>We text our short announcements to {} since {}.</p>
+    <form action="{}" method="post">
+        <input type="hidden" name="opt_in" value="false">
+        <button type="submit">Stop the texts</button>
+    </form>"#,
+            htmlescape::encode_minimal(&pii.open(phone_number).map_err(e500)?),
+            opted_in_at.format("%Y-%m-%d"),
+            htmlescape::encode_attribute(&action)
+        ),
+        _ => "<p>We can only text you once you provided and verified your phone number.</p>"
+            .to_string(),
+    };
+    let verification = if status.verification_pending {
+        format!(
+            r#"
+    <form action="{}" method="post">
+        <label>The code we texted you:
+            <input type="text" name="code" inputmode="numeric" autocomplete="one-time-code">
+        </label>
+        <button type="submit">Verify</button>
+    </form>"#,
+            htmlescape::encode_attribute(
+                &links::sms_phone_verification(&parameters.subscription_token).to_string()
+            )
+        )
+    } else {
+        String::new()
+    };
+    let content = format!(
+        r#"{msg_html}
+    <h1>Announcements by text</h1>
+    {preferences}
+    <h2>Your phone number</h2>
+    <form action="{}" method="post">
+        <label>International number, e.g. +14155550100:
+            <input type="tel" name="phone_number" autocomplete="tel">
+        </label>
+        <button type="submit">Text me a code</button>
+    </form>{verification}"#,
+        htmlescape::encode_attribute(&links::sms_phone(&parameters.subscription_token).to_string())
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page::new(
+            "sms_preferences",
+            "Announcements by text",
+            &content,
+        ))))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SmsPreferencesFormData {
+    opt_in: bool,
+}
+
+/// Opting in, or out, is recorded as a consent, with the version of the
+/// wording shown next to the button.
+#[tracing::instrument(
+    name = "Update the SMS preferences of a subscriber",
+    skip_all,
+    fields(subscriber_id = %subscriber.id, opt_in = form.opt_in)
+)]
+pub async fn update_sms_preferences(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<SmsPreferencesParameters>,
+    form: web::Form<SmsPreferencesFormData>,
+    pool: web::Data<PgPool>,
+    device: Device,
+    settings: Option<web::Data<SmsSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(settings) = settings else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let preferences_page = links::sms_preferences(&parameters.subscription_token).to_string();
+    let status = get_sms_status(&pool, subscriber.id).await.map_err(e500)?;
+    if form.opt_in && !status.phone_verified {
+        FlashMessage::error("Please verify your phone number first.").send();
+        return Ok(see_other(&preferences_page));
+    }
+    let consent = ConsentContext {
+        device,
+        text_version: settings.consent_text_version.clone(),
+    };
+    let action = if form.opt_in {
+        ConsentAction::SmsOptedIn
+    } else {
+        ConsentAction::SmsOptedOut
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    set_sms_opt_in(&mut transaction, subscriber.id, form.opt_in)
+        .await
+        .context("Failed to update the SMS opt-in of a subscriber")
+        .map_err(e500)?;
+    record_consent(&mut *transaction, subscriber.id, action, &consent, None)
+        .await
+        .context("Failed to record an SMS consent")
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    FlashMessage::info(if form.opt_in {
+        "We will text you our announcements."
+    } else {
+        "We will not text you anymore."
+    })
+    .send();
+    Ok(see_other(&preferences_page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PhoneNumberFormData {
+    phone_number: String,
+}
+
+/// The number is only stored as the subscriber's once they typed back the
+/// code texted to it.
+#[tracing::instrument(
+    name = "Start the verification of a phone number",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn provide_phone_number(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<SmsPreferencesParameters>,
+    form: web::Form<PhoneNumberFormData>,
+    pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
+    provider: Option<web::Data<dyn SmsProvider>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(provider) = provider else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let preferences_page = links::sms_preferences(&parameters.subscription_token).to_string();
+    let phone = match SubscriberPhone::parse(form.0.phone_number) {
+        Ok(phone) => phone,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+    match start_phone_verification(&pool, &pii, provider.as_ref(), subscriber.id, &phone).await {
+        Ok(()) => FlashMessage::info(format!("We texted a code to {}.", phone)).send(),
+        Err(PhoneVerificationError::TooSoon) => FlashMessage::error(
+            "We texted you a code less than a minute ago. Please wait before asking for another.",
+        )
+        .send(),
+        Err(PhoneVerificationError::Sms(e)) => {
+            tracing::warn!(error.message = %e, "Failed to text a verification code");
+            FlashMessage::error("We could not text this number. Please check it and try again.")
+                .send()
+        }
+        Err(PhoneVerificationError::Unexpected(e)) => return Err(e500(e)),
+    }
+    Ok(see_other(&preferences_page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerificationCodeFormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify a phone number",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn verify_phone_number(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<SmsPreferencesParameters>,
+    form: web::Form<VerificationCodeFormData>,
+    pool: web::Data<PgPool>,
+    provider: Option<web::Data<dyn SmsProvider>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if provider.is_none() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let preferences_page = links::sms_preferences(&parameters.subscription_token).to_string();
+    let outcome = confirm_phone_verification(&pool, subscriber.id, &form.code)
+        .await
+        .context("Failed to verify a phone number")
+        .map_err(e500)?;
+    match outcome {
+        PhoneVerificationOutcome::Verified => {
+            FlashMessage::info("Your phone number is verified.").send()
+        }
+        PhoneVerificationOutcome::WrongCode => {
+            FlashMessage::error("This is not the code we texted you.").send()
+        }
+        PhoneVerificationOutcome::NoCode => {
+            FlashMessage::error("Your code expired. Please ask for a new one.").send()
+        }
+    }
+    Ok(see_other(&preferences_page))
+}
@@ -0,0 +1,41 @@
+use crate::sponsorship::{record_slot_event, SlotEvent, PIXEL};
+use crate::utils::e500;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use uuid::Uuid;
+
+/// Count the click and send the reader on to the sponsor.
+#[tracing::instrument(name = "Follow a sponsor link")]
+pub async fn sponsor_click(
+    slot_id: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match record_slot_event(&pool, slot_id.into_inner(), SlotEvent::Click)
+        .await
+        .map_err(e500)?
+    {
+        Some(target_url) => Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, target_url))
+            .finish()),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Count the impression. The pixel is never cached, or the opens after the
+/// first would not reach us.
+#[tracing::instrument(name = "Count a sponsor impression")]
+pub async fn sponsor_impression(
+    slot_id: web::Path<Uuid>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match record_slot_event(&pool, slot_id.into_inner(), SlotEvent::Impression)
+        .await
+        .map_err(e500)?
+    {
+        Some(_) => Ok(HttpResponse::Ok()
+            .content_type("image/gif")
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .body(PIXEL)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
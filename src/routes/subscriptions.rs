@@ -1,5 +1,19 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::admin_events::{AdminEvent, AdminEventBroadcaster};
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::{
+    DomainValidationError, Locale, NewSubscriber, SubscriberEmail, SubscriberName,
+};
+use crate::email_client::{EmailClient, MessageStream, SendEmailError, SentEmail};
+use crate::i18n::{validation_message, Language, PreferredLanguage};
+use crate::links;
+use crate::pii::PiiCipher;
+use crate::quarantine::quarantine;
+use crate::referrals::{is_valid_code, record_referrer};
+use crate::signup_protection::{
+    record_signup, Rejection, Screening, SignupAttempt, SignupProtection,
+};
 use crate::startup::ApplicationBaseUrl;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
@@ -11,26 +25,56 @@ use sqlx::{Executor, PgPool, Postgres, Transaction};
 use std::convert::{TryFrom, TryInto};
 use uuid::Uuid;
 
+/// A subscription request repeated within this many seconds, e.g. by a
+/// double click, is answered without sending another confirmation email.
+const RETRY_WINDOW_SECONDS: i64 = 60;
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Which form was used, kept with the consent record.
+    #[serde(default)]
+    source: Option<String>,
+    /// e.g. `pt-br`. Issues translated to it are sent in it.
+    #[serde(default)]
+    locale: Option<String>,
+    /// The code of the share link the form was reached through.
+    #[serde(default)]
+    referral_code: Option<String>,
+    /// The token of the captcha widget, checked in strict mode.
+    #[serde(default)]
+    captcha_response: Option<String>,
+    /// A honeypot: hidden from people, so only bots fill it in.
+    #[serde(default)]
+    website: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = DomainValidationError;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        let locale = value
+            .locale
+            .filter(|locale| !locale.trim().is_empty())
+            .map(Locale::parse)
+            .transpose()?;
+        Ok(Self {
+            email,
+            name,
+            locale,
+        })
     }
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
-    ValidationError(String),
+    ValidationError(DomainValidationError, Language),
+    #[error("The signup was turned away in strict mode: {0:?}")]
+    Rejected(Rejection),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -44,43 +88,152 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(..) => StatusCode::BAD_REQUEST,
+            SubscribeError::Rejected(Rejection::CaptchaFailed) => StatusCode::BAD_REQUEST,
+            SubscribeError::Rejected(Rejection::TooManySignups) => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(e, language) => {
+                HttpResponse::BadRequest().json(serde_json::json!({
+                    "field": e.field(),
+                    "rule": e.rule(),
+                    "message": validation_message(e, *language),
+                }))
+            }
+            SubscribeError::Rejected(Rejection::CaptchaFailed) => {
+                HttpResponse::BadRequest().json(serde_json::json!({
+                    "field": "captcha_response",
+                    "rule": "captcha",
+                    "message": "Please complete the captcha.",
+                }))
+            }
+            SubscribeError::Rejected(Rejection::TooManySignups) => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", "3600"))
+                .finish(),
+            SubscribeError::UnexpectedError(_) => {
+                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(
+        form,
+        pool,
+        pii,
+        email_client,
+        base_url,
+        admin_events,
+        consent,
+        language,
+        signup_protection
+    ),
     fields(
         subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_name = %form.name,
+        client_ip = consent.device.client_ip.as_deref()
     )
 )]
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    pii: web::Data<PiiCipher>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    admin_events: web::Data<AdminEventBroadcaster>,
+    consent: ConsentContext,
+    language: PreferredLanguage,
+    signup_protection: Option<web::Data<SignupProtection>>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let form = form.into_inner();
+    let form_source = form.source.clone();
+    let referral_code = form.referral_code.clone();
+    let attempt = SignupAttempt {
+        name: &form.name,
+        honeypot: form.website.as_deref(),
+        captcha_response: form.captcha_response.as_deref(),
+        client_ip: consent.device.client_ip.as_deref(),
+    };
+    let screening = match signup_protection {
+        Some(signup_protection) => signup_protection
+            .screen(&pool, &attempt, Utc::now())
+            .await
+            .context("Failed to screen a signup.")?,
+        None => Screening::Allowed,
+    };
+    let quarantine_reason = match screening {
+        Screening::Allowed => None,
+        Screening::Quarantined(reason) => Some(reason),
+        Screening::Rejected(rejection) => return Err(SubscribeError::Rejected(rejection)),
+    };
+    let new_subscriber = form
+        .try_into()
+        .map_err(|e| SubscribeError::ValidationError(e, language.0))?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    if let Some(subscriber_id) = find_repeated_request(&mut transaction, &pii, &new_subscriber)
+        .await
+        .context("Failed to look for a repeated subscription request.")?
+    {
+        tracing::info!(
+            %subscriber_id,
+            "Ignoring a repeated subscription request: the confirmation email is on its way"
+        );
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let subscriber_id = insert_subscriber(&mut transaction, &pii, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    record_consent(
+        &mut *transaction,
+        subscriber_id,
+        ConsentAction::Subscribed,
+        &consent,
+        form_source.as_deref(),
+    )
+    .await
+    .context("Failed to record the consent of a new subscriber.")?;
+    record_signup(&mut *transaction, form_source.as_deref(), Utc::now())
+        .await
+        .context("Failed to count a signup.")?;
+    if let Some(code) = referral_code.filter(|code| is_valid_code(code)) {
+        record_referrer(&mut transaction, subscriber_id, &code)
+            .await
+            .context("Failed to record the referrer of a new subscriber.")?;
+    }
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
+    if let Some(reason) = quarantine_reason {
+        quarantine(&mut *transaction, subscriber_id, reason, Utc::now())
+            .await
+            .context("Failed to quarantine a new subscriber.")?;
+    }
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
-    send_confirmation_email(
+    if quarantine_reason.is_some() {
+        // Bots are not told: the confirmation email waits for an admin.
+        tracing::warn!(
+            %subscriber_id,
+            reason = quarantine_reason,
+            "A signup was quarantined"
+        );
+        return Ok(HttpResponse::Ok().finish());
+    }
+    admin_events.publish(AdminEvent::NewSubscriber { subscriber_id });
+    let sent = send_confirmation_email(
         &email_client,
         new_subscriber,
         &base_url.0,
@@ -88,10 +241,13 @@ pub async fn subscribe(
     )
     .await
     .context("Failed to send a confirmation email.")?;
+    record_send(pool.get_ref(), &sent)
+        .await
+        .context("Failed to record the cost of a confirmation email.")?;
     Ok(HttpResponse::Ok().finish())
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -108,11 +264,8 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token
-    );
+) -> Result<SentEmail, SendEmailError> {
+    let confirmation_link = links::subscription_confirmation(subscription_token).absolute(base_url);
     let plain_body = format!(
         "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
         confirmation_link
@@ -122,30 +275,80 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(
+            &new_subscriber.email,
+            "Welcome!",
+            &html_body,
+            &plain_body,
+            MessageStream::Transactional,
+        )
         .await
 }
 
+/// The subscriber that asked to subscribe with the same email, normalised,
+/// within the retry window and is still to confirm. Requests for one email
+/// are serialised until the end of `transaction`, so that concurrent
+/// duplicates find the subscriber the first one stores.
+#[tracing::instrument(skip_all)]
+async fn find_repeated_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    pii: &PiiCipher,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let email = new_subscriber.email.as_ref().trim().to_lowercase();
+    let query = sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtextextended($1, 0))",
+        email
+    );
+    transaction
+        .execute(query)
+        .observe("lock_subscription_requests")
+        .await?;
+    // Sealed emails only match through their blind index.
+    let r = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE (email_index = $1 OR lower(email) = $2)
+            AND status IN ('pending_confirmation', 'quarantined')
+            AND deleted_at IS NULL
+            AND subscribed_at > $3
+        "#,
+        pii.email_index(&email),
+        email,
+        Utc::now() - chrono::Duration::seconds(RETRY_WINDOW_SECONDS)
+    )
+    .fetch_optional(&mut **transaction)
+    .observe("find_repeated_subscription_request")
+    .await?;
+    Ok(r.map(|r| r.id))
+}
+
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(new_subscriber, transaction)
+    skip(new_subscriber, transaction, pii)
 )]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
+    pii: &PiiCipher,
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     let query = sqlx::query!(
         r#"
-    INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    INSERT INTO subscriptions (id, email, name, subscribed_at, status, email_index, locale)
+    VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6)
             "#,
         subscriber_id,
-        new_subscriber.email.as_ref(),
-        new_subscriber.name.as_ref(),
-        Utc::now()
+        pii.seal_email(new_subscriber.email.as_ref()),
+        pii.seal(new_subscriber.name.as_ref()),
+        Utc::now(),
+        pii.email_index(new_subscriber.email.as_ref()),
+        new_subscriber.locale.as_ref().map(|locale| locale.as_ref())
     );
-    transaction.execute(query).await?;
+    transaction
+        .execute(query)
+        .observe("insert_subscriber")
+        .await?;
     Ok(subscriber_id)
 }
 
@@ -166,7 +369,11 @@ pub async fn store_token(
         subscription_token,
         subscriber_id
     );
-    transaction.execute(query).await.map_err(StoreTokenError)?;
+    transaction
+        .execute(query)
+        .observe("store_token")
+        .await
+        .map_err(StoreTokenError)?;
     Ok(())
 }
 
@@ -1,21 +1,62 @@
+use crate::configuration::SubscriberRedirectSettings;
+use crate::consent::{record_consent, ConsentAction, ConsentContext};
+use crate::database::ObserveQuery;
+use crate::events::{DomainEvent, EventBus};
+use crate::i18n::{Language, Message, PreferredLanguage};
+use crate::links;
 use crate::routes::error_chain_fmt;
-use actix_web::http::StatusCode;
+use crate::theme::{Page, Theme};
+use crate::utils::see_other;
+use crate::verified_subscriber::VerifiedSubscriber;
+use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use reqwest::Url;
+use sqlx::{Executor, PgPool, Postgres};
 use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
-pub struct Parameters {
-    subscription_token: String,
+/// The validated redirect URL.
+#[derive(Clone, Debug, Default)]
+pub struct SubscriberRedirects {
+    pub post_confirm: Option<Url>,
+}
+
+impl SubscriberRedirects {
+    /// Only `https` URLs on an allowed domain are accepted: a typo must not
+    /// send freshly confirmed subscribers to a stranger.
+    pub fn parse(settings: &SubscriberRedirectSettings) -> Result<Self, String> {
+        let post_confirm = settings
+            .post_confirm_url
+            .as_deref()
+            .map(|url| parse_redirect(url, &settings.allowed_domains))
+            .transpose()?;
+        Ok(Self { post_confirm })
+    }
+}
+
+fn parse_redirect(url: &str, allowed_domains: &[String]) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("{} is not a valid URL: {}", url, e))?;
+    if parsed.scheme() != "https" {
+        return Err(format!("{} is not an https URL", url));
+    }
+    let host = parsed.host_str().unwrap_or_default().to_lowercase();
+    let allowed = allowed_domains.iter().any(|domain| {
+        let domain = domain.to_lowercase();
+        host == domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    });
+    if !allowed {
+        return Err(format!("{} is not on an allowed domain", url));
+    }
+    Ok(parsed)
 }
 
 #[derive(thiserror::Error)]
 pub enum ConfirmationError {
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
-    #[error("There is no subscriber associated with the provided token.")]
-    UnknownToken,
 }
 
 impl std::fmt::Debug for ConfirmationError {
@@ -24,51 +65,155 @@ impl std::fmt::Debug for ConfirmationError {
     }
 }
 
-impl ResponseError for ConfirmationError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            Self::UnknownToken => StatusCode::UNAUTHORIZED,
-            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
+impl ResponseError for ConfirmationError {}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmParameters {
+    subscription_token: String,
+}
+
+/// Mail scanners follow the links in the emails they inspect: the link only
+/// shows a button, and the subscription is confirmed by the form it submits.
+#[tracing::instrument(
+    name = "Show the confirmation page",
+    skip_all,
+    fields(subscriber_id = %subscriber.id)
+)]
+pub async fn confirm_form(
+    subscriber: VerifiedSubscriber,
+    parameters: web::Query<ConfirmParameters>,
+    redirects: web::Data<SubscriberRedirects>,
+    theme: web::Data<Theme>,
+    PreferredLanguage(language): PreferredLanguage,
+) -> HttpResponse {
+    if subscriber.status == "confirmed" {
+        return confirmed(&redirects, &theme, language);
     }
+    let title = Message::ConfirmTitle.translate(language);
+    let content = format!(
+        r#"<h1>{}</h1>
+    <p>{}</p>
+    <form action="{}" method="post">
+        <button type="submit">{}</button>
+    </form>"#,
+        title,
+        Message::ConfirmPrompt
+            .translate(language)
+            .replace("{email}", &htmlescape::encode_minimal(&subscriber.email)),
+        htmlescape::encode_minimal(
+            &links::subscription_confirmation(&parameters.subscription_token).to_string()
+        ),
+        Message::ConfirmButton.translate(language)
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page {
+            language,
+            ..Page::new("confirm", title, &content)
+        }))
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
+/// Confirming again shows the same page: only the first confirmation is
+/// recorded and published.
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(subscriber, pool, event_bus, redirects, theme, consent, language),
+    fields(subscriber_id = %subscriber.id)
+)]
 pub async fn confirm(
-    parameters: web::Query<Parameters>,
+    subscriber: VerifiedSubscriber,
     pool: web::Data<PgPool>,
+    event_bus: web::Data<EventBus>,
+    redirects: web::Data<SubscriberRedirects>,
+    theme: web::Data<Theme>,
+    consent: ConsentContext,
+    PreferredLanguage(language): PreferredLanguage,
 ) -> Result<HttpResponse, ConfirmationError> {
-    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    let subscriber_id = subscriber.id;
+    let mut transaction = pool
+        .begin()
         .await
-        .context("Failed to retrieve the subscriber id associated with the provided token.")?
-        .ok_or(ConfirmationError::UnknownToken)?;
-    confirm_subscriber(&pool, subscriber_id)
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let newly_confirmed = confirm_subscriber(&mut *transaction, subscriber_id)
         .await
         .context("Failed to update the subscriber status to `confirmed`.")?;
-    Ok(HttpResponse::Ok().finish())
+    if newly_confirmed {
+        record_consent(
+            &mut *transaction,
+            subscriber_id,
+            ConsentAction::Confirmed,
+            &consent,
+            None,
+        )
+        .await
+        .context("Failed to record the confirmation of a subscriber.")?;
+        let event = DomainEvent::SubscriberConfirmed { subscriber_id };
+        event_bus.record(&mut transaction, &event).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the confirmation of a subscriber.")?;
+        event_bus.fan_out(event);
+    }
+    Ok(confirmed(&redirects, &theme, language))
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id,
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
+fn confirmed(redirects: &SubscriberRedirects, theme: &Theme, language: Language) -> HttpResponse {
+    if let Some(url) = &redirects.post_confirm {
+        return see_other(url.as_str());
+    }
+    let content = format!(
+        "<h1>{}</h1>\n    <p>{}</p>",
+        Message::ConfirmedHeading.translate(language),
+        Message::ConfirmedBody.translate(language)
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(theme.render(Page {
+            language,
+            ..Page::new(
+                "confirmed",
+                Message::ConfirmedTitle.translate(language),
+                &content,
+            )
+        }))
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
-    subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+/// Returns whether the subscriber was pending: concurrent confirmations
+/// only see `true` once.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, executor))]
+pub async fn confirm_subscriber<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
     )
-    .fetch_optional(pool)
+    .execute(executor)
+    .observe("confirm_subscriber")
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_redirect;
+
+    #[test]
+    fn redirects_must_be_https_on_an_allowed_domain() {
+        let allowed = vec!["Example.com".to_string()];
+        assert!(parse_redirect("https://example.com/thanks", &allowed).is_ok());
+        assert!(parse_redirect("https://News.example.com/thanks", &allowed).is_ok());
+        assert!(parse_redirect("http://example.com/thanks", &allowed).is_err());
+        assert!(parse_redirect("https://notexample.com/", &allowed).is_err());
+        assert!(parse_redirect("https://example.com.evil.io/", &allowed).is_err());
+        assert!(parse_redirect("/thanks", &allowed).is_err());
+    }
 }
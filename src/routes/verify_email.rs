@@ -0,0 +1,25 @@
+use crate::email_verification::EmailVerifier;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct VerifyEmailRequest {
+    email: String,
+}
+
+/// Let signup forms hosted elsewhere check an address before subscribing it.
+#[tracing::instrument(name = "Verify an email address for an API caller", skip_all)]
+pub async fn verify_email(
+    body: web::Json<VerifyEmailRequest>,
+    pool: web::Data<PgPool>,
+    verifier: web::Data<EmailVerifier>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let verification = verifier
+        .verify(&pool, body.into_inner().email)
+        .await
+        .context("Failed to check the suppression list")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(verification))
+}
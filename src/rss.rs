@@ -0,0 +1,571 @@
+//! Recurring issues made of the new entries of RSS or Atom feeds.
+//!
+//! Each configured feed is checked on its own schedule. Its new entries are
+//! rendered into an issue template, in place of `{{rss}}`, and the issue is
+//! either published straight away or left as a draft to approve. The
+//! entries are remembered in `rss_feed_entries`, in the same transaction as
+//! the issue, so that none is ever sent twice.
+use crate::configuration::{RssFeedSettings, RssSettings, SendQuotaSettings, Settings};
+use crate::database::ObserveQuery;
+use crate::issue_delivery_worker::{set_issue_status, DeliveryLane, IssueStatus};
+use crate::issue_drafts::{get_template_by_name, insert_draft, IssueDraft};
+use crate::issue_enqueue::{enqueue_issue, take_recipient_snapshot};
+use crate::send_quota::monthly_usage;
+use crate::snippets::{expand_issue, record_issue_snippets, SnippetError};
+use crate::startup::{get_connection_pool, StartupError};
+use crate::telegram::enqueue_telegram_post;
+use htmlescape::{encode_attribute, encode_minimal};
+use reqwest::Client;
+use sqlx::{Connection, PgPool, Postgres, Transaction};
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+const ENTRIES_TAG: &str = "{{rss}}";
+
+/// An item of an RSS feed, or an entry of an Atom one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    /// The guid of the entry, or its link.
+    pub id: String,
+    pub title: String,
+    pub link: String,
+}
+
+/// The entries of the feed, in the order of the document. The entries with
+/// neither a link nor an id are left out.
+pub fn parse_feed(xml: &str) -> Vec<FeedEntry> {
+    let mut entries = Vec::new();
+    for block in elements(xml, "item")
+        .into_iter()
+        .chain(elements(xml, "entry"))
+    {
+        let link = elements(block, "link")
+            .into_iter()
+            .map(text)
+            .find(|link| !link.is_empty())
+            .or_else(|| atom_link(block))
+            .unwrap_or_default();
+        let id = elements(block, "guid")
+            .into_iter()
+            .chain(elements(block, "id"))
+            .map(text)
+            .find(|id| !id.is_empty())
+            .unwrap_or_else(|| link.clone());
+        if id.is_empty() {
+            continue;
+        }
+        let title = elements(block, "title")
+            .into_iter()
+            .map(text)
+            .find(|title| !title.is_empty())
+            .unwrap_or_else(|| link.clone());
+        entries.push(FeedEntry { id, title, link });
+    }
+    entries
+}
+
+/// The content of every `<name>` element, without nesting.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut contents = Vec::new();
+    let opening = format!("<{}", name);
+    let closing = format!("</{}>", name);
+    let mut rest = xml;
+    while let Some(start) = rest.find(&opening) {
+        let after = &rest[start + opening.len()..];
+        // `<link` is no `<linkedin`.
+        if !after.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            rest = after;
+            continue;
+        }
+        let Some(tag_end) = after.find('>') else {
+            break;
+        };
+        if after[..tag_end].ends_with('/') {
+            rest = &after[tag_end + 1..];
+            continue;
+        }
+        let content = &after[tag_end + 1..];
+        let Some(end) = content.find(&closing) else {
+            break;
+        };
+        contents.push(&content[..end]);
+        rest = &content[end + closing.len()..];
+    }
+    contents
+}
+
+/// The `href` of the alternate `<link>` of an Atom entry.
+fn atom_link(block: &str) -> Option<String> {
+    let mut rest = block;
+    while let Some(start) = rest.find("<link") {
+        let after = &rest[start + "<link".len()..];
+        let end = after.find('>')?;
+        let attributes = &after[..end];
+        rest = &after[end..];
+        let rel = attribute(attributes, "rel");
+        if rel.is_some() && rel.as_deref() != Some("alternate") {
+            continue;
+        }
+        if let Some(href) = attribute(attributes, "href") {
+            return Some(href);
+        }
+    }
+    None
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let prefix = format!(" {}=", name);
+    let index = attributes.find(&prefix)?;
+    let rest = &attributes[index + prefix.len()..];
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = rest[1..].find(quote)?;
+    Some(decode(&rest[1..=end]))
+}
+
+/// The text of an element, from its CDATA section or with its entities
+/// decoded.
+fn text(content: &str) -> String {
+    let content = content.trim();
+    match content
+        .strip_prefix("<![CDATA[")
+        .and_then(|c| c.strip_suffix("]]>"))
+    {
+        Some(cdata) => cdata.trim().to_owned(),
+        None => decode(content),
+    }
+}
+
+fn decode(text: &str) -> String {
+    htmlescape::decode_html(text).unwrap_or_else(|_| text.to_owned())
+}
+
+/// The entries as an HTML list and as plain text.
+pub fn render_entries(entries: &[FeedEntry]) -> (String, String) {
+    let mut html_content = String::from("<ul>\n");
+    let mut text_content = String::new();
+    for entry in entries {
+        html_content.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            encode_attribute(&entry.link),
+            encode_minimal(&entry.title)
+        ));
+        text_content.push_str(&format!("- {}\n  {}\n", entry.title, entry.link));
+    }
+    html_content.push_str("</ul>");
+    (html_content, text_content.trim_end().to_owned())
+}
+
+/// `content` with the entries in place of its tag, or at the end.
+fn fill(content: &str, entries: &str) -> String {
+    if content.contains(ENTRIES_TAG) {
+        content.replace(ENTRIES_TAG, entries)
+    } else if content.is_empty() {
+        entries.to_owned()
+    } else {
+        format!("{}\n{}", content, entries)
+    }
+}
+
+/// What publishing an issue from a feed takes.
+pub struct RssChecker {
+    http_client: Client,
+    send_quota: Option<SendQuotaSettings>,
+    mirror_to_telegram: bool,
+    enqueue_batch_size: i64,
+}
+
+impl RssChecker {
+    pub fn new(configuration: &Settings, settings: &RssSettings) -> Result<Self, StartupError> {
+        let mut names = HashSet::new();
+        for feed in &settings.feeds {
+            if feed.name.trim().is_empty() || !names.insert(feed.name.as_str()) {
+                return Err(StartupError::InvalidConfiguration(format!(
+                    "rss: feed names must be unique and not empty, {:?} is not",
+                    feed.name
+                )));
+            }
+            if feed.interval_minutes == 0 || feed.max_entries == 0 {
+                return Err(StartupError::InvalidConfiguration(format!(
+                    "rss: the interval and the entries of feed {} must be positive",
+                    feed.name
+                )));
+            }
+        }
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .map_err(|e| StartupError::HttpClient("RSS client", e))?;
+        Ok(Self {
+            http_client,
+            send_quota: configuration.send_quota.clone(),
+            mirror_to_telegram: configuration.telegram.is_some(),
+            enqueue_batch_size: configuration.delivery_enqueue.batch_size,
+        })
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<FeedEntry>, reqwest::Error> {
+        let body = self
+            .http_client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok(parse_feed(&body))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// The feed was checked less than its interval ago.
+    NotDue,
+    /// The error is recorded against the feed.
+    Failed,
+    NoNewEntries,
+    Drafted(Uuid),
+    Published(Uuid),
+}
+
+/// Turn the new entries of `feed` into an issue, if the feed is due.
+#[tracing::instrument(skip(pool, checker, feed), fields(feed = %feed.name))]
+pub async fn check_feed(
+    pool: &PgPool,
+    checker: &RssChecker,
+    feed: &RssFeedSettings,
+) -> Result<CheckOutcome, anyhow::Error> {
+    sqlx::query!(
+        "INSERT INTO rss_feeds (name) VALUES ($1) ON CONFLICT DO NOTHING",
+        feed.name
+    )
+    .execute(pool)
+    .observe("insert_rss_feed")
+    .await?;
+    let mut transaction = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+        SELECT name FROM rss_feeds
+        WHERE name = $1
+            AND (last_checked_at IS NULL OR last_checked_at <= now() - make_interval(mins => $2))
+        FOR UPDATE SKIP LOCKED
+        "#,
+        feed.name,
+        feed.interval_minutes as i32
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("lock_due_rss_feed")
+    .await?;
+    if due.is_none() {
+        return Ok(CheckOutcome::NotDue);
+    }
+    let template = match &feed.template {
+        Some(name) => match get_template_by_name(&mut *transaction, name).await? {
+            Some(template) => template,
+            None => {
+                let error = format!("There is no template named {}.", name);
+                return record_failure(transaction, feed, error).await;
+            }
+        },
+        None => IssueDraft {
+            title: feed.name.clone(),
+            ..Default::default()
+        },
+    };
+    let entries = match checker.fetch(&feed.url).await {
+        Ok(entries) => entries,
+        Err(e) => return record_failure(transaction, feed, e.to_string()).await,
+    };
+    let new_ids = insert_new_entries(&mut transaction, &feed.name, &entries).await?;
+    let new_entries: Vec<_> = entries
+        .into_iter()
+        .filter(|entry| new_ids.contains(&entry.id))
+        .take(feed.max_entries)
+        .collect();
+    if new_entries.is_empty() {
+        record_check(&mut transaction, &feed.name, None).await?;
+        transaction.commit().await?;
+        return Ok(CheckOutcome::NoNewEntries);
+    }
+    let (html_entries, text_entries) = render_entries(&new_entries);
+    let draft = IssueDraft {
+        html_content: fill(&template.html_content, &html_entries),
+        text_content: fill(&template.text_content, &text_entries),
+        ..template
+    };
+    let published = if feed.auto_send {
+        publish(&mut transaction, checker, &draft).await?
+    } else {
+        None
+    };
+    let outcome = match published {
+        Some(issue_id) => CheckOutcome::Published(issue_id),
+        None => CheckOutcome::Drafted(insert_draft(&mut *transaction, &draft, None, None).await?),
+    };
+    let (issue_id, draft_id) = match outcome {
+        CheckOutcome::Published(issue_id) => (Some(issue_id), None),
+        CheckOutcome::Drafted(draft_id) => (None, Some(draft_id)),
+        _ => (None, None),
+    };
+    let ids: Vec<_> = new_entries.iter().map(|entry| entry.id.clone()).collect();
+    sqlx::query!(
+        r#"
+        UPDATE rss_feed_entries SET newsletter_issue_id = $3, draft_id = $4
+        WHERE feed = $1 AND entry_id = ANY($2)
+        "#,
+        feed.name,
+        &ids,
+        issue_id,
+        draft_id
+    )
+    .execute(&mut *transaction)
+    .observe("link_rss_feed_entries")
+    .await?;
+    record_check(&mut transaction, &feed.name, None).await?;
+    transaction.commit().await?;
+    if let Some(issue_id) = issue_id {
+        enqueue_issue(pool, issue_id, checker.enqueue_batch_size).await?;
+    }
+    Ok(outcome)
+}
+
+/// Remember the entries of the feed, returning the ids of the new ones.
+async fn insert_new_entries(
+    transaction: &mut Transaction<'_, Postgres>,
+    feed: &str,
+    entries: &[FeedEntry],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let ids: Vec<_> = entries.iter().map(|entry| entry.id.clone()).collect();
+    let titles: Vec<_> = entries.iter().map(|entry| entry.title.clone()).collect();
+    let links: Vec<_> = entries.iter().map(|entry| entry.link.clone()).collect();
+    let new_ids = sqlx::query_scalar!(
+        r#"
+        INSERT INTO rss_feed_entries (feed, entry_id, title, link, seen_at)
+        SELECT $1, e.entry_id, e.title, e.link, now()
+        FROM UNNEST($2::text[], $3::text[], $4::text[]) AS e(entry_id, title, link)
+        ON CONFLICT DO NOTHING
+        RETURNING entry_id
+        "#,
+        feed,
+        &ids,
+        &titles,
+        &links
+    )
+    .fetch_all(&mut **transaction)
+    .observe("insert_rss_feed_entries")
+    .await?;
+    Ok(new_ids.into_iter().collect())
+}
+
+async fn record_check(
+    transaction: &mut Transaction<'_, Postgres>,
+    feed: &str,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE rss_feeds SET last_checked_at = now(), last_error = $2 WHERE name = $1",
+        feed,
+        error
+    )
+    .execute(&mut **transaction)
+    .observe("record_rss_feed_check")
+    .await?;
+    Ok(())
+}
+
+/// The feed is checked again after its interval.
+async fn record_failure(
+    mut transaction: Transaction<'_, Postgres>,
+    feed: &RssFeedSettings,
+    error: String,
+) -> Result<CheckOutcome, anyhow::Error> {
+    tracing::warn!(feed = %feed.name, error.message = %error, "Failed to check an RSS feed");
+    record_check(&mut transaction, &feed.name, Some(&error)).await?;
+    transaction.commit().await?;
+    Ok(CheckOutcome::Failed)
+}
+
+/// Publish the issue, as the publish form does. `None` if it cannot be
+/// published unattended: it then waits as a draft.
+async fn publish(
+    transaction: &mut Transaction<'_, Postgres>,
+    checker: &RssChecker,
+    draft: &IssueDraft,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    // Dropping the savepoint discards the issue.
+    let mut savepoint = Connection::begin(&mut **transaction).await?;
+    let usage = match &checker.send_quota {
+        Some(quota) => Some(monthly_usage(&mut *savepoint, quota).await?),
+        None => None,
+    };
+    let expanded =
+        match expand_issue(&mut savepoint, &draft.html_content, &draft.text_content).await {
+            Ok(expanded) => expanded,
+            Err(SnippetError::Unknown(name)) => {
+                tracing::warn!(snippet = %name, "An RSS issue refers to an unknown snippet");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+    let lane = if draft.priority {
+        DeliveryLane::Priority
+    } else {
+        DeliveryLane::Bulk
+    };
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            status,
+            paid_only,
+            push,
+            delivery_lane,
+            enqueue_completed,
+            source_text_content,
+            source_html_content
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7, $8, false, $9, $10)
+        "#,
+        issue_id,
+        draft.title,
+        expanded.text_content,
+        expanded.html_content,
+        IssueStatus::InProgress.as_str(),
+        draft.paid_only,
+        draft.push,
+        lane.as_str(),
+        draft.text_content,
+        draft.html_content
+    )
+    .execute(&mut *savepoint)
+    .observe("insert_rss_newsletter_issue")
+    .await?;
+    record_issue_snippets(&mut savepoint, issue_id, &expanded.snippets).await?;
+    if checker.mirror_to_telegram {
+        enqueue_telegram_post(&mut savepoint, issue_id).await?;
+    }
+    let recipients = take_recipient_snapshot(&mut savepoint, issue_id, draft.paid_only)
+        .await?
+        .recipient_count;
+    if let Some(usage) = usage {
+        if recipients as i64 > usage.remaining {
+            tracing::warn!("An RSS issue would exceed the monthly send quota");
+            return Ok(None);
+        }
+    }
+    if recipients == 0 {
+        set_issue_status(&mut *savepoint, issue_id, IssueStatus::Completed).await?;
+    }
+    savepoint.commit().await?;
+    Ok(Some(issue_id))
+}
+
+async fn check_loop(
+    pool: PgPool,
+    checker: RssChecker,
+    feeds: Vec<RssFeedSettings>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        for feed in &feeds {
+            // The failures are logged and the feed is checked again.
+            let _ = check_feed(&pool, &checker, feed).await;
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Check the feeds on their schedule until the process is stopped.
+///
+/// Never resolves if no feed is configured.
+pub async fn run_rss_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let Some(settings) = configuration.rss.clone() else {
+        return std::future::pending().await;
+    };
+    let checker = RssChecker::new(&configuration, &settings)?;
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    check_loop(connection_pool, checker, settings.feeds).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, parse_feed, render_entries, FeedEntry};
+
+    #[test]
+    fn rss_items_are_parsed() {
+        let xml = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+    <title>Blog</title>
+    <link>https://example.com</link>
+    <item>
+        <title><![CDATA[Rust & <friends>]]></title>
+        <link>https://example.com/posts/2?a=1&amp;b=2</link>
+        <guid isPermaLink="false">post-2</guid>
+    </item>
+    <item>
+        <link>https://example.com/posts/1</link>
+    </item>
+</channel></rss>"#;
+        assert_eq!(
+            parse_feed(xml),
+            vec![
+                FeedEntry {
+                    id: "post-2".into(),
+                    title: "Rust & <friends>".into(),
+                    link: "https://example.com/posts/2?a=1&b=2".into(),
+                },
+                FeedEntry {
+                    id: "https://example.com/posts/1".into(),
+                    title: "https://example.com/posts/1".into(),
+                    link: "https://example.com/posts/1".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn atom_entries_are_parsed() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>Blog</title>
+    <entry>
+        <title type="html">Ownership &amp; borrowing</title>
+        <link rel="edit" href="https://example.com/edit/3"/>
+        <link href="https://example.com/posts/3"/>
+        <id>tag:example.com,2026:3</id>
+    </entry>
+</feed>"#;
+        assert_eq!(
+            parse_feed(xml),
+            vec![FeedEntry {
+                id: "tag:example.com,2026:3".into(),
+                title: "Ownership & borrowing".into(),
+                link: "https://example.com/posts/3".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn entries_replace_the_tag_or_go_at_the_end() {
+        let (html, text) = render_entries(&[FeedEntry {
+            id: "1".into(),
+            title: "Rust <3".into(),
+            link: "https://example.com/?a=1&b=2".into(),
+        }]);
+        assert_eq!(
+            html,
+            "<ul>\n<li><a href=\"https&#x3A;&#x2F;&#x2F;example&#x2E;com&#x2F;&#x3F;a&#x3D;1&amp;b&#x3D;2\">\
+             Rust &lt;3</a></li>\n</ul>"
+        );
+        assert_eq!(text, "- Rust <3\n  https://example.com/?a=1&b=2");
+        assert_eq!(
+            fill("<p>Hi</p>{{rss}}<p>Bye</p>", "X"),
+            "<p>Hi</p>X<p>Bye</p>"
+        );
+        assert_eq!(fill("Hi", "X"), "Hi\nX");
+        assert_eq!(fill("", "X"), "X");
+    }
+}
@@ -0,0 +1,86 @@
+//! The Tokio runtime, sized from the configuration, and the export of its
+//! scheduler stats to `/metrics`.
+use crate::configuration::RuntimeSettings;
+use crate::startup::StartupError;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+pub fn build_runtime(settings: &RuntimeSettings) -> Result<Runtime, StartupError> {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    // Tokio panics on zero rather than erroring out.
+    if let Some(worker_threads) = settings.worker_threads {
+        if worker_threads == 0 {
+            return Err(StartupError::InvalidConfiguration(
+                "runtime.worker_threads must be at least 1".into(),
+            ));
+        }
+        builder.worker_threads(worker_threads);
+    }
+    if let Some(max_blocking_threads) = settings.max_blocking_threads {
+        if max_blocking_threads == 0 {
+            return Err(StartupError::InvalidConfiguration(
+                "runtime.max_blocking_threads must be at least 1".into(),
+            ));
+        }
+        builder.max_blocking_threads(max_blocking_threads);
+    }
+    builder.build().map_err(StartupError::Runtime)
+}
+
+/// Export the scheduler stats of the current runtime every `interval`.
+///
+/// Tokio only collects them in builds with `--cfg tokio_unstable`: other
+/// builds export nothing.
+pub async fn run_runtime_metrics(interval: Duration) {
+    #[cfg(tokio_unstable)]
+    {
+        let metrics = tokio::runtime::Handle::current().metrics();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            export(&metrics);
+        }
+    }
+    #[cfg(not(tokio_unstable))]
+    {
+        let _ = interval;
+        tracing::info!("Tokio runtime metrics are only available in builds with tokio_unstable");
+    }
+}
+
+#[cfg(tokio_unstable)]
+fn export(metrics: &tokio::runtime::RuntimeMetrics) {
+    use crate::metrics::{TOKIO_RUNTIME, TOKIO_WORKER};
+
+    let runtime = [
+        ("workers", metrics.num_workers()),
+        ("active_tasks", metrics.active_tasks_count()),
+        ("injection_queue_depth", metrics.injection_queue_depth()),
+        ("blocking_threads", metrics.num_blocking_threads()),
+        ("idle_blocking_threads", metrics.num_idle_blocking_threads()),
+        ("blocking_queue_depth", metrics.blocking_queue_depth()),
+    ];
+    for (stat, value) in runtime {
+        TOKIO_RUNTIME.with_label_values(&[stat]).set(value as i64);
+    }
+    for worker in 0..metrics.num_workers() {
+        let label = worker.to_string();
+        let stats = [
+            (
+                "busy_seconds",
+                metrics.worker_total_busy_duration(worker).as_secs_f64(),
+            ),
+            ("polls", metrics.worker_poll_count(worker) as f64),
+            ("steals", metrics.worker_steal_count(worker) as f64),
+            ("parks", metrics.worker_park_count(worker) as f64),
+            (
+                "local_queue_depth",
+                metrics.worker_local_queue_depth(worker) as f64,
+            ),
+        ];
+        for (stat, value) in stats {
+            TOKIO_WORKER.with_label_values(&[&label, stat]).set(value);
+        }
+    }
+}
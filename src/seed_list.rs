@@ -0,0 +1,178 @@
+//! Deliverability seed tests: every issue first goes to a list of seed
+//! inboxes we control, and someone (or a placement-monitoring service)
+//! reports whether each copy landed in the inbox or in spam.
+use crate::cost_ledger::record_send;
+use crate::database::ObserveQuery;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, MessageStream};
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    Inbox,
+    Spam,
+    Missing,
+}
+
+impl Placement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Placement::Inbox => "inbox",
+            Placement::Spam => "spam",
+            Placement::Missing => "missing",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "inbox" => Some(Placement::Inbox),
+            "spam" => Some(Placement::Spam),
+            "missing" => Some(Placement::Missing),
+            _ => None,
+        }
+    }
+}
+
+/// Who reported a placement.
+#[derive(Copy, Clone, Debug)]
+pub enum PlacementSource {
+    Manual,
+    Webhook,
+}
+
+impl PlacementSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PlacementSource::Manual => "manual",
+            PlacementSource::Webhook => "webhook",
+        }
+    }
+}
+
+/// The subject of the seed copy: the issue id lets the seed inbox owner, or
+/// their tooling, tell which issue a copy belongs to.
+pub fn seed_subject(title: &str, issue_id: Uuid) -> String {
+    format!("{} [seed {}]", title, issue_id)
+}
+
+/// Send the seed copies of an issue. A seed that cannot be reached is
+/// recorded as not sent rather than holding up the issue.
+#[tracing::instrument(name = "Send seed copies", skip_all, fields(newsletter_issue_id = %issue_id))]
+pub async fn send_seed_copies(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    inboxes: &[String],
+    issue_id: Uuid,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), anyhow::Error> {
+    let subject = seed_subject(title, issue_id);
+    for inbox in inboxes {
+        let sent = match SubscriberEmail::parse(inbox.clone()) {
+            Ok(email) => match email_client
+                .send_email(
+                    &email,
+                    &subject,
+                    html_content,
+                    text_content,
+                    MessageStream::Broadcast,
+                )
+                .await
+            {
+                Ok(sent) => {
+                    record_send(pool, &sent).await?;
+                    true
+                }
+                Err(e) => {
+                    tracing::warn!(error.message = %e, seed_email = %inbox, "Failed to send a seed copy");
+                    false
+                }
+            },
+            Err(e) => {
+                tracing::warn!(error.message = %e, seed_email = %inbox, "Invalid seed inbox");
+                false
+            }
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO seed_placements (newsletter_issue_id, seed_email, sent)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (newsletter_issue_id, seed_email) DO UPDATE SET sent = EXCLUDED.sent
+            "#,
+            issue_id,
+            inbox,
+            sent
+        )
+        .execute(pool)
+        .observe("insert_seed_placement")
+        .await?;
+    }
+    Ok(())
+}
+
+/// Record where the seed copy sent to `seed_email` landed. Returns `false`
+/// when no seed copy of the issue went to that address.
+#[tracing::instrument(name = "Record a seed placement", skip(pool))]
+pub async fn record_placement(
+    pool: &PgPool,
+    issue_id: Uuid,
+    seed_email: &str,
+    placement: Placement,
+    source: PlacementSource,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE seed_placements
+        SET placement = $3, reported_by = $4, reported_at = $5
+        WHERE newsletter_issue_id = $1 AND seed_email = $2
+        "#,
+        issue_id,
+        seed_email,
+        placement.as_str(),
+        source.as_str(),
+        Utc::now()
+    )
+    .execute(pool)
+    .observe("update_seed_placement")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(serde::Serialize)]
+pub struct SeedPlacement {
+    pub seed_email: String,
+    pub sent: bool,
+    pub placement: Option<Placement>,
+    pub reported_by: Option<String>,
+}
+
+pub async fn get_seed_placements(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<SeedPlacement>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT seed_email, sent, placement, reported_by
+        FROM seed_placements
+        WHERE newsletter_issue_id = $1
+        ORDER BY seed_email
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .observe("get_seed_placements")
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| SeedPlacement {
+            seed_email: row.seed_email,
+            sent: row.sent,
+            placement: row.placement.as_deref().and_then(Placement::parse),
+            reported_by: row.reported_by,
+        })
+        .collect())
+}
@@ -0,0 +1,44 @@
+use crate::configuration::SendQuotaSettings;
+use crate::database::ObserveQuery;
+use chrono::{Datelike, NaiveTime, Utc};
+use sqlx::{Executor, Postgres};
+
+#[derive(Debug, serde::Serialize)]
+pub struct QuotaUsage {
+    pub limit: i64,
+    /// Emails attempted this month plus those still waiting to be delivered.
+    pub used: i64,
+    pub remaining: i64,
+}
+
+#[tracing::instrument(name = "Compute this month's send quota usage", skip_all)]
+pub async fn monthly_usage<'a, E>(
+    executor: E,
+    settings: &SendQuotaSettings,
+) -> Result<QuotaUsage, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let start_of_month = Utc::now()
+        .date_naive()
+        .with_day(1)
+        .unwrap()
+        .and_time(NaiveTime::MIN)
+        .and_utc();
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM email_deliveries WHERE attempted_at >= $1) +
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "used!"
+        "#,
+        start_of_month
+    )
+    .fetch_one(executor)
+    .observe_one("monthly_usage")
+    .await?;
+    Ok(QuotaUsage {
+        limit: settings.monthly_limit,
+        used: r.used,
+        remaining: (settings.monthly_limit - r.used).max(0),
+    })
+}
@@ -1,3 +1,4 @@
+use crate::oidc::OidcFlow;
 use actix_session::SessionExt;
 use actix_session::{Session, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
@@ -9,6 +10,9 @@ pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const ADMIN_SESSION_ID_KEY: &'static str = "admin_session_id";
+    const SUBSCRIBER_ID_KEY: &'static str = "subscriber_id";
+    const OIDC_FLOW_KEY: &'static str = "oidc_flow";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -22,6 +26,35 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// The id of the session in `admin_sessions`.
+    pub fn insert_admin_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::ADMIN_SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_admin_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::ADMIN_SESSION_ID_KEY)
+    }
+
+    /// A subscriber logged into the archive through a magic link.
+    pub fn insert_subscriber_id(&self, subscriber_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SUBSCRIBER_ID_KEY, subscriber_id)
+    }
+
+    pub fn get_subscriber_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SUBSCRIBER_ID_KEY)
+    }
+
+    pub fn insert_oidc_flow(&self, flow: &OidcFlow) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::OIDC_FLOW_KEY, flow)
+    }
+
+    /// The pending single sign-on flow, if any. It can only be retrieved once.
+    pub fn take_oidc_flow(&self) -> Result<Option<OidcFlow>, SessionGetError> {
+        let flow = self.0.get(Self::OIDC_FLOW_KEY)?;
+        self.0.remove(Self::OIDC_FLOW_KEY);
+        Ok(flow)
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
@@ -0,0 +1,514 @@
+//! Spotting the signup spikes of bot attacks, and making subscribing
+//! stricter while one lasts.
+//!
+//! Every signup is counted per hour and per form source, the hosted signup
+//! pages included. A detector compares the current hour of each source, and
+//! of all of them together, with its average over the baseline hours. A
+//! spike notifies the admins and switches `POST /subscriptions` into strict
+//! mode for a while: the captcha becomes required, when one is configured,
+//! and fewer signups are allowed from each IP address.
+//!
+//! The signups with a bot signal, and the ones made in strict mode without
+//! a captcha, go to [quarantine](crate::quarantine).
+use crate::configuration::{CaptchaSettings, SignupProtectionSettings};
+use crate::consent::MAX_FORM_SOURCE_LENGTH;
+use crate::database::ObserveQuery;
+use crate::events::{DomainEvent, EventBus};
+use crate::quarantine::expire_quarantine;
+use crate::startup::StartupError;
+use anyhow::Context;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use reqwest::Client;
+use secrecy::ExposeSecret;
+use sqlx::{Executor, PgPool, Postgres};
+use std::collections::HashMap;
+
+/// The source of the spikes of all sources together.
+pub const ALL_SOURCES: &str = "*";
+
+/// Count a signup in the current hour of its form source.
+#[tracing::instrument(name = "Count a signup", skip(executor))]
+pub async fn record_signup<'a, E>(
+    executor: E,
+    form_source: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let source: String = form_source
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_FORM_SOURCE_LENGTH)
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO signup_hourly_counts (hour, source, signups)
+        VALUES ($1, $2, 1)
+        ON CONFLICT (hour, source) DO UPDATE
+        SET signups = signup_hourly_counts.signups + 1
+        "#,
+        now.duration_trunc(Duration::hours(1)).unwrap(),
+        source
+    )
+    .execute(executor)
+    .observe("record_signup")
+    .await?;
+    Ok(())
+}
+
+/// Why a signup was turned away in strict mode.
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    CaptchaFailed,
+    TooManySignups,
+}
+
+/// What becomes of a signup.
+#[derive(Debug, PartialEq)]
+pub enum Screening {
+    Allowed,
+    /// Stored, but left for an admin to review before it is sent a
+    /// confirmation email, for the given reason.
+    Quarantined(&'static str),
+    Rejected(Rejection),
+}
+
+/// What the subscribe form tells us about who filled it in.
+pub struct SignupAttempt<'a> {
+    pub name: &'a str,
+    /// The field hidden from people, which only bots fill in.
+    pub honeypot: Option<&'a str>,
+    pub captcha_response: Option<&'a str>,
+    pub client_ip: Option<&'a str>,
+}
+
+/// The tell-tale signs of a signup made by a bot, if any.
+pub fn bot_signal(attempt: &SignupAttempt<'_>) -> Option<&'static str> {
+    if attempt
+        .honeypot
+        .is_some_and(|value| !value.trim().is_empty())
+    {
+        return Some("honeypot");
+    }
+    let name = attempt.name.to_lowercase();
+    if ["http://", "https://", "www."]
+        .iter()
+        .any(|link| name.contains(link))
+    {
+        return Some("link_in_name");
+    }
+    None
+}
+
+pub struct SignupProtection {
+    settings: SignupProtectionSettings,
+    captcha: Option<(CaptchaSettings, Client)>,
+}
+
+impl SignupProtection {
+    pub fn new(settings: &SignupProtectionSettings) -> Result<Self, StartupError> {
+        if settings.baseline_hours == 0 || settings.spike_factor <= 1.0 {
+            return Err(StartupError::InvalidConfiguration(
+                "signup_protection: the baseline must be at least an hour long and the spike factor above 1".into(),
+            ));
+        }
+        let captcha = match &settings.captcha {
+            Some(captcha) => {
+                let http_client = Client::builder()
+                    .timeout(std::time::Duration::from_millis(
+                        captcha.timeout_milliseconds,
+                    ))
+                    .build()
+                    .map_err(|e| StartupError::HttpClient("captcha client", e))?;
+                Some((captcha.clone(), http_client))
+            }
+            None => None,
+        };
+        Ok(Self {
+            settings: settings.clone(),
+            captcha,
+        })
+    }
+
+    /// Quarantine the signups with a bot signal. In strict mode, also turn
+    /// away the signups without a valid captcha or from an IP address over
+    /// its rate limit, and quarantine the others when there is no captcha to
+    /// tell people from bots.
+    #[tracing::instrument(name = "Screen a signup", skip_all)]
+    pub async fn screen(
+        &self,
+        pool: &PgPool,
+        attempt: &SignupAttempt<'_>,
+        now: DateTime<Utc>,
+    ) -> Result<Screening, anyhow::Error> {
+        if let Some(signal) = bot_signal(attempt) {
+            return Ok(Screening::Quarantined(signal));
+        }
+        if !is_strict(pool, now)
+            .await
+            .context("Failed to check for strict mode")?
+        {
+            return Ok(Screening::Allowed);
+        }
+        if self.captcha.is_some()
+            && !self
+                .verify_captcha(attempt.captcha_response, attempt.client_ip)
+                .await?
+        {
+            return Ok(Screening::Rejected(Rejection::CaptchaFailed));
+        }
+        if let Some(client_ip) = attempt.client_ip {
+            let signups = count_signups_from(pool, client_ip, now - Duration::hours(1))
+                .await
+                .context("Failed to count the recent signups of an IP address")?;
+            if signups >= self.settings.strict_signups_per_ip_per_hour as i64 {
+                return Ok(Screening::Rejected(Rejection::TooManySignups));
+            }
+        }
+        if self.captcha.is_some() {
+            Ok(Screening::Allowed)
+        } else {
+            Ok(Screening::Quarantined("signup_spike"))
+        }
+    }
+
+    async fn verify_captcha(
+        &self,
+        captcha_response: Option<&str>,
+        client_ip: Option<&str>,
+    ) -> Result<bool, anyhow::Error> {
+        let (Some((captcha, http_client)), Some(captcha_response)) =
+            (&self.captcha, captcha_response)
+        else {
+            return Ok(false);
+        };
+        let mut form = vec![
+            ("secret", captcha.secret_key.expose_secret().as_str()),
+            ("response", captcha_response),
+        ];
+        if let Some(client_ip) = client_ip {
+            form.push(("remoteip", client_ip));
+        }
+        let verification: CaptchaVerification = http_client
+            .post(&captcha.verify_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("Failed to reach the captcha provider")?
+            .json()
+            .await
+            .context("Failed to read the verdict of the captcha provider")?;
+        Ok(verification.success)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CaptchaVerification {
+    success: bool,
+}
+
+/// Whether a spike put subscribing in strict mode until after `now`.
+pub async fn is_strict(pool: &PgPool, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM signup_spikes WHERE strict_until > $1) AS "strict!""#,
+        now
+    )
+    .fetch_one(pool)
+    .observe_one("is_signup_strict_mode")
+    .await?;
+    Ok(r.strict)
+}
+
+async fn count_signups_from(
+    pool: &PgPool,
+    client_ip: &str,
+    since: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!" FROM consent_records
+        WHERE action = 'subscribed' AND client_ip = $1 AND recorded_at > $2
+        "#,
+        client_ip,
+        since
+    )
+    .fetch_one(pool)
+    .observe_one("count_signups_from_ip")
+    .await?;
+    Ok(r.count)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignupSpike {
+    pub source: String,
+    pub signups: i64,
+    /// The average signups per hour of the baseline.
+    pub baseline: f64,
+}
+
+/// The sources whose signups this hour are at least `spike_factor` times
+/// their average over the baseline, and `min_signups`. All the sources
+/// together are checked as well, for attacks spread across them.
+pub fn find_spikes(
+    current: &HashMap<String, i64>,
+    baseline_totals: &HashMap<String, i64>,
+    settings: &SignupProtectionSettings,
+) -> Vec<SignupSpike> {
+    let mut candidates: Vec<(String, i64, i64)> = current
+        .iter()
+        .map(|(source, signups)| {
+            let total = baseline_totals.get(source).copied().unwrap_or_default();
+            (source.clone(), *signups, total)
+        })
+        .collect();
+    candidates.sort();
+    candidates.push((
+        ALL_SOURCES.into(),
+        current.values().sum(),
+        baseline_totals.values().sum(),
+    ));
+    candidates
+        .into_iter()
+        .filter_map(|(source, signups, total)| {
+            let baseline = total as f64 / settings.baseline_hours as f64;
+            let threshold = (baseline * settings.spike_factor).max(settings.min_signups as f64);
+            (signups > 0 && signups as f64 >= threshold).then_some(SignupSpike {
+                source,
+                signups,
+                baseline,
+            })
+        })
+        .collect()
+}
+
+/// Look for spikes in the current hour. Returns the ones that were not
+/// already detected, which the admins are notified about; every spike,
+/// new or not, extends strict mode.
+#[tracing::instrument(name = "Detect signup spikes", skip_all)]
+pub async fn try_detect_signup_spikes(
+    pool: &PgPool,
+    event_bus: &EventBus,
+    settings: &SignupProtectionSettings,
+    now: DateTime<Utc>,
+) -> Result<Vec<SignupSpike>, anyhow::Error> {
+    let hour = now.duration_trunc(Duration::hours(1)).unwrap();
+    let baseline_start = hour - Duration::hours(settings.baseline_hours as i64);
+    let current: HashMap<String, i64> = sqlx::query!(
+        "SELECT source, signups FROM signup_hourly_counts WHERE hour = $1",
+        hour
+    )
+    .fetch_all(pool)
+    .observe("get_current_signup_counts")
+    .await?
+    .into_iter()
+    .map(|r| (r.source, r.signups as i64))
+    .collect();
+    let baseline_totals: HashMap<String, i64> = sqlx::query!(
+        r#"
+        SELECT source, SUM(signups) AS "signups!"
+        FROM signup_hourly_counts
+        WHERE hour >= $1 AND hour < $2
+        GROUP BY source
+        "#,
+        baseline_start,
+        hour
+    )
+    .fetch_all(pool)
+    .observe("get_baseline_signup_counts")
+    .await?
+    .into_iter()
+    .map(|r| (r.source, r.signups))
+    .collect();
+
+    let strict_until = now + Duration::minutes(settings.strict_mode_minutes as i64);
+    let mut new_spikes = Vec::new();
+    for spike in find_spikes(&current, &baseline_totals, settings) {
+        let mut transaction = pool.begin().await?;
+        let r = sqlx::query!(
+            r#"
+            INSERT INTO signup_spikes (hour, source, signups, baseline, detected_at, strict_until)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (hour, source) DO UPDATE
+            SET signups = EXCLUDED.signups, strict_until = EXCLUDED.strict_until
+            RETURNING (xmax = 0) AS "inserted!"
+            "#,
+            hour,
+            spike.source,
+            spike.signups as i32,
+            spike.baseline,
+            now,
+            strict_until
+        )
+        .fetch_one(&mut *transaction)
+        .observe_one("record_signup_spike")
+        .await?;
+        if !r.inserted {
+            transaction.commit().await?;
+            continue;
+        }
+        tracing::warn!(
+            source = spike.source,
+            signups = spike.signups,
+            baseline = spike.baseline,
+            "Signups are spiking: subscribing is in strict mode"
+        );
+        let event = DomainEvent::SignupSpikeDetected {
+            source: spike.source.clone(),
+            signups: spike.signups,
+            baseline: spike.baseline,
+        };
+        event_bus.record(&mut transaction, &event).await?;
+        transaction.commit().await?;
+        event_bus.fan_out(event);
+        new_spikes.push(spike);
+    }
+    // The counts older than the baseline are of no more use.
+    sqlx::query!(
+        "DELETE FROM signup_hourly_counts WHERE hour < $1",
+        baseline_start
+    )
+    .execute(pool)
+    .observe("prune_signup_counts")
+    .await?;
+    Ok(new_spikes)
+}
+
+/// Check for spikes every `check_interval_seconds`, and reject the signups
+/// left in quarantine for longer than `quarantine_days`.
+pub async fn run_signup_spike_detection(
+    pool: PgPool,
+    event_bus: EventBus,
+    settings: SignupProtectionSettings,
+) {
+    let mut ticks = tokio::time::interval(std::time::Duration::from_secs(
+        settings.check_interval_seconds,
+    ));
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        if let Err(e) = try_detect_signup_spikes(&pool, &event_bus, &settings, Utc::now()).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to detect signup spikes"
+            );
+            event_bus
+                .publish_job_failure("signup_spike_detection", &e)
+                .await;
+        }
+        let quarantined_before = Utc::now() - Duration::days(settings.quarantine_days.into());
+        match expire_quarantine(&pool, quarantined_before).await {
+            Ok(expired) if expired > 0 => {
+                tracing::info!(expired, "Rejected the signups left in quarantine")
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to expire the quarantine"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bot_signal, find_spikes, SignupAttempt, SignupSpike, ALL_SOURCES};
+    use crate::configuration::SignupProtectionSettings;
+    use std::collections::HashMap;
+
+    fn settings() -> SignupProtectionSettings {
+        SignupProtectionSettings {
+            check_interval_seconds: 60,
+            baseline_hours: 10,
+            spike_factor: 5.0,
+            min_signups: 20,
+            strict_mode_minutes: 60,
+            strict_signups_per_ip_per_hour: 1,
+            quarantine_days: 7,
+            captcha: None,
+        }
+    }
+
+    fn counts(counts: &[(&str, i64)]) -> HashMap<String, i64> {
+        counts.iter().map(|(s, c)| (s.to_string(), *c)).collect()
+    }
+
+    #[test]
+    fn a_source_far_above_its_baseline_is_a_spike() {
+        // 10 an hour on average, against 60 this hour.
+        let spikes = find_spikes(
+            &counts(&[("l/earthsea", 60), ("footer", 10)]),
+            &counts(&[("l/earthsea", 100), ("footer", 30)]),
+            &settings(),
+        );
+
+        assert_eq!(
+            spikes,
+            vec![
+                SignupSpike {
+                    source: "l/earthsea".into(),
+                    signups: 60,
+                    baseline: 10.0,
+                },
+                SignupSpike {
+                    source: ALL_SOURCES.into(),
+                    signups: 70,
+                    baseline: 13.0,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn quiet_sources_are_not_flagged_for_a_handful_of_signups() {
+        // Way above a baseline of 0.1 an hour, but below `min_signups`.
+        let spikes = find_spikes(
+            &counts(&[("l/earthsea", 5)]),
+            &counts(&[("l/earthsea", 1)]),
+            &settings(),
+        );
+
+        assert!(spikes.is_empty());
+    }
+
+    #[test]
+    fn an_attack_spread_across_sources_is_caught_by_their_total() {
+        let current: Vec<_> = (0..10).map(|i| (format!("form-{}", i), 4)).collect();
+        let current = current
+            .iter()
+            .map(|(s, c)| (s.as_str(), *c))
+            .collect::<Vec<_>>();
+
+        let spikes = find_spikes(&counts(&current), &counts(&[("form-0", 10)]), &settings());
+
+        assert_eq!(spikes.len(), 1);
+        assert_eq!(spikes[0].source, ALL_SOURCES);
+        assert_eq!(spikes[0].signups, 40);
+    }
+
+    fn attempt<'a>(name: &'a str, honeypot: Option<&'a str>) -> SignupAttempt<'a> {
+        SignupAttempt {
+            name,
+            honeypot,
+            captcha_response: None,
+            client_ip: None,
+        }
+    }
+
+    #[test]
+    fn bots_give_themselves_away() {
+        assert_eq!(bot_signal(&attempt("le guin", None)), None);
+        assert_eq!(bot_signal(&attempt("le guin", Some(""))), None);
+        assert_eq!(
+            bot_signal(&attempt("le guin", Some("https://spam.example.com"))),
+            Some("honeypot")
+        );
+        assert_eq!(
+            bot_signal(&attempt("Cheap pills at WWW.spam.example.com", None)),
+            Some("link_in_name")
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! Latency objectives per route, and alerts when their error budget burns
+//! faster than it can last.
+//!
+//! A request is "good" when it is answered within the objective's latency
+//! without a server error. With a 99% target, the error budget is the 1% of
+//! requests allowed to be bad: a burn rate of 1 spends it exactly over the
+//! window, 14.4 spends a 30-day budget in about two days.
+use crate::configuration::{SloObjectiveSettings, SloSettings};
+use crate::events::{DomainEvent, EventBus};
+use crate::metrics::SLO_BURN_RATE;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, Error};
+use actix_web_lab::middleware::Next;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many buckets a rolling window is split into.
+const BUCKETS: u32 = 60;
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    slot: u64,
+    total: u64,
+    bad: u64,
+}
+
+/// Good and bad request counts over the last `window`, with a resolution of
+/// `window / BUCKETS`.
+struct RollingWindow {
+    origin: Instant,
+    bucket_length: Duration,
+    buckets: Vec<Bucket>,
+}
+
+impl RollingWindow {
+    fn new(window: Duration, origin: Instant) -> Self {
+        Self {
+            origin,
+            bucket_length: (window / BUCKETS).max(Duration::from_millis(1)),
+            buckets: vec![Bucket::default(); BUCKETS as usize],
+        }
+    }
+
+    fn slot(&self, now: Instant) -> u64 {
+        (now.saturating_duration_since(self.origin).as_millis() / self.bucket_length.as_millis())
+            as u64
+    }
+
+    fn record(&mut self, now: Instant, bad: bool) {
+        let slot = self.slot(now);
+        let bucket = &mut self.buckets[(slot % BUCKETS as u64) as usize];
+        if bucket.slot != slot {
+            *bucket = Bucket {
+                slot,
+                ..Bucket::default()
+            };
+        }
+        bucket.total += 1;
+        bucket.bad += u64::from(bad);
+    }
+
+    /// `(total, bad)` over the buckets that are still within the window.
+    fn counts(&self, now: Instant) -> (u64, u64) {
+        let current = self.slot(now);
+        self.buckets
+            .iter()
+            .filter(|bucket| current - bucket.slot.min(current) < BUCKETS as u64)
+            .fold((0, 0), |(total, bad), bucket| {
+                (total + bucket.total, bad + bucket.bad)
+            })
+    }
+}
+
+struct TrackedObjective {
+    settings: SloObjectiveSettings,
+    window: Mutex<RollingWindow>,
+    /// Set while the budget is burning, so that we alert once per episode.
+    burning: AtomicBool,
+}
+
+/// An objective whose error budget started burning too fast.
+#[derive(Debug, PartialEq)]
+pub struct SloBurn {
+    pub route: String,
+    pub burn_rate: f64,
+}
+
+pub struct SloTracker {
+    objectives: Vec<TrackedObjective>,
+    burn_rate_threshold: f64,
+    min_requests: u64,
+}
+
+impl SloTracker {
+    pub fn new(settings: &SloSettings) -> Self {
+        Self::starting_at(settings, Instant::now())
+    }
+
+    fn starting_at(settings: &SloSettings, origin: Instant) -> Self {
+        let objectives = settings
+            .objectives
+            .iter()
+            .map(|objective| TrackedObjective {
+                window: Mutex::new(RollingWindow::new(
+                    Duration::from_secs(objective.window_seconds),
+                    origin,
+                )),
+                settings: objective.clone(),
+                burning: AtomicBool::new(false),
+            })
+            .collect();
+        Self {
+            objectives,
+            burn_rate_threshold: settings.burn_rate_threshold,
+            min_requests: settings.min_requests,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objectives.is_empty()
+    }
+
+    /// Count a request to `route` against the objectives that cover it.
+    pub fn record(&self, route: &str, latency: Duration, server_error: bool, now: Instant) {
+        for objective in self.objectives.iter().filter(|o| o.settings.route == route) {
+            let bad = server_error
+                || latency > Duration::from_millis(objective.settings.latency_milliseconds);
+            objective.window.lock().unwrap().record(now, bad);
+        }
+    }
+
+    /// Refresh the burn rate gauges, returning the objectives whose budget
+    /// has just started to burn faster than the threshold.
+    pub fn evaluate(&self, now: Instant) -> Vec<SloBurn> {
+        let mut burns = Vec::new();
+        for objective in &self.objectives {
+            let (total, bad) = objective.window.lock().unwrap().counts(now);
+            let budget = 1.0 - objective.settings.target;
+            let burn_rate = if total == 0 || budget <= 0.0 {
+                0.0
+            } else {
+                (bad as f64 / total as f64) / budget
+            };
+            SLO_BURN_RATE
+                .with_label_values(&[&objective.settings.route])
+                .set(burn_rate);
+            let burning = total >= self.min_requests && burn_rate >= self.burn_rate_threshold;
+            if burning && !objective.burning.swap(true, Ordering::Relaxed) {
+                burns.push(SloBurn {
+                    route: objective.settings.route.clone(),
+                    burn_rate,
+                });
+            } else if !burning {
+                objective.burning.store(false, Ordering::Relaxed);
+            }
+        }
+        burns
+    }
+}
+
+/// Time every request against the `SloTracker` objectives of its route.
+pub async fn track_slo(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(tracker) = req.app_data::<web::Data<SloTracker>>().cloned() else {
+        return next.call(req).await;
+    };
+    let route = req.match_pattern();
+    let started = Instant::now();
+    let outcome = next.call(req).await;
+    if let Some(route) = route {
+        let server_error = match &outcome {
+            Ok(response) => response.status().is_server_error(),
+            Err(e) => e.as_response_error().status_code().is_server_error(),
+        };
+        tracker.record(&route, started.elapsed(), server_error, Instant::now());
+    }
+    outcome
+}
+
+/// Evaluate the objectives every `interval`, warning the admins about the
+/// budgets that burn too fast.
+pub async fn run_slo_evaluation(
+    tracker: web::Data<SloTracker>,
+    event_bus: EventBus,
+    interval: Duration,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        for SloBurn { route, burn_rate } in tracker.evaluate(Instant::now()) {
+            tracing::warn!(route, burn_rate, "An SLO error budget is burning too fast");
+            if let Err(e) = event_bus
+                .publish(DomainEvent::SloBudgetBurning { route, burn_rate })
+                .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to publish an SLO burn"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SloBurn, SloTracker};
+    use crate::configuration::{SloObjectiveSettings, SloSettings};
+    use std::time::{Duration, Instant};
+
+    fn settings() -> SloSettings {
+        SloSettings {
+            evaluation_interval_seconds: 30,
+            burn_rate_threshold: 10.0,
+            min_requests: 10,
+            objectives: vec![SloObjectiveSettings {
+                route: "/subscriptions".into(),
+                latency_milliseconds: 300,
+                target: 0.99,
+                window_seconds: 3600,
+            }],
+        }
+    }
+
+    fn record(tracker: &SloTracker, now: Instant, good: u32, slow: u32) {
+        for _ in 0..good {
+            tracker.record("/subscriptions", Duration::from_millis(20), false, now);
+        }
+        for _ in 0..slow {
+            tracker.record("/subscriptions", Duration::from_millis(500), false, now);
+        }
+    }
+
+    #[test]
+    fn a_fast_burn_is_reported_once_per_episode() {
+        let start = Instant::now();
+        let tracker = SloTracker::starting_at(&settings(), start);
+        // 20% of slow requests against a 1% budget: a burn rate of 20.
+        record(&tracker, start, 80, 20);
+        let burns = tracker.evaluate(start);
+        assert_eq!(burns.len(), 1);
+        let SloBurn { route, burn_rate } = &burns[0];
+        assert_eq!(route, "/subscriptions");
+        assert!((burn_rate - 20.0).abs() < 1e-9);
+        assert!(tracker.evaluate(start).is_empty());
+    }
+
+    #[test]
+    fn a_slow_burn_or_too_little_traffic_is_not_reported() {
+        let start = Instant::now();
+        let tracker = SloTracker::starting_at(&settings(), start);
+        record(&tracker, start, 2, 3);
+        assert!(tracker.evaluate(start).is_empty());
+        record(&tracker, start, 1000, 5);
+        assert!(tracker.evaluate(start).is_empty());
+    }
+
+    #[test]
+    fn server_errors_burn_the_budget_and_other_routes_do_not() {
+        let start = Instant::now();
+        let tracker = SloTracker::starting_at(&settings(), start);
+        for _ in 0..50 {
+            tracker.record("/subscriptions", Duration::ZERO, true, start);
+            tracker.record("/archive", Duration::from_secs(5), false, start);
+        }
+        assert_eq!(tracker.evaluate(start).len(), 1);
+    }
+
+    #[test]
+    fn old_requests_fall_out_of_the_window() {
+        let start = Instant::now();
+        let tracker = SloTracker::starting_at(&settings(), start);
+        record(&tracker, start, 0, 100);
+        assert_eq!(tracker.evaluate(start).len(), 1);
+        let later = start + Duration::from_secs(3600 + 60);
+        record(&tracker, later, 100, 0);
+        assert!(tracker.evaluate(later).is_empty());
+        // The episode is over: a new burn is reported again.
+        record(&tracker, later, 0, 100);
+        assert_eq!(tracker.evaluate(later).len(), 1);
+    }
+}
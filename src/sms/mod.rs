@@ -0,0 +1,411 @@
+//! Short announcements by text message, to the subscribers who verified
+//! their phone number and opted in to texts.
+//!
+//! The texts of an announcement wait in `sms_delivery_queue`. The SMS
+//! worker sends them through the configured provider, each country at the
+//! pace of its own rate limit: carriers cap how fast a sender may text
+//! their subscribers.
+mod twilio;
+mod verification;
+
+use crate::configuration::{Settings, SmsProviderSettings, SmsRateLimitSettings, SmsSettings};
+use crate::database::ObserveQuery;
+use crate::pii::PiiCipher;
+use crate::startup::{get_connection_pool, StartupError};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+pub use twilio::TwilioClient;
+pub use verification::{
+    confirm_phone_verification, start_phone_verification, PhoneVerificationError,
+    PhoneVerificationOutcome,
+};
+
+/// Two text messages' worth: announcements are meant to be short.
+pub const MAX_MESSAGE_LENGTH: usize = 320;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SmsError {
+    #[error("The SMS provider throttled us")]
+    Throttled,
+    /// E.g. the number cannot receive texts: retrying will not help.
+    #[error("The SMS provider rejected the text: {0}")]
+    Rejected(String),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+pub trait SmsProvider: Send + Sync {
+    /// Text `body` to `to`, an E.164 number. Returns the provider's id of
+    /// the message.
+    fn send<'a>(&'a self, to: &'a str, body: &'a str) -> BoxFuture<'a, Result<String, SmsError>>;
+}
+
+impl SmsSettings {
+    pub fn provider(&self) -> Result<Arc<dyn SmsProvider>, StartupError> {
+        let timeout = Duration::from_millis(self.timeout_milliseconds);
+        Ok(match &self.provider {
+            SmsProviderSettings::Twilio {
+                base_url,
+                account_sid,
+                auth_token,
+                from,
+            } => Arc::new(TwilioClient::new(
+                base_url.clone(),
+                account_sid.clone(),
+                auth_token.clone(),
+                from.clone(),
+                timeout,
+            )?),
+        })
+    }
+}
+
+/// The text of an announcement, trimmed.
+pub fn parse_message(message: &str) -> Result<String, String> {
+    let message = message.trim();
+    if message.is_empty() {
+        return Err("The announcement cannot be empty.".into());
+    }
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(format!(
+            "Announcements by text are at most {} characters long.",
+            MAX_MESSAGE_LENGTH
+        ));
+    }
+    Ok(message.to_owned())
+}
+
+/// The texts sent to each country over the last minute. The limits are
+/// passed on every call, like the domain throttle of the email deliveries.
+#[derive(Default)]
+pub struct CountryRateLimiter {
+    sent_at: HashMap<String, VecDeque<Instant>>,
+}
+
+impl CountryRateLimiter {
+    /// The countries that cannot take another text right now.
+    pub fn saturated_countries(
+        &mut self,
+        limits: &SmsRateLimitSettings,
+        now: Instant,
+    ) -> Vec<String> {
+        self.sent_at.retain(|_, sent_at| {
+            while sent_at
+                .front()
+                .is_some_and(|oldest| now.duration_since(*oldest) >= RATE_WINDOW)
+            {
+                sent_at.pop_front();
+            }
+            !sent_at.is_empty()
+        });
+        self.sent_at
+            .iter()
+            .filter(|(country, sent_at)| sent_at.len() >= limits.per_minute(country) as usize)
+            .map(|(country, _)| country.clone())
+            .collect()
+    }
+
+    pub fn record(&mut self, country: &str, now: Instant) {
+        self.sent_at
+            .entry(country.to_owned())
+            .or_default()
+            .push_back(now);
+    }
+}
+
+pub struct SmsStatus {
+    /// As stored: sealed with PII encryption on.
+    pub phone_number: Option<String>,
+    pub phone_verified: bool,
+    pub opted_in_at: Option<DateTime<Utc>>,
+    /// A code was texted to a number, and not typed back yet.
+    pub verification_pending: bool,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_sms_status(pool: &PgPool, subscriber_id: Uuid) -> Result<SmsStatus, sqlx::Error> {
+    sqlx::query_as!(
+        SmsStatus,
+        r#"
+        SELECT
+            phone_number,
+            phone_verified_at IS NOT NULL AS "phone_verified!",
+            sms_opted_in_at AS opted_in_at,
+            EXISTS (
+                SELECT 1 FROM phone_verifications v
+                WHERE v.subscriber_id = subscriptions.id AND v.expires_at > now()
+            ) AS "verification_pending!"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .observe_one("get_sms_status")
+    .await
+}
+
+/// Opt `subscriber_id` in to texts, or out. Opting in again keeps the date
+/// of the first opt-in.
+#[tracing::instrument(skip(transaction))]
+pub async fn set_sms_opt_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    opted_in: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET sms_opted_in_at = CASE WHEN $2 THEN COALESCE(sms_opted_in_at, now()) END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        opted_in
+    )
+    .execute(&mut **transaction)
+    .observe("set_sms_opt_in")
+    .await?;
+    Ok(())
+}
+
+/// How many subscribers an announcement would be texted to.
+#[tracing::instrument(skip(pool))]
+pub async fn count_sms_recipients(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE status = 'confirmed'
+            AND deleted_at IS NULL
+            AND phone_verified_at IS NOT NULL
+            AND sms_opted_in_at IS NOT NULL
+        "#
+    )
+    .fetch_one(pool)
+    .observe_one("count_sms_recipients")
+    .await?;
+    Ok(r.count)
+}
+
+/// Queue an announcement for every subscriber who can be texted. Returns
+/// how many texts were queued.
+#[tracing::instrument(skip(pool, message))]
+pub async fn create_blast(pool: &PgPool, message: &str, user_id: Uuid) -> Result<u64, sqlx::Error> {
+    let blast_id = Uuid::new_v4();
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO sms_blasts (blast_id, message, created_at, created_by)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        blast_id,
+        message,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .observe("insert_sms_blast")
+    .await?;
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO sms_delivery_queue (blast_id, subscriber_id, phone_country)
+        SELECT $1, id, phone_country
+        FROM subscriptions
+        WHERE status = 'confirmed'
+            AND deleted_at IS NULL
+            AND phone_verified_at IS NOT NULL
+            AND sms_opted_in_at IS NOT NULL
+            AND phone_country IS NOT NULL
+        "#,
+        blast_id
+    )
+    .execute(&mut *transaction)
+    .observe("enqueue_sms_blast")
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    Ok(queued)
+}
+
+pub enum SmsOutcome {
+    Sent,
+    EmptyQueue,
+    /// Every pending text goes to a country at its rate limit, or the
+    /// provider asked us to slow down.
+    Throttled,
+}
+
+/// Send the next text of the queue, if its country can take one.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_send_next(
+    pool: &PgPool,
+    provider: &dyn SmsProvider,
+    pii: &PiiCipher,
+    limiter: &mut CountryRateLimiter,
+    limits: &SmsRateLimitSettings,
+) -> Result<SmsOutcome, anyhow::Error> {
+    let saturated = limiter.saturated_countries(limits, Instant::now());
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query!(
+        r#"
+        SELECT
+            q.blast_id,
+            q.subscriber_id,
+            q.phone_country,
+            b.message,
+            s.phone_number,
+            (
+                s.deleted_at IS NULL
+                AND s.phone_verified_at IS NOT NULL
+                AND s.sms_opted_in_at IS NOT NULL
+            ) AS "reachable!"
+        FROM sms_delivery_queue q
+        JOIN sms_blasts b USING (blast_id)
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.phone_country <> ALL($1)
+        ORDER BY b.created_at
+        FOR UPDATE OF q SKIP LOCKED
+        LIMIT 1
+        "#,
+        &saturated
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("dequeue_sms")
+    .await?;
+    let Some(task) = task else {
+        return Ok(if saturated.is_empty() {
+            SmsOutcome::EmptyQueue
+        } else {
+            SmsOutcome::Throttled
+        });
+    };
+    // Whoever opted out, or lost their verified number, since the
+    // announcement was queued is not texted.
+    if let (true, Some(phone_number)) = (task.reachable, &task.phone_number) {
+        let phone_number = pii.open(phone_number)?;
+        limiter.record(&task.phone_country, Instant::now());
+        let (succeeded, message_id) = match provider.send(&phone_number, &task.message).await {
+            Ok(message_id) => (true, Some(message_id)),
+            Err(SmsError::Throttled) => {
+                tracing::warn!("The SMS provider throttled us, pausing the texts");
+                transaction.rollback().await?;
+                return Ok(SmsOutcome::Throttled);
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to text an announcement to a subscriber. Skipping.",
+                );
+                (false, None)
+            }
+        };
+        sqlx::query!(
+            r#"
+            INSERT INTO sms_deliveries
+                (blast_id, subscriber_id, attempted_at, succeeded, provider_message_id)
+            VALUES ($1, $2, now(), $3, $4)
+            "#,
+            task.blast_id,
+            task.subscriber_id,
+            succeeded,
+            message_id
+        )
+        .execute(&mut *transaction)
+        .observe("record_sms_delivery")
+        .await?;
+    }
+    sqlx::query!(
+        "DELETE FROM sms_delivery_queue WHERE blast_id = $1 AND subscriber_id = $2",
+        task.blast_id,
+        task.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .observe("delete_sms_task")
+    .await?;
+    transaction.commit().await?;
+    Ok(SmsOutcome::Sent)
+}
+
+async fn sms_loop(
+    pool: PgPool,
+    provider: Arc<dyn SmsProvider>,
+    pii: PiiCipher,
+    limits: SmsRateLimitSettings,
+) -> Result<(), anyhow::Error> {
+    let mut limiter = CountryRateLimiter::default();
+    loop {
+        match try_send_next(&pool, provider.as_ref(), &pii, &mut limiter, &limits).await {
+            Ok(SmsOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Ok(SmsOutcome::Throttled) | Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(SmsOutcome::Sent) => {}
+        }
+    }
+}
+
+/// Send the queued texts until the process is stopped.
+///
+/// Never resolves if the SMS channel is not configured.
+pub async fn run_sms_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let Some(settings) = configuration.sms else {
+        return std::future::pending().await;
+    };
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    let pii = PiiCipher::new(configuration.pii_encryption.as_ref()).map_err(anyhow::Error::msg)?;
+    sms_loop(
+        connection_pool,
+        settings.provider()?,
+        pii,
+        settings.rate_limits,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_message, CountryRateLimiter};
+    use crate::configuration::SmsRateLimitSettings;
+    use claims::{assert_err, assert_ok};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn each_country_has_its_own_rate_limit() {
+        let limits = SmsRateLimitSettings {
+            default_per_minute: 2,
+            countries: [("US".to_string(), 1)].into(),
+        };
+        let mut limiter = CountryRateLimiter::default();
+        let now = Instant::now();
+        limiter.record("US", now);
+        limiter.record("GB", now);
+        assert_eq!(limiter.saturated_countries(&limits, now), vec!["US"]);
+
+        limiter.record("GB", now);
+        let mut saturated = limiter.saturated_countries(&limits, now);
+        saturated.sort();
+        assert_eq!(saturated, vec!["GB", "US"]);
+
+        let later = now + Duration::from_secs(60);
+        assert!(limiter.saturated_countries(&limits, later).is_empty());
+    }
+
+    #[test]
+    fn announcements_are_short() {
+        assert_eq!(
+            parse_message("  Tickets are on sale! ").unwrap(),
+            "Tickets are on sale!"
+        );
+        assert_err!(parse_message(" "));
+        assert_ok!(parse_message(&"a".repeat(320)));
+        assert_err!(parse_message(&"a".repeat(321)));
+    }
+}
@@ -0,0 +1,76 @@
+use super::{BoxFuture, SmsError, SmsProvider};
+use crate::startup::StartupError;
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+/// A client for the Twilio messages API, which other providers mimic.
+pub struct TwilioClient {
+    http_client: Client,
+    base_url: String,
+    account_sid: String,
+    auth_token: Secret<String>,
+    from: String,
+}
+
+#[derive(serde::Deserialize)]
+struct MessageResource {
+    sid: String,
+}
+
+impl TwilioClient {
+    pub fn new(
+        base_url: String,
+        account_sid: String,
+        auth_token: Secret<String>,
+        from: String,
+        timeout: Duration,
+    ) -> Result<Self, StartupError> {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| StartupError::HttpClient("SMS client", e))?;
+        Ok(Self {
+            http_client,
+            base_url,
+            account_sid,
+            auth_token,
+            from,
+        })
+    }
+
+    async fn create_message(&self, to: &str, body: &str) -> Result<String, SmsError> {
+        // Messaging service ids start with `MG`, numbers with `+`.
+        let from_field = if self.from.starts_with("MG") {
+            "MessagingServiceSid"
+        } else {
+            "From"
+        };
+        let response = self
+            .http_client
+            .post(format!(
+                "{}/2010-04-01/Accounts/{}/Messages.json",
+                self.base_url, self.account_sid
+            ))
+            .basic_auth(&self.account_sid, Some(self.auth_token.expose_secret()))
+            .form(&[("To", to), (from_field, self.from.as_str()), ("Body", body)])
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => Err(SmsError::Throttled),
+            status if status.is_client_error() => Err(SmsError::Rejected(
+                response.text().await.unwrap_or_default(),
+            )),
+            _ => {
+                let message: MessageResource = response.error_for_status()?.json().await?;
+                Ok(message.sid)
+            }
+        }
+    }
+}
+
+impl SmsProvider for TwilioClient {
+    fn send<'a>(&'a self, to: &'a str, body: &'a str) -> BoxFuture<'a, Result<String, SmsError>> {
+        Box::pin(self.create_message(to, body))
+    }
+}
@@ -0,0 +1,170 @@
+//! Proof that a subscriber reads the texts sent to the number they gave:
+//! a code is texted to it, which they type back on their SMS preferences
+//! page. Only then does the number replace the one we text.
+use super::{SmsError, SmsProvider};
+use crate::database::ObserveQuery;
+use crate::domain::SubscriberPhone;
+use crate::pii::PiiCipher;
+use chrono::{Duration, Utc};
+use rand::{thread_rng, Rng};
+use sha3::{Digest, Sha3_256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const CODE_TTL_MINUTES: i64 = 10;
+/// Past it, the code is dropped: 6 digits only hold against a few guesses.
+const MAX_ATTEMPTS: i32 = 5;
+/// A code is texted at most once a minute, as every text is paid for.
+const RESEND_INTERVAL_SECONDS: i64 = 60;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PhoneVerificationError {
+    #[error("A code was texted less than a minute ago.")]
+    TooSoon,
+    #[error("Failed to text the code")]
+    Sms(#[from] SmsError),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+pub enum PhoneVerificationOutcome {
+    Verified,
+    WrongCode,
+    /// The code expired, was guessed at too many times, or was never sent.
+    NoCode,
+}
+
+/// Only the hash is stored, like the tokens of the magic links.
+fn hash_code(code: &str) -> String {
+    hex::encode(Sha3_256::digest(code.as_bytes()))
+}
+
+/// Text a code to `phone`, replacing the code of any number given before.
+#[tracing::instrument(skip(pool, pii, provider, phone))]
+pub async fn start_phone_verification(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    provider: &dyn SmsProvider,
+    subscriber_id: Uuid,
+    phone: &SubscriberPhone,
+) -> Result<(), PhoneVerificationError> {
+    let mut transaction = pool.begin().await.map_err(anyhow::Error::from)?;
+    let previous = sqlx::query!(
+        "SELECT sent_at FROM phone_verifications WHERE subscriber_id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("get_phone_verification")
+    .await
+    .map_err(anyhow::Error::from)?;
+    let now = Utc::now();
+    if previous.is_some_and(|p| p.sent_at + Duration::seconds(RESEND_INTERVAL_SECONDS) > now) {
+        return Err(PhoneVerificationError::TooSoon);
+    }
+    let code = format!("{:06}", thread_rng().gen_range(0..1_000_000));
+    sqlx::query!(
+        r#"
+        INSERT INTO phone_verifications
+            (subscriber_id, phone_number, phone_country, code_hash, attempts, sent_at, expires_at)
+        VALUES ($1, $2, $3, $4, 0, $5, $6)
+        ON CONFLICT (subscriber_id) DO UPDATE SET
+            phone_number = EXCLUDED.phone_number,
+            phone_country = EXCLUDED.phone_country,
+            code_hash = EXCLUDED.code_hash,
+            attempts = 0,
+            sent_at = EXCLUDED.sent_at,
+            expires_at = EXCLUDED.expires_at
+        "#,
+        subscriber_id,
+        pii.seal(phone.as_ref()),
+        phone.country(),
+        hash_code(&code),
+        now,
+        now + Duration::minutes(CODE_TTL_MINUTES)
+    )
+    .execute(&mut *transaction)
+    .observe("save_phone_verification")
+    .await
+    .map_err(anyhow::Error::from)?;
+    // Texted before committing: a code that could not be sent is not kept.
+    provider
+        .send(
+            phone.as_ref(),
+            &format!("Your verification code is {}.", code),
+        )
+        .await?;
+    transaction.commit().await.map_err(anyhow::Error::from)?;
+    Ok(())
+}
+
+/// Check the code typed by the subscriber. The right one makes the number
+/// theirs.
+#[tracing::instrument(skip(pool, code))]
+pub async fn confirm_phone_verification(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    code: &str,
+) -> Result<PhoneVerificationOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let pending = sqlx::query!(
+        r#"
+        SELECT phone_number, phone_country, code_hash, attempts, expires_at
+        FROM phone_verifications
+        WHERE subscriber_id = $1
+        FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("get_phone_verification")
+    .await?;
+    let Some(pending) = pending else {
+        return Ok(PhoneVerificationOutcome::NoCode);
+    };
+    if pending.expires_at <= Utc::now() || pending.attempts >= MAX_ATTEMPTS {
+        delete_phone_verification(&mut transaction, subscriber_id).await?;
+        transaction.commit().await?;
+        return Ok(PhoneVerificationOutcome::NoCode);
+    }
+    if hash_code(code.trim()) != pending.code_hash {
+        sqlx::query!(
+            "UPDATE phone_verifications SET attempts = attempts + 1 WHERE subscriber_id = $1",
+            subscriber_id
+        )
+        .execute(&mut *transaction)
+        .observe("count_phone_verification_attempt")
+        .await?;
+        transaction.commit().await?;
+        return Ok(PhoneVerificationOutcome::WrongCode);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET phone_number = $2, phone_country = $3, phone_verified_at = now()
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        pending.phone_number,
+        pending.phone_country
+    )
+    .execute(&mut *transaction)
+    .observe("verify_phone_number")
+    .await?;
+    delete_phone_verification(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(PhoneVerificationOutcome::Verified)
+}
+
+async fn delete_phone_verification(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM phone_verifications WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .observe("delete_phone_verification")
+    .await?;
+    Ok(())
+}
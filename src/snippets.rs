@@ -0,0 +1,274 @@
+//! Reusable content blocks, inserted into issues with a `{{snippet:name}}`
+//! tag.
+//!
+//! The tags are expanded when an issue is published, with the latest
+//! version of each snippet: editing a snippet afterwards leaves the issues
+//! already published as they were sent.
+use crate::database::ObserveQuery;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const TAG_PREFIX: &str = "{{snippet:";
+const TAG_SUFFIX: &str = "}}";
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Lower-case letters, digits, `-` and `_`, e.g. `sponsor` or `signature`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// The names of the snippets `content` refers to, in order of appearance.
+pub fn tags(content: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(TAG_PREFIX) {
+        let after = &rest[start + TAG_PREFIX.len()..];
+        match after.find(TAG_SUFFIX) {
+            Some(end) => {
+                let name = after[..end].trim();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+                rest = &after[end + TAG_SUFFIX.len()..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Replace the tags of `content` with the matching snippet content, by
+/// name. Unknown tags are kept.
+pub fn expand(content: &str, snippets: &HashMap<&str, &str>) -> String {
+    let mut expanded = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(TAG_PREFIX) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + TAG_PREFIX.len()..];
+        let snippet = after.find(TAG_SUFFIX).and_then(|end| {
+            snippets
+                .get(after[..end].trim())
+                .map(|snippet| (*snippet, end))
+        });
+        match snippet {
+            Some((snippet, end)) => {
+                expanded.push_str(snippet);
+                rest = &after[end + TAG_SUFFIX.len()..];
+            }
+            None => {
+                expanded.push_str(TAG_PREFIX);
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+pub struct SnippetVersion {
+    pub name: String,
+    pub version: i32,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// An issue's content with its snippets expanded, and the versions used.
+pub struct ExpandedIssue {
+    pub html_content: String,
+    pub text_content: String,
+    pub snippets: Vec<SnippetVersion>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SnippetError {
+    #[error("There is no snippet named {0}.")]
+    Unknown(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Expand the snippets of an issue being published, with their latest
+/// version.
+pub async fn expand_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    html_content: &str,
+    text_content: &str,
+) -> Result<ExpandedIssue, SnippetError> {
+    let mut names: Vec<String> = tags(html_content).into_iter().map(String::from).collect();
+    for name in tags(text_content) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_owned());
+        }
+    }
+    let snippets = if names.is_empty() {
+        Vec::new()
+    } else {
+        get_latest_versions(&mut **transaction, &names).await?
+    };
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !snippets.iter().any(|s| &s.name == *name))
+    {
+        return Err(SnippetError::Unknown(unknown.clone()));
+    }
+    let html: HashMap<&str, &str> = snippets
+        .iter()
+        .map(|s| (s.name.as_str(), s.html_content.as_str()))
+        .collect();
+    let text: HashMap<&str, &str> = snippets
+        .iter()
+        .map(|s| (s.name.as_str(), s.text_content.as_str()))
+        .collect();
+    Ok(ExpandedIssue {
+        html_content: expand(html_content, &html),
+        text_content: expand(text_content, &text),
+        snippets,
+    })
+}
+
+#[tracing::instrument(skip(executor))]
+async fn get_latest_versions<'e>(
+    executor: impl Executor<'e, Database = Postgres>,
+    names: &[String],
+) -> Result<Vec<SnippetVersion>, sqlx::Error> {
+    sqlx::query_as!(
+        SnippetVersion,
+        r#"
+        SELECT DISTINCT ON (name) name, version, html_content, text_content
+        FROM snippet_versions
+        WHERE name = ANY($1)
+        ORDER BY name, version DESC
+        "#,
+        names
+    )
+    .fetch_all(executor)
+    .observe("get_latest_snippet_versions")
+    .await
+}
+
+/// Remember which versions an issue was published with.
+#[tracing::instrument(skip(transaction, snippets))]
+pub async fn record_issue_snippets(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    snippets: &[SnippetVersion],
+) -> Result<(), sqlx::Error> {
+    let names: Vec<String> = snippets.iter().map(|s| s.name.clone()).collect();
+    let versions: Vec<i32> = snippets.iter().map(|s| s.version).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_snippets (newsletter_issue_id, name, version)
+        SELECT $1, * FROM UNNEST($2::TEXT[], $3::INT[])
+        "#,
+        issue_id,
+        &names,
+        &versions
+    )
+    .execute(&mut **transaction)
+    .observe("record_issue_snippets")
+    .await?;
+    Ok(())
+}
+
+/// Save a new version of a snippet, the first one if it is new. Returns
+/// the version number.
+#[tracing::instrument(skip(pool, html_content, text_content))]
+pub async fn save_snippet(
+    pool: &PgPool,
+    name: &str,
+    html_content: &str,
+    text_content: &str,
+    user_id: Uuid,
+) -> Result<i32, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO snippet_versions
+            (name, version, html_content, text_content, created_at, created_by)
+        SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3, now(), $4
+        FROM snippet_versions
+        WHERE name = $1
+        RETURNING version
+        "#,
+        name,
+        html_content,
+        text_content,
+        user_id
+    )
+    .fetch_one(pool)
+    .observe_one("save_snippet")
+    .await?;
+    Ok(r.version)
+}
+
+pub struct SnippetSummary {
+    pub name: String,
+    pub latest_version: i32,
+    pub updated_at: DateTime<Utc>,
+    /// How many issues were published with one of its versions.
+    pub issues: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn list_snippets(pool: &PgPool) -> Result<Vec<SnippetSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SnippetSummary,
+        r#"
+        SELECT
+            v.name,
+            MAX(v.version) AS "latest_version!",
+            MAX(v.created_at) AS "updated_at!",
+            (
+                SELECT COUNT(*) FROM newsletter_issue_snippets i WHERE i.name = v.name
+            ) AS "issues!"
+        FROM snippet_versions v
+        GROUP BY v.name
+        ORDER BY v.name
+        "#
+    )
+    .fetch_all(pool)
+    .observe("list_snippets")
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, is_valid_name, tags};
+    use std::collections::HashMap;
+
+    #[test]
+    fn tags_are_found_once_each() {
+        let content =
+            "{{snippet:sponsor}} Hello {{snippet:signature}} {{snippet:sponsor}} {{name}}";
+        assert_eq!(tags(content), vec!["sponsor", "signature"]);
+    }
+
+    #[test]
+    fn tags_are_replaced_by_the_snippets() {
+        let snippets = HashMap::from([("sponsor", "<p>Brought to you by Earthsea</p>")]);
+        assert_eq!(
+            expand("{{snippet:sponsor}}<p>Hi</p>{{snippet:other}}", &snippets),
+            "<p>Brought to you by Earthsea</p><p>Hi</p>{{snippet:other}}"
+        );
+    }
+
+    #[test]
+    fn snippet_content_is_not_expanded_again() {
+        let snippets = HashMap::from([("a", "{{snippet:a}}")]);
+        assert_eq!(expand("{{snippet:a}}", &snippets), "{{snippet:a}}");
+    }
+
+    #[test]
+    fn names_are_slugs() {
+        assert!(is_valid_name("sponsor-2024_q1"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("Sponsor"));
+        assert!(!is_valid_name("spon sor"));
+        assert!(!is_valid_name(&"a".repeat(65)));
+    }
+}
@@ -0,0 +1,174 @@
+//! Score newsletter content with SpamAssassin before it goes out.
+//!
+//! Talks the spamd protocol (`SPAMC/1.5`) over TCP: the message is sent with
+//! a `SYMBOLS` request, and spamd answers with the score in a `Spam` header
+//! and the names of the rules that matched in the body.
+use crate::configuration::SpamCheckSettings;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub struct SpamAssassinClient {
+    address: String,
+    timeout: Duration,
+    from: String,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct SpamReport {
+    pub is_spam: bool,
+    pub score: f64,
+    pub threshold: f64,
+    pub rules: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SpamCheckError {
+    #[error("Failed to talk to spamd")]
+    Io(#[from] std::io::Error),
+    #[error("spamd did not answer in time")]
+    Timeout,
+    #[error("spamd returned an error: {0}")]
+    Spamd(String),
+    #[error("Malformed spamd response: {0}")]
+    Malformed(&'static str),
+}
+
+impl SpamAssassinClient {
+    pub fn new(settings: SpamCheckSettings, from: &str) -> Self {
+        Self {
+            address: format!("{}:{}", settings.host, settings.port),
+            timeout: Duration::from_millis(settings.timeout_milliseconds),
+            from: from.to_owned(),
+        }
+    }
+
+    #[tracing::instrument(name = "Check an issue for spam", skip_all)]
+    pub async fn check(
+        &self,
+        subject: &str,
+        text_content: &str,
+        html_content: &str,
+    ) -> Result<SpamReport, SpamCheckError> {
+        let message = render_message(&self.from, subject, text_content, html_content);
+        let response = tokio::time::timeout(self.timeout, self.exchange(&message))
+            .await
+            .map_err(|_| SpamCheckError::Timeout)??;
+        parse_response(&response)
+    }
+
+    async fn exchange(&self, message: &str) -> Result<String, SpamCheckError> {
+        let mut stream = TcpStream::connect(&self.address).await?;
+        let request = format!(
+            "SYMBOLS SPAMC/1.5\r\nContent-length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        );
+        stream.write_all(request.as_bytes()).await?;
+        stream.shutdown().await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+}
+
+/// The issue as a subscriber would receive it, with both alternatives: the
+/// text to HTML ratio is one of the things SpamAssassin looks at.
+fn render_message(from: &str, subject: &str, text_content: &str, html_content: &str) -> String {
+    const BOUNDARY: &str = "zero2prod-spam-check";
+    format!(
+        "From: {from}\r\n\
+         Subject: {subject}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/alternative; boundary=\"{BOUNDARY}\"\r\n\
+         \r\n\
+         --{BOUNDARY}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         \r\n\
+         {text_content}\r\n\
+         --{BOUNDARY}\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\
+         \r\n\
+         {html_content}\r\n\
+         --{BOUNDARY}--\r\n"
+    )
+}
+
+fn parse_response(response: &str) -> Result<SpamReport, SpamCheckError> {
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or(SpamCheckError::Malformed("missing header terminator"))?;
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap_or_default();
+    // E.g. `SPAMD/1.1 0 EX_OK`.
+    let mut status_parts = status.splitn(3, ' ');
+    if !status_parts.next().is_some_and(|p| p.starts_with("SPAMD/")) {
+        return Err(SpamCheckError::Malformed("unexpected status line"));
+    }
+    if status_parts.next() != Some("0") {
+        return Err(SpamCheckError::Spamd(status.to_owned()));
+    }
+    // E.g. `Spam: True ; 15.3 / 5.0`.
+    let spam_header = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("spam"))
+        .map(|(_, value)| value.trim())
+        .ok_or(SpamCheckError::Malformed("missing Spam header"))?;
+    let (verdict, scores) = spam_header
+        .split_once(';')
+        .ok_or(SpamCheckError::Malformed("invalid Spam header"))?;
+    let (score, threshold) = scores
+        .split_once('/')
+        .ok_or(SpamCheckError::Malformed("invalid Spam header"))?;
+    let parse_score = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| SpamCheckError::Malformed("invalid score"))
+    };
+    Ok(SpamReport {
+        is_spam: matches!(verdict.trim(), "True" | "Yes"),
+        score: parse_score(score)?,
+        threshold: parse_score(threshold)?,
+        rules: body
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_response, SpamCheckError, SpamReport};
+    use claims::{assert_matches, assert_ok_eq};
+
+    #[test]
+    fn a_symbols_response_is_parsed() {
+        let response = "SPAMD/1.1 0 EX_OK\r\nContent-length: 30\r\nSpam: True ; 15.3 / 5.0\r\n\r\nHTML_MESSAGE,MIME_HTML_ONLY\r\n";
+        assert_ok_eq!(
+            parse_response(response),
+            SpamReport {
+                is_spam: true,
+                score: 15.3,
+                threshold: 5.0,
+                rules: vec!["HTML_MESSAGE".into(), "MIME_HTML_ONLY".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn a_clean_message_may_trigger_no_rule() {
+        let response = "SPAMD/1.1 0 EX_OK\r\nSpam: False ; -0.1 / 5.0\r\n\r\n";
+        let report = parse_response(response).unwrap();
+        assert!(!report.is_spam);
+        assert!(report.rules.is_empty());
+    }
+
+    #[test]
+    fn spamd_errors_are_surfaced() {
+        let response = "SPAMD/1.0 76 Bad header line: foo\r\n\r\n";
+        assert_matches!(parse_response(response), Err(SpamCheckError::Spamd(_)));
+    }
+}
@@ -0,0 +1,258 @@
+//! Sponsor slots: the block of an issue paid for by a sponsor, with a
+//! tracked link and a pixel to report its clicks and impressions.
+//!
+//! The block is part of the issue's content from the moment it is
+//! published, in place of a `{{sponsor}}` tag or at the end of the issue.
+use crate::database::ObserveQuery;
+use crate::links;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const TAG: &str = "{{sponsor}}";
+const MAX_SPONSOR_LENGTH: usize = 100;
+
+/// A transparent 1x1 GIF.
+pub const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlotEvent {
+    Impression,
+    Click,
+}
+
+impl SlotEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SlotEvent::Impression => "impression",
+            SlotEvent::Click => "click",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SponsorSlot {
+    pub slot_id: Uuid,
+    pub sponsor: String,
+    pub target_url: Url,
+    /// What the block says about the sponsor, as plain text.
+    pub message: String,
+}
+
+impl SponsorSlot {
+    /// `None` without a sponsor: the issue has no slot.
+    pub fn parse(sponsor: &str, target_url: &str, message: &str) -> Result<Option<Self>, String> {
+        let sponsor = sponsor.trim();
+        if sponsor.is_empty() {
+            return Ok(None);
+        }
+        if sponsor.chars().count() > MAX_SPONSOR_LENGTH {
+            return Err(format!(
+                "The sponsor name must be at most {} characters long.",
+                MAX_SPONSOR_LENGTH
+            ));
+        }
+        let target_url = Url::parse(target_url.trim())
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or("The sponsor link must be an http or https URL.")?;
+        Ok(Some(Self {
+            slot_id: Uuid::new_v4(),
+            sponsor: sponsor.to_owned(),
+            target_url,
+            message: message.trim().to_owned(),
+        }))
+    }
+
+    /// The HTML and text content of an issue, with the slot in place of the
+    /// `{{sponsor}}` tag, or at the end without one.
+    pub fn inject(
+        &self,
+        base_url: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> (String, String) {
+        let click = links::sponsor_click(self.slot_id).absolute(base_url);
+        let impression = links::sponsor_impression(self.slot_id).absolute(base_url);
+        let message = if self.message.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", self.message)
+        };
+        let html_block = format!(
+            r#"<p>Sponsored by <a href="{}">{}</a>{}</p><img src="{}" width="1" height="1" alt="">"#,
+            htmlescape::encode_minimal(&click),
+            htmlescape::encode_minimal(&self.sponsor),
+            htmlescape::encode_minimal(&message),
+            htmlescape::encode_minimal(&impression)
+        );
+        let text_block = format!("Sponsored by {}{}\n{}", self.sponsor, message, click);
+        (
+            place(html_content, &html_block),
+            place(text_content, &text_block),
+        )
+    }
+}
+
+fn place(content: &str, block: &str) -> String {
+    if content.contains(TAG) {
+        content.replace(TAG, block)
+    } else {
+        format!("{}\n{}", content, block)
+    }
+}
+
+#[tracing::instrument(skip(transaction, slot), fields(slot_id = %slot.slot_id))]
+pub async fn insert_slot(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    slot: &SponsorSlot,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO sponsor_slots
+            (slot_id, newsletter_issue_id, sponsor, target_url, message, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        slot.slot_id,
+        issue_id,
+        slot.sponsor,
+        slot.target_url.as_str(),
+        slot.message
+    );
+    transaction
+        .execute(query)
+        .observe("insert_sponsor_slot")
+        .await?;
+    Ok(())
+}
+
+/// Count an event of `slot_id`. Returns where the slot's link leads, `None`
+/// if there is no such slot.
+#[tracing::instrument(skip(pool))]
+pub async fn record_slot_event(
+    pool: &PgPool,
+    slot_id: Uuid,
+    event: SlotEvent,
+) -> Result<Option<String>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        WITH slot AS (
+            SELECT slot_id, target_url FROM sponsor_slots WHERE slot_id = $1
+        ), recorded AS (
+            INSERT INTO sponsor_slot_events (slot_id, kind, occurred_at)
+            SELECT slot_id, $2, now() FROM slot
+        )
+        SELECT target_url FROM slot
+        "#,
+        slot_id,
+        event.as_str()
+    )
+    .fetch_optional(pool)
+    .observe("record_sponsor_slot_event")
+    .await?;
+    Ok(r.map(|r| r.target_url))
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SlotReport {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub target_url: String,
+    pub impressions: i64,
+    pub clicks: i64,
+}
+
+/// The slots of `sponsor`, with the events that happened in `[from, to)`.
+#[tracing::instrument(skip(pool))]
+pub async fn sponsor_report(
+    pool: &PgPool,
+    sponsor: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<SlotReport>, sqlx::Error> {
+    sqlx::query_as!(
+        SlotReport,
+        r#"
+        SELECT
+            i.newsletter_issue_id AS issue_id,
+            i.title,
+            i.published_at,
+            s.target_url,
+            COUNT(e.id) FILTER (WHERE e.kind = 'impression') AS "impressions!",
+            COUNT(e.id) FILTER (WHERE e.kind = 'click') AS "clicks!"
+        FROM sponsor_slots s
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        LEFT JOIN sponsor_slot_events e
+            ON e.slot_id = s.slot_id AND e.occurred_at >= $2 AND e.occurred_at < $3
+        WHERE s.sponsor = $1
+        GROUP BY i.newsletter_issue_id, s.slot_id
+        ORDER BY i.published_at
+        "#,
+        sponsor,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .observe("sponsor_report")
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SponsorSlot;
+    use claims::{assert_err, assert_none};
+
+    fn slot() -> SponsorSlot {
+        SponsorSlot::parse(
+            "Earthsea & Co",
+            "https://earthsea.example/?ref=news",
+            "Maps",
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn the_slot_replaces_the_tag() {
+        let slot = slot();
+        let (html, text) = slot.inject(
+            "https://news.example/",
+            "<p>Hi</p>{{sponsor}}<p>Bye</p>",
+            "Hi\n{{sponsor}}\nBye",
+        );
+        assert_eq!(
+            html,
+            format!(
+                r#"<p>Hi</p><p>Sponsored by <a href="https://news.example/sponsors/{0}/click">Earthsea &amp; Co</a>: Maps</p><img src="https://news.example/sponsors/{0}/impression.gif" width="1" height="1" alt=""><p>Bye</p>"#,
+                slot.slot_id
+            )
+        );
+        assert_eq!(
+            text,
+            format!(
+                "Hi\nSponsored by Earthsea & Co: Maps\nhttps://news.example/sponsors/{}/click\nBye",
+                slot.slot_id
+            )
+        );
+    }
+
+    #[test]
+    fn without_a_tag_the_slot_goes_at_the_end() {
+        let (html, _) = slot().inject("https://news.example", "<p>Hi</p>", "Hi");
+        assert!(html.starts_with("<p>Hi</p>\n<p>Sponsored by"));
+    }
+
+    #[test]
+    fn sponsors_need_a_web_link() {
+        assert_none!(SponsorSlot::parse(" ", "", "").unwrap());
+        assert_err!(SponsorSlot::parse("Earthsea", "javascript:alert(1)", ""));
+        assert_err!(SponsorSlot::parse("Earthsea", "earthsea.example", ""));
+    }
+}
@@ -1,14 +1,69 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::admin_audit::record_admin_activity;
+use crate::admin_events::{AdminEvent, AdminEventBroadcaster};
+use crate::api_keys::{limit_api_key_rate, ApiKeys};
+use crate::authentication::{
+    protect_against_csrf, reject_anonymous_users, reject_disallowed_admin_clients,
+    reject_invalid_api_key, reject_invalid_scim_token,
+};
+use crate::billing::StripeClient;
+use crate::configuration::{DatabaseSettings, Settings, SmsSettings};
+use crate::database::is_transient;
+use crate::deliverability::DnsChecker;
 use crate::email_client::EmailClient;
+use crate::email_verification::EmailVerifier;
+use crate::events::{DomainEvent, EventBus};
+use crate::idempotency::replay_idempotent_requests;
+use crate::image_proxy::ImageProxy;
+use crate::landing_analytics::{allow_landing_origins, LandingLinks};
+use crate::link_checker::LinkChecker;
+use crate::listener::Listener;
+use crate::load_shedding::{shed_load, LoadShedder};
+use crate::notifier::Notifier;
+use crate::oidc::OidcClient;
+use crate::pii::PiiCipher;
+use crate::polls::PollLinks;
+use crate::referrals::ReferralRewards;
+use crate::reload::ReloadableSettings;
+use crate::request_tracing::{
+    propagate_request_context, AdminAllowlist, PropagatedRootSpanBuilder, TrustedProxies,
+};
+use crate::retention::RetentionPolicy;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    admin_dashboard, admin_notifications, admin_operations, admin_sessions, api_key_usage,
+    archive_image, archive_index, archive_issue, archive_link_form, attach_issue_variant,
+    audit_analytics, change_password, change_password_form, check_dns_records,
+    check_newsletter_links, check_newsletter_spam, clone_newsletter_issue, confirm,
+    confirm_archive_link, confirm_form, confirm_login_link, delete_subscriber,
+    delivery_event_webhook, delivery_status, error_chain_fmt, health_check, home,
+    hosted_signup_page, landing_visit_preflight, log_out, login, login_form, login_link_form,
+    merge_subscriber, metrics, newsletter_issue_report, oidc_callback, oidc_login, poll_results,
+    poll_vote, provide_phone_number, publish_newsletter, publish_newsletter_form,
+    push_service_worker, quarantine_queue, queue_stats, record_landing_visit, referral_leaderboard,
+    referral_signup_page, register_push_subscription, reload_settings, report_seed_placement,
+    request_archive_link, request_login_link, resend_latest_issue, reset_subscriber_links,
+    restore_subscriber, resume_admin_operation, resume_newsletter_delivery, retention_policy,
+    retry_failed, review_quarantine, revoke_admin_session, revoke_other_admin_sessions,
+    roll_back_admin_operation, save_issue_template, save_snippet_version, scim_create_user,
+    scim_get_user, scim_list_users, scim_patch_user, seed_placement_webhook, send_quota_usage,
+    send_sms_blast, sms_blast_form, sms_preferences, snippet_library, sponsor_click,
+    sponsor_impression, sponsor_report, start_admin_operation, start_checkout, stripe_webhook,
+    subscribe, subscriber_consent, subscriber_preferences, update_sms_preferences,
+    update_subscriber_preferences, verify_email, verify_phone_number, view_as_subscriber,
+    SignupPages, SubscriberRedirects,
 };
+use crate::signup_protection::{run_signup_spike_detection, SignupProtection};
+use crate::slo::{run_slo_evaluation, track_slo, SloTracker};
+use crate::sms::SmsProvider;
+use crate::spam_check::SpamAssassinClient;
+use crate::theme::Theme;
+use crate::token_signer::TokenSigner;
+use crate::web_push::WebPush;
+use crate::web_version::WebVersion;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::http::Method;
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
@@ -17,118 +72,696 @@ use actix_web_lab::middleware::from_fn;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
     port: u16,
     server: Server,
+    event_bus: EventBus,
+    settings: ReloadableSettings,
+}
+
+/// Why the application could not start.
+#[derive(thiserror::Error)]
+pub enum StartupError {
+    #[error("Failed to load the configuration")]
+    Configuration(#[from] config::ConfigError),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("Failed to build the HTTP client for the {0}")]
+    HttpClient(&'static str, #[source] reqwest::Error),
+    #[error("Failed to connect to Postgres at {host}:{port}")]
+    Database {
+        host: String,
+        port: u16,
+        #[source]
+        source: sqlx::Error,
+    },
+    #[error("Failed to connect to Redis")]
+    Redis(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to listen on {address}")]
+    Listen {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("No socket was inherited from systemd: {0}")]
+    SocketActivation(String),
+    #[error("Failed to start the Tokio runtime")]
+    Runtime(#[source] std::io::Error),
+}
+
+impl std::fmt::Debug for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl StartupError {
+    /// What the operator can do about it.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Configuration(_) | Self::InvalidConfiguration(_) => {
+                "Check configuration/base.yaml, the file matching APP_ENVIRONMENT and the \
+                APP_* environment variables. `zero2prod config validate <file>` checks a \
+                single file."
+            }
+            Self::HttpClient(..) => {
+                "The TLS backend could not be initialised: check the system certificate store."
+            }
+            Self::Database { .. } => {
+                "Is Postgres running and reachable? Check the `database` settings \
+                (APP_DATABASE__*), or run `zero2prod doctor`."
+            }
+            Self::Redis(_) => {
+                "Is Redis running and reachable? Check `redis_uri` (APP_REDIS_URI), or run \
+                `zero2prod doctor`."
+            }
+            Self::Listen { .. } => {
+                "Is another process already using that address? Change `application.host` or \
+                `application.port` (APP_APPLICATION__PORT), or the socket path of a `unix` \
+                `application.listener`."
+            }
+            Self::SocketActivation(_) => {
+                "Start the service through its systemd .socket unit, or set \
+                `application.listener.kind` to `tcp` or `unix`."
+            }
+            Self::Runtime(_) => {
+                "The worker threads could not be spawned: lower `runtime.worker_threads` or \
+                raise the thread limit of the process."
+            }
+        }
+    }
 }
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
-        let connection_pool = get_connection_pool(&configuration.database)
-            .await
-            .expect("Failed to connect to Postgres.");
+    pub async fn build(configuration: Settings) -> Result<Self, StartupError> {
+        let email_client = configuration.build_email_client()?;
+        let connection_pool =
+            get_connection_pool(&configuration.database)
+                .await
+                .map_err(|source| StartupError::Database {
+                    host: configuration.database.host.clone(),
+                    port: configuration.database.port,
+                    source,
+                })?;
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.api_private_key,
-            configuration.email_client.api_public_key,
-            timeout,
-        );
-
-        let address = format!(
-            "{}:{}",
-            configuration.application.host, configuration.application.port
-        );
-        let listener = TcpListener::bind(address)?;
-        let port = listener.local_addr().unwrap().port();
+        let mut event_bus = if configuration.events.durable {
+            EventBus::durable(connection_pool.clone())
+        } else {
+            EventBus::in_memory()
+        };
+        if configuration.events.export.is_some() {
+            event_bus = event_bus.with_outbox(connection_pool.clone());
+        }
+
+        let listener = Listener::bind(&configuration.application)?;
+        let port = listener.port().unwrap_or_default();
+        tracing::info!("Listening on {}", listener.describe());
+        let settings = ReloadableSettings::new(&configuration);
         let server = run(
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
-            configuration.redis_uri,
+            event_bus.clone(),
+            settings.clone(),
+            configuration,
         )
         .await?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            event_bus,
+            settings,
+        })
     }
 
+    /// The TCP port we listen on, or 0 when listening on a Unix socket.
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The bus that background workers should publish their events on.
+    pub fn event_bus(&self) -> EventBus {
+        self.event_bus.clone()
+    }
+
+    /// The settings that reloads swap, shared with the background worker.
+    pub fn reloadable_settings(&self) -> ReloadableSettings {
+        self.settings.clone()
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
 }
 
 pub async fn get_connection_pool(configuration: &DatabaseSettings) -> Result<PgPool, sqlx::Error> {
-    PgPoolOptions::new()
-        .connect_with(configuration.with_db())
-        .await
+    let mut interval = Duration::from_millis(configuration.connect_retry_interval_milliseconds);
+    let mut attempt = 1;
+    loop {
+        match PgPoolOptions::new()
+            .connect_with(configuration.with_db())
+            .await
+        {
+            Err(e) if attempt < configuration.connect_attempts && is_transient(&e) => {
+                tracing::warn!(
+                    error.message = %e,
+                    attempt,
+                    "Failed to connect to Postgres, retrying in {:?}",
+                    interval
+                );
+                tokio::time::sleep(interval).await;
+                interval *= 2;
+                attempt += 1;
+            }
+            outcome => return outcome,
+        }
+    }
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
 async fn run(
-    listener: TcpListener,
+    listener: Listener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
-    redis_uri: Secret<String>,
-) -> Result<Server, anyhow::Error> {
+    event_bus: EventBus,
+    settings: ReloadableSettings,
+    configuration: Settings,
+) -> Result<Server, StartupError> {
+    let hmac_secret = configuration.application.hmac_secret;
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
-    let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let event_bus = Data::new(event_bus);
+    let oidc = configuration
+        .oidc
+        .map(|oidc| OidcClient::new(oidc, &configuration.application.base_url))
+        .transpose()?
+        .map(Data::new);
+    let base_url = Data::new(ApplicationBaseUrl(configuration.application.base_url));
+    let settings = Data::new(settings);
+    let trusted_proxies = Data::new(
+        TrustedProxies::parse(&configuration.network.trusted_proxies).map_err(|e| {
+            StartupError::InvalidConfiguration(format!("network.trusted_proxies: {}", e))
+        })?,
+    );
+    let admin_allowlist = configuration
+        .admin
+        .allowed_cidrs
+        .as_deref()
+        .map(|entries| {
+            AdminAllowlist::parse(entries).map_err(|e| {
+                StartupError::InvalidConfiguration(format!("admin.allowed_cidrs: {}", e))
+            })
+        })
+        .transpose()?
+        .map(Data::new);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
-    let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let subscriber_redirects = Data::new(
+        SubscriberRedirects::parse(&configuration.subscriber_redirects).map_err(|e| {
+            StartupError::InvalidConfiguration(format!("subscriber_redirects: {}", e))
+        })?,
+    );
+    let http_cache = Data::new(configuration.http_cache);
+    let signup_pages = Data::new(
+        SignupPages::parse(&configuration.signup_pages)
+            .map_err(|e| StartupError::InvalidConfiguration(format!("signup_pages: {}", e)))?,
+    );
+    let theme = Data::new(
+        Theme::new(&configuration.theme)
+            .map_err(|e| StartupError::InvalidConfiguration(format!("theme: {}", e)))?,
+    );
+    let retention = Data::new(
+        RetentionPolicy::new(
+            &configuration.subscriber_retention,
+            &configuration.data_retention,
+        )
+        .map_err(|e| StartupError::InvalidConfiguration(format!("data_retention: {}", e)))?,
+    );
+    let pii = Data::new(
+        PiiCipher::new(configuration.pii_encryption.as_ref())
+            .map_err(|e| StartupError::InvalidConfiguration(format!("pii_encryption: {}", e)))?,
+    );
+    let token_signer = Arc::new(
+        TokenSigner::new(&configuration.token_signing)
+            .map_err(|e| StartupError::InvalidConfiguration(format!("token_signing: {}", e)))?,
+    );
+    let web_version = Data::new(WebVersion::new(base_url.0.clone(), token_signer.clone()));
+    let poll_links = Data::new(PollLinks::new(base_url.0.clone(), token_signer.clone()));
+    let landing_links = configuration
+        .landing_analytics
+        .as_ref()
+        .map(|settings| LandingLinks::new(settings, token_signer.clone()))
+        .transpose()
+        .map_err(|e| StartupError::InvalidConfiguration(format!("landing_analytics: {}", e)))?
+        .map(Data::new);
+    let magic_links = Data::new(configuration.magic_links);
+    let subscription_tokens = Data::new(configuration.subscription_tokens);
+    let login_settings = Data::new(configuration.login);
+    let consent = Data::new(configuration.consent);
+    let resends = Data::new(configuration.resends);
+    let idempotency = Data::new(configuration.idempotency);
+    let i18n = Data::new(configuration.i18n);
+    let scim = configuration.scim.map(Data::new);
+    let seed_list = configuration.seed_list.map(Data::new);
+    let delivery_events = configuration.delivery_events.map(Data::new);
+    let api_keys = configuration
+        .api
+        .as_ref()
+        .map(ApiKeys::new)
+        .transpose()
+        .map_err(|e| StartupError::InvalidConfiguration(format!("api: {}", e)))?
+        .map(Data::new);
+    let email_verifier = Data::new(EmailVerifier::new(&configuration.email_verification));
+    let spam_check = configuration.spam_check.map(|spam_check| {
+        Data::new(SpamAssassinClient::new(
+            spam_check,
+            &configuration.email_client.sender_email,
+        ))
+    });
+    let image_proxy = Data::new(ImageProxy::new(&configuration.image_proxy)?);
+    let link_checker = Data::new(LinkChecker::new(&configuration.link_checker)?);
+    let stripe = configuration
+        .billing
+        .map(StripeClient::new)
+        .transpose()?
+        .map(Data::new);
+    let web_push = configuration
+        .web_push
+        .map(WebPush::new)
+        .transpose()?
+        .map(Data::new);
+    let sms_provider = configuration
+        .sms
+        .as_ref()
+        .map(SmsSettings::provider)
+        .transpose()?
+        .map(Data::<dyn SmsProvider>::from);
+    let sms = configuration.sms.map(Data::new);
+    let telegram = configuration.telegram.map(Data::new);
+    let dns_checker = Data::new(DnsChecker::new(
+        configuration
+            .email_client
+            .sender_domain()
+            .unwrap_or_default()
+            .to_owned(),
+        &configuration.deliverability,
+    ));
+    let redis_store = RedisSessionStore::new(configuration.redis_uri.expose_secret())
+        .await
+        .map_err(|e| StartupError::Redis(e.into()))?;
+    let admin_events = AdminEventBroadcaster::new();
+    forward_admin_events(&event_bus, admin_events.clone());
+    if let Some(notifications) = configuration.notifications.clone() {
+        Notifier::new(notifications)?
+            .with_admin_events(admin_events.clone())
+            .spawn(&event_bus);
+    }
+    ReferralRewards::new(
+        db_pool.get_ref().clone(),
+        email_client.clone().into_inner(),
+        pii.get_ref().clone(),
+        configuration.referrals,
+        event_bus.get_ref().clone(),
+    )
+    .spawn();
+    let admin_events = Data::new(admin_events);
+    let load_shedder = Data::new(LoadShedder::new(&configuration.load_shedding));
+    let signup_protection = configuration
+        .signup_protection
+        .as_ref()
+        .map(SignupProtection::new)
+        .transpose()?
+        .map(Data::new);
+    if let Some(settings) = configuration.signup_protection.clone() {
+        tokio::spawn(run_signup_spike_detection(
+            db_pool.get_ref().clone(),
+            event_bus.get_ref().clone(),
+            settings,
+        ));
+    }
+    let slo_tracker = Data::new(SloTracker::new(&configuration.slo));
+    if !slo_tracker.is_empty() {
+        tokio::spawn(run_slo_evaluation(
+            slo_tracker.clone(),
+            event_bus.get_ref().clone(),
+            Duration::from_secs(configuration.slo.evaluation_interval_seconds),
+        ));
+    }
+    let http_workers = configuration.runtime.http_workers;
+    if http_workers == Some(0) {
+        return Err(StartupError::InvalidConfiguration(
+            "runtime.http_workers must be at least 1".into(),
+        ));
+    }
+    let address = listener.describe();
     let server = HttpServer::new(move || {
-        App::new()
+        let mut app = App::new()
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
                 redis_store.clone(),
                 secret_key.clone(),
             ))
-            .wrap(TracingLogger::default())
+            .wrap(from_fn(shed_load))
+            .wrap(from_fn(track_slo))
+            .wrap(TracingLogger::<PropagatedRootSpanBuilder>::new())
+            .wrap(from_fn(propagate_request_context))
             .route("/", web::get().to(home))
             .service(
                 web::scope("/admin")
+                    // Anonymous users are sent to the login page before
+                    // their CSRF token is looked at.
+                    .wrap(from_fn(record_admin_activity))
+                    .wrap(from_fn(protect_against_csrf))
                     .wrap(from_fn(reject_anonymous_users))
+                    .wrap(from_fn(reject_disallowed_admin_clients))
+                    .configure(chaos_routes)
                     .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/deliverability/dns", web::get().to(check_dns_records))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
                     .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters/check-links",
+                        web::post().to(check_newsletter_links),
+                    )
+                    .route(
+                        "/newsletters/spam-check",
+                        web::post().to(check_newsletter_spam),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/clone",
+                        web::post().to(clone_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/save-as-template",
+                        web::post().to(save_issue_template),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/resume",
+                        web::post().to(resume_newsletter_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/report",
+                        web::get().to(newsletter_issue_report),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/variants",
+                        web::post().to(attach_issue_variant),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/seeds",
+                        web::post().to(report_seed_placement),
+                    )
+                    .route("/operations", web::get().to(admin_operations))
+                    .route("/operations", web::post().to(start_admin_operation))
+                    .route(
+                        "/operations/{operation_id}/resume",
+                        web::post().to(resume_admin_operation),
+                    )
+                    .route(
+                        "/operations/{operation_id}/roll-back",
+                        web::post().to(roll_back_admin_operation),
+                    )
+                    .route("/quarantine", web::get().to(quarantine_queue))
+                    .route("/quarantine", web::post().to(review_quarantine))
+                    .route("/queues", web::get().to(queue_stats))
+                    .route("/queues/{name}/retry-failed", web::post().to(retry_failed))
+                    .route("/quota", web::get().to(send_quota_usage))
+                    .route("/api-keys/{key_id}/usage", web::get().to(api_key_usage))
+                    .route("/audit/analytics", web::get().to(audit_analytics))
+                    .route("/referrals", web::get().to(referral_leaderboard))
+                    .route("/retention", web::get().to(retention_policy))
+                    .route("/sessions", web::get().to(admin_sessions))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(revoke_other_admin_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(revoke_admin_session),
+                    )
+                    .route("/settings/reload", web::post().to(reload_settings))
+                    .route("/sms", web::get().to(sms_blast_form))
+                    .route("/sms", web::post().to(send_sms_blast))
+                    .route("/snippets", web::get().to(snippet_library))
+                    .route("/snippets", web::post().to(save_snippet_version))
+                    .route("/sponsors/{sponsor}/report", web::get().to(sponsor_report))
+                    .route(
+                        "/subscribers/{subscriber_id}/consent",
+                        web::get().to(subscriber_consent),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/restore",
+                        web::post().to(restore_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/reset-links",
+                        web::post().to(reset_subscriber_links),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/merge",
+                        web::post().to(merge_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/view-as",
+                        web::get().to(view_as_subscriber),
+                    )
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/logout", web::post().to(log_out))
+                    .route("/notifications", web::get().to(admin_notifications)),
+            )
+            .service(
+                web::scope("/scim/v2")
+                    .wrap(from_fn(replay_idempotent_requests))
+                    .wrap(from_fn(reject_invalid_scim_token))
+                    .route("/Users", web::get().to(scim_list_users))
+                    .route("/Users", web::post().to(scim_create_user))
+                    .route("/Users/{user_id}", web::get().to(scim_get_user))
+                    .route("/Users/{user_id}", web::patch().to(scim_patch_user)),
             )
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/magic-link", web::post().to(request_login_link))
+            .route("/login/oidc", web::get().to(oidc_login))
+            .route("/login/oidc/callback", web::get().to(oidc_callback))
+            .route("/login/magic-link/confirm", web::get().to(login_link_form))
+            .route(
+                "/login/magic-link/confirm",
+                web::post().to(confirm_login_link),
+            )
             .route("/health_check", web::get().to(health_check))
+            .route("/metrics", web::get().to(metrics))
             .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/confirm", web::get().to(confirm_form))
+            .route("/subscriptions/confirm", web::post().to(confirm))
+            .route("/subscriptions/deliveries", web::get().to(delivery_status))
+            .route(
+                "/subscriptions/deliveries/resend",
+                web::post().to(resend_latest_issue),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(subscriber_preferences),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_subscriber_preferences),
+            )
+            .route(
+                "/subscriptions/push",
+                web::post().to(register_push_subscription),
+            )
+            .route("/subscriptions/sms", web::get().to(sms_preferences))
+            .route("/subscriptions/sms", web::post().to(update_sms_preferences))
+            .route(
+                "/subscriptions/sms/phone",
+                web::post().to(provide_phone_number),
+            )
+            .route(
+                "/subscriptions/sms/phone/verify",
+                web::post().to(verify_phone_number),
+            )
+            .route("/push-worker.js", web::get().to(push_service_worker))
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/l/{slug}", web::get().to(hosted_signup_page))
+            .route("/archive", web::get().to(archive_index))
+            .route("/archive/images", web::get().to(archive_image))
+            .route("/archive/login", web::post().to(request_archive_link))
+            .route("/archive/login/confirm", web::get().to(archive_link_form))
+            .route(
+                "/archive/login/confirm",
+                web::post().to(confirm_archive_link),
+            )
+            .route("/archive/{issue_id}", web::get().to(archive_issue))
+            .route("/p/{poll_id}", web::get().to(poll_results))
+            .route("/r/{referral_code}", web::get().to(referral_signup_page))
+            .route("/p/{poll_id}/{option}", web::get().to(poll_vote))
+            .route("/sponsors/{slot_id}/click", web::get().to(sponsor_click))
+            .route(
+                "/sponsors/{slot_id}/impression.gif",
+                web::get().to(sponsor_impression),
+            )
+            .route("/billing/checkout", web::post().to(start_checkout))
+            .route("/webhooks/stripe", web::post().to(stripe_webhook))
+            .route(
+                "/webhooks/seed-placements",
+                web::post().to(seed_placement_webhook),
+            )
+            .route(
+                "/webhooks/delivery-events",
+                web::post().to(delivery_event_webhook),
+            )
+            // Ahead of `/api`: the website calls it without an API key.
+            .service(
+                web::scope("/api/analytics")
+                    .wrap(from_fn(allow_landing_origins))
+                    .route("/visit", web::post().to(record_landing_visit))
+                    .route(
+                        "/visit",
+                        web::method(Method::OPTIONS).to(landing_visit_preflight),
+                    ),
+            )
+            .service(
+                web::scope("/api")
+                    .wrap(from_fn(replay_idempotent_requests))
+                    .wrap(from_fn(limit_api_key_rate))
+                    .wrap(from_fn(reject_invalid_api_key))
+                    .route("/verify-email", web::post().to(verify_email)),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(event_bus.clone())
             .app_data(base_url.clone())
-            .app_data(Data::new(HmacSecret(hmac_secret.clone())))
-    })
-    .listen(listener)?
+            .app_data(admin_events.clone())
+            .app_data(dns_checker.clone())
+            .app_data(magic_links.clone())
+            .app_data(subscription_tokens.clone())
+            .app_data(login_settings.clone())
+            .app_data(link_checker.clone())
+            .app_data(settings.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(slo_tracker.clone())
+            .app_data(load_shedder.clone())
+            .app_data(image_proxy.clone())
+            .app_data(email_verifier.clone())
+            .app_data(subscriber_redirects.clone())
+            .app_data(signup_pages.clone())
+            .app_data(http_cache.clone())
+            .app_data(theme.clone())
+            .app_data(pii.clone())
+            .app_data(consent.clone())
+            .app_data(resends.clone())
+            .app_data(idempotency.clone())
+            .app_data(i18n.clone())
+            .app_data(retention.clone())
+            .app_data(web_version.clone())
+            .app_data(poll_links.clone())
+            .app_data(Data::new(HmacSecret(hmac_secret.clone())));
+        if let Some(stripe) = &stripe {
+            app = app.app_data(stripe.clone());
+        }
+        if let Some(web_push) = &web_push {
+            app = app.app_data(web_push.clone());
+        }
+        if let Some(sms) = &sms {
+            app = app.app_data(sms.clone());
+        }
+        if let Some(sms_provider) = &sms_provider {
+            app = app.app_data(sms_provider.clone());
+        }
+        if let Some(telegram) = &telegram {
+            app = app.app_data(telegram.clone());
+        }
+        if let Some(admin_allowlist) = &admin_allowlist {
+            app = app.app_data(admin_allowlist.clone());
+        }
+        if let Some(oidc) = &oidc {
+            app = app.app_data(oidc.clone());
+        }
+        if let Some(scim) = &scim {
+            app = app.app_data(scim.clone());
+        }
+        if let Some(seed_list) = &seed_list {
+            app = app.app_data(seed_list.clone());
+        }
+        if let Some(delivery_events) = &delivery_events {
+            app = app.app_data(delivery_events.clone());
+        }
+        if let Some(api_keys) = &api_keys {
+            app = app.app_data(api_keys.clone());
+        }
+        if let Some(spam_check) = &spam_check {
+            app = app.app_data(spam_check.clone());
+        }
+        if let Some(signup_protection) = &signup_protection {
+            app = app.app_data(signup_protection.clone());
+        }
+        if let Some(landing_links) = &landing_links {
+            app = app.app_data(landing_links.clone());
+        }
+        app
+    });
+    let server = match http_workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    let server = match listener {
+        Listener::Tcp(listener) => server.listen(listener),
+        Listener::Unix(listener) => server.listen_uds(listener),
+    }
+    .map_err(|source| StartupError::Listen { address, source })?
     .run();
     Ok(server)
 }
 
+/// The fault injection endpoint, only built with the `chaos` feature and
+/// never mounted outside the local environment.
+#[cfg_attr(not(feature = "chaos"), allow(unused_variables))]
+fn chaos_routes(cfg: &mut web::ServiceConfig) {
+    #[cfg(feature = "chaos")]
+    if crate::chaos::allowed() {
+        cfg.route("/chaos", web::get().to(crate::routes::chaos_faults))
+            .route("/chaos", web::post().to(crate::routes::set_chaos_faults));
+    }
+}
+
+/// Surface delivery, latency, signup and background job problems on the
+/// admin notifications channel.
+fn forward_admin_events(event_bus: &EventBus, admin_events: AdminEventBroadcaster) {
+    event_bus.spawn_subscriber("admin_events", move |event| {
+        match event {
+            DomainEvent::FailureRateExceeded {
+                issue_id,
+                failure_rate,
+            } => admin_events.publish(AdminEvent::BounceSpike {
+                issue_id,
+                bounce_rate: failure_rate,
+            }),
+            DomainEvent::SloBudgetBurning { route, burn_rate } => {
+                admin_events.publish(AdminEvent::SloBurn { route, burn_rate })
+            }
+            DomainEvent::SignupSpikeDetected {
+                source, signups, ..
+            } => admin_events.publish(AdminEvent::SignupSpike { source, signups }),
+            DomainEvent::JobFailed { job, error } => {
+                admin_events.publish(AdminEvent::JobFailure { job, error })
+            }
+            _ => {}
+        }
+        std::future::ready(())
+    });
+}
+
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
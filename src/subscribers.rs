@@ -0,0 +1,519 @@
+use crate::configuration::{
+    DataRetentionSettings, IdempotencySettings, Settings, SubscriberRetentionSettings,
+};
+use crate::database::ObserveQuery;
+use crate::idempotency::delete_expired_responses;
+use crate::pii::PiiCipher;
+use crate::retention::{enforce_retention, RetentionOutcome};
+use crate::startup::get_connection_pool;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The id of the active, confirmed subscriber behind `email`, stored in clear
+/// or sealed.
+#[tracing::instrument(skip(pool, pii))]
+pub async fn get_confirmed_subscriber_id(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    email: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE (email = $1 OR email_index = $2) AND status = 'confirmed' AND deleted_at IS NULL
+        "#,
+        email,
+        pii.email_index(email)
+    )
+    .fetch_optional(pool)
+    .observe("get_confirmed_subscriber_id")
+    .await?;
+    Ok(r.map(|r| r.id))
+}
+
+/// Whether the subscriber is active and on the paid tier.
+#[tracing::instrument(skip(pool))]
+pub async fn is_paid_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT paid FROM subscriptions
+        WHERE id = $1 AND status = 'confirmed' AND deleted_at IS NULL
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .observe("is_paid_subscriber")
+    .await?;
+    Ok(r.is_some_and(|r| r.paid))
+}
+
+/// Hide a subscriber from every delivery until it is restored or purged.
+#[tracing::instrument(name = "Soft delete a subscriber", skip(executor))]
+pub async fn soft_delete_subscriber<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET deleted_at = now()
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .observe("soft_delete_subscriber")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Restore a deleted subscriber", skip(executor))]
+pub async fn restore_subscriber<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET deleted_at = NULL
+        WHERE id = $1 AND deleted_at IS NOT NULL
+        "#,
+        subscriber_id
+    )
+    .execute(executor)
+    .observe("restore_subscriber")
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MergeOutcome {
+    Merged,
+    /// Either subscriber doesn't exist, or the survivor is deleted.
+    NotFound,
+    SameSubscriber,
+    /// Both subscribers pay: one of the subscriptions has to be cancelled
+    /// with the billing provider first.
+    BothPaying,
+}
+
+/// Fold `duplicate_id` into `survivor_id`, e.g. when somebody subscribed
+/// twice under an old plus-address. The survivor inherits the delivery
+/// history, the pending deliveries, the confirmation, the consent records,
+/// the poll votes, the referrals, the browsers, the phone number and the
+/// landing page visits of the duplicate, which is deleted and whose address
+/// is suppressed. The billing of whichever of the two pays moves along with
+/// it; two paying subscribers are not merged.
+#[tracing::instrument(name = "Merge subscribers", skip(pool, pii))]
+pub async fn merge_subscribers(
+    pool: &PgPool,
+    pii: &PiiCipher,
+    survivor_id: Uuid,
+    duplicate_id: Uuid,
+) -> Result<MergeOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let outcome = merge_subscribers_in(&mut transaction, pii, survivor_id, duplicate_id).await?;
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+struct MergedSubscriber {
+    id: Uuid,
+    email: String,
+    status: String,
+    paid: bool,
+    stripe_customer_id: Option<String>,
+    subscribed_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+impl MergedSubscriber {
+    fn is_paying(&self) -> bool {
+        self.paid || self.stripe_customer_id.is_some()
+    }
+}
+
+/// `merge_subscribers`, within `transaction`: nothing is written unless the
+/// outcome is `Merged`.
+pub async fn merge_subscribers_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    pii: &PiiCipher,
+    survivor_id: Uuid,
+    duplicate_id: Uuid,
+) -> Result<MergeOutcome, anyhow::Error> {
+    if survivor_id == duplicate_id {
+        return Ok(MergeOutcome::SameSubscriber);
+    }
+    let rows = sqlx::query_as!(
+        MergedSubscriber,
+        r#"
+        SELECT id, email, status, paid, stripe_customer_id, subscribed_at, deleted_at
+        FROM subscriptions
+        WHERE id = $1 OR id = $2
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        survivor_id,
+        duplicate_id
+    )
+    .fetch_all(&mut **transaction)
+    .observe("lock_subscribers_to_merge")
+    .await?;
+    let (Some(survivor), Some(duplicate)) = (
+        rows.iter().find(|r| r.id == survivor_id),
+        rows.iter().find(|r| r.id == duplicate_id),
+    ) else {
+        return Ok(MergeOutcome::NotFound);
+    };
+    if survivor.deleted_at.is_some() {
+        return Ok(MergeOutcome::NotFound);
+    }
+    if survivor.is_paying() && duplicate.is_paying() {
+        return Ok(MergeOutcome::BothPaying);
+    }
+    let billing = if duplicate.is_paying() {
+        duplicate
+    } else {
+        survivor
+    };
+    let status = if survivor.status == "confirmed" || duplicate.status == "confirmed" {
+        "confirmed"
+    } else {
+        survivor.status.as_str()
+    };
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2, paid = $3, stripe_customer_id = $4, subscribed_at = $5
+        WHERE id = $1
+        "#,
+        survivor_id,
+        status,
+        billing.paid,
+        billing.stripe_customer_id,
+        survivor.subscribed_at.min(duplicate.subscribed_at)
+    )
+    .execute(&mut **transaction)
+    .observe("merge_subscriber_profile")
+    .await?;
+    sqlx::query!(
+        "UPDATE email_deliveries SET subscriber_email = $2 WHERE subscriber_email = $1",
+        duplicate.email,
+        survivor.email
+    )
+    .execute(&mut **transaction)
+    .observe("merge_email_deliveries")
+    .await?;
+    // A pending delivery moves over unless the survivor already gets the
+    // same issue: nobody receives an issue twice.
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue q
+        SET subscriber_email = $2
+        WHERE q.subscriber_email = $1 AND NOT EXISTS (
+            SELECT 1 FROM issue_delivery_queue s
+            WHERE s.newsletter_issue_id = q.newsletter_issue_id AND s.subscriber_email = $2
+        )
+        "#,
+        duplicate.email,
+        survivor.email
+    )
+    .execute(&mut **transaction)
+    .observe("merge_issue_delivery_queue")
+    .await?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        duplicate.email
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_deliveries")
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE recipient_snapshot_members m
+        SET subscriber_email = $2
+        WHERE m.subscriber_email = $1 AND NOT EXISTS (
+            SELECT 1 FROM recipient_snapshot_members s
+            WHERE s.snapshot_id = m.snapshot_id AND s.subscriber_email = $2
+        )
+        "#,
+        duplicate.email,
+        survivor.email
+    )
+    .execute(&mut **transaction)
+    .observe("merge_recipient_snapshot_members")
+    .await?;
+    sqlx::query!(
+        "DELETE FROM recipient_snapshot_members WHERE subscriber_email = $1",
+        duplicate.email
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_snapshot_members")
+    .await?;
+    sqlx::query!(
+        "UPDATE magic_links SET subject_id = $2 WHERE subject_id = $1 AND purpose = 'archive'",
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_archive_links")
+    .await?;
+    sqlx::query!(
+        "UPDATE consent_records SET subscriber_id = $2 WHERE subscriber_id = $1",
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_consent_records")
+    .await?;
+    // A poll counts one vote per subscriber, and a milestone is rewarded
+    // once: the survivor's own are kept.
+    sqlx::query!(
+        r#"
+        UPDATE poll_votes v
+        SET subscriber_id = $2
+        WHERE v.subscriber_id = $1 AND NOT EXISTS (
+            SELECT 1 FROM poll_votes s WHERE s.poll_id = v.poll_id AND s.subscriber_id = $2
+        )
+        "#,
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_poll_votes")
+    .await?;
+    sqlx::query!(
+        "DELETE FROM poll_votes WHERE subscriber_id = $1",
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_poll_votes")
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE referral_rewards r
+        SET subscriber_id = $2
+        WHERE r.subscriber_id = $1 AND NOT EXISTS (
+            SELECT 1 FROM referral_rewards s
+            WHERE s.milestone = r.milestone AND s.subscriber_id = $2
+        )
+        "#,
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_referral_rewards")
+    .await?;
+    sqlx::query!(
+        "DELETE FROM referral_rewards WHERE subscriber_id = $1",
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_referral_rewards")
+    .await?;
+    // Nobody is credited with referring themselves.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET referred_by = CASE WHEN id = $2 THEN NULL ELSE $2 END
+        WHERE referred_by = $1
+        "#,
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_referrals")
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET referred_by = d.referred_by
+        FROM subscriptions d
+        WHERE s.id = $1 AND d.id = $2 AND s.referred_by IS NULL AND d.referred_by <> $1
+        "#,
+        survivor_id,
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_referrer")
+    .await?;
+    sqlx::query!(
+        "UPDATE push_subscriptions SET subscriber_id = $2 WHERE subscriber_id = $1",
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_push_subscriptions")
+    .await?;
+    sqlx::query!(
+        "UPDATE landing_visits SET subscriber_id = $2 WHERE subscriber_id = $1",
+        duplicate_id,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_landing_visits")
+    .await?;
+    // An opt-out of tracking on either side holds for the merged subscriber.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET no_tracking = true
+        WHERE id = $1 AND EXISTS (SELECT 1 FROM subscriptions WHERE id = $2 AND no_tracking)
+        "#,
+        survivor_id,
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_no_tracking")
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM landing_visits v USING subscriptions s
+        WHERE v.subscriber_id = s.id AND s.id = $1 AND s.no_tracking
+        "#,
+        survivor_id
+    )
+    .execute(&mut **transaction)
+    .observe("forget_untracked_landing_visits")
+    .await?;
+    // The survivor keeps their own number, if they gave one.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET phone_number = d.phone_number,
+            phone_country = d.phone_country,
+            phone_verified_at = d.phone_verified_at,
+            sms_opted_in_at = d.sms_opted_in_at
+        FROM subscriptions d
+        WHERE s.id = $1 AND d.id = $2 AND s.phone_number IS NULL
+        "#,
+        survivor_id,
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("merge_phone_number")
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_subscription_tokens")
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET deleted_at = COALESCE(deleted_at, now()), paid = false, stripe_customer_id = NULL,
+            referred_by = NULL, phone_number = NULL, phone_country = NULL,
+            phone_verified_at = NULL, sms_opted_in_at = NULL
+        WHERE id = $1
+        "#,
+        duplicate_id
+    )
+    .execute(&mut **transaction)
+    .observe("delete_duplicate_subscriber")
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, suppressed_at)
+        VALUES ($1, 'merged', now())
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        pii.open_email(&duplicate.email)?
+    )
+    .execute(&mut **transaction)
+    .observe("suppress_duplicate_email")
+    .await?;
+    Ok(MergeOutcome::Merged)
+}
+
+/// Permanently remove the subscribers deleted before `deleted_before`.
+#[tracing::instrument(name = "Purge deleted subscribers", skip(pool))]
+pub async fn purge_deleted_subscribers(
+    pool: &PgPool,
+    deleted_before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE subscriber_id IN (
+            SELECT id FROM subscriptions WHERE deleted_at < $1
+        )
+        "#,
+        deleted_before
+    )
+    .execute(&mut *transaction)
+    .observe("purge_subscription_tokens")
+    .await?;
+    let result = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE deleted_at < $1"#,
+        deleted_before
+    )
+    .execute(&mut *transaction)
+    .observe("purge_deleted_subscribers")
+    .await?;
+    transaction.commit().await?;
+    Ok(result.rows_affected())
+}
+
+async fn purge_loop(
+    pool: PgPool,
+    settings: SubscriberRetentionSettings,
+    data_retention: DataRetentionSettings,
+    idempotency: IdempotencySettings,
+) -> Result<(), anyhow::Error> {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        settings.purge_interval_seconds,
+    ));
+    loop {
+        interval.tick().await;
+        let deleted_before = Utc::now() - Duration::days(settings.retention_days.into());
+        match purge_deleted_subscribers(&pool, deleted_before).await {
+            Ok(purged) if purged > 0 => tracing::info!(purged, "Purged deleted subscribers"),
+            Ok(_) => {}
+            // The next tick will try again.
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to purge deleted subscribers"
+            ),
+        }
+        match enforce_retention(&pool, &data_retention, Utc::now()).await {
+            Ok(outcome) if outcome != RetentionOutcome::default() => {
+                tracing::info!(?outcome, "Removed data past its retention window")
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to enforce the data retention windows"
+            ),
+        }
+        let saved_before = Utc::now() - Duration::hours(idempotency.retention_hours.into());
+        if let Err(e) = delete_expired_responses(&pool, saved_before).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to delete the expired idempotent responses"
+            );
+        }
+    }
+}
+
+pub async fn run_purge_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database).await?;
+    purge_loop(
+        pool,
+        configuration.subscriber_retention,
+        configuration.data_retention,
+        configuration.idempotency,
+    )
+    .await
+}
@@ -0,0 +1,343 @@
+//! Each published issue mirrored to a Telegram channel: its title, the
+//! start of its text and a link to the archive, posted by a bot through the
+//! Bot API.
+//!
+//! Publishing an issue queues its post in `telegram_posts`, in the same
+//! transaction. The mirror posts them, retrying the failures with an
+//! exponential backoff, up to the configured number of attempts.
+use crate::configuration::{Settings, TelegramSettings};
+use crate::database::ObserveQuery;
+use crate::links;
+use crate::startup::{get_connection_pool, StartupError};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The summary is cut around there, at a word boundary.
+const SUMMARY_LENGTH: usize = 280;
+const FIRST_RETRY_DELAY_SECONDS: i64 = 30;
+const MAX_RETRY_DELAY_SECONDS: i64 = 3600;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TelegramError {
+    #[error("Telegram asked us to retry after {0:?}")]
+    RetryAfter(Duration),
+    #[error("Telegram rejected the post: {0}")]
+    Api(String),
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+pub struct TelegramClient {
+    http_client: Client,
+    base_url: String,
+    bot_token: Secret<String>,
+    chat_id: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    result: Option<SentMessage>,
+    description: Option<String>,
+    parameters: Option<ResponseParameters>,
+}
+
+#[derive(serde::Deserialize)]
+struct SentMessage {
+    message_id: i64,
+}
+
+#[derive(serde::Deserialize)]
+struct ResponseParameters {
+    retry_after: Option<u64>,
+}
+
+impl TelegramClient {
+    pub fn new(settings: &TelegramSettings) -> Result<Self, StartupError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .build()
+            .map_err(|e| StartupError::HttpClient("Telegram client", e))?;
+        Ok(Self {
+            http_client,
+            base_url: settings.base_url.clone(),
+            bot_token: settings.bot_token.clone(),
+            chat_id: settings.chat_id.clone(),
+        })
+    }
+
+    /// Post `text`, formatted as Telegram's HTML subset, to the channel.
+    /// Returns the id of the message.
+    ///
+    /// The errors leave the URL out: it holds the bot token.
+    pub async fn send_message(&self, text: &str) -> Result<i64, TelegramError> {
+        let response = self
+            .http_client
+            .post(format!(
+                "{}/bot{}/sendMessage",
+                self.base_url,
+                self.bot_token.expose_secret()
+            ))
+            .json(&serde_json::json!({
+                "chat_id": self.chat_id,
+                "text": text,
+                "parse_mode": "HTML",
+            }))
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        // The Bot API describes its errors in the body, whatever the status.
+        let body: ApiResponse = response.json().await.map_err(reqwest::Error::without_url)?;
+        match body {
+            ApiResponse {
+                ok: true,
+                result: Some(message),
+                ..
+            } => Ok(message.message_id),
+            ApiResponse {
+                parameters:
+                    Some(ResponseParameters {
+                        retry_after: Some(seconds),
+                    }),
+                ..
+            } if status == StatusCode::TOO_MANY_REQUESTS => {
+                Err(TelegramError::RetryAfter(Duration::from_secs(seconds)))
+            }
+            ApiResponse { description, .. } => Err(TelegramError::Api(
+                description.unwrap_or_else(|| status.to_string()),
+            )),
+        }
+    }
+}
+
+/// Escape the characters Telegram's HTML reserves.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The start of the plain text of an issue, on a single line.
+fn summarize(text_content: &str) -> String {
+    let text = text_content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.chars().count() <= SUMMARY_LENGTH {
+        return text;
+    }
+    let cut: String = text.chars().take(SUMMARY_LENGTH).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) => &cut[..space],
+        None => &cut,
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// The post of an issue: its title in bold, a summary, and the link to
+/// read the rest in the archive.
+pub fn format_issue_post(title: &str, text_content: &str, archive_url: &str) -> String {
+    format!(
+        "<b>{}</b>\n\n{}\n\n<a href=\"{}\">Read the issue</a>",
+        escape(title),
+        escape(&summarize(text_content)),
+        escape(archive_url)
+    )
+}
+
+/// Queue the post of a newly published issue.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_telegram_post(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO telegram_posts (newsletter_issue_id, created_at, next_attempt_at)
+        VALUES ($1, now(), now())
+        "#,
+        issue_id
+    )
+    .execute(&mut **transaction)
+    .observe("enqueue_telegram_post")
+    .await?;
+    Ok(())
+}
+
+/// How long to wait before the attempt following `attempts` failed ones.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+    let seconds = FIRST_RETRY_DELAY_SECONDS
+        .saturating_mul(1 << (attempts - 1).clamp(0, 16))
+        .min(MAX_RETRY_DELAY_SECONDS);
+    chrono::Duration::seconds(seconds)
+}
+
+pub enum PostOutcome {
+    Posted,
+    Failed,
+    EmptyQueue,
+}
+
+/// Post the next issue due, if any.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_post_next(
+    pool: &PgPool,
+    client: &TelegramClient,
+    base_url: &str,
+    max_attempts: i32,
+) -> Result<PostOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let post = sqlx::query!(
+        r#"
+        SELECT p.newsletter_issue_id, p.attempts, i.title, i.text_content
+        FROM telegram_posts p
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE p.posted_at IS NULL AND p.attempts < $1 AND p.next_attempt_at <= now()
+        ORDER BY p.next_attempt_at
+        FOR UPDATE OF p SKIP LOCKED
+        LIMIT 1
+        "#,
+        max_attempts
+    )
+    .fetch_optional(&mut *transaction)
+    .observe("dequeue_telegram_post")
+    .await?;
+    let Some(post) = post else {
+        return Ok(PostOutcome::EmptyQueue);
+    };
+    let archive_url = links::issue_web_version(post.newsletter_issue_id, None).absolute(base_url);
+    let text = format_issue_post(&post.title, &post.text_content, &archive_url);
+    let outcome = match client.send_message(&text).await {
+        Ok(message_id) => {
+            sqlx::query!(
+                r#"
+                UPDATE telegram_posts SET posted_at = now(), message_id = $2
+                WHERE newsletter_issue_id = $1
+                "#,
+                post.newsletter_issue_id,
+                message_id
+            )
+            .execute(&mut *transaction)
+            .observe("mark_telegram_post_posted")
+            .await?;
+            PostOutcome::Posted
+        }
+        // Being throttled is no failure of the post.
+        Err(TelegramError::RetryAfter(retry_after)) => {
+            sqlx::query!(
+                "UPDATE telegram_posts SET next_attempt_at = $2 WHERE newsletter_issue_id = $1",
+                post.newsletter_issue_id,
+                Utc::now() + chrono::Duration::from_std(retry_after)?
+            )
+            .execute(&mut *transaction)
+            .observe("throttle_telegram_post")
+            .await?;
+            PostOutcome::Failed
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                issue_id = %post.newsletter_issue_id,
+                "Failed to post an issue to Telegram"
+            );
+            let attempts = post.attempts + 1;
+            sqlx::query!(
+                r#"
+                UPDATE telegram_posts
+                SET attempts = $2, last_error = $3, next_attempt_at = $4
+                WHERE newsletter_issue_id = $1
+                "#,
+                post.newsletter_issue_id,
+                attempts,
+                e.to_string(),
+                Utc::now() + retry_delay(attempts)
+            )
+            .execute(&mut *transaction)
+            .observe("record_telegram_post_failure")
+            .await?;
+            PostOutcome::Failed
+        }
+    };
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+async fn mirror_loop(
+    pool: PgPool,
+    client: TelegramClient,
+    base_url: String,
+    max_attempts: i32,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_post_next(&pool, &client, &base_url, max_attempts).await {
+            Ok(PostOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Ok(PostOutcome::Failed) | Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(PostOutcome::Posted) => {}
+        }
+    }
+}
+
+/// Post the published issues to Telegram until the process is stopped.
+///
+/// Never resolves if the Telegram mirror is not configured.
+pub async fn run_telegram_mirror_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let Some(settings) = configuration.telegram else {
+        return std::future::pending().await;
+    };
+    let connection_pool = get_connection_pool(&configuration.database).await?;
+    let client = TelegramClient::new(&settings)?;
+    mirror_loop(
+        connection_pool,
+        client,
+        configuration.application.base_url,
+        settings.max_attempts,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_issue_post, retry_delay, summarize};
+
+    #[test]
+    fn posts_escape_the_issue_content() {
+        assert_eq!(
+            format_issue_post(
+                "Rust <3 & friends",
+                "Hello,\n\n  <b>world</b>!",
+                "https://example.com/archive/1?a=1&b=2"
+            ),
+            "<b>Rust &lt;3 &amp; friends</b>\n\nHello, &lt;b&gt;world&lt;/b&gt;!\n\n\
+             <a href=\"https://example.com/archive/1?a=1&amp;b=2\">Read the issue</a>"
+        );
+    }
+
+    #[test]
+    fn long_issues_are_summarized_at_a_word_boundary() {
+        let text = "word, ".repeat(100);
+        let summary = summarize(&text);
+        assert!(summary.chars().count() <= 281);
+        assert!(summary.ends_with("word…"));
+        assert_eq!(summarize("A short issue."), "A short issue.");
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_delay(1).num_seconds(), 30);
+        assert_eq!(retry_delay(2).num_seconds(), 60);
+        assert_eq!(retry_delay(4).num_seconds(), 240);
+        assert_eq!(retry_delay(20).num_seconds(), 3600);
+    }
+}
@@ -1,10 +1,15 @@
 use actix_web::rt::task::JoinHandle;
+use anyhow::Context;
+use std::sync::OnceLock;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Registry};
+
+/// Lets `reload_log_filter` swap the filter of the first subscriber we built.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
@@ -22,6 +27,8 @@ where
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    let _ = LOG_FILTER.set(handle);
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
     Registry::default()
         .with(env_filter)
@@ -37,6 +44,23 @@ pub fn init_subscriber(subscriber: impl Subscriber + Sync + Send) {
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
 
+/// Replace the log filter with `directives` (e.g. `info,sqlx=warn`).
+///
+/// `RUST_LOG`, when set, keeps precedence over the configuration, as it does
+/// at startup.
+pub fn reload_log_filter(directives: &str) -> Result<(), anyhow::Error> {
+    let filter = EnvFilter::try_new(directives).context("Invalid log level")?;
+    if std::env::var_os("RUST_LOG").is_some() {
+        return Ok(());
+    }
+    if let Some(handle) = LOG_FILTER.get() {
+        handle
+            .reload(filter)
+            .context("Failed to swap the log filter")?;
+    }
+    Ok(())
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
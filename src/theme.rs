@@ -0,0 +1,264 @@
+//! The look of the public pages: signup, confirmation and archive.
+//!
+//! Every page is laid out by a template with `{{placeholders}}`. The
+//! built-in layout can be replaced by files in `template_directory`: a
+//! `<page>.html` template for a single page, or `layout.html` for all of
+//! them.
+use crate::configuration::ThemeSettings;
+use crate::i18n::Language;
+use htmlescape::encode_minimal;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The public pages, named after their template file.
+pub const PAGES: [&str; 9] = [
+    "signup",
+    "confirm",
+    "confirmed",
+    "archive_index",
+    "archive_issue",
+    "archive_locked",
+    "archive_login",
+    "delivery_status",
+    "poll",
+];
+
+const DEFAULT_LAYOUT: &str = r#"<!DOCTYPE html>
+<html lang="{{lang}}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{{title}}</title>
+    <style>{{style}}</style>
+</head>
+<body>
+    {{logo}}
+    {{content}}
+</body>
+</html>"#;
+
+/// CSS able to load or run code, dropped from custom snippets.
+const FORBIDDEN_CSS: [&str; 5] = [
+    "@import",
+    "expression(",
+    "javascript:",
+    "behavior:",
+    "-moz-binding",
+];
+
+pub fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Keep a custom CSS snippet inside its `<style>` element and away from
+/// scripts and remote stylesheets.
+pub fn sanitize_css(css: &str) -> String {
+    let mut sanitized = css.replace('<', "");
+    for forbidden in FORBIDDEN_CSS {
+        while let Some(index) = sanitized.to_ascii_lowercase().find(forbidden) {
+            sanitized.replace_range(index..index + forbidden.len(), "");
+        }
+    }
+    sanitized
+}
+
+/// What a page is rendered with. `title` and `content` are HTML.
+pub struct Page<'a> {
+    pub name: &'static str,
+    pub title: &'a str,
+    pub content: &'a str,
+    /// Branding that overrides the theme's for this page, e.g. a signup
+    /// page's.
+    pub accent_color: Option<&'a str>,
+    pub logo_url: Option<&'a str>,
+    pub language: Language,
+}
+
+impl<'a> Page<'a> {
+    /// A page with the theme's branding.
+    pub fn new(name: &'static str, title: &'a str, content: &'a str) -> Self {
+        Self {
+            name,
+            title,
+            content,
+            accent_color: None,
+            logo_url: None,
+            language: Language::English,
+        }
+    }
+}
+
+pub struct Theme {
+    accent_color: String,
+    logo_url: Option<String>,
+    custom_css: String,
+    templates: HashMap<&'static str, String>,
+    layout: String,
+}
+
+impl Theme {
+    pub fn new(settings: &ThemeSettings) -> Result<Self, String> {
+        if !is_hex_color(&settings.accent_color) {
+            return Err(format!("{} is not a #rrggbb color", settings.accent_color));
+        }
+        if let Some(logo_url) = &settings.logo_url {
+            if !logo_url.starts_with("https://") {
+                return Err("the logo must be an https URL".into());
+            }
+        }
+        let mut templates = HashMap::new();
+        let mut layout = DEFAULT_LAYOUT.to_owned();
+        if let Some(directory) = &settings.template_directory {
+            let directory = Path::new(directory);
+            if !directory.is_dir() {
+                return Err(format!("{} is not a directory", directory.display()));
+            }
+            let read = |name: &str| {
+                let path = directory.join(format!("{}.html", name));
+                path.exists()
+                    .then(|| std::fs::read_to_string(&path))
+                    .transpose()
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))
+            };
+            if let Some(custom_layout) = read("layout")? {
+                layout = custom_layout;
+            }
+            for name in PAGES {
+                if let Some(template) = read(name)? {
+                    templates.insert(name, template);
+                }
+            }
+        }
+        Ok(Self {
+            accent_color: settings.accent_color.clone(),
+            logo_url: settings.logo_url.clone(),
+            custom_css: sanitize_css(&settings.custom_css),
+            templates,
+            layout,
+        })
+    }
+
+    pub fn render(&self, page: Page<'_>) -> String {
+        let accent_color = page.accent_color.unwrap_or(&self.accent_color);
+        let style = format!(
+            "h1 {{ color: {accent_color}; }}\n\
+             button {{ background: {accent_color}; color: white; border: none; padding: 0.5em 1em; }}\n\
+             {}",
+            self.custom_css
+        );
+        let logo = page
+            .logo_url
+            .or(self.logo_url.as_deref())
+            .map(|url| format!(r#"<img src="{}" alt="" height="64">"#, encode_minimal(url)))
+            .unwrap_or_default();
+        let template = self.templates.get(page.name).unwrap_or(&self.layout);
+        fill(
+            template,
+            &[
+                ("lang", page.language.as_str()),
+                ("title", page.title),
+                ("style", &style),
+                ("logo", &logo),
+                ("accent_color", accent_color),
+                ("content", page.content),
+            ],
+        )
+    }
+}
+
+/// Replace the `{{name}}` placeholders of `template` in a single pass, so
+/// that a value containing a placeholder is left as it is. Unknown
+/// placeholders are kept.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = after[..end].trim();
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                rendered.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                rendered.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, sanitize_css, Page, Theme};
+    use crate::configuration::ThemeSettings;
+    use crate::i18n::Language;
+
+    fn settings() -> ThemeSettings {
+        ThemeSettings {
+            accent_color: "#112233".into(),
+            logo_url: None,
+            custom_css: String::new(),
+            template_directory: None,
+        }
+    }
+
+    #[test]
+    fn css_cannot_escape_its_style_element_or_load_anything() {
+        assert_eq!(
+            sanitize_css("p { color: red; }</style><script>"),
+            "p { color: red; }/style>script>"
+        );
+        assert_eq!(
+            sanitize_css("@IMPORT url(x.css); a { width: expreSSion(alert(1)) }"),
+            " url(x.css); a { width: alert(1)) }"
+        );
+        assert_eq!(
+            sanitize_css("ul > li { margin: 0 }"),
+            "ul > li { margin: 0 }"
+        );
+    }
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let rendered = fill(
+            "<h1>{{title}}</h1>{{ content }}{{unknown}}",
+            &[("title", "{{content}}"), ("content", "<p>x</p>")],
+        );
+        assert_eq!(rendered, "<h1>{{content}}</h1><p>x</p>{{unknown}}");
+    }
+
+    #[test]
+    fn page_branding_overrides_the_theme() {
+        let theme = Theme::new(&settings()).unwrap();
+        let html = theme.render(Page {
+            name: "signup",
+            title: "Earthsea",
+            content: "",
+            accent_color: Some("#445566"),
+            logo_url: Some("https://example.com/logo.png"),
+            language: Language::English,
+        });
+        assert!(html.contains("color: #445566;"));
+        assert!(!html.contains("#112233"));
+        assert!(html.contains(r#"src="https://example.com/logo.png""#));
+    }
+
+    #[test]
+    fn invalid_colors_are_rejected() {
+        let mut settings = settings();
+        settings.accent_color = "red; } body { display: none".into();
+        assert!(Theme::new(&settings).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Signed, expiring tokens for the links we email: the web version of an
+//! issue, poll votes and landing page visits. The links to the preferences
+//! of a subscriber keep their stored tokens instead, which an admin can
+//! revoke (see `verified_subscriber`).
+//!
+//! A token reads `<key id>.<expiry>.<payload>.<signature>`: the payload and
+//! the HMAC-SHA256 signature are base64url, the expiry a Unix timestamp. The
+//! signature also covers the purpose of the token, so a token issued for one
+//! purpose is rejected for any other.
+//!
+//! The key id lets several keys verify at once. To rotate:
+//! 1. add the new key to `token_signing.keys` and deploy, so that every
+//!    instance can verify its tokens;
+//! 2. make it the `current_key` and deploy: new tokens are signed with it,
+//!    those already sent still verify with the previous key;
+//! 3. remove the previous key once the longest-lived of its tokens expired.
+use crate::configuration::TokenSigningSettings;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+const MIN_KEY_LENGTH: usize = 32;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TokenError {
+    #[error("The token is malformed")]
+    Malformed,
+    #[error("The token was signed with the unknown key {0}")]
+    UnknownKey(String),
+    #[error("The token signature is invalid")]
+    InvalidSignature,
+    #[error("The token expired")]
+    Expired,
+}
+
+pub struct TokenSigner {
+    current_key: String,
+    keys: HashMap<String, Secret<Vec<u8>>>,
+}
+
+impl TokenSigner {
+    pub fn new(settings: &TokenSigningSettings) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (id, key) in &settings.keys {
+            // The id is part of the tokens, before a `.`.
+            if id.is_empty() || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+                return Err(format!("the key id {:?} is not alphanumeric", id));
+            }
+            let key = key.expose_secret().as_bytes();
+            if key.len() < MIN_KEY_LENGTH {
+                return Err(format!(
+                    "the key {} is shorter than {} bytes",
+                    id, MIN_KEY_LENGTH
+                ));
+            }
+            keys.insert(id.clone(), Secret::new(key.to_vec()));
+        }
+        if !keys.contains_key(&settings.current_key) {
+            return Err(format!(
+                "the current key {} is not in the keyring",
+                settings.current_key
+            ));
+        }
+        Ok(Self {
+            current_key: settings.current_key.clone(),
+            keys,
+        })
+    }
+
+    /// A token carrying `payload` for `purpose`, valid until `expires_at`.
+    pub fn sign(&self, purpose: &str, payload: &str, expires_at: DateTime<Utc>) -> String {
+        let signed = format!(
+            "{}.{}.{}",
+            self.current_key,
+            expires_at.timestamp(),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = self
+            .mac(&self.current_key, purpose, &signed)
+            .expect("The current key is in the keyring")
+            .finalize()
+            .into_bytes();
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    /// The payload of `token`, if we signed it for `purpose` with one of our
+    /// keys and it has not expired.
+    pub fn verify(&self, purpose: &str, token: &str) -> Result<String, TokenError> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let mut parts = signed.splitn(3, '.');
+        let (Some(key_id), Some(expires_at), Some(payload)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TokenError::Malformed);
+        };
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| TokenError::Malformed)?;
+        // `verify_slice` compares in constant time.
+        self.mac(key_id, purpose, signed)
+            .ok_or_else(|| TokenError::UnknownKey(key_id.to_owned()))?
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::InvalidSignature)?;
+        let expires_at: i64 = expires_at.parse().map_err(|_| TokenError::Malformed)?;
+        if Utc::now().timestamp() >= expires_at {
+            return Err(TokenError::Expired);
+        }
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| TokenError::Malformed)?;
+        String::from_utf8(payload).map_err(|_| TokenError::Malformed)
+    }
+
+    fn mac(&self, key_id: &str, purpose: &str, signed: &str) -> Option<Hmac<Sha256>> {
+        let key = self.keys.get(key_id)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.expose_secret()).unwrap();
+        mac.update(purpose.as_bytes());
+        mac.update(b":");
+        mac.update(signed.as_bytes());
+        Some(mac)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenError, TokenSigner};
+    use crate::configuration::TokenSigningSettings;
+    use chrono::{Duration, Utc};
+    use secrecy::Secret;
+
+    fn signer(current_key: &str, keys: &[&str]) -> TokenSigner {
+        TokenSigner::new(&TokenSigningSettings {
+            current_key: current_key.into(),
+            keys: keys
+                .iter()
+                .map(|id| (id.to_string(), Secret::new(id.repeat(32))))
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn in_an_hour() -> chrono::DateTime<Utc> {
+        Utc::now() + Duration::hours(1)
+    }
+
+    #[test]
+    fn a_signed_token_verifies_to_its_payload() {
+        let signer = signer("k1", &["k1"]);
+        let token = signer.sign("unsubscribe", "subscriber.42", in_an_hour());
+        assert_eq!(
+            signer.verify("unsubscribe", &token),
+            Ok("subscriber.42".into())
+        );
+    }
+
+    #[test]
+    fn a_token_is_only_valid_for_its_purpose() {
+        let signer = signer("k1", &["k1"]);
+        let token = signer.sign("unsubscribe", "42", in_an_hour());
+        assert_eq!(
+            signer.verify("preferences", &token),
+            Err(TokenError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn an_expired_token_is_rejected() {
+        let signer = signer("k1", &["k1"]);
+        let token = signer.sign("unsubscribe", "42", Utc::now() - Duration::seconds(1));
+        assert_eq!(
+            signer.verify("unsubscribe", &token),
+            Err(TokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn a_tampered_token_is_rejected() {
+        let signer = signer("k1", &["k1"]);
+        let token = signer.sign("unsubscribe", "42", in_an_hour());
+        let (key_id, rest) = token.split_once('.').unwrap();
+        let (expires_at, rest) = rest.split_once('.').unwrap();
+        let later = expires_at.parse::<i64>().unwrap() + 3600;
+        let tampered = format!("{}.{}.{}", key_id, later, rest);
+        assert_eq!(
+            signer.verify("unsubscribe", &tampered),
+            Err(TokenError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn tokens_of_the_previous_key_verify_after_a_rotation() {
+        let before = signer("k1", &["k1"]);
+        let token = before.sign("unsubscribe", "42", in_an_hour());
+
+        let after = signer("k2", &["k1", "k2"]);
+        assert_eq!(after.verify("unsubscribe", &token), Ok("42".into()));
+        let new_token = after.sign("unsubscribe", "42", in_an_hour());
+        assert!(new_token.starts_with("k2."));
+
+        let retired = signer("k2", &["k2"]);
+        assert_eq!(
+            retired.verify("unsubscribe", &token),
+            Err(TokenError::UnknownKey("k1".into()))
+        );
+        assert_eq!(retired.verify("unsubscribe", &new_token), Ok("42".into()));
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let signer = signer("k1", &["k1"]);
+        for token in ["", "k1", "k1.123.abc", "k1.123.abc.not base64"] {
+            assert_eq!(
+                signer.verify("unsubscribe", token),
+                Err(TokenError::Malformed),
+                "{:?} was not rejected as malformed",
+                token
+            );
+        }
+    }
+
+    #[test]
+    fn short_keys_and_a_missing_current_key_are_refused() {
+        let settings = |current_key: &str, key: &str| TokenSigningSettings {
+            current_key: current_key.into(),
+            keys: [("k1".to_string(), Secret::new(key.to_string()))].into(),
+        };
+        assert!(TokenSigner::new(&settings("k1", "too-short")).is_err());
+        assert!(TokenSigner::new(&settings("k2", &"a".repeat(32))).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! The extractor behind every link we email to subscribers.
+use crate::configuration::SubscriptionTokenSettings;
+use crate::database::{retry_read, ObserveQuery};
+use crate::pii::PiiCipher;
+use crate::routes::error_chain_fmt;
+use actix_web::dev::Payload;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+use std::future::Future;
+use std::pin::Pin;
+use uuid::Uuid;
+
+/// The subscriber behind the `subscription_token` query parameter.
+///
+/// Tokens stop working as soon as the subscriber is deleted or the token row
+/// is purged, so handlers taking a `VerifiedSubscriber` only ever see active
+/// subscribers. They also expire after `subscription_tokens.ttl_days` and
+/// when the subscriber's tokens are reset.
+#[derive(Debug)]
+pub struct VerifiedSubscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    subscription_token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscriberTokenError {
+    #[error("The subscription token is missing.")]
+    MissingToken,
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The subscription token has expired or been revoked.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscriberTokenError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingToken => StatusCode::BAD_REQUEST,
+            Self::UnknownToken | Self::ExpiredToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl FromRequest for VerifiedSubscriber {
+    type Error = SubscriberTokenError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = web::Query::<Parameters>::from_query(req.query_string())
+            .map(|parameters| parameters.into_inner().subscription_token);
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let settings = req
+            .app_data::<web::Data<SubscriptionTokenSettings>>()
+            .cloned();
+        let pii = req
+            .app_data::<web::Data<PiiCipher>>()
+            .map(|pii| pii.get_ref().clone())
+            .unwrap_or_default();
+        Box::pin(async move {
+            let token = token.map_err(|_| SubscriberTokenError::MissingToken)?;
+            let pool = pool.context("The database pool is not registered")?;
+            let settings =
+                settings.context("The subscription token settings are not registered")?;
+            let (mut subscriber, issued_at) = get_subscriber_from_token(&pool, &token)
+                .await
+                .context("Failed to retrieve the subscriber associated with the provided token.")?
+                .ok_or(SubscriberTokenError::UnknownToken)?;
+            if !issued_at.is_valid(Utc::now() - settings.ttl()) {
+                return Err(SubscriberTokenError::ExpiredToken);
+            }
+            subscriber.email = pii.open_email(&subscriber.email)?;
+            subscriber.name = pii.open(&subscriber.name)?;
+            Ok(subscriber)
+        })
+    }
+}
+
+/// When a token was issued, and when the tokens of its subscriber were last
+/// reset.
+pub struct TokenIssuance {
+    issued_at: DateTime<Utc>,
+    tokens_reset_at: Option<DateTime<Utc>>,
+}
+
+impl TokenIssuance {
+    /// Whether the token was issued after `issued_after` and after the last
+    /// reset.
+    pub fn is_valid(&self, issued_after: DateTime<Utc>) -> bool {
+        self.issued_at > issued_after
+            && self
+                .tokens_reset_at
+                .is_none_or(|reset_at| self.issued_at > reset_at)
+    }
+}
+
+#[tracing::instrument(name = "Get subscriber from token", skip(subscription_token, pool))]
+pub async fn get_subscriber_from_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<(VerifiedSubscriber, TokenIssuance)>, sqlx::Error> {
+    let row = retry_read(|| {
+        sqlx::query!(
+            r#"
+            SELECT s.id, s.email, s.name, s.status, s.tokens_reset_at, t.issued_at
+            FROM subscription_tokens t
+            JOIN subscriptions s ON s.id = t.subscriber_id
+            WHERE t.subscription_token = $1 AND s.deleted_at IS NULL
+            "#,
+            subscription_token,
+        )
+        .fetch_optional(pool)
+        .observe("get_subscriber_from_token")
+    })
+    .await?;
+    Ok(row.map(|row| {
+        (
+            VerifiedSubscriber {
+                id: row.id,
+                email: row.email,
+                name: row.name,
+                status: row.status,
+            },
+            TokenIssuance {
+                issued_at: row.issued_at,
+                tokens_reset_at: row.tokens_reset_at,
+            },
+        )
+    }))
+}
+
+/// Revoke every token of the subscriber issued so far.
+#[tracing::instrument(name = "Reset the subscription tokens of a subscriber", skip(executor))]
+pub async fn reset_subscription_tokens<'a, E>(
+    executor: E,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET tokens_reset_at = now() WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(executor)
+    .observe("reset_subscription_tokens")
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenIssuance;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn tokens_expire_and_are_revoked_by_a_reset() {
+        let now = Utc::now();
+        let token = |issued_days_ago, reset_days_ago: Option<i64>| TokenIssuance {
+            issued_at: now - Duration::days(issued_days_ago),
+            tokens_reset_at: reset_days_ago.map(|days| now - Duration::days(days)),
+        };
+        let issued_after = now - Duration::days(30);
+
+        assert!(token(1, None).is_valid(issued_after));
+        assert!(!token(31, None).is_valid(issued_after));
+        assert!(token(1, Some(2)).is_valid(issued_after));
+        assert!(!token(2, Some(1)).is_valid(issued_after));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::configuration::WarmUpSettings;
+use crate::database::{retry_read, ObserveQuery};
+use chrono::{NaiveDate, NaiveTime, Utc};
+use sqlx::PgPool;
+
+/// Ramps up the daily sending volume of a new sending domain.
+///
+/// `daily_limits[n]` caps the number of newsletter emails that can be
+/// delivered per (UTC) day during the n-th week after `start_date`. Once the
+/// schedule is over the warm-up is complete and no limit applies.
+#[derive(Clone, Debug)]
+pub struct WarmUpSchedule {
+    start_date: NaiveDate,
+    daily_limits: Vec<i64>,
+}
+
+impl WarmUpSchedule {
+    pub fn new(settings: WarmUpSettings) -> Self {
+        Self {
+            start_date: settings.start_date,
+            daily_limits: settings.daily_limits,
+        }
+    }
+
+    pub fn daily_limit(&self, today: NaiveDate) -> Option<i64> {
+        let days = (today - self.start_date).num_days().max(0);
+        let week = usize::try_from(days / 7).ok()?;
+        self.daily_limits.get(week).copied()
+    }
+
+    /// How many more emails can be delivered today, if a limit applies.
+    pub async fn remaining_quota(&self, pool: &PgPool) -> Result<Option<i64>, anyhow::Error> {
+        let today = Utc::now().date_naive();
+        let Some(limit) = self.daily_limit(today) else {
+            return Ok(None);
+        };
+        let sent = sent_today(pool, today).await?;
+        Ok(Some((limit - sent).max(0)))
+    }
+}
+
+#[tracing::instrument(name = "Count emails delivered today", skip(pool))]
+async fn sent_today(pool: &PgPool, today: NaiveDate) -> Result<i64, anyhow::Error> {
+    let start_of_day = today.and_time(NaiveTime::MIN).and_utc();
+    let r = retry_read(|| {
+        sqlx::query!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM email_deliveries
+            WHERE succeeded AND attempted_at >= $1
+            "#,
+            start_of_day
+        )
+        .fetch_one(pool)
+        .observe_one("sent_today")
+    })
+    .await?;
+    Ok(r.count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WarmUpSchedule;
+    use crate::configuration::WarmUpSettings;
+    use chrono::{Days, NaiveDate};
+
+    fn schedule() -> (NaiveDate, WarmUpSchedule) {
+        let start_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let schedule = WarmUpSchedule::new(WarmUpSettings {
+            start_date,
+            daily_limits: vec![50, 200],
+        });
+        (start_date, schedule)
+    }
+
+    #[test]
+    fn the_limit_increases_every_week() {
+        let (start, schedule) = schedule();
+        assert_eq!(schedule.daily_limit(start), Some(50));
+        assert_eq!(schedule.daily_limit(start + Days::new(6)), Some(50));
+        assert_eq!(schedule.daily_limit(start + Days::new(7)), Some(200));
+    }
+
+    #[test]
+    fn no_limit_applies_once_the_schedule_is_over() {
+        let (start, schedule) = schedule();
+        assert_eq!(schedule.daily_limit(start + Days::new(14)), None);
+    }
+
+    #[test]
+    fn the_first_limit_applies_before_the_start_date() {
+        let (start, schedule) = schedule();
+        assert_eq!(schedule.daily_limit(start - Days::new(3)), Some(50));
+    }
+}
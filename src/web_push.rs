@@ -0,0 +1,548 @@
+//! Web Push: issues sent as notifications too, to the browsers the
+//! subscribers registered.
+//!
+//! Each notification is encrypted for the browser it goes to (RFC 8291),
+//! and the requests to the push services are signed with our VAPID key
+//! (RFC 8292).
+use crate::configuration::WebPushSettings;
+use crate::database::ObserveQuery;
+use crate::outbound::{is_private_ip_literal, PublicResolver};
+use crate::startup::StartupError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use reqwest::{Client, StatusCode, Url};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use ring::{aead, agreement, hkdf};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The one record of a notification is at most this long, encrypted.
+const RECORD_SIZE: u32 = 4096;
+/// The signatures we send are valid for this long, at most 24 hours.
+const VAPID_TTL_HOURS: i64 = 12;
+
+/// A browser's subscription, as its push manager hands it out.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushSubscriptionKeys,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PushSubscriptionKeys {
+    /// The browser's P-256 public key, base64url-encoded.
+    pub p256dh: String,
+    /// The browser's authentication secret, base64url-encoded.
+    pub auth: String,
+}
+
+impl PushSubscription {
+    /// Check that the subscription points at a push service, and that we
+    /// can encrypt for it.
+    pub fn validate(&self, push_services: &PushServices) -> Result<(), String> {
+        if !Url::parse(&self.endpoint).is_ok_and(|endpoint| push_services.allows(&endpoint)) {
+            return Err("The push endpoint must be an https URL of a push service.".into());
+        }
+        let p256dh = decode(&self.keys.p256dh);
+        if p256dh.is_none_or(|key| key.len() != 65 || key[0] != 0x04) {
+            return Err("The p256dh key must be an uncompressed P-256 public key.".into());
+        }
+        if decode(&self.keys.auth).is_none_or(|auth| auth.len() != 16) {
+            return Err("The auth secret must be 16 bytes long.".into());
+        }
+        Ok(())
+    }
+}
+
+/// The hosts subscriptions may point at. Subscribers send us the endpoints
+/// we then post to: anything else would let them aim us at any server.
+pub struct PushServices {
+    hosts: Vec<String>,
+    allow_private_hosts: bool,
+}
+
+impl PushServices {
+    pub fn new(hosts: Vec<String>, allow_private_hosts: bool) -> Self {
+        Self {
+            hosts,
+            allow_private_hosts,
+        }
+    }
+
+    pub fn allows(&self, endpoint: &Url) -> bool {
+        let Some(host) = endpoint.host_str() else {
+            return false;
+        };
+        if !self.allow_private_hosts
+            && (endpoint.scheme() != "https" || is_private_ip_literal(endpoint))
+        {
+            return false;
+        }
+        matches!(endpoint.scheme(), "http" | "https")
+            && self.hosts.iter().any(|allowed| {
+                host == allowed
+                    || host
+                        .strip_suffix(allowed.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            })
+    }
+}
+
+fn decode(value: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).ok()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PushError {
+    /// The browser unsubscribed: the subscription is of no use anymore.
+    #[error("The push subscription has expired or was removed")]
+    Gone,
+    #[error("Failed to encrypt the notification")]
+    Encryption,
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
+
+pub struct WebPush {
+    http_client: Client,
+    push_services: PushServices,
+    signing_key: EcdsaKeyPair,
+    public_key: String,
+    subject: String,
+    ttl: Duration,
+    rng: SystemRandom,
+}
+
+impl WebPush {
+    pub fn new(settings: WebPushSettings) -> Result<Self, StartupError> {
+        let invalid = |e: &str| StartupError::InvalidConfiguration(format!("web_push: {}", e));
+        let rng = SystemRandom::new();
+        let public_key = decode(&settings.vapid_public_key)
+            .ok_or_else(|| invalid("the VAPID public key is not base64url"))?;
+        let private_key = decode(settings.vapid_private_key.expose_secret())
+            .ok_or_else(|| invalid("the VAPID private key is not base64url"))?;
+        let signing_key = EcdsaKeyPair::from_private_key_and_public_key(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            &private_key,
+            &public_key,
+            &rng,
+        )
+        .map_err(|e| invalid(&format!("the VAPID key pair was rejected: {}", e)))?;
+        let mut builder = Client::builder()
+            .timeout(Duration::from_millis(settings.timeout_milliseconds))
+            .redirect(reqwest::redirect::Policy::none());
+        if !settings.allow_private_hosts {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let http_client = builder
+            .build()
+            .map_err(|e| StartupError::HttpClient("Web Push client", e))?;
+        Ok(Self {
+            http_client,
+            push_services: PushServices::new(settings.push_services, settings.allow_private_hosts),
+            signing_key,
+            public_key: URL_SAFE_NO_PAD.encode(public_key),
+            subject: settings.subject,
+            ttl: Duration::from_secs(settings.ttl_seconds),
+            rng,
+        })
+    }
+
+    /// Our VAPID public key, the `applicationServerKey` browsers subscribe
+    /// with.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    pub fn push_services(&self) -> &PushServices {
+        &self.push_services
+    }
+
+    #[tracing::instrument(name = "Send a push notification", skip_all)]
+    pub async fn send(
+        &self,
+        subscription: &PushSubscription,
+        payload: &[u8],
+    ) -> Result<(), PushError> {
+        // Subscriptions registered before they were checked get dropped.
+        let endpoint = Url::parse(&subscription.endpoint)
+            .ok()
+            .filter(|endpoint| self.push_services.allows(endpoint))
+            .ok_or(PushError::Gone)?;
+        let body = match (
+            decode(&subscription.keys.p256dh),
+            decode(&subscription.keys.auth),
+        ) {
+            (Some(p256dh), Some(auth)) => encrypt(&self.rng, &p256dh, &auth, payload)?,
+            _ => return Err(PushError::Encryption),
+        };
+        let response = self
+            .http_client
+            .post(endpoint.clone())
+            .header("Authorization", self.authorization(&endpoint)?)
+            .header("Content-Encoding", "aes128gcm")
+            .header("TTL", self.ttl.as_secs())
+            .header("Urgency", "normal")
+            .body(body)
+            .send()
+            .await?;
+        if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+            return Err(PushError::Gone);
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    /// The `vapid` authorization of a request to `endpoint`: a JWT for its
+    /// origin, signed with our key.
+    fn authorization(&self, endpoint: &Url) -> Result<String, PushError> {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::json!({
+            "aud": endpoint.origin().ascii_serialization(),
+            "exp": (Utc::now() + chrono::Duration::hours(VAPID_TTL_HOURS)).timestamp(),
+            "sub": self.subject,
+        });
+        let signed = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let signature = self
+            .signing_key
+            .sign(&self.rng, signed.as_bytes())
+            .map_err(|_| PushError::Encryption)?;
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            signed,
+            URL_SAFE_NO_PAD.encode(signature),
+            self.public_key
+        ))
+    }
+}
+
+/// `payload` encrypted for the browser with the public key `ua_public` and
+/// the authentication secret `auth`, as a single `aes128gcm` record.
+fn encrypt(
+    rng: &SystemRandom,
+    ua_public: &[u8],
+    auth: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, PushError> {
+    let private_key = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, rng)
+        .map_err(|_| PushError::Encryption)?;
+    let as_public = private_key
+        .compute_public_key()
+        .map_err(|_| PushError::Encryption)?;
+    let mut salt = [0u8; 16];
+    rng.fill(&mut salt).map_err(|_| PushError::Encryption)?;
+    agreement::agree_ephemeral(
+        private_key,
+        &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, ua_public),
+        |ecdh_secret| {
+            let keys = ContentKeys::derive(ecdh_secret, auth, ua_public, as_public.as_ref(), &salt);
+            keys.seal(&salt, as_public.as_ref(), payload)
+        },
+    )
+    .map_err(|_| PushError::Encryption)?
+}
+
+/// The content encryption key and nonce of a notification.
+struct ContentKeys {
+    cek: [u8; 16],
+    nonce: [u8; 12],
+}
+
+struct Length(usize);
+
+impl hkdf::KeyType for Length {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn hkdf_expand(salt: &[u8], ikm: &[u8], info: &[u8], out: &mut [u8]) {
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(ikm)
+        .expand(&[info], Length(out.len()))
+        .and_then(|okm| okm.fill(out))
+        .expect("HKDF-SHA256 outputs up to 8160 bytes");
+}
+
+impl ContentKeys {
+    fn derive(
+        ecdh_secret: &[u8],
+        auth: &[u8],
+        ua_public: &[u8],
+        as_public: &[u8],
+        salt: &[u8],
+    ) -> Self {
+        let key_info = [b"WebPush: info\0".as_slice(), ua_public, as_public].concat();
+        let mut ikm = [0u8; 32];
+        hkdf_expand(auth, ecdh_secret, &key_info, &mut ikm);
+        let mut keys = Self {
+            cek: [0; 16],
+            nonce: [0; 12],
+        };
+        hkdf_expand(salt, &ikm, b"Content-Encoding: aes128gcm\0", &mut keys.cek);
+        hkdf_expand(salt, &ikm, b"Content-Encoding: nonce\0", &mut keys.nonce);
+        keys
+    }
+
+    /// The body of the request: the header, then the padded payload
+    /// encrypted as the last record.
+    fn seal(&self, salt: &[u8], as_public: &[u8], payload: &[u8]) -> Result<Vec<u8>, PushError> {
+        // The delimiter of the last record, and its 16 bytes of tag.
+        if payload.len() + 1 + 16 > RECORD_SIZE as usize {
+            return Err(PushError::Encryption);
+        }
+        let key = aead::UnboundKey::new(&aead::AES_128_GCM, &self.cek)
+            .map(aead::LessSafeKey::new)
+            .map_err(|_| PushError::Encryption)?;
+        let mut record = [payload, &[2]].concat();
+        key.seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(self.nonce),
+            aead::Aad::empty(),
+            &mut record,
+        )
+        .map_err(|_| PushError::Encryption)?;
+        let mut body = Vec::with_capacity(16 + 4 + 1 + as_public.len() + record.len());
+        body.extend_from_slice(salt);
+        body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+        body.push(as_public.len() as u8);
+        body.extend_from_slice(as_public);
+        body.extend_from_slice(&record);
+        Ok(body)
+    }
+}
+
+/// Register the subscription of a browser of `subscriber_id`. A browser
+/// subscribing again replaces its keys.
+#[tracing::instrument(skip(pool, subscription))]
+pub async fn save_push_subscription(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    subscription: &PushSubscription,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO push_subscriptions (endpoint, subscriber_id, p256dh, auth, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (endpoint) DO UPDATE
+        SET subscriber_id = EXCLUDED.subscriber_id,
+            p256dh = EXCLUDED.p256dh,
+            auth = EXCLUDED.auth
+        "#,
+        subscription.endpoint,
+        subscriber_id,
+        subscription.keys.p256dh,
+        subscription.keys.auth
+    )
+    .execute(pool)
+    .observe("save_push_subscription")
+    .await?;
+    Ok(())
+}
+
+/// The browsers of the subscriber stored as `email`.
+#[tracing::instrument(skip_all)]
+pub async fn get_push_subscriptions(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<PushSubscription>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT p.endpoint, p.p256dh, p.auth
+        FROM push_subscriptions p
+        JOIN subscriptions s ON s.id = p.subscriber_id
+        WHERE s.email = $1 AND s.deleted_at IS NULL
+        ORDER BY p.created_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .observe("get_push_subscriptions")
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| PushSubscription {
+            endpoint: r.endpoint,
+            keys: PushSubscriptionKeys {
+                p256dh: r.p256dh,
+                auth: r.auth,
+            },
+        })
+        .collect())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn delete_push_subscription(pool: &PgPool, endpoint: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM push_subscriptions WHERE endpoint = $1",
+        endpoint
+    )
+    .execute(pool)
+    .observe("delete_push_subscription")
+    .await?;
+    Ok(())
+}
+
+/// Notify the browsers of the subscriber stored as `email` of an issue,
+/// linking to `url`. A browser failing to get it does not fail the
+/// delivery: the email went out already.
+#[tracing::instrument(skip_all)]
+pub async fn notify_issue(
+    pool: &PgPool,
+    web_push: &WebPush,
+    email: &str,
+    title: &str,
+    url: &str,
+) -> Result<(), sqlx::Error> {
+    let payload = serde_json::json!({ "title": title, "url": url }).to_string();
+    for subscription in get_push_subscriptions(pool, email).await? {
+        match web_push.send(&subscription, payload.as_bytes()).await {
+            Ok(()) => {}
+            Err(PushError::Gone) => {
+                delete_push_subscription(pool, &subscription.endpoint).await?;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a push notification"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, encrypt, ContentKeys, PushServices, PushSubscription, PushSubscriptionKeys,
+    };
+    use claims::{assert_err, assert_ok};
+    use ring::rand::{SecureRandom, SystemRandom};
+    use ring::{aead, agreement};
+
+    /// Decrypt a request body the way a browser does.
+    fn decrypt(
+        ua_private: agreement::EphemeralPrivateKey,
+        ua_public: &[u8],
+        auth: &[u8],
+        body: &[u8],
+    ) -> Vec<u8> {
+        let (salt, rest) = body.split_at(16);
+        assert_eq!(&rest[..4], &4096u32.to_be_bytes());
+        let key_length = rest[4] as usize;
+        let (as_public, record) = rest[5..].split_at(key_length);
+        let keys = agreement::agree_ephemeral(
+            ua_private,
+            &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, as_public),
+            |ecdh_secret| ContentKeys::derive(ecdh_secret, auth, ua_public, as_public, salt),
+        )
+        .unwrap();
+        let key =
+            aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &keys.cek).unwrap());
+        let mut record = record.to_vec();
+        let plaintext = key
+            .open_in_place(
+                aead::Nonce::assume_unique_for_key(keys.nonce),
+                aead::Aad::empty(),
+                &mut record,
+            )
+            .unwrap();
+        assert_eq!(plaintext.last(), Some(&2));
+        plaintext[..plaintext.len() - 1].to_vec()
+    }
+
+    #[test]
+    fn the_browser_decrypts_the_notification() {
+        let rng = SystemRandom::new();
+        let ua_private =
+            agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng).unwrap();
+        let ua_public = ua_private.compute_public_key().unwrap();
+        let mut auth = [0u8; 16];
+        rng.fill(&mut auth).unwrap();
+
+        let payload = br#"{"title":"Issue #1","url":"https://news.example/archive/1"}"#;
+        let body = encrypt(&rng, ua_public.as_ref(), &auth, payload).unwrap();
+
+        assert_eq!(
+            decrypt(ua_private, ua_public.as_ref(), &auth, &body),
+            payload.to_vec()
+        );
+    }
+
+    #[test]
+    fn subscriptions_need_a_web_endpoint_and_valid_keys() {
+        let subscription = |endpoint: &str, p256dh: &str, auth: &str| PushSubscription {
+            endpoint: endpoint.into(),
+            keys: PushSubscriptionKeys {
+                p256dh: p256dh.into(),
+                auth: auth.into(),
+            },
+        };
+        let p256dh = "BF3bcPlET9y-q0hES_ExlwyBYLX0SjhgcpYlGkvZnb6oRiuP75mFwGt6WN0hM0LavY3Be8-9hVNES3-AzUha8CY";
+        let auth = "BTBZMqHH6r4Tts7J_aSIgg";
+        let services = PushServices::new(vec!["push.example".into()], false);
+        assert_eq!(decode(auth).unwrap().len(), 16);
+        assert_ok!(subscription("https://push.example/abc", p256dh, auth).validate(&services));
+        assert_err!(subscription("file:///etc/passwd", p256dh, auth).validate(&services));
+        assert_err!(subscription("https://push.example/abc", auth, auth).validate(&services));
+        assert_err!(subscription("https://push.example/abc", p256dh, p256dh).validate(&services));
+    }
+
+    #[test]
+    fn only_push_services_are_reached() {
+        let services = PushServices::new(
+            vec!["fcm.googleapis.com".into(), "notify.windows.com".into()],
+            false,
+        );
+        let allows = |url: &str| services.allows(&reqwest::Url::parse(url).unwrap());
+        assert!(allows("https://fcm.googleapis.com/fcm/send/abc"));
+        assert!(allows(
+            "https://wns2-par02p.notify.windows.com/w/?token=abc"
+        ));
+        assert!(!allows("http://fcm.googleapis.com/fcm/send/abc"));
+        assert!(!allows("https://fcm.googleapis.com.evil.example/"));
+        assert!(!allows("https://evilnotify.windows.com/"));
+        assert!(!allows("https://127.0.0.1/"));
+        assert!(!allows("https://169.254.169.254/latest/meta-data"));
+        assert!(!allows("https://[::1]/"));
+    }
+
+    /// The example of RFC 8291, Appendix A.
+    #[test]
+    fn notifications_are_encrypted_as_in_the_rfc_example() {
+        let decode = |value: &str| decode(value).unwrap();
+        let ua_public = decode(
+            "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+        );
+        let as_public = decode(
+            "BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8",
+        );
+        let auth = decode("BTBZMqHH6r4Tts7J_aSIgg");
+        let salt = decode("DGv6ra1nlYgDCS1FRnbzlw");
+        let ecdh_secret = decode("kyrL1jIIOHEzg3sM2ZWRHDRB62YACZhhSlknJ672kSs");
+
+        let keys = ContentKeys::derive(&ecdh_secret, &auth, &ua_public, &as_public, &salt);
+        let body = keys
+            .seal(
+                &salt,
+                &as_public,
+                b"When I grow up, I want to be a watermelon",
+            )
+            .unwrap();
+
+        assert_eq!(keys.cek.to_vec(), decode("oIhVW04MRdy2XN9CiKLxTg"));
+        assert_eq!(keys.nonce.to_vec(), decode("4h_95klXJ5E_qnoN"));
+        assert_eq!(
+            body,
+            decode(
+                "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+            )
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! The "View in browser" link at the top of the issues we send, to their
+//! page in the archive.
+//!
+//! Paid-only issues are locked in the archive: their link carries a signed
+//! preview token, so that the recipients read them without logging in.
+use crate::links;
+use crate::token_signer::TokenSigner;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const PREVIEW_PURPOSE: &str = "issue_preview";
+/// How long the link of a paid-only issue opens it. A signing key must stay
+/// in the keyring at least this long after it is rotated out.
+const PREVIEW_TTL_DAYS: i64 = 90;
+
+#[derive(Clone)]
+pub struct WebVersion {
+    base_url: String,
+    signer: Arc<TokenSigner>,
+}
+
+impl WebVersion {
+    pub fn new(base_url: String, signer: Arc<TokenSigner>) -> Self {
+        Self { base_url, signer }
+    }
+
+    /// The base URL of the links we email.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn link(&self, issue_id: Uuid, paid_only: bool) -> String {
+        let token = paid_only.then(|| {
+            self.signer.sign(
+                PREVIEW_PURPOSE,
+                &issue_id.to_string(),
+                Utc::now() + Duration::days(PREVIEW_TTL_DAYS),
+            )
+        });
+        links::issue_web_version(issue_id, token.as_deref()).absolute(&self.base_url)
+    }
+
+    /// Whether `token` previews `issue_id`.
+    pub fn verify_preview(&self, issue_id: Uuid, token: &str) -> bool {
+        self.signer
+            .verify(PREVIEW_PURPOSE, token)
+            .is_ok_and(|payload| payload == issue_id.to_string())
+    }
+
+    /// The HTML and text content of an issue, with the link to `issue_id` on
+    /// top.
+    pub fn inject(
+        &self,
+        issue_id: Uuid,
+        paid_only: bool,
+        html_content: &str,
+        text_content: &str,
+    ) -> (String, String) {
+        let link = self.link(issue_id, paid_only);
+        let banner = format!(
+            r#"<p><a href="{}">View in browser</a></p>"#,
+            htmlescape::encode_minimal(&link)
+        );
+        // Right after `<body>` for full documents, at the very top otherwise.
+        let html = match find_body_start(html_content) {
+            Some(index) => {
+                let (head, body) = html_content.split_at(index);
+                format!("{}{}{}", head, banner, body)
+            }
+            None => format!("{}{}", banner, html_content),
+        };
+        let text = format!("View in browser: {}\n\n{}", link, text_content);
+        (html, text)
+    }
+}
+
+/// The index just past the `<body ...>` tag, if any.
+fn find_body_start(html: &str) -> Option<usize> {
+    let start = html.to_ascii_lowercase().find("<body")?;
+    html[start..].find('>').map(|end| start + end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WebVersion;
+    use crate::configuration::TokenSigningSettings;
+    use crate::token_signer::TokenSigner;
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn web_version() -> WebVersion {
+        let signer = TokenSigner::new(&TokenSigningSettings {
+            current_key: "k1".into(),
+            keys: [("k1".to_string(), Secret::new("k".repeat(32)))].into(),
+        })
+        .unwrap();
+        WebVersion::new("https://example.com".into(), Arc::new(signer))
+    }
+
+    fn preview_token(link: &str) -> &str {
+        link.split_once("?preview=").unwrap().1
+    }
+
+    #[test]
+    fn free_issues_link_to_their_public_page() {
+        let issue_id = Uuid::new_v4();
+        assert_eq!(
+            web_version().link(issue_id, false),
+            format!("https://example.com/archive/{}", issue_id)
+        );
+    }
+
+    #[test]
+    fn the_preview_token_only_opens_its_issue() {
+        let web_version = web_version();
+        let issue_id = Uuid::new_v4();
+        let link = web_version.link(issue_id, true);
+        let token = preview_token(&link);
+        assert!(web_version.verify_preview(issue_id, token));
+        assert!(!web_version.verify_preview(Uuid::new_v4(), token));
+    }
+
+    #[test]
+    fn the_link_goes_at_the_top_of_the_body() {
+        let issue_id = Uuid::new_v4();
+        let (html, text) = web_version().inject(
+            issue_id,
+            false,
+            "<html><BODY class=\"x\"><p>Hi</p></BODY></html>",
+            "Hi",
+        );
+        let link = format!("https://example.com/archive/{}", issue_id);
+        assert_eq!(
+            html,
+            format!(
+                r#"<html><BODY class="x"><p><a href="{}">View in browser</a></p><p>Hi</p></BODY></html>"#,
+                link
+            )
+        );
+        assert_eq!(text, format!("View in browser: {}\n\nHi", link));
+
+        let (html, _) = web_version().inject(issue_id, false, "<p>Hi</p>", "Hi");
+        assert!(html.starts_with(r#"<p><a href=""#));
+    }
+}
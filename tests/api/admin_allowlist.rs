@@ -0,0 +1,57 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with};
+
+#[tokio::test]
+async fn admin_routes_are_not_found_outside_the_allowlist() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.admin.allowed_cidrs = Some(vec!["10.0.0.0/8".into()]);
+    })
+    .await;
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    // The rest of the application is still reachable.
+    assert!(!app.get_login_html().await.is_empty());
+}
+
+#[tokio::test]
+async fn admin_routes_are_reachable_from_the_allowlist() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.admin.allowed_cidrs = Some(vec!["127.0.0.0/8".into(), "::1".into()]);
+    })
+    .await;
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_allowlist_applies_to_the_client_behind_trusted_proxies() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.network.trusted_proxies = vec!["127.0.0.0/8".into(), "::1".into()];
+        c.admin.allowed_cidrs = Some(vec!["203.0.113.0/24".into()]);
+    })
+    .await;
+    let get_dashboard_from = |client_ip: &'static str| {
+        app.api_client
+            .get(format!("{}/admin/dashboard", &app.address))
+            .header("X-Forwarded-For", client_ip)
+            .send()
+    };
+
+    // Act
+    let allowed = get_dashboard_from("203.0.113.7").await.unwrap();
+    let denied = get_dashboard_from("198.51.100.1").await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&allowed, "/login");
+    assert_eq!(denied.status().as_u16(), 404);
+}
@@ -0,0 +1,183 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_subscriber(app: &TestApp, email: &str) -> Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}",
+        urlencoding::encode(email)
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn is_deleted(app: &TestApp, subscriber_id: Uuid) -> bool {
+    sqlx::query!(
+        "SELECT deleted_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .deleted_at
+    .is_some()
+}
+
+struct OperationState {
+    id: Uuid,
+    status: String,
+    checkpoint: i32,
+}
+
+async fn get_operation(app: &TestApp) -> OperationState {
+    sqlx::query_as!(
+        OperationState,
+        "SELECT id, status, checkpoint FROM admin_operations"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_start_an_operation() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_admin_operation("delete_subscribers", &Uuid::new_v4().to_string())
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_bulk_delete_runs_to_completion() {
+    // Arrange
+    let app = spawn_app().await;
+    let first = create_subscriber(&app, "ursula@example.com").await;
+    let second = create_subscriber(&app, "le_guin@example.com").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_admin_operation("delete_subscribers", &format!("{}\n{}", first, second))
+        .await;
+    assert_is_redirect_to(&response, "/admin/operations");
+    app.execute_pending_operations().await;
+
+    // Assert
+    assert!(is_deleted(&app, first).await);
+    assert!(is_deleted(&app, second).await);
+    let operation = get_operation(&app).await;
+    assert_eq!(operation.status, "completed");
+    assert_eq!(operation.checkpoint, 2);
+    let html = app.get_admin_operations_html().await;
+    assert!(html.contains("delete_subscribers"));
+    assert!(html.contains("2/2"));
+}
+
+#[tokio::test]
+async fn an_interrupted_operation_resumes_at_its_checkpoint() {
+    // Arrange
+    let app = spawn_app().await;
+    let first = create_subscriber(&app, "ursula@example.com").await;
+    let second = create_subscriber(&app, "le_guin@example.com").await;
+    app.test_user.login(&app).await;
+    app.post_admin_operation("delete_subscribers", &format!("{}\n{}", first, second))
+        .await;
+    // As if the worker stopped after the first step.
+    let operation = get_operation(&app).await;
+    sqlx::query!(
+        "UPDATE admin_operations SET status = 'running', checkpoint = 1 WHERE id = $1",
+        operation.id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.execute_pending_operations().await;
+
+    // Assert
+    assert!(!is_deleted(&app, first).await);
+    assert!(is_deleted(&app, second).await);
+    assert_eq!(get_operation(&app).await.status, "completed");
+}
+
+#[tokio::test]
+async fn rolling_back_restores_only_what_the_operation_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let already_deleted = create_subscriber(&app, "ursula@example.com").await;
+    let deleted = create_subscriber(&app, "le_guin@example.com").await;
+    app.test_user.login(&app).await;
+    app.post_delete_subscriber(already_deleted).await;
+    app.post_admin_operation(
+        "delete_subscribers",
+        &format!("{}\n{}", already_deleted, deleted),
+    )
+    .await;
+    app.execute_pending_operations().await;
+    let operation = get_operation(&app).await;
+
+    // Act
+    let response = app.post_roll_back_operation(operation.id).await;
+    assert_is_redirect_to(&response, "/admin/operations");
+    app.execute_pending_operations().await;
+
+    // Assert
+    assert!(is_deleted(&app, already_deleted).await);
+    assert!(!is_deleted(&app, deleted).await);
+    let operation = get_operation(&app).await;
+    assert_eq!(operation.status, "rolled_back");
+    assert_eq!(operation.checkpoint, 0);
+}
+
+#[tokio::test]
+async fn merges_cannot_be_rolled_back() {
+    // Arrange
+    let app = spawn_app().await;
+    let survivor = create_subscriber(&app, "ursula@example.com").await;
+    let duplicate = create_subscriber(&app, "ursula+old@example.com").await;
+    app.test_user.login(&app).await;
+    app.post_admin_operation("merge_subscribers", &format!("{} {}", survivor, duplicate))
+        .await;
+    app.execute_pending_operations().await;
+    let operation = get_operation(&app).await;
+    assert_eq!(operation.status, "completed");
+    assert!(is_deleted(&app, duplicate).await);
+
+    // Act
+    app.post_roll_back_operation(operation.id).await;
+
+    // Assert
+    let html = app.get_admin_operations_html().await;
+    assert!(html.contains("Merges cannot be rolled back."));
+    assert_eq!(get_operation(&app).await.status, "completed");
+}
+
+#[tokio::test]
+async fn malformed_plans_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_admin_operation("delete_subscribers", "not-an-id")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/operations");
+    let html = app.get_admin_operations_html().await;
+    assert!(html.contains("Line 1: not-an-id is not a subscriber id."));
+}
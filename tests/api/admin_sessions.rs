@@ -0,0 +1,129 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+
+/// Log the test user in from another browser.
+async fn log_in_elsewhere(app: &TestApp, user_agent: &str) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent(user_agent)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query!("SELECT session_id FROM admin_sessions ORDER BY created_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.session_id)
+        .collect()
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_where_the_admin_is_logged_in() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    log_in_elsewhere(&app, "Firefox <on> Linux").await;
+
+    // Act
+    let html_page = app.get_admin_sessions_html().await;
+
+    // Assert
+    assert!(html_page.contains("Firefox &lt;on&gt; Linux"));
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other = log_in_elsewhere(&app, "Firefox").await;
+    let other_session_id = session_ids(&app).await[1];
+
+    // Act - Part 1 - Revoke the other session
+    let response = app.post_revoke_admin_session(other_session_id).await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("The session has been revoked."));
+
+    // Act - Part 2 - Use it
+    let response = get_dashboard(&app, &other).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn revoking_the_other_sessions_keeps_the_current_one() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let firefox = log_in_elsewhere(&app, "Firefox").await;
+    let safari = log_in_elsewhere(&app, "Safari").await;
+
+    // Act
+    let response = app.post_revoke_other_admin_sessions().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_admin_sessions_html().await;
+    assert!(html_page.contains("2 other session(s) have been revoked."));
+    assert_is_redirect_to(&get_dashboard(&app, &firefox).await, "/login");
+    assert_is_redirect_to(&get_dashboard(&app, &safari).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_in_beyond_the_cap_ends_the_least_recently_used_session() {
+    // Arrange
+    let app = spawn_app_with(|c| c.login.max_sessions_per_user = 2).await;
+    let first = log_in_elsewhere(&app, "Firefox").await;
+    let second = log_in_elsewhere(&app, "Safari").await;
+
+    // Act
+    let third = log_in_elsewhere(&app, "Chrome").await;
+
+    // Assert
+    assert_eq!(session_ids(&app).await.len(), 2);
+    assert_is_redirect_to(&get_dashboard(&app, &first).await, "/login");
+    assert_eq!(get_dashboard(&app, &second).await.status().as_u16(), 200);
+    assert_eq!(get_dashboard(&app, &third).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    assert!(session_ids(&app).await.is_empty());
+}
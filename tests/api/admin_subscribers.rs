@@ -0,0 +1,411 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::events::DomainEvent;
+use zero2prod::subscribers::purge_deleted_subscribers;
+
+async fn create_subscriber(app: &TestApp) -> Uuid {
+    create_subscriber_with_email(app, "ursula_le_guin@gmail.com").await
+}
+
+async fn create_subscriber_with_email(app: &TestApp, email: &str) -> Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}",
+        urlencoding::encode(email)
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn deleted_at(app: &TestApp, subscriber_id: Uuid) -> Option<Option<chrono::DateTime<Utc>>> {
+    sqlx::query!(
+        "SELECT deleted_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.deleted_at)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_delete_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_delete_subscriber(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_keeps_the_row_around() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_delete_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(deleted_at(&app, subscriber_id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn a_deleted_subscriber_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_delete_subscriber(subscriber_id).await;
+
+    // Act
+    let response = app.post_restore_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("The subscriber has been restored."));
+    assert_eq!(deleted_at(&app, subscriber_id).await, Some(None));
+}
+
+#[tokio::test]
+async fn resetting_the_links_of_a_subscriber_revokes_those_already_emailed() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_reset_subscriber_links(subscriber_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_deleted_subscriber_cannot_confirm_their_subscription() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.test_user.login(&app).await;
+    app.post_delete_subscriber(subscriber_id).await;
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_are_purged_after_the_retention_period() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    app.post_delete_subscriber(subscriber_id).await;
+
+    // Act
+    let kept = purge_deleted_subscribers(&app.db_pool, Utc::now() - Duration::days(30))
+        .await
+        .unwrap();
+    let purged = purge_deleted_subscribers(&app.db_pool, Utc::now())
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(kept, 0);
+    assert_eq!(purged, 1);
+    assert_eq!(deleted_at(&app, subscriber_id).await, None);
+}
+
+#[tokio::test]
+async fn merging_moves_the_history_of_the_duplicate_onto_the_survivor() {
+    // Arrange
+    let app = spawn_app().await;
+    let survivor_id = create_subscriber_with_email(&app, "ursula@gmail.com").await;
+    let duplicate_id = create_subscriber_with_email(&app, "ursula+news@gmail.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'confirmed', paid = true WHERE id = $1",
+        duplicate_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "INSERT INTO email_deliveries (newsletter_issue_id, subscriber_email, attempted_at, succeeded)
+         SELECT newsletter_issue_id, 'ursula+news@gmail.com', now(), true FROM newsletter_issues"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let referred_id = create_subscriber_with_email(&app, "ada@gmail.com").await;
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    for query in [
+        sqlx::query!(
+            "UPDATE subscriptions SET referred_by = $1 WHERE id = $2",
+            duplicate_id,
+            referred_id
+        ),
+        sqlx::query!(
+            "UPDATE subscriptions SET phone_number = '+33612345678', phone_country = 'FR',
+                 phone_verified_at = now(), sms_opted_in_at = now()
+             WHERE id = $1",
+            duplicate_id
+        ),
+        sqlx::query!(
+            "INSERT INTO referral_rewards (subscriber_id, milestone, reached_at)
+             VALUES ($1, 3, now())",
+            duplicate_id
+        ),
+        sqlx::query!(
+            "INSERT INTO push_subscriptions (endpoint, subscriber_id, p256dh, auth, created_at)
+             VALUES ('https://fcm.googleapis.com/fcm/send/1', $1, 'key', 'auth', now())",
+            duplicate_id
+        ),
+        sqlx::query!(
+            "INSERT INTO polls (poll_id, newsletter_issue_id, question, options, created_at)
+             SELECT $1, newsletter_issue_id, 'Why?', ARRAY['Yes', 'No'], now()
+             FROM newsletter_issues",
+            Uuid::new_v4()
+        ),
+        sqlx::query!(
+            "INSERT INTO poll_votes (poll_id, subscriber_id, option_index, voted_at)
+             SELECT poll_id, $1, 0, now() FROM polls",
+            duplicate_id
+        ),
+        sqlx::query!(
+            "INSERT INTO landing_visits (visit_id, newsletter_issue_id, subscriber_id, kind, visited_at)
+             SELECT $1, newsletter_issue_id, $2, 'visit', now() FROM newsletter_issues",
+            Uuid::new_v4(),
+            duplicate_id
+        ),
+    ] {
+        query.execute(&mut *transaction).await.unwrap();
+    }
+    transaction.commit().await.unwrap();
+
+    // Act
+    let response = app.post_merge_subscriber(survivor_id, duplicate_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("The subscribers have been merged."));
+    let survivor = sqlx::query!(
+        "SELECT status, paid, deleted_at FROM subscriptions WHERE id = $1",
+        survivor_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(survivor.status, "confirmed");
+    assert!(survivor.paid);
+    assert!(survivor.deleted_at.is_none());
+    assert!(deleted_at(&app, duplicate_id).await.unwrap().is_some());
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "ursula@gmail.com");
+    let delivery = sqlx::query!("SELECT subscriber_email FROM email_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.subscriber_email, "ursula@gmail.com");
+    let suppressed = sqlx::query!("SELECT email, reason FROM suppressed_emails")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppressed.email, "ursula+news@gmail.com");
+    assert_eq!(suppressed.reason, "merged");
+    let referred = sqlx::query!(
+        "SELECT referred_by FROM subscriptions WHERE id = $1",
+        referred_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(referred.referred_by, Some(survivor_id));
+    let phone = sqlx::query!(
+        "SELECT phone_number, sms_opted_in_at FROM subscriptions WHERE id = $1",
+        survivor_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(phone.phone_number.as_deref(), Some("+33612345678"));
+    assert!(phone.sms_opted_in_at.is_some());
+    for (table, owners) in [
+        (
+            "referral_rewards",
+            sqlx::query_scalar!("SELECT subscriber_id FROM referral_rewards")
+                .fetch_all(&app.db_pool)
+                .await
+                .unwrap(),
+        ),
+        (
+            "push_subscriptions",
+            sqlx::query_scalar!("SELECT subscriber_id FROM push_subscriptions")
+                .fetch_all(&app.db_pool)
+                .await
+                .unwrap(),
+        ),
+        (
+            "poll_votes",
+            sqlx::query_scalar!("SELECT subscriber_id FROM poll_votes")
+                .fetch_all(&app.db_pool)
+                .await
+                .unwrap(),
+        ),
+        (
+            "landing_visits",
+            sqlx::query_scalar!("SELECT subscriber_id FROM landing_visits")
+                .fetch_all(&app.db_pool)
+                .await
+                .unwrap(),
+        ),
+    ] {
+        assert_eq!(owners, vec![survivor_id], "{}", table);
+    }
+}
+
+#[tokio::test]
+async fn two_paying_subscribers_are_not_merged() {
+    // Arrange
+    let app = spawn_app().await;
+    let survivor_id = create_subscriber_with_email(&app, "ursula@gmail.com").await;
+    let duplicate_id = create_subscriber_with_email(&app, "ursula+news@gmail.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET paid = true, stripe_customer_id = 'cus_' || id WHERE id IN ($1, $2)",
+        survivor_id,
+        duplicate_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_merge_subscriber(survivor_id, duplicate_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Both subscribers pay"));
+    assert_eq!(deleted_at(&app, duplicate_id).await, Some(None));
+    let customer = sqlx::query!(
+        "SELECT stripe_customer_id FROM subscriptions WHERE id = $1",
+        survivor_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        customer.stripe_customer_id,
+        Some(format!("cus_{}", survivor_id))
+    );
+}
+
+#[tokio::test]
+async fn a_subscriber_cannot_be_merged_into_itself() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_merge_subscriber(subscriber_id, subscriber_id)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("A subscriber cannot be merged into itself."));
+    assert_eq!(deleted_at(&app, subscriber_id).await, Some(None));
+}
+
+#[tokio::test]
+async fn only_owners_can_view_as_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_view_as_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_view_as_a_subscriber_under_a_banner_and_on_the_record() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status, paid_only)
+        VALUES ($1, 'Paid issue', 'Issue body', '<p>Issue body</p>', now(), 'completed', true)
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.test_user.set_role(&app, "owner").await;
+    app.test_user.login(&app).await;
+    let mut events = app.event_bus.subscribe();
+
+    // Act
+    let response = app.get_view_as_subscriber(subscriber_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("You are viewing the newsletter as ursula_le_guin@gmail.com"));
+    assert!(html.contains("Paid issue</a> ("));
+    assert!(html.contains("locked, paid subscribers only"));
+    assert!(html.contains("No issue they can read was sent yet."));
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DomainEvent::SubscriberViewImpersonated { subscriber_id: id, .. } if id == subscriber_id
+    ));
+}
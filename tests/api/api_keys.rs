@@ -0,0 +1,85 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use secrecy::Secret;
+use zero2prod::configuration::{ApiKeySettings, ApiSettings, ApiTierSettings};
+
+const API_KEY: &str = "signup-form-key";
+
+async fn spawn_app_with_quota(requests_per_minute: u32) -> TestApp {
+    spawn_app_with(|c| {
+        c.api = Some(ApiSettings {
+            keys: vec![ApiKeySettings {
+                id: "signup-form".into(),
+                key: Secret::new(API_KEY.into()),
+                tier: "free".into(),
+            }],
+            tiers: [(
+                "free".into(),
+                ApiTierSettings {
+                    requests_per_minute,
+                    requests_per_day: 1000,
+                },
+            )]
+            .into(),
+        });
+    })
+    .await
+}
+
+#[tokio::test]
+async fn api_responses_carry_the_rate_limit_headers() {
+    let app = spawn_app_with_quota(5).await;
+
+    let response = app.post_verify_email(API_KEY, "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let headers = response.headers();
+    assert_eq!(headers["x-ratelimit-limit"], "5");
+    assert_eq!(headers["x-ratelimit-remaining"], "4");
+    let reset: i64 = headers["x-ratelimit-reset"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(reset > chrono::Utc::now().timestamp());
+}
+
+#[tokio::test]
+async fn requests_past_the_quota_are_rejected() {
+    let app = spawn_app_with_quota(2).await;
+    for _ in 0..2 {
+        let response = app.post_verify_email(API_KEY, "ursula@example.com").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app.post_verify_email(API_KEY, "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    assert!(response.headers().contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn the_usage_of_a_key_is_reported_to_admins() {
+    let app = spawn_app_with_quota(5).await;
+    for _ in 0..3 {
+        app.post_verify_email(API_KEY, "ursula@example.com").await;
+    }
+
+    let response = app.get_api_key_usage("signup-form").await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.test_user.login(&app).await;
+    let usage: serde_json::Value = app
+        .get_api_key_usage("signup-form")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(usage["tier"], "free");
+    assert_eq!(usage["requests_per_minute"], 5);
+    assert_eq!(usage["requests_today"], 3);
+    assert_eq!(usage["daily"][0]["requests"], 3);
+
+    let response = app.get_api_key_usage("unknown").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
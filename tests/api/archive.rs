@@ -0,0 +1,379 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn insert_issue(app: &TestApp, paid_only: bool) -> Uuid {
+    insert_issue_with_html(app, paid_only, "<p>Issue body</p>").await
+}
+
+async fn insert_issue_with_html(app: &TestApp, paid_only: bool, html_content: &str) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status, paid_only)
+        VALUES ($1, 'Issue title', 'Issue body', $3, now(), 'completed', $2)
+        "#,
+        issue_id,
+        paid_only,
+        html_content
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    issue_id
+}
+
+async fn insert_subscriber(app: &TestApp, email: &str, paid: bool) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, paid)
+        VALUES ($1, $2, 'name', now(), 'confirmed', $3)
+        "#,
+        Uuid::new_v4(),
+        email,
+        paid
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Request a login link for `email` and return it.
+async fn request_login_link(app: &TestApp, email: &str, issue_id: Uuid) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_archive_login(&serde_json::json!({ "email": email, "issue_id": issue_id }))
+        .await;
+    assert_is_redirect_to(&response, &format!("/archive/{}", issue_id));
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request).html
+}
+
+#[tokio::test]
+async fn free_issues_are_public() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, false).await;
+
+    // Act
+    let response = app.get_archive_issue(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<p>Issue body</p>"));
+}
+
+#[tokio::test]
+async fn paid_only_issues_ask_anonymous_readers_to_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+
+    // Act
+    let response = app.get_archive_issue(issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let html_page = response.text().await.unwrap();
+    assert!(!html_page.contains("<p>Issue body</p>"));
+    assert!(html_page.contains(r#"action="/archive/login""#));
+}
+
+#[tokio::test]
+async fn a_magic_link_unlocks_paid_only_issues_for_paid_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    insert_subscriber(&app, "paid@gmail.com", true).await;
+
+    // Act
+    let login_link = request_login_link(&app, "paid@gmail.com", issue_id).await;
+    let response = app
+        .api_client
+        .post(login_link.clone())
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &format!("/archive/{}", issue_id));
+    // The session cookie belongs to the host of the link.
+    let issue_url = login_link.join(&format!("/archive/{}", issue_id)).unwrap();
+    let response = app.api_client.get(issue_url).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<p>Issue body</p>"));
+}
+
+#[tokio::test]
+async fn the_web_version_link_opens_a_paid_only_issue_without_logging_in() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    let mut link = reqwest::Url::parse(&app.web_version.link(issue_id, true)).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+
+    // Act
+    let response = app.api_client.get(link.clone()).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<p>Issue body</p>"));
+
+    // The token does not open the other issues.
+    let other_issue_id = insert_issue(&app, true).await;
+    link.set_path(&format!("/archive/{}", other_issue_id));
+    let response = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn free_subscribers_cannot_read_paid_only_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    insert_subscriber(&app, "free@gmail.com", false).await;
+
+    // Act
+    let login_link = request_login_link(&app, "free@gmail.com", issue_id).await;
+    app.api_client
+        .post(login_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let issue_url = login_link.join(&format!("/archive/{}", issue_id)).unwrap();
+    let response = app.api_client.get(issue_url).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_magic_link_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    insert_subscriber(&app, "paid@gmail.com", true).await;
+    let login_link = request_login_link(&app, "paid@gmail.com", issue_id).await;
+    app.api_client
+        .post(login_link.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(login_link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn following_a_magic_link_does_not_use_it_up() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    insert_subscriber(&app, "paid@gmail.com", true).await;
+    let login_link = request_login_link(&app, "paid@gmail.com", issue_id).await;
+
+    // Act - a mail scanner prefetches the link
+    let html_page = reqwest::get(login_link.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"<form action="/archive/login/confirm?token="#));
+    let response = app.api_client.post(login_link).send().await.unwrap();
+    assert_is_redirect_to(&response, &format!("/archive/{}", issue_id));
+}
+
+#[tokio::test]
+async fn no_link_is_sent_to_unknown_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, true).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_archive_login(
+            &serde_json::json!({ "email": "nobody@gmail.com", "issue_id": issue_id }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/archive/{}", issue_id));
+    let html_page = app.get_archive_issue(issue_id).await.text().await.unwrap();
+    assert!(html_page.contains("a login link is on its way"));
+}
+
+/// Publish an issue embedding `image_url` and return where the archive
+/// serves it from.
+async fn proxied_image_path(app: &TestApp, image_url: &str) -> String {
+    let issue_id =
+        insert_issue_with_html(app, false, &format!(r#"<img src="{}">"#, image_url)).await;
+    let html_page = app.get_archive_issue(issue_id).await.text().await.unwrap();
+    assert!(!html_page.contains(image_url));
+    let start = html_page.find(r#"src=""#).unwrap() + r#"src=""#.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    htmlescape::decode_html(&html_page[start..end]).unwrap()
+}
+
+#[tokio::test]
+async fn external_images_are_served_through_the_proxy_and_cached() {
+    let app = spawn_app().await;
+    let image_server = MockServer::start().await;
+    Mock::given(path("/logo.png"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"PNG".to_vec(), "image/png"))
+        .expect(1)
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/logo.png", image_server.uri())).await;
+    assert!(image_path.starts_with("/archive/images?"));
+
+    for _ in 0..2 {
+        let response = app
+            .api_client
+            .get(format!("{}{}", app.address, image_path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Content-Type"], "image/png");
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"PNG");
+    }
+}
+
+#[tokio::test]
+async fn the_image_proxy_rejects_unsigned_urls() {
+    let app = spawn_app().await;
+    let image_server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"PNG".to_vec(), "image/png"))
+        .expect(0)
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/logo.png", image_server.uri())).await;
+    let tampered = image_path.replace("logo.png", "other.png");
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", app.address, tampered))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_image_proxy_only_serves_images() {
+    let app = spawn_app().await;
+    let image_server = MockServer::start().await;
+    Mock::given(path("/page"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(b"<script></script>".to_vec(), "image/svg+xml"),
+        )
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/page", image_server.uri())).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", app.address, image_path))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 502);
+}
+
+#[tokio::test]
+async fn the_image_proxy_rejects_images_over_the_size_limit() {
+    let app = spawn_app_with(|c| c.image_proxy.max_image_bytes = 16).await;
+    let image_server = MockServer::start().await;
+    Mock::given(path("/huge.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0; 17], "image/png"))
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/huge.png", image_server.uri())).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", app.address, image_path))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 502);
+}
+
+#[tokio::test]
+async fn the_image_proxy_does_not_follow_redirects() {
+    let app = spawn_app().await;
+    let image_server = MockServer::start().await;
+    Mock::given(path("/logo.png"))
+        .respond_with(ResponseTemplate::new(302).insert_header("Location", "/internal.png"))
+        .mount(&image_server)
+        .await;
+    Mock::given(path("/internal.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"PNG".to_vec(), "image/png"))
+        .expect(0)
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/logo.png", image_server.uri())).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", app.address, image_path))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 502);
+}
+
+#[tokio::test]
+async fn the_image_proxy_does_not_fetch_from_private_addresses() {
+    let app = spawn_app_with(|c| c.image_proxy.allow_private_hosts = false).await;
+    let image_server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"PNG".to_vec(), "image/png"))
+        .expect(0)
+        .mount(&image_server)
+        .await;
+    let image_path = proxied_image_path(&app, &format!("{}/logo.png", image_server.uri())).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}{}", app.address, image_path))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 502);
+}
@@ -0,0 +1,70 @@
+use crate::helpers::spawn_app;
+use chrono::{Timelike, Utc};
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_analytics() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_audit_analytics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_changes_of_each_admin_are_counted_per_day_and_hour() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.set_role(&app, "owner").await;
+    app.test_user.login(&app).await;
+    // Reads are not counted.
+    app.get_admin_dashboard().await;
+
+    // Act
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+    let response = app.get_audit_analytics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let analytics: serde_json::Value = response.json().await.unwrap();
+    let admin = &analytics["admins"][0];
+    assert_eq!(admin["username"], app.test_user.username.as_str());
+    assert_eq!(
+        admin["daily"][0]["day"],
+        Utc::now().date_naive().to_string()
+    );
+    assert_eq!(admin["daily"][0]["actions"], 1);
+    assert_eq!(admin["heatmap"][Utc::now().hour() as usize], 1);
+    assert_eq!(analytics["unusual_hours"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn failed_logins_are_trended_per_day() {
+    // Arrange
+    let app = spawn_app().await;
+    for username in [app.test_user.username.as_str(), "root", "admin"] {
+        app.post_login(&serde_json::json!({
+            "username": username,
+            "password": "random-password"
+        }))
+        .await;
+    }
+    app.test_user.set_role(&app, "owner").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let analytics: serde_json::Value = app.get_audit_analytics().await.json().await.unwrap();
+
+    // Assert
+    let today = &analytics["failed_logins"][0];
+    assert_eq!(today["failures"], 3);
+    assert_eq!(today["unknown_usernames"], 2);
+    assert_eq!(
+        analytics["targeted_usernames"],
+        serde_json::json!([{ "username": app.test_user.username, "failures": 1 }])
+    );
+}
@@ -0,0 +1,213 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::billing::sign;
+use zero2prod::configuration::BillingSettings;
+
+const WEBHOOK_SECRET: &str = "whsec_test";
+
+async fn spawn_billing_app() -> (TestApp, MockServer) {
+    let stripe_server = MockServer::start().await;
+    let api_base_url = stripe_server.uri();
+    let app = spawn_app_with(|c| {
+        c.billing = Some(BillingSettings {
+            api_base_url,
+            secret_key: Secret::new("sk_test".into()),
+            webhook_secret: Secret::new(WEBHOOK_SECRET.into()),
+            price_id: "price_paid".into(),
+            success_url: "http://127.0.0.1/paid".into(),
+            cancel_url: "http://127.0.0.1/".into(),
+            timeout_milliseconds: 1000,
+            signature_tolerance_seconds: 300,
+        });
+    })
+    .await;
+    (app, stripe_server)
+}
+
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str, paid: bool) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, paid)
+        VALUES ($1, $2, 'name', now(), 'confirmed', $3)
+        "#,
+        subscriber_id,
+        email,
+        paid
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn is_paid(app: &TestApp, subscriber_id: Uuid) -> bool {
+    sqlx::query!(
+        "SELECT paid FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .paid
+}
+
+async fn send_event(app: &TestApp, event: serde_json::Value) -> reqwest::Response {
+    let payload = event.to_string();
+    let signature = sign(
+        payload.as_bytes(),
+        &Secret::new(WEBHOOK_SECRET.into()),
+        chrono::Utc::now().timestamp(),
+    );
+    app.post_stripe_webhook(&payload, &signature).await
+}
+
+#[tokio::test]
+async fn billing_endpoints_are_not_found_when_billing_is_disabled() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_billing_checkout("ursula@gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn checkout_redirects_confirmed_subscribers_to_stripe() {
+    // Arrange
+    let (app, stripe_server) = spawn_billing_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@gmail.com", false).await;
+    Mock::given(path("/v1/checkout/sessions"))
+        .and(method("POST"))
+        .and(header("Authorization", "Bearer sk_test"))
+        .and(body_string_contains(subscriber_id.to_string()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "cs_test",
+            "url": "https://checkout.stripe.com/c/pay/cs_test"
+        })))
+        .expect(1)
+        .mount(&stripe_server)
+        .await;
+
+    // Act
+    let response = app.post_billing_checkout("ursula@gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://checkout.stripe.com/c/pay/cs_test"
+    );
+}
+
+#[tokio::test]
+async fn checkout_is_rejected_for_unknown_subscribers() {
+    // Arrange
+    let (app, stripe_server) = spawn_billing_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&stripe_server)
+        .await;
+
+    // Act
+    let response = app.post_billing_checkout("ursula@gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn webhooks_with_an_invalid_signature_are_rejected() {
+    // Arrange
+    let (app, _stripe_server) = spawn_billing_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@gmail.com", false).await;
+    let payload = serde_json::json!({
+        "type": "checkout.session.completed",
+        "data": { "object": { "customer": "cus_1", "client_reference_id": subscriber_id } }
+    })
+    .to_string();
+    let signature = sign(
+        payload.as_bytes(),
+        &Secret::new("whsec_wrong".into()),
+        chrono::Utc::now().timestamp(),
+    );
+
+    // Act
+    let response = app.post_stripe_webhook(&payload, &signature).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(!is_paid(&app, subscriber_id).await);
+}
+
+#[tokio::test]
+async fn a_completed_checkout_grants_the_paid_tier_until_the_subscription_is_cancelled() {
+    // Arrange
+    let (app, _stripe_server) = spawn_billing_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@gmail.com", false).await;
+
+    // Act - Part 1 - Checkout completed
+    let response = send_event(
+        &app,
+        serde_json::json!({
+            "type": "checkout.session.completed",
+            "data": { "object": { "customer": "cus_1", "client_reference_id": subscriber_id } }
+        }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_paid(&app, subscriber_id).await);
+
+    // Act - Part 2 - Subscription cancelled
+    let response = send_event(
+        &app,
+        serde_json::json!({
+            "type": "customer.subscription.deleted",
+            "data": { "object": { "customer": "cus_1", "status": "canceled" } }
+        }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!is_paid(&app, subscriber_id).await);
+}
+
+#[tokio::test]
+async fn paid_only_issues_are_delivered_to_paid_subscribers_only() {
+    // Arrange
+    let (app, _stripe_server) = spawn_billing_app().await;
+    insert_confirmed_subscriber(&app, "paid@gmail.com", true).await;
+    insert_confirmed_subscriber(&app, "free@gmail.com", false).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("paid@gmail.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_string_contains("free@gmail.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "paid_only": true,
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Mocks verify on Drop
+}
@@ -0,0 +1,97 @@
+use crate::helpers::{assert_is_redirect_to, confirm_subscription, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribe from a hosted signup page and return the confirmation link.
+async fn subscribe_from_signup_page(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "Earthsea Browser/1.0")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "l/earthsea"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request).html
+}
+
+async fn subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_are_both_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = subscribe_from_signup_page(&app).await;
+
+    // Act
+    reqwest::Client::new()
+        .post(confirmation_link.clone())
+        .header("User-Agent", "Mail Client/2.0")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // Confirming again confirms nothing new.
+    confirm_subscription(confirmation_link).await.unwrap();
+
+    // Assert
+    app.test_user.login(&app).await;
+    let records = app.get_subscriber_consent(subscriber_id(&app).await).await;
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["action"], "subscribed");
+    assert_eq!(records[0]["user_agent"], "Earthsea Browser/1.0");
+    assert_eq!(records[0]["form_source"], "l/earthsea");
+    assert_eq!(records[0]["consent_text_version"], "1");
+    assert!(records[0]["client_ip"].is_string());
+    assert_eq!(records[1]["action"], "confirmed");
+    assert_eq!(records[1]["user_agent"], "Mail Client/2.0");
+    assert!(records[1]["form_source"].is_null());
+}
+
+#[tokio::test]
+async fn consent_records_are_only_shown_to_admins() {
+    // Arrange
+    let app = spawn_app().await;
+    subscribe_from_signup_page(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/{}/consent",
+            &app.address,
+            subscriber_id(&app).await
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
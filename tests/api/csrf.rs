@@ -0,0 +1,75 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use zero2prod::authentication::CSRF_HEADER;
+
+#[tokio::test]
+async fn an_admin_post_without_a_csrf_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/settings/reload", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_admin_post_with_another_csrf_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.csrf_token().await;
+    assert!(!token.is_empty());
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/settings/reload", &app.address))
+        .header(CSRF_HEADER, "aB3dE5gH7jK9mN1pQ3sT5vW7yZ9bC1dE")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn admin_forms_carry_the_csrf_token_of_the_cookie() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Render a form
+    let html_page = app.get_change_password_html().await;
+    let token = app.csrf_token().await;
+    assert!(html_page.contains(&format!(
+        r#"<input hidden type="text" name="csrf_token" value="{}">"#,
+        token
+    )));
+
+    // Act - Part 2 - Submit it as a browser would
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "current_password": "wrong-password",
+            "new_password": "new-password",
+            "new_password_check": "new-password",
+            "csrf_token": token,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("The current password is incorrect."));
+}
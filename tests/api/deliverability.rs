@@ -0,0 +1,34 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_check_the_dns_records() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_deliverability_dns().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_dns_report_covers_spf_dkim_and_dmarc() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_deliverability_dns().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let records: Vec<_> = report["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|check| check["record"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(records, vec!["spf", "dkim", "dmarc"]);
+}
@@ -0,0 +1,156 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{DeliveryEventProvider, DeliveryEventsSettings};
+
+const TOKEN: &str = "delivery-events-token";
+
+async fn spawn_app_with_provider(provider: DeliveryEventProvider) -> TestApp {
+    spawn_app_with(|c| {
+        c.delivery_events = Some(DeliveryEventsSettings {
+            provider,
+            webhook_token: Secret::new(TOKEN.into()),
+        })
+    })
+    .await
+}
+
+async fn suppressions(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, reason FROM suppressed_emails ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.email, row.reason))
+        .collect()
+}
+
+#[tokio::test]
+async fn postmark_hard_bounces_suppress_the_address() {
+    // Arrange
+    let app = spawn_app_with_provider(DeliveryEventProvider::Postmark).await;
+    let bounce = serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "Ursula@gmail.com",
+        "BouncedAt": "2026-10-14T08:00:00Z",
+        "Inactive": true,
+    });
+
+    // Act
+    let response = app.post_delivery_events(TOKEN, &bounce.to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        suppressions(&app).await,
+        vec![("ursula@gmail.com".into(), "hard_bounce".into())]
+    );
+}
+
+#[tokio::test]
+async fn a_bounced_address_gets_no_new_issue() {
+    // Arrange
+    let app = spawn_app_with_provider(DeliveryEventProvider::Postmark).await;
+    app.create_confirmed_subscriber("ursula@gmail.com").await;
+    app.create_confirmed_subscriber("le_guin@gmail.com").await;
+    let bounce = serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "Ursula@gmail.com",
+        "BouncedAt": "2026-10-14T08:00:00Z",
+        "Inactive": true,
+    });
+    app.post_delivery_events(TOKEN, &bounce.to_string())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["messages"][0]["To"][0]["email"], "le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn sendgrid_batches_only_suppress_permanent_failures() {
+    // Arrange
+    let app = spawn_app_with_provider(DeliveryEventProvider::Sendgrid).await;
+    let events = serde_json::json!([
+        { "email": "deferred@gmail.com", "timestamp": 1791964800, "event": "deferred" },
+        { "email": "spam@gmail.com", "timestamp": 1791964800, "event": "spamreport" },
+        { "email": "open@gmail.com", "timestamp": 1791964800, "event": "open" },
+    ]);
+
+    // Act
+    let response = app.post_delivery_events(TOKEN, &events.to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        suppressions(&app).await,
+        vec![("spam@gmail.com".into(), "spam_complaint".into())]
+    );
+}
+
+#[tokio::test]
+async fn delivery_events_require_the_webhook_token() {
+    // Arrange
+    let app = spawn_app_with_provider(DeliveryEventProvider::Sendgrid).await;
+    let events = r#"[{"email":"spam@gmail.com","timestamp":1791964800,"event":"spamreport"}]"#;
+
+    // Act
+    let response = app.post_delivery_events("wrong-token", events).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(suppressions(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn payloads_in_another_providers_shape_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_provider(DeliveryEventProvider::Sendgrid).await;
+    let bounce = r#"{"RecordType":"Bounce","Email":"ursula@gmail.com","Inactive":true}"#;
+
+    // Act
+    let response = app.post_delivery_events(TOKEN, bounce).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_webhook_is_not_found_unless_configured() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_delivery_events(TOKEN, "[]").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
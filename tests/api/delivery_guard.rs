@@ -0,0 +1,162 @@
+use crate::helpers::{spawn_app, TestApp};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain_throttle::DomainThrottle;
+use zero2prod::issue_delivery_worker::{try_execute_task, LaneScheduler};
+
+const TITLE: &str = "Newsletter title";
+
+async fn received_requests(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+/// Run the worker until it is in the middle of a send, then drop it on the
+/// floor, as a crash would: its transaction never commits. Closing `pool`
+/// afterwards takes its connections down with it.
+async fn crash_mid_send(app: &TestApp, pool: &PgPool) {
+    let received = received_requests(app).await;
+    let domains = DomainThrottle::default();
+    loop {
+        let mut lanes = LaneScheduler::default();
+        let task = try_execute_task(
+            pool,
+            &app.email_client,
+            &app.pii,
+            &app.web_version,
+            &app.poll_links,
+            app.landing_links.as_ref(),
+            app.web_push.as_ref(),
+            &app.event_bus,
+            &app.delivery_policy,
+            &mut lanes,
+            &domains,
+        );
+        let in_flight = async {
+            while received_requests(app).await == received {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            // Nothing was sent, e.g. a task whose delivery was claimed.
+            outcome = task => { outcome.unwrap(); }
+            _ = in_flight => return,
+        }
+    }
+}
+
+/// How many times each address got the issue.
+async fn issues_received(app: &TestApp) -> HashMap<String, usize> {
+    let mut received = HashMap::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let message = &body["messages"][0];
+        if message["Subject"] == TITLE {
+            *received
+                .entry(message["To"][0]["email"].as_str().unwrap().to_owned())
+                .or_default() += 1;
+        }
+    }
+    received
+}
+
+#[tokio::test]
+async fn a_worker_crashing_mid_send_never_causes_a_second_send() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmations = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let emails = ["ursula@gmail.com", "octavia@gmail.com", "ted@gmail.com"];
+    for email in emails {
+        app.create_confirmed_subscriber(email).await;
+    }
+    drop(confirmations);
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": TITLE,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+
+    // Act
+    let hanging_provider = Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let crashing_pool = PgPoolOptions::new()
+        .connect_with((*app.db_pool.connect_options()).clone())
+        .await
+        .unwrap();
+    for _ in 0..emails.len() {
+        crash_mid_send(&app, &crashing_pool).await;
+    }
+    crashing_pool.close().await;
+    drop(hanging_provider);
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let received = issues_received(&app).await;
+    assert_eq!(received.len(), emails.len());
+    assert!(received.values().all(|count| *count == 1), "{:?}", received);
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "completed");
+}
+
+#[tokio::test]
+async fn a_delivery_is_claimed_once_per_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_confirmed_subscriber("ursula@gmail.com").await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": TITLE,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    // Requeue the delivery that already went out.
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, s.email
+        FROM newsletter_issues, subscriptions s
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET status = 'in_progress'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issues_received(&app).await["ursula@gmail.com"], 1);
+    let claims = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM delivery_claims"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(claims.count, 1);
+}
@@ -0,0 +1,198 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_issue(app: &TestApp, title: &str) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status)
+        VALUES ($1, $2, 'Issue body', '<p>Issue body</p>', now(), 'completed')
+        "#,
+        issue_id,
+        title
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    issue_id
+}
+
+async fn record_delivery(app: &TestApp, issue_id: Uuid, email: &str, succeeded: bool) {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_deliveries
+            (newsletter_issue_id, subscriber_email, attempted_at, succeeded)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        issue_id,
+        email,
+        succeeded
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+fn relative(link: &reqwest::Url) -> String {
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+async fn resend(app: &TestApp, status_page: &reqwest::Url) -> reqwest::Response {
+    let mut link = status_page.clone();
+    link.set_path("/subscriptions/deliveries/resend");
+    app.api_client.post(link).send().await.unwrap()
+}
+
+#[tokio::test]
+async fn the_status_page_requires_a_known_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/deliveries?subscription_token=unknown",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_status_page_shows_what_happened_to_each_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin@gmail.com";
+    let status_page = app
+        .create_confirmed_subscriber(email)
+        .await
+        .page("/subscriptions/deliveries");
+    let sent = insert_issue(&app, "Sent issue").await;
+    record_delivery(&app, sent, email, true).await;
+    let failed = insert_issue(&app, "Failed issue").await;
+    record_delivery(&app, failed, email, false).await;
+
+    // Act
+    let response = app.api_client.get(status_page).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"<a href="/archive/{}">Sent issue</a></td><td>"#,
+        sent
+    )));
+    assert!(html.contains("<td>Sent</td>"));
+    assert!(html.contains("<td>Could not be sent</td>"));
+    assert!(html.contains("Send me the latest issue"));
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_are_told_why_and_get_nothing_resent() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = "ursula_le_guin@gmail.com";
+    let status_page = app
+        .create_confirmed_subscriber(email)
+        .await
+        .page("/subscriptions/deliveries");
+    insert_issue(&app, "Latest issue").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressed_emails (email, reason, suppressed_at)
+        VALUES ($1, 'hard_bounce', now())
+        "#,
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let html = app
+        .api_client
+        .get(status_page.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let response = resend(&app, &status_page).await;
+
+    // Assert
+    assert!(html.contains("We stopped sending our issues to ursula_le_guin@gmail.com"));
+    assert!(html.contains("your mail server refused one of our emails"));
+    assert!(html.contains("<td>Not sent: your address is blocked</td>"));
+    assert!(!html.contains("Send me the latest issue"));
+    assert_is_redirect_to(&response, &relative(&status_page));
+}
+
+#[tokio::test]
+async fn the_latest_issue_can_be_resent_a_limited_number_of_times() {
+    // Arrange
+    let app = spawn_app_with(|c| c.resends.max_per_day = 1).await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await
+        .page("/subscriptions/deliveries");
+    insert_issue(&app, "Older issue").await;
+    insert_issue(&app, "Latest issue").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Ask for the latest issue
+    let response = resend(&app, &status_page).await;
+    assert_is_redirect_to(&response, &relative(&status_page));
+    let html = app
+        .api_client
+        .get(status_page.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("The latest issue is on its way to your inbox."));
+
+    // Act - Part 2 - Ask again
+    resend(&app, &status_page).await;
+    let html = app
+        .api_client
+        .get(status_page)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Please try again tomorrow."));
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["messages"][0]["Subject"], "Latest issue");
+}
@@ -0,0 +1,216 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::digests::DigestOutcome;
+
+async fn set_frequency(preferences: &reqwest::Url, frequency: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(preferences.clone())
+        .form(&[("frequency", frequency)])
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn publish_issue(app: &TestApp, title: &str) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": format!("{} as plain text", title),
+            "html_content": format!("<p>{} as HTML</p>", title),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+/// As if the period of the digest had passed.
+async fn make_digest_due(app: &TestApp, subscriber_id: Uuid) {
+    sqlx::query!(
+        "UPDATE subscriptions SET last_digest_at = last_digest_at - interval '8 days' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn unknown_frequencies_are_not_echoed_back() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/preferences");
+    let client = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    // Act - the redirect to the preferences page is followed
+    let html = client
+        .post(preferences.clone())
+        .form(&[("frequency", "<script>alert(1)</script>")])
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html.contains("<p><i>Please pick one of the offered frequencies.</i></p>"));
+    assert!(!html.contains("<script>alert(1)</script>"));
+}
+
+#[tokio::test]
+async fn subscribers_choose_their_frequency_on_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/preferences");
+
+    // Act
+    let response = set_frequency(&preferences, "weekly").await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("{}?{}", preferences.path(), preferences.query().unwrap()),
+    );
+    let html = reqwest::get(preferences.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"value="weekly" checked"#));
+    assert!(!html.contains(r#"value="immediate" checked"#));
+}
+
+#[tokio::test]
+async fn digest_subscribers_are_left_out_of_each_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    let preferences = app
+        .create_confirmed_subscriber("ged@example.com")
+        .await
+        .page("/subscriptions/preferences");
+    set_frequency(&preferences, "monthly").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let issue_id = publish_issue(&app, "Issue #1").await;
+
+    // Assert
+    let recipients = sqlx::query!(
+        r#"
+        SELECT m.subscriber_email
+        FROM recipient_snapshots s
+        JOIN recipient_snapshot_members m USING (snapshot_id)
+        WHERE s.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(recipients.len(), 1);
+    assert_eq!(
+        app.pii.open_email(&recipients[0].subscriber_email).unwrap(),
+        "ursula@example.com"
+    );
+}
+
+#[tokio::test]
+async fn digests_compile_the_issues_published_since_the_last_one() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/preferences");
+    app.test_user.login(&app).await;
+    let earlier_issue = publish_issue(&app, "Issue #0").await;
+    set_frequency(&preferences, "weekly").await;
+    publish_issue(&app, "Issue #1").await;
+    publish_issue(&app, "Issue #2").await;
+    make_digest_due(&app, subscriber_id).await;
+    // Issue #0 was delivered before the subscriber chose a digest.
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = published_at - interval '9 days' WHERE newsletter_issue_id = $1",
+        earlier_issue
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcomes = app.send_due_digests().await;
+
+    // Assert
+    assert_eq!(outcomes, vec![DigestOutcome::Sent]);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let message = &body["messages"][0];
+    assert_eq!(message["Subject"], "Your weekly digest: 2 issues");
+    let text = message["TextPart"].as_str().unwrap();
+    assert!(text.contains("Issue #1 as plain text"));
+    assert!(text.contains("Issue #2 as plain text"));
+    assert!(!text.contains("Issue #0"));
+    assert!(message["HtmlPart"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Issue #2 as HTML</p>"));
+    // The next digest is a week away.
+    assert!(app.send_due_digests().await.is_empty());
+}
+
+#[tokio::test]
+async fn no_digest_is_sent_when_nothing_was_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/preferences");
+    set_frequency(&preferences, "weekly").await;
+    make_digest_due(&app, subscriber_id).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcomes = app.send_due_digests().await;
+
+    // Assert
+    assert_eq!(outcomes, vec![DigestOutcome::Empty]);
+    assert!(app.send_due_digests().await.is_empty());
+}
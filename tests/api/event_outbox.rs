@@ -0,0 +1,137 @@
+use crate::helpers::spawn_app;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+use zero2prod::configuration::{BrokerSettings, EventExportSettings};
+use zero2prod::events::{store_outbox_event, try_relay_batch, Broker, DomainEvent, RelayOutcome};
+
+/// A NATS stand-in whose JetStream acknowledges everything and that reports
+/// the subjects it received messages on.
+async fn spawn_fake_nats() -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut socket = BufReader::new(socket);
+        socket.write_all(b"INFO {}\r\n").await.unwrap();
+        loop {
+            let mut line = String::new();
+            if socket.read_line(&mut line).await.unwrap() == 0 {
+                return;
+            }
+            let parts: Vec<_> = line.split_whitespace().collect();
+            match parts.first().copied() {
+                Some("PING") => socket.write_all(b"PONG\r\n").await.unwrap(),
+                Some("HPUB") => {
+                    let total_len: usize = parts[4].parse().unwrap();
+                    let mut message = vec![0; total_len + 2];
+                    socket.read_exact(&mut message).await.unwrap();
+                    tx.send(parts[1].to_string()).unwrap();
+                    let ack = r#"{"stream":"EVENTS","seq":1}"#;
+                    let reply = format!("MSG {} 1 {}\r\n{}\r\n", parts[2], ack.len(), ack);
+                    socket.write_all(reply.as_bytes()).await.unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+    (address, rx)
+}
+
+fn export_settings(url: String) -> EventExportSettings {
+    EventExportSettings {
+        broker: BrokerSettings::Nats { url },
+        subject_prefix: "zero2prod".into(),
+        batch_size: 10,
+        poll_interval_milliseconds: 10,
+    }
+}
+
+#[tokio::test]
+async fn outbox_events_are_relayed_in_order_and_marked_as_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let (address, mut subjects) = spawn_fake_nats().await;
+    let settings = export_settings(address);
+    let subscriber_id = Uuid::new_v4();
+    store_outbox_event(
+        &app.db_pool,
+        &DomainEvent::SubscriberConfirmed { subscriber_id },
+    )
+    .await
+    .unwrap();
+    store_outbox_event(
+        &app.db_pool,
+        &DomainEvent::IssueSent {
+            issue_id: Uuid::new_v4(),
+            title: "Issue #1".into(),
+            recipients: 1,
+        },
+    )
+    .await
+    .unwrap();
+    let mut connection = Broker::from_settings(&settings.broker)
+        .connect()
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = try_relay_batch(&app.db_pool, &mut connection, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(matches!(outcome, RelayOutcome::Relayed(2)));
+    assert_eq!(
+        subjects.recv().await.unwrap(),
+        "zero2prod.subscriber_confirmed"
+    );
+    assert_eq!(subjects.recv().await.unwrap(), "zero2prod.issue_sent");
+    let unpublished =
+        sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM events_outbox WHERE published_at IS NULL")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(unpublished, 0);
+
+    // An empty outbox has nothing left to relay
+    let outcome = try_relay_batch(&app.db_pool, &mut connection, &settings)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, RelayOutcome::EmptyOutbox));
+}
+
+#[tokio::test]
+async fn only_one_relay_works_through_the_outbox_at_a_time() {
+    // Arrange
+    let app = spawn_app().await;
+    let (address, _subjects) = spawn_fake_nats().await;
+    let settings = export_settings(address);
+    store_outbox_event(
+        &app.db_pool,
+        &DomainEvent::SubscriberConfirmed {
+            subscriber_id: Uuid::new_v4(),
+        },
+    )
+    .await
+    .unwrap();
+    let mut connection = Broker::from_settings(&settings.broker)
+        .connect()
+        .await
+        .unwrap();
+    let mut other_relay = app.db_pool.begin().await.unwrap();
+    sqlx::query!("SELECT pg_advisory_xact_lock(hashtextextended('events_outbox', 0))")
+        .execute(&mut *other_relay)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = try_relay_batch(&app.db_pool, &mut connection, &settings)
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(matches!(outcome, RelayOutcome::Busy));
+}
@@ -1,12 +1,38 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use chrono::Utc;
 use once_cell::sync::Lazy;
+use reqwest::cookie::{CookieStore, Jar};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::authentication::{CSRF_COOKIE, CSRF_HEADER};
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, RssSettings, Settings, SignupProtectionSettings,
+    SmsSettings, TelegramSettings,
+};
+use zero2prod::digests::{try_send_next_digest, DigestOutcome};
+use zero2prod::domain_throttle::DomainThrottle;
+use zero2prod::email_client::EmailClient;
+use zero2prod::events::EventBus;
+use zero2prod::issue_delivery_worker::{
+    try_execute_task, DeliveryPolicy, ExecutionOutcome, LaneScheduler,
+};
+use zero2prod::landing_analytics::LandingLinks;
+use zero2prod::operations::{try_execute_next_step, StepOutcome};
+use zero2prod::pii::PiiCipher;
+use zero2prod::polls::PollLinks;
+use zero2prod::rss::{check_feed, CheckOutcome, RssChecker};
+use zero2prod::signup_protection::{try_detect_signup_spikes, SignupSpike};
+use zero2prod::sms::{try_send_next, CountryRateLimiter, SmsOutcome, SmsProvider};
 use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::telegram::{try_post_next, PostOutcome, TelegramClient};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::token_signer::TokenSigner;
+use zero2prod::web_push::WebPush;
+use zero2prod::web_version::WebVersion;
 
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -28,6 +54,19 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub event_bus: EventBus,
+    pub delivery_policy: DeliveryPolicy,
+    pub cookie_jar: Arc<Jar>,
+    pub pii: PiiCipher,
+    pub web_version: WebVersion,
+    pub poll_links: PollLinks,
+    pub landing_links: Option<LandingLinks>,
+    pub web_push: Option<WebPush>,
+    pub sms: Option<(SmsSettings, Arc<dyn SmsProvider>)>,
+    pub telegram: Option<(TelegramSettings, TelegramClient)>,
+    pub rss: Option<(RssSettings, RssChecker)>,
+    pub signup_protection: Option<SignupProtectionSettings>,
 }
 
 /// Confirmation links embedded in the request to the email API.
@@ -36,10 +75,211 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+pub struct ConfirmedSubscriber {
+    pub id: Uuid,
+    /// The confirmation link, whose token also opens the subscriber's own
+    /// pages.
+    pub link: reqwest::Url,
+}
+
+impl ConfirmedSubscriber {
+    /// The subscriber's page at `path`, e.g. `/subscriptions/preferences`.
+    pub fn page(&self, path: &str) -> reqwest::Url {
+        let mut link = self.link.clone();
+        link.set_path(path);
+        link
+    }
+}
+
 impl TestApp {
+    /// The token of the CSRF cookie, which the admin pages also render into
+    /// their forms. The cookie comes with the first admin response.
+    pub async fn csrf_token(&self) -> String {
+        let url = reqwest::Url::parse(&format!("{}/admin/", self.address)).unwrap();
+        let read = || {
+            self.cookie_jar.cookies(&url).and_then(|cookies| {
+                cookies.to_str().unwrap().split("; ").find_map(|cookie| {
+                    cookie
+                        .strip_prefix(CSRF_COOKIE)
+                        .and_then(|c| c.strip_prefix('='))
+                        .map(str::to_owned)
+                })
+            })
+        };
+        if let Some(token) = read() {
+            return token;
+        }
+        self.get_send_quota().await;
+        read().unwrap_or_default()
+    }
+
+    pub async fn dispatch_all_pending_emails(&self) {
+        let mut lanes = LaneScheduler::default();
+        let domains = DomainThrottle::default();
+        loop {
+            match try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.pii,
+                &self.web_version,
+                &self.poll_links,
+                self.landing_links.as_ref(),
+                self.web_push.as_ref(),
+                &self.event_bus,
+                &self.delivery_policy,
+                &mut lanes,
+                &domains,
+            )
+            .await
+            .unwrap()
+            {
+                ExecutionOutcome::EmptyQueue
+                | ExecutionOutcome::DailyQuotaReached
+                | ExecutionOutcome::Throttled => break,
+                ExecutionOutcome::TaskCompleted => {}
+            }
+        }
+    }
+
+    /// Send every queued text, ignoring the rate limits.
+    pub async fn send_all_pending_texts(&self) {
+        let (settings, provider) = self
+            .sms
+            .as_ref()
+            .expect("The SMS channel is not configured");
+        let mut limits = settings.rate_limits.clone();
+        limits.default_per_minute = u32::MAX;
+        limits.countries.clear();
+        loop {
+            match try_send_next(
+                &self.db_pool,
+                provider.as_ref(),
+                &self.pii,
+                &mut CountryRateLimiter::default(),
+                &limits,
+            )
+            .await
+            .unwrap()
+            {
+                SmsOutcome::EmptyQueue | SmsOutcome::Throttled => break,
+                SmsOutcome::Sent => {}
+            }
+        }
+    }
+
+    /// Post every issue due to the Telegram channel. The ones failing are
+    /// left for a later attempt.
+    pub async fn post_pending_telegram_posts(&self) {
+        let (settings, client) = self
+            .telegram
+            .as_ref()
+            .expect("The Telegram mirror is not configured");
+        while !matches!(
+            try_post_next(&self.db_pool, client, &self.address, settings.max_attempts)
+                .await
+                .unwrap(),
+            PostOutcome::EmptyQueue
+        ) {}
+    }
+
+    /// Send every digest due.
+    pub async fn send_due_digests(&self) -> Vec<DigestOutcome> {
+        let mut outcomes = Vec::new();
+        loop {
+            let outcome = try_send_next_digest(
+                &self.db_pool,
+                &self.email_client,
+                &self.pii,
+                &self.web_version,
+                &self.poll_links,
+            )
+            .await
+            .unwrap();
+            if outcome == DigestOutcome::NoneDue {
+                return outcomes;
+            }
+            outcomes.push(outcome);
+        }
+    }
+
+    /// Check every RSS feed once, whether due or not.
+    pub async fn check_rss_feeds(&self) -> Vec<CheckOutcome> {
+        let (settings, checker) = self.rss.as_ref().expect("No RSS feed is configured");
+        let mut outcomes = Vec::new();
+        for feed in &settings.feeds {
+            outcomes.push(check_feed(&self.db_pool, checker, feed).await.unwrap());
+        }
+        outcomes
+    }
+
+    /// The signup spikes detected for the first time.
+    pub async fn detect_signup_spikes(&self) -> Vec<SignupSpike> {
+        let settings = self
+            .signup_protection
+            .as_ref()
+            .expect("Signup protection is not configured");
+        try_detect_signup_spikes(&self.db_pool, &self.event_bus, settings, Utc::now())
+            .await
+            .unwrap()
+    }
+
+    /// Carry out the admin operations under way, until they are done or
+    /// failed.
+    pub async fn execute_pending_operations(&self) {
+        while !matches!(
+            try_execute_next_step(&self.db_pool, &self.pii)
+                .await
+                .unwrap(),
+            StepOutcome::EmptyQueue
+        ) {}
+    }
+
+    /// Subscribe `email` and follow the confirmation link.
+    pub async fn create_confirmed_subscriber(&self, email: &str) -> ConfirmedSubscriber {
+        self.create_confirmed_subscriber_with(&format!(
+            "name=le%20guin&email={}",
+            urlencoding::encode(email)
+        ))
+        .await
+    }
+
+    /// Subscribe with the form `body` and follow the confirmation link.
+    pub async fn create_confirmed_subscriber_with(&self, body: &str) -> ConfirmedSubscriber {
+        // Answers the confirmation email unless the test mounted its own
+        // mock first.
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body.to_owned())
+            .await
+            .error_for_status()
+            .unwrap();
+        let email_request = &self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let link = self.get_confirmation_links(email_request).html;
+        confirm_subscription(link.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        let id = sqlx::query!("SELECT id FROM subscriptions ORDER BY subscribed_at DESC LIMIT 1")
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .id;
+        ConfirmedSubscriber { id, link }
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -52,16 +292,25 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_login_magic_link(&self, username: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/magic-link", &self.address))
+            .form(&[("username", username)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -72,7 +321,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -82,9 +331,17 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    pub async fn get_deliverability_dns(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/deliverability/dns", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -96,7 +353,8 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -107,7 +365,8 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -116,7 +375,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -131,13 +390,535 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_clone_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/clone",
+                &self.address, issue_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_save_as_template(&self, issue_id: Uuid, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/save-as-template",
+                &self.address, issue_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(&[("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_publish_newsletter_form_html(&self, query: &str) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_check_links(&self, html_content: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters/check-links", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(&[("html_content", html_content)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_spam_check<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/spam-check", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_queues(&self) -> serde_json::Value {
+        self.api_client
+            .get(format!("{}/admin/queues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_audit_analytics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit/analytics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_quarantine_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/quarantine", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `subscriber_id` is repeated for every signup, which a map cannot
+    /// serialise.
+    pub async fn post_review_quarantine(&self, action: &str, ids: &[Uuid]) -> reqwest::Response {
+        let mut body = format!("action={}", action);
+        for id in ids {
+            body.push_str(&format!("&subscriber_id={}", id));
+        }
+        self.api_client
+            .post(format!("{}/admin/quarantine", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_referral_leaderboard(&self) -> serde_json::Value {
+        self.api_client
+            .get(format!("{}/admin/referrals", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_retry_failed(&self, queue: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/queues/{}/retry-failed",
+                &self.address, queue
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_snippets_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/snippets", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_snippet<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/snippets", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sms_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sms", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_sms_blast(&self, message: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sms", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(&serde_json::json!({ "message": message }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sponsor_report(
+        &self,
+        sponsor: &str,
+        from: &str,
+        to: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/sponsors/{}/report",
+                &self.address, sponsor
+            ))
+            .query(&[("from", from), ("to", to)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_send_quota(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/quota", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_consent(&self, subscriber_id: Uuid) -> serde_json::Value {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}/consent",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_admin_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/sessions/{}/revoke",
+                &self.address, session_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_other_admin_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke-others", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reload_settings(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/settings/reload", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/delete",
+                &self.address, subscriber_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_subscriber_links(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/reset-links",
+                &self.address, subscriber_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_operations_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/operations", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_operation(&self, kind: &str, items: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/operations", &self.address))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(&serde_json::json!({ "kind": kind, "items": items }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_roll_back_operation(&self, operation_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/operations/{}/roll-back",
+                &self.address, operation_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_merge_subscriber(
+        &self,
+        subscriber_id: Uuid,
+        duplicate_id: Uuid,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/merge",
+                &self.address, subscriber_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(&serde_json::json!({ "duplicate_id": duplicate_id }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_restore_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/restore",
+                &self.address, subscriber_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_view_as_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}/view-as",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resume_newsletter_delivery(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/resume",
+                &self.address, issue_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_issue_variant<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/variants",
+                &self.address, issue_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_newsletter_issue_report(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}/report",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_seed_placement<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/seeds",
+                &self.address, issue_id
+            ))
+            .header(CSRF_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_seed_placement_webhook(
+        &self,
+        token: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/seed-placements", &self.address))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delivery_events(&self, token: &str, body: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/delivery-events", &self.address))
+            .basic_auth("provider", Some(token))
+            .header("Content-Type", "application/json")
+            .body(body.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email(&self, api_key: &str, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/api/verify-email", &self.address))
+            .bearer_auth(api_key)
+            .json(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// A visit reported by a page served from `origin`, if any.
+    pub async fn post_landing_visit(
+        &self,
+        origin: Option<&str>,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .post(format!("{}/api/analytics/visit", &self.address))
+            .json(body);
+        if let Some(origin) = origin {
+            request = request.header("Origin", origin);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_key_usage(&self, key_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/api-keys/{}/usage", &self.address, key_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_signup_page(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/l/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/archive/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_archive_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/archive/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_billing_checkout(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/billing/checkout", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_stripe_webhook(&self, payload: &str, signature: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/stripe", &self.address))
+            .header("Stripe-Signature", signature)
+            .body(payload.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_notifications(&self, events: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/notifications", &self.address))
+            .query(&[("events", events)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in the request to the email API.
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
@@ -157,13 +938,18 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        let html = get_link(body["messages"][0]["HtmlPart"].as_str().unwrap());
+        let plain_text = get_link(body["messages"][0]["TextPart"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after tweaking its configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     // Launch a mock server to stand in for Postmark's API
@@ -178,6 +964,11 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Don't hang on DNS lookups that are not going to succeed in CI
+        c.deliverability.dns_timeout_milliseconds = 500;
+        // The mock image servers listen on localhost
+        c.image_proxy.allow_private_hosts = true;
+        configure(&mut c);
         c
     };
 
@@ -189,14 +980,17 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let event_bus = application.event_bus();
+    tokio::spawn(application.run_until_stopped());
 
+    let cookie_jar = Arc::new(Jar::default());
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
+        .cookie_provider(cookie_jar.clone())
         .build()
         .unwrap();
 
+    let signer = Arc::new(TokenSigner::new(&configuration.token_signing).unwrap());
     let test_app = TestApp {
         address: format!("http://localhost:{}", application_port),
         port: application_port,
@@ -206,6 +1000,39 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        email_client: configuration
+            .build_email_client()
+            .expect("Failed to build the email client"),
+        event_bus,
+        delivery_policy: DeliveryPolicy::from_settings(&configuration),
+        cookie_jar,
+        pii: PiiCipher::new(configuration.pii_encryption.as_ref()).unwrap(),
+        web_version: WebVersion::new(configuration.application.base_url.clone(), signer.clone()),
+        poll_links: PollLinks::new(configuration.application.base_url.clone(), signer.clone()),
+        landing_links: configuration.landing_analytics.as_ref().map(|settings| {
+            LandingLinks::new(settings, signer).expect("Failed to build the landing links")
+        }),
+        web_push: configuration
+            .web_push
+            .clone()
+            .map(WebPush::new)
+            .transpose()
+            .expect("Failed to build the Web Push client"),
+        sms: configuration.sms.clone().map(|settings| {
+            let provider = settings.provider().expect("Failed to build the SMS client");
+            (settings, provider)
+        }),
+        telegram: configuration.telegram.clone().map(|settings| {
+            let client =
+                TelegramClient::new(&settings).expect("Failed to build the Telegram client");
+            (settings, client)
+        }),
+        rss: configuration.rss.clone().map(|settings| {
+            let checker =
+                RssChecker::new(&configuration, &settings).expect("Failed to build the RSS client");
+            (settings, checker)
+        }),
+        signup_protection: configuration.signup_protection.clone(),
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -250,6 +1077,28 @@ impl TestUser {
         }
     }
 
+    pub async fn set_email(&self, app: &TestApp, email: &str) {
+        sqlx::query!(
+            "UPDATE users SET email = $1 WHERE user_id = $2",
+            email,
+            self.user_id
+        )
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the test user email.");
+    }
+
+    pub async fn set_role(&self, app: &TestApp, role: &str) {
+        sqlx::query!(
+            "UPDATE users SET role = $1 WHERE user_id = $2",
+            role,
+            self.user_id
+        )
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the test user role.");
+    }
+
     pub async fn login(&self, app: &TestApp) {
         app.post_login(&serde_json::json!({
             "username": &self.username,
@@ -286,3 +1135,10 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+/// Submit the form behind a confirmation link, as the subscriber does.
+pub async fn confirm_subscription(
+    confirmation_link: reqwest::Url,
+) -> Result<reqwest::Response, reqwest::Error> {
+    reqwest::Client::new().post(confirmation_link).send().await
+}
@@ -0,0 +1,157 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use secrecy::Secret;
+use zero2prod::configuration::{ApiKeySettings, ApiSettings, ApiTierSettings};
+
+const API_KEY: &str = "signup-form-key";
+
+async fn spawn_app_with_api() -> TestApp {
+    spawn_app_with(|c| {
+        c.api = Some(ApiSettings {
+            keys: vec![ApiKeySettings {
+                id: "signup-form".into(),
+                key: Secret::new(API_KEY.into()),
+                tier: "internal".into(),
+            }],
+            tiers: [(
+                "internal".into(),
+                ApiTierSettings {
+                    requests_per_minute: 1000,
+                    requests_per_day: 100_000,
+                },
+            )]
+            .into(),
+        });
+    })
+    .await
+}
+
+async fn post_verify_email(app: &TestApp, idempotency_key: &str, email: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/verify-email", &app.address))
+        .bearer_auth(API_KEY)
+        .header("Idempotency-Key", idempotency_key)
+        .json(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn saved_responses(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_retry_gets_the_saved_response() {
+    let app = spawn_app_with_api().await;
+
+    let first = post_verify_email(&app, "retry-1", "ursula@example.com").await;
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first_body = first.text().await.unwrap();
+    let retry = post_verify_email(&app, "retry-1", "ursula@example.com").await;
+
+    assert_eq!(retry.status().as_u16(), 200);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    assert_eq!(retry.text().await.unwrap(), first_body);
+    assert_eq!(saved_responses(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_another_request_is_rejected() {
+    let app = spawn_app_with_api().await;
+    post_verify_email(&app, "retry-1", "ursula@example.com").await;
+
+    let response = post_verify_email(&app, "retry-1", "le_guin@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn requests_without_a_key_are_not_saved() {
+    let app = spawn_app_with_api().await;
+
+    let response = app.post_verify_email(API_KEY, "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved_responses(&app).await, 0);
+}
+
+#[tokio::test]
+async fn malformed_keys_are_rejected() {
+    let app = spawn_app_with_api().await;
+
+    let response = post_verify_email(&app, &"k".repeat(256), "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+/// The row a request leaves while its handler runs, claimed `minutes_ago`.
+async fn claim_key(app: &TestApp, idempotency_key: &str, email: &str, minutes_ago: i64) {
+    let body = serde_json::json!({ "email": email }).to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (caller, idempotency_key, route, request_hash, created_at)
+        VALUES ('signup-form', $1, 'POST /api/verify-email', sha256($2), now() - make_interval(mins => $3))
+        "#,
+        idempotency_key,
+        body.as_bytes(),
+        minutes_ago as i32
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn concurrent_duplicates_are_processed_once() {
+    let app = spawn_app_with_api().await;
+
+    let (first, second) = tokio::join!(
+        post_verify_email(&app, "retry-1", "ursula@example.com"),
+        post_verify_email(&app, "retry-1", "ursula@example.com"),
+    );
+
+    // The second one either arrived while the first was being processed, or
+    // got its saved response.
+    let processed = [&first, &second]
+        .iter()
+        .filter(|response| {
+            response.status().as_u16() == 200
+                && !response.headers().contains_key("idempotent-replayed")
+        })
+        .count();
+    assert_eq!(processed, 1);
+    for response in [&first, &second] {
+        assert!([200, 409].contains(&response.status().as_u16()));
+    }
+}
+
+#[tokio::test]
+async fn a_retry_while_the_request_is_processed_gets_a_conflict() {
+    let app = spawn_app_with_api().await;
+    claim_key(&app, "retry-1", "ursula@example.com", 0).await;
+
+    let retry = post_verify_email(&app, "retry-1", "ursula@example.com").await;
+
+    assert_eq!(retry.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn an_abandoned_claim_is_taken_over() {
+    let app = spawn_app_with_api().await;
+    claim_key(&app, "retry-1", "ursula@example.com", 10).await;
+
+    let retry = post_verify_email(&app, "retry-1", "ursula@example.com").await;
+
+    assert_eq!(retry.status().as_u16(), 200);
+    assert!(retry.headers().get("idempotent-replayed").is_none());
+    let saved = sqlx::query!("SELECT response_status FROM idempotency")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.response_status, Some(200));
+}
@@ -0,0 +1,292 @@
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, ConfirmedSubscriber, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::LandingAnalyticsSettings;
+
+const WEBSITE: &str = "https://shop.example.com";
+
+async fn spawn_app_with_landing_analytics() -> TestApp {
+    spawn_app_with(|c| {
+        c.landing_analytics = Some(LandingAnalyticsSettings {
+            allowed_origins: vec![WEBSITE.into()],
+            utm_source: "newsletter".into(),
+        })
+    })
+    .await
+}
+
+/// Publish an issue linking to the website and to elsewhere, and deliver
+/// it to `ursula@example.com`.
+async fn deliver_issue(app: &TestApp) -> Uuid {
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    publish_issue(app).await
+}
+
+/// Publish an issue linking to the website and to elsewhere, and deliver
+/// it to the subscribers.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(app).await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": format!("Our spring sale: {}/sale", WEBSITE),
+            "html_content": format!(
+                r#"<p><a href="{}/sale">Our spring sale</a>, <a href="https://elsewhere.example.com/">elsewhere</a></p>"#,
+                WEBSITE
+            ),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+/// The link to the website in the issue delivered last.
+async fn website_link(app: &TestApp) -> reqwest::Url {
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    linkify::LinkFinder::new()
+        .links(body["messages"][0]["TextPart"].as_str().unwrap())
+        .filter_map(|link| reqwest::Url::parse(link.as_str()).ok())
+        .find(|link| link.as_str().starts_with(WEBSITE))
+        .unwrap()
+}
+
+/// Tick the opt-out of tracking on the preferences page of `subscriber`.
+async fn opt_out_of_tracking(subscriber: &ConfirmedSubscriber) {
+    reqwest::Client::new()
+        .post(subscriber.page("/subscriptions/preferences"))
+        .form(&[("frequency", "immediate"), ("no_tracking", "on")])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn landing_visits(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM landing_visits")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+/// The JSON body the website's script sends for `link`.
+fn visit(link: &reqwest::Url, kind: &str) -> serde_json::Value {
+    let parameter = |name: &str| {
+        link.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    serde_json::json!({
+        "click_token": parameter("z2p_click").unwrap(),
+        "kind": kind,
+        "utm_source": parameter("utm_source"),
+        "utm_medium": parameter("utm_medium"),
+        "utm_campaign": parameter("utm_campaign"),
+        "page": link.path(),
+    })
+}
+
+#[tokio::test]
+async fn links_to_the_website_are_tagged_for_each_recipient() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+
+    // Act
+    let issue_id = deliver_issue(&app).await;
+
+    // Assert
+    let link = website_link(&app).await;
+    let query: Vec<_> = link.query_pairs().collect();
+    assert_eq!(query[0], ("utm_source".into(), "newsletter".into()));
+    assert_eq!(query[1], ("utm_medium".into(), "email".into()));
+    assert_eq!(
+        query[2],
+        ("utm_campaign".into(), issue_id.to_string().into())
+    );
+    assert_eq!(query[3].0, "z2p_click");
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert!(body["messages"][0]["HtmlPart"]
+        .as_str()
+        .unwrap()
+        .contains(r#"<a href="https://elsewhere.example.com/">"#));
+}
+
+#[tokio::test]
+async fn visits_and_conversions_show_in_the_issue_report() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+    let issue_id = deliver_issue(&app).await;
+    let link = website_link(&app).await;
+
+    // Act
+    for kind in ["visit", "visit", "conversion"] {
+        let response = app
+            .post_landing_visit(Some(WEBSITE), &visit(&link, kind))
+            .await;
+        assert_eq!(response.status().as_u16(), 204);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], WEBSITE);
+    }
+
+    // Assert
+    let report: serde_json::Value = app
+        .get_newsletter_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let landing = &report["landing"];
+    assert_eq!(landing["visits"], 2);
+    assert_eq!(landing["visitors"], 1);
+    assert_eq!(landing["conversions"], 1);
+    assert_eq!(landing["converted_visitors"], 1);
+    assert_eq!(
+        landing["campaigns"][0]["utm_campaign"],
+        issue_id.to_string()
+    );
+    assert_eq!(landing["campaigns"][0]["visits"], 2);
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_get_links_without_a_click_token() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    opt_out_of_tracking(&subscriber).await;
+
+    // Act
+    publish_issue(&app).await;
+
+    // Assert
+    let link = website_link(&app).await;
+    assert!(link.query_pairs().any(|(key, _)| key == "utm_source"));
+    assert!(!link.query_pairs().any(|(key, _)| key == "z2p_click"));
+}
+
+#[tokio::test]
+async fn opting_out_forgets_the_visits_and_ignores_the_links_already_sent() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    publish_issue(&app).await;
+    let link = website_link(&app).await;
+    app.post_landing_visit(Some(WEBSITE), &visit(&link, "visit"))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(landing_visits(&app).await, 1);
+
+    // Act
+    opt_out_of_tracking(&subscriber).await;
+    let response = app
+        .post_landing_visit(Some(WEBSITE), &visit(&link, "visit"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(landing_visits(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_preflight_of_an_allowed_origin_is_answered() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+
+    // Act
+    let response = app
+        .api_client
+        .request(
+            reqwest::Method::OPTIONS,
+            format!("{}/api/analytics/visit", &app.address),
+        )
+        .header("Origin", WEBSITE)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "content-type")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(response.headers()["Access-Control-Allow-Origin"], WEBSITE);
+    assert_eq!(response.headers()["Access-Control-Allow-Methods"], "POST");
+}
+
+#[tokio::test]
+async fn visits_from_other_origins_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+    deliver_issue(&app).await;
+    let link = website_link(&app).await;
+
+    // Act
+    let response = app
+        .post_landing_visit(Some("https://evil.example.com"), &visit(&link, "visit"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn a_tampered_click_token_is_rejected() {
+    // Arrange
+    let app = spawn_app_with_landing_analytics().await;
+    deliver_issue(&app).await;
+    let link = website_link(&app).await;
+    let mut body = visit(&link, "visit");
+    let token = body["click_token"].as_str().unwrap().to_owned();
+    body["click_token"] = format!("{}x", token).into();
+
+    // Act
+    let response = app.post_landing_visit(None, &body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(landing_visits(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_endpoint_is_not_found_without_landing_analytics() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_landing_visit(Some(WEBSITE), &serde_json::json!({ "click_token": "x" }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
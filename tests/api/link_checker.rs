@@ -0,0 +1,74 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_check_links() {
+    let app = spawn_app().await;
+
+    let response = app.post_check_links("<p>Hi</p>").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn links_are_reported_as_ok_broken_or_redirected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let site = MockServer::start().await;
+    Mock::given(path("/ok"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&site)
+        .await;
+    Mock::given(path("/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&site)
+        .await;
+    Mock::given(path("/moved"))
+        .respond_with(
+            ResponseTemplate::new(301).insert_header("Location", "https://example.com/new"),
+        )
+        .mount(&site)
+        .await;
+    // Some servers don't implement HEAD: the link is only broken if GET fails too.
+    Mock::given(path("/no-head"))
+        .and(method("HEAD"))
+        .respond_with(ResponseTemplate::new(405))
+        .mount(&site)
+        .await;
+    Mock::given(path("/no-head"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&site)
+        .await;
+    let html = format!(
+        r#"<a href="{0}/ok">a</a> <a href="{0}/missing">b</a>
+        <a href="{0}/moved">c</a> <img src="{0}/no-head"> <a href="/relative">d</a>"#,
+        site.uri()
+    );
+
+    let response = app.post_check_links(&html).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["broken"], 1);
+    assert_eq!(report["redirected"], 1);
+    let status_of = |suffix: &str| {
+        report["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|link| link["url"] == format!("{}{}", site.uri(), suffix))
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(status_of("/ok")["status"], "ok");
+    assert_eq!(status_of("/no-head")["status"], "ok");
+    let missing = status_of("/missing");
+    assert_eq!(missing["status"], "broken");
+    assert_eq!(missing["code"], 404);
+    let moved = status_of("/moved");
+    assert_eq!(moved["status"], "redirected");
+    assert_eq!(moved["location"], "https://example.com/new");
+    assert_eq!(report["links"].as_array().unwrap().len(), 4);
+}
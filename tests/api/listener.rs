@@ -0,0 +1,28 @@
+use crate::helpers::spawn_app_with;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use uuid::Uuid;
+use zero2prod::configuration::ListenerSettings;
+
+#[tokio::test]
+async fn the_server_can_listen_on_a_unix_socket() {
+    let path = std::env::temp_dir().join(format!("zero2prod-{}.sock", Uuid::new_v4()));
+    // A leftover socket from an earlier run must not prevent startup.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let socket_path = path.clone();
+    let _app = spawn_app_with(|c| {
+        c.application.listener = ListenerSettings::Unix { path: socket_path };
+    })
+    .await;
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET /health_check HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,42 @@
+use crate::helpers::spawn_app_with;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn low_priority_routes_are_shed_under_overload_while_subscribing_works() {
+    // Any request is one too many.
+    let app = spawn_app_with(|c| c.load_shedding.max_in_flight_requests = 0).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/archive", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 503);
+    assert_eq!(response.headers()["Retry-After"], "1");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn low_priority_routes_are_served_below_the_thresholds() {
+    let app = spawn_app_with(|_| {}).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/archive", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+}
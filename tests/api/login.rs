@@ -1,4 +1,33 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::LoginMethods;
+
+async fn spawn_app_with_login_methods(methods: LoginMethods) -> TestApp {
+    let app = spawn_app_with(|c| c.login.methods = methods).await;
+    app.test_user.set_email(&app, "admin@gmail.com").await;
+    app
+}
+
+/// Request a login link for the test user and return it.
+async fn request_login_link(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_login_magic_link(&app.test_user.username).await;
+    assert_is_redirect_to(&response, "/login");
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request).html
+}
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -23,3 +52,127 @@ async fn an_error_flash_message_is_set_on_failure() {
     let html_page = app.get_login_html().await;
     assert!(!html_page.contains("Authentication failed"));
 }
+
+#[tokio::test]
+async fn a_magic_link_logs_the_admin_in() {
+    // Arrange
+    let app = spawn_app_with_login_methods(LoginMethods::PasswordAndMagicLink).await;
+    let login_link = request_login_link(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(login_link.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // The session cookie belongs to the host of the link.
+    let dashboard_url = login_link.join("/admin/dashboard").unwrap();
+    let html_page = app
+        .api_client
+        .get(dashboard_url)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn a_login_link_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app_with_login_methods(LoginMethods::MagicLink).await;
+    let login_link = request_login_link(&app).await;
+    reqwest::Client::new()
+        .post(login_link.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app.api_client.post(login_link).send().await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn following_a_login_link_does_not_use_it_up() {
+    // Arrange
+    let app = spawn_app_with_login_methods(LoginMethods::MagicLink).await;
+    let login_link = request_login_link(&app).await;
+
+    // Act - a mail scanner prefetches the link
+    let html_page = reqwest::get(login_link.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"<form action="/login/magic-link/confirm?token="#));
+    let response = app.api_client.post(login_link).send().await.unwrap();
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn login_links_are_not_sent_once_too_many_are_active() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.login.methods = LoginMethods::MagicLink;
+        c.login.max_active_magic_links = 2;
+    })
+    .await;
+    app.test_user.set_email(&app, "admin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for _ in 0..3 {
+        let response = app.post_login_magic_link(&app.test_user.username).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Assert - Mock verifies on Drop that only two links were sent
+}
+
+#[tokio::test]
+async fn magic_links_are_disabled_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_login_magic_link(&app.test_user.username).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn passwords_are_rejected_when_only_magic_links_are_enabled() {
+    // Arrange
+    let app = spawn_app_with_login_methods(LoginMethods::MagicLink).await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains(r#"name="password""#));
+}
@@ -1,9 +1,58 @@
+mod admin_allowlist;
 mod admin_dashboard;
+mod admin_notifications;
+mod admin_operations;
+mod admin_sessions;
+mod admin_subscribers;
+mod api_keys;
+mod archive;
+mod audit_analytics;
+mod billing;
 mod change_password;
+mod consent;
+mod csrf;
+mod deliverability;
+mod delivery_events;
+mod delivery_guard;
+mod delivery_status;
+mod digests;
+mod event_outbox;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod landing_analytics;
+mod link_checker;
+mod listener;
+mod load_shedding;
 mod login;
+mod metrics;
 mod newsletter;
+mod newsletter_drafts;
+mod pii;
+mod polls;
+mod push;
+mod quarantine;
+mod queues;
+mod referrals;
+mod reload;
+mod request_tracing;
+mod retention;
+mod rss;
+mod scim;
+mod seed_list;
+mod signup_page;
+mod signup_protection;
+mod slo;
+mod sms;
+mod snippets;
+mod spam_check;
+mod sponsorship;
+mod sso;
+mod startup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod telegram;
 mod test_user;
+mod theme;
+mod translations;
+mod verify_email;
@@ -1,9 +1,29 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, spawn_app_with, ConfirmationLinks, TestApp,
+};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{
+    DeliveryAlertSettings, DomainLimits, SendQuotaSettings, WarmUpSettings,
+};
+use zero2prod::issue_enqueue::resume_interrupted_enqueues;
+use zero2prod::warm_up::WarmUpSchedule;
 
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let email: String = SafeEmail().fake();
+    create_unconfirmed_subscriber_with_email(app, &email).await
+}
+
+async fn create_unconfirmed_subscriber_with_email(app: &TestApp, email: &str) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let body = format!(
+        "name={}&email={}",
+        urlencoding::encode(&name),
+        urlencoding::encode(email)
+    );
 
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
@@ -12,7 +32,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -28,12 +48,8 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
+    let email: String = SafeEmail().fake();
+    app.create_confirmed_subscriber(&email).await;
 }
 
 #[tokio::test]
@@ -61,6 +77,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // Act - Part 2 - Follow the redirect
     let html_page = app.get_publish_newsletter_html().await;
     assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
@@ -90,6 +107,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Act - Part 2 - Follow the redirect
     let html_page = app.get_publish_newsletter_html().await;
     assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
@@ -121,3 +139,481 @@ async fn you_must_be_logged_in_to_publish_a_newsletter() {
     // Assert
     assert_is_redirect_to(&response, "/login");
 }
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn issue_status(app: &TestApp) -> (String, i64) {
+    let issue = sqlx::query!("SELECT newsletter_issue_id, status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let pending = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE newsletter_issue_id = $1"#,
+        issue.newsletter_issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    (issue.status, pending)
+}
+
+#[tokio::test]
+async fn an_issue_is_marked_as_completed_once_every_email_went_out() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, ("completed".into(), 0));
+}
+
+#[tokio::test]
+async fn delivery_is_paused_when_the_failure_rate_exceeds_the_threshold() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.delivery_policy.alerts = Some(DeliveryAlertSettings {
+        failure_rate_threshold: 0.5,
+        min_attempts: 2,
+    });
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, ("paused".into(), 1));
+}
+
+#[tokio::test]
+async fn a_paused_issue_can_be_resumed() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.delivery_policy.alerts = Some(DeliveryAlertSettings {
+        failure_rate_threshold: 0.5,
+        min_attempts: 1,
+    });
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let failing = Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    drop(failing);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let response = app.post_resume_newsletter_delivery(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, ("completed".into(), 0));
+}
+
+#[tokio::test]
+async fn the_warm_up_schedule_caps_the_daily_volume() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.delivery_policy.warm_up = Some(WarmUpSchedule::new(WarmUpSettings {
+        start_date: chrono::Utc::now().date_naive(),
+        daily_limits: vec![2],
+    }));
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // The last delivery spills over to tomorrow.
+    assert_eq!(issue_status(&app).await, ("in_progress".into(), 1));
+}
+
+async fn spawn_app_with_monthly_quota(monthly_limit: i64) -> TestApp {
+    spawn_app_with(|c| c.send_quota = Some(SendQuotaSettings { monthly_limit })).await
+}
+
+#[tokio::test]
+async fn publishing_beyond_the_monthly_quota_is_rejected() {
+    // Arrange
+    let app = spawn_app_with_monthly_quota(1).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 402);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "monthly_quota_exceeded");
+    assert_eq!(body["requested"], 2);
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn the_monthly_quota_can_be_overridden() {
+    // Arrange
+    let app = spawn_app_with_monthly_quota(1).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "override_quota": true,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn the_quota_usage_includes_pending_deliveries() {
+    // Arrange
+    let app = spawn_app_with_monthly_quota(5).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+
+    // Act
+    let response = app.get_send_quota().await;
+
+    // Assert
+    let usage: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(usage["used"], 2);
+    assert_eq!(usage["remaining"], 3);
+}
+
+async fn publish_titled_newsletter(app: &TestApp, title: &str, priority: bool) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "priority": priority,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+/// The subjects of the newsletter emails received so far, in order.
+async fn delivered_subjects(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["messages"][0]["Subject"].as_str().unwrap().to_owned()
+        })
+        .filter(|subject| subject != "Welcome!")
+        .collect()
+}
+
+#[tokio::test]
+async fn priority_issues_are_delivered_ahead_of_bulk_ones() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_titled_newsletter(&app, "Bulk", false).await;
+    publish_titled_newsletter(&app, "Urgent", true).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        delivered_subjects(&app).await,
+        vec!["Urgent", "Urgent", "Bulk", "Bulk"]
+    );
+}
+
+#[tokio::test]
+async fn the_bulk_lane_is_not_starved_by_the_priority_lane() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.delivery_policy.lanes.max_consecutive_priority_tasks = 1;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_titled_newsletter(&app, "Bulk", false).await;
+    publish_titled_newsletter(&app, "Urgent", true).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(4)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        delivered_subjects(&app).await,
+        vec!["Urgent", "Bulk", "Urgent", "Bulk"]
+    );
+}
+
+#[tokio::test]
+async fn large_sends_are_enqueued_in_batches() {
+    // Arrange
+    let app = spawn_app_with(|c| c.delivery_enqueue.batch_size = 2).await;
+    for _ in 0..5 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&app).await;
+    assert_eq!(issue_status(&app).await, ("in_progress".into(), 5));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(issue_status(&app).await, ("completed".into(), 0));
+}
+
+#[tokio::test]
+async fn an_interrupted_enqueue_resumes_from_its_checkpoint() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    // Pretend we crashed after enqueueing the first subscriber.
+    let first = sqlx::query!(r#"SELECT MIN(email) AS "email!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email > $1",
+        first
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE newsletter_issues SET enqueue_cursor = $1, enqueue_completed = false",
+        first
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let mock = Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    // Half-enqueued issues are left alone by the worker.
+    app.dispatch_all_pending_emails().await;
+    drop(mock);
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let enqueued = resume_interrupted_enqueues(&app.db_pool, 1).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(enqueued, 2);
+    assert_eq!(issue_status(&app).await, ("completed".into(), 0));
+}
+
+#[tokio::test]
+async fn subscribers_confirming_mid_send_are_not_added_to_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    // Restart the enqueue from scratch once somebody else has confirmed.
+    sqlx::query!("DELETE FROM issue_delivery_queue")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET enqueue_cursor = NULL, enqueue_completed = false")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    resume_interrupted_enqueues(&app.db_pool, 10).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let snapshot = sqlx::query!("SELECT snapshot_id, recipient_count FROM recipient_snapshots")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(snapshot.recipient_count, 1);
+    let deliveries = sqlx::query!("SELECT snapshot_id FROM email_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].snapshot_id, Some(snapshot.snapshot_id));
+}
+
+#[tokio::test]
+async fn a_domain_at_its_rate_cap_does_not_hold_up_other_domains() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.domain_throttling.domains.insert(
+            "gmail.com".into(),
+            DomainLimits {
+                max_per_minute: 1,
+                max_concurrency: 1,
+            },
+        );
+    })
+    .await;
+    app.create_confirmed_subscriber("ursula@gmail.com").await;
+    app.create_confirmed_subscriber("le.guin@gmail.com").await;
+    app.create_confirmed_subscriber("ged@earthsea.org").await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0].subscriber_email.ends_with("@gmail.com"));
+}
+
+#[tokio::test]
+async fn a_throttled_delivery_is_requeued_and_shown_on_the_issue_report() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish_newsletter(&app).await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let report: serde_json::Value = app
+        .get_newsletter_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["pending"], 2);
+    assert_eq!(report["failed"], 0);
+    assert_eq!(report["provider_throttles"], 1);
+    assert!(report["throttled_until"].is_string());
+}
@@ -0,0 +1,150 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn publish(app: &TestApp, body: serde_json::Value) -> Uuid {
+    let response = app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues ORDER BY published_at DESC LIMIT 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+fn issue_with_poll() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Issue #1",
+        "text_content": "Hello\n{{poll}}",
+        "html_content": "<p>Hello</p>{{poll}}",
+        "paid_only": "true",
+        "poll_question": "Which island?",
+        "poll_options": "Gont\nRoke",
+    })
+}
+
+#[tokio::test]
+async fn a_cloned_issue_opens_as_a_draft_with_the_same_content_and_settings() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish(&app, issue_with_poll()).await;
+
+    // Act
+    let response = app.post_clone_newsletter(issue_id).await;
+
+    // Assert
+    let draft_id = sqlx::query!("SELECT draft_id, cloned_from FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(draft_id.cloned_from, Some(issue_id));
+    let location = format!("/admin/newsletters?draft={}", draft_id.draft_id);
+    assert_is_redirect_to(&response, &location);
+    let html_page = app
+        .get_publish_newsletter_form_html(&format!("draft={}", draft_id.draft_id))
+        .await;
+    assert!(html_page.contains(r#"value="Issue #1""#));
+    assert!(html_page.contains("&lt;p&gt;Hello&lt;/p&gt;{{poll}}"));
+    assert!(html_page.contains(r#"name="paid_only" value="true" checked"#));
+    assert!(html_page.contains(r#"value="Which island?""#));
+    assert!(html_page.contains(&format!(r#"name="draft_id" value="{}""#, draft_id.draft_id)));
+}
+
+#[tokio::test]
+async fn cloning_an_unknown_issue_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_clone_newsletter(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("There is no issue with the provided id."));
+}
+
+#[tokio::test]
+async fn publishing_a_draft_deletes_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish(&app, issue_with_poll()).await;
+    app.post_clone_newsletter(issue_id).await;
+    let draft_id = sqlx::query!("SELECT draft_id FROM newsletter_drafts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .draft_id;
+
+    // Act
+    let mut body = issue_with_poll();
+    body["title"] = "Issue #2".into();
+    body["draft_id"] = draft_id.to_string().into();
+    publish(&app, body).await;
+
+    // Assert
+    let drafts = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_drafts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(drafts.count, 0);
+}
+
+#[tokio::test]
+async fn an_issue_saved_as_a_template_can_start_a_new_draft() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish(&app, issue_with_poll()).await;
+
+    // Act
+    let response = app.post_save_as_template(issue_id, "Weekly digest").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The template Weekly digest has been saved."));
+    let template_id = sqlx::query!("SELECT template_id FROM issue_templates")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .template_id;
+    assert!(html_page.contains(&format!(
+        r#"<option value="{}">Weekly digest</option>"#,
+        template_id
+    )));
+    let html_page = app
+        .get_publish_newsletter_form_html(&format!("template={}", template_id))
+        .await;
+    assert!(html_page.contains(r#"value="Issue #1""#));
+    assert!(html_page.contains(r#"name="paid_only" value="true" checked"#));
+    assert!(!html_page.contains(r#"value="Which island?""#));
+    assert!(!html_page.contains(r#"name="draft_id""#));
+}
+
+#[tokio::test]
+async fn template_names_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish(&app, issue_with_poll()).await;
+    app.post_save_as_template(issue_id, "Weekly digest").await;
+    app.get_publish_newsletter_html().await;
+
+    // Act
+    let response = app.post_save_as_template(issue_id, "Weekly digest").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("There is already a template named Weekly digest."));
+    let templates = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_templates"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(templates.count, 1);
+}
@@ -0,0 +1,159 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::PiiEncryptionSettings;
+use zero2prod::pii::{rotate_keys, PiiCipher};
+
+const KEY_1: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+const KEY_2: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+fn keyring(active_key: &str, keys: &[(&str, &str)]) -> PiiEncryptionSettings {
+    PiiEncryptionSettings {
+        active_key: active_key.into(),
+        keys: keys
+            .iter()
+            .map(|(id, key)| (id.to_string(), Secret::new(key.to_string())))
+            .collect(),
+        index_key: Secret::new(KEY_1.into()),
+    }
+}
+
+async fn spawn_app_with_encryption() -> TestApp {
+    spawn_app_with(|c| c.pii_encryption = Some(keyring("k1", &[("k1", KEY_1)]))).await
+}
+
+#[tokio::test]
+async fn subscribers_are_stored_sealed_and_delivered_in_clear() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+
+    // Act - Part 1 - Subscribe
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, name, status, email_index FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.email.starts_with("pii:k1:"));
+    assert!(saved.email.ends_with("@gmail.com"));
+    assert!(!saved.email.contains("ursula"));
+    assert!(saved.name.starts_with("pii:k1:"));
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(
+        saved.email_index,
+        app.pii.email_index("ursula_le_guin@gmail.com")
+    );
+
+    // Act - Part 2 - Publish
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(
+        body["messages"][0]["To"][0]["email"],
+        "ursula_le_guin@gmail.com"
+    );
+}
+
+#[tokio::test]
+async fn sealed_subscribers_are_found_by_their_email() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status, paid_only)
+        VALUES ($1, 'Issue title', 'Issue body', '<p>Issue body</p>', now(), 'completed', true)
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_archive_login(
+        &serde_json::json!({ "email": "ursula_le_guin@gmail.com", "issue_id": issue_id }),
+    )
+    .await;
+
+    // Assert
+    // The mock verifies on drop that a login link was sent.
+}
+
+#[tokio::test]
+async fn rotating_the_keys_reseals_every_subscriber() {
+    // Arrange
+    let app = spawn_app_with_encryption().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com")
+        .await;
+    // Stored before encryption was enabled.
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia_butler@gmail.com', 'butler', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let rotated = PiiCipher::new(Some(&keyring("k2", &[("k1", KEY_1), ("k2", KEY_2)]))).unwrap();
+
+    // Act
+    let resealed = rotate_keys(&app.db_pool, &rotated, 1).await.unwrap();
+
+    // Assert
+    assert_eq!(resealed, 2);
+    let saved = sqlx::query!("SELECT email, name, email_index FROM subscriptions ORDER BY name")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let mut emails = Vec::new();
+    for row in &saved {
+        assert!(row.email.starts_with("pii:k2:"));
+        assert!(row.name.starts_with("pii:k2:"));
+        let email = rotated.open_email(&row.email).unwrap();
+        assert_eq!(row.email_index, rotated.email_index(&email));
+        emails.push(email);
+    }
+    emails.sort();
+    assert_eq!(
+        emails,
+        ["octavia_butler@gmail.com", "ursula_le_guin@gmail.com"]
+    );
+    assert_eq!(rotate_keys(&app.db_pool, &rotated, 1).await.unwrap(), 0);
+}
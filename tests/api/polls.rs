@@ -0,0 +1,184 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_issue_with_poll(app: &TestApp) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello\n{{poll}}\nBye",
+            "html_content": "<p>Hello</p>{{poll}}<p>Bye</p>",
+            "poll_question": "Tea or coffee?",
+            "poll_options": "Tea\nCoffee",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM polls")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+/// The vote links of the issues sent so far, by recipient and option.
+async fn vote_links(app: &TestApp) -> Vec<(String, Vec<reqwest::Url>)> {
+    let mut links = Vec::new();
+    for request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let message = &body["messages"][0];
+        if message["Subject"] != "Newsletter title" {
+            continue;
+        }
+        let votes = linkify::LinkFinder::new()
+            .links(message["TextPart"].as_str().unwrap())
+            .filter_map(|link| reqwest::Url::parse(link.as_str()).ok())
+            .filter(|link| link.path().starts_with("/p/"))
+            .map(|mut link| {
+                link.set_port(Some(app.port)).unwrap();
+                link
+            })
+            .collect();
+        let recipient = message["To"][0]["email"].as_str().unwrap().to_owned();
+        links.push((recipient, votes));
+    }
+    links.sort();
+    links
+}
+
+async fn vote(link: &reqwest::Url) -> reqwest::Response {
+    reqwest::get(link.clone()).await.unwrap()
+}
+
+#[tokio::test]
+async fn every_recipient_gets_their_own_vote_links() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.create_confirmed_subscriber("ged@example.com").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue_with_poll(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let links = vote_links(&app).await;
+    assert_eq!(links.len(), 2);
+    assert_eq!(links[0].1.len(), 2);
+    assert_eq!(links[1].1.len(), 2);
+    assert_ne!(links[0].1[0], links[1].1[0]);
+}
+
+#[tokio::test]
+async fn votes_are_counted_once_per_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.create_confirmed_subscriber("ged@example.com").await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue_with_poll(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let links = vote_links(&app).await;
+
+    // Act
+    let response = vote(&links[0].1[1]).await;
+    vote(&links[0].1[0]).await;
+    vote(&links[1].1[1]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Thank you for voting!"));
+    assert!(html_page.contains("Coffee: 1 (100%)"));
+    let report: serde_json::Value = app
+        .get_newsletter_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["poll"]["votes"], 2);
+    assert_eq!(report["poll"]["options"][0]["votes"], 0);
+    assert_eq!(report["poll"]["options"][1]["votes"], 2);
+}
+
+#[tokio::test]
+async fn a_tampered_vote_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.test_user.login(&app).await;
+    publish_issue_with_poll(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let mut link = vote_links(&app).await.remove(0).1.remove(0);
+
+    // Act
+    link.set_query(Some("d=forged"));
+    let response = vote(&link).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let votes = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM poll_votes")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(votes.count, 0);
+}
+
+#[tokio::test]
+async fn a_poll_needs_at_least_two_options() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+            "poll_question": "Tea or coffee?",
+            "poll_options": "Tea",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("A poll needs between 2 and 10 options, one per line."));
+}
+
+#[tokio::test]
+async fn the_archive_links_the_poll_to_its_results() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let issue_id = publish_issue_with_poll(&app).await;
+
+    // Assert
+    let poll_id = sqlx::query!("SELECT poll_id FROM polls")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .poll_id;
+    let html_page = app.get_archive_issue(issue_id).await.text().await.unwrap();
+    assert!(html_page.contains(&format!(r#"<a href="/p/{}">Tea</a>"#, poll_id)));
+    assert!(!html_page.contains("{{poll}}"));
+}
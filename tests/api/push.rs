@@ -0,0 +1,280 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{Settings, WebPushSettings};
+
+/// A P-256 public key, standing in for a browser's.
+const BROWSER_KEY: &str =
+    "BF3bcPlET9y-q0hES_ExlwyBYLX0SjhgcpYlGkvZnb6oRiuP75mFwGt6WN0hM0LavY3Be8-9hVNES3-AzUha8CY";
+const BROWSER_AUTH: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+
+fn configure_web_push(c: &mut Settings) {
+    c.web_push = Some(WebPushSettings {
+        vapid_public_key:
+            "BPvyfMBMdY6wwpcN7ik-BfPpYOZzNPv_We5m0X-q08JP2RHTOsMR_sLlR4wqx5DuGyHONHiJEUDFIwwiOQWNuX8"
+                .into(),
+        vapid_private_key: Secret::new("ygjQw7BJdZHodrVeakfW2ujY6YEgP5wz2Utuv3yHLL8".into()),
+        subject: "mailto:admin@example.com".into(),
+        ttl_seconds: 3600,
+        timeout_milliseconds: 2000,
+        // The mock push services listen on localhost
+        push_services: vec!["push.example".into(), "127.0.0.1".into()],
+        allow_private_hosts: true,
+    });
+}
+
+async fn register_browser(
+    status_page: &reqwest::Url,
+    subscription: &serde_json::Value,
+) -> reqwest::Response {
+    let mut link = status_page.clone();
+    link.set_path("/subscriptions/push");
+    reqwest::Client::new()
+        .post(link)
+        .json(subscription)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn browser_subscription(endpoint: &str) -> serde_json::Value {
+    serde_json::json!({
+        "endpoint": endpoint,
+        "keys": { "p256dh": BROWSER_KEY, "auth": BROWSER_AUTH },
+    })
+}
+
+async fn publish_issue(app: &TestApp, push: bool) {
+    let mut form = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    if push {
+        form["push"] = "true".into();
+    }
+    let response = app.post_publish_newsletter(&form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn push_subscription_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM push_subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn subscribers_register_their_browser_from_their_status_page() {
+    // Arrange
+    let app = spawn_app_with(configure_web_push).await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+
+    // Act - Part 1 - The page offers notifications
+    let html = reqwest::get(status_page.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Notify me"));
+    assert!(html.contains(r#"data-key="BPvyfMBMdY6wwpcN7ik-BfPpYOZzNPv_We5m0X"#));
+
+    // Act - Part 2 - The browser registers, twice
+    let subscription = browser_subscription("https://push.example/send/abc");
+    for _ in 0..2 {
+        let response = register_browser(&status_page, &subscription).await;
+        assert_eq!(response.status().as_u16(), 204);
+    }
+
+    // Assert
+    assert_eq!(push_subscription_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn invalid_push_subscriptions_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(configure_web_push).await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+    let test_cases = vec![
+        (browser_subscription("javascript:alert(1)"), "a script"),
+        (
+            browser_subscription("https://metadata.internal/computeMetadata/v1"),
+            "a host that is not a push service",
+        ),
+        (
+            serde_json::json!({
+                "endpoint": "https://push.example/send/abc",
+                "keys": { "p256dh": BROWSER_AUTH, "auth": BROWSER_AUTH },
+            }),
+            "a short public key",
+        ),
+    ];
+
+    for (subscription, description) in test_cases {
+        // Act
+        let response = register_browser(&status_page, &subscription).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject a subscription with {}.",
+            description
+        );
+    }
+    assert_eq!(push_subscription_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn push_is_not_offered_without_vapid_keys() {
+    // Arrange
+    let app = spawn_app().await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+
+    // Act
+    let html = reqwest::get(status_page.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let response = register_browser(
+        &status_page,
+        &browser_subscription("https://push.example/a"),
+    )
+    .await;
+
+    // Assert
+    assert!(!html.contains("Notify me"));
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn issues_published_with_push_reach_the_registered_browsers() {
+    // Arrange
+    let app = spawn_app_with(configure_web_push).await;
+    let push_service = MockServer::start().await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+    register_browser(
+        &status_page,
+        &browser_subscription(&format!("{}/send/abc", push_service.uri())),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/send/abc"))
+        .and(method("POST"))
+        .and(header("Content-Encoding", "aes128gcm"))
+        .and(header("TTL", "3600"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&push_service)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, true).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let requests = push_service.received_requests().await.unwrap();
+    let authorization = requests[0].headers[&"Authorization".into()].last().as_str();
+    assert!(authorization.starts_with("vapid t="));
+    assert!(authorization.ends_with(
+        ", k=BPvyfMBMdY6wwpcN7ik-BfPpYOZzNPv_We5m0X-q08JP2RHTOsMR_sLlR4wqx5DuGyHONHiJEUDFIwwiOQWNuX8"
+    ));
+}
+
+#[tokio::test]
+async fn issues_published_without_push_are_only_emailed() {
+    // Arrange
+    let app = spawn_app_with(configure_web_push).await;
+    let push_service = MockServer::start().await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+    register_browser(
+        &status_page,
+        &browser_subscription(&format!("{}/send/abc", push_service.uri())),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&push_service)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies on Drop that no notification was pushed
+}
+
+#[tokio::test]
+async fn browsers_that_unsubscribed_are_forgotten() {
+    // Arrange
+    let app = spawn_app_with(configure_web_push).await;
+    let push_service = MockServer::start().await;
+    let status_page = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/deliveries");
+    register_browser(
+        &status_page,
+        &browser_subscription(&format!("{}/send/abc", push_service.uri())),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/send/abc"))
+        .respond_with(ResponseTemplate::new(410))
+        .expect(1)
+        .mount(&push_service)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, true).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(push_subscription_count(&app).await, 0);
+}
@@ -0,0 +1,176 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::SignupProtectionSettings;
+use zero2prod::quarantine::expire_quarantine;
+
+async fn spawn_protected_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.signup_protection = Some(SignupProtectionSettings {
+            check_interval_seconds: 3600,
+            baseline_hours: 24,
+            spike_factor: 5.0,
+            min_signups: 20,
+            strict_mode_minutes: 60,
+            strict_signups_per_ip_per_hour: 10,
+            quarantine_days: 7,
+            captcha: None,
+        })
+    })
+    .await
+}
+
+/// Sign up through the hosted form, honeypot filled in.
+async fn bot_signup(app: &TestApp, name: &str) -> Uuid {
+    let response = app
+        .post_subscriptions(format!(
+            "name={name}&email={name}%40example.com&website=https%3A%2F%2Fspam.example.com"
+        ))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    sqlx::query!("SELECT id FROM subscriptions ORDER BY subscribed_at DESC LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn status_of(app: &TestApp, subscriber_id: Uuid) -> (String, bool) {
+    let r = sqlx::query!(
+        "SELECT status, deleted_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    (r.status, r.deleted_at.is_some())
+}
+
+#[tokio::test]
+async fn signups_that_look_like_bots_are_not_sent_a_confirmation_email() {
+    // Arrange
+    let app = spawn_protected_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let subscriber_id = bot_signup(&app, "ged").await;
+
+    // Assert
+    assert_eq!(
+        status_of(&app, subscriber_id).await,
+        ("quarantined".into(), false)
+    );
+    app.test_user.login(&app).await;
+    let html = app.get_quarantine_html().await;
+    assert!(html.contains("ged@example.com"));
+    assert!(html.contains("honeypot"));
+}
+
+#[tokio::test]
+async fn approved_signups_are_sent_their_confirmation_email() {
+    // Arrange
+    let app = spawn_protected_app().await;
+    let first = bot_signup(&app, "ged").await;
+    let second = bot_signup(&app, "tenar").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_review_quarantine("approve", &[first, second])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/quarantine");
+    assert!(app
+        .get_quarantine_html()
+        .await
+        .contains("2 signups were approved"));
+    for subscriber_id in [first, second] {
+        assert_eq!(
+            status_of(&app, subscriber_id).await,
+            ("pending_confirmation".into(), false)
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejected_signups_are_deleted() {
+    // Arrange
+    let app = spawn_protected_app().await;
+    let rejected = bot_signup(&app, "ged").await;
+    let kept = bot_signup(&app, "tenar").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_review_quarantine("reject", &[rejected]).await;
+
+    // Assert
+    assert_eq!(
+        status_of(&app, rejected).await,
+        ("quarantined".into(), true)
+    );
+    assert_eq!(status_of(&app, kept).await, ("quarantined".into(), false));
+    let html = app.get_quarantine_html().await;
+    assert!(!html.contains("ged@example.com"));
+    assert!(html.contains("tenar@example.com"));
+}
+
+#[tokio::test]
+async fn signups_made_during_a_spike_are_quarantined_without_a_captcha() {
+    // Arrange
+    let app = spawn_protected_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO signup_spikes (hour, source, signups, baseline, detected_at, strict_until)
+        VALUES (date_trunc('hour', now()), '*', 100, 1, now(), now() + interval '1 hour')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_subscriptions("name=ged&email=ged%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let r = sqlx::query!("SELECT status, quarantine_reason FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(r.status, "quarantined");
+    assert_eq!(r.quarantine_reason.as_deref(), Some("signup_spike"));
+}
+
+#[tokio::test]
+async fn signups_left_in_quarantine_expire() {
+    // Arrange
+    let app = spawn_protected_app().await;
+    let subscriber_id = bot_signup(&app, "ged").await;
+
+    // Act
+    let expired = expire_quarantine(&app.db_pool, Utc::now() + Duration::seconds(1))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(expired, 1);
+    assert_eq!(
+        status_of(&app, subscriber_id).await,
+        ("quarantined".into(), true)
+    );
+}
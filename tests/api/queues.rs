@@ -0,0 +1,133 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn insert_issue(app: &TestApp, status: &str) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status)
+        VALUES ($1, 'Issue title', 'Issue body', '<p>Issue body</p>', now(), $2)
+        "#,
+        issue_id,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    issue_id
+}
+
+async fn insert_subscriber(app: &TestApp, email: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'name', now(), 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn record_delivery(app: &TestApp, issue_id: Uuid, email: &str, succeeded: bool) {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_deliveries
+            (newsletter_issue_id, subscriber_email, attempted_at, succeeded)
+        VALUES ($1, $2, now(), $3)
+        "#,
+        issue_id,
+        email,
+        succeeded
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_stats_cover_every_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "in_progress").await;
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) VALUES ($1, 'a@gmail.com')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    record_delivery(&app, issue_id, "b@gmail.com", true).await;
+    record_delivery(&app, issue_id, "c@gmail.com", false).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let stats = app.get_queues().await;
+
+    // Assert
+    let queues = stats["queues"].as_array().unwrap();
+    assert_eq!(queues.len(), 2);
+    let delivery = &queues[0];
+    assert_eq!(delivery["name"], "delivery");
+    assert_eq!(delivery["depth"], 1);
+    assert_eq!(delivery["in_flight"], 0);
+    assert_eq!(delivery["failed"], 1);
+    assert_eq!(delivery["failure_rate"], 0.5);
+    let outbox = &queues[1];
+    assert_eq!(outbox["name"], "outbox");
+    assert_eq!(outbox["depth"], 0);
+    assert!(outbox["oldest_item_age_seconds"].is_null());
+}
+
+#[tokio::test]
+async fn failed_deliveries_go_back_in_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = insert_issue(&app, "completed").await;
+    insert_subscriber(&app, "failed@gmail.com").await;
+    insert_subscriber(&app, "retried@gmail.com").await;
+    record_delivery(&app, issue_id, "failed@gmail.com", false).await;
+    record_delivery(&app, issue_id, "retried@gmail.com", false).await;
+    record_delivery(&app, issue_id, "retried@gmail.com", true).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_retry_failed("delivery").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["retried"], 1);
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].subscriber_email, "failed@gmail.com");
+    let issue = sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.status, "in_progress");
+}
+
+#[tokio::test]
+async fn only_known_queues_can_be_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let unknown = app.post_retry_failed("jobs").await;
+    let outbox = app.post_retry_failed("outbox").await;
+
+    // Assert
+    assert_eq!(unknown.status().as_u16(), 404);
+    assert_eq!(outbox.status().as_u16(), 409);
+}
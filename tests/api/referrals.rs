@@ -0,0 +1,176 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn mock_email_provider(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// The share link the latest issue showed its only recipient.
+async fn share_link(app: &TestApp) -> reqwest::Url {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello\n{{referrals}}",
+            "html_content": "<p>Hello</p>{{referrals}}",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let text = body["messages"][0]["TextPart"].as_str().unwrap();
+    assert!(text.contains("Nobody joined through your link yet."));
+    let link = linkify::LinkFinder::new()
+        .links(text)
+        .find(|link| link.as_str().contains("/r/"))
+        .unwrap();
+    let mut link = reqwest::Url::parse(link.as_str()).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+fn referral_code(link: &reqwest::Url) -> String {
+    link.path().strip_prefix("/r/").unwrap().to_owned()
+}
+
+async fn subscriber_id(app: &TestApp, code: &str) -> Uuid {
+    sqlx::query!(
+        "SELECT id FROM subscriptions WHERE referral_code = $1",
+        code
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .id
+}
+
+#[tokio::test]
+async fn the_share_link_leads_to_a_signup_form_crediting_the_referrer() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_provider(&app).await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.test_user.login(&app).await;
+    let link = share_link(&app).await;
+
+    // Act
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"name="referral_code" value="{}""#,
+        referral_code(&link)
+    )));
+}
+
+#[tokio::test]
+async fn confirmed_referrals_count_towards_the_leaderboard() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_provider(&app).await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.test_user.login(&app).await;
+    let code = referral_code(&share_link(&app).await);
+
+    // Act
+    app.create_confirmed_subscriber_with(&format!(
+        "name=le%20guin&email=ged%40example.com&referral_code={}",
+        code
+    ))
+    .await;
+    app.create_confirmed_subscriber_with(&format!(
+        "name=le%20guin&email=tenar%40example.com&referral_code={}",
+        code
+    ))
+    .await;
+    // Still to confirm: it does not count.
+    app.post_subscriptions(format!(
+        "name=arren&email=arren%40example.com&referral_code={}",
+        code
+    ))
+    .await;
+
+    // Assert
+    let leaderboard = app.get_referral_leaderboard().await;
+    assert_eq!(leaderboard.as_array().unwrap().len(), 1);
+    assert_eq!(leaderboard[0]["rank"], 1);
+    assert_eq!(
+        leaderboard[0]["subscriber_id"],
+        subscriber_id(&app, &code).await.to_string()
+    );
+    assert_eq!(leaderboard[0]["referrals"], 2);
+}
+
+#[tokio::test]
+async fn unknown_referral_codes_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/r/aaaaaaaaaaaa", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn reaching_a_milestone_sends_its_reward_once() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_provider(&app).await;
+    app.create_confirmed_subscriber("ursula@example.com").await;
+    app.test_user.login(&app).await;
+    let code = referral_code(&share_link(&app).await);
+
+    // Act
+    for email in [
+        "ged@example.com",
+        "tenar@example.com",
+        "arren@example.com",
+        "tehanu@example.com",
+    ] {
+        app.create_confirmed_subscriber_with(&format!(
+            "name=le%20guin&email={}&referral_code={}",
+            urlencoding::encode(email),
+            code
+        ))
+        .await;
+    }
+
+    // Assert
+    let is_reward = |request: &wiremock::Request| {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        body["messages"][0]["Subject"] == "Thank you for spreading the word!"
+    };
+    let mut rewards = 0;
+    for _ in 0..50 {
+        let requests = app.email_server.received_requests().await.unwrap();
+        rewards = requests.iter().filter(|r| is_reward(r)).count();
+        if rewards > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    // Give a duplicate the time to show up.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(rewards, 1);
+    assert_eq!(requests.iter().filter(|r| is_reward(r)).count(), 1);
+}
@@ -0,0 +1,27 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+use zero2prod::configuration::SendQuotaSettings;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_reload_the_configuration() {
+    let app = spawn_app().await;
+
+    let response = app.post_reload_settings().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn reloading_applies_the_configuration_files_without_a_restart() {
+    // Started with a send quota the configuration files don't have.
+    let app =
+        spawn_app_with(|c| c.send_quota = Some(SendQuotaSettings { monthly_limit: 10 })).await;
+    app.test_user.login(&app).await;
+    assert_eq!(app.get_send_quota().await.status().as_u16(), 200);
+
+    let response = app.post_reload_settings().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("The configuration has been reloaded."));
+    assert_eq!(app.get_send_quota().await.status().as_u16(), 404);
+}
@@ -0,0 +1,53 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+
+async fn get_health_check(app: &TestApp, request_id: Option<&str>) -> reqwest::Response {
+    let mut request = app.api_client.get(format!("{}/health_check", app.address));
+    if let Some(request_id) = request_id {
+        request = request.header("X-Request-Id", request_id);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+fn request_id(response: &reqwest::Response) -> &str {
+    response.headers()["X-Request-Id"].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn every_response_carries_a_request_id() {
+    let app = spawn_app().await;
+
+    let first = get_health_check(&app, None).await;
+    let second = get_health_check(&app, None).await;
+
+    assert!(Uuid::parse_str(request_id(&first)).is_ok());
+    assert_ne!(request_id(&first), request_id(&second));
+}
+
+#[tokio::test]
+async fn the_request_id_of_a_trusted_proxy_is_reused() {
+    let app = spawn_app_with(|c| c.network.trusted_proxies = vec!["127.0.0.0/8".into()]).await;
+
+    let response = get_health_check(&app, Some("lb-42")).await;
+
+    assert_eq!(request_id(&response), "lb-42");
+}
+
+#[tokio::test]
+async fn the_request_id_of_an_untrusted_client_is_replaced() {
+    let app = spawn_app().await;
+
+    let response = get_health_check(&app, Some("lb-42")).await;
+
+    assert_ne!(request_id(&response), "lb-42");
+}
+
+#[tokio::test]
+async fn rejected_requests_carry_a_request_id_too() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    assert!(response.headers().contains_key("X-Request-Id"));
+}
@@ -0,0 +1,159 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use zero2prod::configuration::DataRetentionSettings;
+use zero2prod::retention::{enforce_retention, RetentionOutcome};
+
+#[tokio::test]
+async fn only_the_data_past_its_window_is_removed() {
+    // Arrange
+    let app = spawn_app().await;
+    let now = Utc::now();
+    let old = now - Duration::days(100);
+    let subscriber_id = Uuid::new_v4();
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues
+            (newsletter_issue_id, title, text_content, html_content, published_at, status)
+        VALUES ($1, 'Issue title', 'Issue body', '<p>Issue body</p>', now(), 'completed')
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    for recorded_at in [old, now] {
+        sqlx::query!(
+            r#"
+            INSERT INTO consent_records
+                (id, subscriber_id, action, recorded_at, client_ip, user_agent, consent_text_version)
+            VALUES ($1, $2, 'subscribed', $3, '203.0.113.7', 'Earthsea Browser/1.0', '1')
+            "#,
+            Uuid::new_v4(),
+            subscriber_id,
+            recorded_at
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO email_deliveries (newsletter_issue_id, subscriber_email, attempted_at, succeeded)
+            VALUES ($1, 'ursula_le_guin@gmail.com', $2, true)
+            "#,
+            issue_id,
+            recorded_at
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO landing_visits
+                (visit_id, newsletter_issue_id, subscriber_id, kind, visited_at)
+            VALUES ($1, $2, $3, 'visit', $4)
+            "#,
+            Uuid::new_v4(),
+            issue_id,
+            subscriber_id,
+            recorded_at
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO domain_events (event_type, payload, occurred_at)
+            VALUES ('subscriber_confirmed', '{}', $1)
+            "#,
+            recorded_at
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    let settings = DataRetentionSettings {
+        audit_log_days: Some(90),
+        consent_ip_days: Some(90),
+        delivery_log_days: Some(90),
+        landing_visits_days: Some(90),
+    };
+
+    // Act
+    let outcome = enforce_retention(&app.db_pool, &settings, now)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        outcome,
+        RetentionOutcome {
+            audit_events_deleted: 1,
+            consent_records_anonymised: 1,
+            delivery_attempts_deleted: 1,
+            landing_visits_deleted: 1,
+        }
+    );
+    let consent = sqlx::query!(
+        "SELECT client_ip, user_agent, consent_text_version FROM consent_records ORDER BY recorded_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.len(), 2);
+    assert_eq!(consent[0].client_ip, None);
+    assert_eq!(consent[0].user_agent, None);
+    assert_eq!(consent[0].consent_text_version, "1");
+    assert_eq!(consent[1].client_ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(
+        enforce_retention(&app.db_pool, &settings, now)
+            .await
+            .unwrap(),
+        RetentionOutcome::default()
+    );
+}
+
+#[tokio::test]
+async fn admins_can_review_the_effective_policy() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.data_retention.delivery_log_days = Some(400);
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let policy: serde_json::Value = app
+        .api_client
+        .get(format!("{}/admin/retention", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    let rules = policy["rules"].as_array().unwrap();
+    let days = |data_class: &str| {
+        rules
+            .iter()
+            .find(|rule| rule["data_class"] == data_class)
+            .unwrap()["retention_days"]
+            .clone()
+    };
+    assert_eq!(days("delivery_log"), 400);
+    assert_eq!(days("deleted_subscribers"), 30);
+    assert!(days("audit_log").is_null());
+}
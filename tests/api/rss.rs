@@ -0,0 +1,197 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{RssFeedSettings, RssSettings, Settings};
+use zero2prod::rss::CheckOutcome;
+
+fn configure_feed(c: &mut Settings, blog: &MockServer, template: Option<&str>, auto_send: bool) {
+    c.rss = Some(RssSettings {
+        timeout_milliseconds: 2000,
+        feeds: vec![RssFeedSettings {
+            name: "Blog".into(),
+            url: format!("{}/feed.xml", blog.uri()),
+            interval_minutes: 60,
+            template: template.map(String::from),
+            auto_send,
+            max_entries: 20,
+        }],
+    });
+}
+
+fn feed(posts: &[u32]) -> String {
+    let items: String = posts
+        .iter()
+        .map(|post| {
+            format!(
+                "<item><title>Post #{post}</title>\
+                 <link>https://blog.example.com/posts/{post}</link>\
+                 <guid>post-{post}</guid></item>"
+            )
+        })
+        .collect();
+    format!(
+        r#"<?xml version="1.0"?><rss version="2.0"><channel><title>Blog</title>{items}</channel></rss>"#
+    )
+}
+
+async fn serve_feed(blog: &MockServer, posts: &[u32]) {
+    blog.reset().await;
+    Mock::given(path("/feed.xml"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(feed(posts)))
+        .mount(blog)
+        .await;
+}
+
+/// As if the feed had been checked past its interval ago.
+async fn make_feed_due(app: &TestApp) {
+    sqlx::query!("UPDATE rss_feeds SET last_checked_at = now() - interval '2 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn new_entries_are_left_as_a_draft_to_approve() {
+    // Arrange
+    let blog = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_feed(c, &blog, None, false)).await;
+    serve_feed(&blog, &[2, 1]).await;
+
+    // Act
+    let outcomes = app.check_rss_feeds().await;
+
+    // Assert
+    let [CheckOutcome::Drafted(draft_id)] = outcomes[..] else {
+        panic!("Expected a draft, got {:?}", outcomes);
+    };
+    let draft = sqlx::query!(
+        "SELECT title, text_content, html_content FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.title, "Blog");
+    assert_eq!(
+        draft.text_content,
+        "- Post #2\n  https://blog.example.com/posts/2\n- Post #1\n  https://blog.example.com/posts/1"
+    );
+    assert!(draft.html_content.contains(">Post #2</a></li>"));
+    app.test_user.login(&app).await;
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/newsletters?draft={}">Blog</a>"#,
+        draft_id
+    )));
+}
+
+#[tokio::test]
+async fn entries_are_only_sent_once() {
+    // Arrange
+    let blog = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_feed(c, &blog, None, false)).await;
+    serve_feed(&blog, &[1]).await;
+    app.check_rss_feeds().await;
+
+    // Act
+    let not_due = app.check_rss_feeds().await;
+    make_feed_due(&app).await;
+    let unchanged = app.check_rss_feeds().await;
+    serve_feed(&blog, &[2, 1]).await;
+    make_feed_due(&app).await;
+    let updated = app.check_rss_feeds().await;
+
+    // Assert
+    assert_eq!(not_due, vec![CheckOutcome::NotDue]);
+    assert_eq!(unchanged, vec![CheckOutcome::NoNewEntries]);
+    let [CheckOutcome::Drafted(draft_id)] = updated[..] else {
+        panic!("Expected a draft, got {:?}", updated);
+    };
+    let draft = sqlx::query!(
+        "SELECT text_content FROM newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        draft.text_content,
+        "- Post #2\n  https://blog.example.com/posts/2"
+    );
+}
+
+#[tokio::test]
+async fn auto_sent_issues_are_rendered_into_the_template() {
+    // Arrange
+    let blog = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_feed(c, &blog, Some("Weekly"), true)).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_templates (
+            template_id, name, title, text_content, html_content, paid_only, priority, push,
+            created_at
+        )
+        VALUES ($1, 'Weekly', 'This week on the blog', E'New posts:\n{{rss}}\nBye',
+            '<p>New posts:</p>{{rss}}<p>Bye</p>', false, false, false, now())
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    serve_feed(&blog, &[1]).await;
+
+    // Act
+    let outcomes = app.check_rss_feeds().await;
+
+    // Assert
+    let [CheckOutcome::Published(issue_id)] = outcomes[..] else {
+        panic!("Expected a published issue, got {:?}", outcomes);
+    };
+    let issue = sqlx::query!(
+        "SELECT title, text_content FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.title, "This week on the blog");
+    assert_eq!(
+        issue.text_content,
+        "New posts:\n- Post #1\n  https://blog.example.com/posts/1\nBye"
+    );
+    let entry = sqlx::query!("SELECT newsletter_issue_id FROM rss_feed_entries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.newsletter_issue_id, Some(issue_id));
+}
+
+#[tokio::test]
+async fn a_feed_that_cannot_be_fetched_is_retried_after_its_interval() {
+    // Arrange
+    let blog = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_feed(c, &blog, None, false)).await;
+    Mock::given(path("/feed.xml"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&blog)
+        .await;
+
+    // Act
+    let outcomes = app.check_rss_feeds().await;
+
+    // Assert
+    assert_eq!(outcomes, vec![CheckOutcome::Failed]);
+    let feed = sqlx::query!("SELECT last_error FROM rss_feeds")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(feed.last_error.unwrap().contains("503"));
+    let entries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM rss_feed_entries"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(entries.count, 0);
+}
@@ -0,0 +1,151 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::configuration::ScimSettings;
+
+const TOKEN: &str = "scim-token";
+
+async fn spawn_scim_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.scim = Some(ScimSettings {
+            bearer_token: Secret::new(TOKEN.into()),
+        })
+    })
+    .await
+}
+
+fn scim_request(app: &TestApp, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+    app.api_client
+        .request(method, format!("{}/scim/v2{}", &app.address, path))
+        .bearer_auth(TOKEN)
+}
+
+async fn create_user(app: &TestApp, username: &str) -> reqwest::Response {
+    scim_request(app, reqwest::Method::POST, "/Users")
+        .header("Content-Type", "application/scim+json")
+        .body(
+            serde_json::json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+                "userName": username,
+                "emails": [{ "value": "grace@gmail.com", "primary": true }],
+                "active": true,
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn scim_is_not_found_when_disabled() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = scim_request(&app, reqwest::Method::GET, "/Users")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn requests_without_the_bearer_token_are_rejected() {
+    // Arrange
+    let app = spawn_scim_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/scim/v2/Users", &app.address))
+        .bearer_auth("wrong-token")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn provisioned_users_can_be_found_by_username() {
+    // Arrange
+    let app = spawn_scim_app().await;
+    let response = create_user(&app, "grace").await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    let response = scim_request(&app, reqwest::Method::GET, "/Users")
+        .query(&[("filter", r#"userName eq "grace""#)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["totalResults"], 1);
+    assert_eq!(body["Resources"][0]["id"], created["id"]);
+    assert_eq!(
+        body["Resources"][0]["emails"][0]["value"],
+        "grace@gmail.com"
+    );
+}
+
+#[tokio::test]
+async fn creating_a_duplicate_username_is_a_conflict() {
+    // Arrange
+    let app = spawn_scim_app().await;
+    create_user(&app, "grace").await;
+
+    // Act
+    let response = create_user(&app, "grace").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["scimType"], "uniqueness");
+}
+
+#[tokio::test]
+async fn deactivating_an_admin_ends_their_session() {
+    // Arrange
+    let app = spawn_scim_app().await;
+    app.test_user.login(&app).await;
+    let user_id: Uuid = sqlx::query!(
+        "SELECT user_id FROM users WHERE username = $1",
+        app.test_user.username
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .user_id;
+
+    // Act
+    let response = scim_request(&app, reqwest::Method::PATCH, &format!("/Users/{}", user_id))
+        .json(&serde_json::json!({
+            "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+            "Operations": [{ "op": "replace", "path": "active", "value": false }]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["active"], false);
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,154 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::SeedListSettings;
+
+const SEED: &str = "seed@example.com";
+const TOKEN: &str = "seed-webhook-token";
+
+async fn spawn_app_with_seed_list() -> TestApp {
+    let app = spawn_app_with(|c| {
+        c.seed_list = Some(SeedListSettings {
+            inboxes: vec![SEED.into()],
+            webhook_token: Secret::new(TOKEN.into()),
+        })
+    })
+    .await;
+    app.test_user.login(&app).await;
+    app
+}
+
+/// Publish an issue nobody is subscribed to, so that only the seed gets it.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn seed_placement(app: &TestApp, issue_id: Uuid) -> serde_json::Value {
+    let report: serde_json::Value = app
+        .get_newsletter_issue_report(issue_id)
+        .await
+        .json()
+        .await
+        .unwrap();
+    report["seed_placements"][0].clone()
+}
+
+#[tokio::test]
+async fn seed_inboxes_receive_a_tagged_copy_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app_with_seed_list().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_issue(&app).await;
+
+    // Assert
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let message = &body["messages"][0];
+    assert_eq!(message["To"][0]["email"], SEED);
+    assert_eq!(
+        message["Subject"],
+        format!("Newsletter title [seed {}]", issue_id)
+    );
+    let placement = seed_placement(&app, issue_id).await;
+    assert_eq!(placement["sent"], true);
+    assert!(placement["placement"].is_null());
+}
+
+#[tokio::test]
+async fn placements_can_be_reported_by_hand() {
+    // Arrange
+    let app = spawn_app_with_seed_list().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let response = app
+        .post_seed_placement(
+            issue_id,
+            &serde_json::json!({ "seed_email": SEED, "placement": "spam" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let placement = seed_placement(&app, issue_id).await;
+    assert_eq!(placement["placement"], "spam");
+    assert_eq!(placement["reported_by"], "manual");
+}
+
+#[tokio::test]
+async fn placements_can_be_reported_through_the_webhook() {
+    // Arrange
+    let app = spawn_app_with_seed_list().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let issue_id = publish_issue(&app).await;
+    let report = |seed_email: &str| {
+        serde_json::json!({
+            "issue_id": issue_id,
+            "seed_email": seed_email,
+            "placement": "inbox",
+        })
+    };
+
+    // Act
+    let unauthorized = app
+        .post_seed_placement_webhook("wrong-token", &report(SEED))
+        .await;
+    let unknown_seed = app
+        .post_seed_placement_webhook(TOKEN, &report("other@example.com"))
+        .await;
+    let accepted = app.post_seed_placement_webhook(TOKEN, &report(SEED)).await;
+
+    // Assert
+    assert_eq!(unauthorized.status().as_u16(), 401);
+    assert_eq!(unknown_seed.status().as_u16(), 404);
+    assert_eq!(accepted.status().as_u16(), 204);
+    let placement = seed_placement(&app, issue_id).await;
+    assert_eq!(placement["placement"], "inbox");
+    assert_eq!(placement["reported_by"], "webhook");
+}
+
+#[tokio::test]
+async fn the_webhook_is_not_found_without_a_seed_list() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_seed_placement_webhook(
+            TOKEN,
+            &serde_json::json!({
+                "issue_id": Uuid::new_v4(),
+                "seed_email": SEED,
+                "placement": "inbox",
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
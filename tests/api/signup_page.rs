@@ -0,0 +1,72 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use zero2prod::configuration::SignupPageSettings;
+
+#[tokio::test]
+async fn an_unknown_signup_page_is_not_found() {
+    let app = spawn_app().await;
+
+    let response = app.get_signup_page("earthsea").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_signup_page_shows_the_branding_and_the_subscribe_form() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.signup_pages = vec![SignupPageSettings {
+            slug: "earthsea".into(),
+            title: "Tales <from> Earthsea".into(),
+            description: "Monthly news from the archipelago.".into(),
+            logo_url: Some("https://example.com/logo.png".into()),
+            accent_color: Some("#225588".into()),
+        }];
+    })
+    .await;
+
+    // Act
+    let response = app.get_signup_page("earthsea").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Tales &lt;from&gt; Earthsea</h1>"));
+    assert!(html_page.contains("Monthly news from the archipelago."));
+    assert!(html_page.contains(r#"src="https://example.com/logo.png""#));
+    assert!(html_page.contains("color: #225588;"));
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+    assert!(html_page.contains(r#"<input type="hidden" name="source" value="l/earthsea">"#));
+}
+
+#[tokio::test]
+async fn a_signup_page_is_not_sent_again_to_clients_with_a_current_copy() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.signup_pages = vec![SignupPageSettings {
+            slug: "earthsea".into(),
+            title: "Tales from Earthsea".into(),
+            description: String::new(),
+            logo_url: None,
+            accent_color: None,
+        }];
+        c.http_cache.signup_page_max_age_seconds = 600;
+    })
+    .await;
+    let response = app.get_signup_page("earthsea").await;
+    assert_eq!(response.headers()["Cache-Control"], "public, max-age=600");
+    let etag = response.headers()["ETag"].clone();
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/l/earthsea", &app.address))
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 304);
+    assert_eq!(response.headers()["ETag"], etag);
+    assert!(response.text().await.unwrap().is_empty());
+}
@@ -0,0 +1,137 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::{CaptchaSettings, SignupProtectionSettings};
+use zero2prod::events::DomainEvent;
+
+fn protection(captcha: Option<CaptchaSettings>) -> SignupProtectionSettings {
+    SignupProtectionSettings {
+        check_interval_seconds: 3600,
+        baseline_hours: 24,
+        spike_factor: 5.0,
+        min_signups: 3,
+        strict_mode_minutes: 60,
+        strict_signups_per_ip_per_hour: 1,
+        quarantine_days: 7,
+        captcha,
+    }
+}
+
+async fn accept_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// As if a spike had been detected a moment ago.
+async fn enter_strict_mode(app: &TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO signup_spikes (hour, source, signups, baseline, detected_at, strict_until)
+        VALUES (date_trunc('hour', now()), '*', 100, 1, now(), now() + interval '1 hour')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_spike_notifies_the_admins_and_lowers_the_rate_limit() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup_protection = Some(protection(None))).await;
+    accept_emails(&app).await;
+    for name in ["ged", "tenar", "arren"] {
+        app.post_subscriptions(format!(
+            "name={name}&email={name}%40example.com&source=l%2Fearthsea"
+        ))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+    let mut events = app.event_bus.subscribe();
+
+    // Act
+    let spikes = app.detect_signup_spikes().await;
+
+    // Assert
+    let sources: Vec<_> = spikes.iter().map(|s| s.source.as_str()).collect();
+    assert_eq!(sources, vec!["l/earthsea", "*"]);
+    let DomainEvent::SignupSpikeDetected {
+        source, signups, ..
+    } = events.recv().await.unwrap()
+    else {
+        panic!("Expected a signup spike");
+    };
+    assert_eq!((source.as_str(), signups), ("l/earthsea", 3));
+    let response = app
+        .post_subscriptions("name=ogion&email=ogion%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+    // The spike is only reported once.
+    assert!(app.detect_signup_spikes().await.is_empty());
+}
+
+#[tokio::test]
+async fn a_handful_of_signups_is_not_a_spike() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup_protection = Some(protection(None))).await;
+    accept_emails(&app).await;
+    app.post_subscriptions("name=ged&email=ged%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let spikes = app.detect_signup_spikes().await;
+
+    // Assert
+    assert!(spikes.is_empty());
+    let response = app
+        .post_subscriptions("name=tenar&email=tenar%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn strict_mode_requires_a_captcha() {
+    // Arrange
+    let captcha_server = wiremock::MockServer::start().await;
+    let captcha = CaptchaSettings {
+        verify_url: format!("{}/siteverify", captcha_server.uri()),
+        secret_key: "captcha-secret".to_string().into(),
+        timeout_milliseconds: 2000,
+    };
+    let app = spawn_app_with(|c| {
+        let mut settings = protection(Some(captcha));
+        settings.strict_signups_per_ip_per_hour = 10;
+        c.signup_protection = Some(settings);
+    })
+    .await;
+    accept_emails(&app).await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=solved"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "success": true })),
+        )
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+    enter_strict_mode(&app).await;
+
+    // Act
+    let without_captcha = app
+        .post_subscriptions("name=ged&email=ged%40example.com".into())
+        .await;
+    let with_captcha = app
+        .post_subscriptions("name=ged&email=ged%40example.com&captcha_response=solved".into())
+        .await;
+
+    // Assert
+    assert_eq!(without_captcha.status().as_u16(), 400);
+    let body: serde_json::Value = without_captcha.json().await.unwrap();
+    assert_eq!(body["field"], "captcha_response");
+    assert_eq!(with_captcha.status().as_u16(), 200);
+}
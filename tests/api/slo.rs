@@ -0,0 +1,52 @@
+use crate::helpers::spawn_app_with;
+use std::time::Duration;
+use zero2prod::configuration::SloObjectiveSettings;
+use zero2prod::events::DomainEvent;
+
+#[tokio::test]
+async fn a_burning_latency_budget_is_published_on_the_event_bus() {
+    // An objective no request can meet.
+    let app = spawn_app_with(|c| {
+        c.slo.evaluation_interval_seconds = 1;
+        c.slo.burn_rate_threshold = 1.0;
+        c.slo.min_requests = 1;
+        c.slo.objectives = vec![SloObjectiveSettings {
+            route: "/health_check".into(),
+            latency_milliseconds: 0,
+            target: 0.99,
+            window_seconds: 60,
+        }];
+    })
+    .await;
+    let mut events = app.event_bus.subscribe();
+
+    app.api_client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let event = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let DomainEvent::SloBudgetBurning { route, burn_rate } = events.recv().await.unwrap()
+            {
+                return (route, burn_rate);
+            }
+        }
+    })
+    .await
+    .expect("No SLO burn was published");
+    assert_eq!(event.0, "/health_check");
+    assert!(event.1 >= 1.0);
+
+    let metrics = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains(r#"slo_burn_rate{route="/health_check"}"#));
+}
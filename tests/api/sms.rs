@@ -0,0 +1,370 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{basic_auth, body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{Settings, SmsProviderSettings, SmsRateLimitSettings, SmsSettings};
+
+fn configure_sms(c: &mut Settings, provider: &MockServer) {
+    c.sms = Some(SmsSettings {
+        provider: SmsProviderSettings::Twilio {
+            base_url: provider.uri(),
+            account_sid: "AC123".into(),
+            auth_token: Secret::new("token".into()),
+            from: "+15005550006".into(),
+        },
+        timeout_milliseconds: 2000,
+        consent_text: "Up to 4 texts a month. Reply STOP to stop.".into(),
+        consent_text_version: "sms-2026-10".into(),
+        rate_limits: SmsRateLimitSettings {
+            default_per_minute: 60,
+            countries: Default::default(),
+        },
+    });
+}
+
+/// Stands in for the verification flow.
+async fn store_verified_phone(app: &TestApp, subscriber_id: Uuid, phone_number: &str) {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET phone_number = $2, phone_country = 'US', phone_verified_at = now()
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        app.pii.seal(phone_number)
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn set_opt_in(preferences: &reqwest::Url, opt_in: bool) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(preferences.clone())
+        .form(&[("opt_in", opt_in.to_string())])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_with_a_verified_phone_opt_in_to_texts() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/sms");
+    store_verified_phone(&app, subscriber_id, "+14155550100").await;
+
+    // Act - Part 1 - The page shows the consent wording
+    let html = reqwest::get(preferences.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("+14155550100"));
+    assert!(html.contains("Reply STOP to stop."));
+
+    // Act - Part 2 - Opt in
+    let response = set_opt_in(&preferences, true).await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    // Assert
+    let consent = app.get_subscriber_consent(subscriber_id).await;
+    let last = consent.as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["action"], "sms_opted_in");
+    assert_eq!(last["consent_text_version"], "sms-2026-10");
+}
+
+#[tokio::test]
+async fn subscribers_without_a_verified_phone_cannot_opt_in() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/sms");
+
+    // Act
+    set_opt_in(&preferences, true).await;
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT sms_opted_in_at FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.sms_opted_in_at.is_none());
+}
+
+#[tokio::test]
+async fn announcements_are_texted_to_the_opted_in_subscribers_only() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let opted_in = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/sms");
+    store_verified_phone(&app, opted_in, "+14155550100").await;
+    set_opt_in(&preferences, true).await;
+    let not_opted_in = app
+        .create_confirmed_subscriber("le_guin@example.com")
+        .await
+        .id;
+    store_verified_phone(&app, not_opted_in, "+14155550101").await;
+    Mock::given(path("/2010-04-01/Accounts/AC123/Messages.json"))
+        .and(method("POST"))
+        .and(basic_auth("AC123", "token"))
+        .and(body_string_contains("To=%2B14155550100"))
+        .and(body_string_contains("Body=Tickets+are+on+sale%21"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sid": "SM1" })))
+        .expect(1)
+        .mount(&provider)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - The form counts the recipients
+    let html = app.get_sms_html().await;
+    assert!(html.contains("1 subscribers verified their phone number"));
+
+    // Act - Part 2 - Send the announcement
+    let response = app.post_sms_blast("Tickets are on sale!").await;
+    assert_is_redirect_to(&response, "/admin/sms");
+    let html = app.get_sms_html().await;
+    assert!(html.contains("The announcement is being texted to 1 subscribers."));
+    app.send_all_pending_texts().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT succeeded, provider_message_id FROM sms_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(delivery.succeeded);
+    assert_eq!(delivery.provider_message_id.as_deref(), Some("SM1"));
+}
+
+#[tokio::test]
+async fn subscribers_who_opted_out_since_are_not_texted() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/sms");
+    store_verified_phone(&app, subscriber_id, "+14155550100").await;
+    set_opt_in(&preferences, true).await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&provider)
+        .await;
+    app.test_user.login(&app).await;
+    app.post_sms_blast("Tickets are on sale!").await;
+
+    // Act
+    set_opt_in(&preferences, false).await;
+    app.send_all_pending_texts().await;
+
+    // Assert
+    // Mock verifies on Drop that nothing was texted
+}
+
+#[tokio::test]
+async fn long_announcements_are_rejected() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_sms_blast(&"a".repeat(321)).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sms");
+    let html = app.get_sms_html().await;
+    assert!(html.contains("Announcements by text are at most 320 characters long."));
+    let queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM sms_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+}
+
+#[tokio::test]
+async fn texts_are_not_offered_without_a_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/sms");
+    app.test_user.login(&app).await;
+
+    // Act
+    let page = reqwest::get(preferences).await.unwrap();
+    let admin_page = app
+        .api_client
+        .get(format!("{}/admin/sms", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 404);
+    assert_eq!(admin_page.status().as_u16(), 404);
+}
+
+async fn post_phone_form(
+    preferences: &reqwest::Url,
+    path: &str,
+    form: &[(&str, &str)],
+) -> reqwest::Response {
+    let mut url = preferences.clone();
+    url.set_path(path);
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .post(url)
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn mount_provider(provider: &MockServer, expected_texts: u64) {
+    Mock::given(path("/2010-04-01/Accounts/AC123/Messages.json"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sid": "SM1" })))
+        .expect(expected_texts)
+        .mount(provider)
+        .await;
+}
+
+#[tokio::test]
+async fn subscribers_verify_their_phone_with_the_texted_code() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let subscriber = app.create_confirmed_subscriber("ursula@example.com").await;
+    let subscriber_id = subscriber.id;
+    let preferences = subscriber.page("/subscriptions/sms");
+    mount_provider(&provider, 1).await;
+
+    // Act - Part 1 - Give a number
+    let response = post_phone_form(
+        &preferences,
+        "/subscriptions/sms/phone",
+        &[("phone_number", "+1 (415) 555-0100")],
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let request = &provider.received_requests().await.unwrap()[0];
+    let body = String::from_utf8(request.body.clone()).unwrap();
+    assert!(body.contains("To=%2B14155550100"));
+    let code: String = body
+        .split("code+is+")
+        .nth(1)
+        .unwrap()
+        .chars()
+        .take(6)
+        .collect();
+
+    // Act - Part 2 - A wrong code does not verify it
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+    post_phone_form(
+        &preferences,
+        "/subscriptions/sms/phone/verify",
+        &[("code", wrong_code)],
+    )
+    .await;
+    let html = reqwest::get(preferences.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("The code we texted you"));
+
+    // Act - Part 3 - The right one does
+    post_phone_form(
+        &preferences,
+        "/subscriptions/sms/phone/verify",
+        &[("code", &code)],
+    )
+    .await;
+
+    // Assert
+    let saved = sqlx::query!(
+        r#"
+        SELECT phone_number, phone_country, phone_verified_at IS NOT NULL AS "verified!"
+        FROM subscriptions WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.phone_number.as_deref(), Some("+14155550100"));
+    assert_eq!(saved.phone_country.as_deref(), Some("US"));
+    assert!(saved.verified);
+}
+
+#[tokio::test]
+async fn invalid_phone_numbers_are_not_texted() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let preferences = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/sms");
+    mount_provider(&provider, 0).await;
+
+    for phone_number in ["415 555 0100", "+1 415 555", "+999 1234 5678"] {
+        // Act
+        let response = post_phone_form(
+            &preferences,
+            "/subscriptions/sms/phone",
+            &[("phone_number", phone_number)],
+        )
+        .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 303);
+    }
+    // Mock verifies on Drop that nothing was texted
+}
+
+#[tokio::test]
+async fn a_code_is_texted_at_most_once_a_minute() {
+    // Arrange
+    let provider = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_sms(c, &provider)).await;
+    let preferences = app
+        .create_confirmed_subscriber("ursula@example.com")
+        .await
+        .page("/subscriptions/sms");
+    mount_provider(&provider, 1).await;
+
+    // Act
+    for _ in 0..2 {
+        post_phone_form(
+            &preferences,
+            "/subscriptions/sms/phone",
+            &[("phone_number", "+44 20 7946 0958")],
+        )
+        .await;
+    }
+
+    // Assert
+    // Mock verifies on Drop that a single code was texted
+}
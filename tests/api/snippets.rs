@@ -0,0 +1,106 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn save_snippet(app: &TestApp, name: &str, content: &str) {
+    let response = app
+        .post_snippet(&serde_json::json!({
+            "name": name,
+            "text_content": content,
+            "html_content": format!("<p>{}</p>", content),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/snippets");
+}
+
+async fn publish(app: &TestApp, text_content: &str, html_content: &str) -> reqwest::Response {
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": text_content,
+        "html_content": html_content,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn saving_a_snippet_adds_a_version() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    save_snippet(&app, "sponsor", "Brought to you by Earthsea").await;
+    save_snippet(&app, "sponsor", "Brought to you by Gont").await;
+
+    // Assert
+    let html_page = app.get_snippets_html().await;
+    assert!(html_page.contains("Snippet sponsor saved as version 2."));
+    assert!(html_page.contains("<code>{{snippet:sponsor}}</code></td><td>2</td>"));
+}
+
+#[tokio::test]
+async fn snippet_names_must_be_slugs() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    save_snippet(&app, "Our Sponsor", "Brought to you by Earthsea").await;
+
+    // Assert
+    let html_page = app.get_snippets_html().await;
+    assert!(html_page.contains("Snippet names are made of lower-case letters"));
+    assert!(!html_page.contains("{{snippet:"));
+}
+
+#[tokio::test]
+async fn published_issues_keep_the_snippet_version_they_were_sent_with() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    save_snippet(&app, "sponsor", "Brought to you by Earthsea").await;
+
+    // Act
+    let response = publish(
+        &app,
+        "Hello\n{{snippet:sponsor}}",
+        "<p>Hello</p>{{snippet:sponsor}}",
+    )
+    .await;
+    save_snippet(&app, "sponsor", "Brought to you by Gont").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.text_content, "Hello\nBrought to you by Earthsea");
+    assert_eq!(
+        issue.html_content,
+        "<p>Hello</p><p>Brought to you by Earthsea</p>"
+    );
+    let used = sqlx::query!("SELECT name, version FROM newsletter_issue_snippets")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!((used.name.as_str(), used.version), ("sponsor", 1));
+}
+
+#[tokio::test]
+async fn issues_referring_to_an_unknown_snippet_are_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = publish(&app, "{{snippet:signature}}", "<p>Hello</p>").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("There is no snippet named signature."));
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
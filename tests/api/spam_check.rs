@@ -0,0 +1,97 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use zero2prod::configuration::SpamCheckSettings;
+
+/// A spamd stand-in answering a single request with `response`, handing back
+/// the request it received.
+async fn spawn_spamd(response: &'static str) -> (u16, oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        stream.read_to_end(&mut request).await.unwrap();
+        stream.write_all(response.as_bytes()).await.unwrap();
+        let _ = sender.send(String::from_utf8(request).unwrap());
+    });
+    (port, receiver)
+}
+
+async fn spawn_app_with_spamd(port: u16) -> TestApp {
+    spawn_app_with(|c| {
+        c.spam_check = Some(SpamCheckSettings {
+            host: "127.0.0.1".into(),
+            port,
+            timeout_milliseconds: 1000,
+        });
+    })
+    .await
+}
+
+fn issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn the_spam_score_and_triggered_rules_are_returned() {
+    let (port, request) = spawn_spamd(
+        "SPAMD/1.1 0 EX_OK\r\nContent-length: 27\r\nSpam: True ; 6.2 / 5.0\r\n\r\nHTML_MESSAGE,FREE_MONEY\r\n",
+    )
+    .await;
+    let app = spawn_app_with_spamd(port).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_spam_check(&issue()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["is_spam"], true);
+    assert_eq!(report["score"], 6.2);
+    assert_eq!(report["threshold"], 5.0);
+    assert_eq!(
+        report["rules"],
+        serde_json::json!(["HTML_MESSAGE", "FREE_MONEY"])
+    );
+    let request = request.await.unwrap();
+    assert!(request.starts_with("SYMBOLS SPAMC/1.5\r\n"));
+    assert!(request.contains("Subject: Newsletter title\r\n"));
+    assert!(request.contains("<p>Newsletter body as HTML</p>"));
+    assert!(request.contains("Newsletter body as plain text"));
+}
+
+#[tokio::test]
+async fn spamd_errors_are_reported_as_server_errors() {
+    let (port, _) = spawn_spamd("SPAMD/1.0 76 Bad header line\r\n\r\n").await;
+    let app = spawn_app_with_spamd(port).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_spam_check(&issue()).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn the_spam_check_is_not_found_when_not_configured() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_spam_check(&issue()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_check_for_spam() {
+    let app = spawn_app().await;
+
+    let response = app.post_spam_check(&issue()).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
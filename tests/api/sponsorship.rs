@@ -0,0 +1,180 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::Utc;
+use uuid::Uuid;
+
+async fn publish_sponsored_issue(app: &TestApp, sponsor: &str) -> Uuid {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello\n{{sponsor}}",
+            "html_content": "<p>Hello</p>{{sponsor}}",
+            "sponsor": sponsor,
+            "sponsor_url": "https://earthsea.example/maps",
+            "sponsor_message": "Maps of every island",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT slot_id FROM sponsor_slots WHERE sponsor = $1",
+        sponsor
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .slot_id
+}
+
+async fn get(app: &TestApp, path: String) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_sponsor_slot_is_part_of_the_published_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let slot_id = publish_sponsored_issue(&app, "Earthsea").await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue
+        .html_content
+        .contains(&format!("/sponsors/{}/click", slot_id)));
+    assert!(issue
+        .html_content
+        .contains(&format!("/sponsors/{}/impression.gif", slot_id)));
+    assert!(!issue.html_content.contains("{{sponsor}}"));
+    assert!(issue
+        .text_content
+        .starts_with("Hello\nSponsored by Earthsea: Maps of every island\n"));
+}
+
+#[tokio::test]
+async fn sponsor_links_must_be_web_links() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hello",
+            "html_content": "<p>Hello</p>",
+            "sponsor": "Earthsea",
+            "sponsor_url": "javascript:alert(1)",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The sponsor link must be an http or https URL."));
+    let issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn clicks_redirect_to_the_sponsor_and_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let slot_id = publish_sponsored_issue(&app, "Earthsea").await;
+    publish_sponsored_issue(&app, "Gont").await;
+
+    // Act
+    let click = get(&app, format!("/sponsors/{}/click", slot_id)).await;
+    for _ in 0..3 {
+        let pixel = get(&app, format!("/sponsors/{}/impression.gif", slot_id)).await;
+        assert_eq!(pixel.status().as_u16(), 200);
+        assert_eq!(pixel.headers()["Content-Type"], "image/gif");
+    }
+
+    // Assert
+    assert_eq!(click.status().as_u16(), 302);
+    assert_eq!(click.headers()["Location"], "https://earthsea.example/maps");
+    let today = Utc::now().date_naive().to_string();
+    let report: serde_json::Value = app
+        .get_sponsor_report("Earthsea", &today, &today)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["impressions"], 3);
+    assert_eq!(report["clicks"], 1);
+    assert_eq!(report["slots"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn events_outside_of_the_range_are_not_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let slot_id = publish_sponsored_issue(&app, "Earthsea").await;
+    get(&app, format!("/sponsors/{}/click", slot_id)).await;
+
+    // Act
+    let report: serde_json::Value = app
+        .get_sponsor_report("Earthsea", "2020-01-01", "2020-01-31")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(report["clicks"], 0);
+    assert_eq!(report["slots"][0]["clicks"], 0);
+}
+
+#[tokio::test]
+async fn unknown_slots_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get(&app, format!("/sponsors/{}/click", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_report_range_must_be_in_order() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_sponsor_report("Earthsea", "2024-02-01", "2024-01-01")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_a_sponsor_report() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .get_sponsor_report("Earthsea", "2024-01-01", "2024-01-31")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,375 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use base64::Engine;
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{GroupRole, OidcSettings, ScimSettings, Settings};
+
+async fn spawn_sso_app() -> (TestApp, MockServer) {
+    spawn_sso_app_with(|_| {}).await
+}
+
+async fn spawn_sso_app_with(configure: impl FnOnce(&mut Settings)) -> (TestApp, MockServer) {
+    let idp = MockServer::start().await;
+    Mock::given(path("/.well-known/openid-configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "issuer": idp.uri(),
+            "authorization_endpoint": format!("{}/authorize", idp.uri()),
+            "token_endpoint": format!("{}/token", idp.uri()),
+        })))
+        .mount(&idp)
+        .await;
+    let issuer_url = idp.uri();
+    let app = spawn_app_with(|c| {
+        c.oidc = Some(OidcSettings {
+            issuer_url,
+            client_id: "zero2prod".into(),
+            client_secret: Secret::new("client-secret".into()),
+            groups_claim: "groups".into(),
+            default_role: None,
+            group_roles: vec![GroupRole {
+                group: "newsletter-admins".into(),
+                role: "admin".into(),
+            }],
+            allowed_email_domains: Vec::new(),
+            timeout_milliseconds: 1000,
+        });
+        configure(c);
+    })
+    .await;
+    (app, idp)
+}
+
+fn id_token(claims: serde_json::Value) -> String {
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    format!(
+        "{}.{}.signature",
+        engine.encode(r#"{"alg":"RS256"}"#),
+        engine.encode(claims.to_string())
+    )
+}
+
+/// Start the login and return the query of the authorization request.
+async fn start_login(app: &TestApp) -> std::collections::HashMap<String, String> {
+    let response = app
+        .api_client
+        .get(format!("{}/login/oidc", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let url = reqwest::Url::parse(location).unwrap();
+    url.query_pairs().into_owned().collect()
+}
+
+async fn callback(app: &TestApp, code: &str, state: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/login/oidc/callback", &app.address))
+        .query(&[("code", code), ("state", state)])
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn mount_token_endpoint(idp: &MockServer, claims: serde_json::Value) {
+    Mock::given(path("/token"))
+        .and(method("POST"))
+        .and(body_string_contains("code=auth-code"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "access_token": "access-token",
+            "token_type": "Bearer",
+            "id_token": id_token(claims),
+        })))
+        .mount(idp)
+        .await;
+}
+
+#[tokio::test]
+async fn the_authorization_request_uses_pkce() {
+    // Arrange
+    let (app, _idp) = spawn_sso_app().await;
+
+    // Act
+    let query = start_login(&app).await;
+
+    // Assert
+    assert_eq!(query["client_id"], "zero2prod");
+    assert_eq!(query["code_challenge_method"], "S256");
+    assert!(!query["code_challenge"].is_empty());
+    assert!(query["redirect_uri"].ends_with("/login/oidc/callback"));
+}
+
+#[tokio::test]
+async fn the_first_sso_login_provisions_the_admin_with_a_mapped_role() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+    let query = start_login(&app).await;
+    mount_token_endpoint(
+        &idp,
+        serde_json::json!({
+            "iss": idp.uri(),
+            "sub": "idp-user-1",
+            "aud": "zero2prod",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": query["nonce"],
+            "email": "grace@gmail.com",
+            "email_verified": true,
+            "preferred_username": "grace",
+            "groups": ["staff", "newsletter-admins"],
+        }),
+    )
+    .await;
+
+    // Act
+    let response = callback(&app, "auth-code", &query["state"]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("Welcome grace!"));
+    let user = sqlx::query!("SELECT role, email FROM users WHERE oidc_subject = 'idp-user-1'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(user.role, "admin");
+    assert_eq!(user.email.as_deref(), Some("grace@gmail.com"));
+}
+
+#[tokio::test]
+async fn a_callback_with_the_wrong_state_is_rejected() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+    let query = start_login(&app).await;
+    mount_token_endpoint(
+        &idp,
+        serde_json::json!({
+            "iss": idp.uri(),
+            "sub": "idp-user-1",
+            "aud": "zero2prod",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": query["nonce"],
+        }),
+    )
+    .await;
+
+    // Act
+    let response = callback(&app, "auth-code", "forged-state").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_id_token_with_another_nonce_is_rejected() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+    let query = start_login(&app).await;
+    mount_token_endpoint(
+        &idp,
+        serde_json::json!({
+            "iss": idp.uri(),
+            "sub": "idp-user-1",
+            "aud": "zero2prod",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": "replayed-nonce",
+        }),
+    )
+    .await;
+
+    // Act
+    let response = callback(&app, "auth-code", &query["state"]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Single sign-on failed"));
+}
+
+#[tokio::test]
+async fn sso_cannot_take_over_a_password_account() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+    let query = start_login(&app).await;
+    mount_token_endpoint(
+        &idp,
+        serde_json::json!({
+            "iss": idp.uri(),
+            "sub": "idp-user-1",
+            "aud": "zero2prod",
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": query["nonce"],
+            "email": "grace@gmail.com",
+            "email_verified": true,
+            "preferred_username": app.test_user.username,
+            "groups": ["newsletter-admins"],
+        }),
+    )
+    .await;
+
+    // Act
+    let response = callback(&app, "auth-code", &query["state"]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+/// Log in through the identity provider with `claims`, on top of the
+/// standard ones, and return the response to the callback.
+async fn sso_login(
+    app: &TestApp,
+    idp: &MockServer,
+    claims: serde_json::Value,
+) -> reqwest::Response {
+    let query = start_login(app).await;
+    let mut id_token_claims = serde_json::json!({
+        "iss": idp.uri(),
+        "sub": "idp-user-1",
+        "aud": "zero2prod",
+        "exp": chrono::Utc::now().timestamp() + 60,
+        "nonce": query["nonce"],
+    });
+    id_token_claims
+        .as_object_mut()
+        .unwrap()
+        .extend(claims.as_object().unwrap().clone());
+    mount_token_endpoint(idp, id_token_claims).await;
+    callback(app, "auth-code", &query["state"]).await
+}
+
+async fn sso_users(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE oidc_subject IS NOT NULL"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn users_outside_of_the_mapped_groups_are_not_provisioned() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+
+    // Act
+    let response = sso_login(
+        &app,
+        &idp,
+        serde_json::json!({
+            "email": "stranger@gmail.com",
+            "email_verified": true,
+            "groups": ["staff"],
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(sso_users(&app).await, 0);
+}
+
+#[tokio::test]
+async fn users_without_a_verified_email_are_not_provisioned() {
+    // Arrange
+    let (app, idp) = spawn_sso_app().await;
+
+    // Act
+    let response = sso_login(
+        &app,
+        &idp,
+        serde_json::json!({
+            "email": "grace@gmail.com",
+            "email_verified": false,
+            "groups": ["newsletter-admins"],
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(sso_users(&app).await, 0);
+}
+
+#[tokio::test]
+async fn users_outside_of_the_allowed_domains_are_not_provisioned() {
+    // Arrange
+    let (app, idp) = spawn_sso_app_with(|c| {
+        c.oidc.as_mut().unwrap().allowed_email_domains = vec!["example.com".into()];
+    })
+    .await;
+
+    // Act
+    let response = sso_login(
+        &app,
+        &idp,
+        serde_json::json!({
+            "email": "grace@gmail.com",
+            "email_verified": true,
+            "groups": ["newsletter-admins"],
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(sso_users(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_first_sso_login_claims_the_account_provisioned_over_scim() {
+    // Arrange
+    let (app, idp) = spawn_sso_app_with(|c| {
+        c.scim = Some(ScimSettings {
+            bearer_token: Secret::new("scim-token".into()),
+        });
+    })
+    .await;
+    let response = app
+        .api_client
+        .post(format!("{}/scim/v2/Users", &app.address))
+        .bearer_auth("scim-token")
+        .json(&serde_json::json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:User"],
+            "userName": "grace",
+            "emails": [{ "value": "grace@gmail.com", "primary": true }],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let created: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    let response = sso_login(
+        &app,
+        &idp,
+        serde_json::json!({
+            "email": "grace@gmail.com",
+            "email_verified": true,
+            "preferred_username": "grace",
+            "groups": ["newsletter-admins"],
+        }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let user = sqlx::query!("SELECT user_id, role FROM users WHERE oidc_subject = 'idp-user-1'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        user.user_id,
+        created["id"].as_str().unwrap().parse::<Uuid>().unwrap()
+    );
+    assert_eq!(user.role, "admin");
+}
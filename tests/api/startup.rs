@@ -0,0 +1,87 @@
+use claims::assert_matches;
+use secrecy::Secret;
+use zero2prod::configuration::{get_configuration, PiiEncryptionSettings};
+use zero2prod::startup::{Application, StartupError};
+
+#[tokio::test]
+async fn an_invalid_sender_is_reported_instead_of_panicking() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.email_client.sender_email = "not-an-email".into();
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn an_unreachable_database_is_reported_instead_of_panicking() {
+    let mut configuration = get_configuration().unwrap();
+    // Nothing listens on port 1.
+    configuration.database.port = 1;
+    configuration.database.connect_attempts = 1;
+
+    let outcome = Application::build(configuration).await;
+
+    let error = outcome.err().unwrap();
+    assert_matches!(error, StartupError::Database { port: 1, .. });
+    assert!(error.hint().contains("Postgres"));
+}
+
+#[tokio::test]
+async fn a_redirect_outside_the_allowed_domains_is_rejected() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.subscriber_redirects.post_confirm_url = Some("https://evil.example.net/".into());
+    configuration.subscriber_redirects.allowed_domains = vec!["example.com".into()];
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn a_missing_template_directory_is_rejected() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.theme.template_directory = Some("/does/not/exist".into());
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn an_invalid_admin_allowlist_is_rejected() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.admin.allowed_cidrs = Some(vec!["10.0.0.0/33".into()]);
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn a_pii_keyring_without_its_active_key_is_rejected() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.pii_encryption = Some(PiiEncryptionSettings {
+        active_key: "k2".into(),
+        keys: [(
+            "k1".to_string(),
+            Secret::new("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".into()),
+        )]
+        .into(),
+        index_key: Secret::new("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".into()),
+    });
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn a_delivery_log_window_shorter_than_the_send_quota_is_rejected() {
+    let mut configuration = get_configuration().unwrap();
+    configuration.data_retention.delivery_log_days = Some(7);
+
+    let outcome = Application::build(configuration).await;
+
+    assert_matches!(outcome.err(), Some(StartupError::InvalidConfiguration(_)));
+}
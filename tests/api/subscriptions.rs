@@ -150,3 +150,99 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribe_explains_which_field_is_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=Ursula&email=definitely-not-an-email";
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        error,
+        serde_json::json!({
+            "field": "email",
+            "rule": "invalid_format",
+            "message": "The email address is not valid.",
+        })
+    );
+    // The rejected value is not echoed back.
+    assert!(!error.to_string().contains("definitely-not-an-email"));
+}
+
+#[tokio::test]
+async fn validation_errors_are_explained_in_the_language_of_the_visitor() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", "fr-FR, pt-BR;q=0.9, en;q=0.5")
+        .body("name=Ursula&email=definitely-not-an-email")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["field"], "email");
+    assert_eq!(error["message"], "O endereço de email não é válido.");
+}
+
+#[tokio::test]
+async fn concurrent_duplicate_subscriptions_send_a_single_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (first, second, third) = tokio::join!(
+        app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()),
+        app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()),
+        app.post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into()),
+    );
+
+    // Assert
+    for response in [first, second, third] {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn a_retried_subscription_is_answered_without_another_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,6 +1,9 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    assert_is_redirect_to, confirm_subscription, spawn_app, spawn_app_with, TestApp,
+};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::i18n::Language;
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -17,46 +20,125 @@ async fn confirmations_without_token_are_rejected_with_a_400() {
 }
 
 #[tokio::test]
-async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
     // Arrange
     let app = spawn_app().await;
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
+    // Act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+async fn create_unconfirmed_subscriber(app: &TestApp) -> reqwest::Url {
     Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
         .await;
-
-    app.post_subscriptions(body.into()).await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(email_request);
+    app.get_confirmation_links(email_request).html
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
 
     // Act
-    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    let response = reqwest::get(confirmation_link).await.unwrap();
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
 }
 
 #[tokio::test]
-async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+async fn following_the_confirmation_link_does_not_confirm_a_subscriber() {
     // Arrange
     let app = spawn_app().await;
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
 
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .mount(&app.email_server)
-        .await;
+    // Act - a mail scanner prefetches the link
+    let html_page = reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
 
-    app.post_subscriptions(body.into()).await;
-    let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(email_request);
+    // Assert
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm?subscription_token="#));
+    assert_eq!(subscriber_status(&app).await, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn the_confirmation_page_is_shown_in_the_language_of_the_visitor() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
 
     // Act
-    reqwest::get(confirmation_links.html)
+    let html_page = reqwest::Client::new()
+        .get(confirmation_link)
+        .header("Accept-Language", "de-CH, de;q=0.9")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"<html lang="de">"#));
+    assert!(html_page.contains("Abonnement bestätigen"));
+}
+
+#[tokio::test]
+async fn the_default_language_is_used_when_the_visitor_prefers_another() {
+    // Arrange
+    let app = spawn_app_with(|c| c.i18n.default_language = Language::Portuguese).await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let html_page = reqwest::Client::new()
+        .post(confirmation_link)
+        .header("Accept-Language", "ja")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("A sua inscrição está confirmada."));
+}
+
+#[tokio::test]
+async fn submitting_the_confirmation_form_confirms_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    confirm_subscription(confirmation_link)
         .await
         .unwrap()
         .error_for_status()
@@ -72,3 +154,64 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirming_twice_shows_the_same_page() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    confirm_subscription(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let resubmitted = confirm_subscription(confirmation_link.clone())
+        .await
+        .unwrap();
+    let followed_again = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    for response in [resubmitted, followed_again] {
+        assert_eq!(response.status().as_u16(), 200);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains("Your subscription is confirmed."));
+    }
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_sent_to_the_configured_page() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscriber_redirects.post_confirm_url = Some("https://www.example.com/welcome".into());
+        c.subscriber_redirects.allowed_domains = vec!["example.com".into()];
+    })
+    .await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+
+    // Act
+    let response = app.api_client.post(confirmation_link).send().await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "https://www.example.com/welcome");
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_link = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET issued_at = now() - interval '400 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "pending_confirmation");
+}
@@ -0,0 +1,161 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use secrecy::Secret;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{Settings, TelegramSettings};
+
+const SEND_MESSAGE_PATH: &str = "/bot123:secret/sendMessage";
+
+fn configure_telegram(c: &mut Settings, bot_api: &MockServer) {
+    c.telegram = Some(TelegramSettings {
+        base_url: bot_api.uri(),
+        bot_token: Secret::new("123:secret".into()),
+        chat_id: "@zero2prod".into(),
+        timeout_milliseconds: 2000,
+        max_attempts: 3,
+    });
+}
+
+async fn publish_issue(app: &TestApp, skip_telegram: bool) {
+    let mut form = serde_json::json!({
+        "title": "Newsletter <title>",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    if skip_telegram {
+        form["skip_telegram"] = "true".into();
+    }
+    let response = app.post_publish_newsletter(&form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+struct TelegramPost {
+    message_id: Option<i64>,
+    attempts: i32,
+    last_error: Option<String>,
+    retry_is_due: bool,
+}
+
+async fn get_telegram_post(app: &TestApp) -> TelegramPost {
+    sqlx::query_as!(
+        TelegramPost,
+        r#"
+        SELECT message_id, attempts, last_error, next_attempt_at <= now() AS "retry_is_due!"
+        FROM telegram_posts
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_are_posted_to_the_channel() {
+    // Arrange
+    let bot_api = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_telegram(c, &bot_api)).await;
+    Mock::given(path(SEND_MESSAGE_PATH))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "chat_id": "@zero2prod",
+            "parse_mode": "HTML",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "ok": true,
+            "result": { "message_id": 42 },
+        })))
+        .expect(1)
+        .mount(&bot_api)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, false).await;
+    app.post_pending_telegram_posts().await;
+
+    // Assert
+    let request = &bot_api.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let text = body["text"].as_str().unwrap();
+    assert!(text.starts_with("<b>Newsletter &lt;title&gt;</b>\n\nNewsletter body as plain text"));
+    assert!(text.contains("/archive/"));
+    assert_eq!(get_telegram_post(&app).await.message_id, Some(42));
+}
+
+#[tokio::test]
+async fn issues_published_with_the_mirror_off_are_not_posted() {
+    // Arrange
+    let bot_api = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_telegram(c, &bot_api)).await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&bot_api)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, true).await;
+    app.post_pending_telegram_posts().await;
+
+    // Assert
+    // Mock verifies on Drop that nothing was posted
+}
+
+#[tokio::test]
+async fn failed_posts_are_retried_later() {
+    // Arrange
+    let bot_api = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_telegram(c, &bot_api)).await;
+    Mock::given(path(SEND_MESSAGE_PATH))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: chat not found",
+        })))
+        .expect(1)
+        .mount(&bot_api)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, false).await;
+    app.post_pending_telegram_posts().await;
+
+    // Assert
+    let post = get_telegram_post(&app).await;
+    assert_eq!(post.message_id, None);
+    assert_eq!(post.attempts, 1);
+    assert_eq!(
+        post.last_error.as_deref(),
+        Some("Telegram rejected the post: Bad Request: chat not found")
+    );
+    assert!(!post.retry_is_due);
+}
+
+#[tokio::test]
+async fn throttled_posts_wait_without_using_an_attempt() {
+    // Arrange
+    let bot_api = MockServer::start().await;
+    let app = spawn_app_with(|c| configure_telegram(c, &bot_api)).await;
+    Mock::given(path(SEND_MESSAGE_PATH))
+        .respond_with(ResponseTemplate::new(429).set_body_json(serde_json::json!({
+            "ok": false,
+            "error_code": 429,
+            "description": "Too Many Requests: retry after 30",
+            "parameters": { "retry_after": 30 },
+        })))
+        .expect(1)
+        .mount(&bot_api)
+        .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    publish_issue(&app, false).await;
+    app.post_pending_telegram_posts().await;
+
+    // Assert
+    let post = get_telegram_post(&app).await;
+    assert_eq!(post.attempts, 0);
+    assert!(!post.retry_is_due);
+}
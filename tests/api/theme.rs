@@ -0,0 +1,78 @@
+use crate::helpers::spawn_app_with;
+use uuid::Uuid;
+use zero2prod::configuration::SignupPageSettings;
+
+#[tokio::test]
+async fn the_custom_css_is_sanitized_into_every_public_page() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.theme.custom_css =
+            "body { font-family: serif; }</style><script>@import url(x.css)".into();
+        c.signup_pages = vec![SignupPageSettings {
+            slug: "earthsea".into(),
+            title: "Earthsea".into(),
+            description: String::new(),
+            logo_url: None,
+            accent_color: None,
+        }];
+    })
+    .await;
+
+    for response in [
+        app.get_signup_page("earthsea").await,
+        app.api_client
+            .get(format!("{}/archive", &app.address))
+            .send()
+            .await
+            .unwrap(),
+    ] {
+        // Assert
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains("body { font-family: serif; }"));
+        assert!(html_page.contains("color: #1f6feb;"));
+        assert!(!html_page.contains("<script>"));
+        assert!(!html_page.contains("@import"));
+    }
+}
+
+#[tokio::test]
+async fn a_template_in_the_template_directory_overrides_its_page() {
+    // Arrange
+    let directory = std::env::temp_dir().join(format!("zero2prod-theme-{}", Uuid::new_v4()));
+    std::fs::create_dir(&directory).unwrap();
+    std::fs::write(
+        directory.join("archive_index.html"),
+        "<main data-theme=\"custom\"><h2>{{title}}</h2>{{content}}</main>",
+    )
+    .unwrap();
+    let template_directory = directory.to_str().unwrap().to_owned();
+    let app = spawn_app_with(|c| {
+        c.theme.template_directory = Some(template_directory);
+        c.signup_pages = vec![SignupPageSettings {
+            slug: "earthsea".into(),
+            title: "Earthsea".into(),
+            description: String::new(),
+            logo_url: None,
+            accent_color: None,
+        }];
+    })
+    .await;
+
+    // Act
+    let archive = app
+        .api_client
+        .get(format!("{}/archive", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let signup_page = app.get_signup_page("earthsea").await.text().await.unwrap();
+
+    // Assert
+    assert!(archive.starts_with("<main data-theme=\"custom\"><h2>Archive</h2><ul>"));
+    // The other pages keep the built-in layout.
+    assert!(signup_page.starts_with("<!DOCTYPE html>"));
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,119 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::collections::HashMap;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_issue_with_portuguese_variant(app: &TestApp) -> Uuid {
+    app.test_user.login(app).await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let response = app
+        .post_issue_variant(
+            issue_id,
+            &serde_json::json!({
+                "locale": "pt",
+                "title": "Título da newsletter",
+                "text_content": "Corpo da newsletter",
+                "html_content": "<p>Corpo da newsletter</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    issue_id
+}
+
+#[tokio::test]
+async fn subscribers_get_the_variant_in_their_locale_or_the_original() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.create_confirmed_subscriber_with("name=le%20guin&email=clarice%40gmail.com&locale=pt_BR")
+        .await;
+    app.create_confirmed_subscriber_with("name=le%20guin&email=ursula%40gmail.com&locale=en")
+        .await;
+    publish_issue_with_portuguese_variant(&app).await;
+    let already_received = app.email_server.received_requests().await.unwrap().len();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let subjects: HashMap<String, String> = app.email_server.received_requests().await.unwrap()
+        [already_received..]
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let message = &body["messages"][0];
+            (
+                message["To"][0]["email"].as_str().unwrap().to_owned(),
+                message["Subject"].as_str().unwrap().to_owned(),
+            )
+        })
+        .collect();
+    assert_eq!(subjects.len(), 2);
+    assert_eq!(subjects["clarice@gmail.com"], "Título da newsletter");
+    assert_eq!(subjects["ursula@gmail.com"], "Newsletter title");
+}
+
+#[tokio::test]
+async fn the_archive_switches_between_the_variants_of_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue_with_portuguese_variant(&app).await;
+
+    // Act
+    let original = app.get_archive_issue(issue_id).await.text().await.unwrap();
+    let translated = app
+        .api_client
+        .get(format!("{}/archive/{}?lang=pt", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(original.contains("<p>Newsletter body as HTML</p>"));
+    assert!(original.contains(&format!(r#"href="/archive/{}?lang=pt""#, issue_id)));
+    assert!(translated.contains("<p>Corpo da newsletter</p>"));
+    assert!(translated.contains(&format!(r#"href="/archive/{}""#, issue_id)));
+}
+
+#[tokio::test]
+async fn subscribe_rejects_an_invalid_locale() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula@gmail.com"),
+            ("locale", "not a locale"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
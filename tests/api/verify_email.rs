@@ -0,0 +1,88 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use secrecy::Secret;
+use zero2prod::configuration::{ApiKeySettings, ApiSettings, ApiTierSettings};
+
+const API_KEY: &str = "signup-form-key";
+
+async fn spawn_app_with_api() -> TestApp {
+    spawn_app_with(|c| {
+        c.api = Some(ApiSettings {
+            keys: vec![
+                ApiKeySettings {
+                    id: "old-signup-form".into(),
+                    key: Secret::new("old-key".into()),
+                    tier: "internal".into(),
+                },
+                ApiKeySettings {
+                    id: "signup-form".into(),
+                    key: Secret::new(API_KEY.into()),
+                    tier: "internal".into(),
+                },
+            ],
+            tiers: [(
+                "internal".into(),
+                ApiTierSettings {
+                    requests_per_minute: 1000,
+                    requests_per_day: 100_000,
+                },
+            )]
+            .into(),
+        });
+        c.email_verification.disposable_domains = vec!["mailinator.com".into()];
+    })
+    .await
+}
+
+#[tokio::test]
+async fn the_api_is_not_found_unless_configured() {
+    let app = spawn_app().await;
+
+    let response = app.post_verify_email(API_KEY, "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_unknown_api_key_is_rejected() {
+    let app = spawn_app_with_api().await;
+
+    let response = app.post_verify_email("guess", "ursula@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_malformed_address_is_invalid() {
+    let app = spawn_app_with_api().await;
+
+    let response = app.post_verify_email(API_KEY, "ursula.example.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let verification: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(verification["verdict"], "invalid");
+    assert_eq!(
+        verification["reasons"],
+        serde_json::json!(["invalid_syntax"])
+    );
+}
+
+#[tokio::test]
+async fn disposable_and_suppressed_addresses_are_invalid() {
+    let app = spawn_app_with_api().await;
+    sqlx::query!(
+        "INSERT INTO suppressed_emails (email, reason, suppressed_at) \
+         VALUES ('ged@mailinator.com', 'hard_bounce', now())"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.post_verify_email(API_KEY, "Ged@mailinator.com").await;
+
+    let verification: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(verification["verdict"], "invalid");
+    assert_eq!(
+        verification["reasons"],
+        serde_json::json!(["disposable_domain", "suppressed"])
+    );
+}